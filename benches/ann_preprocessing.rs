@@ -18,6 +18,20 @@ pub fn shingle_arity_3_benchmark(c: &mut Criterion) {
     });
 }
 
+pub fn shingle_arity_8_benchmark(c: &mut Criterion) {
+    let diff = Diff::from(IdeaPatch(BENCHMARK_DIFF.to_string()));
+    c.bench_function("shingle_arity_8", |b| b.iter(|| shingle_diff(&diff, 8)));
+}
+
+pub fn static_vocabulary_building(c: &mut Criterion) {
+    // Many texts with repeated shingles, without the need to clone a repository
+    let diff = Diff::from(IdeaPatch(BENCHMARK_DIFF.to_string()));
+    let shingled_diffs: Vec<ShingledText> = (0..100).map(|_| shingle_diff(&diff, 3)).collect();
+    c.bench_function("build_static_shingle_vocab", |b| {
+        b.iter(|| Vocabulary::build(&shingled_diffs))
+    });
+}
+
 const DATASET: &str = "https://github.com/VariantSync/VEVOS_Simulation.git";
 fn repo_location() -> RepoLocation {
     RepoLocation::Server(DATASET.to_string())
@@ -45,9 +59,8 @@ pub fn vocabulary_building(c: &mut Criterion) {
 }
 
 pub fn minhash(c: &mut Criterion) {
-    let bits_per_byte = 8;
     let num_byte = 10_000;
-    let minhash = MinHash::new(256, bits_per_byte * num_byte);
+    let minhash = MinHash::new(256);
 
     // get a random byte vector
     let mut bytes: Vec<u8> = vec![];
@@ -78,6 +91,8 @@ pub fn commit_preprocessing(c: &mut Criterion) {
 criterion_group!(
     benches,
     shingle_arity_3_benchmark,
+    shingle_arity_8_benchmark,
+    static_vocabulary_building,
     vocabulary_building,
     minhash,
     commit_preprocessing
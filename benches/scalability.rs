@@ -0,0 +1,340 @@
+//! Runs each search method over synthetic corpora of increasing size and records its runtime,
+//! peak memory, and recall, so that the scalability of the methods can be compared.
+//!
+//! `cargo bench --bench scalability` writes `scalability.csv` (one row per method and corpus
+//! size) as well as `runtime.csv`, `memory.csv`, and `recall.csv` (one row per corpus size and one
+//! column per method, ready to be plotted) to `target/scalability`. The run is configured with
+//! environment variables:
+//! - `SCALABILITY_SIZES`: the comma-separated corpus sizes (default: `10000,100000,1000000`)
+//! - `SCALABILITY_METHODS`: the comma-separated methods (default: all single methods)
+//! - `SCALABILITY_CONFIG`: a method configuration file (see `MethodConfig`), whose methods are
+//!   measured instead, e.g., to compare the parameters of a method
+//! - `SCALABILITY_OUTPUT`: the output folder (default: `target/scalability`)
+use cherry_harvest::git::{calculate_diffs, clone_or_load, collect_commits};
+use cherry_harvest::search::registry::{load_method_configs, MethodConfig, MethodRegistry};
+use cherry_harvest::{Commit, RepoLocation, SearchResult};
+use git2::{Buf, Oid, Repository, Signature, Time, Tree};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use temp_dir::TempDir;
+
+const DEFAULT_SIZES: &str = "10000,100000,1000000";
+const DEFAULT_METHODS: &str = "MessageScan,MessageSimilarity,ExactDiffMatch,ExactChangeMatch,\
+    PatchIdMatch,ChangeIdMatch,TraditionalLSH";
+/// Every n-th commit is picked onto the `picks` branch
+const PICK_INTERVAL: usize = 100;
+/// Every n-th pick is adapted, i.e., its diff differs from the diff of its cherry
+const ADAPTED_INTERVAL: usize = 2;
+/// The number of files that the commits modify
+const FILES: usize = 256;
+/// The number of commits whose objects are kept in memory before they are written as a pack
+const PACK_SIZE: usize = 50_000;
+
+/// Counts the allocated bytes, so that the peak memory of each method can be measured
+struct PeakAllocator {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+impl PeakAllocator {
+    /// Reset the peak to the current allocation and return the current allocation
+    fn reset_peak(&self) -> usize {
+        let current = self.current.load(Ordering::Relaxed);
+        self.peak.store(current, Ordering::Relaxed);
+        current
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+/// The measurements of one method on one corpus
+struct Measurement {
+    method: String,
+    commits: usize,
+    seconds: f64,
+    peak_memory: usize,
+    results: usize,
+    /// The share of the generated picks that were found
+    recall: f64,
+}
+
+fn main() {
+    let sizes: Vec<usize> = list("SCALABILITY_SIZES", DEFAULT_SIZES)
+        .iter()
+        .map(|size| size.parse().expect("corpus sizes must be numbers"))
+        .collect();
+    let methods: Vec<MethodConfig> = match env::var("SCALABILITY_CONFIG") {
+        Ok(file) => load_method_configs(file).expect("the method configuration must be valid"),
+        Err(_) => list("SCALABILITY_METHODS", DEFAULT_METHODS)
+            .iter()
+            .map(|name| MethodConfig::new(name))
+            .collect(),
+    };
+    let output = env::var("SCALABILITY_OUTPUT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("target/scalability"));
+    let registry = MethodRegistry::default();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut measurements = vec![];
+    for size in sizes {
+        let directory = TempDir::new().unwrap();
+        let started = Instant::now();
+        let picks = synthetic_repository(directory.path(), size);
+        println!(
+            "generated {size} commits in {:.1}s",
+            started.elapsed().as_secs_f64()
+        );
+        let location = RepoLocation::Filesystem(directory.path().to_path_buf());
+        let loaded = [runtime.block_on(clone_or_load(&location)).unwrap()];
+        let mut corpus: Vec<Commit> = collect_commits(&loaded).into_iter().collect();
+        // Diffs are extracted once, so that only the searches are measured
+        calculate_diffs(&mut corpus);
+        for config in &methods {
+            let name = label(config);
+            let method = registry.create(config).unwrap();
+            let mut commits = corpus.clone();
+            // The profiling events of earlier runs would inflate the memory of later runs
+            firestorm::clear();
+            let baseline = ALLOCATOR.reset_peak();
+            let started = Instant::now();
+            let results = method.search(&mut commits);
+            let seconds = started.elapsed().as_secs_f64();
+            let peak_memory = ALLOCATOR.peak.load(Ordering::Relaxed) - baseline;
+            let recall = recall(&picks, &results);
+            println!(
+                "{name} on {} commits: {seconds:.3}s, {peak_memory} bytes, {} results, recall {recall:.3}",
+                commits.len(),
+                results.len()
+            );
+            measurements.push(Measurement {
+                method: name,
+                commits: commits.len(),
+                seconds,
+                peak_memory,
+                results: results.len(),
+                recall,
+            });
+        }
+    }
+    write_measurements(&output, &measurements).unwrap();
+    println!("wrote the measurements to {}", output.display());
+}
+
+/// The comma-separated values of the given environment variable
+fn list(variable: &str, default: &str) -> Vec<String> {
+    env::var(variable)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// The name of a method in the measurements, which includes the parameters of the method, if any
+fn label(config: &MethodConfig) -> String {
+    let mut params: Vec<String> = config
+        .params
+        .iter()
+        .map(|(key, value)| {
+            let value = serde_yaml::to_string(value).unwrap_or_default();
+            format!("{key}={}", value.trim())
+        })
+        .collect();
+    params.sort();
+    match params.is_empty() {
+        true => config.name.clone(),
+        false => format!("{}({})", config.name, params.join(" ")),
+    }
+}
+
+/// The share of the given picks (pairs of cherry and pick) that are among the results
+fn recall(picks: &[(Oid, Oid)], results: &HashSet<SearchResult>) -> f64 {
+    let found: HashSet<(&str, &str)> = results
+        .iter()
+        .map(|result| {
+            let pair = result.commit_pair();
+            (pair.cherry().id(), pair.target().id())
+        })
+        .collect();
+    let picks: Vec<(String, String)> = picks
+        .iter()
+        .map(|(cherry, pick)| (cherry.to_string(), pick.to_string()))
+        .collect();
+    let found = picks
+        .iter()
+        .filter(|(cherry, pick)| {
+            found.contains(&(cherry.as_str(), pick.as_str()))
+                || found.contains(&(pick.as_str(), cherry.as_str()))
+        })
+        .count();
+    found as f64 / picks.len().max(1) as f64
+}
+
+/// Generate a repository with the given number of commits on its `main` branch, each of which
+/// rewrites one of the repository's files. Every [PICK_INTERVAL]-th commit is picked, i.e., a
+/// commit with the same parent and a cherry-pick trailer in its message is merged into the
+/// `picks` branch. Most picks have the tree of their cherry, so that all methods find them by
+/// their diff or by their message; every [ADAPTED_INTERVAL]-th pick adds a line to the file,
+/// so that only the methods that compare messages or similar diffs find it. Returns the pairs of
+/// cherry and pick.
+///
+/// The objects are written to an in-memory object database and packed in batches, because
+/// millions of loose objects would make the generation (and the collection) very slow.
+fn synthetic_repository(path: &Path, size: usize) -> Vec<(Oid, Oid)> {
+    let repository = Repository::init(path).unwrap();
+    let odb = repository.odb().unwrap();
+    let mempack = odb.add_new_mempack_backend(1000).unwrap();
+    let write_pack = || {
+        let mut pack = Buf::new();
+        mempack.dump(&repository, &mut pack).unwrap();
+        let mut writer = odb.packwriter().unwrap();
+        writer.write_all(&pack).unwrap();
+        writer.commit().unwrap();
+        mempack.reset().unwrap();
+    };
+
+    let mut tree: Option<Tree> = None;
+    let mut main: Option<Oid> = None;
+    let mut picks: Option<Oid> = None;
+    let mut picked = vec![];
+    for i in 0..size {
+        let time = Time::new(1_600_000_000 + i as i64 * 60, 0);
+        let author = Signature::new(&format!("dev{}", i % 50), "dev@example.com", &time).unwrap();
+        let file = i % FILES;
+        let content = format!(
+            "fn f{i}(x: u64) -> u64 {{\n    x.wrapping_mul({}) ^ {}\n}}\n",
+            i.wrapping_mul(2_654_435_761) % 1_000_003,
+            i / FILES
+        );
+        let blob = repository.blob(content.as_bytes()).unwrap();
+        let with_file = |blob: Oid| {
+            let mut builder = repository.treebuilder(tree.as_ref()).unwrap();
+            builder
+                .insert(format!("file{file:03}.rs"), blob, 0o100644)
+                .unwrap();
+            repository.find_tree(builder.write().unwrap()).unwrap()
+        };
+        let adapted = (i / PICK_INTERVAL) % ADAPTED_INTERVAL == ADAPTED_INTERVAL - 1;
+        let pick_tree = (i % PICK_INTERVAL == PICK_INTERVAL - 1 && adapted).then(|| {
+            let content = format!("{content}// adapted while picking\n");
+            with_file(repository.blob(content.as_bytes()).unwrap())
+        });
+        tree = Some(with_file(blob));
+        let tree = tree.as_ref().unwrap();
+        let parent = main.map(|id| repository.find_commit(id).unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        let message = format!("Rewrite f{i} in file {file}\n\nThe change number {i}.");
+        let cherry = repository
+            .commit(None, &author, &author, &message, tree, &parents)
+            .unwrap();
+        main = Some(cherry);
+
+        if i % PICK_INTERVAL == PICK_INTERVAL - 1 {
+            let picker = Signature::new("maintainer", "maintainer@example.com", &time).unwrap();
+            let message = format!("{message}\n\n(cherry picked from commit {cherry})");
+            let pick_tree = pick_tree.as_ref().unwrap_or(tree);
+            let pick = repository
+                .commit(None, &author, &picker, &message, pick_tree, &parents)
+                .unwrap();
+            picked.push((cherry, pick));
+            // The pick is merged into the picks branch, so that it is reachable
+            let pick = repository.find_commit(pick).unwrap();
+            let previous = picks.map(|id| repository.find_commit(id).unwrap());
+            let merge_parents: Vec<_> = previous.iter().chain([&pick]).collect();
+            picks = Some(
+                repository
+                    .commit(None, &picker, &picker, "Merge pick", tree, &merge_parents)
+                    .unwrap(),
+            );
+        }
+        if i % PACK_SIZE == PACK_SIZE - 1 {
+            write_pack();
+        }
+    }
+    write_pack();
+    for (branch, head) in [("main", main), ("picks", picks)] {
+        if let Some(head) = head {
+            let reference = format!("refs/heads/{branch}");
+            repository.reference(&reference, head, true, "").unwrap();
+        }
+    }
+    picked
+}
+
+/// Write the measurements in long format and the runtime and memory per method in wide format
+fn write_measurements(output: &Path, measurements: &[Measurement]) -> std::io::Result<()> {
+    fs::create_dir_all(output)?;
+    let mut long =
+        String::from("method,commits,runtime_seconds,peak_memory_bytes,results,recall\n");
+    for m in measurements {
+        long += &format!(
+            "\"{}\",{},{:.6},{},{},{:.4}\n",
+            m.method, m.commits, m.seconds, m.peak_memory, m.results, m.recall
+        );
+    }
+    fs::write(output.join("scalability.csv"), long)?;
+
+    let mut methods: Vec<&str> = vec![];
+    for m in measurements {
+        if !methods.contains(&m.method.as_str()) {
+            methods.push(&m.method);
+        }
+    }
+    let wide = |value: fn(&Measurement) -> String| {
+        let mut rows: BTreeMap<usize, BTreeMap<&str, String>> = BTreeMap::new();
+        for m in measurements {
+            rows.entry(m.commits)
+                .or_default()
+                .insert(m.method.as_str(), value(m));
+        }
+        let header: Vec<String> = methods.iter().map(|m| format!("\"{m}\"")).collect();
+        let mut table = format!("commits,{}\n", header.join(","));
+        for (commits, values) in rows {
+            let values: Vec<&str> = methods
+                .iter()
+                .map(|method| values.get(method).map_or("", String::as_str))
+                .collect();
+            table += &format!("{commits},{}\n", values.join(","));
+        }
+        table
+    };
+    fs::write(
+        output.join("runtime.csv"),
+        wide(|m| format!("{:.6}", m.seconds)),
+    )?;
+    fs::write(
+        output.join("memory.csv"),
+        wide(|m| m.peak_memory.to_string()),
+    )?;
+    fs::write(
+        output.join("recall.csv"),
+        wide(|m| format!("{:.4}", m.recall)),
+    )
+}
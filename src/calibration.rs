@@ -0,0 +1,351 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::calculate_diffs;
+use crate::precision::{ReviewSample, Verdict};
+use crate::search::methods::lsh::{
+    DiffSimilarity, Similarity, SimilarityMetrics, DEFAULT_CHANGE_WEIGHT,
+};
+use crate::{Commit, Result};
+use firestorm::profile_fn;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A pair of commits for which a reviewer decided whether it is an actual cherry pick
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabeledPair {
+    pub cherry_id: String,
+    pub target_id: String,
+    pub is_pick: bool,
+}
+
+impl LabeledPair {
+    /// The labeled pairs of a reviewed precision sample. Items without a verdict or with an
+    /// unsure verdict are skipped.
+    pub fn from_review(sample: &ReviewSample) -> Vec<LabeledPair> {
+        sample
+            .items()
+            .iter()
+            .filter_map(|item| {
+                let is_pick = match item.verdict? {
+                    Verdict::Correct => true,
+                    Verdict::Incorrect => false,
+                    Verdict::Unsure => return None,
+                };
+                Some(LabeledPair {
+                    cherry_id: item.cherry_id.clone(),
+                    target_id: item.target_id.clone(),
+                    is_pick,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The parameters of a calibration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationConfig {
+    /// Whether the weight of the changes in the diff similarity is fitted as well (see
+    /// [DiffSimilarity::with_change_weight]). Otherwise, the default weight is kept.
+    pub fit_weights: bool,
+    /// The number of steps into which the weight interval `[0, 1]` is divided when the weights
+    /// are fitted
+    pub weight_steps: usize,
+    /// The lowest acceptable precision. If set, the operating point with the highest recall at
+    /// this precision is chosen; otherwise, the operating point with the highest F1 score.
+    pub min_precision: Option<f64>,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            fit_weights: false,
+            weight_steps: 10,
+            min_precision: None,
+        }
+    }
+}
+
+/// The expected precision and recall for a similarity threshold. Pairs are reported if their
+/// similarity exceeds the threshold, as in TraditionalLSH.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OperatingPoint {
+    pub threshold: Similarity,
+    pub precision: f64,
+    pub recall: f64,
+}
+
+impl OperatingPoint {
+    /// The harmonic mean of precision and recall
+    pub fn f1(&self) -> f64 {
+        if self.precision + self.recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * self.precision * self.recall / (self.precision + self.recall)
+    }
+}
+
+/// A decision threshold (and metric weights) for [DiffSimilarity] that was fitted to labeled
+/// pairs, together with the expected precision and recall at the chosen operating point. The
+/// calibration can be applied with [crate::TraditionalLSH::with_calibration] and is stored with
+/// the run manifest (see [crate::results::ResultStore::save_calibration]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub threshold: Similarity,
+    /// The weight of the changes in the diff similarity
+    pub change_weight: f64,
+    /// The expected precision at the threshold
+    pub precision: f64,
+    /// The expected recall at the threshold
+    pub recall: f64,
+    /// The number of labeled picks that were used for the calibration
+    pub positives: usize,
+    /// The number of labeled non-picks that were used for the calibration
+    pub negatives: usize,
+    /// The number of labeled pairs whose commits were not among the given commits
+    pub unresolved: usize,
+    /// The operating points of all considered thresholds for the chosen weight, ordered by
+    /// descending threshold
+    pub curve: Vec<OperatingPoint>,
+}
+
+/// Fit the similarity threshold (and, optionally, the metric weights) of [DiffSimilarity] to the
+/// given labeled pairs, whose commits are looked up among the given commits.
+///
+/// Every threshold between two distinct similarities of labeled pairs is considered, and the
+/// threshold is placed midway between the lowest reported and the highest rejected similarity.
+/// Thus, the expected precision and recall are the ones observed on the labeled pairs; they are
+/// only reliable estimates if the labeled pairs are a representative sample.
+///
+/// # Errors
+/// Returns an error if the resolved pairs do not contain at least one pick and one non-pick.
+pub fn calibrate(
+    commits: &mut [Commit],
+    labels: &[LabeledPair],
+    config: &CalibrationConfig,
+) -> Result<Calibration> {
+    profile_fn!(calibrate);
+    calculate_diffs(commits);
+    // Pseudo-commits of the same merge share an id; the first one is used
+    let mut by_id: HashMap<String, &Commit> = HashMap::new();
+    for commit in commits.iter() {
+        by_id.entry(commit.id().to_string()).or_insert(commit);
+    }
+
+    let mut comparator = DiffSimilarity::new();
+    let mut labeled_metrics: Vec<(SimilarityMetrics, bool)> = vec![];
+    let mut unresolved = 0;
+    for label in labels {
+        match (by_id.get(&label.cherry_id), by_id.get(&label.target_id)) {
+            (Some(cherry), Some(target)) => {
+                labeled_metrics.push((comparator.metrics(cherry, target), label.is_pick))
+            }
+            _ => unresolved += 1,
+        }
+    }
+    let positives = labeled_metrics.iter().filter(|(_, pick)| *pick).count();
+    let negatives = labeled_metrics.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return Err(Error::new(ErrorKind::Calibration(format!(
+            "calibration requires labeled picks and non-picks, but found {positives} picks and \
+             {negatives} non-picks ({unresolved} pairs could not be resolved)"
+        ))));
+    }
+
+    let weights: Vec<f64> = if config.fit_weights {
+        let steps = config.weight_steps.max(1);
+        (0..=steps).map(|step| step as f64 / steps as f64).collect()
+    } else {
+        vec![DEFAULT_CHANGE_WEIGHT]
+    };
+    let mut best: Option<(f64, Vec<OperatingPoint>, OperatingPoint)> = None;
+    for weight in weights {
+        let scores: Vec<(Similarity, bool)> = labeled_metrics
+            .iter()
+            .map(|(metrics, pick)| (metrics.combine(weight), *pick))
+            .collect();
+        let curve = operating_points(scores);
+        let point = choose(&curve, config.min_precision);
+        // Ties are resolved in favor of the weight that is closest to the default
+        let better = best.as_ref().is_none_or(|(best_weight, _, best_point)| {
+            match compare(&point, best_point, config.min_precision) {
+                std::cmp::Ordering::Equal => {
+                    (weight - DEFAULT_CHANGE_WEIGHT).abs()
+                        < (best_weight - DEFAULT_CHANGE_WEIGHT).abs()
+                }
+                ordering => ordering.is_gt(),
+            }
+        });
+        if better {
+            best = Some((weight, curve, point));
+        }
+    }
+    let (change_weight, curve, point) = best.expect("at least one weight is considered");
+    if config
+        .min_precision
+        .is_some_and(|min_precision| point.precision < min_precision)
+    {
+        warn!(
+            "no threshold reaches the precision {}; using the threshold with the highest F1 score",
+            config.min_precision.unwrap()
+        );
+    }
+    info!(
+        "calibrated threshold {:.3} (change weight {change_weight:.2}) with an expected precision \
+         of {:.3} and recall of {:.3} on {} labeled pairs",
+        point.threshold,
+        point.precision,
+        point.recall,
+        positives + negatives
+    );
+    Ok(Calibration {
+        threshold: point.threshold,
+        change_weight,
+        precision: point.precision,
+        recall: point.recall,
+        positives,
+        negatives,
+        unresolved,
+        curve,
+    })
+}
+
+/// The operating points of all thresholds that separate the given scores, ordered by descending
+/// threshold
+fn operating_points(mut scores: Vec<(Similarity, bool)>) -> Vec<OperatingPoint> {
+    scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    let positives = scores.iter().filter(|(_, pick)| *pick).count();
+    let (mut true_positives, mut false_positives) = (0, 0);
+    let mut points = vec![];
+    for (index, (score, pick)) in scores.iter().enumerate() {
+        if *pick {
+            true_positives += 1;
+        } else {
+            false_positives += 1;
+        }
+        // Pairs with equal scores cannot be separated
+        let next = scores.get(index + 1).map(|(next, _)| *next);
+        if next == Some(*score) {
+            continue;
+        }
+        points.push(OperatingPoint {
+            threshold: (score + next.unwrap_or(0.0)) / 2.0,
+            precision: true_positives as f64 / (true_positives + false_positives) as f64,
+            recall: true_positives as f64 / positives.max(1) as f64,
+        });
+    }
+    points
+}
+
+/// The best operating point of the given curve
+fn choose(curve: &[OperatingPoint], min_precision: Option<f64>) -> OperatingPoint {
+    let mut best = curve[0];
+    for point in &curve[1..] {
+        // The curve is ordered by descending threshold, so ties keep the higher threshold
+        if compare(point, &best, min_precision).is_gt() {
+            best = *point;
+        }
+    }
+    best
+}
+
+/// Compare two operating points by the objective of the calibration
+fn compare(
+    a: &OperatingPoint,
+    b: &OperatingPoint,
+    min_precision: Option<f64>,
+) -> std::cmp::Ordering {
+    let by_f1 = || {
+        a.f1()
+            .total_cmp(&b.f1())
+            .then(a.precision.total_cmp(&b.precision))
+    };
+    match min_precision {
+        Some(min_precision) => {
+            let (a_valid, b_valid) = (a.precision >= min_precision, b.precision >= min_precision);
+            match (a_valid, b_valid) {
+                (true, true) => a.recall.total_cmp(&b.recall).then_with(by_f1),
+                (false, false) => by_f1(),
+                (valid, _) => valid.cmp(&!valid),
+            }
+        }
+        None => by_f1(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calibration::{calibrate, operating_points, CalibrationConfig, LabeledPair};
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::Commit;
+
+    #[test]
+    fn operating_points_separate_distinct_scores() {
+        let points = operating_points(vec![
+            (0.9, true),
+            (0.4, false),
+            (0.8, true),
+            (0.6, false),
+            (0.6, true),
+        ]);
+        assert_eq!(points.len(), 4);
+        assert!((points[0].threshold - 0.85).abs() < 1e-9);
+        assert!((points[1].threshold - 0.7).abs() < 1e-9);
+        assert_eq!((points[1].precision, points[1].recall), (1.0, 2.0 / 3.0));
+        // The pick and non-pick with equal scores are reported together
+        assert_eq!((points[2].precision, points[2].recall), (0.75, 1.0));
+        assert!((points[3].threshold - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thresholds_are_fitted_to_labeled_pairs() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("main", "a\nb\nc\nd\ne\n", None, "base");
+        fixture.commit_file("main", "a\nB\nc\nd\ne\n", Some(base), "fix");
+        let release = fixture.commit_file("release", "a\nb\nc\nd\n", Some(base), "release");
+        fixture.commit_file("release", "a\nB\nc\nd\n", Some(release), "pick");
+        fixture.commit_file("other", "a\nb\nc\nd\nE\n", Some(base), "unrelated");
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let id_of = |message: &str| {
+            commits
+                .iter()
+                .find(|c| c.message() == Some(message))
+                .unwrap()
+                .id()
+                .to_string()
+        };
+        let label = |cherry: &str, target: &str, is_pick| LabeledPair {
+            cherry_id: id_of(cherry),
+            target_id: id_of(target),
+            is_pick,
+        };
+        let labels = vec![
+            label("fix", "pick", true),
+            label("fix", "unrelated", false),
+            label("pick", "unrelated", false),
+            LabeledPair {
+                cherry_id: "unknown".to_string(),
+                ..label("fix", "fix", true)
+            },
+        ];
+        // Only labeled picks cannot be calibrated
+        let config = CalibrationConfig::default();
+        assert!(calibrate(&mut commits, &labels[..1], &config).is_err());
+
+        let calibration = calibrate(&mut commits, &labels, &config).unwrap();
+        assert_eq!((calibration.positives, calibration.negatives), (1, 2));
+        assert_eq!(calibration.unresolved, 1);
+        assert_eq!((calibration.precision, calibration.recall), (1.0, 1.0));
+        assert!(calibration.threshold > 0.0 && calibration.threshold < 1.0);
+
+        let config = CalibrationConfig {
+            fit_weights: true,
+            ..CalibrationConfig::default()
+        };
+        let fitted = calibrate(&mut commits, &labels, &config).unwrap();
+        assert_eq!((fitted.precision, fitted.recall), (1.0, 1.0));
+        assert_eq!(fitted.change_weight, calibration.change_weight);
+    }
+}
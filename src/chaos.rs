@@ -0,0 +1,209 @@
+//! Failure injection for testing the harvest pipeline, which is only compiled with the `chaos`
+//! feature. Once a [ChaosConfig] is installed, the pipeline fails at the configured rates at the
+//! following points, so that retries, checkpoints, and the reporting of failures can be tested
+//! deterministically instead of waiting for real-world flakiness:
+//! * [Fault::CloneError]: An attempt to clone a repository fails with a transient network error.
+//! * [Fault::ApiForbidden]: A request to GitHub's API is answered with `403 Forbidden`.
+//! * [Fault::CorruptDiff]: The diff of a commit cannot be parsed.
+//! * [Fault::SlowRepo]: Loading a repository is delayed (see [ChaosConfig::with_delay]).
+//!
+//! # Examples
+//! ```
+//! use cherry_harvest::chaos::{self, ChaosConfig, Fault};
+//!
+//! chaos::install(ChaosConfig::new(42).with_rate(Fault::CloneError, 0.5));
+//! // ... harvest ...
+//! let injected = chaos::injected(Fault::CloneError);
+//! chaos::uninstall();
+//! ```
+use crate::error::{Error, ErrorKind};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use once_cell::sync::Lazy;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// A failure that can be injected into the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Fault {
+    CloneError,
+    ApiForbidden,
+    CorruptDiff,
+    SlowRepo,
+}
+
+/// The rates at which faults are injected. All rates are `0` by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    seed: u64,
+    rates: BTreeMap<Fault, f64>,
+    delay: Duration,
+}
+
+impl ChaosConfig {
+    /// A configuration whose injection decisions are drawn from a random generator with the given
+    /// seed. The decisions for each kind of fault are drawn from their own generator, so that the
+    /// same operations fail in every run with the same seed, as long as the operations of each
+    /// kind run in the same order.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rates: BTreeMap::new(),
+            delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Inject the given fault with the given probability in `[0, 1]`
+    pub fn with_rate(mut self, fault: Fault, rate: f64) -> Self {
+        self.rates.insert(fault, rate.clamp(0.0, 1.0));
+        self
+    }
+
+    /// The delay with which slow repositories are loaded. One second by default.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// The installed configuration with the state of its generators
+#[derive(Debug)]
+struct Chaos {
+    config: ChaosConfig,
+    generators: BTreeMap<Fault, StdRng>,
+    injected: BTreeMap<Fault, usize>,
+}
+
+impl Chaos {
+    fn new(config: ChaosConfig) -> Self {
+        let generators = config
+            .rates
+            .keys()
+            .map(|fault| {
+                let seed = config.seed.wrapping_add(*fault as u64);
+                (*fault, StdRng::seed_from_u64(seed))
+            })
+            .collect();
+        Self {
+            config,
+            generators,
+            injected: BTreeMap::new(),
+        }
+    }
+
+    fn decide(&mut self, fault: Fault) -> bool {
+        let rate = self.config.rates.get(&fault).copied().unwrap_or(0.0);
+        let Some(generator) = self.generators.get_mut(&fault) else {
+            return false;
+        };
+        let inject = generator.gen_bool(rate);
+        if inject {
+            *self.injected.entry(fault).or_default() += 1;
+        }
+        inject
+    }
+}
+
+static CHAOS: Lazy<Mutex<Option<Chaos>>> = Lazy::new(|| Mutex::new(None));
+
+/// Inject failures according to the given configuration until [uninstall] is called. Replaces
+/// the previously installed configuration and resets the counts of injected faults.
+pub fn install(config: ChaosConfig) {
+    *CHAOS.lock().unwrap_or_else(PoisonError::into_inner) = Some(Chaos::new(config));
+}
+
+/// Stop injecting failures
+pub fn uninstall() {
+    *CHAOS.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// The number of times that the given fault has been injected since the configuration was
+/// installed
+pub fn injected(fault: Fault) -> usize {
+    CHAOS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .and_then(|chaos| chaos.injected.get(&fault).copied())
+        .unwrap_or(0)
+}
+
+/// Whether the given fault is to be injected at this point
+pub(crate) fn inject(fault: Fault) -> bool {
+    CHAOS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_mut()
+        .is_some_and(|chaos| chaos.decide(fault))
+}
+
+/// The delay of a slow repository, if the repository is to be slow
+pub(crate) fn delay() -> Option<Duration> {
+    let mut chaos = CHAOS.lock().unwrap_or_else(PoisonError::into_inner);
+    let chaos = chaos.as_mut()?;
+    chaos.decide(Fault::SlowRepo).then_some(chaos.config.delay)
+}
+
+/// The error of an injected clone failure, which is transient like a dropped connection
+pub(crate) fn clone_error(url: &str) -> Error {
+    Error::new(ErrorKind::RepoClone(git2::Error::new(
+        git2::ErrorCode::GenericError,
+        git2::ErrorClass::Net,
+        format!("injected failure while cloning {url}"),
+    )))
+}
+
+/// The error of an injected `403 Forbidden` answer of GitHub's API, which is mapped like real
+/// answers of the API
+pub(crate) async fn forbidden(uri: &str) -> octocrab::Error {
+    let body = serde_json::json!({ "message": format!("injected 403 for {uri}") }).to_string();
+    let response = http::Response::builder()
+        .status(http::StatusCode::FORBIDDEN)
+        .body(
+            Full::new(Bytes::from(body))
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+        .expect("the injected response is valid");
+    match octocrab::map_github_error(response).await {
+        Err(error) => error,
+        Ok(_) => unreachable!("a 403 is always mapped to an error"),
+    }
+}
+
+/// The error of an injected corrupt diff
+pub(crate) fn corrupt_diff(commit_id: git2::Oid) -> Error {
+    Error::new(ErrorKind::DiffParse(format!(
+        "injected corrupt diff of {commit_id}"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chaos::{Chaos, ChaosConfig, Fault};
+
+    #[test]
+    fn decisions_are_reproducible() {
+        let config = ChaosConfig::new(7)
+            .with_rate(Fault::CloneError, 0.5)
+            .with_rate(Fault::SlowRepo, 1.0);
+        let draw = |chaos: &mut Chaos| {
+            (0..100)
+                .map(|_| chaos.decide(Fault::CloneError))
+                .collect::<Vec<bool>>()
+        };
+        let (mut first, mut second) = (Chaos::new(config.clone()), Chaos::new(config));
+        // Decisions of other faults do not shift the decisions of clone errors
+        assert!(second.decide(Fault::SlowRepo));
+        let decisions = draw(&mut first);
+        assert_eq!(decisions, draw(&mut second));
+
+        let failures = decisions.iter().filter(|d| **d).count();
+        assert!(failures > 20 && failures < 80);
+        assert_eq!(first.injected[&Fault::CloneError], failures);
+        assert!(!first.decide(Fault::ApiForbidden));
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(any(test, feature = "test-util"))]
+use chrono::Duration;
+use chrono::{DateTime, Utc};
+use std::fmt::Debug;
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::{Mutex, PoisonError};
+
+/// A source of the current time. Components that depend on the wall clock (e.g., the expiry of
+/// cached fork networks, or the cooldown between requests to GitHub) read the time from a Clock,
+/// so that tests can replace the system clock with a `ManualClock` (available with the feature
+/// `test-util`).
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's wall clock, which is used by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only advances when told to, which makes time-dependent behavior deterministic in
+/// tests. The clock also creates the signatures of synthetic commits (see
+/// [ManualClock::signature]), so that the order of commits in test repositories does not depend
+/// on how fast the commits are created.
+///
+/// The clock is only meant for tests, and is thus only available with the feature `test-util`.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ManualClock {
+    /// A clock that starts at the given time
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    /// A clock that starts at the given Unix timestamp in seconds
+    ///
+    /// # Panics
+    /// Panics if the timestamp is out of the range of [DateTime].
+    pub fn at_timestamp(seconds: i64) -> Self {
+        Self::new(DateTime::from_timestamp(seconds, 0).expect("timestamp out of range"))
+    }
+
+    /// Move the clock forward (or backward, for negative durations) and return the new time
+    pub fn advance(&self, duration: Duration) -> DateTime<Utc> {
+        let mut now = self.now.lock().unwrap_or_else(PoisonError::into_inner);
+        *now += duration;
+        *now
+    }
+
+    /// Set the clock to the given time
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = time;
+    }
+
+    /// A signature with the given name and email at the clock's current time (in UTC), with which
+    /// synthetic commits can be created at controlled times
+    ///
+    /// # Panics
+    /// Panics if git rejects the name or email (e.g., if they contain angle brackets).
+    pub fn signature(&self, name: &str, email: &str) -> git2::Signature<'static> {
+        let time = git2::Time::new(self.now().timestamp(), 0);
+        git2::Signature::new(name, email, &time).expect("invalid signature")
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, ManualClock};
+    use chrono::Duration;
+
+    #[test]
+    fn manual_clock_only_advances_when_told_to() {
+        let clock = ManualClock::at_timestamp(1674832148);
+        assert_eq!(clock.now(), clock.now());
+        let later = clock.advance(Duration::hours(1));
+        assert_eq!(later.timestamp(), 1674832148 + 3600);
+        assert_eq!(clock.now(), later);
+        assert_eq!(
+            clock.signature("test", "test@example.com").when().seconds(),
+            later.timestamp()
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Loading of artifacts that were written by previous versions of cherry-harvest.
+//!
+//! Whenever the format of an artifact changes in a way that breaks its deserialization, the
+//! [SCHEMA_VERSION] is increased and a migration is added that upgrades the raw content of the
+//! artifact from the previous version (see [upgrade]). Artifacts of any previous version are thus
+//! loaded by applying the migrations of all versions in between, and they can be upgraded in place
+//! (see [upgrade_file]), so that long-running studies keep access to their earlier harvests.
+//!
+//! Artifacts that were written before versions were recorded have the version [UNVERSIONED].
+//! Additive changes (e.g., new optional fields of CommitMetadata) do not require migrations,
+//! because older artifacts lack these fields and are read with their defaults.
+use crate::error::{Error, ErrorKind};
+use crate::git::RepoId;
+use crate::journal::{serialize_atomic, Format};
+use crate::results::{RepoEntry, INDEX_FILE};
+use crate::run::HarvestRun;
+use crate::schema::{Artifact, SCHEMA_VERSION};
+use crate::{Result, SearchResult};
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The schema version of artifacts that were written before versions were recorded
+pub const UNVERSIONED: u32 = 0;
+
+/// The schema version with which the given artifact was written
+pub fn version_of(content: &Value) -> u32 {
+    content
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map_or(UNVERSIONED, |version| version as u32)
+}
+
+/// Upgrade the raw content of an artifact of the given kind to the current [SCHEMA_VERSION] by
+/// applying the migrations of all versions between its version and the current one.
+///
+/// # Errors
+/// Returns an error of kind Validation if the artifact was written by a newer version, or if a
+/// migration cannot interpret the content.
+pub fn upgrade(artifact: Artifact, mut content: Value) -> Result<Value> {
+    let version = version_of(&content);
+    if version > SCHEMA_VERSION {
+        return Err(compat_error(
+            artifact,
+            format!(
+                "written with schema version {version}, but this version of cherry-harvest only \
+                 reads versions up to {SCHEMA_VERSION}"
+            ),
+        ));
+    }
+    for from in version..SCHEMA_VERSION {
+        content = migrate(artifact, from, content)?;
+    }
+    Ok(content)
+}
+
+/// Upgrade the content of an artifact of the given kind from the given version to the next one
+fn migrate(artifact: Artifact, from: u32, content: Value) -> Result<Value> {
+    let content = match (artifact, from) {
+        // Samples were lists of repositories
+        (Artifact::Sample, UNVERSIONED) => match content {
+            Value::Sequence(repositories) => Value::Mapping(Mapping::from_iter([(
+                "repositories".into(),
+                repositories.into(),
+            )])),
+            other => other,
+        },
+        // Repositories were keyed by their full names
+        (Artifact::Manifest, UNVERSIONED) => {
+            map_field(artifact, content, "repositories", |repositories| {
+                let Value::Mapping(repositories) = repositories else {
+                    return Ok(repositories);
+                };
+                Ok(Value::Mapping(
+                    repositories
+                        .into_iter()
+                        .map(|(key, entry)| (rekey(&key), entry))
+                        .collect(),
+                ))
+            })?
+        }
+        // Repositories were identified by their full names
+        (Artifact::Network, UNVERSIONED) => {
+            map_field(artifact, content, "repositories", |repositories| {
+                let Value::Mapping(mut repositories) = repositories else {
+                    return Ok(repositories);
+                };
+                for (_, repository) in repositories.iter_mut() {
+                    if let Value::Mapping(repository) = repository {
+                        if let Some(name) = repository.remove("name") {
+                            repository.insert("repo".into(), rekey(&name));
+                        }
+                    }
+                }
+                Ok(Value::Mapping(repositories))
+            })?
+        }
+        // All other artifacts only gained optional fields
+        _ => content,
+    };
+    Ok(stamp(artifact, content, from + 1))
+}
+
+/// Apply the given function to the field with the given name of a mapping
+fn map_field<F: FnOnce(Value) -> Result<Value>>(
+    artifact: Artifact,
+    content: Value,
+    field: &str,
+    f: F,
+) -> Result<Value> {
+    let Value::Mapping(mut mapping) = content else {
+        return Err(compat_error(artifact, "expected a mapping".to_string()));
+    };
+    if let Some(value) = mapping.remove(field) {
+        mapping.insert(field.into(), f(value)?);
+    }
+    Ok(Value::Mapping(mapping))
+}
+
+/// Convert a repository key of an older version into the string form of its RepoId
+fn rekey(key: &Value) -> Value {
+    match key.as_str() {
+        Some(key) => RepoId::parse_legacy(key).to_string().into(),
+        None => key.clone(),
+    }
+}
+
+/// Record the given schema version as the first field of a versioned artifact. Result files are
+/// versioned by their manifest and are not stamped.
+fn stamp(artifact: Artifact, content: Value, version: u32) -> Value {
+    match (artifact, content) {
+        (Artifact::Results, content) => content,
+        (_, Value::Mapping(mapping)) => {
+            let mut stamped = Mapping::from_iter([("schema_version".into(), version.into())]);
+            stamped.extend(
+                mapping
+                    .into_iter()
+                    .filter(|(key, _)| key != "schema_version"),
+            );
+            Value::Mapping(stamped)
+        }
+        (_, content) => content,
+    }
+}
+
+fn compat_error(artifact: Artifact, message: String) -> Error {
+    Error::new(ErrorKind::Validation(format!(
+        "incompatible {artifact} artifact: {message}"
+    )))
+}
+
+/// Load the artifact of the given kind from the given YAML (or JSON) file, which may have been
+/// written by a previous version (see [upgrade]).
+pub fn load<T: DeserializeOwned, P: AsRef<Path>>(artifact: Artifact, path: P) -> Result<T> {
+    let content: Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    Ok(serde_yaml::from_value(upgrade(artifact, content)?)?)
+}
+
+/// Upgrade the artifact of the given kind in the given file to the current schema version in
+/// place. The original file is kept next to the upgraded one with the suffix `.v<version>` (e.g.,
+/// `sample.yaml.v0`). Returns the version from which the artifact was upgraded, or None if it
+/// did not have to be upgraded.
+pub fn upgrade_file<P: AsRef<Path>>(artifact: Artifact, path: P) -> Result<Option<u32>> {
+    let path = path.as_ref();
+    let content: Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let version = version_of(&content);
+    let upgraded = upgrade(artifact, content.clone())?;
+    if upgraded == content {
+        return Ok(None);
+    }
+    fs::copy(path, backup_path(path, version))?;
+    serialize_atomic(path, &upgraded, Format::of(path))?;
+    Ok(Some(version))
+}
+
+/// Upgrade the results folder of a ResultStore in place: The manifest is upgraded (see
+/// [upgrade_file]), and the results files of versions before the ResultStore are moved into the
+/// store (see [legacy_result_files]). Returns the number of upgraded files.
+pub fn upgrade_result_store<P: AsRef<Path>>(folder: P) -> Result<usize> {
+    let folder = folder.as_ref();
+    let index_file = folder.join(INDEX_FILE);
+    let mut upgraded = 0;
+    if index_file.exists() && upgrade_file(Artifact::Manifest, &index_file)?.is_some() {
+        upgraded += 1;
+    }
+    upgraded += legacy_result_files(folder)?.len();
+    // Opening the store moves all legacy result files into the store
+    crate::results::ResultStore::open(folder)?;
+    Ok(upgraded)
+}
+
+/// The path at which the original of an upgraded file of the given version is kept
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}"));
+    PathBuf::from(backup)
+}
+
+/// The results files of versions before the ResultStore, which stored the results of each
+/// repository together with its metadata in `<name>.yaml` directly in the results folder
+pub(crate) fn legacy_result_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        let is_yaml = path.extension().is_some_and(|e| e == "yaml");
+        if path.is_file() && is_yaml && !path.ends_with(INDEX_FILE) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read a results file of a version before the ResultStore (see [legacy_result_files]), which
+/// holds the metadata of the repository (as strings) and its results. Metadata that was not
+/// recorded is unknown (e.g., the run, see [HarvestRun::unknown]).
+pub(crate) fn read_legacy_results(path: &Path) -> Result<(RepoId, RepoEntry, Vec<SearchResult>)> {
+    let (metadata, results): (BTreeMap<String, String>, Vec<SearchResult>) =
+        serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let known = |key: &str| metadata.get(key).filter(|value| *value != "None").cloned();
+    let invalid = |key: &str| {
+        compat_error(
+            Artifact::Results,
+            format!("{}: invalid {key}", path.display()),
+        )
+    };
+
+    let repo = match known("repo_name") {
+        Some(name) => RepoId::parse_legacy(&name),
+        None => RepoId::parse_legacy(&path.file_stem().unwrap_or_default().to_string_lossy()),
+    };
+    let run = match (known("run_id"), known("seed")) {
+        (Some(id), Some(seed)) => HarvestRun::from_parts(
+            id.parse().map_err(|_| invalid("run_id"))?,
+            seed.parse().map_err(|_| invalid("seed"))?,
+        ),
+        _ => HarvestRun::unknown(),
+    };
+    let total_commits = match known("total_number_of_commits") {
+        Some(commits) => commits
+            .parse()
+            .map_err(|_| invalid("total_number_of_commits"))?,
+        None => 0,
+    };
+    let entry = RepoEntry::new(run, known("language"), known("license"), total_commits);
+    Ok((repo, entry, results))
+}
+
+/// Move the given legacy results file aside after its results were moved into the store, so
+/// that it is not migrated again
+pub(crate) fn retire_legacy_file(path: &Path) -> Result<()> {
+    Ok(fs::rename(path, backup_path(path, UNVERSIONED))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compat::{upgrade, upgrade_file, upgrade_result_store, version_of};
+    use crate::results::{ResultStore, INDEX_FILE};
+    use crate::schema::{validate, Artifact, SCHEMA_VERSION};
+    use crate::{load_repo_sample, HarvestRun, RepoId, SearchResult};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    #[test]
+    fn samples_of_previous_versions_are_upgraded_in_place() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let file = directory.path().join("sample.yaml");
+        // Samples were lists of repositories
+        let repository = json!({
+            "id": 1,
+            "name": "repo",
+            "url": "https://api.github.com/repos/owner/repo",
+        });
+        fs::write(&file, serde_yaml::to_string(&[repository]).unwrap()).unwrap();
+        assert_eq!(load_repo_sample(&file).unwrap().len(), 1);
+
+        assert_eq!(upgrade_file(Artifact::Sample, &file).unwrap(), Some(0));
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.starts_with(&format!("schema_version: {SCHEMA_VERSION}\n")));
+        validate(Artifact::Sample, &content).unwrap();
+        assert_eq!(load_repo_sample(&file).unwrap().len(), 1);
+        // The original is kept, and upgraded files are not upgraded again
+        assert!(directory.path().join("sample.yaml.v0").exists());
+        assert_eq!(upgrade_file(Artifact::Sample, &file).unwrap(), None);
+    }
+
+    #[test]
+    fn legacy_results_are_moved_into_the_store() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let result: SearchResult = serde_yaml::from_str(
+            r#"
+search_method: MessageScan
+cherry_and_target:
+  cherry: {id: a, parent_ids: [], message: "", author: "", committer: "", time: ""}
+  target: {id: b, parent_ids: [a], message: "", author: "", committer: "", time: ""}
+"#,
+        )
+        .unwrap();
+        // Results files of versions before the ResultStore
+        let run = HarvestRun::with_seed(42);
+        let metadata = BTreeMap::from([
+            ("repo_name", "Owner/Repo".to_string()),
+            ("run_id", run.id().to_string()),
+            ("seed", "42".to_string()),
+            ("language", "Rust".to_string()),
+            ("license", "None".to_string()),
+            ("total_number_of_results", "1".to_string()),
+            ("total_number_of_commits", "10".to_string()),
+        ]);
+        let legacy_file = directory.path().join("Repo.yaml");
+        let legacy = serde_yaml::to_string(&(&metadata, [&result])).unwrap();
+        fs::write(&legacy_file, legacy).unwrap();
+
+        assert_eq!(upgrade_result_store(directory.path()).unwrap(), 1);
+        assert!(!legacy_file.exists());
+        assert!(directory.path().join("Repo.yaml.v0").exists());
+        let store = ResultStore::open(directory.path()).unwrap();
+        let repo = RepoId::github("owner", "repo");
+        let entry = &store.index().repositories[&repo];
+        assert_eq!(entry.run, run);
+        assert_eq!(entry.language.as_deref(), Some("Rust"));
+        assert_eq!(entry.license, None);
+        assert_eq!(entry.total_commits, 10);
+        assert_eq!(store.load(&repo, "MessageScan").unwrap(), vec![result]);
+
+        // Manifests were keyed by full names
+        let index_file = directory.path().join(INDEX_FILE);
+        let index = fs::read_to_string(&index_file).unwrap();
+        let legacy = index
+            .replace(&format!("schema_version: {SCHEMA_VERSION}\n"), "")
+            .replace("github:owner/repo", "Owner/Repo");
+        fs::write(&index_file, legacy).unwrap();
+        assert_eq!(upgrade_result_store(directory.path()).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&index_file).unwrap(), index);
+    }
+
+    #[test]
+    fn artifacts_of_newer_versions_are_rejected() {
+        let newer: serde_yaml::Value = serde_yaml::from_str("schema_version: 99\n").unwrap();
+        assert_eq!(version_of(&newer), 99);
+        let error = upgrade(Artifact::Report, newer).unwrap_err();
+        assert!(error.to_string().contains("schema version 99"));
+    }
+}
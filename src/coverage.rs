@@ -0,0 +1,425 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::{
+    calculate_diffs, collect_commits_with_config, CollectionConfig, LoadedRepository,
+};
+use crate::search::CommitFacet;
+use crate::{Commit, Result, SearchMethod, SearchResult};
+use firestorm::profile_fn;
+use git2::{ErrorCode, Oid, Repository, RevparseMode, Revwalk};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Whether a commit of the source range is on the target branch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CoverageStatus {
+    /// The commit itself is reachable from the target, e.g., because its branch was merged
+    Contained,
+    /// The commit was picked to the target
+    Picked {
+        /// The ids of the picks on the target
+        targets: Vec<String>,
+        /// The search methods that found the picks
+        methods: Vec<String>,
+    },
+    /// Neither the commit nor a pick of it is on the target
+    Missing,
+}
+
+/// The coverage status of one commit of the source range
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitCoverage {
+    pub commit_id: String,
+    #[serde(flatten)]
+    pub status: CoverageStatus,
+}
+
+impl CommitCoverage {
+    /// Whether the commit is on the target, either itself or as a pick
+    pub fn is_covered(&self) -> bool {
+        self.status != CoverageStatus::Missing
+    }
+}
+
+/// The pick coverage of a range of source commits on a target branch, i.e., which of the source
+/// commits have been picked to (or merged into) the target. The coverage shows, e.g., which fixes
+/// of a CVE have not been backported to a release branch yet, and can be checked by CI policies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PickCoverage {
+    /// The status of each source commit, in the order of the source commits
+    pub commits: Vec<CommitCoverage>,
+}
+
+impl PickCoverage {
+    /// Compute the coverage of the given source commits from the given results. Results whose
+    /// commits are not a source commit and a target commit (in either direction) are ignored;
+    /// source commits in the given contained set are reachable from the target.
+    pub fn from_results(
+        source_ids: &[String],
+        target_ids: &HashSet<String>,
+        contained: &HashSet<String>,
+        results: &[SearchResult],
+    ) -> Self {
+        profile_fn!(pick_coverage);
+        let mut picks: BTreeMap<&str, (BTreeSet<&str>, BTreeSet<&str>)> = BTreeMap::new();
+        for result in results {
+            let pair = result.commit_pair();
+            let (cherry, target) = (pair.cherry().id(), pair.target().id());
+            for (source, pick) in [(cherry, target), (target, cherry)] {
+                if target_ids.contains(pick) {
+                    let (targets, methods) = picks.entry(source).or_default();
+                    targets.insert(pick);
+                    methods.insert(result.search_method());
+                }
+            }
+        }
+        let commits = source_ids
+            .iter()
+            .map(|id| {
+                let status = match (contained.contains(id), picks.get(id.as_str())) {
+                    (true, _) => CoverageStatus::Contained,
+                    (false, Some((targets, methods))) => CoverageStatus::Picked {
+                        targets: targets.iter().map(|id| id.to_string()).collect(),
+                        methods: methods.iter().map(|name| name.to_string()).collect(),
+                    },
+                    (false, None) => CoverageStatus::Missing,
+                };
+                CommitCoverage {
+                    commit_id: id.clone(),
+                    status,
+                }
+            })
+            .collect();
+        Self { commits }
+    }
+
+    /// The number of source commits that are on the target, either themselves or as picks
+    pub fn covered(&self) -> usize {
+        self.commits.iter().filter(|c| c.is_covered()).count()
+    }
+
+    /// The fraction of source commits that are on the target, or 1 if there are no source commits
+    pub fn ratio(&self) -> f64 {
+        match self.commits.len() {
+            0 => 1.0,
+            total => self.covered() as f64 / total as f64,
+        }
+    }
+
+    /// The source commits that are not on the target
+    pub fn missing(&self) -> impl Iterator<Item = &CommitCoverage> {
+        self.commits.iter().filter(|c| !c.is_covered())
+    }
+}
+
+/// Compute the pick coverage of the source commits on the target in the given repository with the
+/// given search methods (see [PickCoverage]).
+///
+/// The source is a revision range as understood by `git log` (e.g., `v1.0..main`), a symmetric
+/// difference (e.g., `main...release`, the commits reachable from either side but not from both),
+/// or a single revision, whose history is the source. The target is a revision, typically a branch. Only picks
+/// between the source commits and the commits that are reachable from the target but not from a
+/// source commit are searched (see [SearchMethod::search_across]). Merge commits are ignored.
+/// Commits of branches and tags are searched; source commits that are only reachable otherwise
+/// (e.g., from a commit id or a detached HEAD) are not searched and thus reported as missing,
+/// unless they are contained in the target.
+///
+/// # Errors
+/// Returns a configuration error if a revision cannot be resolved, and an error if the history of
+/// the repository cannot be traversed.
+///
+/// # Examples
+/// ```no_run
+/// use cherry_harvest::coverage::pick_coverage;
+/// use cherry_harvest::prelude::*;
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let location = RepoLocation::Filesystem("path/to/repository".into());
+/// let repository = runtime.block_on(clone_or_load(&location)).unwrap();
+/// let methods: Vec<Box<dyn SearchMethod>> =
+///     vec![Box::<MessageScan>::default(), Box::<PatchIdMatch>::default()];
+/// let coverage = pick_coverage(&repository, "v1.0..main", "release/1.x", &methods).unwrap();
+/// for commit in coverage.missing() {
+///     println!("{} has not been backported", commit.commit_id);
+/// }
+/// ```
+pub fn pick_coverage(
+    repository: &LoadedRepository,
+    source: &str,
+    target: &str,
+    methods: &[Box<dyn SearchMethod>],
+) -> Result<PickCoverage> {
+    profile_fn!(compute_pick_coverage);
+    let git_repository = repository.repository();
+    let mut source_walk = revwalk(git_repository)?;
+    push_source(git_repository, &mut source_walk, source)
+        .map_err(|error| invalid_revision(source, error))?;
+    let source_ids: Vec<Oid> = source_walk
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|error| Error::new(ErrorKind::RepoLoad(error)))?;
+
+    let target_head = resolve(git_repository, target)?;
+    let mut target_walk = revwalk(git_repository)?;
+    target_walk
+        .push(target_head)
+        .map_err(|error| invalid_revision(target, error))?;
+    for id in &source_ids {
+        target_walk
+            .hide(*id)
+            .map_err(|error| Error::new(ErrorKind::RepoLoad(error)))?;
+    }
+    let target_ids: HashSet<String> = target_walk
+        .map(|id| id.map(|id| id.to_string()))
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|error| Error::new(ErrorKind::RepoLoad(error)))?;
+    let contained: HashSet<String> = source_ids
+        .iter()
+        .filter(|id| {
+            **id == target_head
+                || git_repository
+                    .graph_descendant_of(target_head, **id)
+                    .unwrap_or(false)
+        })
+        .map(|id| id.to_string())
+        .collect();
+    // Merge commits are not collected and thus never searched
+    let mut source_merges = HashSet::new();
+    for id in &source_ids {
+        let commit = git_repository
+            .find_commit(*id)
+            .map_err(|error| Error::new(ErrorKind::RepoLoad(error)))?;
+        if commit.parent_count() > 1 {
+            source_merges.insert(*id);
+        }
+    }
+    let source_ids: Vec<String> = source_ids
+        .iter()
+        .filter(|id| !source_merges.contains(*id))
+        .map(|id| id.to_string())
+        .collect();
+
+    // The commits of both sides, which are searched if they are not contained in the target
+    let searched: HashSet<&String> = source_ids
+        .iter()
+        .filter(|id| !contained.contains(*id))
+        .collect();
+    let repositories = std::slice::from_ref(repository);
+    // Ranges are often given by tags (e.g., `v1.0..v1.1`), whose commits may be on no branch
+    let mut config = CollectionConfig::default();
+    config.clone.extra_refs = vec!["refs/tags/*".to_string()];
+    let (mut left, mut right): (Vec<Commit>, Vec<Commit>) =
+        collect_commits_with_config(repositories, &config)
+            .into_iter()
+            .filter(|commit| {
+                let id = commit.id().to_string();
+                searched.contains(&id) || target_ids.contains(&id)
+            })
+            .partition(|commit| searched.contains(&commit.id().to_string()));
+    if methods
+        .iter()
+        .any(|method| method.required_facet() == CommitFacet::Diff)
+    {
+        calculate_diffs(&mut left);
+        calculate_diffs(&mut right);
+    }
+    info!(
+        "searching for picks of {} source commits among {} target commits",
+        left.len(),
+        right.len()
+    );
+    let results: Vec<SearchResult> = methods
+        .iter()
+        .flat_map(|method| method.search_across(&mut left, &mut right))
+        .collect();
+    Ok(PickCoverage::from_results(
+        &source_ids,
+        &target_ids,
+        &contained,
+        &results,
+    ))
+}
+
+fn revwalk(repository: &Repository) -> Result<Revwalk<'_>> {
+    repository
+        .revwalk()
+        .map_err(|error| Error::new(ErrorKind::RepoLoad(error)))
+}
+
+/// Push the commits of the given source revision, range, or symmetric difference to the walk.
+/// Unlike [Revwalk::push_range], this supports symmetric differences (`a...b`), for which the
+/// merge bases of both sides are hidden.
+fn push_source(
+    repository: &Repository,
+    walk: &mut Revwalk,
+    source: &str,
+) -> std::result::Result<(), git2::Error> {
+    if !source.contains("..") {
+        let id = repository.revparse_single(source)?.peel_to_commit()?.id();
+        return walk.push(id);
+    }
+    let revspec = repository.revparse(source)?;
+    let (Some(from), Some(to)) = (revspec.from(), revspec.to()) else {
+        return Err(git2::Error::from_str("a range requires two revisions"));
+    };
+    let from = from.peel_to_commit()?.id();
+    let to = to.peel_to_commit()?.id();
+    walk.push(to)?;
+    if !revspec.mode().contains(RevparseMode::MERGE_BASE) {
+        return walk.hide(from);
+    }
+    walk.push(from)?;
+    // Unrelated histories have no merge base, in which case all commits are pushed
+    let bases = match repository.merge_bases(from, to) {
+        Err(error) if error.code() == ErrorCode::NotFound => return Ok(()),
+        bases => bases?,
+    };
+    for base in bases.iter() {
+        walk.hide(*base)?;
+    }
+    Ok(())
+}
+
+/// The commit to which the given revision resolves
+fn resolve(repository: &Repository, revision: &str) -> Result<Oid> {
+    repository
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.id())
+        .map_err(|error| invalid_revision(revision, error))
+}
+
+fn invalid_revision(revision: &str, error: git2::Error) -> Error {
+    Error::new(ErrorKind::Configuration(format!(
+        "invalid revision '{revision}': {error}"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coverage::{pick_coverage, CoverageStatus, PickCoverage};
+    use crate::git::test_util::TestRepository;
+    use crate::{ExactDiffMatch, MessageScan, SearchMethod};
+    use git2::BranchType;
+
+    #[test]
+    fn coverage_of_a_release_branch() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit("main", &[("a", "a\n"), ("b", "b\n")], &[], "base");
+        // The release branch is created before the fixes and picks the first one
+        let release = fixture.commit(
+            "release",
+            &[("a", "a\n"), ("b", "b\n"), ("r", "r\n")],
+            &[base],
+            "release",
+        );
+        let fix_a = fixture.commit("main", &[("a", "A\n"), ("b", "b\n")], &[base], "fix a");
+        let fix_b = fixture.commit("main", &[("a", "A\n"), ("b", "B\n")], &[fix_a], "fix b");
+        fixture.commit(
+            "release",
+            &[("a", "A\n"), ("b", "b\n"), ("r", "r\n")],
+            &[release],
+            "backport a",
+        );
+        let fixes = [fix_a, fix_b];
+        let (_directory, repository) = fixture.into_loaded();
+        let methods: Vec<Box<dyn SearchMethod>> = vec![
+            Box::<MessageScan>::default(),
+            Box::<ExactDiffMatch>::default(),
+        ];
+
+        let source = format!("{base}..main");
+        let coverage = pick_coverage(&repository, &source, "release", &methods).unwrap();
+        assert_eq!(coverage.commits.len(), 2);
+        assert_eq!(coverage.covered(), 1);
+        assert_eq!(coverage.ratio(), 0.5);
+        match status_of(&coverage, fixes[0]) {
+            CoverageStatus::Picked { targets, methods } => {
+                assert_eq!(targets.len(), 1);
+                assert_eq!(methods, vec!["ExactDiffMatch"]);
+            }
+            status => panic!("unexpected status {status:?}"),
+        }
+        assert_eq!(status_of(&coverage, fixes[1]), CoverageStatus::Missing);
+        assert_eq!(coverage.missing().count(), 1);
+
+        // The base is contained in the release branch
+        let coverage = pick_coverage(&repository, "main", "release", &methods).unwrap();
+        assert_eq!(coverage.commits.len(), 3);
+        assert_eq!(status_of(&coverage, base), CoverageStatus::Contained);
+        assert!(pick_coverage(&repository, "main", "unknown", &methods).is_err());
+    }
+
+    #[test]
+    fn coverage_of_tags_without_merges() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit("main", &[("a", "a\n")], &[], "base");
+        // The fix is only reachable from its tag
+        let fix = fixture.commit("fix", &[("a", "A\n")], &[base], "fix");
+        let repository = fixture.repository();
+        let object = repository.find_object(fix, None).unwrap();
+        let signature = crate::git::test_util::signature();
+        repository
+            .tag("v1.1", &object, &signature, "v1.1", false)
+            .unwrap();
+        let mut branch = repository.find_branch("fix", BranchType::Local).unwrap();
+        branch.delete().unwrap();
+        drop((object, branch));
+        fixture.commit("release", &[("a", "A\n")], &[base], "backport");
+        let feature = fixture.commit("feature", &[("a", "a\n"), ("f", "f\n")], &[base], "f");
+        let files = [("a", "a\n"), ("f", "f\n")];
+        fixture.commit("main", &files, &[base, feature], "merge");
+        let (_directory, repository) = fixture.into_loaded();
+        let methods: Vec<Box<dyn SearchMethod>> = vec![Box::<ExactDiffMatch>::default()];
+
+        let source = format!("{base}..v1.1");
+        let coverage = pick_coverage(&repository, &source, "release", &methods).unwrap();
+        assert_eq!(coverage.commits.len(), 1);
+        assert!(matches!(
+            status_of(&coverage, fix),
+            CoverageStatus::Picked { .. }
+        ));
+
+        // The merge is not reported
+        let source = format!("{base}..main");
+        let coverage = pick_coverage(&repository, &source, "release", &methods).unwrap();
+        assert_eq!(coverage.commits.len(), 1);
+        assert_eq!(status_of(&coverage, feature), CoverageStatus::Missing);
+    }
+
+    #[test]
+    fn coverage_of_a_symmetric_difference() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit("main", &[("a", "a\n")], &[], "base");
+        let fix = fixture.commit("main", &[("a", "A\n")], &[base], "fix");
+        let feature = fixture.commit("feature", &[("a", "a\n"), ("f", "f\n")], &[base], "f");
+        let stable = fixture.commit("stable", &[("a", "a\n"), ("s", "s\n")], &[base], "stable");
+        fixture.commit(
+            "stable",
+            &[("a", "A\n"), ("s", "s\n")],
+            &[stable],
+            "backport",
+        );
+        let (_directory, repository) = fixture.into_loaded();
+        let methods: Vec<Box<dyn SearchMethod>> = vec![Box::<ExactDiffMatch>::default()];
+
+        // Both sides since their merge base, which is not part of the source
+        let coverage = pick_coverage(&repository, "main...feature", "stable", &methods).unwrap();
+        assert_eq!(coverage.commits.len(), 2);
+        assert!(matches!(
+            status_of(&coverage, fix),
+            CoverageStatus::Picked { .. }
+        ));
+        assert_eq!(status_of(&coverage, feature), CoverageStatus::Missing);
+        assert!(pick_coverage(&repository, "main...unknown", "stable", &methods).is_err());
+    }
+
+    fn status_of(coverage: &PickCoverage, id: git2::Oid) -> CoverageStatus {
+        coverage
+            .commits
+            .iter()
+            .find(|c| c.commit_id == id.to_string())
+            .map(|c| c.status.clone())
+            .unwrap()
+    }
+}
@@ -1,5 +1,7 @@
+use crate::git::EmptyRepository;
 use git2::Error as G2Error;
 use octocrab::Error as GHError;
+use serde_json::Error as JsonError;
 use serde_yaml::Error as SerdeError;
 use std::fmt::{self, Display, Formatter};
 use std::io::Error as IOError;
@@ -7,12 +9,31 @@ use std::io::Error as IOError;
 #[derive(Debug)]
 pub enum ErrorKind {
     RepoClone(G2Error),
+    /// A clone or fetch made no progress for longer than its stall timeout and was aborted
+    CloneStalled(String),
     RepoLoad(G2Error),
     GitDiff(G2Error),
     DiffParse(String),
+    /// A reviewed precision sample could not be read
+    ReviewParse(String),
     ANNPreprocessing(String),
-    GitHub(GHError),
+    Configuration(String),
+    /// A similarity threshold cannot be calibrated with the given labeled pairs
+    Calibration(String),
+    /// The processing of a repository panicked; contains the panic message
+    Panic(String),
+    /// A journal file is corrupted, or one of its entries cannot be (de)serialized
+    Journal(String),
+    /// An artifact does not match its schema, or was written with another schema version
+    Validation(String),
+    /// None of the searched repositories contains any commits
+    EmptyRepository(EmptyRepository),
+    GitHub(Box<GHError>),
+    /// A request to the API of a forge other than GitHub (e.g., GitLab) failed
+    Forge(String),
     Serde(SerdeError),
+    /// A value cannot be (de)serialized as JSON
+    Json(JsonError),
     IO(IOError),
 }
 
@@ -31,15 +52,30 @@ impl Display for ErrorKind {
             Self::RepoLoad(error) | Self::RepoClone(error) | Self::GitDiff(error) => {
                 write!(f, "{error}")
             }
-            Self::DiffParse(error) | Self::ANNPreprocessing(error) => {
+            Self::DiffParse(error)
+            | Self::CloneStalled(error)
+            | Self::ReviewParse(error)
+            | Self::ANNPreprocessing(error)
+            | Self::Configuration(error)
+            | Self::Calibration(error)
+            | Self::Panic(error)
+            | Self::Journal(error)
+            | Self::Forge(error)
+            | Self::Validation(error) => {
                 write!(f, "{error}")
             }
+            Self::EmptyRepository(emptiness) => {
+                write!(f, "{emptiness}")
+            }
             Self::GitHub(error) => {
                 write!(f, "{error}")
             }
             ErrorKind::Serde(error) => {
                 write!(f, "{error}")
             }
+            ErrorKind::Json(error) => {
+                write!(f, "{error}")
+            }
             ErrorKind::IO(error) => {
                 write!(f, "{error}")
             }
@@ -61,6 +97,18 @@ impl From<SerdeError> for Error {
     }
 }
 
+impl From<JsonError> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self(ErrorKind::Json(error))
+    }
+}
+
+impl From<GHError> for Error {
+    fn from(error: octocrab::Error) -> Self {
+        Self(ErrorKind::GitHub(Box::new(error)))
+    }
+}
+
 impl From<IOError> for Error {
     fn from(error: std::io::Error) -> Self {
         Self(ErrorKind::IO(error))
@@ -0,0 +1,359 @@
+use crate::{Result, SearchResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::Path;
+
+/// The known cherry picks of a repository, against which the results of search methods are
+/// evaluated. Ground truth files list the entries in YAML, e.g.:
+/// ```yaml
+/// - source: 00280496c088622f8a64e8ddb524268f7881f51e
+///   target: 04ebada86336a3562b429ed9af860dfd0b5c6647
+///   method: !CLIGit
+///     message_flagged: true
+///     conflicted: false
+///   change_sets_match: !Fully
+///   context_sets_match: !Partially
+/// ```
+///
+/// # Examples
+/// ```no_run
+/// use cherry_harvest::evaluation::GroundTruth;
+/// use cherry_harvest::SearchResult;
+///
+/// # fn evaluate(results: &[SearchResult]) -> cherry_harvest::Result<()> {
+/// let mut ground_truth = GroundTruth::load("tests/resources/cherries_one_gt.yaml")?;
+/// ground_truth.retain_message_scan();
+/// let evaluation = ground_truth.evaluate(results);
+/// for (method, method_evaluation) in evaluation.methods() {
+///     println!("{method}: F1 {:.2}", method_evaluation.confusion.f1());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroundTruth(Vec<GroundTruthEntry>);
+
+impl GroundTruth {
+    pub fn new(entries: Vec<GroundTruthEntry>) -> Self {
+        Self(entries)
+    }
+
+    /// Load the ground truth from the given YAML file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_yaml::from_reader(File::open(path)?)?)
+    }
+
+    /// Retains only the ground truth entries that are valid for the MessageScan search
+    pub fn retain_message_scan(&mut self) {
+        self.0.retain(|entry| match entry.method {
+            CherryPickMethod::CLIGit {
+                message_flagged, ..
+            }
+            | CherryPickMethod::IDEGit {
+                message_flagged, ..
+            } => message_flagged,
+            CherryPickMethod::Manual => false,
+        });
+    }
+
+    /// Retains only the ground truth entries that are valid for the ExactDiffMatch search
+    pub fn retain_exact_diff(&mut self) {
+        self.0.retain(|entry| {
+            entry.change_sets_match == SetMatch::Fully
+                && entry.context_sets_match == SetMatch::Fully
+        });
+    }
+
+    pub fn entries(&self) -> &Vec<GroundTruthEntry> {
+        &self.0
+    }
+
+    /// Compare the given results with the ground truth, overall and per search method. A result
+    /// matches an entry if it connects the same two commits, regardless of the direction that
+    /// the search method determined for the pick. Several results of one method for the same
+    /// pair (e.g., of pseudo-commits of a merge) count once.
+    pub fn evaluate<'a, I: IntoIterator<Item = &'a SearchResult>>(&self, results: I) -> Evaluation {
+        let mut found: BTreeMap<String, BTreeSet<(String, String)>> = BTreeMap::new();
+        for result in results {
+            let pair = result.commit_pair();
+            found
+                .entry(result.search_method().to_string())
+                .or_default()
+                .insert(unordered(pair.cherry().id(), pair.target().id()));
+        }
+        let all_found = found.values().flatten().cloned().collect();
+        Evaluation {
+            overall: self.evaluate_pairs(&all_found),
+            methods: found
+                .iter()
+                .map(|(method, pairs)| (method.clone(), self.evaluate_pairs(pairs)))
+                .collect(),
+        }
+    }
+
+    fn evaluate_pairs(&self, found: &BTreeSet<(String, String)>) -> MethodEvaluation {
+        let expected: BTreeSet<(String, String)> = self
+            .0
+            .iter()
+            .map(|entry| unordered(&entry.source.0, &entry.target.0))
+            .collect();
+
+        let mut by_pick_method: BTreeMap<String, Recall> = BTreeMap::new();
+        let mut missed = vec![];
+        for entry in &self.0 {
+            let recall = by_pick_method.entry(entry.method.label()).or_default();
+            recall.expected += 1;
+            if found.contains(&unordered(&entry.source.0, &entry.target.0)) {
+                recall.found += 1;
+            } else {
+                missed.push(entry.clone());
+            }
+        }
+        let unexpected: Vec<(String, String)> = found.difference(&expected).cloned().collect();
+
+        MethodEvaluation {
+            confusion: Confusion {
+                true_positives: found.intersection(&expected).count(),
+                false_positives: unexpected.len(),
+                false_negatives: expected.difference(found).count(),
+            },
+            by_pick_method,
+            missed,
+            unexpected,
+        }
+    }
+}
+
+/// The pair of commit ids in a canonical order
+fn unordered(first: &str, second: &str) -> (String, String) {
+    if first <= second {
+        (first.to_string(), second.to_string())
+    } else {
+        (second.to_string(), first.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct GroundTruthEntry {
+    pub source: CommitId,
+    pub target: CommitId,
+    pub method: CherryPickMethod,
+    pub change_sets_match: SetMatch,
+    pub context_sets_match: SetMatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CommitId(pub String);
+
+/// How the developer picked the commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CherryPickMethod {
+    Manual,
+    CLIGit {
+        message_flagged: bool,
+        conflicted: bool,
+    },
+    IDEGit {
+        message_flagged: bool,
+        conflicted: bool,
+    },
+}
+
+impl CherryPickMethod {
+    /// The label by which the recall is broken down, e.g., `CLIGit (flagged, conflicted)`
+    pub fn label(&self) -> String {
+        let (name, message_flagged, conflicted) = match self {
+            CherryPickMethod::Manual => return "Manual".to_string(),
+            CherryPickMethod::CLIGit {
+                message_flagged,
+                conflicted,
+            } => ("CLIGit", message_flagged, conflicted),
+            CherryPickMethod::IDEGit {
+                message_flagged,
+                conflicted,
+            } => ("IDEGit", message_flagged, conflicted),
+        };
+        let flagged = if *message_flagged {
+            "flagged"
+        } else {
+            "unflagged"
+        };
+        let conflicted = if *conflicted { "conflicted" } else { "clean" };
+        format!("{name} ({flagged}, {conflicted})")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SetMatch {
+    // the sets of both commits match exactly
+    Fully,
+    // the sets of both commits match partially (i.e., both have unique changes or context lines)
+    Partially,
+    // the set of the target commit is a superset of the set of the source commit
+    Superset,
+    // the set of the target commit is a subset of the set of the source commit
+    Subset,
+    // The are no commonalities
+    Disjunction,
+}
+
+/// The numbers of correctly found, wrongly found, and missed picks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confusion {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+}
+
+impl Confusion {
+    /// The share of found picks that are in the ground truth; 0 if no picks were found
+    pub fn precision(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    /// The share of picks in the ground truth that were found; 0 if the ground truth is empty
+    pub fn recall(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+
+    /// The harmonic mean of precision and recall
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+fn ratio(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 / total as f64
+}
+
+/// How many picks of one kind (see [CherryPickMethod::label]) were found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recall {
+    pub found: usize,
+    pub expected: usize,
+}
+
+/// The comparison of the results of one search method (or of all methods) with the ground truth
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MethodEvaluation {
+    pub confusion: Confusion,
+    /// The found picks per kind of pick in the ground truth, ordered by kind
+    pub by_pick_method: BTreeMap<String, Recall>,
+    /// The ground truth entries that were not found
+    pub missed: Vec<GroundTruthEntry>,
+    /// The found pairs of commit ids that are not in the ground truth, ordered by id
+    pub unexpected: Vec<(String, String)>,
+}
+
+/// The result of [GroundTruth::evaluate]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    overall: MethodEvaluation,
+    methods: BTreeMap<String, MethodEvaluation>,
+}
+
+impl Evaluation {
+    /// The evaluation of the union of the results of all methods
+    pub fn overall(&self) -> &MethodEvaluation {
+        &self.overall
+    }
+
+    /// The evaluation of the given method, if it found any results. Methods that found nothing
+    /// missed the whole ground truth.
+    pub fn method(&self, method: &str) -> Option<&MethodEvaluation> {
+        self.methods.get(method)
+    }
+
+    /// The evaluations of all methods that found results, ordered by name
+    pub fn methods(&self) -> impl Iterator<Item = (&str, &MethodEvaluation)> {
+        self.methods
+            .iter()
+            .map(|(method, evaluation)| (method.as_str(), evaluation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{CherryPickMethod, GroundTruth, Recall};
+    use crate::git::test_util::ResultBuilder;
+    use crate::SearchResult;
+
+    fn result(method: &str, cherry: &str, target: &str) -> SearchResult {
+        ResultBuilder::new(method)
+            .cherry("id", cherry)
+            .target("id", target)
+            .build()
+    }
+
+    #[test]
+    fn results_are_evaluated_per_method() {
+        let ground_truth: GroundTruth = serde_yaml::from_str(
+            r#"
+- source: aaaa
+  target: bbbb
+  method: !CLIGit
+    message_flagged: true
+    conflicted: false
+  change_sets_match: !Fully
+  context_sets_match: !Fully
+- source: cccc
+  target: dddd
+  method: Manual
+  change_sets_match: !Partially
+  context_sets_match: !Partially
+"#,
+        )
+        .unwrap();
+        let results = [
+            result("MessageScan", "aaaa", "bbbb"),
+            // Found twice, e.g., for two pseudo-commits of a merge
+            result("MessageScan", "aaaa", "bbbb"),
+            result("MessageScan", "aaaa", "eeee"),
+            // Picks match regardless of their direction
+            result("TraditionalLSH", "dddd", "cccc"),
+            result("TraditionalLSH", "bbbb", "aaaa"),
+        ];
+        let evaluation = ground_truth.evaluate(&results);
+
+        let message_scan = evaluation.method("MessageScan").unwrap();
+        assert_eq!(message_scan.confusion.true_positives, 1);
+        assert_eq!(message_scan.confusion.false_positives, 1);
+        assert_eq!(message_scan.confusion.false_negatives, 1);
+        assert_eq!(message_scan.confusion.precision(), 0.5);
+        assert_eq!(message_scan.confusion.f1(), 0.5);
+        assert_eq!(message_scan.missed, vec![ground_truth.entries()[1].clone()]);
+        assert_eq!(
+            message_scan.unexpected,
+            vec![("aaaa".to_string(), "eeee".to_string())]
+        );
+        let manual = CherryPickMethod::Manual.label();
+        assert_eq!(
+            message_scan.by_pick_method[&manual],
+            Recall {
+                found: 0,
+                expected: 1
+            }
+        );
+
+        let lsh = evaluation.method("TraditionalLSH").unwrap();
+        assert_eq!(lsh.confusion.f1(), 1.0);
+        assert!(evaluation.method("ExactDiffMatch").is_none());
+        assert_eq!(evaluation.overall().confusion.true_positives, 2);
+        assert_eq!(evaluation.overall().confusion.false_positives, 1);
+        assert_eq!(evaluation.overall().confusion.recall(), 1.0);
+    }
+}
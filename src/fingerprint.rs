@@ -0,0 +1,264 @@
+use crate::git::{Commit, Diff, LineType};
+use crate::hashing::StableHasher;
+use crate::journal::{serialize_atomic, Format};
+use crate::search::methods::lsh::preprocessing::ShingledText;
+use crate::{Result, SearchResult};
+use firestorm::profile_fn;
+use git2::Oid;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::hash::Hasher;
+use std::path::Path;
+
+/// The parameters of the MinHash signatures in a fingerprint export. Signatures can only be
+/// compared if they were created with the same parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FingerprintConfig {
+    /// The number of characters per shingle
+    pub arity: usize,
+    /// The number of hash values per signature
+    pub signature_size: usize,
+}
+
+impl Default for FingerprintConfig {
+    fn default() -> Self {
+        Self {
+            arity: 8,
+            signature_size: 100,
+        }
+    }
+}
+
+/// The irreversible fingerprints of a single commit. Neither the diff nor the message of the
+/// commit can be restored from its fingerprints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitFingerprint {
+    pub id: String,
+    /// The patch-id of the commit's diff, as computed by libgit2 (see `git patch-id`). Equal
+    /// patch-ids indicate equal changes with equal context, regardless of line numbers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch_id: Option<String>,
+    /// The MinHash signature of the lines changed by the commit. The fraction of equal values in
+    /// two signatures estimates the similarity of the changes (see [signature_similarity]).
+    pub signature: Vec<u64>,
+}
+
+/// A pick that was found by a search method, identified by the ids of cherry and target only
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PickFingerprint {
+    pub search_method: String,
+    pub cherry: String,
+    pub target: String,
+}
+
+impl From<&SearchResult> for PickFingerprint {
+    fn from(result: &SearchResult) -> Self {
+        Self {
+            search_method: result.search_method().to_string(),
+            cherry: result.commit_pair().cherry().id().to_string(),
+            target: result.commit_pair().target().id().to_string(),
+        }
+    }
+}
+
+/// An export of a harvest that only contains irreversible fingerprints of the harvested commits
+/// (commit ids, patch-ids, and MinHash signatures) and the ids of the found picks. The export
+/// contains no diff text or messages, so that it can be shared without redistributing source
+/// code. Other researchers can fingerprint their own corpus with the same configuration and match
+/// the fingerprints against the export.
+///
+/// In contrast to the signatures of TraditionalLSH, the signatures do not depend on the
+/// vocabulary of the corpus and are thus comparable across corpora.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FingerprintExport {
+    pub config: FingerprintConfig,
+    pub commits: Vec<CommitFingerprint>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub picks: Vec<PickFingerprint>,
+}
+
+impl FingerprintExport {
+    /// Fingerprint the given commits and results. The diffs of the commits are calculated, if
+    /// they have not been calculated yet.
+    pub fn new(
+        config: FingerprintConfig,
+        commits: &mut [Commit],
+        results: &[SearchResult],
+    ) -> Self {
+        profile_fn!(fingerprint_export);
+        let mut commits: Vec<CommitFingerprint> = commits
+            .iter_mut()
+            .map(|commit| fingerprint_commit(commit, &config))
+            .collect();
+        // Pseudo-commits of the same merge share an id; only the first one is kept
+        commits.sort_by(|a, b| a.id.cmp(&b.id));
+        commits.dedup_by(|a, b| a.id == b.id);
+        debug!("fingerprinted {} commits", commits.len());
+        Self {
+            config,
+            commits,
+            picks: results.iter().map(PickFingerprint::from).collect(),
+        }
+    }
+
+    /// Write the export as YAML, or as JSON if the path has the extension `.json`
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Compute the fingerprints of the given commit
+pub fn fingerprint_commit(commit: &mut Commit, config: &FingerprintConfig) -> CommitFingerprint {
+    let patch_id = patch_id(commit).map(|id| id.to_string());
+    let signature = minhash_signature(&changed_text(commit.calculate_diff()), config);
+    CommitFingerprint {
+        id: commit.id().to_string(),
+        patch_id,
+        signature,
+    }
+}
+
+/// The patch-id of the given commit's diff, or None if it cannot be computed
+pub(crate) fn patch_id(commit: &Commit) -> Option<Oid> {
+    let repository = commit.repository();
+    let g2_commit = repository.find_commit(commit.id()).ok()?;
+    let parent_tree = g2_commit
+        .parent(commit.merge_parent().unwrap_or(0))
+        .and_then(|p| p.tree())
+        .ok();
+    let diff = repository
+        .diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&g2_commit.tree().ok()?),
+            Some(&mut commit.diff_config().options()),
+        )
+        .ok()?;
+    match diff.patchid(None) {
+        Ok(id) => Some(id),
+        Err(error) => {
+            warn!("cannot compute the patch-id of {}: {error}", commit.id());
+            None
+        }
+    }
+}
+
+/// The text of all lines that are added or deleted by the given diff. Context lines and hunk
+/// headers are omitted, so that the text does not depend on line numbers.
+fn changed_text(diff: &Diff) -> String {
+    diff.hunks
+        .iter()
+        .flat_map(|h| h.body())
+        .filter(|l| l.line_type() != LineType::Context)
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// The stable hash (see [StableHasher]) of the given text, seeded with the given seed
+fn seeded_hash(text: &str, seed: u64) -> u64 {
+    let mut hasher = StableHasher::default();
+    hasher.write(&seed.to_le_bytes());
+    hasher.write(text.as_bytes());
+    hasher.finish()
+}
+
+/// The MinHash signature of the given text, with one seeded hash function per signature value
+pub fn minhash_signature(text: &str, config: &FingerprintConfig) -> Vec<u64> {
+    let shingled_text = ShingledText::new(text, config.arity);
+    (0..config.signature_size as u64)
+        .map(|seed| {
+            shingled_text
+                .shingles()
+                .iter()
+                .map(|shingle| seeded_hash(shingle, seed))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+/// The fraction of equal values in the given signatures, which estimates the Jaccard similarity
+/// of the shingles of the fingerprinted texts. Returns 0 for signatures of different sizes.
+pub fn signature_similarity(a: &[u64], b: &[u64]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    equal as f64 / a.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::{
+        minhash_signature, signature_similarity, FingerprintConfig, FingerprintExport,
+    };
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+
+    #[test]
+    fn equal_changes_have_equal_patch_ids() {
+        let fixture = TestRepository::new();
+        let lines = |changed: &str| {
+            (1..=10)
+                .map(|i| format!("{i}\n"))
+                .collect::<String>()
+                .replace("9\n", changed)
+        };
+        let base = fixture.commit_file("base", &lines("9\n"), None, "base");
+        let other = fixture.commit_file(
+            "other",
+            &format!("0\n{}", lines("9\n")),
+            Some(base),
+            "other",
+        );
+        // The same change is applied at different positions of the file
+        fixture.commit_file("cherry", &lines("nine\n"), Some(base), "cherry");
+        fixture.commit_file(
+            "target",
+            &format!("0\n{}", lines("nine\n")),
+            Some(other),
+            "target",
+        );
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<_> = collect_commits(&repos).into_iter().collect();
+        let export = FingerprintExport::new(FingerprintConfig::default(), &mut commits, &[]);
+        assert_eq!(export.commits.len(), 4);
+        let fingerprint = |message| {
+            let commit = commits
+                .iter()
+                .find(|c| c.message() == Some(message))
+                .unwrap();
+            export
+                .commits
+                .iter()
+                .find(|f| f.id == commit.id().to_string())
+                .unwrap()
+        };
+        let (cherry, target) = (fingerprint("cherry"), fingerprint("target"));
+        assert!(cherry.patch_id.is_some());
+        assert_eq!(cherry.patch_id, target.patch_id);
+        assert_eq!(cherry.signature, target.signature);
+        assert_ne!(cherry.patch_id, fingerprint("other").patch_id);
+    }
+
+    #[test]
+    fn signatures_estimate_similarity() {
+        let config = FingerprintConfig::default();
+        let text = "+fn main() {\n+    println!(\"Hello, world!\");\n+}\n";
+        let close = "+fn main() {\n+    println!(\"Hello, World!\");\n+}\n";
+        let far = "-import os\n+import sys\n";
+        let signature = minhash_signature(text, &config);
+        assert_eq!(signature.len(), config.signature_size);
+        // Signatures are deterministic, so that they can be compared across corpora
+        assert_eq!(signature, minhash_signature(text, &config));
+        let close = signature_similarity(&signature, &minhash_signature(close, &config));
+        let far = signature_similarity(&signature, &minhash_signature(far, &config));
+        assert!(close > 0.5, "{close}");
+        assert!(far < close, "{far} < {close}");
+    }
+}
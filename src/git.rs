@@ -1,27 +1,64 @@
+pub mod annotation;
+mod config;
+mod credentials;
+mod diff_cache;
+mod filter;
 pub mod github;
+mod history;
+pub mod license;
+mod noise;
+mod pin;
+pub mod progress;
+mod repo_id;
+mod resources;
+#[cfg(test)]
+pub(crate) mod test_util;
 mod util;
+mod workspace;
 
 use chrono::{DateTime, Utc};
 use derivative::Derivative;
 use firestorm::{profile_fn, profile_method, profile_section};
 use git2::{Commit as G2Commit, Oid, Repository as G2Repository, Signature};
 use git2::{Diff as G2Diff, DiffFormat, Time};
-use log::info;
+use log::{info, warn};
 use octocrab::models::Repository as OctoRepo;
 use octocrab::models::RepositoryId;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::cmp::Ordering::Equal;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Display, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 use temp_dir::TempDir;
 use tokio::time;
 
+pub use config::{CloneConfig, CollectionConfig, DiffConfig, MergeStrategy, RetryPolicy};
+pub use credentials::Credentials;
+pub use diff_cache::DiffCache;
+pub use filter::CommitFilter;
+pub use history::{CommitHistory, Commits, Provenance};
+pub use noise::NoiseList;
+pub use pin::RepoPin;
+pub use repo_id::RepoId;
+pub(crate) use resources::resources_instance;
+pub use resources::{resource_usage, CloneLease, ResourceLimits, ResourceUsage};
+pub use util::calculate_diffs;
+pub use util::calculate_diffs_with_workers;
 pub use util::clone_or_load;
+pub(crate) use util::clone_or_load_reserved;
+pub use util::clone_or_load_with;
 pub use util::collect_commits;
+pub use util::collect_commits_with_config;
+pub use util::decode_message;
+pub use workspace::{cleanup_failures, remove_orphaned_clones, OrphanedClones};
 
-use crate::git::util::commit_diff;
+use crate::clock::{Clock, SystemClock};
+use crate::git::annotation::Annotations;
+use crate::git::license::{detect_license_in_head, SpdxId};
+use crate::git::util::cached_commit_diff;
 
 /// All relevant data for a commit.
 #[derive(Clone, Derivative)]
@@ -36,16 +73,50 @@ pub struct Commit<'repo: 'com, 'com> {
     commit: G2Commit<'com>,
     #[derivative(PartialEq = "ignore", Hash = "ignore")]
     diff: Option<Diff>,
+    /// The decoded message, if the message is not valid UTF-8
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    decoded_message: Option<String>,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    diff_config: DiffConfig,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    diff_cache: Option<Arc<DiffCache>>,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    annotations: Annotations,
+    // Distinguishes the pseudo-commits of a merge that is collected once per parent
+    diff_base: DiffBase,
+}
+
+/// The parent(s) against which the diff of a commit is calculated (see [MergeStrategy])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum DiffBase {
+    FirstParent,
+    Combined,
+    Parent(usize),
 }
 
 impl<'com, 'repo> Commit<'com, 'repo> {
-    fn new(repository: &'repo G2Repository, commit: G2Commit<'com>) -> Commit<'repo, 'com> {
+    fn new(
+        repository: &'repo G2Repository,
+        commit: G2Commit<'com>,
+        diff_config: DiffConfig,
+        diff_cache: Option<Arc<DiffCache>>,
+        diff_base: DiffBase,
+    ) -> Commit<'repo, 'com> {
+        let decoded_message = match commit.message() {
+            Some(_) => None,
+            None => Some(decode_message(commit.message_bytes())),
+        };
         Self {
             commit_id: commit.id(),
             parent_ids: commit.parent_ids().collect(),
             repository,
             commit,
             diff: None,
+            decoded_message,
+            diff_config,
+            diff_cache,
+            annotations: Annotations::new(),
+            diff_base,
         }
     }
 
@@ -53,15 +124,18 @@ impl<'com, 'repo> Commit<'com, 'repo> {
         self.commit.id()
     }
 
+    /// The message of the commit. Messages that are not valid UTF-8 (e.g., of commits created
+    /// with `i18n.commitEncoding=latin1`, or with mixed encodings) are decoded with a Latin-1
+    /// fallback for the invalid bytes (see [decode_message]).
     pub fn message(&self) -> Option<&str> {
-        self.commit.message()
+        self.commit.message().or(self.decoded_message.as_deref())
     }
 
-    pub fn author(&self) -> Signature {
+    pub fn author(&self) -> Signature<'_> {
         self.commit.author()
     }
 
-    pub fn committer(&self) -> Signature {
+    pub fn committer(&self) -> Signature<'_> {
         self.commit.committer()
     }
 
@@ -69,23 +143,81 @@ impl<'com, 'repo> Commit<'com, 'repo> {
         self.commit.time()
     }
 
+    /// The statistics of this commit's diff, if the diff has already been calculated
+    pub fn diff_stats(&self) -> Option<DiffStats> {
+        self.diff.as_ref().map(Diff::stats)
+    }
+
+    /// The directories of the files that this commit changes, if the diff has already been
+    /// calculated (see [Diff::directories])
+    pub fn changed_directories(&self) -> Option<Vec<String>> {
+        self.diff.as_ref().map(Diff::directories)
+    }
+
     pub fn diff(&self) -> &Diff {
         self.diff
             .as_ref()
             .expect("no diff; it must first be calculcated")
     }
 
+    /// The diff of this commit, if it has already been calculated
+    pub fn calculated_diff(&self) -> Option<&Diff> {
+        self.diff.as_ref()
+    }
+
+    /// Calculate the diff of this commit, if it has not been calculated yet. If the commit has
+    /// been collected with a [DiffCache], a cached diff is reused, and a calculated diff is cached.
     pub fn calculate_diff(&mut self) -> &Diff {
         if self.diff.is_none() {
-            self.diff = Some(commit_diff(self.repository, &self.commit).unwrap());
+            self.diff = Some(
+                cached_commit_diff(
+                    self.repository,
+                    &self.commit,
+                    &self.diff_config,
+                    self.diff_base,
+                    self.diff_cache.as_deref(),
+                )
+                .unwrap(),
+            );
         }
         self.diff()
     }
 
+    /// The options with which the diff of this commit is extracted
+    pub fn diff_config(&self) -> &DiffConfig {
+        &self.diff_config
+    }
+
+    /// The annotations that have been attached to this commit (see [annotation::CommitAnnotator])
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    /// Attach an annotation, replacing any existing annotation with the same key.
+    pub fn annotate(&mut self, key: String, value: String) {
+        self.annotations.insert(key, value);
+    }
+
+    /// Restrict the diff of this commit to the given path prefixes, if it has already been
+    /// calculated (see [Diff::scope_to])
+    pub(crate) fn scope_diff(&mut self, prefixes: &[PathBuf]) {
+        self.diff = self.diff.as_ref().map(|diff| diff.scope_to(prefixes));
+    }
+
     pub fn parent_ids(&self) -> &[Oid] {
         &self.parent_ids
     }
 
+    /// The index of the parent against which this commit is diffed, if it is one of the
+    /// pseudo-commits of a merge that is collected once per parent (see
+    /// [MergeStrategy::PerParent]).
+    pub fn merge_parent(&self) -> Option<usize> {
+        match self.diff_base {
+            DiffBase::Parent(index) => Some(index),
+            DiffBase::FirstParent | DiffBase::Combined => None,
+        }
+    }
+
     pub fn repository(&self) -> &G2Repository {
         self.repository
     }
@@ -97,26 +229,60 @@ pub struct GitRepository {
     pub name: String,
     pub location: RepoLocation,
     pub octorepo: Option<OctoRepo>,
+    /// The SPDX identifier of the repository's license, if it is known
+    pub license: Option<SpdxId>,
+    /// The name of the repository's default branch (e.g., `main`), if it is known from GitHub.
+    /// For loaded repositories, the default branch can also be detected from the repository
+    /// itself (see [LoadedRepository::default_branch]).
+    pub default_branch: Option<String>,
+    /// The heads to which the branches of the repository are pinned after cloning, if any
+    pub pin: Option<RepoPin>,
+    /// The known-noise commits of the repository, which are excluded from the search in addition
+    /// to the commits of CollectionConfig::noise
+    pub noise: NoiseList,
 }
 
 impl GitRepository {
+    /// The id of the repository, which is derived from its GitHub metadata, if it is known, and
+    /// from its location otherwise
+    pub fn repo_id(&self) -> RepoId {
+        match &self.octorepo {
+            Some(octorepo) => RepoId::from(octorepo),
+            None => RepoId::from(&self.location),
+        }
+    }
+
     pub fn new_simple(id: u64, name: String, location: RepoLocation) -> Self {
         Self {
             id: RepositoryId(id),
             name,
             location,
             octorepo: None,
+            license: None,
+            default_branch: None,
+            pin: None,
+            noise: NoiseList::default(),
         }
     }
 }
 
 impl From<OctoRepo> for GitRepository {
     fn from(octo_repo: OctoRepo) -> Self {
+        // GitHub reports licenses that it could not identify as 'NOASSERTION'
+        let license = octo_repo
+            .license
+            .as_ref()
+            .map(|l| l.spdx_id.clone())
+            .filter(|id| id != "NOASSERTION");
         GitRepository {
             id: octo_repo.id,
             name: octo_repo.name.clone(),
             location: RepoLocation::Server(octo_repo.clone_url.as_ref().unwrap().to_string()),
+            default_branch: octo_repo.default_branch.clone(),
             octorepo: Some(octo_repo),
+            license,
+            pin: None,
+            noise: NoiseList::default(),
         }
     }
 }
@@ -138,6 +304,10 @@ impl From<RepoLocation> for GitRepository {
             name,
             location,
             octorepo: None,
+            license: None,
+            default_branch: None,
+            pin: None,
+            noise: NoiseList::default(),
         }
     }
 }
@@ -204,10 +374,129 @@ pub enum LoadedRepository {
     RemoteRepo {
         url: String,
         repository: G2Repository,
-        directory: TempDir,
+        directory: CloneDirectory,
+        metrics: CloneMetrics,
+        /// Releases the clone's resources after the directory has been deleted
+        lease: CloneLease,
     },
 }
 
+/// The directory into which a remote repository was cloned
+#[derive(Debug)]
+pub enum CloneDirectory {
+    /// A temporary directory, which is deleted when the repository is dropped. Directories that
+    /// cannot be deleted are removed by later runs (see [remove_orphaned_clones]).
+    Temporary(TempDir),
+    /// A directory of the clone cache (see CloneConfig::cache_directory), which is kept
+    Cached(PathBuf),
+}
+
+impl CloneDirectory {
+    pub fn path(&self) -> &Path {
+        match self {
+            CloneDirectory::Temporary(directory) => directory.path(),
+            CloneDirectory::Cached(path) => path,
+        }
+    }
+}
+
+impl Drop for CloneDirectory {
+    fn drop(&mut self) {
+        if let CloneDirectory::Temporary(directory) = self {
+            workspace::remove_clone_directory(directory.path());
+        }
+    }
+}
+
+/// The reasons for which a repository contains no commits that can be collected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmptyRepository {
+    /// The repository has no branches (e.g., it was freshly created on GitHub)
+    NoBranches,
+    /// HEAD refers to a branch that does not exist yet, because nothing has been committed
+    UnbornHead,
+}
+
+impl Display for EmptyRepository {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmptyRepository::NoBranches => write!(f, "repository has no branches"),
+            EmptyRepository::UnbornHead => write!(f, "repository has an unborn HEAD"),
+        }
+    }
+}
+
+/// Metrics collected while cloning a remote repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloneMetrics {
+    /// The time that cloning took
+    pub duration: Duration,
+    /// The number of bytes that the clone occupies on disk
+    pub disk_usage: u64,
+    /// Whether the working tree was checked out
+    pub checked_out: bool,
+    /// Whether an existing clone of the clone cache was updated instead of cloning the repository
+    /// (see CloneConfig::cache_directory)
+    pub reused: bool,
+}
+
+impl LoadedRepository {
+    /// The git2 repository that has been loaded
+    pub fn repository(&self) -> &G2Repository {
+        match self {
+            LoadedRepository::LocalRepo { repository, .. }
+            | LoadedRepository::RemoteRepo { repository, .. } => repository,
+        }
+    }
+
+    /// Reset the branches of the repository to the heads of the given pin (see [RepoPin]). Returns
+    /// the number of branches that could be pinned.
+    ///
+    /// Only clones of remote repositories are pinned; local repositories are never modified.
+    pub fn pin(&self, pin: &RepoPin) -> Result<usize, crate::Error> {
+        match self {
+            LoadedRepository::LocalRepo { path, .. } => {
+                warn!("{path} is not pinned, because local repositories are not modified");
+                Ok(0)
+            }
+            LoadedRepository::RemoteRepo { repository, .. } => pin.apply(repository),
+        }
+    }
+
+    /// Detect the license of the repository from the license files in the tree of its HEAD
+    /// commit. The detection does not require a checked out working tree.
+    pub fn detect_license(&self) -> Option<SpdxId> {
+        detect_license_in_head(self.repository())
+    }
+
+    /// The name of the repository's default branch (e.g., `main`), detected from the symbolic
+    /// `HEAD` of the remote or, for local repositories, of the repository itself. The default
+    /// branch is also detected if it does not have any commits yet.
+    pub fn default_branch(&self) -> Option<String> {
+        util::default_branch(self)
+    }
+
+    /// Check whether the repository is empty, i.e., whether none of its branches has any commits.
+    /// Returns the kind of emptiness, or None if the repository has commits that can be
+    /// collected.
+    pub fn check_empty(&self) -> Option<EmptyRepository> {
+        util::check_empty(self)
+    }
+
+    /// Check whether the commit with the given id is reachable from any branch of the repository.
+    pub fn contains_commit(&self, commit_id: Oid) -> bool {
+        util::contains_commit(self, commit_id)
+    }
+
+    /// The metrics of cloning the repository, if it is a remote repository
+    pub fn clone_metrics(&self) -> Option<&CloneMetrics> {
+        match self {
+            LoadedRepository::LocalRepo { .. } => None,
+            LoadedRepository::RemoteRepo { metrics, .. } => Some(metrics),
+        }
+    }
+}
+
 /// Represents a single line in a Diff
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub struct DiffLine {
@@ -298,12 +587,42 @@ impl TryFrom<char> for LineType {
     }
 }
 
+/// Numstat-style statistics of a commit's changes (see `git diff --numstat`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DiffStats {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStats {
+    /// Count the changed files and lines in the given hunks. Changes to binary files are not
+    /// contained in hunks and are thus not counted.
+    fn from_hunks(hunks: &[Hunk]) -> Self {
+        let files = hunks
+            .iter()
+            .map(|h| (&h.old_file, &h.new_file))
+            .collect::<HashSet<_>>();
+        let lines = hunks.iter().flat_map(|h| &h.body);
+        Self {
+            files_changed: files.len(),
+            insertions: lines
+                .clone()
+                .filter(|l| l.line_type == LineType::Addition)
+                .count(),
+            deletions: lines.filter(|l| l.line_type == LineType::Deletion).count(),
+        }
+    }
+}
+
 /// A CommitDiff holds all hunks with the changes that happened in a commit.
 #[derive(Debug, Clone, Derivative, Eq)]
 #[derivative(PartialEq, Hash)]
 pub struct Diff {
     #[derivative(PartialEq = "ignore", Hash = "ignore")]
     diff_text: String,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    stats: DiffStats,
     pub hunks: Vec<Hunk>,
 }
 
@@ -311,6 +630,7 @@ impl Diff {
     pub fn empty() -> Self {
         Diff {
             diff_text: String::new(),
+            stats: DiffStats::default(),
             hunks: vec![],
         }
     }
@@ -319,6 +639,135 @@ impl Diff {
         &self.diff_text
     }
 
+    /// The number of changed files, inserted lines, and deleted lines
+    pub fn stats(&self) -> DiffStats {
+        self.stats
+    }
+
+    /// The distinct directories that contain the changed files, ordered by path. Components are
+    /// separated by `/`, and the root directory of the repository is `.`. Deleted files count for
+    /// the directory from which they were deleted.
+    pub fn directories(&self) -> Vec<String> {
+        let directories: BTreeSet<String> = self
+            .hunks
+            .iter()
+            .filter_map(|hunk| hunk.new_file.as_ref().or(hunk.old_file.as_ref()))
+            .map(|file| {
+                let components: Vec<_> = file
+                    .parent()
+                    .into_iter()
+                    .flat_map(|parent| parent.components())
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect();
+                match components.is_empty() {
+                    true => ".".to_string(),
+                    false => components.join("/"),
+                }
+            })
+            .collect();
+        directories.into_iter().collect()
+    }
+
+    /// Remove all context lines from the hunks (see [DiffConfig::strip_context]). The hunks and
+    /// their headers are kept, and so are the statistics, which only count changed lines.
+    pub(crate) fn strip_context(self) -> Self {
+        profile_method!(strip_context);
+        let hunks: Vec<Hunk> = self
+            .hunks
+            .into_iter()
+            .map(|mut hunk| {
+                hunk.body.retain(|line| {
+                    !matches!(line.line_type, LineType::Context | LineType::ContextEofnl)
+                });
+                hunk.body.shrink_to_fit();
+                hunk
+            })
+            .collect();
+        Self {
+            diff_text: Diff::build_diff_text(&hunks),
+            stats: self.stats,
+            hunks,
+        }
+    }
+
+    /// Fold the file paths of all hunks to lower case and order the hunks by the folded paths (see
+    /// [DiffConfig::fold_path_case]). Renames that only change the case of a path appear as the
+    /// deletion of the file and the addition of the same content; such pairs of hunks are removed.
+    /// The statistics of the folded diff are counted from its hunks.
+    pub(crate) fn fold_path_case(self) -> Self {
+        profile_method!(fold_path_case);
+        let fold = |path: Option<PathBuf>| {
+            path.map(|path| PathBuf::from(path.to_string_lossy().to_lowercase()))
+        };
+        let mut hunks: Vec<Hunk> = self
+            .hunks
+            .into_iter()
+            .map(|mut hunk| {
+                hunk.old_file = fold(hunk.old_file);
+                hunk.new_file = fold(hunk.new_file);
+                hunk
+            })
+            .collect();
+
+        let mut renamed = vec![false; hunks.len()];
+        for deletion in 0..hunks.len() {
+            if !hunks[deletion].replaces_file(LineType::Deletion) {
+                continue;
+            }
+            let addition = (0..hunks.len()).find(|&addition| {
+                !renamed[addition]
+                    && hunks[addition].replaces_file(LineType::Addition)
+                    && hunks[addition].new_file == hunks[deletion].new_file
+                    && hunks[addition].changed_lines() == hunks[deletion].changed_lines()
+            });
+            if let Some(addition) = addition {
+                renamed[deletion] = true;
+                renamed[addition] = true;
+            }
+        }
+        let mut renamed = renamed.into_iter();
+        hunks.retain(|_| !renamed.next().unwrap_or_default());
+        hunks.sort();
+        Self::from_hunks(hunks)
+    }
+
+    /// Restrict the diff to the files below the given path prefixes, whose paths are made relative
+    /// to the prefix (see [crate::search::component::ComponentSearch]). Files that are moved into
+    /// or out of the prefixes keep only the path below them. The statistics of the scoped diff are
+    /// counted from its hunks.
+    pub(crate) fn scope_to(&self, prefixes: &[PathBuf]) -> Self {
+        profile_method!(scope_to);
+        let relative = |path: &Option<PathBuf>| {
+            let path = path.as_ref()?;
+            prefixes
+                .iter()
+                .find_map(|prefix| path.strip_prefix(prefix).ok())
+                .map(Path::to_path_buf)
+        };
+        let hunks = self
+            .hunks
+            .iter()
+            .filter_map(|hunk| {
+                let (old_file, new_file) = (relative(&hunk.old_file), relative(&hunk.new_file));
+                (old_file.is_some() || new_file.is_some()).then(|| Hunk {
+                    old_file,
+                    new_file,
+                    ..hunk.clone()
+                })
+            })
+            .collect();
+        Self::from_hunks(hunks)
+    }
+
+    /// Create a diff with the given hunks
+    pub(crate) fn from_hunks(hunks: Vec<Hunk>) -> Self {
+        Self {
+            diff_text: Diff::build_diff_text(&hunks),
+            stats: DiffStats::from_hunks(&hunks),
+            hunks,
+        }
+    }
+
     fn build_diff_text(hunks: &Vec<Hunk>) -> String {
         profile_fn!(build_diff_text);
         let mut diff_text = String::new();
@@ -397,6 +846,30 @@ impl Hunk {
     pub fn new_start(&self) -> u32 {
         self.new_start
     }
+
+    /// Whether the hunk deletes or adds (depending on the given line type) the entire content of a
+    /// file, i.e., it starts at line 0 in the version without the file and has no other lines
+    fn replaces_file(&self, line_type: LineType) -> bool {
+        let start = match line_type {
+            LineType::Deletion => self.new_start,
+            _ => self.old_start,
+        };
+        start == 0
+            && !self.body.is_empty()
+            && self.body.iter().all(|line| {
+                line.line_type == line_type
+                    || matches!(
+                        (line_type, line.line_type),
+                        (LineType::Deletion, LineType::DelEofnl)
+                            | (LineType::Addition, LineType::AddEofnl)
+                    )
+            })
+    }
+
+    /// The contents of the hunk's lines, without the line types
+    fn changed_lines(&self) -> Vec<&str> {
+        self.body.iter().map(|line| line.content.as_str()).collect()
+    }
 }
 
 impl PartialEq<Self> for Hunk {
@@ -446,6 +919,12 @@ impl<'repo> From<G2Diff<'repo>> for Diff {
     fn from(diff: G2Diff) -> Self {
         profile_fn!(from_g2diff);
         // Converts a git2::Diff to a CommitDiff by reading and converting all information relevant to us.
+        // git2 also counts changes to binary files, which are not contained in the hunks
+        let stats = diff.stats().ok().map(|stats| DiffStats {
+            files_changed: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        });
         let mut hunk_map = HashMap::<String, Hunk>::new();
         {
             profile_section!(diff_print);
@@ -490,6 +969,7 @@ impl<'repo> From<G2Diff<'repo>> for Diff {
             }
             Self {
                 diff_text: Diff::build_diff_text(&hunks),
+                stats: stats.unwrap_or_else(|| DiffStats::from_hunks(&hunks)),
                 hunks,
             }
         }
@@ -497,6 +977,8 @@ impl<'repo> From<G2Diff<'repo>> for Diff {
 }
 
 /// String wrapper for representing patches extracted with IDEA IDEs
+// Internal, only public for benchmarking
+#[doc(hidden)]
 pub struct IdeaPatch(pub String);
 
 impl From<IdeaPatch> for Diff {
@@ -576,7 +1058,7 @@ impl From<IdeaPatch> for Diff {
             hunks.extend(
                 hunk_headers
                     .into_iter()
-                    .zip(hunk_bodies.into_iter())
+                    .zip(hunk_bodies)
                     .map(|(header, body)| Hunk {
                         body,
                         header,
@@ -591,10 +1073,7 @@ impl From<IdeaPatch> for Diff {
             hunk_headers = vec![];
             hunk_bodies = vec![];
         }
-        Diff {
-            diff_text: Diff::build_diff_text(&hunks),
-            hunks,
-        }
+        Diff::from_hunks(hunks)
     }
 }
 
@@ -607,6 +1086,7 @@ struct RequestCooldown {
     queue: VecDeque<DateTime<Utc>>,
     global_cooldown: i64,
     max_requests: usize,
+    clock: Arc<dyn Clock>,
 }
 
 impl Default for RequestCooldown {
@@ -615,13 +1095,26 @@ impl Default for RequestCooldown {
             queue: Default::default(),
             global_cooldown: DEFAULT_GLOBAL_COOLDOWN,
             max_requests: DEFAULT_MAX_REQUESTS,
+            clock: Arc::new(SystemClock),
         }
     }
 }
 
 impl RequestCooldown {
     async fn wait_for_global_cooldown(&mut self) {
-        let now = Utc::now();
+        if let Some(wait_time) = self.wait_time() {
+            // We have to wait, because we cannot do more requests
+            info!("GitHub requires cooldown. Waiting for {wait_time} seconds");
+            time::sleep(Duration::from_secs(wait_time)).await;
+        }
+        // Add a new timestamp that represents the last call
+        self.queue.push_back(self.clock.now());
+    }
+
+    /// The number of seconds to wait before the next request, if the maximum number of requests
+    /// has been reached within the cooldown
+    fn wait_time(&mut self) -> Option<u64> {
+        let now = self.clock.now();
         let mut wait_time = None;
 
         // Remove previous timestamps that have cooled down
@@ -638,14 +1131,37 @@ impl RequestCooldown {
             }
         }
 
-        if self.queue.len() < self.max_requests {
-            // No need to wait, if we can do more requests
-        } else if let Some(wait_time) = wait_time {
-            // We have to wait, because we cannot do more requests
-            info!("GitHub requires cooldown. Waiting for {wait_time} seconds");
-            time::sleep(Duration::from_secs(wait_time)).await;
+        // No need to wait, if we can do more requests
+        wait_time.filter(|_| self.queue.len() >= self.max_requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use crate::git::RequestCooldown;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    #[test]
+    fn requests_wait_for_the_cooldown() {
+        let clock = Arc::new(ManualClock::at_timestamp(1674832148));
+        let mut cooldown = RequestCooldown {
+            global_cooldown: 60,
+            max_requests: 2,
+            clock: clock.clone(),
+            ..RequestCooldown::default()
+        };
+        for _ in 0..2 {
+            assert_eq!(cooldown.wait_time(), None);
+            cooldown
+                .queue
+                .push_back(clock.advance(Duration::try_seconds(10).unwrap()));
         }
-        // Add a new timestamp that represents the last call
-        self.queue.push_back(Utc::now());
+        // The first request cools down 60 seconds after it was made, plus an offset of 5 seconds
+        assert_eq!(cooldown.wait_time(), Some(60 - 10 + 5));
+        clock.advance(Duration::try_seconds(51).unwrap());
+        assert_eq!(cooldown.wait_time(), None);
+        assert_eq!(cooldown.queue.len(), 1);
     }
 }
@@ -0,0 +1,189 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::Commit;
+use crate::Result;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// Key-value annotations of a commit, such as `cve: CVE-2024-1234`
+pub type Annotations = BTreeMap<String, String>;
+
+/// A CommitAnnotator attaches annotations from external sources (e.g., CVE databases or bug
+/// trackers) to commits. Annotators are applied to all commits while they are collected, i.e.,
+/// before the search, and the annotations are carried into the CommitMetadata of the results.
+///
+/// Besides implementing this trait, closures can be used as annotators.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::git::annotation::{Annotators, CommitAnnotator};
+/// use cherry_harvest::git::CollectionConfig;
+/// use cherry_harvest::Commit;
+///
+/// let mut config = CollectionConfig::default();
+/// config.annotators = Annotators::new(vec![Box::new(|commit: &Commit| {
+///     match commit.message().is_some_and(|m| m.contains("security")) {
+///         true => vec![("security".to_string(), "true".to_string())],
+///         false => vec![],
+///     }
+/// })]);
+/// ```
+pub trait CommitAnnotator: Send + Sync {
+    /// Returns the annotations for the given commit. Annotations with the same key as an existing
+    /// annotation replace it.
+    fn annotate(&self, commit: &Commit) -> Vec<(String, String)>;
+}
+
+impl<F> CommitAnnotator for F
+where
+    F: Fn(&Commit) -> Vec<(String, String)> + Send + Sync,
+{
+    fn annotate(&self, commit: &Commit) -> Vec<(String, String)> {
+        self(commit)
+    }
+}
+
+/// A lookup table that annotates commits by their id or by patterns in their message.
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationTable {
+    by_id: HashMap<String, Annotations>,
+    by_message: Vec<(Regex, String, String)>,
+}
+
+impl AnnotationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Annotate the commit with the given id.
+    pub fn annotate_id(&mut self, commit_id: &str, key: &str, value: &str) {
+        self.by_id
+            .entry(commit_id.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+    }
+
+    /// Annotate all commits whose message matches the given regular expression.
+    ///
+    /// # Errors
+    /// Returns a Configuration error if the pattern is not a valid regular expression.
+    pub fn annotate_message(&mut self, pattern: &str, key: &str, value: &str) -> Result<()> {
+        let regex =
+            Regex::new(pattern).map_err(|e| Error::new(ErrorKind::Configuration(e.to_string())))?;
+        self.by_message
+            .push((regex, key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+impl CommitAnnotator for AnnotationTable {
+    fn annotate(&self, commit: &Commit) -> Vec<(String, String)> {
+        let mut annotations = vec![];
+        if let Some(message) = commit.message() {
+            annotations.extend(
+                self.by_message
+                    .iter()
+                    .filter(|(regex, _, _)| regex.is_match(message))
+                    .map(|(_, key, value)| (key.clone(), value.clone())),
+            );
+        }
+        if let Some(by_id) = self.by_id.get(&commit.id().to_string()) {
+            annotations.extend(by_id.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        annotations
+    }
+}
+
+/// The annotators that are applied during the collection of commits. By default, no annotators
+/// are applied.
+#[derive(Clone, Default)]
+pub struct Annotators(Vec<Arc<dyn CommitAnnotator>>);
+
+impl Annotators {
+    pub fn new(annotators: Vec<Box<dyn CommitAnnotator>>) -> Self {
+        Self(annotators.into_iter().map(Arc::from).collect())
+    }
+
+    pub fn push<A: CommitAnnotator + 'static>(&mut self, annotator: A) {
+        self.0.push(Arc::new(annotator));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Apply all annotators to the given commit.
+    pub fn apply(&self, commit: &mut Commit) {
+        for annotator in &self.0 {
+            for (key, value) in annotator.annotate(commit) {
+                commit.annotate(key, value);
+            }
+        }
+    }
+}
+
+impl Debug for Annotators {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Annotators({})", self.0.len())
+    }
+}
+
+// Annotators are equal if they share the same annotator instances
+impl PartialEq for Annotators {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for Annotators {}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::annotation::AnnotationTable;
+    use crate::git::{clone_or_load, collect_commits_with_config, CollectionConfig};
+    use crate::search::CommitMetadata;
+    use crate::RepoLocation;
+    use std::env;
+
+    #[test]
+    fn commits_are_annotated() {
+        let location = RepoLocation::Filesystem(env::current_dir().unwrap());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let loaded_repo = [runtime.block_on(clone_or_load(&location)).unwrap()];
+
+        let commit_id = collect_commits_with_config(&loaded_repo, &CollectionConfig::default())
+            .iter()
+            .next()
+            .unwrap()
+            .id()
+            .to_string();
+        let mut table = AnnotationTable::new();
+        table.annotate_id(&commit_id, "cve", "CVE-0000-0001");
+        table.annotate_message("(?i)^fix", "kind", "fix").unwrap();
+        let mut config = CollectionConfig::default();
+        config.annotators.push(table);
+
+        let commits = collect_commits_with_config(&loaded_repo, &config);
+        let annotated = commits
+            .iter()
+            .find(|c| c.id().to_string() == commit_id)
+            .unwrap();
+        assert_eq!(
+            annotated.annotations().get("cve").map(String::as_str),
+            Some("CVE-0000-0001")
+        );
+        let metadata = CommitMetadata::from(annotated);
+        assert_eq!(metadata.annotations(), annotated.annotations());
+
+        for commit in &commits {
+            let is_fix = commit
+                .message()
+                .is_some_and(|m| m.to_lowercase().starts_with("fix"));
+            assert_eq!(commit.annotations().contains_key("kind"), is_fix);
+        }
+        assert!(AnnotationTable::new()
+            .annotate_message("(", "k", "v")
+            .is_err());
+    }
+}
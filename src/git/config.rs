@@ -0,0 +1,253 @@
+use crate::git::annotation::Annotators;
+use crate::git::credentials::Credentials;
+use crate::git::diff_cache::DiffCache;
+use crate::git::filter::CommitFilter;
+use crate::git::noise::NoiseList;
+use crate::git::resources::ResourceLimits;
+use crate::search::DiffExport;
+use git2::DiffOptions;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Configuration of the collection pipeline that loads commits from repositories and extracts
+/// their diffs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectionConfig {
+    /// The options with which remote repositories are cloned
+    pub clone: CloneConfig,
+    /// The options with which the diffs of collected commits are extracted
+    pub diff: DiffConfig,
+    /// The cache in which extracted diffs are persisted, so that they are reused by later
+    /// collections of the same commits, e.g., when a repository is harvested again. By default,
+    /// diffs are not cached.
+    pub diff_cache: Option<DiffCache>,
+    /// The annotators that attach external information to the collected commits
+    pub annotators: Annotators,
+    /// Whether and how merge commits are collected
+    pub merges: MergeStrategy,
+    /// The known-noise commits that are excluded from all searches (see also
+    /// GitRepository::noise)
+    pub noise: NoiseList,
+    /// The maximum number of parent links between a branch head and the commits that are
+    /// collected for it (e.g., with a depth of `1`, only the heads and their parents are
+    /// collected). This bounds the work per head for harvests of the recent history of huge
+    /// repositories, regardless of their age. By default, the entire history is collected.
+    pub max_history_depth: Option<usize>,
+    /// Globs of the names of the branches whose commits are collected (e.g., `main` or
+    /// `release/*`), in which `*` matches any sequence of characters. Remote branches are matched
+    /// without the name of their remote (i.e., `origin/release/1.0` as `release/1.0`). Extra refs
+    /// are not filtered. By default, the commits of all branches are collected.
+    pub branches: Vec<String>,
+    /// The filter that narrows the collected commits before they are searched, e.g., by time,
+    /// author, or path. By default, all commits are searched.
+    pub filter: CommitFilter,
+    /// Whether the diffs of the cherry and target of each result are stored with the result, and
+    /// whether their lines are redacted. By default, results contain no diffs.
+    pub result_diffs: DiffExport,
+    /// Whether each result is checked for evidence that its pick involved a conflict resolution
+    /// (see crate::search::conflict::annotate_conflicts). The check applies each cherry to the
+    /// parent of its target, which is expensive for large result sets. Disabled by default.
+    pub annotate_conflicts: bool,
+    /// The minimum number of consecutive picks between two branches from which the picks are
+    /// annotated as a rebase (see crate::search::rebase::annotate_rebases), e.g.,
+    /// [crate::search::rebase::DEFAULT_MIN_RUN]. By default, rebases are not detected.
+    pub rebase_min_run: Option<usize>,
+}
+
+/// Whether merge commits are collected and against which of their parents they are diffed. By
+/// default, merge commits are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MergeStrategy {
+    /// Merge commits are not collected
+    #[default]
+    Ignore,
+    /// Merges are diffed against their first parent, i.e., the diff contains all changes that the
+    /// merge brought into the branch it was merged into
+    FirstParent,
+    /// Merges are diffed against their first parent, but only files that differ from every
+    /// parent are kept. Similar to git's combined diff, this drops changes that were taken over
+    /// unchanged from one of the merged branches and keeps changes that were made while merging
+    /// (e.g., conflict resolutions).
+    Combined,
+    /// Each merge is collected once per parent, as a separate pseudo-commit whose diff is the
+    /// diff against that parent (see Commit::merge_parent)
+    PerParent,
+}
+
+/// Options with which remote repositories are cloned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CloneConfig {
+    /// Whether the working tree is checked out after cloning. Commits and their diffs are read
+    /// from the object database only, so a checkout is usually not required. Skipping it saves
+    /// the time and disk space for writing the working tree, which matters for repositories with
+    /// huge files.
+    ///
+    /// Note that libgit2 does not support partial clones (e.g., `--filter=blob:none`); all
+    /// objects are always fetched.
+    pub checkout: bool,
+    /// Whether repositories are cloned bare, i.e., without a working tree and an index. Bare
+    /// clones skip writing the index as well, which the dry-run checkout of non-bare clones still
+    /// writes. If set, [CloneConfig::checkout] is ignored.
+    pub bare: bool,
+    /// The names of the branches that are fetched (e.g., `main` or `release/*`). By default, all
+    /// branches are fetched. The first branch that is not a glob becomes the head of the clone;
+    /// if all branches are globs, the default branch of the remote must be among the fetched
+    /// branches.
+    pub branches: Vec<String>,
+    /// Only fetch the given number of commits of each branch (i.e., a shallow clone). The oldest
+    /// fetched commits appear as root commits whose diffs add all files, and picks whose cherry
+    /// is beyond the depth cannot be found. Note that libgit2 does not support shallow clones of
+    /// local repositories (i.e., `file://` URLs).
+    pub depth: Option<u32>,
+    /// Clone into this directory instead of a temporary directory that is deleted after the
+    /// search. Each repository is cloned into a subdirectory named after its id (see
+    /// [crate::git::RepoId]); if the subdirectory already holds a clone from an earlier run, the
+    /// clone is updated with a fetch instead of being cloned again. Thus, repeated experiments on
+    /// the same sample do not download the repositories again.
+    ///
+    /// Cached clones keep the options with which they were created (e.g., whether they are bare
+    /// or which branches they contain). The directory can be removed at any time to reclaim its
+    /// space.
+    pub cache_directory: Option<PathBuf>,
+    /// The limits on the resources that all clones may occupy at the same time
+    pub limits: ResourceLimits,
+    /// Globs of additional refs outside of the branches (e.g., `refs/notes/*`, or `refs/changes/*`
+    /// in Gerrit mirrors) whose commits are collected as well. Some projects record backports
+    /// only in such refs. Clones fetch the matching refs in addition to the branches; loaded
+    /// repositories must already contain them.
+    ///
+    /// Note that notes refs point to the commits that record the notes, not to the annotated
+    /// commits.
+    pub extra_refs: Vec<String>,
+    /// The maximum number of repositories of a search that are cloned or loaded, and whose
+    /// histories are traversed, at the same time. By default, as many repositories as there are
+    /// available CPUs are processed at the same time. Clones are additionally bounded by the
+    /// resource limits and GitHub's rate limit.
+    pub max_concurrent_repos: Option<usize>,
+    /// Clones and fetches that make no progress (i.e., receive no data) for longer than this are
+    /// aborted with an error of kind CloneStalled, instead of blocking the harvest indefinitely.
+    /// By default, transfers never time out. Transfers that block while waiting for data are only
+    /// aborted if libgit2's server timeout has been set at startup (see
+    /// [set_server_timeout](crate::git::progress::set_server_timeout)).
+    pub stall_timeout: Option<Duration>,
+    /// The credentials with which clones and fetches authenticate, if the server requires
+    /// authentication (e.g., for private repositories)
+    pub credentials: Credentials,
+    /// How often and after which delays clones that failed for transient reasons (e.g., a
+    /// dropped connection or a server error) are attempted again
+    pub retry: RetryPolicy,
+}
+
+impl CloneConfig {
+    /// The number of repositories that are processed at the same time (see
+    /// [CloneConfig::max_concurrent_repos])
+    pub(crate) fn concurrency(&self) -> usize {
+        self.max_concurrent_repos
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .max(1)
+    }
+}
+
+/// The policy by which failed clones and fetches are retried. Only transient failures are retried,
+/// such as network errors, stalled transfers, rate limits, and server errors; permanent failures,
+/// such as repositories that do not exist (anymore) or rejected credentials, fail immediately.
+///
+/// The delay before each retry doubles, starting with the initial backoff, up to the maximum
+/// backoff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts per clone, including the first one; `1` disables retries
+    pub max_attempts: u32,
+    /// The delay before the first retry
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that attempts each clone only once
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The delay after the given failed attempt (starting with `1`) before the next attempt
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Options with which libgit2 extracts the diff of a commit. Options set to None use libgit2's
+/// defaults.
+///
+/// Note that these options change the diffs that search methods operate on. For example, reducing
+/// the number of context lines to `0` shrinks the memory required by the diffs, but also lets
+/// ExactDiffMatch only consider the changed lines of two commits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DiffConfig {
+    /// The number of unchanged lines that surround each hunk (libgit2 default: 3)
+    pub context_lines: Option<u32>,
+    /// The maximum number of unchanged lines between two hunks before they are merged into one
+    /// (libgit2 default: 0)
+    pub interhunk_lines: Option<u32>,
+    /// Files larger than this number of bytes are treated as binary (libgit2 default: 512MB)
+    pub max_file_size: Option<i64>,
+    /// Ignore all whitespace
+    pub ignore_whitespace: bool,
+    /// Ignore changes in the amount of whitespace
+    pub ignore_whitespace_change: bool,
+    /// Ignore whitespace at the end of lines
+    pub ignore_whitespace_eol: bool,
+    /// Fold the file paths of the diffs to lower case. Histories that were written on
+    /// case-insensitive file systems (e.g., the defaults of macOS and Windows) often change the
+    /// case of paths, so that the same change is recorded for different paths in different
+    /// commits. With folded paths, such hunks are compared and ordered independently of the case
+    /// of their paths, and files that are only renamed to another case are not part of the diff.
+    pub fold_path_case: bool,
+    /// Strip all context lines from the hunks of the diffs, so that only the changed lines and the
+    /// paths of the changed files are kept. Unlike reducing the number of context lines to `0`,
+    /// which splits hunks whose changes are only separated by a few unchanged lines, stripping
+    /// keeps the hunks that libgit2 built with the configured context.
+    ///
+    /// Stripping cuts the memory that the diffs occupy by roughly two thirds on typical diffs. It
+    /// should only be enabled for runs whose search methods do not use context lines (e.g.,
+    /// ExactChangeMatch or PatchIdMatch); TraditionalLSH and ExactDiffMatch consider them.
+    pub strip_context: bool,
+}
+
+impl DiffConfig {
+    /// Create the libgit2 DiffOptions for this configuration.
+    pub fn options(&self) -> DiffOptions {
+        let mut options = DiffOptions::new();
+        if let Some(context_lines) = self.context_lines {
+            options.context_lines(context_lines);
+        }
+        if let Some(interhunk_lines) = self.interhunk_lines {
+            options.interhunk_lines(interhunk_lines);
+        }
+        if let Some(max_file_size) = self.max_file_size {
+            options.max_size(max_file_size);
+        }
+        options
+            .ignore_whitespace(self.ignore_whitespace)
+            .ignore_whitespace_change(self.ignore_whitespace_change)
+            .ignore_whitespace_eol(self.ignore_whitespace_eol);
+        options
+    }
+}
@@ -0,0 +1,314 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::{Diff, DiffBase, DiffConfig, DiffLine, DiffStats, Hunk, LineType};
+use crate::hashing::StableHasher;
+use crate::Result;
+use git2::Oid;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// The version of the format of cached diffs. Diffs that were cached with another version are not
+/// reused.
+const FORMAT_VERSION: u32 = 1;
+
+/// A DiffCache persists the diffs of commits on disk, so that repeated harvests of the same
+/// repositories do not have to extract every diff again (see CollectionConfig::diff_cache).
+///
+/// Diffs are stored as one JSON file per commit, keyed by the commit's id and the options with
+/// which the diff was extracted. Because commits with the same id have the same diff in every
+/// repository, cached diffs are also reused for the other repositories of a fork network.
+/// Entries are never invalidated; the directory can be removed at any time to reclaim its space.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiffCache {
+    directory: PathBuf,
+}
+
+/// The serialized form of a Diff. The diff text is not stored, because it is derived from the
+/// hunks.
+#[derive(Serialize, Deserialize)]
+struct CachedDiff {
+    stats: DiffStats,
+    hunks: Vec<CachedHunk>,
+}
+
+/// The serialized form of a Hunk, whose lines are prefixed with the char of their line type
+#[derive(Serialize, Deserialize)]
+struct CachedHunk {
+    header: String,
+    old_file: Option<PathBuf>,
+    new_file: Option<PathBuf>,
+    old_start: u32,
+    new_start: u32,
+    lines: Vec<String>,
+}
+
+impl DiffCache {
+    /// Create a cache that stores diffs in the given directory, which is created on demand
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Load the cached diff of the given commit, if it has been extracted with the given options
+    /// before. Invalid entries are ignored.
+    pub(crate) fn load(&self, id: Oid, config: &DiffConfig, base: DiffBase) -> Option<Diff> {
+        let content = fs::read(self.path(id, config, base)).ok()?;
+        match serde_json::from_slice::<CachedDiff>(&content)
+            .map_err(Error::from)
+            .and_then(Diff::try_from)
+        {
+            Ok(diff) => Some(diff),
+            Err(error) => {
+                debug!("ignoring invalid cached diff of {id}: {error}");
+                None
+            }
+        }
+    }
+
+    /// Store the diff of the given commit, replacing any previously cached diff that has been
+    /// extracted with the same options.
+    ///
+    /// # Errors
+    /// Returns an error if the diff cannot be written, e.g., if it contains paths that are not
+    /// valid UTF-8.
+    pub(crate) fn store(
+        &self,
+        id: Oid,
+        config: &DiffConfig,
+        base: DiffBase,
+        diff: &Diff,
+    ) -> Result<()> {
+        let path = self.path(id, config, base);
+        let content = serde_json::to_vec(&CachedDiff::from(diff))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Entries are written to a temporary file first, so that concurrent readers (e.g., other
+        // harvests) never see partial entries. The name of the file is unique, because threads of
+        // the same process may store the same entry concurrently.
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(format!(".{}.tmp", Uuid::new_v4()));
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })?;
+        Ok(())
+    }
+
+    /// The path of a cached diff: Diffs are grouped by the options with which they are extracted,
+    /// and sharded by the first two hex digits of the commit ids, as in git's object database.
+    fn path(&self, id: Oid, config: &DiffConfig, base: DiffBase) -> PathBuf {
+        let id = id.to_string();
+        let file = match base {
+            DiffBase::FirstParent => format!("{}.json", &id[2..]),
+            DiffBase::Combined => format!("{}.combined.json", &id[2..]),
+            DiffBase::Parent(index) => format!("{}.parent-{index}.json", &id[2..]),
+        };
+        self.directory
+            .join(config_key(config))
+            .join(&id[..2])
+            .join(file)
+    }
+}
+
+/// The name of the directory in which the diffs that were extracted with the given options are
+/// cached. The [FORMAT_VERSION] and each option are hashed explicitly, in a fixed order and with
+/// a fixed byte order, so that the name only changes if one of them changes. Options that are
+/// added to DiffConfig have to be appended.
+fn config_key(config: &DiffConfig) -> String {
+    let DiffConfig {
+        context_lines,
+        interhunk_lines,
+        max_file_size,
+        ignore_whitespace,
+        ignore_whitespace_change,
+        ignore_whitespace_eol,
+        fold_path_case,
+        strip_context,
+    } = config;
+    let mut hasher = StableHasher::default();
+    hasher.write(&FORMAT_VERSION.to_le_bytes());
+    for option in [
+        context_lines.map(i64::from),
+        interhunk_lines.map(i64::from),
+        *max_file_size,
+    ] {
+        match option {
+            Some(value) => {
+                hasher.write(&[1]);
+                hasher.write(&value.to_le_bytes());
+            }
+            None => hasher.write(&[0]),
+        }
+    }
+    for flag in [
+        ignore_whitespace,
+        ignore_whitespace_change,
+        ignore_whitespace_eol,
+        fold_path_case,
+        strip_context,
+    ] {
+        hasher.write(&[u8::from(*flag)]);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+impl From<&Diff> for CachedDiff {
+    fn from(diff: &Diff) -> Self {
+        Self {
+            stats: diff.stats,
+            hunks: diff
+                .hunks
+                .iter()
+                .map(|hunk| CachedHunk {
+                    header: hunk.header.clone(),
+                    old_file: hunk.old_file.clone(),
+                    new_file: hunk.new_file.clone(),
+                    old_start: hunk.old_start,
+                    new_start: hunk.new_start,
+                    lines: hunk.body.iter().map(DiffLine::to_string).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<CachedDiff> for Diff {
+    type Error = Error;
+
+    fn try_from(cached: CachedDiff) -> Result<Self> {
+        let hunks = cached
+            .hunks
+            .into_iter()
+            .map(|hunk| {
+                let body = hunk
+                    .lines
+                    .into_iter()
+                    .map(|line| {
+                        let mut chars = line.chars();
+                        let line_type = chars.next().ok_or_else(|| {
+                            Error::new(ErrorKind::DiffParse("empty cached line".to_string()))
+                        })?;
+                        Ok(DiffLine::new(
+                            chars.as_str().to_string(),
+                            LineType::try_from(line_type)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<DiffLine>>>()?;
+                Ok(Hunk {
+                    body,
+                    header: hunk.header,
+                    old_file: hunk.old_file,
+                    new_file: hunk.new_file,
+                    old_start: hunk.old_start,
+                    new_start: hunk.new_start,
+                })
+            })
+            .collect::<Result<Vec<Hunk>>>()?;
+        Ok(Diff {
+            diff_text: Diff::build_diff_text(&hunks),
+            stats: cached.stats,
+            hunks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::diff_cache::config_key;
+    use crate::git::test_util::TestRepository;
+    use crate::git::{
+        calculate_diffs_with_workers, collect_commits_with_config, CollectionConfig, DiffBase,
+        DiffCache, DiffConfig,
+    };
+    use crate::{Commit, Diff};
+    use temp_dir::TempDir;
+
+    #[test]
+    fn diffs_are_reused_between_runs() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit("main", &[("file", "a\n")], &[], "base");
+        let fix = fixture.commit("main", &[("file", "a\nb\n")], &[base], "fix");
+        fixture.commit(
+            "main",
+            &[("file", "a\nb\n"), ("lib/other", "c\n")],
+            &[fix],
+            "feature",
+        );
+        let (_repo_directory, repository) = fixture.into_loaded();
+        let loaded_repo = [repository];
+        let directory = TempDir::new().unwrap();
+        let cache = DiffCache::new(directory.path());
+        let config = CollectionConfig {
+            diff_cache: Some(cache.clone()),
+            ..CollectionConfig::default()
+        };
+
+        let mut commits: Vec<Commit> = collect_commits_with_config(&loaded_repo, &config)
+            .into_iter()
+            .collect();
+        assert_eq!(commits.len(), 3);
+        calculate_diffs_with_workers(&mut commits, 2);
+        for commit in &commits {
+            let cached = cache
+                .load(commit.id(), commit.diff_config(), DiffBase::FirstParent)
+                .unwrap();
+            assert_eq!(&cached, commit.diff());
+            assert_eq!(cached.diff_text(), commit.diff().diff_text());
+            assert_eq!(cached.stats(), commit.diff().stats());
+            // Diffs extracted with other options are cached separately
+            let other_config = DiffConfig {
+                context_lines: Some(0),
+                ..DiffConfig::default()
+            };
+            assert!(cache
+                .load(commit.id(), &other_config, DiffBase::FirstParent)
+                .is_none());
+        }
+
+        // Later runs read the diffs from the cache instead of extracting them again
+        cache
+            .store(
+                fix,
+                &DiffConfig::default(),
+                DiffBase::FirstParent,
+                &Diff::empty(),
+            )
+            .unwrap();
+        let mut commits: Vec<Commit> = collect_commits_with_config(&loaded_repo, &config)
+            .into_iter()
+            .collect();
+        calculate_diffs_with_workers(&mut commits, 2);
+        let commit = commits.iter().find(|c| c.id() == fix).unwrap();
+        assert!(commit.diff().hunks.is_empty());
+        let mut commits: Vec<Commit> = collect_commits_with_config(&loaded_repo, &config)
+            .into_iter()
+            .collect();
+        let commit = commits.iter_mut().find(|c| c.id() == fix).unwrap();
+        assert!(commit.calculate_diff().hunks.is_empty());
+    }
+
+    #[test]
+    fn config_keys_are_stable() {
+        // Changing these values invalidates all cached diffs
+        assert_eq!(config_key(&DiffConfig::default()), "c8794010590f21ab");
+        let context = DiffConfig {
+            context_lines: Some(0),
+            ..DiffConfig::default()
+        };
+        assert_eq!(config_key(&context), "3a8f1f19833995d1");
+        let stripped = DiffConfig {
+            strip_context: true,
+            ..DiffConfig::default()
+        };
+        assert_eq!(config_key(&stripped), "1086af41f99047bd");
+    }
+}
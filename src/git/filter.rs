@@ -0,0 +1,218 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::Commit;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use firestorm::profile_method;
+use git2::Tree;
+use log::debug;
+use regex::Regex;
+use std::path::Path;
+
+/// A filter that narrows the collected commits before they are searched (see
+/// CollectionConfig::filter), e.g., to the recent history or the components of a huge repository.
+/// Commits are filtered before their diffs are extracted, so filtering also saves the extraction.
+///
+/// A commit is kept if it matches all predicates of the filter; the empty filter keeps all commits.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::prelude::*;
+/// use chrono::{TimeZone, Utc};
+///
+/// let filter = CommitFilter::new()
+///     .after(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap())
+///     .author(r"@example\.com>$")
+///     .unwrap()
+///     .path_prefix("src/");
+/// let config = CollectionConfig {
+///     filter,
+///     ..CollectionConfig::default()
+/// };
+/// assert!(!config.filter.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CommitFilter {
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    author: Option<Regex>,
+    path_prefixes: Vec<String>,
+}
+
+impl CommitFilter {
+    /// The empty filter, which keeps all commits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep only commits that were committed at or after the given time
+    pub fn after(mut self, time: DateTime<Utc>) -> Self {
+        self.after = Some(time);
+        self
+    }
+
+    /// Keep only commits that were committed before the given time
+    pub fn before(mut self, time: DateTime<Utc>) -> Self {
+        self.before = Some(time);
+        self
+    }
+
+    /// Keep only commits whose author matches the given regular expression. The author is matched
+    /// as `Name <email>`.
+    ///
+    /// # Errors
+    /// Returns a configuration error if the pattern is not a valid regular expression.
+    pub fn author(mut self, pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|error| {
+            Error::new(ErrorKind::Configuration(format!(
+                "invalid author pattern '{pattern}': {error}"
+            )))
+        })?;
+        self.author = Some(regex);
+        Ok(self)
+    }
+
+    /// Keep only commits that change a file below the given path (e.g., `src/`), or the file with
+    /// the given path. If several prefixes are given, commits must change a file below one of
+    /// them.
+    pub fn path_prefix(mut self, prefix: &str) -> Self {
+        self.path_prefixes.push(prefix.to_string());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.after.is_none()
+            && self.before.is_none()
+            && self.author.is_none()
+            && self.path_prefixes.is_empty()
+    }
+
+    /// Whether the given commit is kept by the filter. The paths are compared by the trees of the
+    /// commit and its parent, so that no diff has to be extracted.
+    pub fn matches(&self, commit: &Commit) -> bool {
+        let time = DateTime::from_timestamp(commit.time().seconds(), 0).unwrap_or_default();
+        if self.after.is_some_and(|after| time < after)
+            || self.before.is_some_and(|before| time >= before)
+        {
+            return false;
+        }
+        if let Some(author) = &self.author {
+            let signature = commit.author();
+            let name = format!(
+                "{} <{}>",
+                String::from_utf8_lossy(signature.name_bytes()),
+                String::from_utf8_lossy(signature.email_bytes())
+            );
+            if !author.is_match(&name) {
+                return false;
+            }
+        }
+        self.path_prefixes.is_empty() || self.changes_prefix(commit)
+    }
+
+    /// Whether the given commit changes the entry of one of the path prefixes, compared to the
+    /// parent against which it is diffed
+    fn changes_prefix(&self, commit: &Commit) -> bool {
+        let repository = commit.repository();
+        let tree = |id| repository.find_commit(id).and_then(|c| c.tree()).ok();
+        let Some(commit_tree) = tree(commit.id()) else {
+            return false;
+        };
+        let parent = commit.merge_parent().unwrap_or(0);
+        let parent_tree = commit.parent_ids().get(parent).and_then(|id| tree(*id));
+        let entry = |tree: &Tree, prefix: &str| {
+            let path = Path::new(prefix.trim_end_matches('/'));
+            tree.get_path(path).ok().map(|entry| entry.id())
+        };
+        self.path_prefixes.iter().any(|prefix| {
+            let changed = entry(&commit_tree, prefix);
+            let original = parent_tree.as_ref().and_then(|tree| entry(tree, prefix));
+            changed != original
+        })
+    }
+
+    /// Remove all commits that are not kept by the filter from the given commits. Returns the
+    /// number of removed commits.
+    pub fn remove_from(&self, commits: &mut Vec<Commit>) -> usize {
+        profile_method!(filter_commits);
+        if self.is_empty() {
+            return 0;
+        }
+        let count = commits.len();
+        commits.retain(|commit| self.matches(commit));
+        let removed = count - commits.len();
+        debug!("removed {removed} commits that do not match the filter");
+        removed
+    }
+}
+
+impl PartialEq for CommitFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.after == other.after
+            && self.before == other.before
+            && self.author.as_ref().map(Regex::as_str) == other.author.as_ref().map(Regex::as_str)
+            && self.path_prefixes == other.path_prefixes
+    }
+}
+
+impl Eq for CommitFilter {}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::TestRepository;
+    use crate::git::{collect_commits, CommitFilter};
+    use crate::Commit;
+    use chrono::{TimeZone, Utc};
+    use git2::{Oid, Signature, Time};
+
+    #[test]
+    fn commits_are_filtered() {
+        let fixture = TestRepository::new();
+        // Commits a tree with the file `file` in both directories, authored by the given author
+        let commit = |[src, docs]: [&str; 2], parents: &[Oid], author: &str, time| {
+            let email = format!("{}@example.com", author.to_lowercase());
+            let signature = Signature::new(author, &email, &Time::new(time, 0)).unwrap();
+            let files = [("src/file", src), ("docs/file", docs)];
+            fixture.commit_as(&signature, "main", &files, parents, author)
+        };
+        // 2019-01-01, 2021-01-01, and 2022-01-01
+        let base = commit(["a", "a"], &[], "Jane", 1546300800);
+        let src = commit(["b", "a"], &[base], "Joe", 1609459200);
+        commit(["b", "b"], &[src], "Jane", 1640995200);
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let kept = |filter: CommitFilter| {
+            let mut commits = commits.clone();
+            let removed = filter.remove_from(&mut commits);
+            assert_eq!(removed + commits.len(), 3);
+            let mut times: Vec<i64> = commits.iter().map(|c| c.time().seconds()).collect();
+            times.sort();
+            times
+        };
+
+        assert_eq!(kept(CommitFilter::new()).len(), 3);
+        let year = |year| Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            kept(CommitFilter::new().after(year(2020))),
+            vec![1609459200, 1640995200]
+        );
+        assert_eq!(
+            kept(CommitFilter::new().after(year(2020)).before(year(2022))),
+            vec![1609459200]
+        );
+        assert_eq!(
+            kept(CommitFilter::new().author("^Jane <").unwrap()),
+            vec![1546300800, 1640995200]
+        );
+        // The root commit adds all paths
+        assert_eq!(
+            kept(CommitFilter::new().path_prefix("src/")),
+            vec![1546300800, 1609459200]
+        );
+        assert_eq!(
+            kept(CommitFilter::new().path_prefix("docs/file")),
+            vec![1546300800, 1640995200]
+        );
+        assert!(CommitFilter::new().author("(").is_err());
+    }
+}
@@ -1,20 +1,26 @@
-mod extensions;
-
-use crate::error::{Error, ErrorKind};
-use crate::git::github::extensions::ForksExt;
-use crate::git::GitRepository;
+mod cache;
+mod harvest;
+mod propagation;
+mod rate_limit;
+
+use crate::error::Error;
+use crate::git::{GitRepository, RepoPin};
+use crate::sampling::forge::{ForgeClient, GitHubForge};
+use crate::SearchResult;
 use chrono::NaiveDateTime;
 use http::Uri;
 use log::{debug, error};
 use octocrab::models::{Repository as OctoRepo, RepositoryId};
 use octocrab::Page;
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
-use super::RequestCooldown;
+pub use cache::NetworkCache;
+pub use harvest::{NetworkHarvest, NetworkRepository};
+pub use propagation::{
+    ChainCommit, Propagation, PropagationEdge, PropagationPath, PropagationStep, Relation,
+};
 
 /// A ForkNetwork comprises repositories that are connected through parent-child relationships
 /// depending on whether one repo has been forked from the other. The network has the following
@@ -58,6 +64,16 @@ impl ForkNetwork {
     /// * seed: A repository on GitHub
     /// * max_forks: The maximum number of forks in the network that should be retrieved (if desired)
     pub async fn build_from(seed: OctoRepo, max_forks: Option<usize>) -> Self {
+        Self::build_with(&GitHubForge, seed, max_forks).await
+    }
+
+    /// Build a new ForkNetwork for the given repository by listing its forks on the given forge
+    /// (see [ForkNetwork::build_from]).
+    pub async fn build_with(
+        forge: &dyn ForgeClient,
+        seed: OctoRepo,
+        max_forks: Option<usize>,
+    ) -> Self {
         debug!("building fork network for {}:{}", seed.name, seed.id);
         let source_id;
         let mut repository_map = HashMap::new();
@@ -80,7 +96,7 @@ impl ForkNetwork {
         let source = repository_map.get(&source_id).unwrap();
 
         let mut forks_retrieved = 0;
-        let mut forks = retrieve_forks(source, max_forks).await;
+        let mut forks = forks_of(forge, source, max_forks).await;
         if let Some(repos) = forks.as_ref() {
             // Map the source to its children
             let children_ids: Vec<RepositoryId> = repos.iter().map(|c| c.id).collect();
@@ -101,7 +117,7 @@ impl ForkNetwork {
                 let fork_id = fork.id;
                 // Handle all forks of the fork (i.e., the forks children)
                 if let Some(mut children) =
-                    retrieve_forks(fork, max_forks.map(|mf| mf - forks_retrieved)).await
+                    forks_of(forge, fork, max_forks.map(|mf| mf - forks_retrieved)).await
                 {
                     let children_ids: Vec<RepositoryId> = children.iter().map(|c| c.id).collect();
                     forks_retrieved += children_ids.len();
@@ -164,6 +180,65 @@ impl ForkNetwork {
         }
     }
 
+    /// Returns the maximum number of forks that this network was built with, if any was set.
+    pub fn max_forks(&self) -> Option<usize> {
+        self.max_forks
+    }
+
+    /// Returns the fork depth of the repository with the given id (i.e., the number of forks
+    /// between the repository and the network's source). The source has a depth of `0`.
+    ///
+    /// Returns None if the repository is not part of the network.
+    pub fn depth(&self, repo_id: RepositoryId) -> Option<usize> {
+        if !self.repositories.contains_key(&repo_id) {
+            return None;
+        }
+        let mut depth = 0;
+        let mut current = repo_id;
+        while let Some(parent) = self.parents.get(&current) {
+            depth += 1;
+            current = *parent;
+        }
+        Some(depth)
+    }
+
+    /// Returns true if the repository with id `fork` has (directly or transitively) been forked
+    /// from the repository with id `origin`.
+    pub fn is_fork_of(&self, fork: RepositoryId, origin: RepositoryId) -> bool {
+        let mut current = fork;
+        while let Some(parent) = self.parents.get(&current) {
+            if *parent == origin {
+                return true;
+            }
+            current = *parent;
+        }
+        false
+    }
+
+    /// Determine the relationship of the repositories of the given result's cherry and target.
+    ///
+    /// Returns None if the repository of the cherry or target is unknown or not part of this
+    /// network.
+    pub fn fork_context(&self, result: &SearchResult) -> Option<ForkContext> {
+        let cherry_repo = result.commit_pair().cherry().repository()?;
+        let target_repo = result.commit_pair().target().repository()?;
+        Some(ForkContext {
+            network_source: self.source_id,
+            cherry_depth: self.depth(cherry_repo)?,
+            target_depth: self.depth(target_repo)?,
+            target_is_fork_of_cherry: self.is_fork_of(target_repo, cherry_repo),
+            cherry_is_fork_of_target: self.is_fork_of(cherry_repo, target_repo),
+        })
+    }
+
+    /// Annotate each of the given results with its fork context.
+    pub fn annotate_results(&self, results: &mut [SearchResult]) {
+        for result in results {
+            let context = self.fork_context(result);
+            result.set_fork_context(context);
+        }
+    }
+
     /// Returns the number of repositories in the network.
     pub fn len(&self) -> usize {
         self.repositories.len()
@@ -173,12 +248,35 @@ impl ForkNetwork {
         self.len() == 0
     }
 
+    /// Pin the repository with the given id to the given branch heads (see [RepoPin]). Does
+    /// nothing if the repository is not part of the network.
+    pub fn pin(&mut self, repo_id: RepositoryId, pin: Option<RepoPin>) {
+        if let Some(repo) = self.repositories.get_mut(&repo_id) {
+            repo.pin = pin;
+        }
+    }
+
     /// Returns a reference to the source repository.
     pub fn source(&self) -> &GitRepository {
         self.repositories.get(&self.source_id).unwrap()
     }
 }
 
+/// Describes how the repositories of a cherry and its target are related in their ForkNetwork.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkContext {
+    /// The source repository of the network
+    pub network_source: RepositoryId,
+    /// The fork depth of the cherry's repository (0 for the source repository)
+    pub cherry_depth: usize,
+    /// The fork depth of the target's repository (0 for the source repository)
+    pub target_depth: usize,
+    /// Whether the target's repository is a (transitive) fork of the cherry's repository
+    pub target_is_fork_of_cherry: bool,
+    /// Whether the cherry's repository is a (transitive) fork of the target's repository
+    pub cherry_is_fork_of_target: bool,
+}
+
 impl Display for ForkNetwork {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let source = self.repositories.get(&self.source_id).unwrap();
@@ -197,8 +295,8 @@ impl Display for ForkNetwork {
                 start
                     .octorepo
                     .as_ref()
-                    .map(|o| &o.owner.as_ref().unwrap().login)
-                    .unwrap(),
+                    .and_then(|o| o.owner.as_ref())
+                    .map_or("", |owner| owner.login.as_str()),
                 start.name
             )?;
             if let Some(children) = network.forks(start) {
@@ -213,17 +311,13 @@ impl Display for ForkNetwork {
     }
 }
 
-static STATIC_COOLDOWN_INSTANCE: Lazy<arc_swap::ArcSwap<Mutex<RequestCooldown>>> =
-    Lazy::new(|| arc_swap::ArcSwap::from_pointee(Mutex::new(RequestCooldown::default())));
-
-fn cooldown_instance() -> Arc<Mutex<RequestCooldown>> {
-    STATIC_COOLDOWN_INSTANCE.load().clone()
-}
-
 /// Retrieves the forks for the given repository. This function collects forks until all forks have
 /// been retrieved or until the specified maximum number of forks has been retrieved, if one has been
 /// provided.
-async fn retrieve_forks(octo_repo: &OctoRepo, max_forks: Option<usize>) -> Option<Vec<OctoRepo>> {
+pub(crate) async fn retrieve_forks(
+    octo_repo: &OctoRepo,
+    max_forks: Option<usize>,
+) -> Option<Vec<OctoRepo>> {
     match octo_repo.forks_count {
         None => return None,
         Some(0) => return None,
@@ -236,15 +330,7 @@ async fn retrieve_forks(octo_repo: &OctoRepo, max_forks: Option<usize>) -> Optio
 
     // Retrieve the first page with forks
     debug!("retrieve_forks");
-    let gh = cooldown_instance();
-    // Lock the global cooldown tracker until the request completed
-    let mut gh_lock = gh.lock().await;
-    gh_lock.wait_for_global_cooldown().await;
-
-    let api_result: Result<Page<OctoRepo>, octocrab::Error> =
-        octocrab::instance().list_forks(url).await;
-    // drop the lock after the request
-    drop(gh_lock);
+    let api_result: Result<Page<OctoRepo>, octocrab::Error> = rate_limit::get(url.as_str()).await;
     let page = match api_result {
         Ok(page) => page,
         Err(error) => {
@@ -257,6 +343,48 @@ async fn retrieve_forks(octo_repo: &OctoRepo, max_forks: Option<usize>) -> Optio
     collect_repos_from_pages(page, max_forks).await
 }
 
+/// Lists the forks of the given repository on the given forge. Returns None if the repository has
+/// no forks, or if they cannot be listed.
+async fn forks_of(
+    forge: &dyn ForgeClient,
+    repo: &OctoRepo,
+    max_forks: Option<usize>,
+) -> Option<Vec<OctoRepo>> {
+    match forge.forks(repo, max_forks).await {
+        Ok(forks) if !forks.is_empty() => Some(forks),
+        Ok(_) => None,
+        Err(error) => {
+            error!("was not able to list the forks of {}: {error}", repo.name);
+            None
+        }
+    }
+}
+
+/// Retrieves the current heads of all branches of the given repository, so that the repository
+/// can be pinned to its current state.
+pub async fn retrieve_branch_heads(octo_repo: &OctoRepo) -> Result<RepoPin, Error> {
+    let owner = octo_repo
+        .owner
+        .as_ref()
+        .map(|owner| owner.login.clone())
+        .unwrap_or_default();
+    debug!("retrieve_branch_heads of {owner}/{}", octo_repo.name);
+    let path = format!("/repos/{owner}/{}/branches", octo_repo.name);
+    let route = rate_limit::route(&path, &[("per_page", "100".to_string())]);
+    let mut page: Page<octocrab::models::repos::Branch> = rate_limit::get(&route).await?;
+    let mut heads = BTreeMap::new();
+    loop {
+        for branch in &page {
+            heads.insert(branch.name.clone(), branch.commit.sha.clone());
+        }
+        match next_page(&page.next).await {
+            None => break,
+            Some(p) => page = p,
+        }
+    }
+    Ok(RepoPin::new(heads))
+}
+
 /// Retrieve a single repository that was created in the given time range,
 pub async fn repos_created_in_time_range(
     start: NaiveDateTime,
@@ -273,7 +401,7 @@ pub async fn repos_created_in_time_range(
     // Retrieve the first page
     let page = match search_repositories(query.as_str()).await {
         Ok(page) => page,
-        Err(error) => return Err(Error::new(ErrorKind::GitHub(error))),
+        Err(error) => return Err(Error::from(error)),
     };
 
     let repos = collect_repos_from_pages(page, Some(1))
@@ -311,25 +439,22 @@ pub async fn collect_repos_from_pages(
     }
 }
 
+/// Retrieves the given page of the results of the given search query
 pub async fn search_query(
     query: &str,
     sort: &str,
     order: &str,
     results_per_page: u8,
+    page: u32,
 ) -> Result<Page<OctoRepo>, octocrab::Error> {
-    // Lock the global cooldown tracker until the request completed
-    let gh = cooldown_instance();
-    let mut gh_lock = gh.lock().await;
-    gh_lock.wait_for_global_cooldown().await;
-    octocrab::instance()
-        .search()
-        .repositories(query)
-        .sort(sort)
-        .order(order)
-        .per_page(results_per_page)
-        .page(0u32)
-        .send()
-        .await
+    let parameters = [
+        ("q", query.to_string()),
+        ("sort", sort.to_string()),
+        ("order", order.to_string()),
+        ("per_page", results_per_page.to_string()),
+        ("page", page.to_string()),
+    ];
+    rate_limit::get(&rate_limit::route("/search/repositories", &parameters)).await
 }
 
 /// Retrieves the next page for the given page
@@ -352,39 +477,74 @@ pub async fn get_page<T: serde::de::DeserializeOwned>(
     url: &Option<Uri>,
 ) -> Result<Option<Page<T>>, octocrab::Error> {
     debug!("get_page");
-    // Lock the global cooldown tracker until the request completed
-    let gh = cooldown_instance();
-    let mut gh_lock = gh.lock().await;
-    gh_lock.wait_for_global_cooldown().await;
-
-    octocrab::instance().get_page::<T>(url).await
+    match url {
+        Some(url) => rate_limit::get(&url.to_string()).await.map(Some),
+        None => Ok(None),
+    }
 }
 
 pub async fn search_repositories(query: &str) -> Result<Page<OctoRepo>, octocrab::Error> {
     debug!("search_repositories");
-    // Lock the global cooldown tracker until the request completed
-    let gh = cooldown_instance();
-    let mut gh_lock = gh.lock().await;
-    gh_lock.wait_for_global_cooldown().await;
-
-    octocrab::instance()
-        .search()
-        .repositories(query)
-        .send()
-        .await
+    rate_limit::get(&rate_limit::route(
+        "/search/repositories",
+        &[("q", query.to_string())],
+    ))
+    .await
 }
 
-// pub async fn check_search_limit(&self) -> Result<(), octocrab::Error> {
-//     let limit = self.octocrab.ratelimit().get().await?;
-//     let search_limit = limit.resources.search;
-//     if search_limit.remaining < 2 {
-//         info!(
-//             "GitHub API search rate remaining: {}",
-//             search_limit.remaining
-//         );
-//         info!("rate limit too low; waiting for reset");
-//         // The search API is the limiting factor. It resets every minute.
-//         time::sleep(Duration::from_secs(60)).await;
-//     }
-//     Ok(())
-// }
+#[cfg(test)]
+mod tests {
+    use crate::git::github::ForkNetwork;
+    use crate::git::GitRepository;
+    use crate::RepoLocation;
+    use octocrab::models::RepositoryId;
+    use std::collections::HashMap;
+
+    // Builds the network 1 -> 2 -> 3 and 1 -> 4
+    fn network() -> ForkNetwork {
+        let repositories = (1..=4)
+            .map(|id| {
+                let repo = GitRepository::new_simple(
+                    id,
+                    format!("repo-{id}"),
+                    RepoLocation::Server(format!("https://example.org/{id}.git")),
+                );
+                (RepositoryId(id), repo)
+            })
+            .collect();
+        let parents = HashMap::from([
+            (RepositoryId(2), RepositoryId(1)),
+            (RepositoryId(3), RepositoryId(2)),
+            (RepositoryId(4), RepositoryId(1)),
+        ]);
+        let forks = HashMap::from([
+            (RepositoryId(1), vec![RepositoryId(2), RepositoryId(4)]),
+            (RepositoryId(2), vec![RepositoryId(3)]),
+        ]);
+        ForkNetwork {
+            repositories,
+            source_id: RepositoryId(1),
+            parents,
+            forks,
+            max_forks: None,
+        }
+    }
+
+    #[test]
+    fn fork_depths() {
+        let network = network();
+        assert_eq!(network.depth(RepositoryId(1)), Some(0));
+        assert_eq!(network.depth(RepositoryId(3)), Some(2));
+        assert_eq!(network.depth(RepositoryId(4)), Some(1));
+        assert_eq!(network.depth(RepositoryId(5)), None);
+    }
+
+    #[test]
+    fn fork_relationships() {
+        let network = network();
+        assert!(network.is_fork_of(RepositoryId(3), RepositoryId(1)));
+        assert!(network.is_fork_of(RepositoryId(3), RepositoryId(2)));
+        assert!(!network.is_fork_of(RepositoryId(1), RepositoryId(3)));
+        assert!(!network.is_fork_of(RepositoryId(4), RepositoryId(2)));
+    }
+}
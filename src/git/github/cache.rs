@@ -0,0 +1,214 @@
+use crate::clock::{Clock, SystemClock};
+use crate::error::{Error, ErrorKind};
+use crate::git::github::ForkNetwork;
+use crate::git::GitRepository;
+use crate::journal::{serialize_atomic, Format};
+use crate::sampling::forge::{ForgeClient, GitHubForge};
+use crate::Result;
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info, warn};
+use octocrab::models::{Repository as OctoRepo, RepositoryId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A NetworkCache persists the topology of fork networks, so that repeated harvests of the same
+/// sample do not have to retrieve all forks from GitHub again.
+///
+/// Networks are stored as one file per source repository. A cached network is reused as long as it
+/// is younger than the cache's time-to-live and has been built with the same maximum number of
+/// forks.
+#[derive(Debug, Clone)]
+pub struct NetworkCache {
+    directory: PathBuf,
+    ttl: Duration,
+    refresh: bool,
+    clock: Arc<dyn Clock>,
+    forge: Arc<dyn ForgeClient>,
+}
+
+/// The serialized form of a ForkNetwork
+#[derive(Serialize, Deserialize)]
+struct CachedNetwork {
+    created_at: DateTime<Utc>,
+    source_id: RepositoryId,
+    max_forks: Option<usize>,
+    repositories: Vec<OctoRepo>,
+    parents: HashMap<RepositoryId, RepositoryId>,
+    forks: HashMap<RepositoryId, Vec<RepositoryId>>,
+}
+
+impl NetworkCache {
+    /// Create a cache that stores networks in the given directory and reuses them for the given
+    /// time-to-live.
+    pub fn new<P: AsRef<Path>>(directory: P, ttl: Duration) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            ttl,
+            refresh: false,
+            clock: Arc::new(SystemClock),
+            forge: Arc::new(GitHubForge),
+        }
+    }
+
+    /// Use the given clock to date stored networks and to determine whether cached networks have
+    /// expired (e.g., a ManualClock in tests). By default, the system clock is used.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Build networks by listing the forks on the given forge (by default, GitHub)
+    pub fn with_forge(mut self, forge: Arc<dyn ForgeClient>) -> Self {
+        self.forge = forge;
+        self
+    }
+
+    /// If set, cached networks are ignored and all networks are rebuilt and stored again.
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Returns the cached network of the given repository, or builds the network from the forge
+    /// (see [ForkNetwork::build_with]) and stores it in the cache.
+    ///
+    /// Failing to store a network is logged, but does not affect the returned network.
+    pub async fn get_or_build(&self, seed: OctoRepo, max_forks: Option<usize>) -> ForkNetwork {
+        let source_id = seed.source.as_ref().map_or(seed.id, |source| source.id);
+        if !self.refresh {
+            if let Some(network) = self.load(source_id, max_forks) {
+                debug!("reusing cached fork network of {source_id}");
+                return network;
+            }
+        }
+        let network = ForkNetwork::build_with(self.forge.as_ref(), seed, max_forks).await;
+        if let Err(error) = self.store(&network) {
+            warn!("was not able to cache the fork network of {source_id}: {error}");
+        }
+        network
+    }
+
+    /// Load the network of the given source repository, if a fresh network that has been built
+    /// with the given maximum number of forks is cached.
+    pub fn load(&self, source_id: RepositoryId, max_forks: Option<usize>) -> Option<ForkNetwork> {
+        let content = fs::read_to_string(self.path(source_id)).ok()?;
+        let cached: CachedNetwork = match serde_yaml::from_str(&content) {
+            Ok(cached) => cached,
+            Err(error) => {
+                warn!("ignoring invalid cached fork network of {source_id}: {error}");
+                return None;
+            }
+        };
+        if cached.max_forks != max_forks {
+            debug!("cached fork network of {source_id} was built with a different fork limit");
+            return None;
+        }
+        if self.clock.now() - cached.created_at > self.ttl {
+            debug!("cached fork network of {source_id} has expired");
+            return None;
+        }
+        Some(ForkNetwork {
+            repositories: cached
+                .repositories
+                .into_iter()
+                .map(|repo| (repo.id, GitRepository::from(repo)))
+                .collect(),
+            source_id: cached.source_id,
+            parents: cached.parents,
+            forks: cached.forks,
+            max_forks: cached.max_forks,
+        })
+    }
+
+    /// Store the given network in the cache, replacing any previously cached network of the same
+    /// source repository.
+    ///
+    /// # Errors
+    /// Returns an error if the network cannot be written. Networks that contain repositories that
+    /// have not been retrieved from GitHub cannot be cached and result in an error as well.
+    pub fn store(&self, network: &ForkNetwork) -> Result<()> {
+        let repositories = network
+            .repositories
+            .values()
+            .map(|repo| repo.octorepo.clone())
+            .collect::<Option<Vec<OctoRepo>>>()
+            .ok_or_else(|| {
+                Error::new(ErrorKind::Configuration(
+                    "only networks of GitHub repositories can be cached".to_string(),
+                ))
+            })?;
+        let cached = CachedNetwork {
+            created_at: self.clock.now(),
+            source_id: network.source_id,
+            max_forks: network.max_forks,
+            repositories,
+            parents: network.parents.clone(),
+            forks: network.forks.clone(),
+        };
+        fs::create_dir_all(&self.directory)?;
+        serialize_atomic(self.path(network.source_id), &cached, Format::Yaml)?;
+        info!("cached fork network of {}", network.source_id);
+        Ok(())
+    }
+
+    fn path(&self, source_id: RepositoryId) -> PathBuf {
+        self.directory.join(format!("{source_id}.yaml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use crate::git::github::{ForkNetwork, NetworkCache};
+    use chrono::Duration;
+    use octocrab::models::Repository as OctoRepo;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn repo(id: u64) -> OctoRepo {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": format!("repo{id}"),
+            "url": format!("https://api.github.com/repos/owner/repo{id}"),
+            "clone_url": format!("https://github.com/owner/repo{id}.git"),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn cached_network_is_reused() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let cache = NetworkCache::new(directory.path(), Duration::try_days(1).unwrap());
+        let network = ForkNetwork::single(repo(1));
+        cache.store(&network).unwrap();
+
+        let cached = cache.load(1.into(), network.max_forks()).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached.source().id, network.source().id);
+        // Networks that were built with a different limit are not reused
+        assert!(cache.load(1.into(), None).is_none());
+        assert!(cache.load(2.into(), network.max_forks()).is_none());
+
+        // Expired networks are not reused
+        let expired = NetworkCache::new(directory.path(), Duration::zero());
+        assert!(expired.load(1.into(), network.max_forks()).is_none());
+    }
+
+    #[test]
+    fn cached_networks_expire_after_their_ttl() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let clock = Arc::new(ManualClock::at_timestamp(1674832148));
+        let cache = NetworkCache::new(directory.path(), Duration::try_hours(1).unwrap())
+            .with_clock(clock.clone());
+        let network = ForkNetwork::single(repo(1));
+        cache.store(&network).unwrap();
+
+        clock.advance(Duration::try_hours(1).unwrap());
+        assert!(cache.load(1.into(), network.max_forks()).is_some());
+        clock.advance(Duration::try_seconds(1).unwrap());
+        assert!(cache.load(1.into(), network.max_forks()).is_none());
+    }
+}
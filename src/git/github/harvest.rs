@@ -0,0 +1,178 @@
+use crate::git::github::ForkNetwork;
+use crate::git::RepoId;
+use crate::journal::{serialize_atomic, Format};
+use crate::schema::SCHEMA_VERSION;
+use crate::{CommitCounts, Result, SearchResult};
+use octocrab::models::RepositoryId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A repository of a harvested ForkNetwork
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkRepository {
+    /// The id of the repository. Artifacts of older versions recorded the repository's full
+    /// name instead, which is parsed leniently (see [RepoId::parse_legacy]).
+    #[serde(alias = "name")]
+    pub repo: RepoId,
+    /// The fork depth of the repository (0 for the source repository)
+    pub depth: usize,
+    /// The number of searched commits that were found in the repository first (see
+    /// [crate::search_with_commit_counts])
+    pub commits: usize,
+}
+
+/// A NetworkHarvest combines the topology of a harvested ForkNetwork with the number of commits
+/// of each repository and all results whose cherry and target were found in different
+/// repositories of the network. Thus, the propagation of picks through a network can be analyzed
+/// from a single artifact, without joining the results of several repositories by their names.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkHarvest {
+    /// The schema version with which the artifact was written (see [crate::schema])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// The source repository of the network
+    pub source: RepositoryId,
+    /// The maximum number of forks that the network was built with, if any was set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_forks: Option<usize>,
+    pub repositories: BTreeMap<RepositoryId, NetworkRepository>,
+    /// Maps the ids of forks to the ids of their parents
+    pub parents: BTreeMap<RepositoryId, RepositoryId>,
+    /// Maps the ids of forked repositories to the ids of their forks
+    pub forks: BTreeMap<RepositoryId, Vec<RepositoryId>>,
+    pub results: Vec<SearchResult>,
+}
+
+impl NetworkHarvest {
+    /// Combine the given network with the commit counts and results of its harvest. Results
+    /// whose cherry and target were found in the same repository, or whose repositories are
+    /// unknown, are dropped.
+    pub fn new(
+        network: &ForkNetwork,
+        commit_counts: &CommitCounts,
+        results: Vec<SearchResult>,
+    ) -> Self {
+        let repositories = network
+            .repositories
+            .values()
+            .map(|repo| {
+                let entry = NetworkRepository {
+                    repo: repo.repo_id(),
+                    depth: network.depth(repo.id).unwrap_or_default(),
+                    commits: commit_counts.get(&repo.id).copied().unwrap_or_default(),
+                };
+                (repo.id, entry)
+            })
+            .collect();
+        let mut forks: BTreeMap<RepositoryId, Vec<RepositoryId>> = network
+            .forks
+            .iter()
+            .map(|(parent, forks)| (*parent, forks.clone()))
+            .collect();
+        forks.values_mut().for_each(|forks| forks.sort());
+
+        Self {
+            schema_version: Some(SCHEMA_VERSION),
+            source: network.source_id,
+            max_forks: network.max_forks,
+            repositories,
+            parents: network.parents.iter().map(|(k, v)| (*k, *v)).collect(),
+            forks,
+            results: results
+                .into_iter()
+                .filter(|result| {
+                    let pair = result.commit_pair();
+                    match (pair.cherry().repository(), pair.target().repository()) {
+                        (Some(cherry), Some(target)) => cherry != target,
+                        _ => false,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Write the artifact as YAML (or JSON, for paths ending in `.json`)
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::github::{ForkNetwork, NetworkHarvest};
+    use crate::git::test_util::ResultBuilder;
+    use crate::git::{GitRepository, RepoId, RepoLocation};
+    use crate::schema::{validate, Artifact};
+    use crate::{CommitCounts, SearchResult};
+    use octocrab::models::RepositoryId;
+    use std::collections::HashMap;
+
+    fn result(cherry_repo: u64, target_repo: u64) -> SearchResult {
+        ResultBuilder::new("MessageScan")
+            .cherry("repository", cherry_repo)
+            .target("parent_ids", ["a"].as_slice())
+            .target("repository", target_repo)
+            .build()
+    }
+
+    #[test]
+    fn cross_repository_results_are_combined_with_the_topology() {
+        let repo = |id: u64| {
+            let location = RepoLocation::Filesystem(format!("/tmp/{id}").into());
+            (
+                RepositoryId(id),
+                GitRepository::new_simple(id, format!("repo-{id}"), location),
+            )
+        };
+        let network = ForkNetwork {
+            repositories: HashMap::from([repo(1), repo(2), repo(3)]),
+            source_id: RepositoryId(1),
+            parents: HashMap::from([
+                (RepositoryId(2), RepositoryId(1)),
+                (RepositoryId(3), RepositoryId(2)),
+            ]),
+            forks: HashMap::from([
+                (RepositoryId(1), vec![RepositoryId(2)]),
+                (RepositoryId(2), vec![RepositoryId(3)]),
+            ]),
+            max_forks: Some(2),
+        };
+        let commit_counts = CommitCounts::from([(RepositoryId(1), 10), (RepositoryId(3), 2)]);
+        let harvest =
+            NetworkHarvest::new(&network, &commit_counts, vec![result(1, 3), result(2, 2)]);
+
+        assert_eq!(harvest.results.len(), 1);
+        let repositories: Vec<(RepoId, usize, usize)> = harvest
+            .repositories
+            .values()
+            .map(|r| (r.repo.clone(), r.depth, r.commits))
+            .collect();
+        let id = |id: u64| RepoId::from_url(&format!("/tmp/{id}"));
+        assert_eq!(
+            repositories,
+            vec![(id(1), 0, 10), (id(2), 1, 0), (id(3), 2, 2)]
+        );
+        assert_eq!(harvest.parents[&RepositoryId(3)], RepositoryId(2));
+
+        let yaml = serde_yaml::to_string(&harvest).unwrap();
+        validate(Artifact::Network, &yaml).unwrap();
+        // Results are checked against the schema of result files
+        let invalid = yaml.replace("search_method: MessageScan", "search_method: 1");
+        let error = validate(Artifact::Network, &invalid).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("/results/0/search_method: expected string"));
+        let read: NetworkHarvest = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(read, harvest);
+
+        // Artifacts of older versions record full names
+        let legacy = yaml.replacen(&format!("repo: {}", id(1)), "name: owner/repo-1", 1);
+        validate(Artifact::Network, &legacy).unwrap();
+        let read: NetworkHarvest = serde_yaml::from_str(&legacy).unwrap();
+        assert_eq!(
+            read.repositories[&RepositoryId(1)].repo,
+            RepoId::github("owner", "repo-1")
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use crate::git::github::NetworkHarvest;
+use crate::journal::{serialize_atomic, Format};
+use crate::schema::SCHEMA_VERSION;
+use crate::visualization::escape;
+use crate::{CommitMetadata, Result};
+use chrono::{DateTime, Utc};
+use octocrab::models::RepositoryId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// How the repositories of two consecutive commits of a propagation path are related in their
+/// fork network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    /// The change was picked from a repository into one of its (transitive) forks
+    Downstream,
+    /// The change was picked from a fork into one of the repositories that it was (transitively)
+    /// forked from
+    Upstream,
+    /// The change was picked between repositories of which neither is a fork of the other, e.g.,
+    /// between two forks of the same repository
+    Lateral,
+    /// The change was picked again within the same repository
+    Internal,
+}
+
+/// A commit of a chain, i.e., one of the copies of the same change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainCommit {
+    pub id: String,
+    /// The repository in which the commit was found first
+    pub repository: RepositoryId,
+    /// The commit time, if it could be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl ChainCommit {
+    fn of(commit: &CommitMetadata) -> Option<Self> {
+        Some(Self {
+            id: commit.id().to_string(),
+            repository: commit.repository()?,
+            time: commit.timestamp(),
+        })
+    }
+}
+
+/// A step of a propagation path: the change was (likely) picked from one commit into the next
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropagationStep {
+    pub from: ChainCommit,
+    pub to: ChainCommit,
+    pub relation: Relation,
+    /// Whether a search method found the pick between the two commits. Otherwise, the pick is
+    /// inferred from the picks of the other commits of the chain.
+    pub direct: bool,
+    /// The time between the two commits in seconds, if both times are known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lag_seconds: Option<i64>,
+}
+
+/// The reconstructed propagation of a chain of picks, i.e., of all commits that are connected by
+/// picks, through the repositories of a fork network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropagationPath {
+    /// The earliest commit of the chain, which is assumed to be the origin of the change
+    pub origin: ChainCommit,
+    /// The steps through which the change reached each other commit of the chain, ordered by
+    /// the times of the commits that they reach
+    pub steps: Vec<PropagationStep>,
+}
+
+impl PropagationPath {
+    /// The distinct repositories that the change reached, in the order in which it reached them
+    pub fn repositories(&self) -> Vec<RepositoryId> {
+        let mut seen = HashSet::new();
+        std::iter::once(&self.origin)
+            .chain(self.steps.iter().map(|step| &step.to))
+            .map(|commit| commit.repository)
+            .filter(|repository| seen.insert(*repository))
+            .collect()
+    }
+}
+
+/// An edge of the network-level propagation graph, which aggregates the steps of all paths
+/// between the same two repositories
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropagationEdge {
+    pub from: RepositoryId,
+    pub to: RepositoryId,
+    pub relation: Relation,
+    /// The number of steps from the one repository to the other
+    pub picks: usize,
+}
+
+/// The propagation of picks through a harvested fork network (see [NetworkHarvest::propagation]).
+///
+/// The results of the harvest are grouped into chains of commits that are connected by picks.
+/// Within each chain, the earliest commit is assumed to be the origin of the change, and every
+/// other commit is assumed to be picked from one of the earlier commits: preferably from a commit
+/// with which a search method paired it, then from the commit whose repository is closest in the
+/// fork network (e.g., the parent of the commit's repository), and then from the most recent one.
+/// Commits whose times are unknown are ordered after all others.
+///
+/// The paths of all chains are aggregated into a graph of the repositories of the network, whose
+/// edges count the picks from one repository to another. Picks within the same repository are
+/// only recorded in the paths.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Propagation {
+    /// The schema version with which the artifact was written (see [crate::schema])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// The source repository of the network
+    pub source: RepositoryId,
+    /// The paths of all chains, ordered by the times of their origins
+    pub paths: Vec<PropagationPath>,
+    /// The edges of the propagation graph, ordered by their repositories
+    pub edges: Vec<PropagationEdge>,
+}
+
+impl Propagation {
+    /// Reconstruct the propagation paths of the picks of the given harvest
+    pub fn reconstruct(harvest: &NetworkHarvest) -> Self {
+        let mut commits: HashMap<&str, ChainCommit> = HashMap::new();
+        let mut picks: HashSet<(&str, &str)> = HashSet::new();
+        let mut chains = Chains::default();
+        for result in &harvest.results {
+            let pair = result.commit_pair();
+            let (Some(cherry), Some(target)) = (
+                ChainCommit::of(pair.cherry()),
+                ChainCommit::of(pair.target()),
+            ) else {
+                continue;
+            };
+            commits.entry(pair.cherry().id()).or_insert(cherry);
+            commits.entry(pair.target().id()).or_insert(target);
+            picks.insert((pair.cherry().id(), pair.target().id()));
+            picks.insert((pair.target().id(), pair.cherry().id()));
+            chains.join(pair.cherry().id(), pair.target().id());
+        }
+
+        let mut paths: Vec<PropagationPath> = chains
+            .groups()
+            .into_iter()
+            .map(|ids| {
+                let mut chain: Vec<&ChainCommit> = ids.iter().map(|id| &commits[id]).collect();
+                chain.sort_by_key(|commit| (commit.time.is_none(), commit.time, &commit.id));
+                let steps = (1..chain.len())
+                    .map(|i| {
+                        let to = chain[i];
+                        let from = chain[..i]
+                            .iter()
+                            .rev()
+                            .min_by_key(|from| {
+                                (
+                                    !picks.contains(&(from.id.as_str(), to.id.as_str())),
+                                    distance(harvest, from.repository, to.repository),
+                                )
+                            })
+                            .expect("every commit but the origin has earlier commits");
+                        PropagationStep {
+                            from: (*from).clone(),
+                            to: to.clone(),
+                            relation: relation(harvest, from.repository, to.repository),
+                            direct: picks.contains(&(from.id.as_str(), to.id.as_str())),
+                            lag_seconds: from
+                                .time
+                                .zip(to.time)
+                                .map(|(from, to)| (to - from).num_seconds()),
+                        }
+                    })
+                    .collect();
+                PropagationPath {
+                    origin: chain[0].clone(),
+                    steps,
+                }
+            })
+            .collect();
+        paths.sort_by(|a, b| {
+            (a.origin.time.is_none(), a.origin.time, &a.origin.id).cmp(&(
+                b.origin.time.is_none(),
+                b.origin.time,
+                &b.origin.id,
+            ))
+        });
+
+        let mut edges: BTreeMap<(RepositoryId, RepositoryId), PropagationEdge> = BTreeMap::new();
+        for step in paths.iter().flat_map(|path| &path.steps) {
+            if step.relation == Relation::Internal {
+                continue;
+            }
+            let (from, to) = (step.from.repository, step.to.repository);
+            edges
+                .entry((from, to))
+                .or_insert(PropagationEdge {
+                    from,
+                    to,
+                    relation: step.relation,
+                    picks: 0,
+                })
+                .picks += 1;
+        }
+
+        Self {
+            schema_version: Some(SCHEMA_VERSION),
+            source: harvest.source,
+            paths,
+            edges: edges.into_values().collect(),
+        }
+    }
+
+    /// The propagation graph in the DOT format of Graphviz, with one node per repository that
+    /// was involved in a pick and one edge per [PropagationEdge], labeled with its number of
+    /// picks. Edges between forks and their parents are drawn solid; lateral edges are dashed.
+    pub fn to_dot(&self, harvest: &NetworkHarvest) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph propagation {\n");
+        dot.push_str("  node [shape=box, fontname=monospace, fontsize=10];\n");
+        let mut repositories: Vec<RepositoryId> = self
+            .edges
+            .iter()
+            .flat_map(|edge| [edge.from, edge.to])
+            .collect();
+        repositories.sort();
+        repositories.dedup();
+        for repository in repositories {
+            let label = harvest
+                .repositories
+                .get(&repository)
+                .map_or(repository.to_string(), |repo| repo.repo.to_string());
+            let shape = match repository == self.source {
+                true => ", style=bold",
+                false => "",
+            };
+            let _ = writeln!(
+                dot,
+                "  \"{repository}\" [label=\"{}\"{shape}];",
+                escape(&label)
+            );
+        }
+        for edge in &self.edges {
+            let style = match edge.relation {
+                Relation::Lateral => ", style=dashed",
+                _ => "",
+            };
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\"{style}];",
+                edge.from, edge.to, edge.picks
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Write the artifact as YAML (or JSON, for paths ending in `.json`)
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+}
+
+impl NetworkHarvest {
+    /// Reconstruct how the picks of the harvest propagated through the network (see
+    /// [Propagation])
+    pub fn propagation(&self) -> Propagation {
+        Propagation::reconstruct(self)
+    }
+}
+
+/// The repositories from the given repository up to the source of the network
+fn ancestors(harvest: &NetworkHarvest, repository: RepositoryId) -> Vec<RepositoryId> {
+    let mut ancestors = vec![repository];
+    while let Some(parent) = harvest.parents.get(ancestors.last().unwrap()) {
+        // Guards against cycles in corrupt artifacts
+        if ancestors.contains(parent) {
+            break;
+        }
+        ancestors.push(*parent);
+    }
+    ancestors
+}
+
+/// The number of fork relations between the given repositories, or `usize::MAX` if they are not
+/// connected
+fn distance(harvest: &NetworkHarvest, a: RepositoryId, b: RepositoryId) -> usize {
+    let (ancestors_a, ancestors_b) = (ancestors(harvest, a), ancestors(harvest, b));
+    ancestors_a
+        .iter()
+        .enumerate()
+        .find_map(|(i, ancestor)| {
+            let j = ancestors_b.iter().position(|other| other == ancestor)?;
+            Some(i + j)
+        })
+        .unwrap_or(usize::MAX)
+}
+
+fn relation(harvest: &NetworkHarvest, from: RepositoryId, to: RepositoryId) -> Relation {
+    if from == to {
+        Relation::Internal
+    } else if ancestors(harvest, to).contains(&from) {
+        Relation::Downstream
+    } else if ancestors(harvest, from).contains(&to) {
+        Relation::Upstream
+    } else {
+        Relation::Lateral
+    }
+}
+
+/// Groups commits into chains of commits that are connected by picks (i.e., a union-find of
+/// commit ids)
+#[derive(Default)]
+struct Chains<'a> {
+    indices: HashMap<&'a str, usize>,
+    ids: Vec<&'a str>,
+    parents: Vec<usize>,
+}
+
+impl<'a> Chains<'a> {
+    fn index(&mut self, id: &'a str) -> usize {
+        *self.indices.entry(id).or_insert_with(|| {
+            self.ids.push(id);
+            self.parents.push(self.parents.len());
+            self.parents.len() - 1
+        })
+    }
+
+    fn root(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+        index
+    }
+
+    fn join(&mut self, a: &'a str, b: &'a str) {
+        let (a, b) = (self.index(a), self.index(b));
+        let (root_a, root_b) = (self.root(a), self.root(b));
+        self.parents[root_b] = root_a;
+    }
+
+    /// The ids of the commits of each chain
+    fn groups(mut self) -> Vec<Vec<&'a str>> {
+        let mut groups: BTreeMap<usize, Vec<&'a str>> = BTreeMap::new();
+        for index in 0..self.ids.len() {
+            let root = self.root(index);
+            groups.entry(root).or_default().push(self.ids[index]);
+        }
+        groups.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::github::{NetworkHarvest, Relation};
+    use crate::schema::{validate, Artifact};
+    use crate::SearchResult;
+    use octocrab::models::RepositoryId;
+    use std::collections::BTreeMap;
+
+    fn pick(cherry: (&str, u64, i64), target: (&str, u64, i64)) -> SearchResult {
+        let commit = |(id, repository, time): (&str, u64, i64)| {
+            format!(
+                "{{id: {id}, parent_ids: [], message: \"\", author: \"\", committer: \"\", \
+                 time: \"\", timestamp: {time}, repository: {repository}}}"
+            )
+        };
+        serde_yaml::from_str(&format!(
+            "search_method: MessageScan\ncherry_and_target:\n  cherry: {}\n  target: {}\n",
+            commit(cherry),
+            commit(target)
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn picks_are_traced_through_the_network() {
+        // 1 is forked by 2 and 3, and 2 is forked by 4
+        let parents = BTreeMap::from([
+            (RepositoryId(2), RepositoryId(1)),
+            (RepositoryId(3), RepositoryId(1)),
+            (RepositoryId(4), RepositoryId(2)),
+        ]);
+        let (a, b, c, d) = (("a", 1, 100), ("b", 2, 200), ("c", 4, 300), ("d", 3, 400));
+        let harvest = |results| NetworkHarvest {
+            schema_version: None,
+            source: RepositoryId(1),
+            max_forks: None,
+            repositories: BTreeMap::new(),
+            parents: parents.clone(),
+            forks: BTreeMap::new(),
+            results,
+        };
+        // The methods paired the fix in the fork of a fork only with the original fix
+        let propagation = harvest(vec![
+            pick(a, b),
+            pick(a, c),
+            pick(a, d),
+            pick(("x", 3, 50), ("y", 1, 60)),
+        ])
+        .propagation();
+        assert_eq!(propagation.paths.len(), 2);
+
+        let upstream = &propagation.paths[0];
+        assert_eq!(upstream.origin.id, "x");
+        assert_eq!(upstream.steps[0].relation, Relation::Upstream);
+        assert_eq!(upstream.steps[0].lag_seconds, Some(10));
+
+        let fix = &propagation.paths[1];
+        assert_eq!(fix.steps.len(), 3);
+        let steps: Vec<(&str, &str, Relation)> = fix
+            .steps
+            .iter()
+            .map(|step| (step.from.id.as_str(), step.to.id.as_str(), step.relation))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("a", "b", Relation::Downstream),
+                ("a", "c", Relation::Downstream),
+                ("a", "d", Relation::Downstream),
+            ]
+        );
+        assert!(fix.steps.iter().all(|step| step.direct));
+        assert_eq!(
+            fix.repositories(),
+            vec![
+                RepositoryId(1),
+                RepositoryId(2),
+                RepositoryId(4),
+                RepositoryId(3)
+            ]
+        );
+
+        // Without a direct pick, the fork of a fork picks from its parent
+        let indirect = harvest(vec![
+            pick(a, b),
+            pick(a, d),
+            pick(c, d),
+            pick(c, ("e", 3, 500)),
+        ]);
+        let propagation = indirect.propagation();
+        let steps: Vec<(&str, &str, bool)> = propagation.paths[0]
+            .steps
+            .iter()
+            .map(|step| (step.from.id.as_str(), step.to.id.as_str(), step.direct))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("a", "b", true),
+                ("b", "c", false),
+                ("a", "d", true),
+                ("c", "e", true)
+            ]
+        );
+        let edges: Vec<(u64, u64, usize)> = propagation
+            .edges
+            .iter()
+            .map(|edge| (edge.from.0, edge.to.0, edge.picks))
+            .collect();
+        assert_eq!(edges, vec![(1, 2, 1), (1, 3, 1), (2, 4, 1), (4, 3, 1)]);
+        assert_eq!(propagation.edges[3].relation, Relation::Lateral);
+        assert!(propagation
+            .to_dot(&indirect)
+            .contains("\"4\" -> \"3\" [label=\"1\", style=dashed];"));
+
+        let yaml = serde_yaml::to_string(&propagation).unwrap();
+        validate(Artifact::Propagation, &yaml).unwrap();
+    }
+}
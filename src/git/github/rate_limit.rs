@@ -0,0 +1,431 @@
+use crate::clock::{Clock, SystemClock};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use http::{HeaderMap, StatusCode};
+use log::{debug, info, warn};
+use octocrab::models::{Rate, RateLimit};
+use octocrab::FromResponse;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time;
+
+/// The number of times a request is sent before a rate limit error is returned
+const MAX_ATTEMPTS: u32 = 5;
+/// The first backoff after a secondary rate limit without a `retry-after` header. GitHub asks to
+/// wait at least one minute.
+const SECONDARY_BACKOFF: i64 = 60;
+/// The longest backoff after repeated secondary rate limits
+const MAX_SECONDARY_BACKOFF: i64 = 15 * 60;
+/// The seconds that are added to each reset time, which accounts for clock skew
+const RESET_MARGIN: i64 = 1;
+
+/// The resources of GitHub's API, which have separate rate limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ApiResource {
+    /// The REST API, except for the search
+    Core,
+    /// The search API, which has a much lower limit per minute
+    Search,
+}
+
+impl ApiResource {
+    /// The resource of the given route or URL
+    fn of(uri: &str) -> Self {
+        let path = uri.split_once("://").map_or(uri, |(_, rest)| {
+            rest.find('/').map_or("", |start| &rest[start..])
+        });
+        match path.starts_with("/search/") {
+            true => ApiResource::Search,
+            false => ApiResource::Core,
+        }
+    }
+
+    /// The resource with the given name of the `x-ratelimit-resource` header
+    fn named(name: &str) -> Option<Self> {
+        match name {
+            "core" => Some(ApiResource::Core),
+            "search" => Some(ApiResource::Search),
+            _ => None,
+        }
+    }
+}
+
+/// The state of the rate limit of one resource in its current window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RateWindow {
+    pub limit: u64,
+    pub remaining: u64,
+    /// The time at which the window resets
+    pub reset: DateTime<Utc>,
+}
+
+impl From<&Rate> for RateWindow {
+    fn from(rate: &Rate) -> Self {
+        Self {
+            limit: rate.limit as u64,
+            remaining: rate.remaining as u64,
+            reset: DateTime::from_timestamp(rate.reset as i64, 0).unwrap_or_default(),
+        }
+    }
+}
+
+/// Tracks the rate limits that GitHub reports in the `x-ratelimit-*` headers of its responses (or
+/// on the `/rate_limit` endpoint), so that requests wait exactly until the window of their
+/// resource resets once it is exhausted, instead of guessing a cooldown. Secondary rate limits
+/// (e.g., for too many concurrent requests) pause all requests for the time in the `retry-after`
+/// header or, without one, for an exponentially increasing backoff.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    windows: HashMap<ApiResource, RateWindow>,
+    /// The time until which all requests are paused after a secondary rate limit
+    paused_until: Option<DateTime<Utc>>,
+    /// The number of consecutive secondary rate limits
+    strikes: u32,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+}
+
+impl RateLimiter {
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            windows: HashMap::new(),
+            paused_until: None,
+            strikes: 0,
+            clock,
+        }
+    }
+
+    /// The time to wait before the next request to the given resource, if its window is exhausted
+    /// or requests are paused after a secondary rate limit
+    pub fn wait_time(&self, resource: ApiResource) -> Option<Duration> {
+        let now = self.clock.now();
+        let exhausted = self
+            .windows
+            .get(&resource)
+            .filter(|window| window.remaining == 0)
+            .map(|window| window.reset + ChronoDuration::seconds(RESET_MARGIN));
+        [exhausted, self.paused_until]
+            .into_iter()
+            .flatten()
+            .filter(|until| *until > now)
+            .max()
+            .and_then(|until| (until - now).to_std().ok())
+    }
+
+    /// Update the window of the given resource from the rate limit headers of a response
+    pub fn update(&mut self, resource: ApiResource, headers: &HeaderMap) {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+        let number = |name: &str| header(name).and_then(|value| value.parse::<u64>().ok());
+        let resource = header("x-ratelimit-resource")
+            .and_then(ApiResource::named)
+            .unwrap_or(resource);
+        let (Some(limit), Some(remaining), Some(reset)) = (
+            number("x-ratelimit-limit"),
+            number("x-ratelimit-remaining"),
+            number("x-ratelimit-reset"),
+        ) else {
+            return;
+        };
+        let reset = DateTime::from_timestamp(reset as i64, 0).unwrap_or_default();
+        self.windows.insert(
+            resource,
+            RateWindow {
+                limit,
+                remaining,
+                reset,
+            },
+        );
+    }
+
+    /// Update the windows of all resources from the response of the `/rate_limit` endpoint
+    pub fn update_from(&mut self, rate_limit: &RateLimit) {
+        let resources = &rate_limit.resources;
+        self.windows
+            .insert(ApiResource::Core, RateWindow::from(&resources.core));
+        self.windows
+            .insert(ApiResource::Search, RateWindow::from(&resources.search));
+    }
+
+    /// Pause all requests after a secondary rate limit, either for the time in the `retry-after`
+    /// header or for a backoff that doubles with each consecutive secondary rate limit. Returns
+    /// the duration of the pause.
+    pub fn back_off(&mut self, headers: &HeaderMap) -> Duration {
+        let retry_after = headers
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<i64>().ok());
+        let seconds = retry_after.unwrap_or_else(|| {
+            (SECONDARY_BACKOFF << self.strikes.min(8)).min(MAX_SECONDARY_BACKOFF)
+        });
+        self.strikes += 1;
+        self.paused_until = Some(self.clock.now() + ChronoDuration::seconds(seconds));
+        Duration::from_secs(seconds.max(0) as u64)
+    }
+
+    /// Record a successful request, which ends the backoff of secondary rate limits
+    pub fn succeeded(&mut self) {
+        self.strikes = 0;
+    }
+}
+
+/// Whether the given error response reports a rate limit, and whether it is a secondary limit
+fn rate_limited(status: StatusCode, message: &str, headers: &HeaderMap) -> Option<Limit> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let message = message.to_lowercase();
+    if message.contains("secondary rate limit") || headers.contains_key("retry-after") {
+        Some(Limit::Secondary)
+    } else if message.contains("rate limit")
+        || headers
+            .get("x-ratelimit-remaining")
+            .is_some_and(|remaining| remaining == "0")
+    {
+        Some(Limit::Primary)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Limit {
+    Primary,
+    Secondary,
+}
+
+static STATIC_RATE_LIMITER: Lazy<arc_swap::ArcSwap<Mutex<RateLimiter>>> =
+    Lazy::new(|| arc_swap::ArcSwap::from_pointee(Mutex::new(RateLimiter::default())));
+
+fn rate_limiter() -> Arc<Mutex<RateLimiter>> {
+    STATIC_RATE_LIMITER.load().clone()
+}
+
+/// Update the global rate limiter from GitHub's `/rate_limit` endpoint, which does not count
+/// against the rate limit
+async fn refresh(limiter: &mut RateLimiter) {
+    match octocrab::instance().ratelimit().get().await {
+        Ok(rate_limit) => limiter.update_from(&rate_limit),
+        Err(error) => warn!("was not able to retrieve GitHub's rate limits: {error}"),
+    }
+}
+
+/// The route to the given path with the given query parameters, e.g.,
+/// `/search/repositories?q=language%3ARust`
+pub(crate) fn route(path: &str, parameters: &[(&str, String)]) -> String {
+    match serde_urlencoded::to_string(parameters) {
+        Ok(query) if !query.is_empty() => format!("{path}?{query}"),
+        _ => path.to_string(),
+    }
+}
+
+/// Send a GET request to the given route or URL of GitHub's API with the global octocrab
+/// instance. The request waits until the rate limit of its resource allows it and is retried
+/// after rate limit errors.
+///
+/// The global rate limiter is locked until the request completed, so that requests are sent one
+/// at a time, which also avoids the secondary rate limit for concurrent requests.
+pub(crate) async fn get<T: FromResponse>(uri: &str) -> Result<T, octocrab::Error> {
+    let resource = ApiResource::of(uri);
+    let limiter = rate_limiter();
+    let mut limiter = limiter.lock().await;
+    let mut attempt = 1;
+    loop {
+        if let Some(wait_time) = limiter.wait_time(resource) {
+            info!(
+                "GitHub's {resource:?} rate limit is exhausted. Waiting for {} seconds",
+                wait_time.as_secs()
+            );
+            time::sleep(wait_time).await;
+        }
+        debug!("GET {uri}");
+        #[cfg(feature = "chaos")]
+        if crate::chaos::inject(crate::chaos::Fault::ApiForbidden) {
+            return Err(crate::chaos::forbidden(uri).await);
+        }
+        let response = octocrab::instance()._get(uri).await?;
+        limiter.update(resource, response.headers());
+        let headers = response.headers().clone();
+        let error = match octocrab::map_github_error(response).await {
+            Ok(response) => {
+                limiter.succeeded();
+                drop(limiter);
+                return T::from_response(response).await;
+            }
+            Err(error) => error,
+        };
+        let limit = match &error {
+            octocrab::Error::GitHub { source, .. } => {
+                rate_limited(source.status_code, &source.message, &headers)
+            }
+            _ => None,
+        };
+        match limit {
+            Some(_) if attempt >= MAX_ATTEMPTS => return Err(error),
+            Some(Limit::Secondary) => {
+                let pause = limiter.back_off(&headers);
+                warn!(
+                    "hit GitHub's secondary rate limit; pausing for {} seconds",
+                    pause.as_secs()
+                );
+            }
+            Some(Limit::Primary) => {
+                warn!("hit GitHub's {resource:?} rate limit");
+                if limiter.wait_time(resource).is_none() {
+                    refresh(&mut limiter).await;
+                }
+            }
+            None => return Err(error),
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, ManualClock};
+    use crate::git::github::rate_limit::{
+        rate_limited, route, ApiResource, Limit, RateLimiter, RateWindow,
+    };
+    use chrono::Duration as ChronoDuration;
+    use http::{HeaderMap, HeaderValue, StatusCode};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn requests_wait_until_the_window_resets() {
+        let clock = Arc::new(ManualClock::at_timestamp(1_700_000_000));
+        let mut limiter = RateLimiter::with_clock(clock.clone());
+        assert_eq!(limiter.wait_time(ApiResource::Search), None);
+
+        let reset = (clock.now().timestamp() + 30).to_string();
+        limiter.update(
+            ApiResource::Core,
+            &headers(&[
+                ("x-ratelimit-limit", "30"),
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", &reset),
+                ("x-ratelimit-resource", "search"),
+            ]),
+        );
+        // The resource of the header takes precedence over the resource of the route
+        assert_eq!(limiter.windows.get(&ApiResource::Core), None);
+        assert_eq!(
+            limiter
+                .windows
+                .get(&ApiResource::Search)
+                .map(|w| w.remaining),
+            Some(0)
+        );
+        assert_eq!(
+            limiter.wait_time(ApiResource::Search),
+            Some(Duration::from_secs(31))
+        );
+        assert_eq!(limiter.wait_time(ApiResource::Core), None);
+
+        clock.advance(ChronoDuration::seconds(31));
+        assert_eq!(limiter.wait_time(ApiResource::Search), None);
+
+        // Remaining requests do not have to wait
+        limiter.windows.insert(
+            ApiResource::Core,
+            RateWindow {
+                limit: 5000,
+                remaining: 1,
+                reset: clock.now() + ChronoDuration::hours(1),
+            },
+        );
+        assert_eq!(limiter.wait_time(ApiResource::Core), None);
+    }
+
+    #[test]
+    fn secondary_limits_back_off() {
+        let clock = Arc::new(ManualClock::at_timestamp(1_700_000_000));
+        let mut limiter = RateLimiter::with_clock(clock.clone());
+        assert_eq!(limiter.back_off(&HeaderMap::new()).as_secs(), 60);
+        assert_eq!(limiter.back_off(&HeaderMap::new()).as_secs(), 120);
+        assert_eq!(
+            limiter.wait_time(ApiResource::Core),
+            Some(Duration::from_secs(120))
+        );
+        limiter.succeeded();
+        assert_eq!(limiter.back_off(&HeaderMap::new()).as_secs(), 60);
+        // GitHub's retry-after header takes precedence
+        let retry_after = headers(&[("retry-after", "7")]);
+        assert_eq!(limiter.back_off(&retry_after).as_secs(), 7);
+        for _ in 0..20 {
+            limiter.back_off(&HeaderMap::new());
+        }
+        assert_eq!(limiter.back_off(&HeaderMap::new()).as_secs(), 15 * 60);
+    }
+
+    #[test]
+    fn rate_limit_errors_are_classified() {
+        let none = HeaderMap::new();
+        let secondary = "You have exceeded a secondary rate limit.";
+        let primary = "API rate limit exceeded for user ID 1.";
+        assert_eq!(
+            rate_limited(StatusCode::FORBIDDEN, secondary, &none),
+            Some(Limit::Secondary)
+        );
+        assert_eq!(
+            rate_limited(StatusCode::FORBIDDEN, primary, &none),
+            Some(Limit::Primary)
+        );
+        assert_eq!(
+            rate_limited(
+                StatusCode::TOO_MANY_REQUESTS,
+                "",
+                &headers(&[("retry-after", "1")])
+            ),
+            Some(Limit::Secondary)
+        );
+        assert_eq!(
+            rate_limited(StatusCode::FORBIDDEN, "Resource not accessible", &none),
+            None
+        );
+        assert_eq!(rate_limited(StatusCode::NOT_FOUND, primary, &none), None);
+    }
+
+    #[test]
+    fn routes_are_encoded() {
+        assert_eq!(
+            ApiResource::of("/search/repositories?q=x"),
+            ApiResource::Search
+        );
+        assert_eq!(
+            ApiResource::of("https://api.github.com/search/repositories?page=2"),
+            ApiResource::Search
+        );
+        assert_eq!(
+            ApiResource::of("https://api.github.com/repos/o/r/forks"),
+            ApiResource::Core
+        );
+        assert_eq!(
+            route(
+                "/search/repositories",
+                &[("q", "created:2020-01-01T00:00:00+00:00..".to_string())]
+            ),
+            "/search/repositories?q=created%3A2020-01-01T00%3A00%3A00%2B00%3A00.."
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::git::util::{named_heads, repository_and_branch_type, wrap_commit};
+use crate::git::{
+    clone_or_load_with, CollectionConfig, Commit, DiffCache, GitRepository, LoadedRepository,
+    NoiseList, RepoId,
+};
+use crate::Result;
+use firestorm::profile_method;
+use git2::Oid;
+use log::{error, info};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+/// Where a collected commit was found: the repository, the ref through whose history the commit
+/// was reached first, and the distance of the commit to the head of that ref.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Provenance {
+    /// The id of the repository in which the commit was found
+    pub repository: RepoId,
+    /// The full name of the ref from which the commit was reached first, e.g.,
+    /// `refs/remotes/origin/main` or an extra ref such as `refs/pull/1/head`
+    pub reference: String,
+    /// The head of the ref
+    pub head: Oid,
+    /// The number of parent links between the head and the commit (`0` for the head itself)
+    pub depth: usize,
+}
+
+/// The commits of a loaded repository, which are collected lazily with the collection pipeline
+/// of the search (i.e., with the same branches, merge strategy, diff cache, annotators, noise, and
+/// filter), but independently of any search method. This allows other mining tools to reuse the
+/// collection and diffing of cherry-harvest.
+///
+/// Each commit is yielded once, together with its [Provenance]. Diffs are not extracted while
+/// collecting; they are calculated on demand with [Commit::calculate_diff].
+///
+/// # Examples
+/// ```no_run
+/// use cherry_harvest::git::{CollectionConfig, GitRepository};
+/// use cherry_harvest::RepoLocation;
+///
+/// # async fn mine() -> cherry_harvest::Result<()> {
+/// let repo = GitRepository::from(RepoLocation::Filesystem("path/to/repo".into()));
+/// let history = repo.commits(&CollectionConfig::default()).await?;
+/// for (mut commit, provenance) in &history {
+///     let hunks = commit.calculate_diff().hunks.len();
+///     println!("{} on {}: {hunks} hunks", commit.id(), provenance.reference);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct CommitHistory {
+    repository: RepoId,
+    loaded: LoadedRepository,
+    config: CollectionConfig,
+    diff_cache: Option<Arc<DiffCache>>,
+}
+
+impl CommitHistory {
+    /// The history of the given loaded repository, whose known-noise commits are excluded in
+    /// addition to those of the configuration
+    pub fn new(
+        repository: RepoId,
+        loaded: LoadedRepository,
+        mut config: CollectionConfig,
+        noise: &NoiseList,
+    ) -> Self {
+        config.noise.extend(noise);
+        let diff_cache = config.diff_cache.clone().map(Arc::new);
+        Self {
+            repository,
+            loaded,
+            config,
+            diff_cache,
+        }
+    }
+
+    /// The repository whose commits are collected
+    pub fn loaded(&self) -> &LoadedRepository {
+        &self.loaded
+    }
+
+    /// An iterator over the commits of the repository and their provenance
+    pub fn iter(&self) -> Commits<'_> {
+        let (repository, branch_type) = repository_and_branch_type(&self.loaded);
+        let heads: VecDeque<(String, Oid)> = named_heads(repository, branch_type, &self.config)
+            .into_iter()
+            .map(|(name, head)| (name, head.id()))
+            .collect();
+        info!("collecting the commits of {} heads", heads.len());
+        Commits {
+            history: self,
+            heads,
+            head: None,
+            queue: VecDeque::new(),
+            visited: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<'h> IntoIterator for &'h CommitHistory {
+    type Item = (Commit<'h, 'h>, Provenance);
+    type IntoIter = Commits<'h>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The iterator over the commits of a [CommitHistory]. The history of each head is traversed
+/// breadth-first, so that the commits closest to the head are yielded first.
+pub struct Commits<'h> {
+    history: &'h CommitHistory,
+    heads: VecDeque<(String, Oid)>,
+    head: Option<(String, Oid)>,
+    queue: VecDeque<(Oid, usize)>,
+    visited: HashSet<Oid>,
+    pending: VecDeque<(Commit<'h, 'h>, Provenance)>,
+}
+
+impl<'h> Commits<'h> {
+    /// Visit the commit with the given id, which queues its parents and the commits that are
+    /// yielded for it
+    fn visit(&mut self, id: Oid, depth: usize) {
+        profile_method!(visit);
+        let history = self.history;
+        let config = &history.config;
+        let repository = history.loaded.repository();
+        let commit = match repository.find_commit(id) {
+            Ok(commit) => commit,
+            Err(err) => {
+                error!("Was not able to find commit {id}: {err}");
+                return;
+            }
+        };
+        if config.max_history_depth.is_none_or(|max| depth < max) {
+            self.queue
+                .extend(commit.parent_ids().map(|parent| (parent, depth + 1)));
+        }
+        let Some((reference, head)) = &self.head else {
+            return;
+        };
+        for mut commit in wrap_commit(
+            repository,
+            commit,
+            config.diff,
+            history.diff_cache.as_ref(),
+            config.merges,
+        ) {
+            config.annotators.apply(&mut commit);
+            if config.noise.contains(&commit) || !config.filter.matches(&commit) {
+                continue;
+            }
+            let provenance = Provenance {
+                repository: history.repository.clone(),
+                reference: reference.clone(),
+                head: *head,
+                depth,
+            };
+            self.pending.push_back((commit, provenance));
+        }
+    }
+}
+
+impl<'h> Iterator for Commits<'h> {
+    type Item = (Commit<'h, 'h>, Provenance);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            match self.queue.pop_front() {
+                Some((id, depth)) => {
+                    if self.visited.insert(id) {
+                        self.visit(id, depth);
+                    }
+                }
+                None => {
+                    let (reference, head) = self.heads.pop_front()?;
+                    self.queue.push_back((head, 0));
+                    self.head = Some((reference, head));
+                }
+            }
+        }
+    }
+}
+
+impl GitRepository {
+    /// Clone or load this repository with the clone options of the given configuration and
+    /// collect its commits with their provenance (see [CommitHistory]). The branches of the
+    /// repository are pinned first, if it has a pin.
+    ///
+    /// # Errors
+    /// Returns an error if the repository cannot be cloned, loaded, or pinned.
+    pub async fn commits(&self, config: &CollectionConfig) -> Result<CommitHistory> {
+        let loaded = clone_or_load_with(&self.location, &config.clone).await?;
+        if let Some(pin) = &self.pin {
+            loaded.pin(pin)?;
+        }
+        Ok(CommitHistory::new(
+            self.repo_id(),
+            loaded,
+            config.clone(),
+            &self.noise,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::TestRepository;
+    use crate::git::{CollectionConfig, GitRepository, MergeStrategy};
+    use crate::RepoLocation;
+    use git2::Oid;
+
+    #[tokio::test]
+    async fn commits_are_yielded_with_provenance() {
+        let fixture = TestRepository::new();
+        let commit = |branch, content, parents: &[Oid]| {
+            fixture.commit(branch, &[("file.txt", content)], parents, content)
+        };
+        // The root is only reachable through the branches that are later moved past it
+        let root = commit("main", "root", &[]);
+        let main = commit("main", "main", &[root]);
+        let feature = commit("feature", "feature", &[root]);
+        let merge = commit("merged", "merge", &[main, feature]);
+
+        let repo = GitRepository::from(RepoLocation::Filesystem(fixture.path().to_path_buf()));
+        let history = repo.commits(&CollectionConfig::default()).await.unwrap();
+        let commits: Vec<_> = history
+            .iter()
+            .map(|(commit, provenance)| (commit.id(), provenance))
+            .collect();
+        // Each commit is yielded once, and the merge is ignored by default
+        assert_eq!(commits.len(), 3);
+        assert!(commits.iter().all(|(id, _)| *id != merge));
+        for (id, provenance) in &commits {
+            assert_eq!(provenance.repository, repo.repo_id());
+            let expected_depth = if *id == root { 1 } else { 0 };
+            assert_eq!(provenance.depth, expected_depth, "{provenance:?}");
+            if *id == feature {
+                assert_eq!(provenance.reference, "refs/heads/feature");
+            }
+        }
+
+        let config = CollectionConfig {
+            merges: MergeStrategy::FirstParent,
+            max_history_depth: Some(0),
+            ..CollectionConfig::default()
+        };
+        let history = repo.commits(&config).await.unwrap();
+        let mut ids: Vec<Oid> = history.iter().map(|(commit, _)| commit.id()).collect();
+        ids.sort();
+        let mut heads = vec![main, feature, merge];
+        heads.sort();
+        assert_eq!(ids, heads);
+    }
+}
@@ -0,0 +1,151 @@
+use git2::Repository as G2Repository;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The SPDX identifier of a license (e.g., "MIT" or "Apache-2.0")
+pub type SpdxId = String;
+
+// Prefixes of names of files that commonly contain the license of a repository
+const LICENSE_FILE_PREFIXES: [&str; 3] = ["LICENSE", "LICENCE", "COPYING"];
+
+/// Detect the license of a repository by searching its root directory for license files and
+/// matching the files' text against phrases that are characteristic for common licenses.
+///
+/// If a repository contains multiple license files with different licenses (e.g., LICENSE_MIT and
+/// LICENSE_APACHE), the licenses are combined into an SPDX expression ("Apache-2.0 OR MIT").
+///
+/// Returns None if no license file was found or if no license could be identified.
+pub fn detect_license(root: &Path) -> Option<SpdxId> {
+    let mut license_files = fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(is_license_file)
+        })
+        .collect::<Vec<PathBuf>>();
+    license_files.sort();
+
+    combine_licenses(license_files.iter().filter_map(|path| {
+        let license = identify_license(&fs::read_to_string(path).ok()?);
+        debug!("identified license {:?} in {}", license, path.display());
+        license
+    }))
+}
+
+/// Detect the license of a repository from the license files in the root tree of its HEAD
+/// commit. In contrast to [detect_license], this does not require a checked out working tree.
+pub fn detect_license_in_head(repository: &G2Repository) -> Option<SpdxId> {
+    let tree = repository.head().ok()?.peel_to_tree().ok()?;
+    combine_licenses(tree.iter().filter_map(|entry| {
+        if !entry.name().is_some_and(is_license_file) {
+            return None;
+        }
+        let blob = entry.to_object(repository).ok()?.peel_to_blob().ok()?;
+        let license = identify_license(&String::from_utf8_lossy(blob.content()));
+        debug!("identified license {:?} in {:?}", license, entry.name());
+        license
+    }))
+}
+
+fn is_license_file(name: &str) -> bool {
+    let name = name.to_uppercase();
+    LICENSE_FILE_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+fn combine_licenses<I: Iterator<Item = SpdxId>>(licenses: I) -> Option<SpdxId> {
+    let mut licenses = licenses.collect::<Vec<SpdxId>>();
+    licenses.sort();
+    licenses.dedup();
+    match licenses.is_empty() {
+        true => None,
+        false => Some(licenses.join(" OR ")),
+    }
+}
+
+/// Identify the license in the given license text.
+pub fn identify_license(text: &str) -> Option<SpdxId> {
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+    let contains = |phrase: &str| text.contains(phrase);
+
+    let license = if contains("gnu affero general public license") {
+        "AGPL-3.0"
+    } else if contains("gnu lesser general public license") {
+        if contains("version 2.1") {
+            "LGPL-2.1"
+        } else {
+            "LGPL-3.0"
+        }
+    } else if contains("gnu general public license") {
+        if contains("version 2,") || contains("version 2 ") {
+            "GPL-2.0"
+        } else {
+            "GPL-3.0"
+        }
+    } else if contains("apache license") && contains("version 2.0") {
+        "Apache-2.0"
+    } else if contains("mozilla public license version 2.0") {
+        "MPL-2.0"
+    } else if contains("permission is hereby granted, free of charge") {
+        "MIT"
+    } else if contains("redistribution and use in source and binary forms") {
+        if contains("neither the name") {
+            "BSD-3-Clause"
+        } else {
+            "BSD-2-Clause"
+        }
+    } else if contains("permission to use, copy, modify, and/or distribute this software") {
+        "ISC"
+    } else if contains("this is free and unencumbered software released into the public domain") {
+        "Unlicense"
+    } else {
+        return None;
+    };
+    Some(license.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::license::{detect_license, detect_license_in_head, identify_license};
+    use git2::Repository as G2Repository;
+    use std::env;
+
+    #[test]
+    fn identify_common_licenses() {
+        assert_eq!(
+            identify_license("MIT License\n\nPermission is hereby granted, free of\n charge"),
+            Some("MIT".to_string())
+        );
+        assert_eq!(
+            identify_license("Apache License\n Version 2.0, January 2004"),
+            Some("Apache-2.0".to_string())
+        );
+        assert_eq!(
+            identify_license("GNU GENERAL PUBLIC LICENSE\n Version 3, 29 June 2007"),
+            Some("GPL-3.0".to_string())
+        );
+        assert_eq!(identify_license("All rights reserved."), None);
+    }
+
+    #[test]
+    fn detect_license_of_this_repo() {
+        // This project is dual-licensed with LICENSE_MIT and LICENSE_APACHE
+        let path_buf = env::current_dir().unwrap();
+        assert_eq!(
+            detect_license(&path_buf),
+            Some("Apache-2.0 OR MIT".to_string())
+        );
+        let repository = G2Repository::open(&path_buf).unwrap();
+        assert_eq!(
+            detect_license_in_head(&repository),
+            Some("Apache-2.0 OR MIT".to_string())
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::fingerprint::patch_id;
+use crate::git::Commit;
+use crate::Result;
+use firestorm::profile_method;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// The shortest abbreviated commit id that is matched; shorter ids are too ambiguous
+const MIN_ID_LENGTH: usize = 7;
+
+/// A denylist of known-noise commits that are excluded from the search, e.g., commits that
+/// reformat the entire code base or update the license headers of all files. Such commits touch
+/// many files in the same way and generate enormous clusters of false-positive picks.
+///
+/// Commits are either listed by their (possibly abbreviated) id, or by the patch-id of their diff
+/// (see `git patch-id --stable`). Patch-ids also match the copies of a noise commit in other
+/// repositories or on other branches (e.g., after a rebase).
+///
+/// A noise list can be read from a YAML file:
+/// ```yaml
+/// commits:
+///   - 3f2a9c1
+/// patch_ids:
+///   - 9d1c0a6e5b4f3e2d1c0b9a8f7e6d5c4b3a2f1e0d
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoiseList {
+    /// The ids of the noise commits
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub commits: BTreeSet<String>,
+    /// The patch-ids of the diffs of the noise commits
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub patch_ids: BTreeSet<String>,
+}
+
+impl NoiseList {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty() && self.patch_ids.is_empty()
+    }
+
+    /// Add all commits and patch-ids of the other list to this list
+    pub fn extend(&mut self, other: &NoiseList) {
+        self.commits.extend(other.commits.iter().cloned());
+        self.patch_ids.extend(other.patch_ids.iter().cloned());
+    }
+
+    /// Whether the given commit is listed by its full or abbreviated id
+    fn lists_id(&self, commit: &Commit) -> bool {
+        let id = commit.id().to_string();
+        (MIN_ID_LENGTH..=id.len()).any(|length| self.commits.contains(&id[..length]))
+    }
+
+    /// Whether the given commit is listed by its id or patch-id. The patch-id is only computed if
+    /// the list contains patch-ids.
+    pub fn contains(&self, commit: &Commit) -> bool {
+        self.lists_id(commit)
+            || (!self.patch_ids.is_empty()
+                && patch_id(commit).is_some_and(|id| self.patch_ids.contains(&id.to_string())))
+    }
+
+    /// Remove all listed commits from the given commits. Returns the number of removed commits.
+    pub fn remove_from(&self, commits: &mut Vec<Commit>) -> usize {
+        profile_method!(remove_noise);
+        if self.is_empty() {
+            return 0;
+        }
+        let count = commits.len();
+        commits.retain(|commit| !self.contains(commit));
+        let removed = count - commits.len();
+        debug!("removed {removed} known-noise commits");
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::patch_id;
+    use crate::git::collect_commits;
+    use crate::git::noise::NoiseList;
+    use crate::git::test_util::TestRepository;
+    use crate::Commit;
+
+    #[test]
+    fn listed_commits_are_removed() {
+        let fixture = TestRepository::new();
+        let mut parent = None;
+        for (message, content) in [("base", "a\n"), ("reformat", "A\n"), ("fix", "B\n")] {
+            parent = Some(fixture.commit_file("main", content, parent, message));
+        }
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let find = |message| {
+            commits
+                .iter()
+                .find(|c| c.message() == Some(message))
+                .unwrap()
+        };
+
+        let mut by_id = NoiseList::default();
+        by_id
+            .commits
+            .insert(find("reformat").id().to_string()[..7].to_string());
+        let mut remaining = commits.clone();
+        assert_eq!(by_id.remove_from(&mut remaining), 1);
+        assert!(remaining.iter().all(|c| c.message() != Some("reformat")));
+
+        let mut by_patch_id = NoiseList::default();
+        let fix_patch_id = patch_id(find("fix")).unwrap().to_string();
+        by_patch_id.patch_ids.insert(fix_patch_id);
+        by_patch_id.extend(&by_id);
+        let mut remaining = commits.clone();
+        assert_eq!(by_patch_id.remove_from(&mut remaining), 2);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].message(), Some("base"));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::error::{Error, ErrorKind};
+use git2::{BranchType, Oid, ReferenceType, Repository as G2Repository};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const REMOTE: &str = "origin";
+
+/// The heads of a repository's branches at a specific point in time (e.g., when the repository was
+/// sampled). A pinned clone only contains the pinned branches, each at its pinned head, so that a
+/// harvest that is repeated later analyzes the exact same history even though the repository has
+/// moved on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoPin {
+    /// The ids of the head commits, keyed by the branch names (e.g., `main`)
+    pub heads: BTreeMap<String, String>,
+}
+
+impl RepoPin {
+    pub fn new(heads: BTreeMap<String, String>) -> Self {
+        Self { heads }
+    }
+
+    /// Rewrite the remote branches of the given clone to the pinned state: Branches that are not
+    /// pinned are removed, pinned branches are reset to their pinned heads.
+    ///
+    /// Pinned heads that are no longer part of the clone (e.g., because the branch was
+    /// force-pushed or deleted) cannot be restored and are skipped with a warning. Returns the
+    /// number of branches that could be pinned.
+    pub(crate) fn apply(&self, repository: &G2Repository) -> Result<usize, Error> {
+        let to_error = |e| Error::new(ErrorKind::RepoLoad(e));
+        let mut references = vec![];
+        for branch in repository
+            .branches(Some(BranchType::Remote))
+            .map_err(to_error)?
+        {
+            let (branch, _) = branch.map_err(to_error)?;
+            // Symbolic references (i.e., origin/HEAD) follow the branches they point to
+            if branch.get().kind() == Some(ReferenceType::Symbolic) {
+                continue;
+            }
+            if let Some(name) = branch.get().name() {
+                references.push(name.to_string());
+            }
+        }
+        for reference in references {
+            debug!("removing {reference} before pinning");
+            repository
+                .find_reference(&reference)
+                .and_then(|mut r| r.delete())
+                .map_err(to_error)?;
+        }
+
+        let mut pinned = 0;
+        for (branch, head) in &self.heads {
+            let commit = Oid::from_str(head).and_then(|id| repository.find_commit(id));
+            match commit {
+                Ok(commit) => {
+                    repository
+                        .reference(
+                            &format!("refs/remotes/{REMOTE}/{branch}"),
+                            commit.id(),
+                            true,
+                            "pin branch head",
+                        )
+                        .map_err(to_error)?;
+                    pinned += 1;
+                }
+                Err(error) => warn!("cannot pin {branch} to {head}: {error}"),
+            }
+        }
+        Ok(pinned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::pin::RepoPin;
+    use crate::git::test_util::TestRepository;
+    use git2::BranchType;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn clones_are_reset_to_pinned_heads() {
+        let fixture = TestRepository::new();
+        let first = fixture.commit("main", &[], &[], "first");
+        let second = fixture.commit("main", &[], &[first], "second");
+        let repository = fixture.repository();
+        // Simulate the remote branches of a clone
+        for branch in ["main", "feature"] {
+            repository
+                .reference(&format!("refs/remotes/origin/{branch}"), second, true, "")
+                .unwrap();
+        }
+
+        let pin = RepoPin::new(BTreeMap::from([
+            ("main".to_string(), first.to_string()),
+            ("deleted".to_string(), "1".repeat(40)),
+        ]));
+        assert_eq!(pin.apply(repository).unwrap(), 1);
+
+        let branches: Vec<_> = repository
+            .branches(Some(BranchType::Remote))
+            .unwrap()
+            .map(|b| {
+                let (branch, _) = b.unwrap();
+                (
+                    branch.name().unwrap().unwrap().to_string(),
+                    branch.get().target().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(branches, vec![("origin/main".to_string(), first)]);
+    }
+}
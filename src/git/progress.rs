@@ -0,0 +1,196 @@
+use crate::clock::{Clock, SystemClock};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+/// The progress of a clone or fetch, as reported by libgit2 while objects are received
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TransferProgress {
+    /// The number of objects that the remote sends, once it is known
+    pub total_objects: usize,
+    pub received_objects: usize,
+    pub indexed_objects: usize,
+    pub received_bytes: usize,
+}
+
+impl From<&git2::Progress<'_>> for TransferProgress {
+    fn from(progress: &git2::Progress<'_>) -> Self {
+        Self {
+            total_objects: progress.total_objects(),
+            received_objects: progress.received_objects(),
+            indexed_objects: progress.indexed_objects(),
+            received_bytes: progress.received_bytes(),
+        }
+    }
+}
+
+/// Receives the progress of all clones and fetches of the process (see
+/// [set_progress_reporter]), e.g., to show the progress of long clones
+pub trait ProgressReporter: Debug + Send + Sync {
+    /// Called whenever libgit2 received or indexed objects of the repository with the given URL
+    fn transfer(&self, url: &str, progress: &TransferProgress);
+
+    /// Called when the transfer of the repository with the given URL has stalled and is aborted
+    fn stalled(&self, url: &str, timeout: Duration) {
+        warn!("aborting the transfer of {url}: no progress for {timeout:?}");
+    }
+}
+
+/// Logs the progress of each transfer at most once per interval (default: 30 seconds), so that
+/// long clones are visible in the log without flooding it
+#[derive(Debug)]
+pub struct LogReporter {
+    interval: Duration,
+    last_logged: Mutex<HashMap<String, Instant>>,
+}
+
+impl Default for LogReporter {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30))
+    }
+}
+
+impl LogReporter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_logged: Mutex::default(),
+        }
+    }
+}
+
+impl ProgressReporter for LogReporter {
+    fn transfer(&self, url: &str, progress: &TransferProgress) {
+        let now = Instant::now();
+        let mut last_logged = self
+            .last_logged
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let done = progress.total_objects > 0 && progress.indexed_objects == progress.total_objects;
+        let due = last_logged
+            .get(url)
+            .is_none_or(|last| now.duration_since(*last) >= self.interval);
+        if done {
+            last_logged.remove(url);
+        } else if due {
+            last_logged.insert(url.to_string(), now);
+        } else {
+            return;
+        }
+        info!(
+            "receiving {url}: {}/{} objects ({} indexed), {} MiB",
+            progress.received_objects,
+            progress.total_objects,
+            progress.indexed_objects,
+            progress.received_bytes / (1024 * 1024)
+        );
+    }
+}
+
+static STATIC_PROGRESS_REPORTER: Lazy<RwLock<Arc<dyn ProgressReporter>>> =
+    Lazy::new(|| RwLock::new(Arc::new(LogReporter::default())));
+
+/// Replace the reporter that receives the progress of all clones and fetches of the process. By
+/// default, progress is logged (see [LogReporter]).
+pub fn set_progress_reporter(reporter: Arc<dyn ProgressReporter>) {
+    *STATIC_PROGRESS_REPORTER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = reporter;
+}
+
+pub(crate) fn progress_reporter() -> Arc<dyn ProgressReporter> {
+    STATIC_PROGRESS_REPORTER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Detects transfers that make no progress, i.e., that receive neither objects nor bytes, for
+/// longer than a timeout
+#[derive(Debug)]
+pub(crate) struct StallDetector {
+    timeout: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    last_change: DateTime<Utc>,
+    last_progress: TransferProgress,
+}
+
+impl StallDetector {
+    pub(crate) fn new(timeout: Option<Duration>) -> Self {
+        Self::with_clock(timeout, Arc::new(SystemClock))
+    }
+
+    pub(crate) fn with_clock(timeout: Option<Duration>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            timeout,
+            last_change: clock.now(),
+            clock,
+            last_progress: TransferProgress::default(),
+        }
+    }
+
+    /// Record the given progress and return whether the transfer has stalled
+    pub(crate) fn stalled(&mut self, progress: &TransferProgress) -> bool {
+        let now = self.clock.now();
+        if *progress != self.last_progress {
+            self.last_progress = *progress;
+            self.last_change = now;
+            return false;
+        }
+        self.timeout
+            .is_some_and(|timeout| (now - self.last_change).to_std().unwrap_or_default() > timeout)
+    }
+}
+
+/// Configure libgit2 to fail reads from servers that send nothing for the given timeout (e.g.,
+/// the [CloneConfig::stall_timeout](crate::git::CloneConfig::stall_timeout)). A transfer that
+/// blocks while waiting for data never reports progress, so these transfers cannot be detected by
+/// a [StallDetector].
+///
+/// # Safety
+/// The timeout is a global option of libgit2, which is set without synchronization. It must be
+/// set before any clone or fetch starts, i.e., before other threads may use libgit2 (e.g., at the
+/// start of `main`).
+pub unsafe fn set_server_timeout(timeout: Duration) -> Result<(), git2::Error> {
+    let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    git2::opts::set_server_timeout_in_milliseconds(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use crate::git::progress::{StallDetector, TransferProgress};
+    use chrono::Duration as ChronoDuration;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn transfers_without_progress_stall() {
+        let clock = Arc::new(ManualClock::at_timestamp(1674832148));
+        let mut detector = StallDetector::with_clock(Some(Duration::from_secs(120)), clock.clone());
+        let mut progress = TransferProgress {
+            total_objects: 100,
+            ..TransferProgress::default()
+        };
+        assert!(!detector.stalled(&progress));
+        clock.advance(ChronoDuration::try_seconds(100).unwrap());
+        assert!(!detector.stalled(&progress));
+        // Receiving bytes counts as progress, even if no object is complete yet
+        progress.received_bytes = 1024;
+        clock.advance(ChronoDuration::try_seconds(100).unwrap());
+        assert!(!detector.stalled(&progress));
+        clock.advance(ChronoDuration::try_seconds(120).unwrap());
+        assert!(!detector.stalled(&progress));
+        clock.advance(ChronoDuration::try_seconds(1).unwrap());
+        assert!(detector.stalled(&progress));
+
+        // Without a timeout, transfers never stall
+        let mut detector = StallDetector::with_clock(None, clock.clone());
+        clock.advance(ChronoDuration::try_days(1).unwrap());
+        assert!(!detector.stalled(&TransferProgress::default()));
+    }
+}
@@ -0,0 +1,236 @@
+use log::info;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::Notify;
+
+/// Limits on the resources that clones of remote repositories may occupy at the same time. The
+/// limits apply to all clones of the process, across all concurrent harvests.
+///
+/// If a limit is reached, cloning pauses until enough clones have been dropped (which deletes
+/// their temporary directories); it does not fail. All repositories of a single search are held at
+/// the same time, so a search reserves the clone directories of all its repositories in one step
+/// before it clones any of them, and never waits while it holds clones. A reservation is always
+/// allowed if no other clones exist, so that a fork network with more repositories than
+/// [ResourceLimits::max_clone_dirs], or a repository larger than the disk quota, can still be
+/// harvested.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ResourceLimits {
+    /// The maximum number of temporary clone directories that exist at the same time
+    pub max_clone_dirs: Option<usize>,
+    /// The maximum number of bytes that all clones may occupy on disk. The size of a clone is only
+    /// known after cloning, so a clone that starts below the quota may exceed it.
+    pub disk_quota: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Whether the given number of additional clone directories may be reserved
+    fn allow(&self, usage: &ResourceUsage, clone_dirs: usize) -> bool {
+        usage.clone_dirs == 0
+            || (self
+                .max_clone_dirs
+                .is_none_or(|max| usage.clone_dirs + clone_dirs <= max)
+                && self.disk_quota.is_none_or(|quota| usage.disk_usage < quota))
+    }
+}
+
+/// The resources that are currently occupied by clones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub clone_dirs: usize,
+    pub disk_usage: u64,
+}
+
+/// Tracks the resources occupied by clones and lets clones wait until resources become available
+#[derive(Debug, Default)]
+pub(crate) struct CloneResources {
+    usage: Mutex<ResourceUsage>,
+    released: Notify,
+}
+
+static STATIC_RESOURCES_INSTANCE: Lazy<Arc<CloneResources>> = Lazy::new(Default::default);
+
+pub(crate) fn resources_instance() -> Arc<CloneResources> {
+    STATIC_RESOURCES_INSTANCE.clone()
+}
+
+/// The resources that are currently occupied by all clones of the process
+pub fn resource_usage() -> ResourceUsage {
+    *STATIC_RESOURCES_INSTANCE.usage()
+}
+
+impl CloneResources {
+    /// Lock the usage. The usage consists of plain counters that are never left in an
+    /// inconsistent state, so a lock that was poisoned by a panicking harvest is recovered.
+    fn usage(&self) -> MutexGuard<'_, ResourceUsage> {
+        self.usage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait until the given limits allow another clone and reserve a clone directory for it.
+    pub(crate) async fn acquire(self: &Arc<Self>, limits: &ResourceLimits) -> CloneLease {
+        Arc::new(self.reserve(limits, 1).await).lease()
+    }
+
+    /// Wait until the given limits allow the given number of clones and reserve all of their
+    /// clone directories at once. The caller must not hold other leases while it waits, so that
+    /// concurrent searches cannot wait for each other's clones.
+    pub(crate) async fn reserve(
+        self: &Arc<Self>,
+        limits: &ResourceLimits,
+        clone_dirs: usize,
+    ) -> CloneReservation {
+        let mut waiting = false;
+        loop {
+            // Register for notifications before checking, so that no release is missed
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            {
+                let mut usage = self.usage();
+                if limits.allow(&usage, clone_dirs) {
+                    usage.clone_dirs += clone_dirs;
+                    return CloneReservation {
+                        resources: self.clone(),
+                        clone_dirs,
+                    };
+                }
+                if !waiting {
+                    info!(
+                        "resource limits reached ({} clones, {} bytes); pausing {clone_dirs} \
+                         clone(s)",
+                        usage.clone_dirs, usage.disk_usage
+                    );
+                    waiting = true;
+                }
+            }
+            released.await;
+        }
+    }
+}
+
+/// Clone directories that were reserved for the repositories of a search. The reserved
+/// directories are released when the reservation and all leases taken from it are dropped.
+#[derive(Debug)]
+pub(crate) struct CloneReservation {
+    resources: Arc<CloneResources>,
+    clone_dirs: usize,
+}
+
+impl CloneReservation {
+    /// Take a lease for one of the reserved clone directories, which never waits
+    pub(crate) fn lease(self: Arc<Self>) -> CloneLease {
+        CloneLease {
+            resources: self.resources.clone(),
+            disk_usage: 0,
+            _reservation: self,
+        }
+    }
+}
+
+impl Drop for CloneReservation {
+    fn drop(&mut self) {
+        let mut usage = self.resources.usage();
+        usage.clone_dirs -= self.clone_dirs;
+        drop(usage);
+        self.resources.released.notify_waiters();
+    }
+}
+
+/// A reserved clone directory. The disk usage of the clone is released when the lease is dropped,
+/// and its directory once all leases of its reservation are dropped.
+#[derive(Debug)]
+pub struct CloneLease {
+    resources: Arc<CloneResources>,
+    disk_usage: u64,
+    _reservation: Arc<CloneReservation>,
+}
+
+impl CloneLease {
+    /// Record the disk usage of the clone after cloning has finished
+    pub(crate) fn record_disk_usage(&mut self, disk_usage: u64) {
+        let mut usage = self.resources.usage();
+        usage.disk_usage = usage.disk_usage - self.disk_usage + disk_usage;
+        self.disk_usage = disk_usage;
+    }
+}
+
+impl Drop for CloneLease {
+    fn drop(&mut self) {
+        let mut usage = self.resources.usage();
+        usage.disk_usage -= self.disk_usage;
+        drop(usage);
+        self.resources.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::resources::{CloneResources, ResourceLimits};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn clones_wait_for_released_resources() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let resources = Arc::new(CloneResources::default());
+            let limits = ResourceLimits {
+                max_clone_dirs: Some(2),
+                disk_quota: Some(100),
+            };
+            let mut first = resources.acquire(&limits).await;
+            first.record_disk_usage(150);
+
+            // The disk quota is exceeded, so the second clone has to wait
+            let waiting = tokio::spawn({
+                let resources = resources.clone();
+                async move { resources.acquire(&limits).await }
+            });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(!waiting.is_finished());
+
+            drop(first);
+            let second = tokio::time::timeout(Duration::from_secs(1), waiting)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(resources.usage.lock().unwrap().clone_dirs, 1);
+            drop(second);
+            assert_eq!(resources.usage.lock().unwrap().clone_dirs, 0);
+        });
+    }
+
+    #[test]
+    fn searches_reserve_all_clones_at_once() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let resources = Arc::new(CloneResources::default());
+            let limits = ResourceLimits {
+                max_clone_dirs: Some(2),
+                disk_quota: None,
+            };
+            // A network larger than the limit is searched if no other clones exist
+            let network = Arc::new(resources.reserve(&limits, 3).await);
+            let leases: Vec<_> = (0..3).map(|_| network.clone().lease()).collect();
+            assert_eq!(resources.usage.lock().unwrap().clone_dirs, 3);
+
+            // Another search waits without holding any clones
+            let waiting = tokio::spawn({
+                let resources = resources.clone();
+                async move { resources.reserve(&limits, 2).await }
+            });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(!waiting.is_finished());
+
+            // The directories are released once the search drops all of its clones
+            drop(network);
+            drop(leases);
+            let other = tokio::time::timeout(Duration::from_secs(1), waiting)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(resources.usage.lock().unwrap().clone_dirs, 2);
+            drop(other);
+            assert_eq!(resources.usage.lock().unwrap().clone_dirs, 0);
+        });
+    }
+}
@@ -0,0 +1,181 @@
+use crate::git::LoadedRepository;
+use crate::SearchResult;
+use git2::{Oid, Repository, Signature, Time};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use temp_dir::TempDir;
+
+/// A repository in a temporary directory, in which tests create the commits that they collect and
+/// search. The directory is deleted when the repository (or the directory returned by
+/// [TestRepository::into_loaded]) is dropped.
+pub(crate) struct TestRepository {
+    directory: TempDir,
+    repository: Repository,
+}
+
+impl TestRepository {
+    pub(crate) fn new() -> Self {
+        let directory = TempDir::new().unwrap();
+        let repository = Repository::init(directory.path()).unwrap();
+        Self {
+            directory,
+            repository,
+        }
+    }
+
+    pub(crate) fn repository(&self) -> &Repository {
+        &self.repository
+    }
+
+    pub(crate) fn path(&self) -> &std::path::Path {
+        self.directory.path()
+    }
+
+    /// Commit a tree with the given files and their contents on the given branch, which is
+    /// created or moved to the commit. Files in directories (e.g., `lib/file`) are committed in
+    /// subtrees.
+    pub(crate) fn commit(
+        &self,
+        branch: &str,
+        files: &[(&str, &str)],
+        parents: &[Oid],
+        message: &str,
+    ) -> Oid {
+        self.commit_as(&signature(), branch, files, parents, message)
+    }
+
+    /// Commit a tree with a single file named `file` (see [TestRepository::commit])
+    pub(crate) fn commit_file(
+        &self,
+        branch: &str,
+        content: &str,
+        parent: Option<Oid>,
+        message: &str,
+    ) -> Oid {
+        let parents: Vec<Oid> = parent.into_iter().collect();
+        self.commit(branch, &[("file", content)], &parents, message)
+    }
+
+    /// Commit with the given author and committer (see [TestRepository::commit])
+    pub(crate) fn commit_as(
+        &self,
+        signature: &Signature,
+        branch: &str,
+        files: &[(&str, &str)],
+        parents: &[Oid],
+        message: &str,
+    ) -> Oid {
+        let files: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(path, content)| (*path, content.as_bytes()))
+            .collect();
+        let tree = self.repository.find_tree(self.write_tree(&files)).unwrap();
+        let parents: Vec<_> = parents
+            .iter()
+            .map(|id| self.repository.find_commit(*id).unwrap())
+            .collect();
+        let id = self
+            .repository
+            .commit(
+                None,
+                signature,
+                signature,
+                message,
+                &tree,
+                &parents.iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+        // Unlike Repository::branch, this also moves the branch that HEAD points to
+        let reference = format!("refs/heads/{branch}");
+        self.repository
+            .reference(&reference, id, true, message)
+            .unwrap();
+        id
+    }
+
+    fn write_tree(&self, files: &[(&str, &[u8])]) -> Oid {
+        let mut builder = self.repository.treebuilder(None).unwrap();
+        let mut directories: BTreeMap<&str, Vec<(&str, &[u8])>> = BTreeMap::new();
+        for (path, content) in files {
+            match path.split_once('/') {
+                Some((directory, path)) => directories
+                    .entry(directory)
+                    .or_default()
+                    .push((path, content)),
+                None => {
+                    let blob = self.repository.blob(content).unwrap();
+                    builder.insert(path, blob, 0o100644).unwrap();
+                }
+            }
+        }
+        for (directory, files) in directories {
+            let tree = self.write_tree(&files);
+            builder.insert(directory, tree, 0o040000).unwrap();
+        }
+        builder.write().unwrap()
+    }
+
+    /// The repository as a local repository, with the directory that has to outlive it
+    pub(crate) fn into_loaded(self) -> (TempDir, LoadedRepository) {
+        let loaded = LoadedRepository::LocalRepo {
+            path: self.directory.path().display().to_string(),
+            repository: self.repository,
+        };
+        (self.directory, loaded)
+    }
+}
+
+/// The signature with which test commits are created by default
+pub(crate) fn signature() -> Signature<'static> {
+    Signature::now("test", "test@example.com").unwrap()
+}
+
+/// The default signature at the given time, in seconds since the epoch
+pub(crate) fn signature_at(time: i64) -> Signature<'static> {
+    Signature::new("test", "test@example.com", &Time::new(time, 0)).unwrap()
+}
+
+/// Builds search results for tests that process results without searching commits. By default,
+/// the result pairs the cherry `a` with the target `b`, whose other metadata is empty.
+pub(crate) struct ResultBuilder(Value);
+
+impl ResultBuilder {
+    pub(crate) fn new(method: &str) -> Self {
+        let commit = |id: &str| {
+            json!({
+                "id": id,
+                "parent_ids": [],
+                "message": "",
+                "author": "",
+                "committer": "",
+                "time": "",
+            })
+        };
+        Self(json!({
+            "search_method": method,
+            "cherry_and_target": {"cherry": commit("a"), "target": commit("b")},
+        }))
+    }
+
+    /// Set a field of the cherry's metadata (e.g., `id` or `repository`)
+    pub(crate) fn cherry(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.0["cherry_and_target"]["cherry"][field] = value.into();
+        self
+    }
+
+    /// Set a field of the target's metadata (e.g., `id` or `repository`)
+    pub(crate) fn target(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.0["cherry_and_target"]["target"][field] = value.into();
+        self
+    }
+
+    /// Set a field of the result itself (e.g., `score` or `fork_context`)
+    pub(crate) fn with(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.0[field] = value.into();
+        self
+    }
+
+    pub(crate) fn build(self) -> SearchResult {
+        serde_json::from_value(self.0).unwrap()
+    }
+}
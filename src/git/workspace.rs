@@ -0,0 +1,240 @@
+use crate::git::util::disk_usage;
+use crate::run::RunId;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use temp_dir::TempDir;
+use uuid::Uuid;
+
+/// The prefix of the names of the temporary directories into which repositories are cloned
+pub(crate) const CLONE_PREFIX: &str = "cherry-harvest-";
+/// The name of the marker file in each temporary clone directory
+const MARKER: &str = ".cherry-harvest-clone.yaml";
+/// Clone directories whose owner cannot be determined are considered orphans after this time
+const ORPHAN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The id of the current process's clones, with which its clone directories are marked
+static RUN_ID: Lazy<RunId> = Lazy::new(Uuid::new_v4);
+static CLEANUP_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// The marker of a temporary clone directory, which identifies the run that created it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CloneMarker {
+    pub(crate) run_id: RunId,
+    pub(crate) pid: u32,
+    pub(crate) created: DateTime<Utc>,
+}
+
+impl CloneMarker {
+    /// The marker of the current run
+    fn current() -> Self {
+        Self {
+            run_id: *RUN_ID,
+            pid: std::process::id(),
+            created: Utc::now(),
+        }
+    }
+
+    fn read(directory: &Path) -> Option<Self> {
+        let content = fs::read_to_string(directory.join(MARKER)).ok()?;
+        serde_yaml::from_str(&content).ok()
+    }
+
+    pub(crate) fn write(&self, directory: &Path) -> io::Result<()> {
+        let content = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(directory.join(MARKER), content)
+    }
+
+    /// Whether the run that created the marker has ended, i.e., whether it is not the current run
+    /// and its process no longer runs
+    fn is_orphaned(&self) -> bool {
+        self.run_id != *RUN_ID && !is_running(self.pid, self.created)
+    }
+}
+
+/// Whether the process with the given id, which was running at the given time, still runs. On
+/// platforms whose processes cannot be queried, processes are assumed to run for at most a day.
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32, _since: DateTime<Utc>) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: u32, since: DateTime<Utc>) -> bool {
+    Utc::now()
+        .signed_duration_since(since)
+        .to_std()
+        .unwrap_or_default()
+        < ORPHAN_AGE
+}
+
+/// Create a temporary directory for a clone. The directory must be marked once the repository
+/// has been cloned into it (see [mark_clone_directory]).
+pub(crate) fn create_clone_directory() -> io::Result<TempDir> {
+    TempDir::with_prefix(CLONE_PREFIX)
+}
+
+/// Mark the given clone directory with the current run, so that it can be removed by later runs
+/// if it is not cleaned up (see [remove_orphaned_clones]). Repositories can only be cloned into
+/// empty directories, so clones are marked after cloning; clones that were interrupted before
+/// are removed once they are old enough.
+pub(crate) fn mark_clone_directory(directory: &Path) -> io::Result<()> {
+    CloneMarker::current().write(directory)
+}
+
+/// Remove the given temporary clone directory. Failures (e.g., because files are locked on
+/// Windows) are logged and counted (see [cleanup_failures]).
+pub(crate) fn remove_clone_directory(path: &Path) {
+    match fs::remove_dir_all(path) {
+        Ok(()) => debug!("removed the clone in {}", path.display()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => {
+            CLEANUP_FAILURES.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "could not remove the clone in {}; it is removed by a later run: {error}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// The number of temporary clone directories that the current process could not remove
+pub fn cleanup_failures() -> usize {
+    CLEANUP_FAILURES.load(Ordering::Relaxed)
+}
+
+/// The outcome of removing orphaned clones (see [remove_orphaned_clones])
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanedClones {
+    /// The removed clone directories
+    pub removed: Vec<PathBuf>,
+    /// The number of bytes that the removed directories occupied
+    pub reclaimed_bytes: u64,
+    /// The orphaned clone directories that could not be removed
+    pub failed: Vec<PathBuf>,
+}
+
+/// Remove the temporary clone directories in the given directory (usually
+/// [std::env::temp_dir]) that earlier runs did not clean up, e.g., because they crashed or
+/// because files were locked. A clone directory is orphaned if the run that created it has ended,
+/// i.e., if its process no longer runs. Directories without a marker are orphaned once they have
+/// not been modified for a day. Clones of running harvests are never removed.
+pub fn remove_orphaned_clones<P: AsRef<Path>>(directory: P) -> OrphanedClones {
+    let directory = directory.as_ref();
+    let mut outcome = OrphanedClones::default();
+    let Ok(entries) = fs::read_dir(directory) else {
+        return outcome;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let is_clone = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(CLONE_PREFIX)
+            && entry.file_type().is_ok_and(|t| t.is_dir());
+        if !is_clone {
+            continue;
+        }
+        let orphaned = match CloneMarker::read(&path) {
+            Some(marker) => marker.is_orphaned(),
+            None => entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    SystemTime::now()
+                        .duration_since(modified)
+                        .is_ok_and(|age| age >= ORPHAN_AGE)
+                }),
+        };
+        if !orphaned {
+            continue;
+        }
+        let size = disk_usage(&path);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {
+                outcome.reclaimed_bytes += size;
+                outcome.removed.push(path);
+            }
+            Err(error) => {
+                warn!(
+                    "could not remove the orphaned clone in {}: {error}",
+                    path.display()
+                );
+                outcome.failed.push(path);
+            }
+        }
+    }
+    if !outcome.removed.is_empty() {
+        info!(
+            "removed {} orphaned clones, reclaiming {} bytes",
+            outcome.removed.len(),
+            outcome.reclaimed_bytes
+        );
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::workspace::{
+        create_clone_directory, mark_clone_directory, remove_orphaned_clones, CloneMarker,
+        CLONE_PREFIX, RUN_ID,
+    };
+    use chrono::Utc;
+    use std::fs;
+    use uuid::Uuid;
+
+    #[test]
+    fn orphaned_clones_are_removed() {
+        let workspace = temp_dir::TempDir::new().unwrap();
+        let clone = |name: &str, marker: Option<CloneMarker>| {
+            let path = workspace.path().join(name);
+            fs::create_dir(&path).unwrap();
+            fs::write(path.join("objects"), [0u8; 1000]).unwrap();
+            if let Some(marker) = marker {
+                marker.write(&path).unwrap();
+            }
+            path
+        };
+        let marker = |run_id, pid| CloneMarker {
+            run_id,
+            pid,
+            created: Utc::now(),
+        };
+        // The process of the crashed run no longer runs
+        let crashed = clone(
+            &format!("{CLONE_PREFIX}crashed"),
+            Some(marker(Uuid::new_v4(), u32::MAX)),
+        );
+        let current = clone(
+            &format!("{CLONE_PREFIX}current"),
+            Some(marker(*RUN_ID, u32::MAX)),
+        );
+        let concurrent = clone(
+            &format!("{CLONE_PREFIX}concurrent"),
+            Some(marker(Uuid::new_v4(), std::process::id())),
+        );
+        // Recently modified directories without a marker may still be in use
+        let unmarked = clone(&format!("{CLONE_PREFIX}unmarked"), None);
+        let unrelated = clone("unrelated", Some(marker(Uuid::new_v4(), u32::MAX)));
+
+        let outcome = remove_orphaned_clones(workspace.path());
+        assert_eq!(outcome.removed, vec![crashed.clone()]);
+        assert!(outcome.reclaimed_bytes >= 1000);
+        assert!(outcome.failed.is_empty());
+        assert!(!crashed.exists());
+        for path in [current, concurrent, unmarked, unrelated] {
+            assert!(path.exists());
+        }
+
+        let directory = create_clone_directory().unwrap();
+        mark_clone_directory(directory.path()).unwrap();
+        assert!(CloneMarker::read(directory.path()).is_some_and(|m| !m.is_orphaned()));
+    }
+}
@@ -0,0 +1,65 @@
+//! Hashes that are equal across runs, platforms, and Rust releases. std's DefaultHasher is not
+//! guaranteed to be stable across Rust releases, so it is not used for hashes that end up in
+//! persisted signatures or statistics.
+use std::hash::Hasher;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a over the written bytes, whose result is finalized with [splitmix64] to mix the bits of
+/// short inputs
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StableHasher {
+    state: u64,
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self { state: FNV_OFFSET }
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        splitmix64(self.state)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// The FNV-1a hash of the given bytes
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = StableHasher::default();
+    hasher.write(bytes);
+    hasher.state
+}
+
+/// The stable hash of the given bytes
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    splitmix64(fnv1a(bytes))
+}
+
+/// Mixes the bits of the given value (see http://xorshift.di.unimi.it/splitmix64.c)
+pub(crate) fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashing::stable_hash;
+
+    #[test]
+    fn hashes_are_stable() {
+        // Changing these values changes the signatures of all persisted runs
+        assert_eq!(stable_hash(b""), 0xc3817c016ba4ff30);
+        assert_ne!(stable_hash(b"+fn"), stable_hash(b"-fn"));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::git::RepoId;
+use crate::precision::csv_field;
+use crate::{Result, SearchResult};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+/// A directory of a repository together with the number of picks that changed it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryHotspot {
+    pub directory: String,
+    /// The rank of the directory in its repository; directories with the same number of picks
+    /// share a rank
+    pub rank: usize,
+    pub picks: usize,
+    /// The fraction of all picks of the repository that changed the directory
+    pub share: f64,
+}
+
+/// Hotspots aggregates the picks of each repository by the directories that the picks change, so
+/// that the subsystems which are backported most often can be ranked.
+///
+/// Each pick is counted once per directory that its target changes (or that its cherry changes,
+/// if the directories of the target are unknown). A pick that was found by several search
+/// methods is counted only once. The directories of a commit are only known if its diff was
+/// extracted during the search; picks whose directories are unknown are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hotspots {
+    depth: Option<usize>,
+    /// The number of counted picks per repository
+    picks: BTreeMap<RepoId, usize>,
+    counts: BTreeMap<(RepoId, String), usize>,
+    counted: HashSet<(RepoId, String, String)>,
+}
+
+impl Hotspots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count picks only for the first `depth` components of the directories (e.g., `src/git` for
+    /// `src/git/github` with a depth of two), so that picks are aggregated per subsystem. By
+    /// default, picks are counted for the full directories.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Count the given pick for the given repository
+    pub fn add(&mut self, repo: &RepoId, result: &SearchResult) {
+        let pair = result.commit_pair();
+        let directories = match pair.target().directories() {
+            [] => pair.cherry().directories(),
+            directories => directories,
+        };
+        if directories.is_empty() {
+            return;
+        }
+        let key = (
+            repo.clone(),
+            pair.cherry().id().to_string(),
+            pair.target().id().to_string(),
+        );
+        if !self.counted.insert(key) {
+            return;
+        }
+
+        *self.picks.entry(repo.clone()).or_default() += 1;
+        let truncated: HashSet<String> = directories
+            .iter()
+            .map(|directory| self.truncate(directory))
+            .collect();
+        for directory in truncated {
+            *self.counts.entry((repo.clone(), directory)).or_default() += 1;
+        }
+    }
+
+    pub fn add_all<'a, I: IntoIterator<Item = &'a SearchResult>>(
+        &mut self,
+        repo: &RepoId,
+        results: I,
+    ) {
+        results
+            .into_iter()
+            .for_each(|result| self.add(repo, result));
+    }
+
+    fn truncate(&self, directory: &str) -> String {
+        match self.depth {
+            Some(depth) if directory != "." => directory
+                .split('/')
+                .take(depth.max(1))
+                .collect::<Vec<_>>()
+                .join("/"),
+            _ => directory.to_string(),
+        }
+    }
+
+    /// The repositories for which picks were counted
+    pub fn repositories(&self) -> impl Iterator<Item = &RepoId> {
+        self.picks.keys()
+    }
+
+    /// The directories of the given repository, ordered by their rank and then by path
+    pub fn ranking(&self, repo: &RepoId) -> Vec<DirectoryHotspot> {
+        let total = self.picks.get(repo).copied().unwrap_or_default();
+        let mut directories: Vec<(&str, usize)> = self
+            .counts
+            .iter()
+            .filter(|((r, _), _)| r == repo)
+            .map(|((_, directory), picks)| (directory.as_str(), *picks))
+            .collect();
+        directories.sort_by(|(dir_a, a), (dir_b, b)| b.cmp(a).then(dir_a.cmp(dir_b)));
+
+        let mut ranking: Vec<DirectoryHotspot> = Vec::with_capacity(directories.len());
+        for (index, (directory, picks)) in directories.into_iter().enumerate() {
+            let rank = match ranking.last() {
+                Some(previous) if previous.picks == picks => previous.rank,
+                _ => index + 1,
+            };
+            ranking.push(DirectoryHotspot {
+                directory: directory.to_string(),
+                rank,
+                picks,
+                share: picks as f64 / total as f64,
+            });
+        }
+        ranking
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.picks.is_empty()
+    }
+
+    /// Export the rankings of all repositories as CSV with the columns
+    /// `repository,rank,directory,picks,share`. Only the `top_n` directories of each repository
+    /// are exported, if given.
+    pub fn write_csv<W: Write>(&self, mut writer: W, top_n: Option<usize>) -> Result<()> {
+        writeln!(writer, "repository,rank,directory,picks,share")?;
+        for repo in self.repositories() {
+            let ranking = self.ranking(repo);
+            for hotspot in ranking.iter().take(top_n.unwrap_or(usize::MAX)) {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{:.4}",
+                    csv_field(&repo.to_string()),
+                    hotspot.rank,
+                    csv_field(&hotspot.directory),
+                    hotspot.picks,
+                    hotspot.share
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::ResultBuilder;
+    use crate::hotspots::Hotspots;
+    use crate::{RepoId, SearchResult};
+
+    fn result(method: &str, target: &str, directories: &[&str]) -> SearchResult {
+        ResultBuilder::new(method)
+            .target("id", target)
+            .target("directories", directories)
+            .build()
+    }
+
+    #[test]
+    fn picks_are_ranked_per_directory() {
+        let results = [
+            result("MessageScan", "b", &["src/git", "src/search/methods"]),
+            // Found again by another method
+            result("ExactDiffMatch", "b", &["src/git", "src/search/methods"]),
+            result("MessageScan", "c", &["src/git/github"]),
+            result("MessageScan", "d", &["docs, old"]),
+            result("MessageScan", "e", &[]),
+        ];
+        let repo = RepoId::github("owner", "repo");
+        let mut hotspots = Hotspots::new();
+        hotspots.add_all(&repo, &results);
+        let ranking = hotspots.ranking(&repo);
+        let ranked: Vec<(&str, usize, usize)> = ranking
+            .iter()
+            .map(|h| (h.directory.as_str(), h.rank, h.picks))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                ("docs, old", 1, 1),
+                ("src/git", 1, 1),
+                ("src/git/github", 1, 1),
+                ("src/search/methods", 1, 1)
+            ]
+        );
+        assert!((ranking[0].share - 1.0 / 3.0).abs() < f64::EPSILON);
+
+        let mut subsystems = Hotspots::new().with_depth(2);
+        subsystems.add_all(&repo, &results);
+        let ranking = subsystems.ranking(&repo);
+        assert_eq!(
+            (
+                ranking[0].directory.as_str(),
+                ranking[0].rank,
+                ranking[0].picks
+            ),
+            ("src/git", 1, 2)
+        );
+        assert_eq!((ranking[1].rank, ranking[2].rank), (2, 2));
+
+        let mut csv = vec![];
+        subsystems.write_csv(&mut csv, Some(2)).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "repository,rank,directory,picks,share\n\
+             github:owner/repo,1,src/git,2,0.6667\n\
+             github:owner/repo,2,\"docs, old\",1,0.3333\n"
+        );
+    }
+}
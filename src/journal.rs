@@ -0,0 +1,257 @@
+use crate::error::{Error, ErrorKind};
+use crate::Result;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// The number of bytes that are buffered while an artifact is written. Artifacts are serialized
+/// directly into the buffered file, so that writing an artifact holds at most this many bytes of
+/// its serialized form in memory, no matter how many results it contains.
+pub(crate) const WRITE_BUDGET: usize = 64 * 1024;
+
+/// The formats in which artifacts are serialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// JSON for paths with the extension `.json`, YAML otherwise. Both formats can be read with
+    /// serde_yaml, because JSON is a subset of YAML.
+    pub(crate) fn of<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Yaml,
+        }
+    }
+}
+
+/// Serialize the given value to the given path atomically: The value is written to a temporary
+/// file next to the target, which then replaces the target. An interrupted write thus never
+/// leaves a truncated file behind.
+///
+/// The value is streamed into the file through a buffer of [WRITE_BUDGET] bytes instead of being
+/// serialized into a string first.
+pub(crate) fn serialize_atomic<P: AsRef<Path>, T: Serialize + ?Sized>(
+    path: P,
+    value: &T,
+    format: Format,
+) -> Result<()> {
+    write_atomic_with(path, |writer| match format {
+        Format::Yaml => Ok(serde_yaml::to_writer(writer, value)?),
+        Format::Json => Ok(serde_json::to_writer(writer, value)?),
+    })
+}
+
+/// Write a temporary file with the given function and replace the file at the given path with it.
+/// The temporary file is removed if the function fails. Its name is unique, so that concurrent
+/// writers of the same file (e.g., threads or other harvests) never write into the same temporary
+/// file, and each rename publishes a complete file.
+fn write_atomic_with<P: AsRef<Path>, F: FnOnce(&mut BufWriter<File>) -> Result<()>>(
+    path: P,
+    write: F,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", Uuid::new_v4()));
+    let temp_path = PathBuf::from(temp_path);
+    let mut writer = BufWriter::with_capacity(WRITE_BUDGET, File::create(&temp_path)?);
+    let written = write(&mut writer)
+        .and_then(|_| {
+            let file = writer.into_inner().map_err(|error| error.into_error())?;
+            Ok(file.sync_all()?)
+        })
+        .and_then(|_| Ok(fs::rename(&temp_path, path)?));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+/// An append-only journal with one JSON entry per line (JSONL). Each entry is flushed when it is
+/// appended, so that a crash loses at most the entry that was being written.
+///
+/// A crash in the middle of an append leaves a truncated last line behind. When the journal is
+/// opened, a last line that cannot be parsed is dropped with a warning, and the journal is
+/// rewritten atomically without it. Unparsable lines before the last line indicate other kinds of
+/// corruption and result in an error.
+pub(crate) struct Journal<T> {
+    file: File,
+    entry_type: PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Journal<T> {
+    /// Open the journal at the given path, or create it if it does not exist. Returns the journal
+    /// and all entries that it contains.
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> Result<(Self, Vec<T>)> {
+        let path = path.as_ref();
+        let entries = if path.exists() {
+            let (entries, recovered) = Self::parse(path, &fs::read_to_string(path)?)?;
+            if recovered {
+                Self::rewrite(path, &entries)?;
+            }
+            entries
+        } else {
+            vec![]
+        };
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok((
+            Self {
+                file,
+                entry_type: PhantomData,
+            },
+            entries,
+        ))
+    }
+
+    /// Parse the entries of the journal. Returns whether a truncated last line was dropped.
+    fn parse(path: &Path, content: &str) -> Result<(Vec<T>, bool)> {
+        let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+        let mut entries = Vec::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(error) if index + 1 == lines.len() => {
+                    warn!(
+                        "dropping the truncated last entry of {}: {error}",
+                        path.display()
+                    );
+                    return Ok((entries, true));
+                }
+                Err(error) => {
+                    return Err(Error::new(ErrorKind::Journal(format!(
+                        "{} is corrupted in line {}: {error}",
+                        path.display(),
+                        index + 1
+                    ))))
+                }
+            }
+        }
+        // A last line without a line break may have been cut off between two entries
+        let truncated = !content.is_empty() && !content.ends_with('\n');
+        Ok((entries, truncated))
+    }
+
+    /// Atomically replace the journal with a snapshot of the given entries
+    pub(crate) fn rewrite<P: AsRef<Path>>(path: P, entries: &[T]) -> Result<()> {
+        write_atomic_with(path, |writer| {
+            for entry in entries {
+                serde_json::to_writer(&mut *writer, entry).map_err(journal_error)?;
+                writer.write_all(b"\n")?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Append the given entry and flush it to disk
+    pub(crate) fn append(&mut self, entry: &T) -> Result<()> {
+        let mut line = serde_json::to_string(entry).map_err(journal_error)?;
+        line.push('\n');
+        // A single write, so that concurrent readers never observe a partial entry
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// An entry of a journal that cannot be serialized
+fn journal_error(error: serde_json::Error) -> Error {
+    Error::new(ErrorKind::Journal(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, ErrorKind};
+    use crate::journal::{serialize_atomic, write_atomic_with, Format, Journal, WRITE_BUDGET};
+    use std::fs;
+
+    #[test]
+    fn truncated_last_entries_are_recovered() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let path = directory.path().join("journal.jsonl");
+        {
+            let (mut journal, entries) = Journal::<String>::open(&path).unwrap();
+            assert!(entries.is_empty());
+            journal.append(&"first".to_string()).unwrap();
+            journal.append(&"second".to_string()).unwrap();
+        }
+        // Simulate a crash in the middle of an append
+        let mut content = fs::read_to_string(&path).unwrap();
+        content.push_str("\"thi");
+        fs::write(&path, content).unwrap();
+
+        let (mut journal, entries) = Journal::<String>::open(&path).unwrap();
+        assert_eq!(entries, vec!["first", "second"]);
+        journal.append(&"third".to_string()).unwrap();
+        let (_, entries) = Journal::<String>::open(&path).unwrap();
+        assert_eq!(entries, vec!["first", "second", "third"]);
+
+        // Corruption before the last line cannot be recovered
+        fs::write(&path, "\"first\"\n{\n\"third\"\n").unwrap();
+        assert!(Journal::<String>::open(&path).is_err());
+    }
+
+    #[test]
+    fn atomic_writes_replace_files() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let path = directory.path().join("snapshot.yaml");
+        serialize_atomic(&path, "old", Format::Yaml).unwrap();
+        serialize_atomic(&path, "new", Format::Yaml).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn concurrent_atomic_writes_publish_complete_files() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let path = directory.path().join("results.json");
+        let values: Vec<Vec<String>> = (0..8)
+            .map(|writer| (0..1000).map(|i| format!("{writer}-{i}")).collect())
+            .collect();
+        std::thread::scope(|scope| {
+            for value in &values {
+                let path = &path;
+                scope.spawn(move || serialize_atomic(path, value, Format::Json).unwrap());
+            }
+        });
+        let written: Vec<String> =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(values.contains(&written));
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+
+        // The temporary file of a failed write is removed
+        let failed = write_atomic_with(&path, |_| {
+            Err(Error::new(ErrorKind::Journal("failed".to_string())))
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn artifacts_larger_than_the_budget_are_streamed() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let entries: Vec<String> = (0..WRITE_BUDGET / 8)
+            .map(|i| format!("entry {i}"))
+            .collect();
+        let yaml = directory.path().join("entries.yaml");
+        serialize_atomic(&yaml, &entries, Format::Yaml).unwrap();
+        assert!(fs::metadata(&yaml).unwrap().len() > WRITE_BUDGET as u64);
+        let read: Vec<String> = serde_yaml::from_str(&fs::read_to_string(&yaml).unwrap()).unwrap();
+        assert_eq!(read, entries);
+
+        let json = directory.path().join("entries.json");
+        serialize_atomic(&json, &entries, Format::of(&json)).unwrap();
+        assert!(fs::read_to_string(&json)
+            .unwrap()
+            .starts_with("[\"entry 0\","));
+        let read: Vec<String> = serde_json::from_str(&fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(read, entries);
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 2);
+    }
+}
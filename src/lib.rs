@@ -1,52 +1,116 @@
+//! cherry-harvest searches the history of git repositories for cherry picks.
+//!
+//! The supported public API consists of the items that are defined in or re-exported by the crate
+//! root and the items that are re-exported by the [prelude]. Only changes to these items (including
+//! their signatures) are considered breaking changes. The remaining
+//! public modules expose internals that may change in any release; internals that are only public
+//! for benchmarking are hidden from the documentation.
 pub use crate::git::collect_commits;
-use log::{error, info};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, StreamExt};
+use journal::{serialize_atomic, Format, Journal};
+use log::{debug, error, info, warn};
+use octocrab::models::RepositoryId;
 use sampling::Sample;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use schema::Artifact;
+use search::adequacy::CorpusProfile;
+use search::conflict::annotate_conflicts;
+use search::rebase::annotate_rebases;
+use search::revert::annotate_reverts;
+use search::stored_diff::attach_diffs;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::fs::File;
-use std::io::Write;
+use std::panic;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task;
 
+pub mod calibration;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod clock;
+pub mod compat;
+pub mod coverage;
 pub mod error;
+pub mod evaluation;
+pub mod fingerprint;
 pub mod git;
+mod hashing;
+pub mod hotspots;
+mod journal;
+pub mod message_clusters;
+pub mod output;
+pub mod precision;
+pub mod prelude;
+pub mod report;
+pub mod results;
+pub mod run;
 pub mod sampling;
+pub mod schema;
 pub mod search;
+pub mod security;
+pub mod timeline;
+pub mod visualization;
 
 pub use error::Error;
 pub use git::Commit;
 pub use git::Diff;
+pub use git::GitRepository;
+pub use git::RepoId;
 pub use git::RepoLocation;
+pub use run::HarvestRun;
+pub use search::ChangeIdMatch;
 pub use search::CherryAndTarget;
+pub use search::CombinedSearch;
+pub use search::CommitFacet;
+pub use search::CommitMetadata;
+pub use search::ExactChangeMatch;
 pub use search::ExactDiffMatch;
 pub use search::MessageScan;
+pub use search::MessageSimilarity;
+pub use search::MethodMetrics;
+pub use search::PartialDiffMatch;
+pub use search::PatchIdMatch;
 pub use search::SearchMethod;
+pub use search::SearchPreset;
 pub use search::SearchResult;
 pub use search::TraditionalLSH;
 
 // For profiling with flame graphs to find bottlenecks
-use crate::git::{GitRepository, LoadedRepository};
+use crate::error::ErrorKind;
+use crate::git::{
+    calculate_diffs, collect_commits_with_config, CloneConfig, CollectionConfig, CommitFilter,
+    EmptyRepository, LoadedRepository,
+};
 pub(crate) use firestorm::{profile_fn, profile_section};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 // TODO: Check out GitHub torrent for science
 
-/// Searches for cherry picks with all given search methods.
+/// Searches for cherry picks with all given search methods among the commits that pass the given
+/// filter, which narrows the searched commits, e.g., by time, author, or path, before their diffs
+/// are computed. [CommitFilter::default] keeps all commits.
 ///
 /// # Examples
 /// TODO: Update after implementing other search methods
 /// ```
-/// use cherry_harvest::{MessageScan, RepoLocation};
-/// use cherry_harvest::git::GitRepository;
+/// use cherry_harvest::prelude::*;
 ///
 /// let method = MessageScan::default();
 /// // link to a test repository
 /// let server = "https://github.com/AlexanderSchultheiss/cherries-one".to_string();
 /// let runtime = tokio::runtime::Runtime::new().unwrap();
-/// let results = runtime.block_on(
-///     cherry_harvest::search_with(&[&GitRepository::from(RepoLocation::Server(server))], method)
-/// ).unwrap().1;
+/// let methods: Vec<Box<dyn SearchMethod>> = vec![Box::new(method)];
+/// let results = runtime.block_on(cherry_harvest::search_with_multiple(
+///     &[&GitRepository::from(RepoLocation::Server(server))],
+///     &methods,
+///     &CommitFilter::default(),
+/// )).unwrap().1;
 /// assert_eq!(results.len(), 2);
 /// let expected_commits = vec![
 ///     "b7d2e4b330165ae92e4442fb8ccfa067acd62d44",
@@ -67,7 +131,69 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub async fn search_with_multiple(
     repos: &[&GitRepository],
     methods: &[Box<dyn SearchMethod>],
+    filter: &CommitFilter,
+) -> Result<(TotalCommitsCount, Vec<SearchResult>)> {
+    let config = CollectionConfig {
+        filter: filter.clone(),
+        ..CollectionConfig::default()
+    };
+    search_with_config(repos, methods, &config).await
+}
+
+/// Searches for cherry picks with all given search methods. The commits of the repositories are
+/// collected according to the given configuration.
+pub async fn search_with_config(
+    repos: &[&GitRepository],
+    methods: &[Box<dyn SearchMethod>],
+    config: &CollectionConfig,
 ) -> Result<(TotalCommitsCount, Vec<SearchResult>)> {
+    let (commit_counts, results) = search_with_commit_counts(repos, methods, config).await?;
+    Ok((commit_counts.values().sum(), results))
+}
+
+/// Searches for cherry picks like [search_with_config], but counts the searched commits per
+/// repository. Commits that appear in several repositories (e.g., in a fork and its parent) are
+/// counted for the first repository in which they were found, which is also the repository that
+/// is recorded for the commits of the results (see [CommitMetadata::repository]).
+pub async fn search_with_commit_counts(
+    repos: &[&GitRepository],
+    methods: &[Box<dyn SearchMethod>],
+    config: &CollectionConfig,
+) -> Result<(CommitCounts, Vec<SearchResult>)> {
+    let (commit_counts, results, _) = search_with_metrics(repos, methods, config).await?;
+    Ok((commit_counts, results))
+}
+
+/// Searches for cherry picks like [search_with_commit_counts], but also measures each search
+/// method, i.e., its wall-clock time and, for methods that compare candidate pairs, its peak
+/// number of candidates and its number of similarity comparisons (see [MethodMetrics]). The
+/// metrics are ordered like the given methods.
+///
+/// # Examples
+/// ```no_run
+/// use cherry_harvest::git::CollectionConfig;
+/// use cherry_harvest::{GitRepository, RepoLocation, SearchMethod, TraditionalLSH};
+///
+/// # async fn compare() -> cherry_harvest::Result<()> {
+/// let repo = GitRepository::from(RepoLocation::Filesystem("path/to/repo".into()));
+/// let methods: Vec<Box<dyn SearchMethod>> = vec![
+///     Box::new(TraditionalLSH::new(8, 100, 5, 0.7)),
+///     Box::new(TraditionalLSH::new(8, 100, 10, 0.7)),
+/// ];
+/// let (_, _, metrics) =
+///     cherry_harvest::search_with_metrics(&[&repo], &methods, &CollectionConfig::default())
+///         .await?;
+/// for metrics in metrics {
+///     println!("{metrics}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn search_with_metrics(
+    repos: &[&GitRepository],
+    methods: &[Box<dyn SearchMethod>],
+    config: &CollectionConfig,
+) -> Result<(CommitCounts, Vec<SearchResult>, Vec<MethodMetrics>)> {
     let repo_locations: Vec<&RepoLocation> = repos.iter().map(|r| &r.location).collect();
     profile_fn!(search_with_multiple);
     info!(
@@ -75,18 +201,8 @@ pub async fn search_with_multiple(
         repo_locations.len(),
         methods.len()
     );
-    // TODO: Collect commits in parallel
-    let mut loaded_repos: Vec<LoadedRepository> = Vec::new();
-    for repo_location in repo_locations.iter() {
-        match git::clone_or_load(repo_location).await {
-            Ok(repo) => loaded_repos.push(repo),
-            Err(error) => {
-                error!("was not able to clone or load repository: {error}");
-                return Err(error);
-            }
-        }
-    }
-    let commits = collect_commits(&loaded_repos);
+    let loaded_repos = load_repositories(repos, &config.clone).await?;
+    let commits = collect_commits_with_config(&loaded_repos, config);
     // Some commits have empty textual diffs (e.g., only changes to file modifiers)
     // We cannot consider these as cherry-picks, because no text == no information
     // TODO: Migrate to better location
@@ -101,12 +217,33 @@ pub async fn search_with_multiple(
     );
     // Reassign to convert to vector
     let mut commits = commits.into_iter().collect::<Vec<Commit>>();
+    remove_noise(&mut commits, repos, config);
+    filter_commits(&mut commits, config);
+    extract_facets(&mut commits, methods.iter().map(|m| m.required_facet()));
+    // Low result counts are easier to interpret if the corpus is known to be unsuitable
+    for warning in CorpusProfile::of(&commits).assess(methods) {
+        warn!("{warning}");
+    }
     {
         profile_section!(map_results);
-        let results = methods
-            .iter()
-            .flat_map(|m| m.search(&mut commits))
-            .collect::<Vec<SearchResult>>();
+        let mut results = vec![];
+        let mut metrics = Vec::with_capacity(methods.len());
+        for method in methods {
+            let (method_results, method_metrics) =
+                MethodMetrics::measure(method.as_ref(), &mut commits);
+            debug!("{method_metrics}");
+            results.extend(method_results);
+            metrics.push(method_metrics);
+        }
+        trace_result_repositories(&mut results, &commits, repos, &loaded_repos);
+        attach_diffs(&mut results, &mut commits, &config.result_diffs);
+        annotate_reverts(&mut results, &commits);
+        if config.annotate_conflicts {
+            annotate_conflicts(&mut results, &commits);
+        }
+        if let Some(min_run) = config.rebase_min_run {
+            annotate_rebases(&mut results, min_run);
+        }
 
         info!(
             "number of cherry-picks found in {} repositories by search:\n{:#?}",
@@ -121,18 +258,272 @@ pub async fn search_with_multiple(
             }
         );
 
-        Ok((commits.len(), results))
+        let repo_ids = repository_ids(repos, &loaded_repos);
+        let mut commit_counts: CommitCounts = repos.iter().map(|repo| (repo.id, 0)).collect();
+        for commit in &commits {
+            if let Some(id) = repo_ids.get(commit.repository().path()) {
+                *commit_counts.entry(*id).or_default() += 1;
+            }
+        }
+        Ok((commit_counts, results, metrics))
+    }
+}
+
+/// Searches for cherry picks between the repositories of each given pair with all given search
+/// methods. In contrast to [search_with_multiple], only candidates across the two repositories of
+/// a pair are considered; picks within a single repository are never reported. This allows
+/// comparing, e.g., a fork with its upstream without searching their (possibly large) histories
+/// for internal picks.
+///
+/// Commits that are part of both repositories (i.e., their shared history) cannot be the cherry or
+/// target of a pick across the repositories and are excluded. The returned count is the total
+/// number of commits that were searched in all pairs.
+pub async fn search_across(
+    pairs: &[(GitRepository, GitRepository)],
+    methods: &[Box<dyn SearchMethod>],
+) -> Result<(TotalCommitsCount, Vec<SearchResult>)> {
+    search_across_with_config(pairs, methods, &CollectionConfig::default()).await
+}
+
+/// Searches for cherry picks across the repositories of each given pair like [search_across]. The
+/// commits of the repositories are collected according to the given configuration.
+pub async fn search_across_with_config(
+    pairs: &[(GitRepository, GitRepository)],
+    methods: &[Box<dyn SearchMethod>],
+    config: &CollectionConfig,
+) -> Result<(TotalCommitsCount, Vec<SearchResult>)> {
+    profile_fn!(search_across);
+    info!(
+        "started searching for cherry-picks across {} repository pairs with {} search method(s)",
+        pairs.len(),
+        methods.len()
+    );
+    let mut total_commits = 0;
+    let mut results = vec![];
+    for (left, right) in pairs {
+        let repos = [left, right];
+        let loaded_repos = load_repositories(&repos, &config.clone).await?;
+        let left_commits = collect_commits_with_config(&loaded_repos[..1], config);
+        let right_commits = collect_commits_with_config(&loaded_repos[1..], config);
+        // The shared history of both repositories is excluded
+        let (mut left_commits, mut right_commits): (Vec<Commit>, Vec<Commit>) = (
+            left_commits.difference(&right_commits).cloned().collect(),
+            right_commits.difference(&left_commits).cloned().collect(),
+        );
+        remove_noise(&mut left_commits, &repos, config);
+        filter_commits(&mut left_commits, config);
+        remove_noise(&mut right_commits, &repos, config);
+        filter_commits(&mut right_commits, config);
+        info!(
+            "searching across {} and {} unique commits of {} and {}",
+            left_commits.len(),
+            right_commits.len(),
+            left.name,
+            right.name
+        );
+        let facets = methods.iter().map(|m| m.required_facet());
+        extract_facets(&mut left_commits, facets.clone());
+        extract_facets(&mut right_commits, facets);
+        let mut pair_results = methods
+            .iter()
+            .flat_map(|m| m.search_across(&mut left_commits, &mut right_commits))
+            .collect::<Vec<SearchResult>>();
+        let mut commits = left_commits;
+        commits.append(&mut right_commits);
+        trace_result_repositories(&mut pair_results, &commits, &repos, &loaded_repos);
+        attach_diffs(&mut pair_results, &mut commits, &config.result_diffs);
+        annotate_reverts(&mut pair_results, &commits);
+        if config.annotate_conflicts {
+            annotate_conflicts(&mut pair_results, &commits);
+        }
+        if let Some(min_run) = config.rebase_min_run {
+            annotate_rebases(&mut pair_results, min_run);
+        }
+        info!(
+            "found {} cherry-picks across {} and {}",
+            pair_results.len(),
+            left.name,
+            right.name
+        );
+        total_commits += commits.len();
+        results.append(&mut pair_results);
+    }
+    Ok((total_commits, results))
+}
+
+/// Clone or load all repositories, with at most [CloneConfig::max_concurrent_repos] repositories
+/// at the same time. The loaded repositories are returned in the order of the given
+/// repositories. Fails if a repository cannot be loaded, or if all repositories are empty. The
+/// repositories that have not started loading when a repository fails are not loaded; loads that
+/// are already running finish, and their repositories are dropped right away.
+async fn load_repositories(
+    repos: &[&GitRepository],
+    config: &CloneConfig,
+) -> Result<Vec<LoadedRepository>> {
+    let repo_locations: Vec<&RepoLocation> = repos.iter().map(|r| &r.location).collect();
+    // Cloning blocks the thread on which it runs, so each repository is loaded on its own blocking
+    // thread. Tasks are only spawned when the stream is polled, which bounds their number.
+    let runtime = Handle::current();
+    // The clone directories of all remote repositories are reserved at once, so that concurrent
+    // searches never wait for each other while they hold clones
+    let remote = repo_locations
+        .iter()
+        .filter(|location| matches!(location, RepoLocation::Server(_)))
+        .count();
+    let reservation = match remote {
+        0 => None,
+        _ => Some(Arc::new(
+            git::resources_instance()
+                .reserve(&config.limits, remote)
+                .await,
+        )),
+    };
+    // If loading stops early (i.e., a load failed or panicked), the loads that have already been
+    // spawned but have not started yet are skipped
+    let aborted = AbortLoads(Arc::new(AtomicBool::new(false)));
+    let mut loads = stream::iter(repos.iter().map(|repo| {
+        let (location, config, runtime) = (repo.location.clone(), config.clone(), runtime.clone());
+        let (reservation, aborted) = (reservation.clone(), aborted.0.clone());
+        task::spawn_blocking(move || {
+            (!aborted.load(Ordering::Relaxed)).then(|| {
+                runtime.block_on(git::clone_or_load_reserved(&location, &config, reservation))
+            })
+        })
+    }))
+    .buffered(config.concurrency());
+    let mut loaded_repos: Vec<LoadedRepository> = Vec::new();
+    for repo in repos {
+        let load = match loads.next().await {
+            Some(Ok(Some(load))) => load,
+            Some(Ok(None)) => unreachable!("loads are only skipped after loading stopped"),
+            // A panic while loading is propagated as if the repository had been loaded here
+            Some(Err(error)) => panic::resume_unwind(error.into_panic()),
+            None => unreachable!("one load is spawned per repository"),
+        };
+        match load {
+            Ok(loaded) => {
+                if let Some(pin) = &repo.pin {
+                    let pinned = loaded.pin(pin)?;
+                    info!(
+                        "pinned {pinned} of {} branches of {}",
+                        pin.heads.len(),
+                        repo.name
+                    );
+                }
+                loaded_repos.push(loaded)
+            }
+            Err(error) => {
+                error!("was not able to clone or load repository: {error}");
+                return Err(error);
+            }
+        }
+    }
+    // Empty repositories do not contribute any commits, but they are reported
+    let empty_repos = loaded_repos
+        .iter()
+        .zip(repo_locations.iter())
+        .filter_map(|(loaded, location)| {
+            let emptiness = loaded.check_empty()?;
+            warn!("{location}: {emptiness}");
+            Some(emptiness)
+        })
+        .collect::<Vec<EmptyRepository>>();
+    if !loaded_repos.is_empty() && empty_repos.len() == loaded_repos.len() {
+        return Err(Error::new(ErrorKind::EmptyRepository(empty_repos[0])));
+    }
+    Ok(loaded_repos)
+}
+
+/// Skips the loads of [load_repositories] that have not started yet once it is dropped
+struct AbortLoads(Arc<AtomicBool>);
+
+impl Drop for AbortLoads {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Remove the known-noise commits of the configuration and of the given repositories. Commits are
+/// shared by the repositories of a fork network, so a commit that is listed for one repository is
+/// removed regardless of the repository in which it was found.
+fn remove_noise(commits: &mut Vec<Commit>, repos: &[&GitRepository], config: &CollectionConfig) {
+    let mut noise = config.noise.clone();
+    repos.iter().for_each(|repo| noise.extend(&repo.noise));
+    let removed = noise.remove_from(commits);
+    if removed > 0 {
+        info!("ignoring {removed} known-noise commits");
+    }
+}
+
+/// Remove the commits that are not kept by the filter of the configuration (see [git::CommitFilter])
+fn filter_commits(commits: &mut Vec<Commit>, config: &CollectionConfig) {
+    let removed = config.filter.remove_from(commits);
+    if removed > 0 {
+        info!("ignoring {removed} commits that do not match the commit filter");
+    }
+}
+
+/// Extract the commit data that is required by at least one search method. Commit metadata is
+/// always available, so only diffs have to be extracted.
+fn extract_facets<I: IntoIterator<Item = CommitFacet>>(commits: &mut [Commit], facets: I) {
+    match facets.into_iter().max() {
+        Some(CommitFacet::Diff) => {
+            info!("extracting the diffs of {} commits", commits.len());
+            calculate_diffs(commits);
+        }
+        Some(CommitFacet::Metadata) | None => {
+            info!("skipping diff extraction; all search methods only require commit metadata");
+        }
+    }
+}
+
+/// Maps the paths of the loaded repositories to the ids of the repositories
+fn repository_ids<'r>(
+    repos: &[&GitRepository],
+    loaded_repos: &'r [LoadedRepository],
+) -> HashMap<&'r Path, RepositoryId> {
+    loaded_repos
+        .iter()
+        .zip(repos)
+        .map(|(loaded, repo)| (loaded.repository().path(), repo.id))
+        .collect()
+}
+
+/// Record the repository in which the cherry and target of each result were found
+fn trace_result_repositories(
+    results: &mut [SearchResult],
+    commits: &[Commit],
+    repos: &[&GitRepository],
+    loaded_repos: &[LoadedRepository],
+) {
+    let repo_ids = repository_ids(repos, loaded_repos);
+    let commit_repos: HashMap<String, RepositoryId> = commits
+        .iter()
+        .filter_map(|c| {
+            repo_ids
+                .get(c.repository().path())
+                .map(|id| (c.id().to_string(), *id))
+        })
+        .collect();
+    for result in results {
+        let pair = result.commit_pair_mut();
+        let cherry_repo = commit_repos.get(pair.cherry().id()).copied();
+        let target_repo = commit_repos.get(pair.target().id()).copied();
+        pair.cherry_mut().set_repository(cherry_repo);
+        pair.target_mut().set_repository(target_repo);
     }
 }
 
 pub type TotalCommitsCount = usize;
 
+/// The number of searched commits per repository (see [search_with_commit_counts])
+pub type CommitCounts = BTreeMap<RepositoryId, usize>;
+
 /// Searches for cherry picks with the given search search.
 ///
 /// # Examples
 /// ```
-/// use cherry_harvest::{MessageScan, RepoLocation};
-/// use cherry_harvest::git::GitRepository;
+/// use cherry_harvest::prelude::*;
 ///
 /// // initialize the search search
 /// let search = MessageScan::default();
@@ -167,73 +558,554 @@ pub async fn search_with<T: SearchMethod + 'static>(
     method: T,
 ) -> Result<(TotalCommitsCount, Vec<SearchResult>)> {
     profile_fn!(search_with);
-    search_with_multiple(repos, &[Box::new(method)]).await
+    search_with_multiple(repos, &[Box::new(method)], &CommitFilter::default()).await
+}
+
+/// Searches for cherry picks with all given search methods and streams the results as they are
+/// found, so that callers can react to results before the entire search has completed.
+///
+/// Results are sent as soon as a search method has finished; they are not sorted in any way. If a
+/// repository cannot be loaded, or if a search method panics, the stream yields the error (of kind
+/// [ErrorKind::Panic] for panics) and ends. Dropping the stream stops the
+/// search after the currently running search method has finished.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::prelude::*;
+/// use futures_util::StreamExt;
+///
+/// let server = "https://github.com/AlexanderSchultheiss/cherries-one".to_string();
+/// let repository = GitRepository::from(RepoLocation::Server(server));
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let results = runtime.block_on(async {
+///     let methods: Vec<Box<dyn SearchMethod + Send>> = vec![Box::<MessageScan>::default()];
+///     let mut stream = Box::pin(cherry_harvest::search_with_streaming(&[&repository], methods));
+///     let mut results = vec![];
+///     while let Some(result) = stream.next().await {
+///         results.push(result.unwrap());
+///     }
+///     results
+/// });
+/// assert_eq!(results.len(), 2);
+/// ```
+pub fn search_with_streaming(
+    repos: &[&GitRepository],
+    methods: Vec<Box<dyn SearchMethod + Send>>,
+) -> impl Stream<Item = Result<SearchResult>> {
+    search_with_streaming_config(repos, methods, CollectionConfig::default())
+}
+
+/// Streams the results of a search as they are found (see [search_with_streaming]). The commits
+/// of the repositories are collected according to the given configuration.
+pub fn search_with_streaming_config(
+    repos: &[&GitRepository],
+    methods: Vec<Box<dyn SearchMethod + Send>>,
+    config: CollectionConfig,
+) -> impl Stream<Item = Result<SearchResult>> {
+    let repos: Vec<GitRepository> = repos.iter().map(|r| (*r).clone()).collect();
+    let search = async move {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let repo_refs: Vec<&GitRepository> = repos.iter().collect();
+        match load_repositories(&repo_refs, &config.clone).await {
+            Ok(loaded_repos) => {
+                // The search is CPU-bound and must not block the async runtime
+                tokio::task::spawn_blocking(move || {
+                    // A panic of a search method ends the stream with an error, so that it cannot
+                    // be mistaken for a completed search
+                    let outcome = report::catch_panic(panic::AssertUnwindSafe(|| {
+                        stream_results(&repos, &methods, &loaded_repos, &config, &sender);
+                        Ok(())
+                    }));
+                    if let Err(error) = outcome {
+                        let _ = sender.send(Err(error));
+                    }
+                });
+            }
+            Err(error) => {
+                let _ = sender.send(Err(error));
+            }
+        }
+        stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|result| (result, receiver))
+        })
+    };
+    stream::once(search).flatten()
+}
+
+/// Runs all search methods one after another and sends their results. Stops early if the receiver
+/// has been dropped.
+fn stream_results(
+    repos: &[GitRepository],
+    methods: &[Box<dyn SearchMethod + Send>],
+    loaded_repos: &[LoadedRepository],
+    config: &CollectionConfig,
+    sender: &UnboundedSender<Result<SearchResult>>,
+) {
+    profile_fn!(stream_results);
+    let repos: Vec<&GitRepository> = repos.iter().collect();
+    let mut commits = collect_commits_with_config(loaded_repos, config)
+        .into_iter()
+        .collect::<Vec<Commit>>();
+    remove_noise(&mut commits, &repos, config);
+    filter_commits(&mut commits, config);
+    info!(
+        "streaming results for {} unique commits from {} repositories",
+        commits.len(),
+        repos.len()
+    );
+    extract_facets(&mut commits, methods.iter().map(|m| m.required_facet()));
+    for method in methods {
+        let mut results = method.search(&mut commits).into_iter().collect::<Vec<_>>();
+        trace_result_repositories(&mut results, &commits, &repos, loaded_repos);
+        attach_diffs(&mut results, &mut commits, &config.result_diffs);
+        annotate_reverts(&mut results, &commits);
+        if config.annotate_conflicts {
+            annotate_conflicts(&mut results, &commits);
+        }
+        if let Some(min_run) = config.rebase_min_run {
+            annotate_rebases(&mut results, min_run);
+        }
+        for result in results {
+            if sender.send(Ok(result)).is_err() {
+                debug!("result stream was dropped; stopping the search");
+                return;
+            }
+        }
+    }
 }
 
 pub fn save_repo_sample<P: AsRef<Path>>(path: P, sample: &Sample) -> Result<()> {
-    let sample = serde_yaml::to_string(&sample)?;
-    fs::write(path, sample)?;
-    Ok(())
+    serialize_atomic(path, sample, Format::Yaml)
 }
 
+/// Load the sample in the given file. Samples of previous versions are upgraded (see [compat]).
 pub fn load_repo_sample<P: AsRef<Path>>(path: P) -> Result<Sample> {
-    let file = fs::File::open(path)?;
-    Ok(serde_yaml::from_reader(file)?)
+    compat::load(Artifact::Sample, path)
 }
 
-pub type RepoName = String;
+/// The outcome of the harvest of a repository, as recorded by a [HarvestTracker]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HarvestStatus {
+    /// The repository was harvested
+    Success,
+    /// The harvest of the repository failed; it is attempted again by later harvests
+    Failed,
+    /// There was nothing to harvest in the repository (e.g., because it is empty); it is not
+    /// attempted again
+    Skipped,
+}
 
+/// The record of the harvest of a repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoRecord {
+    pub repo: RepoId,
+    pub status: HarvestStatus,
+    /// When the harvest finished. Unknown for repositories that older versions tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<DateTime<Utc>>,
+    /// The number of commits that were searched, if the repository was harvested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commits: Option<usize>,
+    /// The cause of the failure, or the reason why the repository was skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl RepoRecord {
+    fn new(repo: RepoId, status: HarvestStatus) -> Self {
+        Self {
+            repo,
+            status,
+            time: Some(Utc::now()),
+            commits: None,
+            message: None,
+        }
+    }
+}
+
+/// Tracks the outcome of the harvest of each repository, so that an interrupted harvest can be
+/// continued and failed repositories can be retried. The outcomes are stored as records in an
+/// append-only journal with one JSON entry per line, which survives a crash in the middle of a
+/// write. The journal also marks the start of each harvest run.
+///
+/// Repositories are tracked by their [RepoId], and the latest record of a repository determines
+/// its status. The separate success and error files of older versions can be imported with
+/// [HarvestTracker::import_legacy]; names in these files are parsed leniently (see
+/// [RepoId::parse_legacy]).
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::{HarvestStatus, HarvestTracker, RepoId};
+///
+/// # fn main() -> cherry_harvest::Result<()> {
+/// # let directory = temp_dir::TempDir::new().unwrap();
+/// let mut tracker = HarvestTracker::open(directory.path().join("harvest.jsonl"))?;
+/// let repo = RepoId::github("owner", "repo");
+/// tracker.add_success(repo.clone(), 1200)?;
+/// assert!(tracker.contains(&repo));
+/// assert_eq!(tracker.record(&repo).unwrap().status, HarvestStatus::Success);
+/// # Ok(())
+/// # }
+/// ```
 pub struct HarvestTracker {
-    success_tracking_file: File,
-    error_tracking_file: File,
-    harvested_repos: HashSet<RepoName>,
-    failed_repos: HashSet<RepoName>,
+    journal: Journal<TrackerEntry>,
+    // The latest record of each repository
+    records: HashMap<RepoId, RepoRecord>,
+}
+
+/// An entry of a tracking journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TrackerEntry {
+    /// The start of a harvest run
+    Run(String),
+    /// A repository that older versions tracked without its outcome, in a success or error file
+    Repo(RepoId),
+    Record(RepoRecord),
 }
 
 impl HarvestTracker {
-    fn load_repo_list<P: AsRef<Path>>(path_to_file: P) -> Result<(HashSet<RepoName>, File)> {
-        Ok(if Path::exists(path_to_file.as_ref()) {
-            let repos = serde_yaml::from_str(&fs::read_to_string(&path_to_file)?)?;
-            let file = File::options().append(true).open(&path_to_file)?;
-            (repos, file)
-        } else {
-            (HashSet::new(), File::create_new(path_to_file)?)
-        })
+    /// Open the tracking journal in the given file, which is created if it does not exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<HarvestTracker> {
+        let (journal, entries) = Journal::open(path.as_ref())?;
+        let mut tracker = HarvestTracker {
+            journal,
+            records: HashMap::new(),
+        };
+        for entry in entries {
+            if let TrackerEntry::Record(record) = entry {
+                tracker.records.insert(record.repo.clone(), record);
+            }
+        }
+        Ok(tracker)
+    }
+
+    /// Import the repositories of the success and error files of older versions, which are YAML
+    /// lists of names or journals of repository ids. The imported records have no timestamps.
+    /// Repositories that are already tracked are not imported again.
+    pub fn import_legacy<P: AsRef<Path>>(
+        &mut self,
+        success_tracking_file: P,
+        error_tracking_file: P,
+    ) -> Result<()> {
+        // The records of the journal are newer than those of the legacy files
+        let tracked: HashSet<RepoId> = self.records.keys().cloned().collect();
+        // Failures are imported first, so that later successes of the same repositories win
+        for (path, status) in [
+            (error_tracking_file.as_ref(), HarvestStatus::Failed),
+            (success_tracking_file.as_ref(), HarvestStatus::Success),
+        ] {
+            let imported: Vec<RepoId> = HarvestTracker::load_legacy_list(path)?
+                .into_iter()
+                .filter(|repo| !tracked.contains(repo))
+                .filter(|repo| self.records.get(repo).is_none_or(|r| r.status != status))
+                .collect();
+            info!(
+                "importing {} repositories from {}",
+                imported.len(),
+                path.display()
+            );
+            for repo in imported {
+                let record = RepoRecord {
+                    time: None,
+                    ..RepoRecord::new(repo, status)
+                };
+                self.add(record)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn load_harvest_tracker<P: AsRef<Path>>(
-        path_to_success_tracking_file: P,
-        path_to_error_tracking_file: P,
-    ) -> Result<HarvestTracker> {
-        let (harvested_repos, success_tracking_file) =
-            HarvestTracker::load_repo_list(path_to_success_tracking_file)?;
-        let (failed_repos, error_tracking_file) =
-            HarvestTracker::load_repo_list(path_to_error_tracking_file)?;
+    fn load_legacy_list(path: &Path) -> Result<Vec<RepoId>> {
+        if !Path::exists(path) {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(path)?;
+        // Journal entries are JSON objects; the oldest versions wrote YAML lists
+        let yaml = content
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .is_some_and(|line| !line.starts_with('{'));
+        if yaml {
+            return Ok(serde_yaml::from_str(&content)?);
+        }
+        let entries: Vec<TrackerEntry> = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                TrackerEntry::Repo(repo) => Some(repo),
+                TrackerEntry::Record(record) => Some(record.repo),
+                TrackerEntry::Run(_) => None,
+            })
+            .collect())
+    }
 
-        Ok(HarvestTracker {
-            success_tracking_file,
-            error_tracking_file,
-            harvested_repos,
+    /// Record the start of the given harvest run
+    pub fn start_run(&mut self, run: &HarvestRun) -> Result<()> {
+        self.journal.append(&TrackerEntry::Run(run.to_string()))
+    }
 
-            failed_repos,
+    /// Whether the given repository has been harvested successfully or skipped, i.e., whether it
+    /// is done. Repositories that older versions tracked only by their names are matched by name.
+    pub fn contains(&self, repo: &RepoId) -> bool {
+        let done = |repo: &RepoId| {
+            self.records
+                .get(repo)
+                .is_some_and(|record| record.status != HarvestStatus::Failed)
+        };
+        done(repo)
+            || repo
+                .name()
+                .is_some_and(|name| done(&RepoId::Legacy(name.into())))
+    }
+
+    /// The latest record of the given repository, if it is tracked
+    pub fn record(&self, repo: &RepoId) -> Option<&RepoRecord> {
+        self.records.get(repo)
+    }
+
+    /// The latest records of all tracked repositories, in no particular order
+    pub fn records(&self) -> impl Iterator<Item = &RepoRecord> {
+        self.records.values()
+    }
+
+    /// The records of the repositories whose latest harvest failed
+    pub fn failed_repos(&self) -> impl Iterator<Item = &RepoRecord> {
+        self.records()
+            .filter(|record| record.status == HarvestStatus::Failed)
+    }
+
+    /// The repositories of the given sample that are not done yet, i.e., that have not been
+    /// harvested or whose harvest failed
+    pub fn pending<'s>(&self, sample: &'s Sample) -> Vec<&'s octocrab::models::Repository> {
+        sample
+            .repos()
+            .iter()
+            .filter(|repo| !self.contains(&RepoId::from(*repo)))
+            .collect()
+    }
+
+    /// The cause of the latest failure of the given repository. None if its latest harvest did
+    /// not fail, or if the failure was recorded by an older version without its cause.
+    pub fn failure_cause(&self, repo: &RepoId) -> Option<&str> {
+        self.records
+            .get(repo)
+            .filter(|record| record.status == HarvestStatus::Failed)?
+            .message
+            .as_deref()
+    }
+
+    /// Record that the given repository was harvested, and how many commits were searched
+    pub fn add_success(&mut self, repo: RepoId, commits: usize) -> Result<()> {
+        self.add(RepoRecord {
+            commits: Some(commits),
+            ..RepoRecord::new(repo, HarvestStatus::Success)
         })
     }
 
-    pub fn contains(&self, repo: &RepoName) -> bool {
-        self.harvested_repos.contains(repo)
+    /// Record that there was nothing to harvest in the given repository for the given reason
+    pub fn add_skipped(&mut self, repo: RepoId, reason: &str) -> Result<()> {
+        self.add(RepoRecord {
+            message: Some(reason.to_string()),
+            ..RepoRecord::new(repo, HarvestStatus::Skipped)
+        })
     }
 
-    pub fn add_success(&mut self, repo: RepoName) -> Result<()> {
-        let repo = format!("- {repo}\n");
-        self.success_tracking_file.write_all(repo.as_bytes())?;
-        self.harvested_repos.insert(repo);
-        Ok(())
+    /// Record that the harvest of the given repository failed with the given error, whose
+    /// message is stored as the cause of the failure
+    pub fn add_error(&mut self, repo: RepoId, error: &Error) -> Result<()> {
+        self.add(RepoRecord {
+            message: Some(error.to_string()),
+            ..RepoRecord::new(repo, HarvestStatus::Failed)
+        })
     }
 
-    pub fn add_error(&mut self, repo: RepoName) -> Result<()> {
-        let repo = format!("- {repo}\n");
-        self.error_tracking_file.write_all(repo.as_bytes())?;
-        self.failed_repos.insert(repo);
+    fn add(&mut self, record: RepoRecord) -> Result<()> {
+        self.journal.append(&TrackerEntry::Record(record.clone()))?;
+        self.records.insert(record.repo.clone(), record);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, ErrorKind};
+    use crate::git::test_util::TestRepository;
+    use crate::git::{CollectionConfig, CommitFilter};
+    use crate::{
+        search_across, search_across_with_config, search_with_multiple, search_with_streaming,
+        Commit, ExactDiffMatch, GitRepository, HarvestRun, HarvestStatus, HarvestTracker, RepoId,
+        RepoLocation, SearchMethod, SearchResult,
+    };
+    use futures_util::StreamExt;
+    use std::collections::HashSet;
+    use std::fs;
+
+    #[test]
+    fn searched_commits_are_filtered() {
+        let test_repo = TestRepository::new();
+        let root = test_repo.commit("main", &[("README", "readme")], &[], "root");
+        let files = [("README", "readme"), ("src/lib", "fn main() {}")];
+        test_repo.commit("main", &files, &[root], "add main");
+        test_repo.commit("feature", &files, &[root], "pick main");
+        let repo = GitRepository::from(RepoLocation::Filesystem(test_repo.path().to_path_buf()));
+        let methods: Vec<Box<dyn SearchMethod>> = vec![Box::<ExactDiffMatch>::default()];
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let (total, results) = runtime
+            .block_on(search_with_multiple(
+                &[&repo],
+                &methods,
+                &CommitFilter::default(),
+            ))
+            .unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(results.len(), 1);
+
+        let filter = CommitFilter::new().path_prefix("docs/");
+        let (total, results) = runtime
+            .block_on(search_with_multiple(&[&repo], &methods, &filter))
+            .unwrap();
+        assert_eq!(total, 0);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn searches_across_repositories_are_configured() {
+        let (upstream, fork) = (TestRepository::new(), TestRepository::new());
+        let files = [("src/lib", "fn main() {}")];
+        upstream.commit("main", &files, &[], "fix");
+        fork.commit("main", &files, &[], "pick");
+        let location = |repo: &TestRepository| {
+            GitRepository::from(RepoLocation::Filesystem(repo.path().to_path_buf()))
+        };
+        let pairs = [(location(&upstream), location(&fork))];
+        let methods: Vec<Box<dyn SearchMethod>> = vec![Box::<ExactDiffMatch>::default()];
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let (total, results) = runtime.block_on(search_across(&pairs, &methods)).unwrap();
+        assert_eq!((total, results.len()), (2, 1));
+
+        let config = CollectionConfig {
+            filter: CommitFilter::new().path_prefix("docs/"),
+            ..CollectionConfig::default()
+        };
+        let (total, results) = runtime
+            .block_on(search_across_with_config(&pairs, &methods, &config))
+            .unwrap();
+        assert_eq!(total, 0);
+        assert!(results.is_empty());
+    }
+
+    struct PanickingMethod;
+
+    impl SearchMethod for PanickingMethod {
+        fn search(&self, _commits: &mut [Commit]) -> HashSet<SearchResult> {
+            panic!("search method failed");
+        }
+
+        fn name(&self) -> &'static str {
+            "PanickingMethod"
+        }
+    }
+
+    #[test]
+    fn streams_end_with_the_panic_of_a_search_method() {
+        let test_repo = TestRepository::new();
+        let root = test_repo.commit("main", &[("file", "a")], &[], "root");
+        test_repo.commit("feature", &[("file", "b")], &[root], "feature");
+        test_repo.commit("main", &[("file", "b")], &[root], "pick");
+        let repo = GitRepository::from(RepoLocation::Filesystem(test_repo.path().to_path_buf()));
+        let methods: Vec<Box<dyn SearchMethod + Send>> =
+            vec![Box::<ExactDiffMatch>::default(), Box::new(PanickingMethod)];
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let items: Vec<_> = runtime.block_on(search_with_streaming(&[&repo], methods).collect());
+        // The results of the methods before the panicking one are streamed
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        match &items[1] {
+            Err(Error(ErrorKind::Panic(message))) => assert_eq!(message, "search method failed"),
+            other => panic!("expected a panic, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn legacy_tracking_files_are_imported() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let journal = directory.path().join("harvest.jsonl");
+        let harvested = directory.path().join("harvested.yaml");
+        let failed = directory.path().join("failed.jsonl");
+        fs::write(&harvested, "# run 1\n- first\n- owner/Second\n").unwrap();
+        fs::write(&failed, "{\"repo\":\"github:owner/third\"}\n").unwrap();
+        let first = RepoId::github("owner", "first");
+        let second = RepoId::github("owner", "second");
+        let third = RepoId::github("owner", "third");
+
+        let mut tracker = HarvestTracker::open(&journal).unwrap();
+        tracker.import_legacy(&harvested, &failed).unwrap();
+        // Legacy names match repositories of any owner
+        assert!(tracker.contains(&first));
+        assert!(tracker.contains(&RepoId::github("other", "first")));
+        assert!(!tracker.contains(&RepoId::github("other", "second")));
+        assert!(tracker.contains(&second));
+        assert!(!tracker.contains(&third));
+        assert_eq!(tracker.record(&third).unwrap().time, None);
+        // Importing again does not duplicate the records
+        tracker.import_legacy(&harvested, &failed).unwrap();
+        drop(tracker);
+        let records = fs::read_to_string(&journal).unwrap().lines().count();
+        assert_eq!(records, 3);
+
+        // Repositories that the journal already tracks keep their records
+        let journal = directory.path().join("resumed.jsonl");
+        let mut tracker = HarvestTracker::open(&journal).unwrap();
+        tracker.add_success(third.clone(), 10).unwrap();
+        tracker.import_legacy(&harvested, &failed).unwrap();
+        assert!(tracker.contains(&third));
+        assert_eq!(tracker.record(&third).unwrap().commits, Some(10));
+    }
+
+    #[test]
+    fn records_are_queried_by_status() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let journal = directory.path().join("harvest.jsonl");
+        let harvested = RepoId::github("owner", "harvested");
+        let empty = RepoId::github("owner", "empty");
+        let failing = RepoId::from_url("https://git.example.org/failing");
+
+        let mut tracker = HarvestTracker::open(&journal).unwrap();
+        tracker.start_run(&HarvestRun::with_seed(42)).unwrap();
+        tracker.add_success(harvested.clone(), 1200).unwrap();
+        tracker.add_skipped(empty.clone(), "no commits").unwrap();
+        let error = Error::new(ErrorKind::Configuration("no such repository".to_string()));
+        tracker.add_error(failing.clone(), &error).unwrap();
+        drop(tracker);
+
+        let mut tracker = HarvestTracker::open(&journal).unwrap();
+        assert!(tracker.contains(&harvested));
+        assert!(tracker.contains(&empty));
+        assert!(!tracker.contains(&failing));
+        let record = tracker.record(&harvested).unwrap();
+        assert_eq!(record.commits, Some(1200));
+        assert!(record.time.is_some());
+        assert_eq!(
+            tracker.record(&empty).unwrap().status,
+            HarvestStatus::Skipped
+        );
+        let failed: Vec<_> = tracker.failed_repos().map(|r| &r.repo).collect();
+        assert_eq!(failed, [&failing]);
+        assert_eq!(tracker.failure_cause(&failing), Some("no such repository"));
+
+        // A later success replaces the failure
+        tracker.add_success(failing.clone(), 7).unwrap();
+        assert!(tracker.contains(&failing));
+        assert_eq!(tracker.failure_cause(&failing), None);
+        assert_eq!(tracker.failed_repos().count(), 0);
+    }
+}
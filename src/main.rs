@@ -1,27 +1,358 @@
 #[macro_use]
 extern crate log;
 
-use cherry_harvest::git::github::ForkNetwork;
+use cherry_harvest::compat;
+use cherry_harvest::error::{Error, ErrorKind};
+use cherry_harvest::git::github::{ForkNetwork, NetworkCache, NetworkHarvest};
+use cherry_harvest::git::progress::set_server_timeout;
+use cherry_harvest::git::{
+    remove_orphaned_clones, CollectionConfig, DiffCache, GitRepository, NoiseList, RepoId,
+    RepoLocation, ResourceLimits, RetryPolicy,
+};
+use cherry_harvest::hotspots::Hotspots;
+use cherry_harvest::output::export_result_store;
+use cherry_harvest::report::{catch_panic, FailureKind, HarvestReport, Policy, RunSummary};
+use cherry_harvest::results::{RepoEntry, ResultCap, ResultStore};
+use cherry_harvest::sampling::filter::RepoFilter;
+use cherry_harvest::sampling::forge::{BitbucketForge, ForgeClient, GitHubForge, GitLabForge};
 use cherry_harvest::sampling::most_stars::{MostStarsSampler, ProgrammingLanguage};
-use cherry_harvest::sampling::GitHubSampler;
+use cherry_harvest::sampling::{GitHubSampler, Sample};
+use cherry_harvest::schema::{validate_file, validate_result_store, Artifact};
+use cherry_harvest::search::message_diff::annotate_message_diffs;
+use cherry_harvest::search::registry::{load_method_configs, MethodConfig, MethodRegistry};
+use cherry_harvest::search::DiffExport;
+use cherry_harvest::timeline::{Period, Timeline};
+use cherry_harvest::visualization::CherryGraph;
 use cherry_harvest::{
-    load_repo_sample, save_repo_sample, HarvestTracker, MessageScan, SearchMethod,
+    load_repo_sample, save_repo_sample, HarvestRun, HarvestTracker, SearchResult,
 };
+use chrono::Duration;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use log::LevelFilter;
 use rayon::prelude::*;
-use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::runtime::Runtime;
+
+/// Harvest cherry-picks from the fork networks of GitHub repositories
+#[derive(Parser)]
+#[command(name = "cherry-harvest", version)]
+struct Cli {
+    /// The folder to which all artifacts are written
+    #[arg(long, global = true, default_value = "output")]
+    output: PathBuf,
+    /// The file that contains the GitHub API token
+    #[arg(long, global = true, default_value = ".github-api-token")]
+    token: PathBuf,
+    /// Write a machine-readable summary of the run as JSON to this file, or to stdout with `-`.
+    /// The exit code is 3 if the run violates the policy, and 1 if it fails with an error.
+    #[arg(long, global = true, value_name = "FILE")]
+    summary: Option<PathBuf>,
+    #[command(flatten)]
+    policy: PolicyArgs,
+    #[command(flatten)]
+    forge: ForgeArgs,
+    /// Harvests a sample of repositories if no command is given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sample repositories from GitHub (unless a sample exists) and harvest their fork networks
+    Harvest {
+        #[command(flatten)]
+        sample: SampleArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        #[command(flatten)]
+        network: NetworkArgs,
+    },
+    /// Sample repositories from GitHub without harvesting them
+    Sample(SampleArgs),
+    /// Continue the harvest of an existing sample; repositories that have been harvested are
+    /// skipped
+    Resume {
+        #[command(flatten)]
+        search: SearchArgs,
+        #[command(flatten)]
+        network: NetworkArgs,
+    },
+    /// Harvest a single repository without its forks
+    AnalyzeRepo {
+        /// The URL or local path of the repository
+        repo: String,
+        #[command(flatten)]
+        search: SearchArgs,
+    },
+    /// Validate artifacts against their schemas, e.g., `validate manifest output/results/index.yaml`.
+    /// The artifact kind `store` validates an entire results folder.
+    Validate {
+        artifact: String,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Upgrade artifacts of previous versions in place, e.g., `upgrade sample output/sample.yaml`;
+    /// the originals are kept with the suffix `.v<version>`. The artifact kind `store` upgrades an
+    /// entire results folder.
+    Upgrade {
+        artifact: String,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Export the picks of results files as graphs of the involved commits, e.g.,
+    /// `graph output/results/github_owner_repo/MessageScan.yaml`. Each graph is written next to
+    /// its results file in the DOT format of Graphviz.
+    Graph {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Also lay out each graph with Graphviz and write it as SVG
+        #[arg(long)]
+        svg: bool,
+    },
+}
+
+#[derive(Args)]
+struct SampleArgs {
+    /// The languages from which repositories are sampled; if none are given, the top 10
+    /// languages of 2024/1 by stars are sampled
+    #[arg(long = "language")]
+    languages: Vec<String>,
+    /// The number of repositories that are sampled per language
+    #[arg(long, default_value_t = 250)]
+    sample_size: usize,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// The search methods to use, which replace the methods of the methods file
+    #[arg(long = "method")]
+    methods: Vec<String>,
+    /// A YAML file with the configurations of the search methods; if the file does not exist,
+    /// MessageScan is used
+    #[arg(long, default_value = "methods.yaml")]
+    methods_file: PathBuf,
+    /// A YAML file with known-noise commits (e.g., mass reformatting), if it exists
+    #[arg(long, default_value = "noise.yaml")]
+    noise_file: PathBuf,
+    /// Only collect commits that are at most this many parent links away from a branch head
+    #[arg(long)]
+    max_history_depth: Option<usize>,
+    /// The maximum number of repositories of a fork network that are cloned and traversed at the
+    /// same time; defaults to the number of CPUs
+    #[arg(long)]
+    max_concurrent_repos: Option<usize>,
+    /// Clone repositories bare, i.e., without a working tree and an index
+    #[arg(long)]
+    bare_clones: bool,
+    /// Only clone the branches with these names or globs (e.g., `main` or `release/*`); the
+    /// first name is the head of the clones
+    #[arg(long = "clone-branch")]
+    clone_branches: Vec<String>,
+    /// Only clone this many commits of each branch
+    #[arg(long)]
+    clone_depth: Option<u32>,
+    /// Keep the clones in `cache/clones` in the output folder, and update them with a fetch in
+    /// later harvests instead of cloning the repositories again
+    #[arg(long)]
+    cache_clones: bool,
+    /// A file with a token with which clones over HTTPS authenticate (e.g., the GitHub API token
+    /// file), for private repositories or GitHub Enterprise instances
+    #[arg(long, value_name = "FILE")]
+    clone_token: Option<PathBuf>,
+    /// The private key with which clones over SSH authenticate, in addition to the keys of the SSH
+    /// agent
+    #[arg(long, value_name = "FILE")]
+    ssh_key: Option<PathBuf>,
+    /// Abort clones that receive no data for this many minutes
+    #[arg(long, value_name = "MINUTES")]
+    stall_timeout: Option<u64>,
+    /// Pause cloning while the clones of the harvest occupy more than this many GiB on disk; 0
+    /// disables the quota
+    #[arg(long, value_name = "GIB", default_value_t = DEFAULT_DISK_QUOTA_GIB)]
+    disk_quota: u64,
+    /// Pause cloning while this many temporary clone directories exist
+    #[arg(long, value_name = "N")]
+    max_clone_dirs: Option<usize>,
+    /// Attempt clones that fail for transient reasons (e.g., network or server errors) up to this
+    /// many times, with exponentially increasing delays
+    #[arg(long, value_name = "N", default_value_t = RetryPolicy::default().max_attempts)]
+    clone_attempts: u32,
+    /// Extract all diffs again instead of reusing the diffs of earlier harvests, which are cached
+    /// in `cache/diffs` in the output folder
+    #[arg(long)]
+    no_diff_cache: bool,
+    /// Record how the message of each target differs from the message of its cherry
+    #[arg(long)]
+    message_diffs: bool,
+    /// Check whether the pick of each result involved a conflict resolution, e.g., by applying its
+    /// cherry to the parent of its target
+    #[arg(long)]
+    annotate_conflicts: bool,
+    /// Annotate runs of at least this many consecutive picks between two branches as rebases
+    /// (e.g., 10), which are single events rather than individual picks
+    #[arg(long, value_name = "MIN_RUN")]
+    detect_rebases: Option<usize>,
+    /// Store the diffs of cherry and target with each result
+    #[arg(long)]
+    store_diffs: bool,
+    /// Store the diffs of cherry and target with each result, but replace the content of their
+    /// lines with hashes that are salted with the content of this file, so that the results
+    /// contain no source code
+    #[arg(long, value_name = "SALT_FILE", conflicts_with = "store_diffs")]
+    redact_diffs: Option<PathBuf>,
+    /// Tune the configured LSH parameters with the statistics of earlier harvests of the same
+    /// repository, which are stored in `cache/lsh` in the output folder, instead of using them as
+    /// they are
+    #[arg(long)]
+    parameter_memory: bool,
+    /// Store at most this many results per method and repository; the results of methods that
+    /// find more are summarized, only a sample of them is stored, and the repository is flagged
+    /// as overflowed in the manifest
+    #[arg(long, value_name = "N")]
+    max_results: Option<usize>,
+    /// The number of results that are stored of methods that exceed the maximum number of results
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    overflow_sample_size: usize,
+}
+
+/// The default disk quota of the clones of a harvest, in GiB
+const DEFAULT_DISK_QUOTA_GIB: u64 = 50;
 
-async fn init() {
+/// The forge from which repositories are sampled and whose forks are harvested
+#[derive(Args)]
+struct ForgeArgs {
+    /// The forge from which repositories are sampled
+    #[arg(long, global = true, value_enum, default_value_t = Forge::GitHub)]
+    forge: Forge,
+    /// The base URL of the forge's API, e.g., of a self-managed GitLab instance
+    #[arg(long, global = true, value_name = "URL")]
+    forge_api: Option<String>,
+    /// The file that contains the API token of GitLab or Bitbucket (GitHub's token is read from
+    /// the token file)
+    #[arg(long, global = true, value_name = "FILE")]
+    forge_token: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Forge {
+    #[value(name = "github")]
+    GitHub,
+    #[value(name = "gitlab")]
+    GitLab,
+    Bitbucket,
+}
+
+impl ForgeArgs {
+    /// The client of the selected forge
+    ///
+    /// # Errors
+    /// Returns a configuration error if the token file cannot be read.
+    fn client(&self) -> Result<Arc<dyn ForgeClient>, Error> {
+        let token = match &self.forge_token {
+            Some(file) => Some(read_secret(file, "forge token")?),
+            None => None,
+        };
+        Ok(match self.forge {
+            Forge::GitHub => Arc::new(GitHubForge),
+            Forge::GitLab => {
+                let mut forge = self
+                    .forge_api
+                    .as_deref()
+                    .map_or_else(GitLabForge::default, GitLabForge::new);
+                if let Some(token) = &token {
+                    forge = forge.with_token(token);
+                }
+                Arc::new(forge)
+            }
+            Forge::Bitbucket => {
+                let mut forge = self
+                    .forge_api
+                    .as_deref()
+                    .map_or_else(BitbucketForge::default, BitbucketForge::new);
+                if let Some(token) = &token {
+                    forge = forge.with_token(token);
+                }
+                Arc::new(forge)
+            }
+        })
+    }
+}
+
+/// The trimmed content of the given file, which contains a secret such as a token
+///
+/// # Errors
+/// Returns a configuration error if the file cannot be read.
+fn read_secret(file: &Path, name: &str) -> Result<String, Error> {
+    fs::read_to_string(file)
+        .map(|secret| secret.trim().to_string())
+        .map_err(|error| {
+            Error::new(ErrorKind::Configuration(format!(
+                "could not read the {name} in {}: {error}",
+                file.display()
+            )))
+        })
+}
+
+/// The policy against which runs are evaluated, e.g., to gate CI pipelines. Commands that do not
+/// search (e.g., `sample`) are not evaluated.
+#[derive(Args)]
+struct PolicyArgs {
+    /// Fail with exit code 3 if more picks are found
+    #[arg(long, global = true)]
+    max_picks: Option<usize>,
+    /// Fail with exit code 3 if fewer picks are found
+    #[arg(long, global = true)]
+    min_picks: Option<usize>,
+    /// Fail with exit code 3 if the harvests of more repositories fail
+    #[arg(long, global = true)]
+    max_failures: Option<usize>,
+}
+
+impl PolicyArgs {
+    fn policy(&self) -> Policy {
+        Policy {
+            max_picks: self.max_picks,
+            min_picks: self.min_picks,
+            max_failures: self.max_failures,
+        }
+    }
+}
+
+#[derive(Args)]
+struct NetworkArgs {
+    /// The maximum number of forks that are harvested per network; with 0, only the sampled
+    /// repositories are harvested
+    #[arg(long, default_value_t = 0)]
+    max_forks: usize,
+    /// Rebuild the fork networks instead of reusing cached networks
+    #[arg(long)]
+    refresh_networks: bool,
+    /// The number of days for which cached fork networks are reused
+    #[arg(long, value_name = "DAYS", default_value_t = 7)]
+    network_cache_ttl_days: u32,
+}
+
+impl Default for NetworkArgs {
+    fn default() -> Self {
+        Self {
+            max_forks: 0,
+            refresh_networks: false,
+            network_cache_ttl_days: 7,
+        }
+    }
+}
+
+async fn init(token_file: &Path) {
+    // The log is written to stderr, so that the summary can be written to stdout
     let _ = env_logger::builder()
-        .is_test(true)
         .filter_level(LevelFilter::Info)
         .try_init();
 
-    let token = fs::read_to_string(".github-api-token").map(|s| match !s.is_empty() {
+    let token = fs::read_to_string(token_file).map(|s| match !s.is_empty() {
         true => Some(s.trim().to_owned()),
         false => None,
     });
@@ -42,18 +373,13 @@ async fn init() {
     }
 }
 
-// TODO: Track which repository a certain commit identified as cherry or pick comes from;
-// currently, we only track the seed repo of a ForkNetwork
 // TODO: Trace commits to all repositories and branches in which they appear in (required for analysis)
 // TODO: More filter options for GitHub sampling (e.g., number of commits, number of forks)
 // TODO: Try to improve performance of ANN similarity search by using FAISS
 // TODO: Set up Docker
 // TODO: Set up GitHub repos as fork network with known cherry-picks to validate functionality
-// TODO: Plot abbreviated history with cherry-picks as graph (only show relevant events) (svg export)?
 // TODO: Set up all tests to not require local repositories
 // TODO: External configuration file
-// TODO: Decent CLI
-// TODO: Allow analysis of specific repositories
 //
 // Just read an interesting SCAM paper that has some nice ideas
 // TODO: Check whether we can consider the hashes of blobs instead of hashes of commits. Can we
@@ -65,145 +391,722 @@ async fn init() {
 // [1]: Mockus et al.: A complete set of related git repositories identified via community
 // detection approaches based on shared commits
 
+/// Lock the given mutex, even if it was poisoned by a panic during the harvest of a repository.
+/// The harvest state is only updated after a repository has been processed, so a panic never
+/// leaves it half-updated.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Validate the given artifacts of the given kind. Returns the exit code.
+fn validate(kind: &str, paths: &[String]) -> i32 {
+    let kinds: Vec<&str> = Artifact::ALL.iter().map(Artifact::name).collect();
+    let artifact = Artifact::from_name(kind);
+    if artifact.is_none() && kind != "store" {
+        eprintln!(
+            "unknown artifact {kind}, expected one of {}, store",
+            kinds.join(", ")
+        );
+        return 2;
+    }
+    let mut code = 0;
+    for path in paths {
+        let validation = match artifact {
+            Some(artifact) => validate_file(artifact, path),
+            None => validate_result_store(path),
+        };
+        match validation {
+            Ok(()) => println!("{path}: valid"),
+            Err(error) => {
+                eprintln!("{error}");
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Upgrade the given artifacts of the given kind in place. Returns the exit code.
+fn upgrade(kind: &str, paths: &[String]) -> i32 {
+    let artifact = Artifact::from_name(kind);
+    if artifact.is_none() && kind != "store" {
+        let kinds: Vec<&str> = Artifact::ALL.iter().map(Artifact::name).collect();
+        eprintln!(
+            "unknown artifact {kind}, expected one of {}, store",
+            kinds.join(", ")
+        );
+        return 2;
+    }
+    let mut code = 0;
+    for path in paths {
+        let upgrade = match artifact {
+            Some(artifact) => compat::upgrade_file(artifact, path).map(|version| match version {
+                Some(version) => format!("upgraded from schema version {version}"),
+                None => "up to date".to_string(),
+            }),
+            None => {
+                compat::upgrade_result_store(path).map(|files| format!("upgraded {files} files"))
+            }
+        };
+        match upgrade {
+            Ok(outcome) => println!("{path}: {outcome}"),
+            Err(error) => {
+                eprintln!("{path}: {error}");
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Export the picks of the given results files as graphs (see [CherryGraph]). Returns the exit
+/// code.
+fn graph(paths: &[String], svg: bool) -> i32 {
+    let export = |path: &Path| -> cherry_harvest::Result<String> {
+        let results: Vec<SearchResult> = compat::load(Artifact::Results, path)?;
+        let graph = CherryGraph::new(&results);
+        let dot_file = path.with_extension("dot");
+        graph.write_dot(fs::File::create(&dot_file)?)?;
+        if svg {
+            graph.write_svg(path.with_extension("svg"))?;
+        }
+        Ok(format!(
+            "{} commits and {} picks in {}",
+            graph.nodes().count(),
+            graph.cherry_edges().count(),
+            dot_file.display()
+        ))
+    };
+    let mut code = 0;
+    for path in paths {
+        match export(Path::new(path)) {
+            Ok(outcome) => println!("{path}: {outcome}"),
+            Err(error) => {
+                eprintln!("{path}: {error}");
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
 fn main() {
-    let runtime = tokio::runtime::Runtime::new().unwrap();
-    runtime.block_on(init());
+    let cli = Cli::parse();
+    // Downstream pipelines check artifacts with `cherry-harvest validate` instead of harvesting
+    match &cli.command {
+        Some(Command::Validate { artifact, paths }) => exit(validate(artifact, paths)),
+        Some(Command::Upgrade { artifact, paths }) => exit(upgrade(artifact, paths)),
+        Some(Command::Graph { paths, svg }) => exit(graph(paths, *svg)),
+        _ => {}
+    }
+
+    // libgit2's server timeout is a global option, which has to be set before any thread uses
+    // libgit2
+    let stall_timeout = match &cli.command {
+        Some(
+            Command::Harvest { search, .. }
+            | Command::Resume { search, .. }
+            | Command::AnalyzeRepo { search, .. },
+        ) => search.stall_timeout,
+        _ => None,
+    };
+    // SAFETY: No other threads have been started yet
+    let server_timeout = stall_timeout
+        .map(|minutes| unsafe { set_server_timeout(std::time::Duration::from_secs(minutes * 60)) });
+
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(init(&cli.token));
+    if let Some(Err(error)) = server_timeout {
+        warn!("was not able to configure the server timeout: {error}");
+    }
 
     info!("starting up");
+    // A fixed seed can be provided to reproduce the random choices of a previous run
+    let run = match std::env::var("CHERRY_HARVEST_SEED") {
+        Ok(seed) => match seed.trim().parse() {
+            Ok(seed) => HarvestRun::with_seed(seed),
+            Err(_) => Cli::command()
+                .error(
+                    clap::error::ErrorKind::ValueValidation,
+                    format!("CHERRY_HARVEST_SEED must be an unsigned integer, not '{seed}'"),
+                )
+                .exit(),
+        },
+        Err(_) => HarvestRun::new(),
+    };
+    info!("started {run}");
+    // Clones of earlier runs that crashed or could not be deleted occupy the disk until removed
+    let orphans = remove_orphaned_clones(std::env::temp_dir());
+    if !orphans.failed.is_empty() {
+        warn!("could not remove {} orphaned clones", orphans.failed.len());
+    }
+    fs::create_dir_all(&cli.output).unwrap();
+    let sample_file = cli.output.join("sample.yaml");
+
+    // Panics are tool errors as well, so that they are summarized like other errors
+    let outcome = catch_panic(AssertUnwindSafe(|| {
+        let forge = cli.forge.client()?;
+        match &cli.command {
+            None => {
+                let sample =
+                    load_or_sample(&runtime, &sample_file, &SampleArgs::default(), &forge, run)?;
+                harvest(
+                    &runtime,
+                    &cli.output,
+                    sample,
+                    &SearchArgs::default(),
+                    &NetworkArgs::default(),
+                    &forge,
+                    run,
+                )
+            }
+            Some(Command::Harvest {
+                sample,
+                search,
+                network,
+            }) => {
+                let sample = load_or_sample(&runtime, &sample_file, sample, &forge, run)?;
+                harvest(&runtime, &cli.output, sample, search, network, &forge, run)
+            }
+            Some(Command::Sample(sample)) => {
+                load_or_sample(&runtime, &sample_file, sample, &forge, run)?;
+                Ok(HarvestReport::new(run))
+            }
+            Some(Command::Resume { search, network }) => {
+                if !sample_file.exists() {
+                    return Err(Error::new(ErrorKind::Configuration(format!(
+                        "there is no sample to resume in {}",
+                        cli.output.display()
+                    ))));
+                }
+                let sample =
+                    load_or_sample(&runtime, &sample_file, &SampleArgs::default(), &forge, run)?;
+                harvest(&runtime, &cli.output, sample, search, network, &forge, run)
+            }
+            Some(Command::AnalyzeRepo { repo, search }) => {
+                analyze_repo(&runtime, &cli.output, repo, search, run)
+            }
+            Some(Command::Validate { .. } | Command::Upgrade { .. } | Command::Graph { .. }) => {
+                unreachable!()
+            }
+        }
+    }));
+
+    let policy = match &cli.command {
+        Some(Command::Sample(_)) => Policy::default(),
+        _ => cli.policy.policy(),
+    };
+    let summary = match outcome {
+        Ok(report) => RunSummary::new(&report, policy),
+        Err(error) => {
+            error!("run failed: {error}");
+            RunSummary::failed(run, policy, &error)
+        }
+    };
+    for violation in &summary.violations {
+        error!("policy violation: {violation}");
+    }
+    match cli.summary.as_deref() {
+        Some(path) if path == Path::new("-") => {
+            println!("{}", serde_json::to_string(&summary).unwrap())
+        }
+        Some(path) => {
+            if let Err(error) = summary.write(path) {
+                error!(
+                    "was not able to write the summary to {}: {error}",
+                    path.display()
+                );
+                exit(summary.exit_code.max(1));
+            }
+        }
+        None => {}
+    }
+    exit(summary.exit_code);
+}
+
+impl Default for SampleArgs {
+    fn default() -> Self {
+        Self {
+            languages: vec![],
+            sample_size: 250,
+        }
+    }
+}
+
+impl Default for SearchArgs {
+    fn default() -> Self {
+        Self {
+            methods: vec![],
+            methods_file: PathBuf::from("methods.yaml"),
+            noise_file: PathBuf::from("noise.yaml"),
+            max_history_depth: None,
+            max_concurrent_repos: None,
+            bare_clones: false,
+            clone_branches: vec![],
+            clone_depth: None,
+            cache_clones: false,
+            clone_token: None,
+            ssh_key: None,
+            stall_timeout: None,
+            disk_quota: DEFAULT_DISK_QUOTA_GIB,
+            max_clone_dirs: None,
+            clone_attempts: RetryPolicy::default().max_attempts,
+            no_diff_cache: false,
+            message_diffs: false,
+            annotate_conflicts: false,
+            detect_rebases: None,
+            store_diffs: false,
+            redact_diffs: None,
+            parameter_memory: false,
+            max_results: None,
+            overflow_sample_size: 1000,
+        }
+    }
+}
+
+impl SearchArgs {
+    /// The configurations of the selected search methods
+    ///
+    /// # Errors
+    /// Returns an error if the methods file cannot be read, or if a method is invalid.
+    fn method_configs(&self, registry: &MethodRegistry) -> Result<Vec<MethodConfig>, Error> {
+        let method_configs = if !self.methods.is_empty() {
+            self.methods.iter().map(|m| MethodConfig::new(m)).collect()
+        } else if self.methods_file.exists() {
+            load_method_configs(&self.methods_file)?
+        } else {
+            vec![MethodConfig::new("MessageScan")]
+        };
+        registry.create_all(&method_configs)?;
+        Ok(method_configs)
+    }
+
+    /// The given method configurations with a memory file for each TraditionalLSH configuration
+    /// that has none, if the parameter memory is enabled (see LshMemory)
+    fn with_parameter_memory(
+        &self,
+        mut method_configs: Vec<MethodConfig>,
+        output: &Path,
+        repo_id: &RepoId,
+    ) -> Vec<MethodConfig> {
+        if !self.parameter_memory {
+            return method_configs;
+        }
+        let repo_name: String = repo_id
+            .to_string()
+            .chars()
+            .map(
+                |c| match c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                    true => c,
+                    false => '_',
+                },
+            )
+            .collect();
+        for (index, config) in method_configs.iter_mut().enumerate() {
+            if config.name == "TraditionalLSH" && !config.params.contains_key("memory") {
+                let file = output.join(format!("cache/lsh/{repo_name}-{index}.yaml"));
+                config.params.insert(
+                    "memory".to_string(),
+                    serde_yaml::Value::from(file.display().to_string()),
+                );
+            }
+        }
+        method_configs
+    }
+
+    /// The store of the results in the output folder, with the configured cap of the results
+    ///
+    /// # Errors
+    /// Returns an error if the index of the store cannot be read.
+    fn result_store(&self, output: &Path) -> Result<ResultStore, Error> {
+        let store = ResultStore::open(output.join("results"))?;
+        Ok(match self.max_results {
+            Some(max_results) => {
+                store.with_cap(ResultCap::new(max_results, self.overflow_sample_size))
+            }
+            None => store,
+        })
+    }
+
+    /// The configuration with which the commits of the repositories are collected
+    ///
+    /// # Errors
+    /// Returns a configuration error if the token file or the salt file cannot be read, and an
+    /// error if the noise file cannot be read.
+    fn collection_config(&self, output: &Path) -> Result<CollectionConfig, Error> {
+        let mut collection_config = CollectionConfig {
+            max_history_depth: self.max_history_depth,
+            diff_cache: (!self.no_diff_cache).then(|| DiffCache::new(output.join("cache/diffs"))),
+            annotate_conflicts: self.annotate_conflicts,
+            rebase_min_run: self.detect_rebases,
+            ..CollectionConfig::default()
+        };
+        collection_config.clone.limits = ResourceLimits {
+            max_clone_dirs: self.max_clone_dirs,
+            disk_quota: (self.disk_quota > 0).then(|| self.disk_quota * 1024 * 1024 * 1024),
+        };
+        collection_config.clone.max_concurrent_repos = self.max_concurrent_repos;
+        collection_config.clone.bare = self.bare_clones;
+        collection_config.clone.branches = self.clone_branches.clone();
+        collection_config.clone.depth = self.clone_depth;
+        collection_config.clone.cache_directory =
+            self.cache_clones.then(|| output.join("cache/clones"));
+        if let Some(token_file) = &self.clone_token {
+            let token = read_secret(token_file, "clone token")?;
+            collection_config.clone.credentials.token = Some(token);
+        }
+        collection_config.clone.credentials.ssh_key = self.ssh_key.clone();
+        collection_config.clone.stall_timeout = self
+            .stall_timeout
+            .map(|minutes| std::time::Duration::from_secs(minutes * 60));
+        collection_config.clone.retry.max_attempts = self.clone_attempts.max(1);
+        if let Some(salt_file) = &self.redact_diffs {
+            let salt = read_secret(salt_file, "salt")?;
+            collection_config.result_diffs = DiffExport::Redacted { salt };
+        } else if self.store_diffs {
+            collection_config.result_diffs = DiffExport::Plain;
+        }
+        if self.noise_file.exists() {
+            collection_config.noise = NoiseList::read(&self.noise_file)?;
+            info!(
+                "ignoring {} known-noise commits and {} patch-ids",
+                collection_config.noise.commits.len(),
+                collection_config.noise.patch_ids.len()
+            );
+        }
+        Ok(collection_config)
+    }
+}
+
+/// Load the sample in the given file, or sample repositories from GitHub and store the sample in
+/// the file, if there is none yet
+///
+/// # Errors
+/// Returns an error if the sample cannot be read, sampled, pinned, or stored.
+fn load_or_sample(
+    runtime: &Runtime,
+    sample_file: &Path,
+    args: &SampleArgs,
+    forge: &Arc<dyn ForgeClient>,
+    run: HarvestRun,
+) -> Result<Sample, Error> {
     //    let range = SampleRange::new(
     //        NaiveDate::from_ymd_opt(2010, 1, 1).unwrap(),
     //        NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
     //    );
 
-    // Top languages 2024/1 by stars
-    let languages = vec![
-        "Python".to_string(),
-        "JavaScript".to_string(),
-        "Go".to_string(),
-        "C++".to_string(),
-        "Java".to_string(),
-        "TypeScript".to_string(),
-        "C".to_string(),
-        "C#".to_string(),
-        "PHP".to_string(),
-        "Rust".to_string(),
-    ]
-    .into_iter()
-    .map(ProgrammingLanguage::new)
-    .collect();
-
-    let mut sampler = MostStarsSampler::new(languages);
-    // Number of repos per language
-    let sample_size = 250;
-    let max_forks = 0;
-
     info!("Starting repo sampling");
-    fs::create_dir_all("output").unwrap();
-    let sample_file = Path::new("output/sample.yaml");
-    let sample = if Path::exists(sample_file) {
-        let sample = load_repo_sample(sample_file).unwrap();
+    let mut sample = if Path::exists(sample_file) {
+        let sample = load_repo_sample(sample_file)?;
         info!("Loaded sample with {} repositories", sample.len());
         sample
     } else {
-        let sample = sampler.sample(sample_size).unwrap();
-        info!("Sampled {} repositories", sample.len());
-        save_repo_sample(sample_file, &sample).unwrap();
+        // Top languages 2024/1 by stars
+        let languages = match args.languages.is_empty() {
+            true => vec![
+                "Python",
+                "JavaScript",
+                "Go",
+                "C++",
+                "Java",
+                "TypeScript",
+                "C",
+                "C#",
+                "PHP",
+                "Rust",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            false => args.languages.clone(),
+        }
+        .into_iter()
+        .map(ProgrammingLanguage::new)
+        .collect();
+        let mut sampler = MostStarsSampler::new_with_seed(languages, run.component_seed("sampler"))
+            .with_filter(RepoFilter::excluding_inactive())
+            .with_forge(forge.clone());
+        let mut sample = runtime.block_on(sampler.sample(args.sample_size))?;
+        sample.set_run(run);
+        info!(
+            "Sampled {} repositories and skipped {}",
+            sample.len(),
+            sample.skipped().len()
+        );
+        save_repo_sample(sample_file, &sample)?;
         sample
     };
+    // Pin the repositories to their current branch heads, so that repeated harvests of the sample
+    // analyze the same history. Samples from older versions are pinned when they are first loaded.
+    // Only repositories on GitHub can be pinned
+    let pinnable = sample.repos().iter().filter(|r| r.owner.is_some()).count();
+    if sample.pins().len() < pinnable {
+        runtime.block_on(sample.pin())?;
+        save_repo_sample(sample_file, &sample)?;
+    }
+    Ok(sample)
+}
+
+/// The given method configurations, in which the randomized methods that have no seed of their
+/// own are seeded with the run's MinHash seed, so that a harvest with the same seed finds the same
+/// picks (see MethodConfig::with_default_seed)
+fn with_run_seed(method_configs: Vec<MethodConfig>, run: &HarvestRun) -> Vec<MethodConfig> {
+    let seed = run.component_seed("minhash");
+    method_configs
+        .into_iter()
+        .map(|config| config.with_default_seed(seed))
+        .collect()
+}
 
-    let harvested_file = Path::new("output/harvested.yaml");
-    let failure_file = Path::new("output/failed.yaml");
-    let harvest_tracker = Arc::new(Mutex::new(
-        HarvestTracker::load_harvest_tracker(harvested_file, failure_file).unwrap(),
-    ));
+/// Harvest the fork networks of all repositories of the given sample that have not been
+/// harvested yet. Returns the report of the harvest, in which failed repositories are recorded.
+fn harvest(
+    runtime: &Runtime,
+    output: &Path,
+    sample: Sample,
+    args: &SearchArgs,
+    network_args: &NetworkArgs,
+    forge: &Arc<dyn ForgeClient>,
+    run: HarvestRun,
+) -> Result<HarvestReport, Error> {
+    let pins = sample.pins().clone();
+    let overrides = sample.overrides().clone();
+    let collection_config = args.collection_config(output)?;
 
-    let results_folder = Path::new("output/results/");
-    fs::create_dir_all(results_folder).unwrap();
-    let total_number_of_cherries: Arc<Mutex<HashMap<String, usize>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    let tracker_file = output.join("harvest.jsonl");
+    let new_tracker = !Path::exists(&tracker_file);
+    let mut harvest_tracker = HarvestTracker::open(&tracker_file)?;
+    // The tracking files of older versions are imported into a new journal
+    if new_tracker {
+        for (harvested_file, failure_file) in [
+            (output.join("harvested.yaml"), output.join("failed.yaml")),
+            (output.join("harvested.jsonl"), output.join("failed.jsonl")),
+        ] {
+            harvest_tracker.import_legacy(&harvested_file, &failure_file)?;
+        }
+    }
+    harvest_tracker.start_run(&run)?;
+    let harvest_tracker = Arc::new(Mutex::new(harvest_tracker));
+
+    // The search methods are configured in an optional configuration file. Downstream crates can
+    // register their own methods with the registry.
+    let registry = MethodRegistry::default();
+    let method_configs = args.method_configs(&registry)?;
+
+    // Fork networks are reused across harvests until they expire, unless a refresh is requested
+    let ttl = Duration::try_days(network_args.network_cache_ttl_days.into()).unwrap();
+    let network_cache = NetworkCache::new(output.join("networks"), ttl)
+        .refresh(network_args.refresh_networks)
+        .with_forge(forge.clone());
+    let network_folder = output.join("network_results");
+    fs::create_dir_all(&network_folder)?;
+
+    let result_store = Arc::new(Mutex::new(args.result_store(output)?));
     let total_commits = Arc::new(Mutex::new(0));
+    let report = Arc::new(Mutex::new(HarvestReport::new(run)));
+    let timeline = Arc::new(Mutex::new(Timeline::new(Period::Month)));
+    let hotspots = Arc::new(Mutex::new(Hotspots::new()));
+    info!(
+        "{} of {} repositories in the sample are pending",
+        lock(&harvest_tracker).pending(&sample).len(),
+        sample.len()
+    );
     sample.into_repos().into_par_iter().for_each(|repo| {
-        if harvest_tracker.lock().unwrap().contains(&repo.name) {
+        let repo_id = RepoId::from(&repo);
+        if lock(&harvest_tracker).contains(&repo_id) {
             // Only process repos that have not been harvested yet
-            info!("already harvested {}. [skip]", repo.name);
+            info!("already harvested {repo_id}. [skip]");
             return;
         }
-        info!("harvesting {}", repo.name);
-        let message_based = Box::<MessageScan>::default() as Box<dyn SearchMethod>;
-        let methods = vec![message_based];
+        info!("harvesting {repo_id}");
 
-        let repo_language = repo.language.clone();
-        let repo_name = repo.name.clone();
-        let repo_full_name = repo.full_name.clone();
+        // A panic (e.g., in a search method) only fails the current repository
+        let outcome = catch_panic(AssertUnwindSafe(|| {
+            let source_id = repo.id;
+            // Repositories may override the configuration of the harvest in the sample
+            let (repo_method_configs, collection_config) = match overrides.get(&source_id) {
+                Some(overrides) => {
+                    info!("applying the overrides of the sample to {repo_id}");
+                    (
+                        overrides.method_configs(&method_configs),
+                        overrides.collection_config(&collection_config),
+                    )
+                }
+                None => (method_configs.clone(), collection_config.clone()),
+            };
+            let methods = registry.create_all(&args.with_parameter_memory(
+                with_run_seed(repo_method_configs, &run),
+                output,
+                &repo_id,
+            ))?;
 
-        let network = if max_forks == 0 {
-            ForkNetwork::single(repo)
-        } else {
-            runtime.block_on(ForkNetwork::build_from(repo, Some(max_forks)))
-        };
+            let repo_language = repo.language.clone();
 
-        info!(
-            "{} repositories in network of {}",
-            network.len(),
-            repo_full_name.as_ref().unwrap_or(&repo_name)
-        );
+            let mut network = if network_args.max_forks == 0 {
+                ForkNetwork::single(repo)
+            } else {
+                runtime.block_on(network_cache.get_or_build(repo, Some(network_args.max_forks)))
+            };
+            // Forks are discovered after sampling and are thus not pinned
+            network.pin(source_id, pins.get(&source_id).cloned());
+
+            info!("{} repositories in network of {repo_id}", network.len());
 
-        let (total_commits_count, results) = match runtime.block_on(
-            cherry_harvest::search_with_multiple(&network.repositories(), &methods),
-        ) {
-            Ok(r) => r,
-            Err(_) => {
-                harvest_tracker
-                    .lock()
-                    .unwrap()
-                    .add_error(repo_name)
-                    .unwrap();
-                return;
+            let (commit_counts, mut results) =
+                runtime.block_on(cherry_harvest::search_with_commit_counts(
+                    &network.repositories(),
+                    &methods,
+                    &collection_config,
+                ))?;
+            let total_commits_count = commit_counts.values().sum();
+
+            *lock(&total_commits) += total_commits_count;
+            network.annotate_results(&mut results);
+            if args.message_diffs {
+                annotate_message_diffs(&mut results);
             }
-        };
 
-        *total_commits.lock().unwrap() += total_commits_count;
+            let entry = RepoEntry::new(
+                run,
+                repo_language
+                    .as_ref()
+                    .and_then(|lang| lang.as_str())
+                    .map(String::from),
+                network.source().license.clone(),
+                total_commits_count,
+            );
+            let method_names = methods.iter().map(|m| m.name()).collect::<Vec<&str>>();
+            if let Err(error) = lock(&result_store).save(&repo_id, entry, &method_names, &results) {
+                error!("was not able to store the results of {repo_id}: {error}");
+            }
 
-        // TODO: improve results storage
-        if !results.is_empty() {
-            let mut result_map = HashMap::new();
-            result_map.insert("repo_name", repo_full_name.unwrap());
-            match repo_language {
-                Some(lang) => {
-                    result_map.insert("language", lang.to_string());
+            lock(&timeline).add_all(&results);
+            lock(&hotspots).add_all(&repo_id, &results);
+            lock(&report).add_results(&results);
+
+            // The picks between the repositories of a network are combined with its topology
+            if network.len() > 1 {
+                let network_file = network_folder.join(format!("{}.yaml", network.source().id));
+                let network_harvest = NetworkHarvest::new(&network, &commit_counts, results);
+                if let Err(error) = network_harvest.write(network_file) {
+                    error!("was not able to store the network results of {repo_id}: {error}");
                 }
-                None => {
-                    result_map.insert("language", "None".to_string());
+                // The paths along which the picks propagated through the network
+                let propagation = network_harvest.propagation();
+                let propagation_file =
+                    network_folder.join(format!("{}.propagation.yaml", network.source().id));
+                let graph_file =
+                    network_folder.join(format!("{}.propagation.dot", network.source().id));
+                if let Err(error) = propagation.write(propagation_file).and_then(|_| {
+                    fs::write(graph_file, propagation.to_dot(&network_harvest))?;
+                    Ok(())
+                }) {
+                    error!("was not able to store the propagation of {repo_id}: {error}");
                 }
             }
-            result_map.insert("total_number_of_results", results.len().to_string());
-            result_map.insert("total_number_of_commits", total_commits_count.to_string());
-            let results = serde_yaml::to_string(&(&result_map, &results)).unwrap();
-            let results_file =
-                results_folder.join(Path::new(&format!("{}.yaml", &network.source().name)));
-            fs::write(results_file, results).unwrap();
-        }
-
-        for result in results {
-            let name = result.search_method().to_string();
-            // Increment the number of results for this search method
-            *total_number_of_cherries
-                .lock()
-                .unwrap()
-                .entry(name)
-                .or_default() += 1;
-        }
-
-        harvest_tracker
-            .lock()
-            .unwrap()
-            .add_success(repo_name)
-            .unwrap();
+            Ok(total_commits_count)
+        }));
+
+        let mut harvest_tracker = lock(&harvest_tracker);
+        // A repository whose outcome cannot be tracked is harvested again when the harvest resumes
+        let tracked = match outcome {
+            Ok(commits) => {
+                lock(&report).add_success();
+                harvest_tracker.add_success(repo_id.clone(), commits)
+            }
+            Err(Error(ErrorKind::EmptyRepository(emptiness))) => {
+                // There is nothing to harvest in empty repositories, so they are not retried
+                info!("skipping {repo_id}: {emptiness}");
+                lock(&report).add_empty();
+                harvest_tracker.add_skipped(repo_id.clone(), &emptiness.to_string())
+            }
+            Err(error) => {
+                lock(&report).add_failure(&repo_id, &error);
+                harvest_tracker.add_error(repo_id.clone(), &error)
+            }
+        };
+        if let Err(error) = tracked {
+            error!("was not able to track the harvest of {repo_id}: {error}");
+        }
     });
 
-    let total_commits = total_commits.lock().unwrap();
-    for (name, count) in total_number_of_cherries.lock().unwrap().iter() {
-        info!("found a total of {count} cherry picks using {name}");
+    let timeline_file = fs::File::create(output.join("timeline_monthly.csv"))?;
+    lock(&timeline).write_csv(timeline_file)?;
+    // The 20 directories of each repository into which changes are picked most often
+    let hotspot_file = fs::File::create(output.join("hotspots.csv"))?;
+    lock(&hotspots).write_csv(hotspot_file, Some(20))?;
+    // The results of all harvests are exported for analyses with, e.g., pandas or R
+    export_result_store(&lock(&result_store), output)?;
+
+    let mut report = lock(&report);
+    report.record_peak_memory();
+    report.write(output.join("report.yaml"))?;
+    if let Some(peak_memory) = report.peak_memory {
+        info!("peak memory usage: {} MiB", peak_memory / (1024 * 1024));
+    }
+    info!(
+        "harvested {} repositories, skipped {} empty repositories, and failed on {} repositories \
+         ({} panics)",
+        report.harvested,
+        report.empty,
+        report.failures.len(),
+        report.failures_of(FailureKind::Panic).count()
+    );
+    let total_commits = lock(&total_commits);
+    for (name, counts) in &report.picks {
+        info!(
+            "found a total of {} cherry picks using {name} ({} preserve the author, {} rewrite it)",
+            counts.total(),
+            counts.author_preserving,
+            counts.author_rewriting
+        );
         info!("harvested from a total of {total_commits}");
     }
+    Ok(report.clone())
+}
+
+/// Harvest the repository with the given URL or local path and store its results with the
+/// results of other harvests
+fn analyze_repo(
+    runtime: &Runtime,
+    output: &Path,
+    repo: &str,
+    args: &SearchArgs,
+    run: HarvestRun,
+) -> Result<HarvestReport, Error> {
+    let registry = MethodRegistry::default();
+    let location = match Path::new(repo).exists() {
+        true => RepoLocation::Filesystem(PathBuf::from(repo)),
+        false => RepoLocation::Server(repo.to_string()),
+    };
+    let repo_id = RepoId::from(&location);
+    let method_configs = args.with_parameter_memory(
+        with_run_seed(args.method_configs(&registry)?, &run),
+        output,
+        &repo_id,
+    );
+    let methods = registry.create_all(&method_configs)?;
+    info!("harvesting {repo_id}");
+    let (total_commits, mut results) = runtime.block_on(cherry_harvest::search_with_config(
+        &[&GitRepository::from(location)],
+        &methods,
+        &args.collection_config(output)?,
+    ))?;
+    if args.message_diffs {
+        annotate_message_diffs(&mut results);
+    }
+
+    let entry = RepoEntry::new(run, None, None, total_commits);
+    let method_names = methods.iter().map(|m| m.name()).collect::<Vec<&str>>();
+    let mut result_store = args.result_store(output)?;
+    result_store.save(&repo_id, entry, &method_names, &results)?;
+    export_result_store(&result_store, output)?;
+    info!(
+        "found {} cherry picks among {total_commits} commits of {repo_id}",
+        results.len()
+    );
+    let mut report = HarvestReport::new(run);
+    report.add_success();
+    report.add_results(&results);
+    Ok(report)
 }
@@ -0,0 +1,299 @@
+use crate::fingerprint::{minhash_signature, signature_similarity, FingerprintConfig};
+use crate::git::Commit;
+use crate::journal::{serialize_atomic, Format};
+use crate::{Result, SearchResult};
+use firestorm::profile_fn;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// The shortest run of hex digits that is treated as a commit hash during normalization
+const MIN_HASH_LENGTH: usize = 7;
+
+/// The parameters of the message clustering
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MessageClusterConfig {
+    /// The number of characters per shingle
+    pub arity: usize,
+    /// The number of hash values per MinHash signature
+    pub signature_size: usize,
+    /// The number of signature values per band. Messages become candidates if they agree on all
+    /// values of at least one band. The signature size must be divisible by the band size.
+    pub band_size: usize,
+    /// The lowest estimated similarity at which two messages are considered near-duplicates
+    pub threshold: f64,
+    /// Clusters with fewer commits are not reported
+    pub min_cluster_size: usize,
+}
+
+impl Default for MessageClusterConfig {
+    fn default() -> Self {
+        Self {
+            arity: 5,
+            signature_size: 60,
+            band_size: 3,
+            threshold: 0.7,
+            min_cluster_size: 2,
+        }
+    }
+}
+
+/// A cluster of commits with near-duplicate messages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageCluster {
+    /// The normalized message of the cluster's first commit
+    pub representative: String,
+    /// The ids of the commits in the cluster, ordered ascending
+    pub commits: Vec<String>,
+}
+
+/// Clusters of commits whose messages are near-duplicates, independent of the commits' diffs.
+/// Templated messages, e.g., of backports (`Backport #123 to 1.2`) or of cherry picks with `-x`,
+/// form clusters across the entire corpus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageClusters {
+    pub config: MessageClusterConfig,
+    /// The clusters, ordered by size (largest first)
+    pub clusters: Vec<MessageCluster>,
+}
+
+impl MessageClusters {
+    /// The index of the cluster that contains the given commit, if any
+    pub fn cluster_of(&self, commit_id: &str) -> Option<usize> {
+        self.clusters.iter().position(|c| {
+            c.commits
+                .binary_search_by(|id| id.as_str().cmp(commit_id))
+                .is_ok()
+        })
+    }
+
+    /// Whether the messages of the cherry and target of the given result are in the same cluster
+    pub fn shares_cluster(&self, result: &SearchResult) -> bool {
+        let pair = result.commit_pair();
+        self.cluster_of(pair.cherry().id())
+            .is_some_and(|cluster| self.cluster_of(pair.target().id()) == Some(cluster))
+    }
+
+    /// Write the clusters as YAML, or as JSON if the path has the extension `.json`
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Normalize a commit message for clustering: The message is lowercased, commit hashes and numbers
+/// (e.g., of issues or versions) are replaced by placeholders, and all whitespace is collapsed.
+/// Messages that only differ in the referenced commits, issues, or versions are thus equal.
+pub fn normalize_message(message: &str) -> String {
+    let mut normalized = String::with_capacity(message.len());
+    for word in message.split_whitespace() {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        let word = word.to_lowercase();
+        let mut chars = word.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !c.is_ascii_hexdigit() {
+                normalized.push(c);
+                continue;
+            }
+            let mut token = String::from(c);
+            while let Some(next) = chars.next_if(|c| c.is_ascii_hexdigit()) {
+                token.push(next);
+            }
+            if token.len() >= MIN_HASH_LENGTH && token.chars().any(|c| c.is_ascii_digit()) {
+                normalized.push_str("<hash>");
+            } else if token.chars().all(|c| c.is_ascii_digit()) {
+                normalized.push('#');
+            } else {
+                normalized.push_str(&token);
+            }
+        }
+    }
+    normalized
+}
+
+/// Cluster the messages of the given commits by their similarity. The messages are normalized
+/// (see [normalize_message]) and hashed to MinHash signatures, which are banded to find candidate
+/// pairs as in TraditionalLSH. Candidates whose signatures estimate a similarity of at least the
+/// threshold are linked, and each cluster is a connected component of linked messages.
+///
+/// Within a bucket, each message is only compared to the bucket's first message, so that huge
+/// buckets of templated messages do not require a quadratic number of comparisons.
+///
+/// # Panics
+/// This function panics if the signature size cannot be divided by the band size
+pub fn cluster_messages(commits: &[Commit], config: &MessageClusterConfig) -> MessageClusters {
+    profile_fn!(cluster_messages);
+    assert_eq!(
+        config.signature_size % config.band_size,
+        0,
+        "a signature of length {} cannot be divided into bands of length {}",
+        config.signature_size,
+        config.band_size
+    );
+    // Pseudo-commits of the same merge share an id and a message
+    let mut seen = HashSet::new();
+    let messages: Vec<(String, String)> = commits
+        .iter()
+        .filter(|c| seen.insert(c.id()))
+        .filter_map(|c| {
+            let message = normalize_message(c.message()?);
+            (!message.is_empty()).then(|| (c.id().to_string(), message))
+        })
+        .collect();
+    let fingerprint_config = FingerprintConfig {
+        arity: config.arity,
+        signature_size: config.signature_size,
+    };
+    let signatures: Vec<Vec<u64>> = messages
+        .iter()
+        .map(|(_, message)| minhash_signature(message, &fingerprint_config))
+        .collect();
+
+    let mut components = Components::new(messages.len());
+    let mut comparisons = 0;
+    for band in 0..config.signature_size / config.band_size {
+        let range = band * config.band_size..(band + 1) * config.band_size;
+        let mut buckets: HashMap<&[u64], usize> = HashMap::new();
+        for (index, signature) in signatures.iter().enumerate() {
+            let first = *buckets.entry(&signature[range.clone()]).or_insert(index);
+            if first == index || components.find(first) == components.find(index) {
+                continue;
+            }
+            comparisons += 1;
+            if signature_similarity(&signatures[first], signature) >= config.threshold {
+                components.union(first, index);
+            }
+        }
+    }
+
+    let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..messages.len() {
+        clusters
+            .entry(components.find(index))
+            .or_default()
+            .push(index);
+    }
+    let mut clusters: Vec<MessageCluster> = clusters
+        .into_values()
+        .filter(|members| members.len() >= config.min_cluster_size.max(1))
+        .map(|members| {
+            let mut commits: Vec<String> = members.iter().map(|i| messages[*i].0.clone()).collect();
+            commits.sort();
+            MessageCluster {
+                representative: messages[members[0]].1.clone(),
+                commits,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.commits
+            .len()
+            .cmp(&a.commits.len())
+            .then_with(|| a.commits.cmp(&b.commits))
+    });
+    debug!(
+        "clustered {} messages into {} clusters with {comparisons} comparisons",
+        messages.len(),
+        clusters.len()
+    );
+    MessageClusters {
+        config: *config,
+        clusters,
+    }
+}
+
+/// A union-find structure over the indices of the clustered messages
+struct Components {
+    parents: Vec<usize>,
+}
+
+impl Components {
+    fn new(size: usize) -> Self {
+        Self {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, index: usize) -> usize {
+        let mut root = index;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        // Compress the path, so that later lookups are fast
+        let mut current = index;
+        while self.parents[current] != root {
+            current = std::mem::replace(&mut self.parents[current], root);
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        self.parents[root_b.max(root_a)] = root_a.min(root_b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::message_clusters::{cluster_messages, normalize_message, MessageClusterConfig};
+    use crate::Commit;
+
+    #[test]
+    fn messages_are_normalized() {
+        assert_eq!(
+            normalize_message(
+                "Backport #1234 to 2.1\n\n(cherry picked from commit 4e39e242712568e6)"
+            ),
+            "backport ## to #.# (cherry picked from commit <hash>)"
+        );
+        // Words of hex letters are kept
+        assert_eq!(normalize_message("Add  cafe\tfeed"), "add cafe feed");
+    }
+
+    #[test]
+    fn templated_messages_are_clustered() {
+        let fixture = TestRepository::new();
+        let mut parents = vec![];
+        for message in [
+            "Backport #101 to release-1.2: fix crash in parser",
+            "Backport #245 to release-1.3: fix crash in parser",
+            "Backport #245 to release-1.4: fix crash in the parser",
+            "Add support for custom key bindings",
+            "Refactor the parser for better error messages",
+        ] {
+            parents = vec![fixture.commit("main", &[], &parents, message)];
+        }
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let id_of = |prefix: &str| {
+            commits
+                .iter()
+                .find(|c| c.message().unwrap().starts_with(prefix))
+                .unwrap()
+                .id()
+                .to_string()
+        };
+
+        let clusters = cluster_messages(&commits, &MessageClusterConfig::default());
+        assert_eq!(clusters.clusters.len(), 1);
+        assert_eq!(clusters.clusters[0].commits.len(), 3);
+        let backport = clusters.cluster_of(&id_of("Backport #101"));
+        assert_eq!(backport, Some(0));
+        assert_eq!(
+            clusters.cluster_of(&id_of("Backport #245 to release-1.4")),
+            backport
+        );
+        assert_eq!(clusters.cluster_of(&id_of("Add support")), None);
+        assert_eq!(clusters.cluster_of(&id_of("Refactor")), None);
+    }
+}
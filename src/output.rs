@@ -0,0 +1,269 @@
+use crate::git::RepoId;
+use crate::precision::csv_field;
+use crate::results::ResultStore;
+use crate::search::CommitMetadata;
+use crate::{Result, SearchResult};
+use firestorm::profile_fn;
+use serde::Serialize;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The columns of CSV exports, with one row per cherry/target pair
+pub const CSV_COLUMNS: &str = "repository,method,cherry_id,cherry_time,cherry_repository,\
+                               target_id,target_time,target_repository,author_preserved,\
+                               cross_repository,score";
+
+/// The columns of SZZ exports, with one row per cherry/target pair. The target of a pick is the
+/// fix that was propagated, and its cherry is the origin of the fix.
+pub const SZZ_COLUMNS: &str = "repo_name,fix_commit_hash,origin_commit_hash,fix_commit_date,\
+                               origin_commit_date,confidence,method";
+
+/// A result together with the id of the repository in which it was found
+#[derive(Serialize)]
+struct Record<'a> {
+    repository: &'a RepoId,
+    #[serde(flatten)]
+    result: &'a SearchResult,
+}
+
+/// Write the given results of the given repository as JSON Lines, i.e., one JSON object per line
+/// and result. Each object has the fields of the result (as in the YAML result files) and the
+/// id of the repository in its `repository` field (see [RepoId]).
+pub fn write_json_lines<W: Write>(
+    mut writer: W,
+    repo: &RepoId,
+    results: &[SearchResult],
+) -> Result<()> {
+    profile_fn!(write_json_lines);
+    for result in results {
+        serde_json::to_writer(
+            &mut writer,
+            &Record {
+                repository: repo,
+                result,
+            },
+        )?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Write the header of a CSV export (see [CSV_COLUMNS])
+pub fn write_csv_header<W: Write>(mut writer: W) -> Result<()> {
+    writeln!(writer, "{CSV_COLUMNS}")?;
+    Ok(())
+}
+
+/// Write the given results of the given repository as CSV rows without header (see
+/// [write_csv_header]), so that the results of several repositories can be written to the same
+/// file.
+///
+/// Commit times are written as RFC 3339 timestamps in UTC. Repositories of commits are written as
+/// their ids and are empty if unknown, as are the cross-repository flag and the score.
+pub fn write_csv_rows<W: Write>(
+    mut writer: W,
+    repo: &RepoId,
+    results: &[SearchResult],
+) -> Result<()> {
+    profile_fn!(write_csv_rows);
+    fn repository(commit: &CommitMetadata) -> String {
+        commit
+            .repository()
+            .map_or(String::new(), |id| id.to_string())
+    }
+
+    for result in results {
+        let pair = result.commit_pair();
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&repo.to_string()),
+            csv_field(result.search_method()),
+            pair.cherry().id(),
+            time(pair.cherry()),
+            repository(pair.cherry()),
+            pair.target().id(),
+            time(pair.target()),
+            repository(pair.target()),
+            result.author_preserved(),
+            result
+                .cross_repository()
+                .map_or(String::new(), |cross| cross.to_string()),
+            result
+                .score()
+                .map_or(String::new(), |score| score.to_string())
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the header of an SZZ export (see [SZZ_COLUMNS])
+pub fn write_szz_header<W: Write>(mut writer: W) -> Result<()> {
+    writeln!(writer, "{SZZ_COLUMNS}")?;
+    Ok(())
+}
+
+/// Write the given results of the given repository as CSV rows for SZZ and defect-propagation
+/// tooling, without header (see [write_szz_header]). Each row links a propagated fix (i.e., the
+/// target of a pick) to its origin (i.e., the cherry).
+///
+/// Repositories on known forges are named by their full names (e.g., `owner/repo`), as SZZ tools
+/// expect, and all other repositories by their ids. Commit dates are written as RFC 3339
+/// timestamps in UTC. The confidence is the share of agreeing methods for results of a
+/// CombinedSearch, the similarity score for other results that record one, and empty otherwise.
+pub fn write_szz_rows<W: Write>(
+    mut writer: W,
+    repo: &RepoId,
+    results: &[SearchResult],
+) -> Result<()> {
+    profile_fn!(write_szz_rows);
+    let repo_name = match repo {
+        RepoId::Forge { owner, name, .. } => format!("{owner}/{name}"),
+        _ => repo.to_string(),
+    };
+    for result in results {
+        let pair = result.commit_pair();
+        let confidence = result
+            .agreement()
+            .map(|agreement| agreement.confidence())
+            .or(result.score());
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            csv_field(&repo_name),
+            pair.target().id(),
+            pair.cherry().id(),
+            time(pair.target()),
+            time(pair.cherry()),
+            confidence.map_or(String::new(), |confidence| confidence.to_string()),
+            csv_field(result.search_method()),
+        )?;
+    }
+    Ok(())
+}
+
+/// The commit time of the given commit as RFC 3339 timestamp, or as recorded if it cannot be
+/// parsed
+fn time(commit: &CommitMetadata) -> String {
+    commit
+        .timestamp()
+        .map_or_else(|| csv_field(commit.time()), |time| time.to_rfc3339())
+}
+
+/// Export all results in the given store to `results.jsonl`, `results.csv`, and `results.szz.csv`
+/// in the given folder (see [write_json_lines], [write_csv_rows], and [write_szz_rows]).
+/// Existing exports are replaced.
+pub fn export_result_store<P: AsRef<Path>>(store: &ResultStore, folder: P) -> Result<()> {
+    profile_fn!(export_result_store);
+    let folder = folder.as_ref();
+    let mut json_lines = BufWriter::new(fs::File::create(folder.join("results.jsonl"))?);
+    let mut csv = BufWriter::new(fs::File::create(folder.join("results.csv"))?);
+    let mut szz = BufWriter::new(fs::File::create(folder.join("results.szz.csv"))?);
+    write_csv_header(&mut csv)?;
+    write_szz_header(&mut szz)?;
+    for (repo, entry) in &store.index().repositories {
+        for method in entry.methods.keys() {
+            let results = store.load(repo, method)?;
+            write_json_lines(&mut json_lines, repo, &results)?;
+            write_csv_rows(&mut csv, repo, &results)?;
+            write_szz_rows(&mut szz, repo, &results)?;
+        }
+    }
+    json_lines.flush()?;
+    csv.flush()?;
+    szz.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::ResultBuilder;
+    use crate::output::{
+        export_result_store, write_csv_header, write_csv_rows, write_szz_header, write_szz_rows,
+        CSV_COLUMNS, SZZ_COLUMNS,
+    };
+    use crate::results::{RepoEntry, ResultStore};
+    use crate::search::combined::Agreement;
+    use crate::{HarvestRun, RepoId, SearchResult};
+    use std::fs;
+
+    fn result() -> SearchResult {
+        ResultBuilder::new("MessageScan")
+            .cherry("author", "Jane <jane@example.com>")
+            // Results of earlier versions only record the time as text
+            .cherry("time", "Time { time: 1674832148, offset: 60, sign: '+' }")
+            .cherry("repository", 7)
+            .target("parent_ids", ["a"].as_slice())
+            .target("author", "Joe")
+            .target("time", "unknown")
+            .with("cross_repository", true)
+            .with("score", 1.0)
+            .build()
+    }
+
+    #[test]
+    fn results_are_exported() {
+        let repo = RepoId::github("owner", "repo");
+        let mut csv = vec![];
+        write_csv_header(&mut csv).unwrap();
+        write_csv_rows(&mut csv, &repo, &[result()]).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_COLUMNS);
+        assert_eq!(lines[0].split(',').count(), 11);
+        assert_eq!(
+            lines[1],
+            "github:owner/repo,MessageScan,a,2023-01-27T15:09:08+00:00,7,b,unknown,,false,true,1"
+        );
+
+        let directory = temp_dir::TempDir::new().unwrap();
+        let mut store = ResultStore::open(directory.path().join("results")).unwrap();
+        let entry = RepoEntry::new(HarvestRun::with_seed(42), None, None, 2);
+        store
+            .save(&repo, entry, &["MessageScan"], &[result()])
+            .unwrap();
+        export_result_store(&store, directory.path()).unwrap();
+        let json_lines = fs::read_to_string(directory.path().join("results.jsonl")).unwrap();
+        let record: serde_json::Value = serde_json::from_str(json_lines.trim()).unwrap();
+        assert_eq!(record["repository"], "github:owner/repo");
+        assert_eq!(record["cherry_and_target"]["target"]["id"], "b");
+        let read: SearchResult = serde_json::from_value(record).unwrap();
+        assert_eq!(read, result());
+        let exported = fs::read_to_string(directory.path().join("results.csv")).unwrap();
+        assert_eq!(exported, csv);
+        let exported = fs::read_to_string(directory.path().join("results.szz.csv")).unwrap();
+        assert_eq!(exported.lines().count(), 2);
+    }
+
+    #[test]
+    fn results_are_exported_for_szz() {
+        let mut combined = result();
+        combined.set_agreement(Some(Agreement {
+            methods: vec!["MessageScan".to_string()],
+            total: 2,
+        }));
+        let mut csv = vec![];
+        write_szz_header(&mut csv).unwrap();
+        write_szz_rows(
+            &mut csv,
+            &RepoId::github("Owner", "Repo"),
+            &[result(), combined],
+        )
+        .unwrap();
+        write_szz_rows(&mut csv, &RepoId::from_url("/tmp/repo"), &[result()]).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], SZZ_COLUMNS);
+        // The target is the propagated fix, and the cherry is its origin
+        assert_eq!(
+            lines[1],
+            "owner/repo,b,a,unknown,2023-01-27T15:09:08+00:00,1,MessageScan"
+        );
+        assert_eq!(
+            lines[2],
+            "owner/repo,b,a,unknown,2023-01-27T15:09:08+00:00,0.5,MessageScan"
+        );
+        assert!(lines[3].starts_with("url:"));
+    }
+}
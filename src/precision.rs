@@ -0,0 +1,533 @@
+use crate::error::ErrorKind;
+use crate::search::methods::lsh::Similarity;
+use crate::{Error, GitRepository, RepoLocation, Result, SearchResult};
+use octocrab::models::RepositoryId;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// The label of the bucket for results without a confidence score
+pub const UNSCORED: &str = "unscored";
+
+// The z-value of a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+const CSV_HEADER: &str =
+    "method,bucket,population,cherry,target,cherry_url,target_url,cherry_summary,target_summary,verdict";
+
+/// The confidence of a result, which is the mean similarity of its hunk matches. Returns None if
+/// no hunk matches have been computed for the result (see DiffSimilarity::hunk_matches).
+pub fn confidence(result: &SearchResult) -> Option<Similarity> {
+    let matches = result.hunk_matches()?;
+    if matches.is_empty() {
+        return None;
+    }
+    Some(matches.iter().map(|m| m.similarity).sum::<Similarity>() / matches.len() as Similarity)
+}
+
+/// A stratum groups all results of one search method whose confidence falls into the same bucket
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Stratum {
+    pub method: String,
+    pub bucket: String,
+}
+
+impl Display for Stratum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.method, self.bucket)
+    }
+}
+
+/// A reviewer's verdict on whether a sampled result is an actual cherry-pick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Correct,
+    Incorrect,
+    /// The reviewer could not decide; such items are not considered in the estimates
+    Unsure,
+}
+
+impl Verdict {
+    fn parse(value: &str) -> Result<Option<Self>> {
+        match value.trim().to_lowercase().as_str() {
+            "" => Ok(None),
+            "y" | "yes" | "true" | "correct" => Ok(Some(Verdict::Correct)),
+            "n" | "no" | "false" | "incorrect" => Ok(Some(Verdict::Incorrect)),
+            "?" | "unsure" => Ok(Some(Verdict::Unsure)),
+            other => Err(Error::new(ErrorKind::ReviewParse(format!(
+                "unknown verdict '{other}'"
+            )))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Correct => "yes",
+            Verdict::Incorrect => "no",
+            Verdict::Unsure => "?",
+        }
+    }
+}
+
+/// A sampled result that is to be reviewed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewItem {
+    pub stratum: Stratum,
+    pub cherry_id: String,
+    pub target_id: String,
+    /// Link to the cherry on GitHub, if the cherry's repository is known
+    pub cherry_url: Option<String>,
+    /// Link to the target on GitHub, if the target's repository is known
+    pub target_url: Option<String>,
+    pub cherry_summary: String,
+    pub target_summary: String,
+    pub verdict: Option<Verdict>,
+}
+
+/// Draws stratified random samples of search results for manual precision estimation. Results
+/// are stratified by search method and confidence bucket (see [confidence]), and up to
+/// `per_stratum` results are drawn from each stratum.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::precision::PrecisionSampler;
+/// use cherry_harvest::HarvestRun;
+///
+/// let run = HarvestRun::with_seed(42);
+/// let sampler = PrecisionSampler::new(50).with_buckets(vec![0.8, 0.9]);
+/// let sample = sampler.sample(&[], &[], &mut run.component_rng("precision"));
+/// assert!(sample.items().is_empty());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecisionSampler {
+    per_stratum: usize,
+    buckets: Vec<Similarity>,
+}
+
+impl PrecisionSampler {
+    /// Create a sampler that draws up to `per_stratum` results per stratum. By default, all
+    /// scored results fall into a single bucket.
+    pub fn new(per_stratum: usize) -> Self {
+        Self {
+            per_stratum,
+            buckets: vec![],
+        }
+    }
+
+    /// Split the confidence range at the given bounds. For example, the bounds `[0.8, 0.9]`
+    /// result in the buckets `0.00-0.80`, `0.80-0.90`, and `0.90-1.00`.
+    pub fn with_buckets(mut self, mut bounds: Vec<Similarity>) -> Self {
+        bounds.retain(|b| *b > 0.0 && *b < 1.0);
+        bounds.sort_by(|a, b| a.total_cmp(b));
+        bounds.dedup();
+        self.buckets = bounds;
+        self
+    }
+
+    /// The label of the confidence bucket into which the given result falls
+    pub fn bucket(&self, result: &SearchResult) -> String {
+        let Some(confidence) = confidence(result) else {
+            return UNSCORED.to_string();
+        };
+        let upper = self
+            .buckets
+            .iter()
+            .position(|bound| confidence < *bound)
+            .unwrap_or(self.buckets.len());
+        let lower_bound = if upper == 0 {
+            0.0
+        } else {
+            self.buckets[upper - 1]
+        };
+        let upper_bound = self.buckets.get(upper).copied().unwrap_or(1.0);
+        format!("{lower_bound:.2}-{upper_bound:.2}")
+    }
+
+    /// Draw the sample from the given results. The given repositories are used to link sampled
+    /// commits to GitHub.
+    pub fn sample<R: Rng>(
+        &self,
+        results: &[SearchResult],
+        repos: &[&GitRepository],
+        rng: &mut R,
+    ) -> ReviewSample {
+        let mut strata: BTreeMap<Stratum, Vec<&SearchResult>> = BTreeMap::new();
+        for result in results {
+            let stratum = Stratum {
+                method: result.search_method().to_string(),
+                bucket: self.bucket(result),
+            };
+            strata.entry(stratum).or_default().push(result);
+        }
+
+        let repo_urls: HashMap<RepositoryId, String> = repos
+            .iter()
+            .filter_map(|repo| repository_url(repo).map(|url| (repo.id, url)))
+            .collect();
+        let commit_url = |repository: Option<RepositoryId>, id: &str| {
+            repository
+                .and_then(|r| repo_urls.get(&r))
+                .map(|url| format!("{url}/commit/{id}"))
+        };
+
+        let mut population = BTreeMap::new();
+        let mut items = vec![];
+        for (stratum, results) in strata {
+            population.insert(stratum.clone(), results.len());
+            for result in results.choose_multiple(rng, self.per_stratum) {
+                let cherry = result.commit_pair().cherry();
+                let target = result.commit_pair().target();
+                items.push(ReviewItem {
+                    stratum: stratum.clone(),
+                    cherry_id: cherry.id().to_string(),
+                    target_id: target.id().to_string(),
+                    cherry_url: commit_url(cherry.repository(), cherry.id()),
+                    target_url: commit_url(target.repository(), target.id()),
+                    cherry_summary: summary(cherry.message()),
+                    target_summary: summary(target.message()),
+                    verdict: None,
+                });
+            }
+        }
+        ReviewSample { population, items }
+    }
+}
+
+/// The web URL of a repository on GitHub, if it can be determined
+fn repository_url(repo: &GitRepository) -> Option<String> {
+    if let Some(url) = repo.octorepo.as_ref().and_then(|r| r.html_url.as_ref()) {
+        return Some(url.to_string().trim_end_matches('/').to_string());
+    }
+    match &repo.location {
+        RepoLocation::Server(url) if url.starts_with("https://github.com/") => {
+            Some(url.trim_end_matches(".git").to_string())
+        }
+        _ => None,
+    }
+}
+
+fn summary(message: &str) -> String {
+    message.lines().next().unwrap_or_default().to_string()
+}
+
+/// A stratified sample of results together with the population size of each stratum.
+///
+/// The sample is exported as CSV, in which reviewers fill in the `verdict` column with `yes`,
+/// `no`, or `?`. The reviewed CSV is then read back in to estimate the precision of each method.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReviewSample {
+    population: BTreeMap<Stratum, usize>,
+    items: Vec<ReviewItem>,
+}
+
+impl ReviewSample {
+    pub fn items(&self) -> &[ReviewItem] {
+        &self.items
+    }
+
+    pub fn items_mut(&mut self) -> &mut [ReviewItem] {
+        &mut self.items
+    }
+
+    /// The number of results in each stratum from which the sample was drawn
+    pub fn population(&self) -> &BTreeMap<Stratum, usize> {
+        &self.population
+    }
+
+    /// Export the sample as CSV with one row per sampled result. The population size of each
+    /// stratum is repeated in every row, so that the file is self-contained.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{CSV_HEADER}")?;
+        for item in &self.items {
+            let fields = [
+                item.stratum.method.as_str(),
+                item.stratum.bucket.as_str(),
+                &self.population[&item.stratum].to_string(),
+                &item.cherry_id,
+                &item.target_id,
+                item.cherry_url.as_deref().unwrap_or_default(),
+                item.target_url.as_deref().unwrap_or_default(),
+                &item.cherry_summary,
+                &item.target_summary,
+                item.verdict.map_or("", |v| v.as_str()),
+            ];
+            let row = fields.map(csv_field).join(",");
+            writeln!(writer, "{row}")?;
+        }
+        Ok(())
+    }
+
+    /// Read a (reviewed) sample that was exported with [ReviewSample::write_csv].
+    pub fn read_csv<R: BufRead>(reader: R) -> Result<Self> {
+        let mut sample = ReviewSample::default();
+        for (number, line) in reader.lines().enumerate().skip(1) {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = parse_csv_line(&line);
+            if fields.len() != 10 {
+                return Err(Error::new(ErrorKind::ReviewParse(format!(
+                    "line {}: expected 10 fields but found {}",
+                    number + 1,
+                    fields.len()
+                ))));
+            }
+            let stratum = Stratum {
+                method: fields[0].clone(),
+                bucket: fields[1].clone(),
+            };
+            let population = fields[2].parse().map_err(|_| {
+                Error::new(ErrorKind::ReviewParse(format!(
+                    "line {}: invalid population '{}'",
+                    number + 1,
+                    fields[2]
+                )))
+            })?;
+            sample.population.insert(stratum.clone(), population);
+            let url = |field: &String| Some(field.clone()).filter(|f| !f.is_empty());
+            sample.items.push(ReviewItem {
+                stratum,
+                cherry_id: fields[3].clone(),
+                target_id: fields[4].clone(),
+                cherry_url: url(&fields[5]),
+                target_url: url(&fields[6]),
+                cherry_summary: fields[7].clone(),
+                target_summary: fields[8].clone(),
+                verdict: Verdict::parse(&fields[9])?,
+            });
+        }
+        Ok(sample)
+    }
+
+    /// Estimate the precision of each stratum and each search method from the reviewer verdicts.
+    /// Items without a verdict or with an unsure verdict are not considered.
+    ///
+    /// The precision of a stratum is estimated with a 95% Wilson score interval. The precision of
+    /// a method is the stratified estimate over its reviewed strata, in which each stratum is
+    /// weighted by its population size; its 95% interval is based on the normal approximation
+    /// with finite population correction.
+    pub fn estimate(&self) -> PrecisionReport {
+        let mut counts: BTreeMap<&Stratum, (usize, usize)> = BTreeMap::new();
+        for item in &self.items {
+            let (reviewed, correct) = counts.entry(&item.stratum).or_default();
+            match item.verdict {
+                Some(Verdict::Correct) => {
+                    *reviewed += 1;
+                    *correct += 1;
+                }
+                Some(Verdict::Incorrect) => *reviewed += 1,
+                Some(Verdict::Unsure) | None => {}
+            }
+        }
+        counts.retain(|_, (reviewed, _)| *reviewed > 0);
+
+        let strata = counts
+            .iter()
+            .map(|(stratum, (reviewed, correct))| {
+                ((*stratum).clone(), Estimate::wilson(*reviewed, *correct))
+            })
+            .collect::<BTreeMap<Stratum, Estimate>>();
+
+        let mut methods: BTreeMap<String, Vec<(&Stratum, &Estimate)>> = BTreeMap::new();
+        for (stratum, estimate) in &strata {
+            methods
+                .entry(stratum.method.clone())
+                .or_default()
+                .push((stratum, estimate));
+        }
+        let methods = methods
+            .into_iter()
+            .map(|(method, strata)| {
+                let estimate = Estimate::stratified(
+                    strata
+                        .into_iter()
+                        .map(|(stratum, estimate)| (self.population[stratum], estimate)),
+                );
+                (method, estimate)
+            })
+            .collect();
+
+        PrecisionReport { strata, methods }
+    }
+}
+
+/// An estimated precision with its 95% confidence interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// The number of reviewed results with a definite verdict
+    pub reviewed: usize,
+    /// The number of reviewed results that are correct
+    pub correct: usize,
+    pub precision: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Estimate {
+    fn wilson(reviewed: usize, correct: usize) -> Self {
+        let n = reviewed as f64;
+        let p = correct as f64 / n;
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+        Self {
+            reviewed,
+            correct,
+            precision: p,
+            lower: (center - margin).max(0.0),
+            upper: (center + margin).min(1.0),
+        }
+    }
+
+    fn stratified<'e, I: IntoIterator<Item = (usize, &'e Estimate)>>(strata: I) -> Self {
+        let strata = strata.into_iter().collect::<Vec<_>>();
+        let total = strata.iter().map(|(size, _)| *size).sum::<usize>() as f64;
+        let mut estimate = Self {
+            reviewed: 0,
+            correct: 0,
+            precision: 0.0,
+            lower: 0.0,
+            upper: 0.0,
+        };
+        let mut variance = 0.0;
+        for (size, stratum) in strata {
+            let weight = size as f64 / total;
+            let n = stratum.reviewed as f64;
+            let p = stratum.precision;
+            let correction = if size > 1 {
+                (size as f64 - n).max(0.0) / (size as f64 - 1.0)
+            } else {
+                0.0
+            };
+            estimate.reviewed += stratum.reviewed;
+            estimate.correct += stratum.correct;
+            estimate.precision += weight * p;
+            variance += weight * weight * correction * p * (1.0 - p) / n;
+        }
+        let margin = Z_95 * variance.sqrt();
+        estimate.lower = (estimate.precision - margin).max(0.0);
+        estimate.upper = (estimate.precision + margin).min(1.0);
+        estimate
+    }
+}
+
+/// The estimated precision per stratum and per search method
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrecisionReport {
+    pub strata: BTreeMap<Stratum, Estimate>,
+    pub methods: BTreeMap<String, Estimate>,
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::ResultBuilder;
+    use crate::precision::{PrecisionSampler, ReviewSample, Verdict, UNSCORED};
+    use crate::{GitRepository, RepoLocation, SearchResult};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::json;
+
+    fn result(method: &str, id: usize, similarity: Option<f64>) -> SearchResult {
+        let result = ResultBuilder::new(method)
+            .cherry("id", format!("c{id}"))
+            .cherry("message", "Fix parser, again\n\nDetails")
+            .cherry("repository", 7)
+            .target("id", format!("t{id}"))
+            .target("message", "Fix \"parser\"");
+        match similarity {
+            Some(similarity) => result.with(
+                "hunk_matches",
+                json!([{"cherry_hunk": 0, "target_hunk": 0, "similarity": similarity}]),
+            ),
+            None => result,
+        }
+        .build()
+    }
+
+    #[test]
+    fn samples_are_stratified_and_reviewed() {
+        let mut results = (0..10)
+            .map(|i| result("MessageScan", i, None))
+            .collect::<Vec<_>>();
+        results.extend((10..14).map(|i| result("TraditionalLSH", i, Some(0.95))));
+        results.push(result("TraditionalLSH", 14, Some(0.5)));
+        let repo = GitRepository::new_simple(
+            7,
+            "cherries-one".to_string(),
+            RepoLocation::Server(
+                "https://github.com/AlexanderSchultheiss/cherries-one.git".to_string(),
+            ),
+        );
+
+        let sampler = PrecisionSampler::new(3).with_buckets(vec![0.9]);
+        let mut sample = sampler.sample(&results, &[&repo], &mut StdRng::seed_from_u64(42));
+        assert_eq!(sample.items().len(), 7);
+        assert_eq!(sample.population().len(), 3);
+        let item = &sample.items()[0];
+        assert_eq!(item.stratum.bucket, UNSCORED);
+        assert_eq!(item.cherry_summary, "Fix parser, again");
+        assert_eq!(
+            item.cherry_url.as_deref(),
+            Some(
+                format!(
+                    "https://github.com/AlexanderSchultheiss/cherries-one/commit/{}",
+                    item.cherry_id
+                )
+                .as_str()
+            )
+        );
+        assert_eq!(item.target_url, None);
+
+        for (i, item) in sample.items_mut().iter_mut().enumerate() {
+            item.verdict = Some(if i == 0 {
+                Verdict::Incorrect
+            } else {
+                Verdict::Correct
+            });
+        }
+        let mut csv = vec![];
+        sample.write_csv(&mut csv).unwrap();
+        let reviewed = ReviewSample::read_csv(csv.as_slice()).unwrap();
+        assert_eq!(reviewed, sample);
+
+        let report = reviewed.estimate();
+        let message_scan = report.methods["MessageScan"];
+        assert_eq!((message_scan.reviewed, message_scan.correct), (3, 2));
+        assert!(message_scan.lower < message_scan.precision);
+        let lsh = report.methods["TraditionalLSH"];
+        assert_eq!(lsh.precision, 1.0);
+        // The single result in the lower bucket has been reviewed completely
+        assert_eq!(report.strata.len(), 3);
+    }
+}
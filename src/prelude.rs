@@ -0,0 +1,40 @@
+//! The prelude re-exports the core search API of cherry-harvest (repositories, commits and their
+//! diffs, the search methods, samplers, and results), so that it can be imported with a single
+//! `use cherry_harvest::prelude::*;`. Analyses of the results (e.g., evaluations or coverage) are
+//! imported from their modules. The crate's Result and Error are not re-exported, so that the
+//! glob import does not shadow std's Result.
+//!
+//! # Examples
+//! ```
+//! use cherry_harvest::prelude::*;
+//!
+//! let repo = GitRepository::from(RepoLocation::Server(
+//!     "https://github.com/AlexanderSchultheiss/cherries-one".to_string(),
+//! ));
+//! let methods: Vec<Box<dyn SearchMethod>> = vec![
+//!     Box::<MessageScan>::default(),
+//!     Box::<ExactDiffMatch>::default(),
+//!     Box::new(TraditionalLSH::new(8, 100, 5, 0.75)),
+//! ];
+//! assert_eq!(methods.len(), 3);
+//! ```
+
+pub use crate::git::{
+    clone_or_load, clone_or_load_with, collect_commits, collect_commits_with_config, CloneConfig,
+    CollectionConfig, Commit, CommitFilter, Diff, DiffLine, GitRepository, Hunk, LineType,
+    LoadedRepository, RepoId, RepoLocation,
+};
+pub use crate::sampling::fully_random::FullyRandomSampler;
+pub use crate::sampling::most_stars::{MostStarsSampler, ProgrammingLanguage};
+pub use crate::sampling::{GitHubSampler, Sample, SampleRange};
+pub use crate::search::combined::{CombinedSearch, VotingPolicy};
+pub use crate::search::{
+    ChangeIdMatch, CherryAndTarget, CommitMetadata, ExactChangeMatch, ExactDiffMatch, MessageScan,
+    MessageSimilarity, PartialDiffMatch, PatchIdMatch, SearchMethod, SearchPreset, SearchResult,
+    TraditionalLSH,
+};
+pub use crate::{
+    search_across, search_across_with_config, search_with, search_with_commit_counts,
+    search_with_config, search_with_metrics, search_with_multiple, search_with_streaming,
+    search_with_streaming_config,
+};
@@ -0,0 +1,443 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::RepoId;
+use crate::journal::{serialize_atomic, Format};
+use crate::run::HarvestRun;
+use crate::schema::SCHEMA_VERSION;
+use crate::{Result, SearchResult};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::panic::{self, UnwindSafe};
+use std::path::Path;
+
+/// Run the given processing of a single repository and convert a panic into an error of kind
+/// [ErrorKind::Panic], so that a bug in a search method (or in the processing of an unusual
+/// repository) only fails that repository instead of the entire harvest.
+///
+/// The panic is still reported by the panic hook, which prints the panic's location.
+pub fn catch_panic<T, F: FnOnce() -> Result<T> + UnwindSafe>(processing: F) -> Result<T> {
+    panic::catch_unwind(processing).unwrap_or_else(|payload| {
+        Err(Error::new(ErrorKind::Panic(panic_message(
+            payload.as_ref(),
+        ))))
+    })
+}
+
+/// The message of a panic, if the panic's payload is a string
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panic with a non-string payload".to_string()
+    }
+}
+
+/// Whether a repository failed with an error or with a panic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Error,
+    Panic,
+}
+
+/// A repository whose harvest failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoFailure {
+    pub repo: RepoId,
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+/// The number of picks of a search method that preserve or rewrite the author of their cherry (see
+/// [SearchResult::author_preserved]). Picks that are part of rebases are not counted as picks;
+/// instead, each rebase is counted once (see [SearchResult::rebase]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PickCounts {
+    pub author_preserving: usize,
+    pub author_rewriting: usize,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub rebases: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+impl PickCounts {
+    pub fn total(&self) -> usize {
+        self.author_preserving + self.author_rewriting
+    }
+}
+
+/// The summary of a harvest run: How many repositories were harvested, and which repositories
+/// failed for which reason. Unlike the [crate::HarvestTracker], which records the latest outcome
+/// of each repository across runs to continue interrupted harvests, the report summarizes a
+/// single run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HarvestReport {
+    /// The schema version with which the report was written (see [crate::schema])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub run: HarvestRun,
+    /// The number of successfully harvested repositories
+    pub harvested: usize,
+    /// The number of skipped repositories without commits
+    pub empty: usize,
+    pub failures: Vec<RepoFailure>,
+    /// The number of picks per search method
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub picks: BTreeMap<String, PickCounts>,
+    /// The peak resident memory of the harvest in bytes, if it was recorded (see
+    /// [HarvestReport::record_peak_memory])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory: Option<u64>,
+}
+
+impl HarvestReport {
+    pub fn new(run: HarvestRun) -> Self {
+        Self {
+            schema_version: Some(SCHEMA_VERSION),
+            run,
+            harvested: 0,
+            empty: 0,
+            failures: vec![],
+            picks: BTreeMap::new(),
+            peak_memory: None,
+        }
+    }
+
+    pub fn add_success(&mut self) {
+        self.harvested += 1;
+    }
+
+    /// Count the given picks per search method. The picks of a rebase are counted as one rebase.
+    pub fn add_results(&mut self, results: &[SearchResult]) {
+        for result in results {
+            let counts = self
+                .picks
+                .entry(result.search_method().to_string())
+                .or_default();
+            if let Some(rebase) = result.rebase() {
+                if rebase.position == 0 {
+                    counts.rebases += 1;
+                }
+                continue;
+            }
+            match result.author_preserved() {
+                true => counts.author_preserving += 1,
+                false => counts.author_rewriting += 1,
+            }
+        }
+    }
+
+    pub fn add_empty(&mut self) {
+        self.empty += 1;
+    }
+
+    /// Record the failure of the given repository. Errors of kind [ErrorKind::Panic] are recorded
+    /// as panics.
+    pub fn add_failure(&mut self, repo: &RepoId, failure: &Error) {
+        let kind = match failure.0 {
+            ErrorKind::Panic(_) => FailureKind::Panic,
+            _ => FailureKind::Error,
+        };
+        error!("harvest of {repo} failed: {failure}");
+        self.failures.push(RepoFailure {
+            repo: repo.clone(),
+            kind,
+            message: failure.to_string(),
+        });
+    }
+
+    /// The failures of the given kind
+    pub fn failures_of(&self, kind: FailureKind) -> impl Iterator<Item = &RepoFailure> {
+        self.failures.iter().filter(move |f| f.kind == kind)
+    }
+
+    /// Record the peak resident memory of the process so far. The peak is only known on Linux,
+    /// where it is read from `/proc/self/status`.
+    pub fn record_peak_memory(&mut self) {
+        self.peak_memory = peak_resident_memory();
+    }
+
+    /// Write the report as YAML (or JSON, for paths ending in `.json`)
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+}
+
+/// The exit code of runs that violate their [Policy]. Usage errors of the command line exit with
+/// `2`, so violations use the next code.
+pub const EXIT_VIOLATIONS: i32 = 3;
+/// The exit code of runs that failed with an error, e.g., an invalid configuration
+pub const EXIT_ERROR: i32 = 1;
+
+/// The rules of a [Policy]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    MaxPicks,
+    MinPicks,
+    MaxFailures,
+}
+
+/// Thresholds against which the outcome of a run is evaluated, so that the binary can gate CI
+/// pipelines (e.g., fail if a release branch did not receive the picks of its fixes). Rules that
+/// are None are not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Policy {
+    /// The maximum number of picks found by all search methods, e.g., `0` to forbid cherry picks
+    pub max_picks: Option<usize>,
+    /// The minimum number of picks found by all search methods
+    pub min_picks: Option<usize>,
+    /// The maximum number of repositories whose harvest failed
+    pub max_failures: Option<usize>,
+}
+
+impl Policy {
+    /// The violations of this policy by the run with the given report
+    pub fn evaluate(&self, report: &HarvestReport) -> Vec<PolicyViolation> {
+        let picks = report.picks.values().map(PickCounts::total).sum();
+        let failures = report.failures.len();
+        let checks = [
+            (PolicyRule::MaxPicks, self.max_picks, picks),
+            (PolicyRule::MinPicks, self.min_picks, picks),
+            (PolicyRule::MaxFailures, self.max_failures, failures),
+        ];
+        checks
+            .into_iter()
+            .filter_map(|(rule, limit, actual)| {
+                let limit = limit?;
+                let violated = match rule {
+                    PolicyRule::MinPicks => actual < limit,
+                    PolicyRule::MaxPicks | PolicyRule::MaxFailures => actual > limit,
+                };
+                violated.then_some(PolicyViolation {
+                    rule,
+                    limit,
+                    actual,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A rule of a [Policy] that a run violated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub rule: PolicyRule,
+    pub limit: usize,
+    pub actual: usize,
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (what, bound) = match self.rule {
+            PolicyRule::MaxPicks => ("picks", "at most"),
+            PolicyRule::MinPicks => ("picks", "at least"),
+            PolicyRule::MaxFailures => ("failed repositories", "at most"),
+        };
+        write!(
+            f,
+            "found {} {what}, but the policy allows {bound} {}",
+            self.actual, self.limit
+        )
+    }
+}
+
+/// Whether a run passed its policy, violated it, or failed with an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Passed,
+    Violations,
+    Error,
+}
+
+impl RunStatus {
+    /// The exit code of the binary for runs with this status. Violations are distinguished from
+    /// errors, so that scripts can tell failed gates from broken runs.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunStatus::Passed => 0,
+            RunStatus::Violations => EXIT_VIOLATIONS,
+            RunStatus::Error => EXIT_ERROR,
+        }
+    }
+}
+
+/// The machine-readable summary of a run of the binary, which is written when the run ends, so
+/// that scripts (e.g., CI gates) do not have to parse the log. Unlike the [HarvestReport], the
+/// summary only contains counts, the evaluation of the run's [Policy], and the run's error, if
+/// any.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// The schema version with which the summary was written (see [crate::schema])
+    pub schema_version: u32,
+    pub run: HarvestRun,
+    pub status: RunStatus,
+    /// The exit code of the binary (see [RunStatus::exit_code])
+    pub exit_code: i32,
+    pub harvested: usize,
+    pub empty: usize,
+    pub failed: usize,
+    /// The number of picks found by all search methods
+    pub picks: usize,
+    /// The number of picks per search method
+    pub picks_per_method: BTreeMap<String, usize>,
+    pub policy: Policy,
+    pub violations: Vec<PolicyViolation>,
+    /// The message of the error with which the run failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunSummary {
+    /// Summarize the run with the given report and evaluate it against the given policy
+    pub fn new(report: &HarvestReport, policy: Policy) -> Self {
+        let violations = policy.evaluate(report);
+        let status = match violations.is_empty() {
+            true => RunStatus::Passed,
+            false => RunStatus::Violations,
+        };
+        Self {
+            schema_version: SCHEMA_VERSION,
+            run: report.run,
+            status,
+            exit_code: status.exit_code(),
+            harvested: report.harvested,
+            empty: report.empty,
+            failed: report.failures.len(),
+            picks: report.picks.values().map(PickCounts::total).sum(),
+            picks_per_method: report
+                .picks
+                .iter()
+                .map(|(method, counts)| (method.clone(), counts.total()))
+                .collect(),
+            policy,
+            violations,
+            error: None,
+        }
+    }
+
+    /// Summarize a run that failed with the given error. The policy is not evaluated.
+    pub fn failed(run: HarvestRun, policy: Policy, error: &Error) -> Self {
+        Self {
+            status: RunStatus::Error,
+            exit_code: RunStatus::Error.exit_code(),
+            policy,
+            error: Some(error.to_string()),
+            ..Self::new(&HarvestReport::new(run), Policy::default())
+        }
+    }
+
+    /// Write the summary as JSON, or as YAML for paths ending in `.yaml` or `.yml`
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let format = match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        };
+        serialize_atomic(&path, self, format)
+    }
+}
+
+/// The peak resident set size of the process in bytes (`VmHWM`), if it is known
+fn peak_resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, ErrorKind};
+    use crate::report::{
+        catch_panic, FailureKind, HarvestReport, PickCounts, Policy, PolicyRule, RunStatus,
+        RunSummary, EXIT_ERROR, EXIT_VIOLATIONS,
+    };
+    use crate::{HarvestRun, RepoId};
+    use std::sync::{Mutex, PoisonError};
+
+    #[test]
+    fn panics_become_repo_failures() {
+        let counter = Mutex::new(0);
+        let mut report = HarvestReport::new(HarvestRun::with_seed(42));
+        for repo in ["ok", "panicking", "failing"] {
+            let outcome = catch_panic(|| {
+                // The panic poisons the lock, which is recovered for the following repositories
+                let mut count = counter.lock().unwrap_or_else(PoisonError::into_inner);
+                *count += 1;
+                match repo {
+                    "panicking" => panic!("invalid object id {}", "xyz"),
+                    "failing" => Err(Error::new(ErrorKind::Configuration("broken".to_string()))),
+                    _ => Ok(()),
+                }
+            });
+            match outcome {
+                Ok(()) => report.add_success(),
+                Err(error) => report.add_failure(&RepoId::github("owner", repo), &error),
+            }
+        }
+        assert_eq!(report.harvested, 1);
+        let panics: Vec<_> = report.failures_of(FailureKind::Panic).collect();
+        assert_eq!(panics.len(), 1);
+        assert_eq!(panics[0].repo.to_string(), "github:owner/panicking");
+        assert_eq!(panics[0].message, "invalid object id xyz");
+        assert_eq!(report.failures_of(FailureKind::Error).count(), 1);
+        assert!(counter.is_poisoned());
+        assert_eq!(*counter.lock().unwrap_or_else(PoisonError::into_inner), 3);
+    }
+
+    #[test]
+    fn runs_are_evaluated_against_their_policy() {
+        let run = HarvestRun::with_seed(42);
+        let mut report = HarvestReport::new(run);
+        report.add_success();
+        report.picks.insert(
+            "MessageScan".to_string(),
+            PickCounts {
+                author_preserving: 2,
+                author_rewriting: 1,
+                rebases: 0,
+            },
+        );
+        report.add_failure(
+            &RepoId::github("owner", "broken"),
+            &Error::new(ErrorKind::Configuration("broken".to_string())),
+        );
+
+        let summary = RunSummary::new(&report, Policy::default());
+        assert_eq!(summary.status, RunStatus::Passed);
+        assert_eq!(summary.exit_code, 0);
+        assert_eq!(summary.picks, 3);
+        assert_eq!(summary.picks_per_method["MessageScan"], 3);
+        assert_eq!(summary.failed, 1);
+
+        let policy = Policy {
+            max_picks: Some(3),
+            min_picks: Some(4),
+            max_failures: Some(0),
+        };
+        let summary = RunSummary::new(&report, policy);
+        assert_eq!(summary.status, RunStatus::Violations);
+        assert_eq!(summary.exit_code, EXIT_VIOLATIONS);
+        let rules: Vec<_> = summary.violations.iter().map(|v| v.rule).collect();
+        assert_eq!(rules, [PolicyRule::MinPicks, PolicyRule::MaxFailures]);
+        assert_eq!(
+            summary.violations[0].to_string(),
+            "found 3 picks, but the policy allows at least 4"
+        );
+
+        let error = Error::new(ErrorKind::Configuration("invalid method".to_string()));
+        let summary = RunSummary::failed(run, policy, &error);
+        assert_eq!(summary.exit_code, EXIT_ERROR);
+        assert_eq!(summary.error.as_deref(), Some("invalid method"));
+        assert!(summary.violations.is_empty());
+    }
+}
@@ -0,0 +1,519 @@
+use crate::calibration::Calibration;
+use crate::compat;
+use crate::git::RepoId;
+use crate::journal::{serialize_atomic, Format};
+use crate::run::HarvestRun;
+use crate::schema::{Artifact, SCHEMA_VERSION};
+use crate::{Result, SearchResult};
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub(crate) const INDEX_FILE: &str = "index.yaml";
+
+/// The number of results that a search method found in a repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MethodEntry {
+    pub results: usize,
+    /// The file with the method's results, relative to the results folder. Methods without
+    /// results have no file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// The summary of all results, if they exceeded the cap of the store and only a sample of
+    /// them is stored in the file (see [ResultCap])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overflow: Option<OverflowSummary>,
+}
+
+/// The maximum number of results that are stored per search method and repository. Pathological
+/// repositories (e.g., repositories of generated files) can yield millions of pairs; if a method
+/// finds more results than the cap allows, only a random sample of them is stored together with
+/// aggregate statistics of all results (see [OverflowSummary]), and the repository is flagged as
+/// overflowed in the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultCap {
+    max_results: usize,
+    sample_size: usize,
+}
+
+impl ResultCap {
+    /// A cap of `max_results` results, of which `sample_size` results are stored if the cap is
+    /// exceeded. The sample is never larger than the cap.
+    pub fn new(max_results: usize, sample_size: usize) -> Self {
+        Self {
+            max_results,
+            sample_size: sample_size.min(max_results),
+        }
+    }
+
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    /// The results that are stored of the given results, which are sampled with the given
+    /// generator if they exceed the cap. The order of the sampled results is kept.
+    fn apply<'r>(
+        &self,
+        results: Vec<&'r SearchResult>,
+        mut rng: StdRng,
+    ) -> (Vec<&'r SearchResult>, Option<OverflowSummary>) {
+        if results.len() <= self.max_results {
+            return (results, None);
+        }
+        let mut sampled =
+            rand::seq::index::sample(&mut rng, results.len(), self.sample_size).into_vec();
+        sampled.sort_unstable();
+        let summary = OverflowSummary::new(&results, sampled.len());
+        (
+            sampled.into_iter().map(|i| results[i]).collect(),
+            Some(summary),
+        )
+    }
+}
+
+/// Aggregate statistics of all results of a search method in a repository whose results exceeded
+/// the cap of the store (see [ResultCap])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverflowSummary {
+    /// The number of results that are stored in the results file, which are a random sample of
+    /// all results
+    pub stored: usize,
+    /// The number of distinct cherries of all results
+    pub cherries: usize,
+    /// The number of distinct targets of all results
+    pub targets: usize,
+    /// The number of results whose target preserves the author of the cherry
+    pub author_preserving: usize,
+    /// The number of results between different repositories of a fork network
+    pub cross_repository: usize,
+    /// The range and mean of the similarity scores of all results that record one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<ScoreSummary>,
+}
+
+impl OverflowSummary {
+    fn new(results: &[&SearchResult], stored: usize) -> Self {
+        let mut cherries = HashSet::new();
+        let mut targets = HashSet::new();
+        let mut author_preserving = 0;
+        let mut cross_repository = 0;
+        let mut scores: Vec<f64> = vec![];
+        for result in results {
+            let pair = result.commit_pair();
+            cherries.insert(pair.cherry().id());
+            targets.insert(pair.target().id());
+            author_preserving += usize::from(result.author_preserved());
+            cross_repository += usize::from(result.cross_repository() == Some(true));
+            scores.extend(result.score());
+        }
+        Self {
+            stored,
+            cherries: cherries.len(),
+            targets: targets.len(),
+            author_preserving,
+            cross_repository,
+            scores: ScoreSummary::of(&scores),
+        }
+    }
+}
+
+/// The range and mean of similarity scores
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScoreSummary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl ScoreSummary {
+    fn of(scores: &[f64]) -> Option<Self> {
+        if scores.is_empty() {
+            return None;
+        }
+        Some(Self {
+            min: scores.iter().copied().fold(f64::INFINITY, f64::min),
+            max: scores.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean: scores.iter().sum::<f64>() / scores.len() as f64,
+        })
+    }
+}
+
+/// Everything that is known about the harvest of a single repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepoEntry {
+    /// The run in which the repository was harvested
+    pub run: HarvestRun,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// The SPDX identifier of the repository's license, if it is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// The number of unique commits that were searched
+    pub total_commits: usize,
+    pub methods: BTreeMap<String, MethodEntry>,
+    /// Whether the results of any method exceeded the cap of the store, so that only a sample of
+    /// them is stored (see [ResultCap]). Analyses should exclude such repositories or treat them
+    /// specially.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub overflowed: bool,
+}
+
+impl RepoEntry {
+    pub fn new(
+        run: HarvestRun,
+        language: Option<String>,
+        license: Option<String>,
+        total_commits: usize,
+    ) -> Self {
+        Self {
+            run,
+            language,
+            license,
+            total_commits,
+            methods: BTreeMap::new(),
+            overflowed: false,
+        }
+    }
+
+    /// The number of results of all methods, including results that were not stored because
+    /// they exceeded the cap of the store
+    pub fn total_results(&self) -> usize {
+        self.methods.values().map(|m| m.results).sum()
+    }
+}
+
+/// The manifest of all harvested repositories, keyed by the repositories' ids. Indexes of older
+/// versions, which are keyed by full names, are parsed leniently (see [RepoId::parse_legacy]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResultIndex {
+    /// The schema version with which the index was written (see [crate::schema])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub repositories: BTreeMap<RepoId, RepoEntry>,
+    /// The calibration of the similarity threshold that was used for the harvest, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<Calibration>,
+}
+
+impl ResultIndex {
+    /// The repositories whose results exceeded the cap of the store (see [ResultCap])
+    pub fn overflowed(&self) -> impl Iterator<Item = &RepoId> {
+        self.repositories
+            .iter()
+            .filter(|(_, entry)| entry.overflowed)
+            .map(|(repo, _)| repo)
+    }
+}
+
+/// A ResultStore persists search results with one file per search method and repository, so that
+/// downstream analyses can load only the methods they care about. A combined index file lists
+/// all harvested repositories together with their metadata and the number of results per method.
+///
+/// The layout of the results folder is:
+/// ```text
+/// index.yaml
+/// <forge>_<owner>_<repo>/<method>.yaml
+/// ```
+///
+/// Results of repositories that are not on a known forge are stored in `url_<hash>/` (see
+/// [RepoId]). The index records the file of each method, so results that older versions stored
+/// in `<owner>_<repo>/` can still be loaded.
+#[derive(Debug, Clone)]
+pub struct ResultStore {
+    folder: PathBuf,
+    index: ResultIndex,
+    cap: Option<ResultCap>,
+}
+
+impl ResultStore {
+    /// Open the store in the given folder. The index of a previous harvest in the same folder is
+    /// loaded, so that interrupted harvests can be continued.
+    ///
+    /// Indexes of previous versions are upgraded when they are loaded (see [crate::compat]).
+    /// Results files of versions before the ResultStore, which are located directly in the folder,
+    /// are moved into the store, unless the store already contains results of their repositories.
+    pub fn open<P: AsRef<Path>>(folder: P) -> Result<Self> {
+        let folder = folder.as_ref().to_path_buf();
+        fs::create_dir_all(&folder)?;
+        let index_file = folder.join(INDEX_FILE);
+        let index = if index_file.exists() {
+            compat::load(Artifact::Manifest, index_file)?
+        } else {
+            ResultIndex::default()
+        };
+        let mut store = Self {
+            folder,
+            index,
+            cap: None,
+        };
+        store.migrate_legacy_results()?;
+        Ok(store)
+    }
+
+    /// Move the results files of versions before the ResultStore into the store (see
+    /// [compat::legacy_result_files])
+    fn migrate_legacy_results(&mut self) -> Result<()> {
+        for file in compat::legacy_result_files(&self.folder)? {
+            let (repo, entry, results) = match compat::read_legacy_results(&file) {
+                Ok(legacy) => legacy,
+                Err(error) => {
+                    warn!(
+                        "ignoring unreadable results file {}: {error}",
+                        file.display()
+                    );
+                    continue;
+                }
+            };
+            if !self.index.repositories.contains_key(&repo) {
+                let methods: BTreeSet<&str> = results.iter().map(|r| r.search_method()).collect();
+                let methods: Vec<&str> = methods.into_iter().collect();
+                self.save(&repo, entry, &methods, &results)?;
+            }
+            compat::retire_legacy_file(&file)?;
+            info!(
+                "moved the results of {repo} from {} into the store",
+                file.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Cap the results that are stored per method and repository. The results of earlier
+    /// harvests are not affected.
+    pub fn with_cap(mut self, cap: ResultCap) -> Self {
+        self.cap = Some(cap);
+        self
+    }
+
+    pub fn index(&self) -> &ResultIndex {
+        &self.index
+    }
+
+    /// Store the results of a repository and update the index. All given methods are listed in
+    /// the index, even if they found no results. If the results of a method exceed the cap of
+    /// the store, a sample of them is stored, which is drawn with a seed derived from the entry's
+    /// run.
+    pub fn save(
+        &mut self,
+        repo: &RepoId,
+        mut entry: RepoEntry,
+        methods: &[&str],
+        results: &[SearchResult],
+    ) -> Result<()> {
+        let mut by_method: BTreeMap<&str, Vec<&SearchResult>> =
+            methods.iter().map(|m| (*m, vec![])).collect();
+        for result in results {
+            by_method
+                .entry(result.search_method())
+                .or_default()
+                .push(result);
+        }
+
+        let repo_folder = PathBuf::from(file_name(&repo.to_string()));
+        for (method, results) in by_method {
+            let found = results.len();
+            let (results, overflow) = match self.cap {
+                Some(cap) => cap.apply(
+                    results,
+                    entry
+                        .run
+                        .component_rng(&format!("overflow/{repo}/{method}")),
+                ),
+                None => (results, None),
+            };
+            if overflow.is_some() {
+                warn!(
+                    "{method} found {found} results in {repo}, of which only {} are stored",
+                    results.len()
+                );
+                entry.overflowed = true;
+            }
+            let file = if results.is_empty() {
+                None
+            } else {
+                let file = repo_folder.join(format!("{}.yaml", file_name(method)));
+                fs::create_dir_all(self.folder.join(&repo_folder))?;
+                serialize_atomic(self.folder.join(&file), &results, Format::Yaml)?;
+                Some(file)
+            };
+            entry.methods.insert(
+                method.to_string(),
+                MethodEntry {
+                    results: found,
+                    file,
+                    overflow,
+                },
+            );
+        }
+        debug!("stored {} results of {repo}", entry.total_results());
+        self.index.repositories.insert(repo.clone(), entry);
+        self.write_index()
+    }
+
+    /// Store the calibration of the similarity threshold with the index, so that the operating
+    /// point of the harvest can be looked up together with its results
+    pub fn save_calibration(&mut self, calibration: Calibration) -> Result<()> {
+        self.index.calibration = Some(calibration);
+        self.write_index()
+    }
+
+    /// Load the results that the given method found in the given repository
+    pub fn load(&self, repo: &RepoId, method: &str) -> Result<Vec<SearchResult>> {
+        let file = self
+            .index
+            .repositories
+            .get(repo)
+            .and_then(|entry| entry.methods.get(method))
+            .and_then(|method| method.file.as_ref());
+        match file {
+            Some(file) => Ok(serde_yaml::from_str(&fs::read_to_string(
+                self.folder.join(file),
+            )?)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn write_index(&mut self) -> Result<()> {
+        // All entries are written in the current format, even if they were loaded from the index
+        // of an older version
+        self.index.schema_version = Some(SCHEMA_VERSION);
+        // An interrupted harvest never leaves a truncated index behind
+        serialize_atomic(self.folder.join(INDEX_FILE), &self.index, Format::Yaml)
+    }
+}
+
+/// Replace all characters that are not safe in file names
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::ResultBuilder;
+    use crate::results::{RepoEntry, ResultCap, ResultStore, INDEX_FILE};
+    use crate::schema::validate_result_store;
+    use crate::{HarvestRun, RepoId, SearchResult};
+    use std::fs;
+
+    fn result(method: &str) -> SearchResult {
+        pair(method, "a", "b")
+    }
+
+    fn pair(method: &str, cherry: &str, target: &str) -> SearchResult {
+        ResultBuilder::new(method)
+            .cherry("id", cherry)
+            .target("id", target)
+            .build()
+    }
+
+    #[test]
+    fn results_are_stored_per_method() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let mut store = ResultStore::open(directory.path()).unwrap();
+        let repo = RepoId::github("owner", "repo");
+        let entry = RepoEntry::new(
+            HarvestRun::with_seed(42),
+            Some("Rust".to_string()),
+            None,
+            10,
+        );
+        store
+            .save(
+                &repo,
+                entry,
+                &["MessageScan", "ExactDiffMatch"],
+                &[result("MessageScan")],
+            )
+            .unwrap();
+        assert!(directory
+            .path()
+            .join("github_owner_repo/MessageScan.yaml")
+            .exists());
+        assert!(!directory
+            .path()
+            .join("github_owner_repo/ExactDiffMatch.yaml")
+            .exists());
+
+        // The index is restored when the store is opened again
+        let store = ResultStore::open(directory.path()).unwrap();
+        let entry = &store.index().repositories[&repo];
+        assert_eq!(entry.total_commits, 10);
+        assert_eq!(entry.methods["MessageScan"].results, 1);
+        assert_eq!(entry.methods["ExactDiffMatch"].results, 0);
+        assert_eq!(
+            store.load(&repo, "MessageScan").unwrap(),
+            vec![result("MessageScan")]
+        );
+        assert!(store.load(&repo, "ExactDiffMatch").unwrap().is_empty());
+
+        // Indexes of older versions are keyed by full names
+        let index_file = directory.path().join(INDEX_FILE);
+        let index = fs::read_to_string(&index_file).unwrap();
+        fs::write(
+            &index_file,
+            index.replace("github:owner/repo", "Owner/Repo"),
+        )
+        .unwrap();
+        let store = ResultStore::open(directory.path()).unwrap();
+        assert_eq!(store.load(&repo, "MessageScan").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn overflowing_results_are_sampled_and_summarized() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let mut store = ResultStore::open(directory.path())
+            .unwrap()
+            .with_cap(ResultCap::new(10, 4));
+        let picks: Vec<SearchResult> = (0..25)
+            .map(|i| pair("ExactDiffMatch", "a", &format!("b{i}")))
+            .chain([pair("MessageScan", "c", "d")])
+            .collect();
+        let entry = RepoEntry::new(HarvestRun::with_seed(42), None, None, 30);
+        let repo = RepoId::github("owner", "generated");
+        store
+            .save(&repo, entry, &["ExactDiffMatch", "MessageScan"], &picks)
+            .unwrap();
+
+        let entry = &store.index().repositories[&repo];
+        assert!(entry.overflowed);
+        assert_eq!(store.index().overflowed().collect::<Vec<_>>(), vec![&repo]);
+        let method = &entry.methods["ExactDiffMatch"];
+        assert_eq!(method.results, 25);
+        let overflow = method.overflow.as_ref().unwrap();
+        assert_eq!(
+            (overflow.stored, overflow.cherries, overflow.targets),
+            (4, 1, 25)
+        );
+        assert_eq!(overflow.scores, None);
+        let sample = store.load(&repo, "ExactDiffMatch").unwrap();
+        assert_eq!(sample.len(), 4);
+        assert!(sample.iter().all(|result| picks.contains(result)));
+        // Methods within the cap are stored completely
+        assert_eq!(entry.methods["MessageScan"].overflow, None);
+        assert_eq!(store.load(&repo, "MessageScan").unwrap().len(), 1);
+        validate_result_store(directory.path()).unwrap();
+
+        // The sample is reproducible with the seed of the run
+        let other = temp_dir::TempDir::new().unwrap();
+        let mut other_store = ResultStore::open(other.path())
+            .unwrap()
+            .with_cap(ResultCap::new(10, 4));
+        let entry = RepoEntry::new(HarvestRun::with_seed(42), None, None, 30);
+        other_store
+            .save(&repo, entry, &["ExactDiffMatch"], &picks)
+            .unwrap();
+        assert_eq!(other_store.load(&repo, "ExactDiffMatch").unwrap(), sample);
+    }
+}
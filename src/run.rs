@@ -0,0 +1,104 @@
+use crate::hashing::{fnv1a, splitmix64};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use uuid::Uuid;
+
+/// Identifies a single harvest run.
+pub type RunId = Uuid;
+
+/// A HarvestRun bundles the id of a harvest and the global seed from which all randomized
+/// components of the harvest (e.g., samplers and MinHash) derive their own seeds.
+///
+/// The id and seed are stamped into every artifact written during a harvest, so that each
+/// result can be traced back to the exact run that produced it. Running a harvest again with the
+/// same seed reproduces all random choices of the original run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HarvestRun {
+    id: RunId,
+    seed: u64,
+}
+
+impl HarvestRun {
+    /// Start a new run with a random seed.
+    pub fn new() -> Self {
+        Self::with_seed(rand::thread_rng().gen())
+    }
+
+    /// Start a new run with the given seed. Each run receives a fresh id, even if the seed is the
+    /// same as the seed of a previous run.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            seed,
+        }
+    }
+
+    /// The run of artifacts of older versions that did not record the run in which they were
+    /// written. Its id is the nil UUID, and its seed is `0`.
+    pub fn unknown() -> Self {
+        Self::from_parts(Uuid::nil(), 0)
+    }
+
+    /// The run with the given id and seed, e.g., as recorded in an artifact
+    pub(crate) fn from_parts(id: RunId, seed: u64) -> Self {
+        Self { id, seed }
+    }
+
+    pub fn id(&self) -> RunId {
+        self.id
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Derive the seed for the given component (e.g., "sampler" or "minhash") from the run's
+    /// global seed. The same component always receives the same seed for the same global seed,
+    /// while different components receive independent seeds.
+    pub fn component_seed(&self, component: &str) -> u64 {
+        splitmix64(self.seed ^ fnv1a(component.as_bytes()))
+    }
+
+    /// Create a deterministic RNG for the given component.
+    pub fn component_rng(&self, component: &str) -> StdRng {
+        StdRng::seed_from_u64(self.component_seed(component))
+    }
+}
+
+impl Default for HarvestRun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for HarvestRun {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "run {} (seed {})", self.id, self.seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::run::HarvestRun;
+
+    #[test]
+    fn component_seeds_are_reproducible() {
+        let run_a = HarvestRun::with_seed(42);
+        let run_b = HarvestRun::with_seed(42);
+        assert_ne!(run_a.id(), run_b.id());
+        assert_eq!(
+            run_a.component_seed("sampler"),
+            run_b.component_seed("sampler")
+        );
+        assert_ne!(
+            run_a.component_seed("sampler"),
+            run_a.component_seed("minhash")
+        );
+        assert_ne!(
+            run_a.component_seed("sampler"),
+            HarvestRun::with_seed(43).component_seed("sampler")
+        );
+    }
+}
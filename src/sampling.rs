@@ -1,13 +1,25 @@
+pub mod filter;
+pub mod forge;
 pub mod fully_random;
+pub mod metadata;
 pub mod most_stars;
+pub mod overrides;
 use crate::Result;
 
-use crate::Error;
-use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
-use fallible_iterator::FallibleIterator;
-use octocrab::models::Repository;
+use crate::git::github::retrieve_branch_heads;
+use crate::git::RepoPin;
+use crate::run::HarvestRun;
+use crate::sampling::filter::SkippedRepo;
+use crate::sampling::metadata::{Capture, RepoMetadata};
+use crate::sampling::overrides::RepoOverrides;
+use crate::schema::SCHEMA_VERSION;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use log::{debug, info};
+use octocrab::models::{Repository, RepositoryId};
 use serde::Deserialize;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct SampleRange {
@@ -39,19 +51,144 @@ impl SampleRange {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Sample(Vec<Repository>);
+pub struct Sample {
+    // The schema version with which the sample was written (see crate::schema). Samples are
+    // always written in the current format, even if they were loaded from an older version.
+    #[serde(default, serialize_with = "current_schema_version")]
+    schema_version: Option<u32>,
+    // The harvest run in which the sample was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run: Option<HarvestRun>,
+    repositories: Vec<Repository>,
+    // Repositories that were skipped by the sampler's filter
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<SkippedRepo>,
+    // The branch heads of the repositories at sampling time, if the sample has been pinned
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pins: BTreeMap<RepositoryId, RepoPin>,
+    // Overrides of the harvest configuration for individual repositories
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    overrides: BTreeMap<RepositoryId, RepoOverrides>,
+    // When the metadata of the repositories was captured, i.e., when they were sampled
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<RepositoryId, Capture>,
+}
 
 impl Sample {
+    pub fn new(repositories: Vec<Repository>) -> Self {
+        Self {
+            schema_version: None,
+            run: None,
+            repositories,
+            skipped: vec![],
+            pins: BTreeMap::new(),
+            overrides: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// The harvest run in which this sample was created, if it has been recorded
+    pub fn run(&self) -> Option<&HarvestRun> {
+        self.run.as_ref()
+    }
+
+    /// The schema version with which the sample was written, if it was loaded from a file that
+    /// records it (see [crate::schema])
+    pub fn schema_version(&self) -> Option<u32> {
+        self.schema_version
+    }
+
+    /// Stamp the sample with the given harvest run
+    pub fn set_run(&mut self, run: HarvestRun) {
+        self.run = Some(run);
+    }
+
+    /// The repositories that were skipped during sampling and the reasons for skipping them
+    pub fn skipped(&self) -> &[SkippedRepo] {
+        &self.skipped
+    }
+
+    /// Record the current branch heads of all repositories that have not been pinned yet, so that
+    /// later harvests of the sample analyze the same history (see [RepoPin]). Only repositories
+    /// on GitHub can be pinned; repositories of other forges are harvested at their current state.
+    pub async fn pin(&mut self) -> Result<()> {
+        for repo in &self.repositories {
+            if repo.owner.is_none() {
+                debug!("cannot pin {}, which is not on GitHub", repo.name);
+                continue;
+            }
+            if let Entry::Vacant(entry) = self.pins.entry(repo.id) {
+                entry.insert(retrieve_branch_heads(repo).await?);
+            }
+        }
+        info!("pinned {} repositories", self.pins.len());
+        Ok(())
+    }
+
+    /// The branch heads to which the given repository has been pinned, if any
+    pub fn pin_of(&self, repo_id: RepositoryId) -> Option<&RepoPin> {
+        self.pins.get(&repo_id)
+    }
+
+    /// The branch heads of all pinned repositories
+    pub fn pins(&self) -> &BTreeMap<RepositoryId, RepoPin> {
+        &self.pins
+    }
+
+    /// The overrides of the harvest configuration for the given repository, if any
+    pub fn overrides_of(&self, repo_id: RepositoryId) -> Option<&RepoOverrides> {
+        self.overrides.get(&repo_id)
+    }
+
+    /// The overrides of the harvest configuration for all repositories that have overrides
+    pub fn overrides(&self) -> &BTreeMap<RepositoryId, RepoOverrides> {
+        &self.overrides
+    }
+
+    /// Override the harvest configuration for the given repository
+    pub fn set_overrides(&mut self, repo_id: RepositoryId, overrides: RepoOverrides) {
+        self.overrides.insert(repo_id, overrides);
+    }
+
+    /// Record the metadata of all repositories that have no snapshot yet, as it was reported by
+    /// the forge when the repositories were sampled (see [RepoMetadata]). Samplers capture the
+    /// metadata of the repositories that they sample.
+    pub fn capture_metadata(&mut self, captured_at: DateTime<Utc>) {
+        for repo in &self.repositories {
+            self.metadata
+                .entry(repo.id)
+                .or_insert(Capture { captured_at });
+        }
+    }
+
+    /// The metadata of the given repository at sampling time, if it has been captured
+    pub fn metadata_of(&self, repo_id: RepositoryId) -> Option<RepoMetadata<'_>> {
+        let capture = self.metadata.get(&repo_id)?;
+        self.repositories
+            .iter()
+            .find(|repo| repo.id == repo_id)
+            .map(|repo| RepoMetadata::new(repo, capture))
+    }
+
+    /// The metadata of all repositories whose metadata has been captured
+    pub fn metadata(&self) -> impl Iterator<Item = RepoMetadata<'_>> {
+        self.repositories.iter().filter_map(|repo| {
+            self.metadata
+                .get(&repo.id)
+                .map(|capture| RepoMetadata::new(repo, capture))
+        })
+    }
+
     pub fn repos(&self) -> &[Repository] {
-        &self.0
+        &self.repositories
     }
 
     pub fn into_repos(self) -> Vec<Repository> {
-        self.0
+        self.repositories
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.repositories.len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -59,8 +196,22 @@ impl Sample {
     }
 }
 
+fn current_schema_version<S: Serializer>(
+    _: &Option<u32>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_u32(SCHEMA_VERSION)
+}
+
 /// A trait for defining GitHub samplers using different sampling strategies.
-pub trait GitHubSampler: FallibleIterator<Item = Repository, Error = Error> {
+///
+/// Samplers are async, so that they can be used from within an async runtime. Callers outside of
+/// a runtime have to block on the returned futures themselves.
+#[async_trait::async_trait]
+pub trait GitHubSampler: Send {
     /// Sample a desired number of fork networks with a desired max size.
-    fn sample(&mut self, sample_size: usize) -> Result<Sample>;
+    async fn sample(&mut self, sample_size: usize) -> Result<Sample>;
+
+    /// Sample the next repository. Returns None if no further repositories can be found.
+    async fn next(&mut self) -> Result<Option<Repository>>;
 }
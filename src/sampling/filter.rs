@@ -0,0 +1,220 @@
+use chrono::{Months, Utc};
+use octocrab::models::{Repository, RepositoryId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Reasons for which a sampler can skip a repository before it is added to a sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+    Archived,
+    Disabled,
+    Template,
+    /// The repository has not been pushed to in the required number of months
+    Inactive,
+    /// The repository's license is excluded or not among the included licenses
+    License,
+}
+
+/// A repository that was skipped during sampling, together with the reason for skipping it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedRepo {
+    pub id: RepositoryId,
+    pub name: String,
+    pub reason: SkipReason,
+}
+
+/// A RepoFilter decides whether a sampler should skip a repository, so that repositories that are
+/// irrelevant for a harvest do not consume any clone budget.
+///
+/// By default, no repositories are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoFilter {
+    exclude_archived: bool,
+    exclude_disabled: bool,
+    exclude_templates: bool,
+    min_activity_months: Option<u32>,
+    // SPDX ids of licenses; if set, repositories must have one of these licenses
+    include_licenses: Option<HashSet<String>>,
+    // SPDX ids of licenses that are excluded
+    exclude_licenses: HashSet<String>,
+}
+
+impl RepoFilter {
+    /// A filter that excludes archived, disabled, and template repositories.
+    pub fn excluding_inactive() -> Self {
+        Self {
+            exclude_archived: true,
+            exclude_disabled: true,
+            exclude_templates: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn exclude_archived(mut self, exclude: bool) -> Self {
+        self.exclude_archived = exclude;
+        self
+    }
+
+    pub fn exclude_disabled(mut self, exclude: bool) -> Self {
+        self.exclude_disabled = exclude;
+        self
+    }
+
+    pub fn exclude_templates(mut self, exclude: bool) -> Self {
+        self.exclude_templates = exclude;
+        self
+    }
+
+    /// Require that a repository has been pushed to in the last `months` months.
+    pub fn min_activity_months(mut self, months: Option<u32>) -> Self {
+        self.min_activity_months = months;
+        self
+    }
+
+    /// Only keep repositories with one of the given licenses (identified by their SPDX id).
+    /// Repositories without a license reported by GitHub are skipped.
+    pub fn include_licenses<I: IntoIterator<Item = String>>(mut self, licenses: I) -> Self {
+        self.include_licenses = Some(licenses.into_iter().collect());
+        self
+    }
+
+    /// Skip repositories with any of the given licenses (identified by their SPDX id).
+    pub fn exclude_licenses<I: IntoIterator<Item = String>>(mut self, licenses: I) -> Self {
+        self.exclude_licenses = licenses.into_iter().collect();
+        self
+    }
+
+    /// Check whether the given repository should be skipped. Returns the reason for skipping it,
+    /// or None if the repository passes the filter.
+    ///
+    /// Flags that are not reported by the GitHub API are treated as unset.
+    pub fn check(&self, repo: &Repository) -> Option<SkipReason> {
+        if self.exclude_archived && repo.archived.unwrap_or(false) {
+            return Some(SkipReason::Archived);
+        }
+        if self.exclude_disabled && repo.disabled.unwrap_or(false) {
+            return Some(SkipReason::Disabled);
+        }
+        if self.exclude_templates && repo.is_template.unwrap_or(false) {
+            return Some(SkipReason::Template);
+        }
+        if let Some(months) = self.min_activity_months {
+            let cutoff = Utc::now().checked_sub_months(Months::new(months));
+            match (repo.pushed_at, cutoff) {
+                (Some(pushed_at), Some(cutoff)) if pushed_at < cutoff => {
+                    return Some(SkipReason::Inactive)
+                }
+                (None, _) => return Some(SkipReason::Inactive),
+                _ => {}
+            }
+        }
+        let license = repo.license.as_ref().map(|l| &l.spdx_id);
+        if let Some(included) = &self.include_licenses {
+            if !license.is_some_and(|l| included.contains(l)) {
+                return Some(SkipReason::License);
+            }
+        }
+        if license.is_some_and(|l| self.exclude_licenses.contains(l)) {
+            return Some(SkipReason::License);
+        }
+        None
+    }
+
+    /// Check the given repository and create a SkippedRepo entry if it should be skipped.
+    pub fn skip(&self, repo: &Repository) -> Option<SkippedRepo> {
+        self.check(repo).map(|reason| SkippedRepo {
+            id: repo.id,
+            name: repo.full_name.clone().unwrap_or(repo.name.clone()),
+            reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampling::filter::{RepoFilter, SkipReason};
+    use chrono::{Duration, Utc};
+    use octocrab::models::Repository;
+    use serde_json::json;
+
+    fn repo(archived: bool, is_template: bool, days_since_push: i64) -> Repository {
+        let pushed_at = Utc::now() - Duration::try_days(days_since_push).unwrap();
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "repo",
+            "url": "https://api.github.com/repos/owner/repo",
+            "archived": archived,
+            "is_template": is_template,
+            "pushed_at": pushed_at.to_rfc3339(),
+        }))
+        .unwrap()
+    }
+
+    fn licensed_repo(spdx_id: &str) -> Repository {
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "repo",
+            "url": "https://api.github.com/repos/owner/repo",
+            "license": {
+                "key": spdx_id.to_lowercase(),
+                "name": spdx_id,
+                "node_id": "node",
+                "spdx_id": spdx_id,
+                "html_url": null,
+                "description": null,
+                "implementation": null,
+                "permissions": null,
+                "conditions": null,
+                "limitations": null,
+                "body": null,
+                "featured": null,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn default_filter_skips_nothing() {
+        let filter = RepoFilter::default();
+        assert_eq!(filter.check(&repo(true, true, 1000)), None);
+    }
+
+    #[test]
+    fn skip_reasons() {
+        let filter = RepoFilter::excluding_inactive().min_activity_months(Some(6));
+        assert_eq!(
+            filter.check(&repo(true, false, 1)),
+            Some(SkipReason::Archived)
+        );
+        assert_eq!(
+            filter.check(&repo(false, true, 1)),
+            Some(SkipReason::Template)
+        );
+        assert_eq!(
+            filter.check(&repo(false, false, 365)),
+            Some(SkipReason::Inactive)
+        );
+        assert_eq!(filter.check(&repo(false, false, 1)), None);
+    }
+
+    #[test]
+    fn license_lists() {
+        let permissive = RepoFilter::default().include_licenses(["MIT".to_string()]);
+        assert_eq!(permissive.check(&licensed_repo("MIT")), None);
+        assert_eq!(
+            permissive.check(&licensed_repo("GPL-3.0")),
+            Some(SkipReason::License)
+        );
+        assert_eq!(
+            permissive.check(&repo(false, false, 1)),
+            Some(SkipReason::License)
+        );
+
+        let no_gpl = RepoFilter::default().exclude_licenses(["GPL-3.0".to_string()]);
+        assert_eq!(no_gpl.check(&licensed_repo("MIT")), None);
+        assert_eq!(
+            no_gpl.check(&licensed_repo("GPL-3.0")),
+            Some(SkipReason::License)
+        );
+    }
+}
@@ -0,0 +1,142 @@
+mod bitbucket;
+mod gitlab;
+
+use crate::error::{Error, ErrorKind};
+use crate::git::github;
+use crate::Result;
+use chrono::NaiveDateTime;
+use log::debug;
+use octocrab::models::Repository;
+use std::fmt::Debug;
+
+pub use bitbucket::BitbucketForge;
+pub use gitlab::GitLabForge;
+
+/// The number of repositories that are requested per page. GitHub, GitLab, and Bitbucket return
+/// at most 100 repositories per page.
+pub(crate) const PER_PAGE: u8 = 100;
+
+/// The number of results of a search that GitHub returns at most; requests for later pages fail
+const GITHUB_SEARCH_CAP: u32 = 1000;
+
+/// A ForgeClient searches the repositories of a forge (e.g., GitHub or GitLab) and lists their
+/// forks, so that samplers and fork networks are not bound to GitHub's API.
+///
+/// Repositories of all forges are represented by GitHub's repository model, in which clients fill
+/// the fields for which their forge has an equivalent (e.g., GitLab's project path as full name).
+/// Repositories of forges other than GitHub have no owner, so that they are identified by their
+/// URL (see [crate::git::RepoId]). Note that the numeric ids of different forges may collide, so a
+/// sample should only contain repositories of one forge.
+///
+/// Not every forge supports every search; clients return a configuration error for searches that
+/// their forge does not support.
+#[async_trait::async_trait]
+pub trait ForgeClient: Debug + Send + Sync {
+    /// The name of the forge, e.g., `GitHub`
+    fn name(&self) -> &'static str;
+
+    /// The given page (starting at 1) of the repositories whose primary language is the given
+    /// language, ordered by their number of stars in descending order. Returns an empty page if
+    /// there are no more repositories.
+    async fn most_starred(&self, language: &str, page: u32) -> Result<Vec<Repository>>;
+
+    /// At most `limit` repositories that were created in the given time range (in UTC)
+    async fn created_between(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Repository>>;
+
+    /// The direct forks of the given repository, at most `max_forks` if given
+    async fn forks(&self, repo: &Repository, max_forks: Option<usize>) -> Result<Vec<Repository>>;
+}
+
+/// The client of GitHub's API, which uses the global octocrab instance and GitHub's request
+/// cooldown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GitHubForge;
+
+#[async_trait::async_trait]
+impl ForgeClient for GitHubForge {
+    fn name(&self) -> &'static str {
+        "GitHub"
+    }
+
+    async fn most_starred(&self, language: &str, page: u32) -> Result<Vec<Repository>> {
+        if page.saturating_sub(1).saturating_mul(PER_PAGE as u32) >= GITHUB_SEARCH_CAP {
+            debug!("reached the {GITHUB_SEARCH_CAP} results that GitHub searches return at most");
+            return Ok(vec![]);
+        }
+        let query = format!("language:{language}");
+        let page = github::search_query(&query, "stars", "desc", PER_PAGE, page).await?;
+        Ok(page.items)
+    }
+
+    async fn created_between(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Repository>> {
+        let time_format = "%Y-%m-%dT%H:%M:%S+00:00";
+        let query = format!(
+            "created:{}..{}",
+            start.format(time_format),
+            end.format(time_format)
+        );
+        debug!("search query: '{}'", query);
+        let page = github::search_repositories(&query).await?;
+        Ok(github::collect_repos_from_pages(page, Some(limit))
+            .await
+            .unwrap_or_default())
+    }
+
+    async fn forks(&self, repo: &Repository, max_forks: Option<usize>) -> Result<Vec<Repository>> {
+        Ok(github::retrieve_forks(repo, max_forks)
+            .await
+            .unwrap_or_default())
+    }
+}
+
+/// Convert a repository of another forge, given in GitHub's JSON format, to GitHub's model
+pub(crate) fn repository_from_json(forge: &str, value: serde_json::Value) -> Result<Repository> {
+    serde_json::from_value(value).map_err(|error| {
+        Error::new(ErrorKind::Forge(format!(
+            "{forge} returned an unexpected repository: {error}"
+        )))
+    })
+}
+
+/// The error for a search that the given forge does not support
+pub(crate) fn unsupported(forge: &str, search: &str) -> Error {
+    Error::new(ErrorKind::Configuration(format!(
+        "{forge} does not support searching {search}"
+    )))
+}
+
+/// Send the given request and parse its JSON response
+pub(crate) async fn get_json(
+    forge: &str,
+    request: reqwest::RequestBuilder,
+) -> Result<serde_json::Value> {
+    let forge_error = |error: reqwest::Error| {
+        Error::new(ErrorKind::Forge(format!(
+            "the request to {forge} failed: {error}"
+        )))
+    };
+    let response = request.send().await.map_err(forge_error)?;
+    let status = response.status();
+    let url = response.url().clone();
+    let body = response.text().await.map_err(forge_error)?;
+    if !status.is_success() {
+        return Err(Error::new(ErrorKind::Forge(format!(
+            "{forge} answered {status} for {url}: {body}"
+        ))));
+    }
+    serde_json::from_str(&body).map_err(|error| {
+        Error::new(ErrorKind::Forge(format!(
+            "{forge} answered with invalid JSON for {url}: {error}"
+        )))
+    })
+}
@@ -0,0 +1,219 @@
+use crate::sampling::forge::{get_json, repository_from_json, unsupported, ForgeClient, PER_PAGE};
+use crate::Result;
+use chrono::NaiveDateTime;
+use octocrab::models::Repository;
+use serde_json::{json, Value};
+use std::fmt::{Debug, Formatter};
+
+const NAME: &str = "Bitbucket";
+
+/// The client of the API of Bitbucket Cloud
+///
+/// Bitbucket does not record stars, so it cannot be sampled with the MostStarsSampler.
+/// Repositories are identified by UUIDs on Bitbucket; their numeric ids are derived from the
+/// UUIDs.
+#[derive(Clone)]
+pub struct BitbucketForge {
+    api: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl BitbucketForge {
+    /// A client of the API with the given base URL, i.e., `https://api.bitbucket.org/2.0`
+    pub fn new(api: &str) -> Self {
+        Self {
+            api: api.trim_end_matches('/').to_string(),
+            token: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Authenticate with the given access token, which is required for private repositories
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Get all pages of the given URL, until at least `limit` values have been collected
+    async fn get_values(
+        &self,
+        url: String,
+        query: &[(&str, String)],
+        limit: usize,
+    ) -> Result<Vec<Value>> {
+        let mut values = vec![];
+        let mut request = self.client.get(url).query(query);
+        loop {
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let page = get_json(NAME, request).await?;
+            values.extend(page["values"].as_array().into_iter().flatten().cloned());
+            match page["next"].as_str() {
+                Some(next) if values.len() < limit => request = self.client.get(next),
+                _ => break,
+            }
+        }
+        values.truncate(limit);
+        Ok(values)
+    }
+
+    /// Convert the given repository to GitHub's repository model
+    fn repository(&self, repo: &Value) -> Result<Repository> {
+        let field = |name: &str| repo.get(name).cloned().unwrap_or(Value::Null);
+        let clone_url = |protocol: &str| {
+            repo["links"]["clone"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|link| link["name"] == protocol)
+                .and_then(|link| link["href"].as_str())
+                .map(|href| match href.split_once("://") {
+                    // Authenticated responses include the user in the URL
+                    Some((scheme, rest)) if protocol == "https" => {
+                        let rest = rest.split_once('@').map_or(rest, |(_, host)| host);
+                        format!("{scheme}://{rest}")
+                    }
+                    _ => href.to_string(),
+                })
+        };
+        let full_name = field("full_name");
+        let url = format!(
+            "{}/repositories/{}",
+            self.api,
+            full_name.as_str().unwrap_or_default()
+        );
+        repository_from_json(
+            NAME,
+            json!({
+                "id": uuid_id(repo["uuid"].as_str().unwrap_or_default()),
+                "name": field("slug"),
+                "full_name": full_name,
+                "url": url,
+                "html_url": repo["links"]["html"]["href"],
+                "clone_url": clone_url("https"),
+                "ssh_url": clone_url("ssh"),
+                "description": field("description"),
+                "default_branch": repo["mainbranch"]["name"],
+                "created_at": field("created_on"),
+                "pushed_at": field("updated_on"),
+                "language": field("language"),
+                "fork": repo.get("parent").is_some_and(|p| !p.is_null()),
+            }),
+        )
+    }
+}
+
+/// The numeric id of the repository with the given UUID (e.g., `{0c5f1f3e-...}`), which consists
+/// of the first 64 bits of the UUID
+fn uuid_id(uuid: &str) -> u64 {
+    let hex: String = uuid
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .take(16)
+        .collect();
+    u64::from_str_radix(&hex, 16).unwrap_or_default()
+}
+
+impl Default for BitbucketForge {
+    fn default() -> Self {
+        Self::new("https://api.bitbucket.org/2.0")
+    }
+}
+
+impl Debug for BitbucketForge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BitbucketForge")
+            .field("api", &self.api)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl ForgeClient for BitbucketForge {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn most_starred(&self, _language: &str, _page: u32) -> Result<Vec<Repository>> {
+        Err(unsupported(NAME, "repositories by their stars"))
+    }
+
+    async fn created_between(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Repository>> {
+        let time_format = "%Y-%m-%dT%H:%M:%S+00:00";
+        let filter = format!(
+            "created_on >= {} AND created_on < {}",
+            start.format(time_format),
+            end.format(time_format)
+        );
+        let query = [
+            ("q", filter),
+            ("sort", "created_on".to_string()),
+            ("pagelen", limit.clamp(1, PER_PAGE as usize).to_string()),
+        ];
+        let url = format!("{}/repositories", self.api);
+        self.get_values(url, &query, limit)
+            .await?
+            .iter()
+            .map(|repo| self.repository(repo))
+            .collect()
+    }
+
+    async fn forks(&self, repo: &Repository, max_forks: Option<usize>) -> Result<Vec<Repository>> {
+        let Some(full_name) = &repo.full_name else {
+            return Ok(vec![]);
+        };
+        let url = format!("{}/repositories/{full_name}/forks", self.api);
+        let query = [("pagelen", PER_PAGE.to_string())];
+        self.get_values(url, &query, max_forks.unwrap_or(usize::MAX))
+            .await?
+            .iter()
+            .map(|repo| self.repository(repo))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::RepoId;
+    use crate::sampling::forge::BitbucketForge;
+    use serde_json::json;
+
+    #[test]
+    fn repositories_are_converted() {
+        let repo = json!({
+            "uuid": "{0c5f1f3e-2b1a-4c6d-9e8f-1234567890ab}",
+            "slug": "tools",
+            "full_name": "atlassian/tools",
+            "links": {
+                "html": {"href": "https://bitbucket.org/atlassian/tools"},
+                "clone": [
+                    {"name": "https", "href": "https://jane@bitbucket.org/atlassian/tools.git"},
+                    {"name": "ssh", "href": "git@bitbucket.org:atlassian/tools.git"}
+                ]
+            },
+            "mainbranch": {"name": "main"},
+            "language": "java",
+            "created_on": "2022-01-01T10:00:00.000000+00:00",
+            "updated_on": "2023-01-01T10:00:00.000000+00:00",
+            "parent": null
+        });
+        let repo = BitbucketForge::default().repository(&repo).unwrap();
+        assert_eq!(repo.id.0, 0x0c5f1f3e2b1a4c6d);
+        assert_eq!(repo.name, "tools");
+        assert_eq!(
+            repo.clone_url.as_ref().map(|url| url.as_str()),
+            Some("https://bitbucket.org/atlassian/tools.git")
+        );
+        assert_eq!(repo.default_branch.as_deref(), Some("main"));
+        assert_eq!(repo.fork, Some(false));
+        assert_eq!(RepoId::from(&repo).to_string(), "bitbucket:atlassian/tools");
+    }
+}
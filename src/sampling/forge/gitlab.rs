@@ -0,0 +1,195 @@
+use crate::sampling::forge::{get_json, repository_from_json, unsupported, ForgeClient, PER_PAGE};
+use crate::Result;
+use chrono::NaiveDateTime;
+use octocrab::models::Repository;
+use serde_json::{json, Value};
+use std::fmt::{Debug, Formatter};
+
+const NAME: &str = "GitLab";
+
+/// The client of the API of gitlab.com or of a self-managed GitLab instance
+///
+/// GitLab cannot search projects by their creation time, so it cannot be sampled with the
+/// FullyRandomSampler. The languages of projects are not listed; the sampled projects record the
+/// language for which they were sampled.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::sampling::forge::GitLabForge;
+/// use cherry_harvest::sampling::most_stars::{MostStarsSampler, ProgrammingLanguage};
+/// use std::sync::Arc;
+///
+/// let forge = GitLabForge::new("https://gitlab.example.com/api/v4").with_token("glpat-...");
+/// let sampler = MostStarsSampler::new(vec![ProgrammingLanguage::new("Rust".to_string())])
+///     .with_forge(Arc::new(forge));
+/// ```
+#[derive(Clone)]
+pub struct GitLabForge {
+    api: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl GitLabForge {
+    /// A client of the API with the given base URL, e.g., `https://gitlab.com/api/v4`
+    pub fn new(api: &str) -> Self {
+        Self {
+            api: api.trim_end_matches('/').to_string(),
+            token: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Authenticate with the given personal, group, or project access token, which is required
+    /// for private projects and raises the rate limit
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, String)]) -> Result<Value> {
+        let mut request = self.client.get(format!("{}{path}", self.api)).query(query);
+        if let Some(token) = &self.token {
+            request = request.header("PRIVATE-TOKEN", token);
+        }
+        get_json(NAME, request).await
+    }
+
+    /// Convert the given project to GitHub's repository model
+    fn repository(&self, project: &Value, language: Option<&str>) -> Result<Repository> {
+        let field = |name: &str| project.get(name).cloned().unwrap_or(Value::Null);
+        repository_from_json(
+            NAME,
+            json!({
+                "id": field("id"),
+                "name": field("path"),
+                "full_name": field("path_with_namespace"),
+                "url": format!("{}/projects/{}", self.api, field("id")),
+                "html_url": field("web_url"),
+                "clone_url": field("http_url_to_repo"),
+                "ssh_url": field("ssh_url_to_repo"),
+                "description": field("description"),
+                "default_branch": field("default_branch"),
+                "stargazers_count": field("star_count"),
+                "forks_count": field("forks_count"),
+                "created_at": field("created_at"),
+                "pushed_at": field("last_activity_at"),
+                "archived": field("archived"),
+                "fork": project.get("forked_from_project").is_some_and(|p| !p.is_null()),
+                "language": language,
+            }),
+        )
+    }
+
+    fn repositories(&self, projects: Value, language: Option<&str>) -> Result<Vec<Repository>> {
+        projects
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|project| self.repository(project, language))
+            .collect()
+    }
+}
+
+impl Default for GitLabForge {
+    fn default() -> Self {
+        Self::new("https://gitlab.com/api/v4")
+    }
+}
+
+impl Debug for GitLabForge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitLabForge")
+            .field("api", &self.api)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl ForgeClient for GitLabForge {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn most_starred(&self, language: &str, page: u32) -> Result<Vec<Repository>> {
+        let query = [
+            ("with_programming_language", language.to_string()),
+            ("order_by", "star_count".to_string()),
+            ("sort", "desc".to_string()),
+            ("per_page", PER_PAGE.to_string()),
+            ("page", page.to_string()),
+        ];
+        let projects = self.get("/projects", &query).await?;
+        self.repositories(projects, Some(language))
+    }
+
+    async fn created_between(
+        &self,
+        _start: NaiveDateTime,
+        _end: NaiveDateTime,
+        _limit: usize,
+    ) -> Result<Vec<Repository>> {
+        Err(unsupported(NAME, "repositories by their creation time"))
+    }
+
+    async fn forks(&self, repo: &Repository, max_forks: Option<usize>) -> Result<Vec<Repository>> {
+        let mut forks = vec![];
+        if repo.forks_count.unwrap_or_default() == 0 {
+            return Ok(forks);
+        }
+        let path = format!("/projects/{}/forks", repo.id);
+        for page in 1.. {
+            let query = [
+                ("per_page", PER_PAGE.to_string()),
+                ("page", page.to_string()),
+            ];
+            let page = self.repositories(self.get(&path, &query).await?, None)?;
+            if page.is_empty() {
+                break;
+            }
+            forks.extend(page);
+            if max_forks.is_some_and(|max| forks.len() >= max) {
+                break;
+            }
+        }
+        forks.truncate(max_forks.unwrap_or(usize::MAX));
+        Ok(forks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::RepoId;
+    use crate::sampling::forge::GitLabForge;
+    use serde_json::json;
+
+    #[test]
+    fn projects_are_converted() {
+        let forge = GitLabForge::default().with_token("secret");
+        let project = json!({
+            "id": 278964,
+            "path": "gitlab",
+            "path_with_namespace": "gitlab-org/gitlab",
+            "web_url": "https://gitlab.com/gitlab-org/gitlab",
+            "http_url_to_repo": "https://gitlab.com/gitlab-org/gitlab.git",
+            "ssh_url_to_repo": "git@gitlab.com:gitlab-org/gitlab.git",
+            "default_branch": "master",
+            "star_count": 5000,
+            "forks_count": 10000,
+            "created_at": "2015-05-20T10:47:11.949Z",
+            "last_activity_at": "2024-01-01T00:00:00.000Z",
+            "archived": false
+        });
+        let repo = forge.repository(&project, Some("Ruby")).unwrap();
+        assert_eq!(repo.id.0, 278964);
+        assert_eq!(repo.name, "gitlab");
+        assert_eq!(repo.full_name.as_deref(), Some("gitlab-org/gitlab"));
+        assert_eq!(repo.stargazers_count, Some(5000));
+        assert_eq!(repo.forks_count, Some(10000));
+        assert_eq!(repo.fork, Some(false));
+        assert!(repo.pushed_at.is_some());
+        assert_eq!(RepoId::from(&repo).to_string(), "gitlab:gitlab-org/gitlab");
+        assert!(!format!("{forge:?}").contains("secret"));
+    }
+}
@@ -1,58 +1,81 @@
 use std::collections::HashSet;
 
-use chrono::Duration;
-use fallible_iterator::FallibleIterator;
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
 use log::{debug, warn};
 use octocrab::models::{Repository, RepositoryId};
-use rand::{rngs::ThreadRng, Rng};
-use tokio::runtime::Runtime;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::{git::github, Result};
+use crate::Result;
 
+use super::filter::{RepoFilter, SkippedRepo};
+use super::forge::{ForgeClient, GitHubForge};
 use super::{GitHubSampler, Sample, SampleRange};
 
 /// This GitHub sampler selects GitHub repos by choosing a random day from the given range
-/// and then choosing a random repository that was created on that day.
+/// and then choosing a random repository that was created on that day. Other forges can be
+/// sampled with [FullyRandomSampler::with_forge].
 #[derive(Debug)]
 pub struct FullyRandomSampler {
+    forge: Arc<dyn ForgeClient>,
     sample_range: SampleRange,
     previously_sampled: HashSet<RepositoryId>,
-    random: ThreadRng,
-    runtime: Runtime,
+    random: StdRng,
+    filter: RepoFilter,
+    skipped: Vec<SkippedRepo>,
 }
 
 impl FullyRandomSampler {
     pub fn new(sample_range: SampleRange) -> Self {
-        debug!("created a new FullyRandomSampler");
+        Self::new_with_seed(sample_range, rand::thread_rng().gen())
+    }
+
+    /// Create a new sampler whose random choices are determined by the given seed.
+    pub fn new_with_seed(sample_range: SampleRange, seed: u64) -> Self {
+        debug!("created a new FullyRandomSampler with seed {seed}");
 
         Self {
+            forge: Arc::new(GitHubForge),
             sample_range,
             previously_sampled: HashSet::new(),
-            random: rand::thread_rng(),
-            runtime: Runtime::new().unwrap(),
+            random: StdRng::seed_from_u64(seed),
+            filter: RepoFilter::default(),
+            skipped: vec![],
         }
     }
+
+    /// Skip all repositories that do not pass the given filter.
+    pub fn with_filter(mut self, filter: RepoFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sample the repositories from the given forge (by default, GitHub)
+    pub fn with_forge(mut self, forge: Arc<dyn ForgeClient>) -> Self {
+        self.forge = forge;
+        self
+    }
 }
 
+#[async_trait::async_trait]
 impl GitHubSampler for FullyRandomSampler {
-    fn sample(&mut self, sample_size: usize) -> Result<Sample> {
-        let mut sample = Sample(Vec::with_capacity(sample_size));
+    async fn sample(&mut self, sample_size: usize) -> Result<Sample> {
+        let mut sample = Sample::new(Vec::with_capacity(sample_size));
 
-        while sample.0.len() < sample_size {
-            match self.next()? {
-                Some(next) => sample.0.push(next),
+        while sample.len() < sample_size {
+            match self.next().await? {
+                Some(next) => sample.repositories.push(next),
                 None => break,
             }
         }
+        sample.skipped.append(&mut self.skipped);
+        sample.capture_metadata(Utc::now());
         Ok(sample)
     }
-}
 
-impl FallibleIterator for FullyRandomSampler {
-    type Item = Repository;
-    type Error = crate::Error;
-
-    fn next(&mut self) -> Result<Option<Self::Item>> {
+    async fn next(&mut self) -> Result<Option<Repository>> {
         debug!("retrieving next repo");
         let mut next = Ok(None);
 
@@ -71,17 +94,24 @@ impl FallibleIterator for FullyRandomSampler {
             let random_start = self.sample_range.start + random_num_seconds;
             debug!(
                 "random datetime: {}",
-                random_start.format("%Y-%m-%d %H:%M:%S").to_string()
+                random_start.format("%Y-%m-%d %H:%M:%S")
             );
             let one_hour = Duration::try_hours(1).unwrap();
             let end = random_start + one_hour;
 
             let random_repo = self
-                .runtime
-                .block_on(github::repos_created_in_time_range(random_start, end));
+                .forge
+                .created_between(random_start, end, 1)
+                .await
+                .map(|mut repos| repos.pop());
 
             next = random_repo.map(|op| {
                 if let Some(repo) = op {
+                    if let Some(skipped) = self.filter.skip(&repo) {
+                        debug!("skipping {}: {:?}", skipped.name, skipped.reason);
+                        self.skipped.push(skipped);
+                        return None;
+                    }
                     if !self.previously_sampled.contains(&repo.id) {
                         debug!(
                             "found repository {} with id {} created at {}",
@@ -123,9 +153,10 @@ mod tests {
             NaiveDate::from_ymd_opt(2022, 1, 2).unwrap(),
         );
         let mut sampler = FullyRandomSampler::new(range);
-        let sample = sampler.sample(2).unwrap();
-        println!("sampled {} networks", sample.0.len());
-        for repo in sample.0.iter() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let sample = runtime.block_on(sampler.sample(2)).unwrap();
+        println!("sampled {} networks", sample.len());
+        for repo in sample.repos() {
             println!("sampled repo {:#?}", repo.full_name);
         }
     }
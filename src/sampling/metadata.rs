@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use octocrab::models::Repository;
+use serde::{Deserialize, Serialize};
+
+/// The characteristics of a repository as reported by its forge when the repository was sampled
+/// (see [crate::sampling::Sample::metadata_of]). Forges report the current state of a repository,
+/// e.g., its topics or whether it is archived, so later analyses that segment results by these
+/// characteristics rely on the snapshot instead of querying the forge again.
+///
+/// The sample already stores each repository as it was returned by the forge's API, so the
+/// characteristics are read from it; the sample only records when they were captured.
+///
+/// # Examples
+/// ```yaml
+/// metadata:
+///   # The GitHub id of the repository
+///   2325298:
+///     captured_at: 2024-05-01T12:00:00Z
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepoMetadata<'s> {
+    /// When the metadata was captured
+    pub captured_at: DateTime<Utc>,
+    repo: &'s Repository,
+}
+
+impl<'s> RepoMetadata<'s> {
+    pub(crate) fn new(repo: &'s Repository, capture: &Capture) -> Self {
+        Self {
+            captured_at: capture.captured_at,
+            repo,
+        }
+    }
+
+    pub fn description(&self) -> Option<&'s str> {
+        self.repo.description.as_deref().filter(|d| !d.is_empty())
+    }
+
+    /// The topics of the repository; empty if the forge does not report topics (e.g., Bitbucket)
+    pub fn topics(&self) -> &'s [String] {
+        self.repo.topics.as_deref().unwrap_or_default()
+    }
+
+    pub fn default_branch(&self) -> Option<&'s str> {
+        self.repo.default_branch.as_deref()
+    }
+
+    /// The size of the repository in kilobytes, as reported by the forge
+    pub fn size(&self) -> Option<u32> {
+        self.repo.size
+    }
+
+    pub fn archived(&self) -> bool {
+        self.repo.archived.unwrap_or(false)
+    }
+
+    pub fn stars(&self) -> Option<u32> {
+        self.repo.stargazers_count
+    }
+
+    pub fn forks(&self) -> Option<u32> {
+        self.repo.forks_count
+    }
+}
+
+/// When the metadata of a repository was captured, as it is stored in a sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Capture {
+    pub(crate) captured_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampling::Sample;
+    use crate::schema::{validate, Artifact};
+    use chrono::DateTime;
+    use octocrab::models::{Repository, RepositoryId};
+
+    #[test]
+    fn metadata_is_captured_once() {
+        let repo: Repository = serde_json::from_value(serde_json::json!({
+            "id": 42,
+            "name": "repo",
+            "url": "https://api.github.com/repos/owner/repo",
+            "description": "A repository",
+            "topics": ["rust", "git"],
+            "default_branch": "main",
+            "size": 1024,
+            "archived": true,
+            "stargazers_count": 7
+        }))
+        .unwrap();
+        let sampled_at = DateTime::from_timestamp(1674832148, 0).unwrap();
+        let mut sample = Sample::new(vec![repo]);
+        assert!(sample.metadata_of(RepositoryId(42)).is_none());
+        sample.capture_metadata(sampled_at);
+        // Repositories that already have a snapshot keep it
+        sample.capture_metadata(DateTime::from_timestamp(1700000000, 0).unwrap());
+
+        let metadata = sample.metadata_of(RepositoryId(42)).unwrap();
+        assert_eq!(metadata.captured_at, sampled_at);
+        assert_eq!(metadata.description(), Some("A repository"));
+        assert_eq!(metadata.topics(), ["rust", "git"]);
+        assert_eq!(metadata.default_branch(), Some("main"));
+        assert_eq!(metadata.size(), Some(1024));
+        assert!(metadata.archived());
+        assert_eq!(metadata.stars(), Some(7));
+        assert_eq!(metadata.forks(), None);
+
+        let yaml = serde_yaml::to_string(&sample).unwrap();
+        assert!(yaml.contains("metadata:\n  42:\n    captured_at:"));
+        validate(Artifact::Sample, &yaml).unwrap();
+        let loaded: Sample = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(loaded.metadata_of(RepositoryId(42)), Some(metadata));
+        assert_eq!(loaded.metadata().count(), 1);
+    }
+}
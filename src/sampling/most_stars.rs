@@ -1,18 +1,17 @@
 use std::collections::HashSet;
-use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::git::github;
 use crate::Result;
-use fallible_iterator::FallibleIterator;
+use chrono::Utc;
 use log::{debug, error, info};
 use octocrab::models::{Repository, RepositoryId};
-use octocrab::Page;
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use tokio::runtime::Runtime;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::{sampling::Sample, Error};
+use crate::sampling::Sample;
 
+use super::filter::{RepoFilter, SkippedRepo};
+use super::forge::{ForgeClient, GitHubForge};
 use super::GitHubSampler;
 
 /// The name of a programming language. Values should match the names of languages on GitHub.
@@ -26,139 +25,117 @@ impl ProgrammingLanguage {
 }
 
 /// This GitHub sampler selects the most popular repositories (indicated by stars)
-/// from the given propgramming lanugages
+/// from the given propgramming lanugages. Other forges can be sampled with
+/// [MostStarsSampler::with_forge]. GitHub's search returns at most 1000 repositories per language,
+/// so larger samples of a language are cut short.
 #[derive(Debug)]
 pub struct MostStarsSampler {
+    forge: Arc<dyn ForgeClient>,
     languages: Vec<ProgrammingLanguage>,
     previously_sampled: HashSet<RepositoryId>,
-    random: ThreadRng,
-    runtime: Rc<Runtime>,
+    random: StdRng,
+    filter: RepoFilter,
+    skipped: Vec<SkippedRepo>,
 }
 
-const THRESHOLD: f64 = 0.5;
-
 impl MostStarsSampler {
     pub fn new(languages: Vec<ProgrammingLanguage>) -> Self {
-        debug!("created a new FullyRandomSampler");
+        Self::new_with_seed(languages, rand::thread_rng().gen())
+    }
+
+    /// Create a new sampler whose random choices are determined by the given seed.
+    pub fn new_with_seed(languages: Vec<ProgrammingLanguage>, seed: u64) -> Self {
+        debug!("created a new MostStarsSampler with seed {seed}");
 
         Self {
+            forge: Arc::new(GitHubForge),
             languages,
-            random: rand::thread_rng(),
+            random: StdRng::seed_from_u64(seed),
             previously_sampled: HashSet::new(),
-            runtime: Rc::new(Runtime::new().unwrap()),
+            filter: RepoFilter::default(),
+            skipped: vec![],
         }
     }
 
+    /// Skip all repositories that do not pass the given filter.
+    pub fn with_filter(mut self, filter: RepoFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sample the repositories from the given forge (by default, GitHub)
+    pub fn with_forge(mut self, forge: Arc<dyn ForgeClient>) -> Self {
+        self.forge = forge;
+        self
+    }
+
     async fn sample_for_language(
         &mut self,
         language: ProgrammingLanguage,
         sample_size: usize,
     ) -> Result<Sample> {
-        info!("sampling for {}", language.0);
-        let query = format!("language:{}", language.0);
-
-        // While sample < sample_size
-        let mut sample = Sample(Vec::with_capacity(sample_size));
-        let mut new_repo_ratio = 1.0;
-        let mut next_page = None;
-        while sample.0.len() < sample_size {
-            let result;
-            if new_repo_ratio > THRESHOLD {
-                // get repos with fresh sample request
-                result = self.run_fresh_query(sample_size, &query).await.map(Some);
-            } else if next_page.is_some() {
-                // else if
-                // get repos from next page
-                result = github::get_page(&next_page).await;
-            } else {
-                // else
-                // return current sample
-                return Ok(sample);
-            }
-            let result = match result {
-                Ok(page) => page,
+        info!("sampling for {} on {}", language.0, self.forge.name());
+
+        // Walk through the pages of the search results until the sample is complete, skipping
+        // repositories that have been sampled before
+        let mut sample = Sample::new(Vec::with_capacity(sample_size));
+        let mut page = 1;
+        while sample.len() < sample_size {
+            let repos = match self.forge.most_starred(&language.0, page).await {
+                Ok(repos) => repos,
                 Err(error) => {
                     error!("was not able to search for repos");
-                    return Err(Error::new(crate::error::ErrorKind::GitHub(error)));
+                    return Err(error);
                 }
             };
-
-            match result {
-                Some(page) => {
-                    next_page.clone_from(&page.next);
-                    let repos = github::collect_repos_from_pages(page, Some(sample_size)).await;
-
-                    let mut new: f64 = 0.;
-                    let num_repos;
-                    match repos {
-                        Some(repos) => {
-                            num_repos = repos.len();
-                            for repo in repos {
-                                if !self.previously_sampled.contains(&repo.id) {
-                                    new += 1.0;
-                                    self.previously_sampled.insert(repo.id);
-                                    sample.0.push(repo);
-                                }
-                            }
-
-                            // We collect a fresh sample, if the number of new repos is above a
-                            // certain THRESHOLD. If it is below this threshold, we instead
-                            // retrieve repos from the next pages in the query.
-                            new_repo_ratio = new / (num_repos as f64);
-                        }
-                        None => return Ok(sample),
+            if repos.is_empty() {
+                break;
+            }
+            for repo in repos {
+                if sample.len() == sample_size {
+                    break;
+                }
+                if !self.previously_sampled.insert(repo.id) {
+                    continue;
+                }
+                match self.filter.skip(&repo) {
+                    Some(skipped) => {
+                        debug!("skipping {}: {:?}", skipped.name, skipped.reason);
+                        self.skipped.push(skipped);
                     }
+                    None => sample.repositories.push(repo),
                 }
-                None => return Ok(sample),
             }
+            page += 1;
             debug!("current sample size: {}", sample.len());
         }
-        let sample = Sample(sample.0.into_iter().take(sample_size).collect());
         info!("sampled {} repos for {}", sample.len(), language.0);
         Ok(sample)
     }
-
-    async fn run_fresh_query(
-        &self,
-        sample_size: usize,
-        query: &str,
-    ) -> std::result::Result<Page<Repository>, octocrab::Error> {
-        // GitHub allows up to 100 results per page
-        let results_per_page = usize::max(sample_size, 100) as u8 /*safe cast*/;
-        let sort = "stars";
-        let order = "desc";
-        debug!("run_fresh_query");
-        github::search_query(query, sort, order, results_per_page).await
-    }
 }
 
+#[async_trait::async_trait]
 impl GitHubSampler for MostStarsSampler {
-    fn sample(&mut self, sample_size: usize) -> Result<Sample> {
-        let runtime = Rc::clone(&self.runtime);
-        let mut sample = Sample(Vec::with_capacity(sample_size * self.languages.len()));
+    async fn sample(&mut self, sample_size: usize) -> Result<Sample> {
+        let mut sample = Sample::new(Vec::with_capacity(sample_size * self.languages.len()));
         for language in self.languages.clone() {
-            let s = runtime.block_on(self.sample_for_language(language, sample_size))?;
-            sample.0.extend(s.0.into_iter());
+            let s = self.sample_for_language(language, sample_size).await?;
+            sample.repositories.extend(s.into_repos());
         }
 
+        sample.skipped.append(&mut self.skipped);
+        sample.capture_metadata(Utc::now());
         // Clear, because a new sample call should start with the initial state
         self.previously_sampled.clear();
         Ok(sample)
     }
-}
-
-impl FallibleIterator for MostStarsSampler {
-    type Item = Repository;
-
-    type Error = crate::Error;
 
-    fn next(&mut self) -> core::result::Result<Option<Self::Item>, Self::Error> {
-        let runtime = Rc::clone(&self.runtime);
+    async fn next(&mut self) -> Result<Option<Repository>> {
         let language_number = self.random.gen_range(0..self.languages.len());
         let language = self.languages[language_number].clone();
 
         // Sample one entry for a randomly selected language
-        let sample = runtime.block_on(self.sample_for_language(language, 1));
-        sample.map(|mut s| s.0.pop())
+        let sample = self.sample_for_language(language, 1).await;
+        sample.map(|mut s| s.repositories.pop())
     }
 }
@@ -0,0 +1,145 @@
+use crate::git::CollectionConfig;
+use crate::search::component::VirtualRepository;
+use crate::search::registry::MethodConfig;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// Overrides of the harvest configuration for a single repository of a sample (see
+/// [crate::sampling::Sample::overrides_of]). Heterogeneous samples, e.g., of monorepos and small
+/// libraries, are rarely served well by one set of parameters.
+///
+/// # Examples
+/// ```yaml
+/// overrides:
+///   # The GitHub id of the repository
+///   2325298:
+///     # Replace the search methods of the harvest
+///     methods:
+///       - name: MessageScan
+///       - name: TraditionalLSH
+///     # Override parameters of the search methods with the given names
+///     params:
+///       TraditionalLSH:
+///         threshold: 0.9
+///     # Only collect the commits of these branches
+///     branches: [master, "linux-*.y"]
+///     # Only search commits that change files below these paths
+///     paths: [drivers/, fs/]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepoOverrides {
+    /// The search methods that replace the methods of the harvest, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub methods: Option<Vec<MethodConfig>>,
+    /// Parameters by method name, e.g., thresholds, that are merged into the parameters of the
+    /// methods with that name: a given parameter replaces the method's parameter with the same key,
+    /// and the method's other parameters are kept
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, HashMap<String, Value>>,
+    /// Globs of the branches whose commits are collected (see CollectionConfig::branches)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<String>,
+    /// Path prefixes to which the searches are restricted. Each method searches the commits of
+    /// each prefix separately (see [crate::search::shard::PathSharding]), unless the method
+    /// configures its own shards.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    /// The components of a monorepo between which each method searches for picks, unless the
+    /// method configures its own components (see [crate::search::component::ComponentSearch])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<VirtualRepository>,
+}
+
+impl RepoOverrides {
+    /// The configurations of the search methods for the repository, based on the given
+    /// configurations of the harvest
+    pub fn method_configs(&self, harvest_configs: &[MethodConfig]) -> Vec<MethodConfig> {
+        let mut configs = self
+            .methods
+            .clone()
+            .unwrap_or_else(|| harvest_configs.to_vec());
+        for config in &mut configs {
+            if let Some(params) = self.params.get(&config.name) {
+                config
+                    .params
+                    .extend(params.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+            if !self.paths.is_empty() && !config.params.contains_key("shard_prefixes") {
+                let prefixes = self.paths.iter().cloned().map(Value::from).collect();
+                config
+                    .params
+                    .insert("shard_prefixes".to_string(), Value::Sequence(prefixes));
+            }
+            if !self.components.is_empty() && !config.params.contains_key("components") {
+                let components = serde_yaml::to_value(&self.components)
+                    .expect("components can always be serialized");
+                config.params.insert("components".to_string(), components);
+            }
+        }
+        configs
+    }
+
+    /// The collection configuration for the repository, based on the given configuration of the
+    /// harvest
+    pub fn collection_config(&self, harvest_config: &CollectionConfig) -> CollectionConfig {
+        let mut config = harvest_config.clone();
+        if !self.branches.is_empty() {
+            config.branches = self.branches.clone();
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::CollectionConfig;
+    use crate::sampling::overrides::RepoOverrides;
+    use crate::search::registry::{MethodConfig, MethodRegistry};
+
+    #[test]
+    fn overrides_are_applied() {
+        let overrides: RepoOverrides = serde_yaml::from_str(
+            r#"
+params:
+  TraditionalLSH:
+    threshold: 0.9
+branches: [main, "release/*"]
+paths: [src/]
+components:
+  - name: lib
+    paths: [lib/]
+  - name: vendored
+    paths: [app/vendor/lib/]
+"#,
+        )
+        .unwrap();
+        let mut lsh = MethodConfig::new("TraditionalLSH");
+        lsh.params = serde_yaml::from_str("{arity: 5, threshold: 0.7}").unwrap();
+        let harvest_configs = [MethodConfig::new("MessageScan"), lsh];
+        let configs = overrides.method_configs(&harvest_configs);
+        assert_eq!(configs.len(), 2);
+        assert_eq!(
+            configs[0].param_strings("shard_prefixes").unwrap(),
+            Some(vec!["src/".to_string()])
+        );
+        assert_eq!(configs[0].param_f64("threshold", 0.5).unwrap(), 0.5);
+        assert!(configs[1].params.contains_key("components"));
+        let methods = MethodRegistry::default().create_all(&configs).unwrap();
+        assert_eq!(methods[1].name(), "TraditionalLSH");
+        assert_eq!(configs[1].param_f64("threshold", 0.5).unwrap(), 0.9);
+        // The parameters that are not overridden are kept
+        assert_eq!(configs[1].param_usize("arity", 8).unwrap(), 5);
+        let config = overrides.collection_config(&CollectionConfig::default());
+        assert_eq!(config.branches, vec!["main", "release/*"]);
+
+        let overrides: RepoOverrides =
+            serde_yaml::from_str("methods: [{name: ExactDiffMatch}]").unwrap();
+        let configs = overrides.method_configs(&harvest_configs);
+        assert_eq!(configs, vec![MethodConfig::new("ExactDiffMatch")]);
+        assert_eq!(
+            overrides.collection_config(&CollectionConfig::default()),
+            CollectionConfig::default()
+        );
+    }
+}
@@ -0,0 +1,601 @@
+use crate::error::{Error, ErrorKind};
+use crate::results::{ResultIndex, INDEX_FILE};
+use crate::Result;
+use firestorm::profile_fn;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Map, Number, Value};
+use serde_yaml::Value as YamlValue;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+/// The version of the formats in which artifacts are written. The version is increased whenever a
+/// format changes in a way that breaks downstream readers (e.g., a field is renamed or removed),
+/// together with the schemas in the `schemas` folder of the crate.
+///
+/// Versioned artifacts record the version with which they were written in their `schema_version`
+/// field. Artifacts of versions before the field was introduced have no version and are only
+/// checked against the schemas.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The parsed schemas of all artifact kinds. They are parsed once, because schemas reference the
+/// definitions of other schemas, which are resolved for each value that is checked against them.
+static SCHEMAS: Lazy<HashMap<Artifact, Value>> = Lazy::new(|| {
+    Artifact::ALL
+        .into_iter()
+        // The shipped schemas are valid JSON, which is checked by the tests
+        .map(|artifact| (artifact, serde_json::from_str(artifact.schema()).unwrap()))
+        .collect()
+});
+
+/// The kinds of serialized artifacts that are written by a harvest, each of which has a JSON
+/// Schema definition (see [Artifact::schema]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Artifact {
+    /// The results of one search method in one repository (see [crate::results::ResultStore]).
+    /// Result files have no version of their own; they are versioned by the manifest.
+    Results,
+    /// The index of a results folder (see [crate::results::ResultIndex])
+    Manifest,
+    /// A sample of repositories (see [crate::sampling::Sample])
+    Sample,
+    /// The summary of a harvest run (see [crate::report::HarvestReport])
+    Report,
+    /// The metrics of a TraditionalLSH run (see [crate::search::methods::lsh::LshDiagnostics])
+    LshDiagnostics,
+    /// The statistics of the TraditionalLSH runs on one repository (see
+    /// [crate::search::methods::lsh::LshMemory])
+    LshMemory,
+    /// The topology and cross-repository results of a harvested fork network (see
+    /// [crate::git::github::NetworkHarvest])
+    Network,
+    /// The reconstructed propagation of the picks of a harvested fork network (see
+    /// [crate::git::github::Propagation])
+    Propagation,
+    /// The machine-readable summary of a run of the binary (see [crate::report::RunSummary])
+    Summary,
+}
+
+impl Artifact {
+    pub const ALL: [Artifact; 9] = [
+        Artifact::Results,
+        Artifact::Manifest,
+        Artifact::Sample,
+        Artifact::Report,
+        Artifact::LshDiagnostics,
+        Artifact::LshMemory,
+        Artifact::Network,
+        Artifact::Propagation,
+        Artifact::Summary,
+    ];
+
+    /// The name of the artifact kind, which is also the prefix of its schema's file name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Artifact::Results => "results",
+            Artifact::Manifest => "manifest",
+            Artifact::Sample => "sample",
+            Artifact::Report => "report",
+            Artifact::LshDiagnostics => "lsh-diagnostics",
+            Artifact::LshMemory => "lsh-memory",
+            Artifact::Network => "network",
+            Artifact::Propagation => "propagation",
+            Artifact::Summary => "summary",
+        }
+    }
+
+    /// The artifact kind with the given name, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|artifact| artifact.name() == name)
+    }
+
+    /// The JSON Schema (draft 2020-12) of the artifact kind, as shipped in the `schemas` folder
+    pub fn schema(&self) -> &'static str {
+        match self {
+            Artifact::Results => include_str!("../schemas/results.schema.json"),
+            Artifact::Manifest => include_str!("../schemas/manifest.schema.json"),
+            Artifact::Sample => include_str!("../schemas/sample.schema.json"),
+            Artifact::Report => include_str!("../schemas/report.schema.json"),
+            Artifact::LshDiagnostics => include_str!("../schemas/lsh-diagnostics.schema.json"),
+            Artifact::LshMemory => include_str!("../schemas/lsh-memory.schema.json"),
+            Artifact::Network => include_str!("../schemas/network.schema.json"),
+            Artifact::Propagation => include_str!("../schemas/propagation.schema.json"),
+            Artifact::Summary => include_str!("../schemas/summary.schema.json"),
+        }
+    }
+}
+
+impl Display for Artifact {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Check the given YAML (or JSON) content against the schema of the given artifact kind, so that
+/// downstream pipelines can fail fast if an artifact was written in another format than the one
+/// they expect.
+///
+/// Validation fails if the artifact records a schema version other than [SCHEMA_VERSION], or if
+/// it does not match the schema. The error lists all violations with the JSON pointers of the
+/// offending values. Unlike deserialization, validation also rejects unknown fields, which
+/// usually indicate that the artifact was written by a newer version.
+pub fn validate(artifact: Artifact, content: &str) -> Result<()> {
+    profile_fn!(validate);
+    let yaml: YamlValue = serde_yaml::from_str(content)?;
+    let value = to_json(yaml).map_err(|error| validation_error(artifact, error))?;
+    if let Some(version) = value.get("schema_version").and_then(Value::as_u64) {
+        if version != SCHEMA_VERSION as u64 {
+            return Err(validation_error(
+                artifact,
+                format!(
+                    "written with schema version {version}, but this version of cherry-harvest \
+                     uses schema version {SCHEMA_VERSION}"
+                ),
+            ));
+        }
+    }
+
+    let schema = &SCHEMAS[&artifact];
+    let mut validator = Validator {
+        root: schema,
+        violations: vec![],
+    };
+    validator.check(schema, &value, "");
+    match validator.violations.is_empty() {
+        true => Ok(()),
+        false => Err(validation_error(artifact, validator.violations.join("; "))),
+    }
+}
+
+/// Validate the artifact in the given file (see [validate])
+pub fn validate_file<P: AsRef<Path>>(artifact: Artifact, path: P) -> Result<()> {
+    let path = path.as_ref();
+    validate(artifact, &fs::read_to_string(path)?).map_err(|error| match error.0 {
+        ErrorKind::Validation(message) => Error::new(ErrorKind::Validation(format!(
+            "{}: {message}",
+            path.display()
+        ))),
+        _ => error,
+    })
+}
+
+/// Validate the manifest of the given results folder and all result files that it lists
+pub fn validate_result_store<P: AsRef<Path>>(folder: P) -> Result<()> {
+    let folder = folder.as_ref();
+    let index_file = folder.join(INDEX_FILE);
+    validate_file(Artifact::Manifest, &index_file)?;
+    let index: ResultIndex = serde_yaml::from_str(&fs::read_to_string(index_file)?)?;
+    for file in index
+        .repositories
+        .values()
+        .flat_map(|entry| entry.methods.values())
+        .filter_map(|method| method.file.as_ref())
+    {
+        validate_file(Artifact::Results, folder.join(file))?;
+    }
+    Ok(())
+}
+
+fn validation_error(artifact: Artifact, message: String) -> Error {
+    Error::new(ErrorKind::Validation(format!(
+        "invalid {artifact} artifact: {message}"
+    )))
+}
+
+/// Convert a YAML value to the JSON data model. Keys of mappings become strings (e.g., the
+/// numeric repository ids of a sample's pins), as they would in a JSON file.
+fn to_json(value: YamlValue) -> std::result::Result<Value, String> {
+    Ok(match value {
+        YamlValue::Null => Value::Null,
+        YamlValue::Bool(b) => Value::Bool(b),
+        YamlValue::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                Value::from(n)
+            } else if let Some(n) = n.as_i64() {
+                Value::from(n)
+            } else {
+                // NaN and infinity cannot be represented in JSON and match no schema type
+                n.as_f64()
+                    .and_then(Number::from_f64)
+                    .map_or(Value::Null, Value::Number)
+            }
+        }
+        YamlValue::String(s) => Value::String(s),
+        YamlValue::Sequence(values) => Value::Array(
+            values
+                .into_iter()
+                .map(to_json)
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        YamlValue::Mapping(mapping) => {
+            let mut map = Map::new();
+            for (key, value) in mapping {
+                let key = match key {
+                    YamlValue::String(s) => s,
+                    YamlValue::Number(n) => n.to_string(),
+                    YamlValue::Bool(b) => b.to_string(),
+                    other => return Err(format!("unsupported mapping key {other:?}")),
+                };
+                map.insert(key, to_json(value)?);
+            }
+            Value::Object(map)
+        }
+        // Enum variants with data, as serialized by serde_yaml
+        YamlValue::Tagged(tagged) => {
+            let mut map = Map::new();
+            map.insert(tagged.tag.to_string(), to_json(tagged.value)?);
+            Value::Object(map)
+        }
+    })
+}
+
+/// The keywords of JSON Schema that the [Validator] checks
+const KEYWORDS: [&str; 11] = [
+    "$ref",
+    "type",
+    "const",
+    "enum",
+    "minimum",
+    "maximum",
+    "pattern",
+    "required",
+    "properties",
+    "additionalProperties",
+    "items",
+];
+/// The keywords of JSON Schema that do not constrain values
+const ANNOTATIONS: [&str; 5] = ["$schema", "$id", "$defs", "title", "description"];
+
+/// Validates values against the subset of JSON Schema that is used by the shipped schemas (see
+/// [KEYWORDS]). `$ref` may point to local definitions and to definitions of other shipped schemas
+/// (e.g., `results.schema.json#/$defs/searchResult`). Other keywords (e.g., `oneOf` or
+/// `patternProperties`) are not silently ignored, but reported as violations, so that a schema
+/// that uses them fails validation instead of accepting values that it should reject.
+struct Validator<'s> {
+    root: &'s Value,
+    violations: Vec<String>,
+}
+
+impl<'s> Validator<'s> {
+    fn check(&mut self, schema: &'s Value, value: &Value, path: &str) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return self.violation(path, "no value is allowed".to_string()),
+            Value::Object(schema) => schema,
+            _ => return self.violation(path, "invalid schema".to_string()),
+        };
+        for keyword in schema.keys() {
+            if !KEYWORDS.contains(&keyword.as_str()) && !ANNOTATIONS.contains(&keyword.as_str()) {
+                self.violation(path, format!("unsupported schema keyword `{keyword}`"));
+            }
+        }
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match reference.split_once('#') {
+                Some(("", pointer)) => match self.root.pointer(pointer) {
+                    Some(definition) => self.check(definition, value, path),
+                    None => self.violation(path, format!("unresolvable reference {reference}")),
+                },
+                Some((file, pointer)) => self.check_external(file, pointer, value, path),
+                None => self.violation(path, format!("unresolvable reference {reference}")),
+            }
+        }
+        if let Some(types) = schema.get("type") {
+            let types: Vec<&str> = match types {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            if !types.iter().any(|t| has_type(value, t)) {
+                // The remaining keywords are meaningless for values of the wrong type
+                return self.violation(
+                    path,
+                    format!("expected {}, found {value}", types.join(" or ")),
+                );
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if value != expected {
+                self.violation(path, format!("expected {expected}, found {value}"));
+            }
+        }
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.contains(value) {
+                self.violation(
+                    path,
+                    format!("{value} is not one of {}", Value::Array(options.clone())),
+                );
+            }
+        }
+        if let (Some(minimum), Some(number)) = (
+            schema.get("minimum").and_then(Value::as_f64),
+            value.as_f64(),
+        ) {
+            if number < minimum {
+                self.violation(path, format!("{number} is less than {minimum}"));
+            }
+        }
+        if let (Some(maximum), Some(number)) = (
+            schema.get("maximum").and_then(Value::as_f64),
+            value.as_f64(),
+        ) {
+            if number > maximum {
+                self.violation(path, format!("{number} is greater than {maximum}"));
+            }
+        }
+        if let (Some(pattern), Some(text)) = (
+            schema.get("pattern").and_then(Value::as_str),
+            value.as_str(),
+        ) {
+            match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(text) => {}
+                Ok(_) => self.violation(path, format!("{value} does not match {pattern}")),
+                Err(error) => self.violation(path, format!("invalid pattern {pattern}: {error}")),
+            }
+        }
+        if let Value::Object(object) = value {
+            self.check_object(schema, object, path);
+        }
+        if let (Some(items), Value::Array(values)) = (schema.get("items"), value) {
+            for (index, item) in values.iter().enumerate() {
+                self.check(items, item, &format!("{path}/{index}"));
+            }
+        }
+    }
+
+    /// Check the value against a definition of another shipped schema (e.g.,
+    /// `results.schema.json#/$defs/searchResult`), whose local references are resolved against
+    /// that schema
+    fn check_external(&mut self, file: &str, pointer: &str, value: &Value, path: &str) {
+        let schema = file
+            .strip_suffix(".schema.json")
+            .and_then(Artifact::from_name)
+            .map(|artifact| &SCHEMAS[&artifact]);
+        match schema.and_then(|schema| Some((schema, schema.pointer(pointer)?))) {
+            Some((schema, definition)) => {
+                let mut validator = Validator {
+                    root: schema,
+                    violations: vec![],
+                };
+                validator.check(definition, value, path);
+                self.violations.append(&mut validator.violations);
+            }
+            None => self.violation(path, format!("unresolvable reference {file}#{pointer}")),
+        }
+    }
+
+    fn check_object(
+        &mut self,
+        schema: &'s Map<String, Value>,
+        object: &Map<String, Value>,
+        path: &str,
+    ) {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for property in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(property) {
+                    self.violation(path, format!("missing property `{property}`"));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let additional = schema.get("additionalProperties");
+        for (key, value) in object {
+            let value_path = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => self.check(property, value, &value_path),
+                None => match additional {
+                    Some(Value::Bool(false)) => {
+                        self.violation(path, format!("unexpected property `{key}`"))
+                    }
+                    Some(additional) => self.check(additional, value, &value_path),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    fn violation(&mut self, path: &str, message: String) {
+        let path = if path.is_empty() { "/" } else { path };
+        self.violations.push(format!("{path}: {message}"));
+    }
+}
+
+fn has_type(value: &Value, schema_type: &str) -> bool {
+    match schema_type {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorKind;
+    use crate::report::{HarvestReport, Policy, RunSummary};
+    use crate::results::{RepoEntry, ResultStore};
+    use crate::sampling::Sample;
+    use crate::schema::{
+        validate, validate_result_store, Artifact, Validator, ANNOTATIONS, KEYWORDS, SCHEMA_VERSION,
+    };
+    use crate::{HarvestRun, RepoId, SearchResult};
+    use std::fs;
+
+    fn violations(artifact: Artifact, content: &str) -> String {
+        match validate(artifact, content) {
+            Err(error) => match error.0 {
+                ErrorKind::Validation(message) => message,
+                other => panic!("unexpected error {other}"),
+            },
+            Ok(()) => panic!("{artifact} artifact is valid"),
+        }
+    }
+
+    #[test]
+    fn schemas_are_valid_json() {
+        for artifact in Artifact::ALL {
+            let schema: serde_json::Value = serde_json::from_str(artifact.schema()).unwrap();
+            assert!(schema["$id"]
+                .as_str()
+                .unwrap()
+                .ends_with(&format!("/v{SCHEMA_VERSION}/{artifact}.schema.json")));
+            assert_eq!(Artifact::from_name(artifact.name()), Some(artifact));
+        }
+    }
+
+    /// The keywords of the given schema and all of its subschemas
+    fn keywords(schema: &serde_json::Value, keywords: &mut Vec<String>) {
+        let Some(schema) = schema.as_object() else {
+            return;
+        };
+        for (keyword, value) in schema {
+            keywords.push(keyword.clone());
+            match keyword.as_str() {
+                "properties" | "$defs" => value
+                    .as_object()
+                    .unwrap()
+                    .values()
+                    .for_each(|subschema| self::keywords(subschema, keywords)),
+                "items" | "additionalProperties" => self::keywords(value, keywords),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn schemas_only_use_supported_keywords() {
+        for artifact in Artifact::ALL {
+            let schema: serde_json::Value = serde_json::from_str(artifact.schema()).unwrap();
+            let mut used = vec![];
+            keywords(&schema, &mut used);
+            for keyword in used {
+                assert!(
+                    KEYWORDS.contains(&keyword.as_str()) || ANNOTATIONS.contains(&keyword.as_str()),
+                    "{artifact} schema uses the unsupported keyword {keyword}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_keywords_are_reported() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "score": {"type": "number", "maximum": 1},
+                "kind": {"oneOf": [{"const": "a"}, {"const": "b"}]}
+            }
+        });
+        let mut validator = Validator {
+            root: &schema,
+            violations: vec![],
+        };
+        validator.check(&schema, &serde_json::json!({"score": 2, "kind": "c"}), "");
+        assert_eq!(
+            validator.violations,
+            vec![
+                "/kind: unsupported schema keyword `oneOf`",
+                "/score: 2 is greater than 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn written_artifacts_are_valid() {
+        let directory = temp_dir::TempDir::new().unwrap();
+        let run = HarvestRun::with_seed(42);
+        let result: SearchResult = serde_yaml::from_str(
+            r#"
+search_method: MessageScan
+cherry_and_target:
+  cherry: {id: a, parent_ids: [], message: "", author: "", committer: "", time: ""}
+  target: {id: b, parent_ids: [a], message: "", author: "", committer: "", time: ""}
+hunk_matches:
+- {cherry_hunk: 0, target_hunk: null, similarity: 0.0}
+conflict: ApplyConflict
+"#,
+        )
+        .unwrap();
+        let mut report = HarvestReport::new(run);
+        report.add_results(std::slice::from_ref(&result));
+        assert_eq!(report.picks["MessageScan"].author_rewriting, 0);
+        let mut store = ResultStore::open(directory.path()).unwrap();
+        let entry = RepoEntry::new(run, Some("Rust".to_string()), None, 2);
+        store
+            .save(
+                &RepoId::github("owner", "repo"),
+                entry,
+                &["MessageScan"],
+                &[result],
+            )
+            .unwrap();
+        validate_result_store(directory.path()).unwrap();
+
+        report.add_failure(
+            &RepoId::github("owner", "broken"),
+            &crate::Error::new(ErrorKind::Panic("boom".to_string())),
+        );
+        report.record_peak_memory();
+        assert_eq!(report.peak_memory.is_some(), cfg!(target_os = "linux"));
+        validate(Artifact::Report, &serde_yaml::to_string(&report).unwrap()).unwrap();
+
+        let mut sample = Sample::new(vec![]);
+        let overrides =
+            serde_yaml::from_str("{params: {TraditionalLSH: {threshold: 0.9}}, branches: [main]}")
+                .unwrap();
+        sample.set_overrides(octocrab::models::RepositoryId(42), overrides);
+        validate(Artifact::Sample, &serde_yaml::to_string(&sample).unwrap()).unwrap();
+
+        let policy = Policy {
+            max_failures: Some(0),
+            ..Policy::default()
+        };
+        let summary = RunSummary::new(&report, policy);
+        assert_eq!(summary.violations.len(), 1);
+        validate(Artifact::Summary, &serde_json::to_string(&summary).unwrap()).unwrap();
+        let error = crate::Error::new(ErrorKind::Configuration("no sample".to_string()));
+        let summary = RunSummary::failed(run, policy, &error);
+        validate(Artifact::Summary, &serde_json::to_string(&summary).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn format_drift_is_detected() {
+        let report = serde_yaml::to_string(&HarvestReport::new(HarvestRun::with_seed(42))).unwrap();
+        validate(Artifact::Report, &report).unwrap();
+
+        let newer = report.replace(
+            &format!("schema_version: {SCHEMA_VERSION}"),
+            "schema_version: 99",
+        );
+        assert!(violations(Artifact::Report, &newer).contains("schema version 99"));
+
+        let renamed = report.replace("harvested:", "succeeded:");
+        let message = violations(Artifact::Report, &renamed);
+        assert!(message.contains("/: missing property `harvested`"));
+        assert!(message.contains("/: unexpected property `succeeded`"));
+
+        let failure = format!("{report}failures:\n- {{repo: a/b, kind: crash, message: m}}\n");
+        let failure = failure.replace("failures: []\n", "");
+        let message = violations(Artifact::Report, &failure);
+        assert_eq!(
+            message,
+            r#"invalid report artifact: /failures/0/kind: "crash" is not one of ["error","panic"]"#
+        );
+
+        // Files are reported with their paths
+        let directory = temp_dir::TempDir::new().unwrap();
+        fs::write(directory.path().join("index.yaml"), "repositories: []\n").unwrap();
+        let error = validate_result_store(directory.path()).unwrap_err();
+        assert!(error.to_string().ends_with(
+            "index.yaml: invalid manifest artifact: /repositories: expected object, found []"
+        ));
+    }
+}
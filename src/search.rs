@@ -1,13 +1,47 @@
-use crate::git::Commit;
+use crate::git::annotation::Annotations;
+use crate::git::github::ForkContext;
+use crate::git::{Commit, DiffStats};
+use crate::search::ancestry::Ancestry;
+use crate::search::combined::Agreement;
+use crate::search::conflict::ConflictEvidence;
+use crate::search::message_diff::MessageDiff;
+use crate::search::methods::lsh::HunkMatch;
+use crate::search::rebase::RebaseRun;
+use crate::search::revert::Revert;
+use chrono::{DateTime, Utc};
 use firestorm::profile_fn;
+use octocrab::models::RepositoryId;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 
+pub mod adequacy;
+pub(crate) mod ancestry;
+pub mod combined;
+pub mod component;
+pub mod conflict;
+pub mod message_diff;
 pub mod methods;
+pub mod metrics;
+pub mod preset;
+pub mod rebase;
+pub mod registry;
+pub mod revert;
+pub mod shard;
+pub mod stored_diff;
 
+pub use combined::CombinedSearch;
+pub use methods::change_id::ChangeIdMatch;
+pub use methods::exact_change::ExactChangeMatch;
 pub use methods::exact_diff::ExactDiffMatch;
 pub use methods::lsh::TraditionalLSH;
 pub use methods::message_scan::MessageScan;
+pub use methods::message_similarity::MessageSimilarity;
+pub use methods::partial_diff::PartialDiffMatch;
+pub use methods::patch_id::PatchIdMatch;
+pub use metrics::{MethodMetrics, SearchCounters};
+pub use preset::SearchPreset;
+pub use stored_diff::{DiffExport, StoredDiff, StoredHunk};
 
 #[derive(Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CherryAndTarget {
@@ -23,6 +57,27 @@ pub struct CommitMetadata {
     author: String,
     committer: String,
     time: String,
+    // The commit time in seconds since the epoch, which results of earlier versions do not record
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+    // The repository in which the commit was found first, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repository: Option<RepositoryId>,
+    // Annotations from external sources (see CommitAnnotator)
+    #[serde(default, skip_serializing_if = "Annotations::is_empty")]
+    annotations: Annotations,
+    // Only known if the commit's diff was extracted during the search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<DiffStats>,
+    // Only known if the commit's diff was extracted during the search
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    directories: Vec<String>,
+    // The parent against which a merge was diffed, if merges are collected once per parent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merge_parent: Option<usize>,
+    // Only stored if requested (see DiffExport)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diff: Option<StoredDiff>,
 }
 
 impl CommitMetadata {
@@ -42,9 +97,69 @@ impl CommitMetadata {
         &self.time
     }
 
+    /// The commit time as UTC timestamp. Results of earlier versions only record the time as
+    /// text (e.g., `Time { time: 1674832148, offset: 60, sign: '+' }`), from which the timestamp
+    /// is parsed. Returns None if the time is unknown.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let seconds = match self.timestamp {
+            Some(seconds) => seconds,
+            None => {
+                let seconds = self.time.split("time: ").nth(1)?;
+                let seconds = seconds
+                    .split(|c: char| !c.is_ascii_digit() && c != '-')
+                    .next()?;
+                seconds.parse().ok()?
+            }
+        };
+        DateTime::from_timestamp(seconds, 0)
+    }
+
     pub fn parent_ids(&self) -> &[String] {
         &self.parent_ids
     }
+
+    /// The id of the repository in which the commit was found first, if known
+    pub fn repository(&self) -> Option<RepositoryId> {
+        self.repository
+    }
+
+    /// The annotations that were attached to the commit during collection
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    /// The number of changed files, inserted lines, and deleted lines. Only known if the diff of
+    /// the commit was extracted, i.e., if at least one search method required diffs (see
+    /// [CommitFacet]).
+    pub fn stats(&self) -> Option<DiffStats> {
+        self.stats
+    }
+
+    /// The directories of the changed files (see [crate::Diff::directories]). Only known if the
+    /// diff of the commit was extracted; empty otherwise.
+    pub fn directories(&self) -> &[String] {
+        &self.directories
+    }
+
+    /// The index of the parent against which the commit was diffed, if it is a merge that was
+    /// collected once per parent (see MergeStrategy::PerParent)
+    pub fn merge_parent(&self) -> Option<usize> {
+        self.merge_parent
+    }
+
+    /// The diff of the commit, whose lines may be redacted. Only stored if the search was
+    /// configured to export diffs (see [DiffExport]).
+    pub fn diff(&self) -> Option<&StoredDiff> {
+        self.diff.as_ref()
+    }
+
+    pub(crate) fn set_diff(&mut self, diff: Option<StoredDiff>) {
+        self.diff = diff;
+    }
+
+    pub(crate) fn set_repository(&mut self, repository: Option<RepositoryId>) {
+        self.repository = repository;
+    }
 }
 
 impl<'r, 'c> From<&Commit<'r, 'c>> for CommitMetadata {
@@ -56,6 +171,13 @@ impl<'r, 'c> From<&Commit<'r, 'c>> for CommitMetadata {
             author: commit.author().to_string(),
             committer: commit.committer().to_string(),
             time: format!("{:?}", commit.time()),
+            timestamp: Some(commit.time().seconds()),
+            repository: None,
+            annotations: commit.annotations().clone(),
+            stats: commit.diff_stats(),
+            directories: commit.changed_directories().unwrap_or_default(),
+            merge_parent: commit.merge_parent(),
+            diff: None,
         }
     }
 }
@@ -63,9 +185,50 @@ impl<'r, 'c> From<&Commit<'r, 'c>> for CommitMetadata {
 // TODO: A commit can only be the target for a cherry-pick once? Or should the library return all possible source-target pairs?
 
 impl CherryAndTarget {
-    /// Construct a new CherryPick for two commits. Cherry and target are determined based on the commit time
+    /// Construct a new CherryPick for two commits. If one of the commits is reachable from the
+    /// other in the commit graph, it existed first and is thus the cherry, regardless of the
+    /// timestamps of the commits (which may be skewed, e.g., by a lagging clock).
+    ///
+    /// Otherwise, the direction is determined by the timestamps: Cherry-picks preserve the author
+    /// (and the author time) of the cherry, but are committed again. Thus, if both commits have the
+    /// same author and only one of them was committed by someone else or at another time than it
+    /// was authored, this commit is the target. Otherwise, cherry and target are determined based
+    /// on the commit time.
     pub fn construct(commit_a: &Commit, commit_b: &Commit) -> Self {
+        Self::construct_by(commit_a, commit_b, is_ancestor)
+    }
+
+    /// Construct a new CherryPick for two commits like [CherryAndTarget::construct], but decide
+    /// their ancestry with the given index, which is shared by all pairs of a result set
+    pub(crate) fn construct_with(
+        ancestry: &Ancestry,
+        commit_a: &Commit,
+        commit_b: &Commit,
+    ) -> Self {
+        Self::construct_by(commit_a, commit_b, |ancestor, descendant| {
+            ancestry.is_ancestor(ancestor, descendant)
+        })
+    }
+
+    fn construct_by<F: Fn(&Commit, &Commit) -> bool>(
+        commit_a: &Commit,
+        commit_b: &Commit,
+        is_ancestor: F,
+    ) -> Self {
         profile_fn!(construct);
+        if is_ancestor(commit_a, commit_b) {
+            return Self::new(commit_a, commit_b);
+        }
+        if is_ancestor(commit_b, commit_a) {
+            return Self::new(commit_b, commit_a);
+        }
+        if same_author(commit_a, commit_b) {
+            match (is_recommitted(commit_a), is_recommitted(commit_b)) {
+                (false, true) => return Self::new(commit_a, commit_b),
+                (true, false) => return Self::new(commit_b, commit_a),
+                _ => {}
+            }
+        }
         if commit_a.time() < commit_b.time() {
             // commit_a is older than commit_b
             Self::new(commit_a, commit_b)
@@ -97,19 +260,117 @@ impl CherryAndTarget {
     pub fn target(&self) -> &CommitMetadata {
         &self.target
     }
+
+    /// Whether cherry and target have the same author
+    pub fn author_preserved(&self) -> bool {
+        self.cherry.author == self.target.author
+    }
+
+    pub(crate) fn cherry_mut(&mut self) -> &mut CommitMetadata {
+        &mut self.cherry
+    }
+
+    pub(crate) fn target_mut(&mut self) -> &mut CommitMetadata {
+        &mut self.target
+    }
+}
+
+/// Whether the first commit is reachable from the second commit in the second commit's
+/// repository. Commits of other repositories (e.g., of forks that were loaded separately) are
+/// never reachable.
+fn is_ancestor(ancestor: &Commit, descendant: &Commit) -> bool {
+    ancestor.id() != descendant.id()
+        && descendant
+            .repository()
+            .graph_descendant_of(descendant.id(), ancestor.id())
+            .unwrap_or(false)
+}
+
+/// Whether both commits have the same author, including the time at which they were authored
+fn same_author(commit_a: &Commit, commit_b: &Commit) -> bool {
+    let (a, b) = (commit_a.author(), commit_b.author());
+    a.name_bytes() == b.name_bytes() && a.email_bytes() == b.email_bytes() && a.when() == b.when()
+}
+
+/// Whether the commit was committed by someone else or at another time than it was authored
+fn is_recommitted(commit: &Commit) -> bool {
+    let (author, committer) = (commit.author(), commit.committer());
+    author.name_bytes() != committer.name_bytes()
+        || author.email_bytes() != committer.email_bytes()
+        || author.when() != committer.when()
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SearchResult {
     search_method: String,
     cherry_and_target: CherryAndTarget,
+    // How the repositories of cherry and target are related in their fork network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fork_context: Option<ForkContext>,
+    // Which hunks of the cherry map to which hunks of the target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hunk_matches: Option<Vec<HunkMatch>>,
+    // The commit that reverted the target on the target's branch, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    revert: Option<Revert>,
+    // Evidence that the pick likely involved a conflict resolution, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    conflict: Option<ConflictEvidence>,
+    // How the target's message differs from the cherry's message, if computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message_diff: Option<MessageDiff>,
+    // The inner methods that found the pick, if it was found by a CombinedSearch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    agreement: Option<Agreement>,
+    // The rebase of which the pick is a part, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rebase: Option<RebaseRun>,
+    // Whether the cherry lives in another repository of the network than the target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cross_repository: Option<bool>,
+    // Whether the target has the same author as the cherry; not recorded by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author_preserved: Option<bool>,
+    // How similar cherry and target are according to the search method, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<Score>,
+}
+
+/// The score of a result, which is serialized as a plain number. Scores are never NaN, so
+/// comparing their bits is sufficient.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Score {}
+
+impl Hash for Score {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
 }
 
 impl SearchResult {
     pub fn new(search_method: String, cherry_ids: CherryAndTarget) -> Self {
         Self {
             search_method,
+            author_preserved: Some(cherry_ids.author_preserved()),
             cherry_and_target: cherry_ids,
+            fork_context: None,
+            hunk_matches: None,
+            revert: None,
+            conflict: None,
+            message_diff: None,
+            agreement: None,
+            rebase: None,
+            score: None,
+            cross_repository: None,
         }
     }
 
@@ -122,6 +383,144 @@ impl SearchResult {
     pub fn commit_pair(&self) -> &CherryAndTarget {
         &self.cherry_and_target
     }
+
+    pub(crate) fn commit_pair_mut(&mut self) -> &mut CherryAndTarget {
+        &mut self.cherry_and_target
+    }
+
+    /// The relationship of the repositories of cherry and target in their fork network, if the
+    /// result has been annotated with it (see ForkNetwork::annotate_results).
+    pub fn fork_context(&self) -> Option<&ForkContext> {
+        self.fork_context.as_ref()
+    }
+
+    pub fn set_fork_context(&mut self, fork_context: Option<ForkContext>) {
+        self.fork_context = fork_context;
+    }
+
+    /// The matches of the cherry's hunks to the target's hunks, if they have been computed for
+    /// this result (see DiffSimilarity::hunk_matches).
+    pub fn hunk_matches(&self) -> Option<&[HunkMatch]> {
+        self.hunk_matches.as_deref()
+    }
+
+    pub fn set_hunk_matches(&mut self, hunk_matches: Option<Vec<HunkMatch>>) {
+        self.hunk_matches = hunk_matches;
+    }
+
+    /// The commit that reverted the target after it was picked, if the result has been checked
+    /// for reverts and the target was reverted (see revert::annotate_reverts).
+    pub fn revert(&self) -> Option<&Revert> {
+        self.revert.as_ref()
+    }
+
+    /// Whether the pick was later reverted on the target's branch
+    pub fn is_reverted(&self) -> bool {
+        self.revert.is_some()
+    }
+
+    pub fn set_revert(&mut self, revert: Option<Revert>) {
+        self.revert = revert;
+    }
+
+    /// The evidence that the pick likely involved a conflict resolution, if the result has been
+    /// checked for conflicts and evidence was found (see conflict::annotate_conflicts, which runs
+    /// during searches if CollectionConfig::annotate_conflicts is set).
+    pub fn conflict(&self) -> Option<ConflictEvidence> {
+        self.conflict
+    }
+
+    /// Whether the pick likely involved a conflict resolution, i.e., was adapted to the target
+    pub fn is_conflicted(&self) -> bool {
+        self.conflict.is_some()
+    }
+
+    pub fn set_conflict(&mut self, conflict: Option<ConflictEvidence>) {
+        self.conflict = conflict;
+    }
+
+    /// The lines that were added to or removed from the cherry's message in the target's message,
+    /// if the result has been annotated (see message_diff::annotate_message_diffs)
+    pub fn message_diff(&self) -> Option<&MessageDiff> {
+        self.message_diff.as_ref()
+    }
+
+    pub fn set_message_diff(&mut self, message_diff: Option<MessageDiff>) {
+        self.message_diff = message_diff;
+    }
+
+    /// The inner methods that agreed on the pick, if it was found by a CombinedSearch
+    pub fn agreement(&self) -> Option<&Agreement> {
+        self.agreement.as_ref()
+    }
+
+    pub fn set_agreement(&mut self, agreement: Option<Agreement>) {
+        self.agreement = agreement;
+    }
+
+    /// The run of consecutive picks of which this pick is a part, if the pick was found to be part
+    /// of a rebased or re-created branch (see rebase::annotate_rebases, which runs during searches
+    /// if CollectionConfig::rebase_min_run is set)
+    pub fn rebase(&self) -> Option<&RebaseRun> {
+        self.rebase.as_ref()
+    }
+
+    /// Whether the pick is part of a rebase rather than an individual cherry pick
+    pub fn is_rebased(&self) -> bool {
+        self.rebase.is_some()
+    }
+
+    pub fn set_rebase(&mut self, rebase: Option<RebaseRun>) {
+        self.rebase = rebase;
+    }
+
+    /// The similarity of cherry and target in the interval `[0, 1]`, as computed by the search
+    /// method that found the result, e.g., the diff similarity for TraditionalLSH. Methods that
+    /// match exactly (e.g., ExactDiffMatch or MessageScan) score their results with 1.0. None, if
+    /// the method does not score its results, or for results of older versions.
+    pub fn score(&self) -> Option<f64> {
+        self.score.map(|score| score.0)
+    }
+
+    pub fn set_score(&mut self, score: Option<f64>) {
+        self.score = score.map(Score);
+    }
+
+    /// Score the result with the given similarity of cherry and target (see SearchResult::score)
+    pub fn with_score(mut self, score: f64) -> Self {
+        self.set_score(Some(score));
+        self
+    }
+
+    /// Whether the cherry was resolved in another repository of the fork network than the one of
+    /// the target, i.e., the target's repository does not contain the cherry. Only known for
+    /// methods that resolve references to cherries (see MessageScan).
+    pub fn cross_repository(&self) -> Option<bool> {
+        self.cross_repository
+    }
+
+    pub fn set_cross_repository(&mut self, cross_repository: Option<bool>) {
+        self.cross_repository = cross_repository;
+    }
+
+    /// Whether the target has the same author as the cherry. Cherry-picks preserve the author,
+    /// whereas picks that rewrite the author were usually ported manually (e.g., by re-applying a
+    /// patch).
+    pub fn author_preserved(&self) -> bool {
+        self.author_preserved
+            .unwrap_or_else(|| self.cherry_and_target.author_preserved())
+    }
+}
+
+/// The commit data that a search method operates on. The search only extracts the data that is
+/// required by at least one of the configured methods, so that searches with metadata-only methods
+/// (e.g., MessageScan) skip the expensive diff extraction entirely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CommitFacet {
+    /// Ids, parents, messages, authors, committers, and times
+    Metadata,
+    /// The metadata and the diff of each commit
+    Diff,
 }
 
 /// Trait for implementing new search methods. This trait is meant to annotate the capabilities of
@@ -178,14 +577,66 @@ pub trait SearchMethod {
     /// The search's name that is to be stored with each SearchResult
     /// TODO: Find a better approach to handling the association of results and search methods
     fn name(&self) -> &'static str;
+
+    /// The commit data that this method operates on. Methods that only require commit metadata
+    /// should override this, so that diffs are not extracted for them.
+    fn required_facet(&self) -> CommitFacet {
+        CommitFacet::Diff
+    }
+
+    /// The numbers of candidates and similarity comparisons of the last search, for methods that
+    /// compare candidate pairs of commits (see [metrics::MethodMetrics]). Methods that match
+    /// commits by exact keys (e.g., ExactDiffMatch) do not compare pairs and return None.
+    fn last_counters(&self) -> Option<SearchCounters> {
+        None
+    }
+
+    /// Searches for cherry picks whose cherry and target are on different sides, i.e., one of them
+    /// is in `left` and the other one is in `right`. Pairs of commits on the same side are never
+    /// reported.
+    ///
+    /// By default, the method searches the union of both sides and drops all results on the same
+    /// side. Methods should override this, if they can skip the matching within each side.
+    fn search_across(&self, left: &mut [Commit], right: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_fn!(search_across);
+        let left_ids: HashSet<String> = left.iter().map(|c| c.id().to_string()).collect();
+        let mut commits: Vec<Commit> = left.iter().chain(right.iter()).cloned().collect();
+        self.search(&mut commits)
+            .into_iter()
+            .filter(|result| {
+                let pair = result.commit_pair();
+                left_ids.contains(pair.cherry().id()) != left_ids.contains(pair.target().id())
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::clock::ManualClock;
+    use crate::git::annotation::Annotations;
+    use crate::git::collect_commits;
+    use crate::git::test_util::{signature_at, TestRepository};
     use crate::search::CommitMetadata;
-    use crate::{CherryAndTarget, SearchResult};
+    use crate::{
+        CherryAndTarget, Commit, ExactDiffMatch, MessageScan, SearchMethod, SearchResult,
+        TraditionalLSH,
+    };
+    use chrono::Duration;
     use std::collections::HashSet;
 
+    /// The messages of the commits of each result, ordered alphabetically
+    fn pairs(results: HashSet<SearchResult>) -> HashSet<(String, String)> {
+        results
+            .into_iter()
+            .map(|r| {
+                let pair = r.commit_pair();
+                let (a, b) = (pair.cherry().message(), pair.target().message());
+                (a.min(b).to_string(), a.max(b).to_string())
+            })
+            .collect()
+    }
+
     #[test]
     fn same_result_same_hash() {
         let create_a = || CommitMetadata {
@@ -195,6 +646,13 @@ mod tests {
             author: "aaa".to_string(),
             committer: "aaa".to_string(),
             time: "aaa".to_string(),
+            timestamp: None,
+            repository: None,
+            annotations: Annotations::new(),
+            stats: None,
+            directories: vec![],
+            merge_parent: None,
+            diff: None,
         };
         let create_b = || CommitMetadata {
             id: "aba".to_string(),
@@ -203,6 +661,13 @@ mod tests {
             author: "aba".to_string(),
             committer: "aba".to_string(),
             time: "aba".to_string(),
+            timestamp: None,
+            repository: None,
+            annotations: Annotations::new(),
+            stats: None,
+            directories: vec![],
+            merge_parent: None,
+            diff: None,
         };
 
         let result_a = SearchResult {
@@ -211,6 +676,16 @@ mod tests {
                 cherry: create_a(),
                 target: create_b(),
             },
+            fork_context: None,
+            hunk_matches: None,
+            revert: None,
+            conflict: None,
+            message_diff: None,
+            agreement: None,
+            rebase: None,
+            score: None,
+            cross_repository: None,
+            author_preserved: None,
         };
 
         let result_b = SearchResult {
@@ -219,6 +694,16 @@ mod tests {
                 cherry: create_a(),
                 target: create_b(),
             },
+            fork_context: None,
+            hunk_matches: None,
+            revert: None,
+            conflict: None,
+            message_diff: None,
+            agreement: None,
+            rebase: None,
+            score: None,
+            cross_repository: None,
+            author_preserved: None,
         };
 
         let mut set = HashSet::new();
@@ -227,4 +712,247 @@ mod tests {
 
         assert_eq!(set.len(), 1);
     }
+
+    #[test]
+    fn older_commits_are_cherries() {
+        let clock = ManualClock::at_timestamp(1674832148);
+        let fixture = TestRepository::new();
+        let files = [("file", "a\n")];
+        let author = clock.signature("author", "author@example.com");
+        fixture.commit_as(&author, "main", &files, &[], "fix");
+        clock.advance(Duration::try_hours(1).unwrap());
+        let porter = clock.signature("porter", "porter@example.com");
+        fixture.commit_as(&porter, "port", &files, &[], "port");
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits = collect_commits(&repos);
+        let by_message = |message: &str| {
+            commits
+                .iter()
+                .find(|c| c.message() == Some(message))
+                .unwrap()
+        };
+        let (original, port) = (by_message("fix"), by_message("port"));
+        // The direction does not depend on the order in which the commits are compared
+        for pair in [
+            CherryAndTarget::construct(original, port),
+            CherryAndTarget::construct(port, original),
+        ] {
+            assert_eq!(pair.cherry().message(), "fix");
+            assert_eq!(pair.target().message(), "port");
+            assert!(!pair.author_preserved());
+        }
+    }
+
+    #[test]
+    fn ancestors_are_cherries_despite_clock_skew() {
+        let clock = ManualClock::at_timestamp(1674832148);
+        let fixture = TestRepository::new();
+        // The descendant is committed with a clock that lags behind by a day
+        let author = clock.signature("author", "author@example.com");
+        let skewed = git2::Signature::new(
+            "porter",
+            "porter@example.com",
+            &git2::Time::new(1674832148 - 86400, 0),
+        )
+        .unwrap();
+        let fix = fixture.commit_as(&author, "main", &[("file", "a\n")], &[], "fix");
+        fixture.commit_as(
+            &skewed,
+            "main",
+            &[("file", "a\n"), ("other", "b\n")],
+            &[fix],
+            "reapply",
+        );
+        // An unrelated commit is still ordered by time
+        clock.advance(Duration::try_hours(1).unwrap());
+        let porter = clock.signature("porter", "porter@example.com");
+        fixture.commit_as(&porter, "port", &[("file", "a\n")], &[], "port");
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits = collect_commits(&repos);
+        let by_message = |message: &str| {
+            commits
+                .iter()
+                .find(|c| c.message() == Some(message))
+                .unwrap()
+        };
+        let (fix, reapply, port) = (by_message("fix"), by_message("reapply"), by_message("port"));
+        for pair in [
+            CherryAndTarget::construct(fix, reapply),
+            CherryAndTarget::construct(reapply, fix),
+        ] {
+            assert_eq!(pair.cherry().message(), "fix");
+            assert_eq!(pair.target().message(), "reapply");
+        }
+        for pair in [
+            CherryAndTarget::construct(reapply, port),
+            CherryAndTarget::construct(port, reapply),
+        ] {
+            assert_eq!(pair.cherry().message(), "reapply");
+            assert_eq!(pair.target().message(), "port");
+        }
+    }
+
+    #[test]
+    fn searches_order_picks_by_ancestry_despite_clock_skew() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_as(&signature_at(1000), "main", &[("file", "a\n")], &[], "base");
+        let fix = fixture.commit_as(
+            &signature_at(2000),
+            "main",
+            &[("file", "a\nb\n")],
+            &[base],
+            "fix",
+        );
+        let revert = fixture.commit_as(
+            &signature_at(3000),
+            "main",
+            &[("file", "a\n")],
+            &[fix],
+            "revert",
+        );
+        // The reapplication is a descendant of the fix, but its clock lags behind
+        fixture.commit_as(
+            &signature_at(500),
+            "main",
+            &[("file", "a\nb\n")],
+            &[revert],
+            "reapply",
+        );
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let results = ExactDiffMatch::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let pair = results.iter().next().unwrap().commit_pair();
+        assert_eq!(pair.cherry().message(), "fix");
+        assert_eq!(pair.target().message(), "reapply");
+    }
+
+    #[test]
+    fn picks_preserve_the_author_of_their_cherry() {
+        let fixture = TestRepository::new();
+        {
+            let base = fixture.commit("main", &[("file", "a\n")], &[], "base");
+            let repository = fixture.repository();
+            let tree = |content: &str| {
+                let mut builder = repository.treebuilder(None).unwrap();
+                let blob = repository.blob(content.as_bytes()).unwrap();
+                builder.insert("file", blob, 0o100644).unwrap();
+                repository.find_tree(builder.write().unwrap()).unwrap()
+            };
+            let signature = |name: &str, time: i64| {
+                git2::Signature::new(
+                    name,
+                    &format!("{name}@example.com"),
+                    &git2::Time::new(time, 0),
+                )
+                .unwrap()
+            };
+            let base = repository.find_commit(base).unwrap();
+            let tree = tree("b\n");
+            let author = signature("author", 2000);
+            let fix = repository
+                .commit(None, &author, &author, "fix", &tree, &[&base])
+                .unwrap();
+            // The pick is committed with a clock that lags behind, so that it appears to be older
+            let pick = repository
+                .commit(
+                    None,
+                    &author,
+                    &signature("maintainer", 1500),
+                    "pick",
+                    &tree,
+                    &[&base],
+                )
+                .unwrap();
+            let port = repository
+                .commit(
+                    None,
+                    &signature("porter", 3000),
+                    &signature("porter", 3000),
+                    "port",
+                    &tree,
+                    &[&base],
+                )
+                .unwrap();
+            for (name, id) in [("fix", fix), ("pick", pick), ("port", port)] {
+                repository
+                    .branch(name, &repository.find_commit(id).unwrap(), true)
+                    .unwrap();
+            }
+        }
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let results = ExactDiffMatch::default().search(&mut commits);
+        assert_eq!(results.len(), 3);
+        let mut directions: Vec<(&str, &str, bool)> = results
+            .iter()
+            .map(|r| {
+                let pair = r.commit_pair();
+                (
+                    pair.cherry().message(),
+                    pair.target().message(),
+                    r.author_preserved(),
+                )
+            })
+            .collect();
+        directions.sort();
+        assert_eq!(
+            directions,
+            vec![
+                ("fix", "pick", true),
+                ("fix", "port", false),
+                ("pick", "port", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn searches_across_sides_skip_pairs_on_the_same_side() {
+        let (left, right) = (TestRepository::new(), TestRepository::new());
+        let original = "fn main() {\n    println!(\"hello\");\n}\n";
+        let fixed = "fn main() {\n    println!(\"hello, world\");\n}\n";
+        let base = left.commit("main", &[("main.rs", original)], &[], "base");
+        let fix = left.commit("fix", &[("main.rs", fixed)], &[base], "fix");
+        // A duplicate of the fix on the same side, which must not be matched with the fix
+        left.commit("again", &[("main.rs", fixed)], &[base], "again");
+        let files = [("main.rs", original), ("README", "readme\n")];
+        let base = right.commit("main", &files, &[], "import");
+        let files = [("main.rs", fixed), ("README", "readme\n")];
+        let message = format!("port\n\n(cherry picked from commit {fix})");
+        right.commit("port", &files, &[base], &message);
+
+        let (_left_dir, left_repo) = left.into_loaded();
+        let (_right_dir, right_repo) = right.into_loaded();
+        let (left_repos, right_repos) = ([left_repo], [right_repo]);
+        let mut left: Vec<Commit> = collect_commits(&left_repos).into_iter().collect();
+        let mut right: Vec<Commit> = collect_commits(&right_repos).into_iter().collect();
+        let port = format!("port\n\n(cherry picked from commit {fix})");
+
+        let exact = ExactDiffMatch::default().search_across(&mut left, &mut right);
+        assert_eq!(
+            pairs(exact),
+            HashSet::from([
+                ("again".to_string(), port.clone()),
+                ("fix".to_string(), port.clone())
+            ])
+        );
+        let scanned = MessageScan::default().search_across(&mut left, &mut right);
+        assert!(scanned
+            .iter()
+            .all(|r| r.cross_repository() == Some(true)
+                && r.commit_pair().cherry().message() == "fix"));
+        assert_eq!(pairs(scanned), HashSet::from([("fix".to_string(), port)]));
+        // The same duplicates are matched within a single side
+        let lsh = TraditionalLSH::new(8, 100, 5, 0.75);
+        assert_eq!(lsh.search_across(&mut left, &mut right).len(), 2);
+        assert_eq!(lsh.search(&mut left).len(), 1);
+    }
 }
@@ -0,0 +1,222 @@
+use crate::git::Commit;
+use crate::search::{CommitFacet, SearchMethod};
+use firestorm::profile_fn;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Corpora with fewer commits are not assessed, because their characteristics are too noisy
+const MIN_COMMITS: usize = 10;
+/// The fraction of empty messages above which message-based methods are likely ineffective
+const MAX_EMPTY_MESSAGES: f64 = 0.5;
+/// The fraction of diffs without textual changes above which diff-based methods are likely
+/// ineffective
+const MAX_EMPTY_DIFFS: f64 = 0.5;
+/// The fraction of lossily decoded diffs above which diff-based methods are likely unreliable
+const MAX_NON_UTF8_DIFFS: f64 = 0.25;
+/// The median number of changed lines below which diff-based methods are likely imprecise
+const MIN_MEDIAN_DIFF_SIZE: usize = 3;
+
+/// The characteristics of a corpus of commits that determine whether search methods can be
+/// effective on it (see [CorpusProfile::assess])
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorpusProfile {
+    pub commits: usize,
+    /// The number of commits whose message is empty or only consists of whitespace
+    pub empty_messages: usize,
+    /// The number of commits whose diff has been calculated; the remaining characteristics only
+    /// consider these commits
+    pub diffs: usize,
+    /// The number of diffs without textual changes (e.g., of binary files or file modes)
+    pub empty_diffs: usize,
+    /// The number of diffs that are not valid UTF-8 and were decoded lossily
+    pub non_utf8_diffs: usize,
+    /// The median number of changed (i.e., inserted and deleted) lines per diff, if any diff has
+    /// been calculated
+    pub median_diff_size: Option<usize>,
+}
+
+/// The reasons for which a search method is likely to be ineffective on a corpus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AdequacyIssue {
+    /// Most messages are empty (e.g., in shallow or stripped mirrors), so that there are no
+    /// references or trailers to match
+    EmptyMessages,
+    /// Most diffs have no textual changes, so that there is nothing to compare
+    EmptyDiffs,
+    /// Many diffs are not valid UTF-8 (e.g., legacy encodings), so that their decoded changes
+    /// are distorted
+    NonUtf8Diffs,
+    /// Typical diffs change so few lines that unrelated commits often have similar diffs
+    SmallDiffs,
+}
+
+/// A warning that a search method is likely to be ineffective on a corpus, which helps to
+/// interpret low (or suspiciously high) result counts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdequacyWarning {
+    pub method: String,
+    pub issue: AdequacyIssue,
+    /// The fraction of affected commits, or the median diff size for [AdequacyIssue::SmallDiffs]
+    pub value: f64,
+}
+
+impl Display for AdequacyWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let percent = self.value * 100.0;
+        match self.issue {
+            AdequacyIssue::EmptyMessages => write!(
+                f,
+                "{} likely ineffective: {percent:.0}% of messages empty (shallow mirror?)",
+                self.method
+            ),
+            AdequacyIssue::EmptyDiffs => write!(
+                f,
+                "{} likely ineffective: {percent:.0}% of diffs without textual changes",
+                self.method
+            ),
+            AdequacyIssue::NonUtf8Diffs => write!(
+                f,
+                "{} likely unreliable: {percent:.0}% of diffs not valid UTF-8",
+                self.method
+            ),
+            AdequacyIssue::SmallDiffs => write!(
+                f,
+                "{} likely imprecise: diffs change a median of {} lines",
+                self.method, self.value
+            ),
+        }
+    }
+}
+
+impl CorpusProfile {
+    /// Profile the given commits. Diffs are only considered for commits whose diff has already
+    /// been calculated.
+    pub fn of(commits: &[Commit]) -> Self {
+        profile_fn!(profile_corpus);
+        let empty_messages = commits
+            .iter()
+            .filter(|c| c.message().is_none_or(|m| m.trim().is_empty()))
+            .count();
+        let diffs: Vec<_> = commits.iter().filter_map(Commit::calculated_diff).collect();
+        let mut sizes: Vec<usize> = diffs
+            .iter()
+            .map(|diff| diff.stats().insertions + diff.stats().deletions)
+            .collect();
+        sizes.sort_unstable();
+        Self {
+            commits: commits.len(),
+            empty_messages,
+            diffs: diffs.len(),
+            empty_diffs: diffs.iter().filter(|diff| diff.hunks.is_empty()).count(),
+            // Invalid UTF-8 is decoded with replacement characters
+            non_utf8_diffs: diffs
+                .iter()
+                .filter(|diff| diff.diff_text().contains(char::REPLACEMENT_CHARACTER))
+                .count(),
+            median_diff_size: sizes.get(sizes.len() / 2).copied(),
+        }
+    }
+
+    /// Assess whether the given methods can be effective on the profiled corpus. Methods that
+    /// only require commit metadata (see [SearchMethod::required_facet]) are assumed to match
+    /// messages, and all other methods are assumed to compare diffs.
+    ///
+    /// Corpora with fewer than ten commits are not assessed.
+    pub fn assess(&self, methods: &[Box<dyn SearchMethod>]) -> Vec<AdequacyWarning> {
+        if self.commits < MIN_COMMITS {
+            return vec![];
+        }
+        let fraction = |count: usize, total: usize| count as f64 / total as f64;
+        let mut warnings = vec![];
+        for method in methods {
+            let mut warn = |issue, value| {
+                warnings.push(AdequacyWarning {
+                    method: method.name().to_string(),
+                    issue,
+                    value,
+                })
+            };
+            match method.required_facet() {
+                CommitFacet::Metadata => {
+                    let empty = fraction(self.empty_messages, self.commits);
+                    if empty > MAX_EMPTY_MESSAGES {
+                        warn(AdequacyIssue::EmptyMessages, empty);
+                    }
+                }
+                CommitFacet::Diff if self.diffs > 0 => {
+                    let empty = fraction(self.empty_diffs, self.diffs);
+                    if empty > MAX_EMPTY_DIFFS {
+                        warn(AdequacyIssue::EmptyDiffs, empty);
+                    }
+                    let non_utf8 = fraction(self.non_utf8_diffs, self.diffs);
+                    if non_utf8 > MAX_NON_UTF8_DIFFS {
+                        warn(AdequacyIssue::NonUtf8Diffs, non_utf8);
+                    }
+                    match self.median_diff_size {
+                        Some(size) if size < MIN_MEDIAN_DIFF_SIZE => {
+                            warn(AdequacyIssue::SmallDiffs, size as f64)
+                        }
+                        _ => {}
+                    }
+                }
+                CommitFacet::Diff => {}
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::TestRepository;
+    use crate::search::adequacy::{AdequacyIssue, CorpusProfile};
+    use crate::{collect_commits, Commit, ExactDiffMatch, MessageScan, SearchMethod};
+
+    #[test]
+    fn inadequate_methods_are_reported() {
+        let fixture = TestRepository::new();
+        let mut parent = None;
+        for i in 0..12 {
+            // Most commits of a stripped mirror have no message
+            let message = if i % 4 == 0 {
+                format!("commit {i}")
+            } else {
+                String::new()
+            };
+            parent = Some(fixture.commit_file("main", &format!("{i}\n"), parent, &message));
+        }
+        let (_directory, repository) = fixture.into_loaded();
+        let loaded_repo = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&loaded_repo).into_iter().collect();
+        commits.iter_mut().for_each(|c| {
+            c.calculate_diff();
+        });
+
+        let profile = CorpusProfile::of(&commits);
+        assert_eq!((profile.commits, profile.empty_messages), (12, 9));
+        assert_eq!(profile.median_diff_size, Some(2));
+        let methods: Vec<Box<dyn SearchMethod>> = vec![
+            Box::<MessageScan>::default(),
+            Box::<ExactDiffMatch>::default(),
+        ];
+        let warnings = profile.assess(&methods);
+        let issues: Vec<(&str, AdequacyIssue)> = warnings
+            .iter()
+            .map(|w| (w.method.as_str(), w.issue))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                ("MessageScan", AdequacyIssue::EmptyMessages),
+                ("ExactDiffMatch", AdequacyIssue::SmallDiffs)
+            ]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "MessageScan likely ineffective: 75% of messages empty (shallow mirror?)"
+        );
+
+        // Small corpora are not assessed
+        assert!(CorpusProfile::of(&commits[..5]).assess(&methods).is_empty());
+    }
+}
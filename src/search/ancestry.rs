@@ -0,0 +1,160 @@
+use crate::git::Commit;
+use firestorm::profile_method;
+use git2::{Oid, Repository};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Decides whether commits are ancestors of each other, for all pairs of a result set.
+///
+/// Asking libgit2 walks the history of the descendant for each pair, which dominates the
+/// construction of large result sets. Instead, the parents and the generation of each commit
+/// (one more than the highest generation of its parents) are indexed once, while the first pair is
+/// decided. A commit can only be an ancestor of commits with a higher generation, so that most
+/// pairs are decided without a walk, and the remaining walks skip all commits whose generation is
+/// lower than the one of the ancestor. Each decision is remembered, too.
+///
+/// The index is keyed by commit id, and is thus shared by all repositories of a fork network: A
+/// commit is indexed with the repository in which it is first seen, which is sound, because the
+/// id of a commit covers the ids of its parents. Commits of different repositories are thus
+/// compared by their ids, too.
+#[derive(Debug, Default)]
+pub(crate) struct Ancestry {
+    generations: RefCell<HashMap<Oid, Generation>>,
+    decisions: RefCell<HashMap<(Oid, Oid), bool>>,
+}
+
+#[derive(Debug)]
+struct Generation {
+    number: u32,
+    parents: Vec<Oid>,
+}
+
+impl Ancestry {
+    /// Whether the first commit is reachable from the second commit, i.e., whether a commit with
+    /// the first commit's id is an ancestor of the second commit. The first commit may thus be a
+    /// commit of another repository, e.g., of the shared history of a fork that was loaded
+    /// separately.
+    pub(crate) fn is_ancestor(&self, ancestor: &Commit, descendant: &Commit) -> bool {
+        profile_method!(is_ancestor);
+        let key = (ancestor.id(), descendant.id());
+        if key.0 == key.1 {
+            return false;
+        }
+        if let Some(decision) = self.decisions.borrow().get(&key) {
+            return *decision;
+        }
+        let decision = self.reachable(ancestor, descendant);
+        self.decisions.borrow_mut().insert(key, decision);
+        decision
+    }
+
+    fn reachable(&self, ancestor: &Commit, descendant: &Commit) -> bool {
+        let target = self.generation(ancestor.repository(), ancestor.id());
+        let start = self.generation(descendant.repository(), descendant.id());
+        if target >= start {
+            return false;
+        }
+        let generations = self.generations.borrow();
+        let mut visited = HashSet::from([descendant.id()]);
+        let mut pending = vec![descendant.id()];
+        while let Some(id) = pending.pop() {
+            // All ancestors of the descendant were indexed with its generation
+            for parent in &generations[&id].parents {
+                if *parent == ancestor.id() {
+                    return true;
+                }
+                if generations[parent].number > target && visited.insert(*parent) {
+                    pending.push(*parent);
+                }
+            }
+        }
+        false
+    }
+
+    /// The generation of the commit with the given id, for which the commit and all of its
+    /// ancestors are indexed. Parents that are missing in the repository (e.g., in shallow
+    /// clones) are treated as if the commit had no parents.
+    fn generation(&self, repository: &Repository, id: Oid) -> u32 {
+        let mut generations = self.generations.borrow_mut();
+        let mut pending = vec![id];
+        // Iterative, because histories are far deeper than the stack
+        while let Some(&id) = pending.last() {
+            if generations.contains_key(&id) {
+                pending.pop();
+                continue;
+            }
+            let parents: Vec<Oid> = repository
+                .find_commit(id)
+                .map(|commit| commit.parent_ids().collect())
+                .unwrap_or_default();
+            let unindexed: Vec<Oid> = parents
+                .iter()
+                .filter(|parent| !generations.contains_key(parent))
+                .copied()
+                .collect();
+            if unindexed.is_empty() {
+                let number = parents
+                    .iter()
+                    .map(|parent| generations[parent].number)
+                    .max()
+                    .map_or(1, |highest| highest + 1);
+                generations.insert(id, Generation { number, parents });
+                pending.pop();
+            } else {
+                pending.extend(unindexed);
+            }
+        }
+        generations[&id].number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::{signature_at, TestRepository};
+    use crate::git::{collect_commits_with_config, CollectionConfig, MergeStrategy};
+    use crate::search::ancestry::Ancestry;
+
+    #[test]
+    fn decisions_agree_with_libgit2() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("main", "a\n", None, "base");
+        let fix = fixture.commit_file("main", "b\n", Some(base), "fix");
+        let follow_up = fixture.commit_file("main", "c\n", Some(fix), "follow-up");
+        let feature = fixture.commit_file("feature", "d\n", Some(base), "feature");
+        fixture.commit("main", &[("file", "e\n")], &[follow_up, feature], "merge");
+        // The clock of the unrelated root lags behind by a day
+        fixture.commit_as(
+            &signature_at(1674832148 - 86400),
+            "orphan",
+            &[("file", "f\n")],
+            &[],
+            "orphan",
+        );
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let config = CollectionConfig {
+            merges: MergeStrategy::FirstParent,
+            ..CollectionConfig::default()
+        };
+        let commits = collect_commits_with_config(&repos, &config);
+        assert_eq!(commits.len(), 6);
+        let ancestry = Ancestry::default();
+        for ancestor in &commits {
+            for descendant in &commits {
+                let expected = ancestor.id() != descendant.id()
+                    && descendant
+                        .repository()
+                        .graph_descendant_of(descendant.id(), ancestor.id())
+                        .unwrap();
+                assert_eq!(
+                    ancestry.is_ancestor(ancestor, descendant),
+                    expected,
+                    "{:?} -> {:?}",
+                    ancestor.message(),
+                    descendant.message()
+                );
+            }
+        }
+    }
+}
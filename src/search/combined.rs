@@ -0,0 +1,253 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::Commit;
+use crate::search::CommitFacet;
+use crate::{SearchMethod, SearchResult};
+use firestorm::profile_method;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub const NAME: &str = "CombinedSearch";
+
+/// How many of the inner methods of a CombinedSearch must find a pick for it to be reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VotingPolicy {
+    /// At least one inner method must find the pick
+    #[default]
+    Any,
+    /// More than half of the inner methods must find the pick
+    Majority,
+    /// All inner methods must find the pick
+    All,
+}
+
+impl VotingPolicy {
+    /// Whether a pick that was found by the given number of inner methods is accepted
+    pub fn accepts(&self, votes: usize, methods: usize) -> bool {
+        match self {
+            VotingPolicy::Any => votes > 0,
+            VotingPolicy::Majority => votes * 2 > methods,
+            VotingPolicy::All => votes == methods,
+        }
+    }
+}
+
+impl Display for VotingPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VotingPolicy::Any => write!(f, "any"),
+            VotingPolicy::Majority => write!(f, "majority"),
+            VotingPolicy::All => write!(f, "all"),
+        }
+    }
+}
+
+impl FromStr for VotingPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(VotingPolicy::Any),
+            "majority" => Ok(VotingPolicy::Majority),
+            "all" => Ok(VotingPolicy::All),
+            _ => Err(Error::new(ErrorKind::Configuration(format!(
+                "unknown voting policy '{s}'; expected any, majority, or all"
+            )))),
+        }
+    }
+}
+
+/// The inner methods of a CombinedSearch that found a pick
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Agreement {
+    /// The names of the inner methods that found the pick, in the order of the inner methods
+    pub methods: Vec<String>,
+    /// The number of inner methods
+    pub total: usize,
+}
+
+impl Agreement {
+    /// The share of the inner methods that found the pick, in the interval `[0, 1]`
+    pub fn confidence(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.methods.len() as f64 / self.total as f64
+        }
+    }
+}
+
+/// CombinedSearch runs several search methods on the same commits and merges their results by
+/// vote. A pick is reported once, if enough of the inner methods found it (see [VotingPolicy]);
+/// the direction of the pick is ignored, so that picks whose cherry and target were swapped by
+/// different methods are merged as well.
+///
+/// Each merged result records which of the inner methods agreed (see SearchResult::agreement), so
+/// that the share of agreeing methods can be reported as confidence. Other data of the result
+/// (e.g., hunk matches) is taken from the result of the first agreeing method.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::prelude::*;
+///
+/// let combined = CombinedSearch::new(
+///     vec![
+///         Box::<MessageScan>::default(),
+///         Box::<ExactDiffMatch>::default(),
+///         Box::<PatchIdMatch>::default(),
+///     ],
+///     VotingPolicy::Majority,
+/// );
+/// assert_eq!(combined.name(), "CombinedSearch");
+/// ```
+pub struct CombinedSearch {
+    methods: Vec<Box<dyn SearchMethod>>,
+    policy: VotingPolicy,
+}
+
+impl CombinedSearch {
+    pub fn new(methods: Vec<Box<dyn SearchMethod>>, policy: VotingPolicy) -> Self {
+        Self { methods, policy }
+    }
+
+    pub fn methods(&self) -> &[Box<dyn SearchMethod>] {
+        &self.methods
+    }
+
+    pub fn policy(&self) -> VotingPolicy {
+        self.policy
+    }
+}
+
+impl SearchMethod for CombinedSearch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        // The results of all methods, grouped by their pair of commits
+        let mut votes: HashMap<(String, String), (SearchResult, Vec<usize>)> = HashMap::new();
+        for (index, method) in self.methods.iter().enumerate() {
+            for result in method.search(commits) {
+                let pair = result.commit_pair();
+                let (a, b) = (pair.cherry().id(), pair.target().id());
+                let key = if a <= b {
+                    (a.to_string(), b.to_string())
+                } else {
+                    (b.to_string(), a.to_string())
+                };
+                let (_, agreeing) = votes.entry(key).or_insert_with(|| (result, vec![]));
+                // Methods may report a pick several times, e.g., in different shards
+                if agreeing.last() != Some(&index) {
+                    agreeing.push(index);
+                }
+            }
+        }
+
+        let total = self.methods.len();
+        let candidates = votes.len();
+        let results: HashSet<SearchResult> = votes
+            .into_values()
+            .filter(|(_, agreeing)| self.policy.accepts(agreeing.len(), total))
+            .map(|(mut result, agreeing)| {
+                result.search_method = NAME.to_string();
+                let methods = agreeing
+                    .into_iter()
+                    .map(|index| self.methods[index].name().to_string())
+                    .collect();
+                result.set_agreement(Some(Agreement { methods, total }));
+                result
+            })
+            .collect();
+        debug!(
+            "{} of {candidates} picks were accepted by the {} of {total} methods",
+            results.len(),
+            self.policy
+        );
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn required_facet(&self) -> CommitFacet {
+        self.methods
+            .iter()
+            .map(|method| method.required_facet())
+            .max()
+            .unwrap_or(CommitFacet::Metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::combined::{CombinedSearch, VotingPolicy};
+    use crate::search::CommitFacet;
+    use crate::{
+        Commit, ExactChangeMatch, ExactDiffMatch, MessageScan, SearchMethod, SearchResult,
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn picks_are_merged_by_vote() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("main", "a\nb\nc\nd\n", None, "base");
+        let fix = fixture.commit_file("main", "a\nB\nc\nd\n", Some(base), "fix");
+        // An exact pick with -x, which is found by all methods
+        let message = format!("fix\n\n(cherry picked from commit {fix})");
+        fixture.commit_file("exact", "a\nB\nc\nd\n", Some(base), &message);
+        // An adapted pick without -x, which is only found by ExactChangeMatch
+        let release = fixture.commit_file("release", "a\nb\nc\n", Some(base), "release");
+        fixture.commit_file("release", "a\nB\nc\n", Some(release), "backport");
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let combined = |policy| {
+            CombinedSearch::new(
+                vec![
+                    Box::<MessageScan>::default(),
+                    Box::<ExactDiffMatch>::default(),
+                    Box::<ExactChangeMatch>::default(),
+                ],
+                policy,
+            )
+        };
+        assert_eq!(
+            combined(VotingPolicy::Any).required_facet(),
+            CommitFacet::Diff
+        );
+
+        // ExactChangeMatch also pairs the exact pick with the backport
+        let results = combined(VotingPolicy::Any).search(&mut commits);
+        assert_eq!(results.len(), 3);
+        let results = combined(VotingPolicy::All).search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result: &SearchResult = results.iter().next().unwrap();
+        assert_eq!(result.search_method(), "CombinedSearch");
+        let agreement = result.agreement().unwrap();
+        assert_eq!(
+            agreement.methods,
+            vec!["MessageScan", "ExactDiffMatch", "ExactChangeMatch"]
+        );
+        assert_eq!(agreement.confidence(), 1.0);
+        let messages: HashSet<&str> = result
+            .commit_pair()
+            .as_vec()
+            .into_iter()
+            .map(|c| c.message().lines().next().unwrap())
+            .collect();
+        assert_eq!(messages, HashSet::from(["fix"]));
+
+        let results = combined(VotingPolicy::Majority).search(&mut commits);
+        assert_eq!(results.len(), 1);
+        assert!("unanimous".parse::<VotingPolicy>().is_err());
+        assert_eq!(
+            "majority".parse::<VotingPolicy>().unwrap(),
+            VotingPolicy::Majority
+        );
+    }
+}
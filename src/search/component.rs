@@ -0,0 +1,181 @@
+use crate::git::{calculate_diffs, Commit};
+use crate::{SearchMethod, SearchResult};
+use firestorm::profile_method;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// The key of the annotation with which the commits of a [VirtualRepository] are annotated
+pub const COMPONENT_ANNOTATION: &str = "component";
+
+/// A component of a monorepo that is analyzed as if it were a repository of its own, such as a
+/// library and the copy of it that is vendored by an application. The commits of a virtual
+/// repository are the commits that change a file below one of its paths, and their diffs are
+/// restricted to these files, whose paths are relative to the path below which they are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualRepository {
+    /// The name of the component, with which its commits are annotated (see
+    /// [COMPONENT_ANNOTATION])
+    pub name: String,
+    /// The path prefixes of the component, e.g., `lib/` or `vendor/lib/`
+    pub paths: Vec<PathBuf>,
+}
+
+impl VirtualRepository {
+    pub fn new<P: Into<PathBuf>, I: IntoIterator<Item = P>>(name: &str, paths: I) -> Self {
+        Self {
+            name: name.to_string(),
+            paths: paths.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The commits of the component among the given commits, whose diffs must have been
+    /// calculated. The returned commits are annotated with the component and their diffs are
+    /// scoped to it.
+    fn commits<'r, 'c>(&self, commits: &[Commit<'r, 'c>]) -> Vec<Commit<'r, 'c>> {
+        commits
+            .iter()
+            .filter_map(|commit| {
+                let mut commit = commit.clone();
+                commit.scope_diff(&self.paths);
+                if commit.diff().hunks.is_empty() {
+                    return None;
+                }
+                commit.annotate(COMPONENT_ANNOTATION.to_string(), self.name.clone());
+                Some(commit)
+            })
+            .collect()
+    }
+}
+
+/// ComponentSearch searches for cherry picks between the components of a monorepo (see
+/// [VirtualRepository]), e.g., for backports between vendored copies of a library. The wrapped
+/// method searches across each pair of components (see [SearchMethod::search_across]), so picks
+/// within a component are never reported. Because the diffs are scoped to the components, a change
+/// that is applied to `lib/src/io.rs` and to `vendor/lib/src/io.rs` has identical diffs.
+///
+/// Commits that change several components cannot be a pick between them and are excluded from the
+/// search across these components. Each result keeps the name of the wrapped method; the
+/// components of its commits are recorded in their annotations.
+pub struct ComponentSearch {
+    method: Box<dyn SearchMethod>,
+    components: Vec<VirtualRepository>,
+}
+
+impl ComponentSearch {
+    pub fn new(method: Box<dyn SearchMethod>, components: Vec<VirtualRepository>) -> Self {
+        Self { method, components }
+    }
+
+    pub fn components(&self) -> &[VirtualRepository] {
+        &self.components
+    }
+}
+
+impl SearchMethod for ComponentSearch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        // The changed paths are only known after the diffs have been calculated
+        calculate_diffs(commits);
+        let components: Vec<Vec<Commit>> = self
+            .components
+            .iter()
+            .map(|component| component.commits(commits))
+            .collect();
+        let mut results = HashSet::new();
+        for (i, left) in components.iter().enumerate() {
+            for (j, right) in components.iter().enumerate().skip(i + 1) {
+                let ids = |commits: &[Commit]| -> HashSet<String> {
+                    commits.iter().map(|c| c.id().to_string()).collect()
+                };
+                let shared: HashSet<String> = &ids(left) & &ids(right);
+                let (mut left, mut right) = (exclusive(left, &shared), exclusive(right, &shared));
+                debug!(
+                    "searching with {} across {} commits of {} and {} commits of {}",
+                    self.method.name(),
+                    left.len(),
+                    self.components[i].name,
+                    right.len(),
+                    self.components[j].name
+                );
+                results.extend(self.method.search_across(&mut left, &mut right));
+            }
+        }
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        self.method.name()
+    }
+}
+
+/// The given commits, except for those with one of the given ids
+fn exclusive<'r, 'c>(commits: &[Commit<'r, 'c>], shared: &HashSet<String>) -> Vec<Commit<'r, 'c>> {
+    commits
+        .iter()
+        .filter(|c| !shared.contains(&c.id().to_string()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::{signature_at, TestRepository};
+    use crate::search::component::{ComponentSearch, VirtualRepository, COMPONENT_ANNOTATION};
+    use crate::{Commit, ExactDiffMatch, SearchMethod};
+    use git2::Oid;
+
+    #[test]
+    fn picks_between_vendored_copies() {
+        let fixture = TestRepository::new();
+        // Commits a tree with the file `file` in each of the given directories
+        let commit = |[lib, vendor]: [&str; 2], parents: &[Oid], message, time| {
+            let files = [("lib/file", lib), ("vendor/file", vendor)];
+            fixture.commit_as(&signature_at(time), "main", &files, parents, message)
+        };
+        let base = commit(["a\n", "a\n"], &[], "base", 1000);
+        let fix = commit(["b\n", "a\n"], &[base], "fix", 2000);
+        let backport = commit(["b\n", "b\n"], &[fix], "backport", 3000);
+        // A commit that changes both components is no pick between them
+        commit(["c\n", "c\n"], &[backport], "update", 4000);
+        let (_directory, repository) = fixture.into_loaded();
+        let loaded = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&loaded).into_iter().collect();
+
+        // Without components, the diffs of the vendored copy differ by their paths
+        let exact = ExactDiffMatch::default();
+        let messages = |results: &[&crate::SearchResult]| {
+            let mut pairs: Vec<_> = results
+                .iter()
+                .map(|r| {
+                    let pair = r.commit_pair();
+                    (
+                        pair.cherry().message().trim().to_string(),
+                        pair.target().message().trim().to_string(),
+                    )
+                })
+                .collect();
+            pairs.sort();
+            pairs
+        };
+        assert!(exact.search(&mut commits).is_empty());
+
+        let components = vec![
+            VirtualRepository::new("lib", ["lib"]),
+            VirtualRepository::new("vendor", ["vendor/"]),
+        ];
+        let search = ComponentSearch::new(Box::new(exact), components);
+        assert_eq!(search.name(), "ExactDiffMatch");
+        let results = search.search(&mut commits);
+        let results: Vec<_> = results.iter().collect();
+        assert_eq!(
+            messages(&results),
+            vec![("fix".to_string(), "backport".to_string())]
+        );
+        let pair = results[0].commit_pair();
+        assert_eq!(pair.cherry().annotations()[COMPONENT_ANNOTATION], "lib");
+        assert_eq!(pair.target().annotations()[COMPONENT_ANNOTATION], "vendor");
+    }
+}
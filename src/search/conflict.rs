@@ -0,0 +1,167 @@
+use crate::git::{Commit, Diff, LineType};
+use crate::SearchResult;
+use firestorm::profile_fn;
+use git2::{Oid, Repository as G2Repository};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Evidence that the target of a cherry pick likely involved a conflict resolution, i.e., that the
+/// cherry had to be adapted to the target
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictEvidence {
+    /// Applying the cherry to the target's parent results in conflicts
+    ApplyConflict,
+    /// The target adds conflict markers (e.g., `<<<<<<< HEAD`) that were committed by accident
+    ConflictMarkers,
+    /// The target's message lists conflicted files, as inserted by git (i.e., `Conflicts:`)
+    ConflictMessage,
+    /// The target's changes are a strict superset of the cherry's changes. Only considered if the
+    /// cherry could not be applied to the target's parent for verification.
+    AdaptedDiff,
+}
+
+/// Whether applying the cherry to the first parent of the target results in conflicts. Returns None
+/// if the result cannot be determined, e.g., because the cherry is not part of the target's
+/// repository or the target has no parent.
+pub fn apply_conflicts(repository: &G2Repository, cherry: Oid, target: Oid) -> Option<bool> {
+    profile_fn!(apply_conflicts);
+    let cherry = repository.find_commit(cherry).ok()?;
+    let onto = repository.find_commit(target).ok()?.parent(0).ok()?;
+    // Merges are picked relative to their first parent
+    let mainline = if cherry.parent_count() > 1 { 1 } else { 0 };
+    let index = repository
+        .cherrypick_commit(&cherry, &onto, mainline, None)
+        .ok()?;
+    Some(index.has_conflicts())
+}
+
+/// Whether the given message lists conflicted files, as inserted by `git cherry-pick` after a
+/// conflict was resolved (i.e., `Conflicts:` followed by the files). Recent git versions comment
+/// the list out, so the list is only found in messages that were not cleaned up.
+fn lists_conflicts(message: &str) -> bool {
+    message
+        .lines()
+        .map(|line| line.trim_start_matches('#').trim())
+        .any(|line| line == "Conflicts:")
+}
+
+/// Whether the given diff adds conflict markers
+fn adds_conflict_markers(diff: &Diff) -> bool {
+    diff.hunks
+        .iter()
+        .flat_map(|h| h.body())
+        .filter(|l| l.line_type() == LineType::Addition)
+        .any(|l| l.content().starts_with("<<<<<<< ") || l.content().starts_with(">>>>>>> "))
+}
+
+/// The lines that are added or deleted by the given diff
+fn changed_lines(diff: &Diff) -> HashSet<(LineType, &str)> {
+    diff.hunks
+        .iter()
+        .flat_map(|h| h.body())
+        .filter(|l| l.line_type() != LineType::Context)
+        .map(|l| (l.line_type(), l.content()))
+        .collect()
+}
+
+/// Annotates each result whose target likely involved a conflict resolution (see
+/// SearchResult::conflict). Conflicted picks are adapted to their target and are thus
+/// qualitatively different from clean picks.
+///
+/// The evidence is checked in the order of ConflictEvidence's variants, and the first matching
+/// evidence is recorded. Diffs are only compared if they have already been calculated during the
+/// search.
+pub fn annotate_conflicts(results: &mut [SearchResult], commits: &[Commit]) {
+    profile_fn!(annotate_conflicts);
+    let commits_by_id: HashMap<String, &Commit> =
+        commits.iter().map(|c| (c.id().to_string(), c)).collect();
+    let mut conflicted = 0;
+    for result in results.iter_mut() {
+        let pair = result.commit_pair();
+        let (Some(cherry), Some(target)) = (
+            commits_by_id.get(pair.cherry().id()),
+            commits_by_id.get(pair.target().id()),
+        ) else {
+            continue;
+        };
+        let applies = apply_conflicts(target.repository(), cherry.id(), target.id());
+        let target_diff = target.calculated_diff();
+        let evidence = if applies == Some(true) {
+            Some(ConflictEvidence::ApplyConflict)
+        } else if target_diff.is_some_and(adds_conflict_markers) {
+            Some(ConflictEvidence::ConflictMarkers)
+        } else if target.message().is_some_and(lists_conflicts) {
+            Some(ConflictEvidence::ConflictMessage)
+        } else if let (None, Some(cherry_diff), Some(target_diff)) =
+            (applies, cherry.calculated_diff(), target_diff)
+        {
+            let cherry_lines = changed_lines(cherry_diff);
+            let target_lines = changed_lines(target_diff);
+            (cherry_lines.len() < target_lines.len() && cherry_lines.is_subset(&target_lines))
+                .then_some(ConflictEvidence::AdaptedDiff)
+        } else {
+            None
+        };
+        if evidence.is_some() {
+            conflicted += 1;
+        }
+        result.set_conflict(evidence);
+    }
+    debug!(
+        "{conflicted} of {} picks were likely conflicted",
+        results.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::conflict::{annotate_conflicts, lists_conflicts, ConflictEvidence};
+    use crate::{CherryAndTarget, Commit, SearchResult};
+    use git2::Oid;
+
+    fn result(commits: &[Commit], cherry: Oid, target: Oid) -> SearchResult {
+        let find = |id| commits.iter().find(|c| c.id() == id).unwrap();
+        SearchResult::new(
+            "TEST".to_string(),
+            CherryAndTarget::new(find(cherry), find(target)),
+        )
+    }
+
+    #[test]
+    fn conflict_messages_are_recognized() {
+        assert!(lists_conflicts(
+            "Fix parser\n\nConflicts:\n\tsrc/parser.rs\n"
+        ));
+        assert!(lists_conflicts(
+            "Fix parser\n\n# Conflicts:\n#\tsrc/parser.rs\n"
+        ));
+        assert!(!lists_conflicts("Fix conflicts in parser"));
+    }
+
+    #[test]
+    fn conflicted_picks_are_annotated() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("base", "a\nb\nc\n", None, "base");
+        let cherry = fixture.commit_file("fix", "a\nB\nc\n", Some(base), "fix");
+        let main = fixture.commit_file("main", "a\nX\nc\n", Some(base), "change");
+        // The cherry changes a line that was also changed on main
+        let conflicted = fixture.commit_file("main", "a\nB\nc\n", Some(main), "fix");
+        let other = fixture.commit_file("other", "a\nb\nc\nd\n", Some(base), "extend");
+        let clean = fixture.commit_file("other", "a\nB\nc\nd\n", Some(other), "fix");
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits: Vec<_> = collect_commits(&repos).into_iter().collect();
+        let mut results = vec![
+            result(&commits, cherry, conflicted),
+            result(&commits, cherry, clean),
+        ];
+        annotate_conflicts(&mut results, &commits);
+        assert_eq!(results[0].conflict(), Some(ConflictEvidence::ApplyConflict));
+        assert!(results[0].is_conflicted());
+        assert_eq!(results[1].conflict(), None);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::SearchResult;
+use firestorm::profile_fn;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+const CHERRY_PICK_MARKER: &str = "(cherry picked from commit ";
+
+/// The lines that were added to or removed from the message of a cherry when it was picked, e.g.,
+/// a `[backport]` prefix, a ticket number, or a note on resolved conflicts
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageDiff {
+    /// The lines of the target's message that are not in the cherry's message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// The lines of the cherry's message that are not in the target's message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl MessageDiff {
+    /// Compute the diff between the messages of a cherry and its target.
+    ///
+    /// The messages are compared line by line after normalization: Leading and trailing
+    /// whitespace and empty lines are ignored, and so is the marker that `git cherry-pick -x`
+    /// inserts (i.e., `(cherry picked from commit SOME_HASH)`), because it is added to every such
+    /// pick. Lines that were moved are reported as removed and added.
+    pub fn new(cherry_message: &str, target_message: &str) -> Self {
+        profile_fn!(message_diff);
+        let cherry = normalized_lines(cherry_message);
+        let target = normalized_lines(target_message);
+
+        // The longest common subsequence of lines, computed from the back so that the diff can be
+        // collected from the front
+        let mut lengths = vec![vec![0usize; target.len() + 1]; cherry.len() + 1];
+        for i in (0..cherry.len()).rev() {
+            for j in (0..target.len()).rev() {
+                lengths[i][j] = if cherry[i] == target[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        let mut diff = Self::default();
+        let (mut i, mut j) = (0, 0);
+        while i < cherry.len() && j < target.len() {
+            if cherry[i] == target[j] {
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                diff.removed.push(cherry[i].to_string());
+                i += 1;
+            } else {
+                diff.added.push(target[j].to_string());
+                j += 1;
+            }
+        }
+        diff.removed
+            .extend(cherry[i..].iter().map(|line| line.to_string()));
+        diff.added
+            .extend(target[j..].iter().map(|line| line.to_string()));
+        diff
+    }
+
+    /// Whether the target's message equals the cherry's message after normalization
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The trimmed, non-empty lines of the given message, without cherry-pick markers
+fn normalized_lines(message: &str) -> Vec<&str> {
+    message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(CHERRY_PICK_MARKER))
+        .collect()
+}
+
+/// Annotates each result with the diff between the messages of its cherry and target (see
+/// SearchResult::message_diff), which supports analyses of how developers adapt messages when they
+/// pick commits. Results whose messages are equal are annotated with an empty diff, so that they
+/// can be told apart from results that have not been annotated.
+pub fn annotate_message_diffs(results: &mut [SearchResult]) {
+    profile_fn!(annotate_message_diffs);
+    let mut adapted = 0;
+    for result in results.iter_mut() {
+        let pair = result.commit_pair();
+        let diff = MessageDiff::new(pair.cherry().message(), pair.target().message());
+        if !diff.is_empty() {
+            adapted += 1;
+        }
+        result.set_message_diff(Some(diff));
+    }
+    debug!(
+        "the messages of {adapted} of {} picks were adapted",
+        results.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::message_diff::MessageDiff;
+
+    #[test]
+    fn adapted_messages_are_diffed() {
+        let cherry = "Fix the overflow in the parser\n\n\
+            The line counter overflowed for huge files.\n\n\
+            Signed-off-by: Jane Doe <jane@example.com>\n";
+        let target = "[backport] Fix the overflow in the parser\n\n\
+            The line counter overflowed for huge files.\n\n\
+            Conflicts:\n    src/parser.rs\n\n\
+            Signed-off-by: Jane Doe <jane@example.com>\n\
+            (cherry picked from commit 6a5c6e1a3b4c)\n";
+        let diff = MessageDiff::new(cherry, target);
+        assert_eq!(diff.removed, vec!["Fix the overflow in the parser"]);
+        assert_eq!(
+            diff.added,
+            vec![
+                "[backport] Fix the overflow in the parser",
+                "Conflicts:",
+                "src/parser.rs"
+            ]
+        );
+
+        // Picks with -x only add the marker, and whitespace is ignored
+        let target = format!("  {cherry}\n(cherry picked from commit 6a5c6e1a3b4c)");
+        assert!(MessageDiff::new(cherry, &target).is_empty());
+        let diff = MessageDiff::new(cherry, "Fix the overflow in the parser\nRefs: #42");
+        assert_eq!(diff.added, vec!["Refs: #42"]);
+        assert_eq!(
+            diff.removed,
+            vec![
+                "The line counter overflowed for huge files.",
+                "Signed-off-by: Jane Doe <jane@example.com>"
+            ]
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod change_id;
+pub mod exact_change;
 pub mod exact_diff;
 pub mod lsh;
 pub mod message_scan;
+pub mod message_similarity;
+pub mod partial_diff;
+pub mod patch_id;
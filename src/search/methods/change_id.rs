@@ -0,0 +1,149 @@
+use crate::git::{calculate_diffs, Commit};
+use crate::search::ancestry::Ancestry;
+use crate::search::methods::lsh::DiffSimilarity;
+use crate::{CherryAndTarget, SearchMethod, SearchResult};
+use firestorm::profile_method;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+pub const NAME: &str = "ChangeIdMatch";
+
+const TRAILER: &str = "Change-Id:";
+
+/// ChangeIdMatch identifies cherry picks by the `Change-Id:` trailers that Gerrit adds to commit
+/// messages (e.g., `Change-Id: I8473b95934b5732ac55d26311a706c9c2bde9940`).
+///
+/// Gerrit keeps the Change-Id of a change when it is cherry-picked to another branch, which makes
+/// Change-Ids the dominant backport mechanism in Android- or Chromium-style projects. Picks in
+/// these projects are usually done without `-x` and are often adapted to the target branch, so
+/// they are neither found by MessageScan nor by ExactDiffMatch.
+///
+/// All commits that share a Change-Id are paired. Because Change-Ids are occasionally reused for
+/// unrelated work (e.g., by copying a message), each pair is only reported if the similarity of
+/// its diffs (see [DiffSimilarity::change_similarity]) reaches the threshold. As for ExactDiffMatch,
+/// the older commit of a pair is considered the cherry.
+pub struct ChangeIdMatch {
+    threshold: f64,
+}
+
+impl Default for ChangeIdMatch {
+    fn default() -> Self {
+        // A shared Change-Id is strong evidence; the threshold only rejects reused Change-Ids
+        Self::new(0.5)
+    }
+}
+
+impl ChangeIdMatch {
+    /// Initialize the search with the lowest diff similarity in the interval `[0, 1]` that a pair
+    /// of commits with the same Change-Id must have
+    pub fn new(similarity_threshold: f64) -> Self {
+        Self {
+            threshold: similarity_threshold,
+        }
+    }
+}
+
+impl SearchMethod for ChangeIdMatch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let start = Instant::now();
+        calculate_diffs(commits);
+        let mut by_change_id: HashMap<&str, Vec<&Commit>> = HashMap::new();
+        for commit in commits.iter() {
+            if let Some(change_id) = commit.message().and_then(change_id) {
+                by_change_id.entry(change_id).or_default().push(commit);
+            }
+        }
+        debug!(
+            "found {} distinct Change-Ids in {} commits",
+            by_change_id.len(),
+            commits.len()
+        );
+
+        let mut similarity_comparator = DiffSimilarity::new();
+        let mut rejected = 0;
+        let ancestry = Ancestry::default();
+        let mut results = HashSet::new();
+        for group in by_change_id.values().filter(|group| group.len() > 1) {
+            for (i, commit_a) in group.iter().enumerate() {
+                for commit_b in group.iter().skip(i + 1) {
+                    if commit_a.id() == commit_b.id() {
+                        continue;
+                    }
+                    let similarity = similarity_comparator.change_similarity(commit_a, commit_b);
+                    if similarity < self.threshold {
+                        rejected += 1;
+                        continue;
+                    }
+                    results.insert(
+                        SearchResult::new(
+                            NAME.to_string(),
+                            CherryAndTarget::construct_with(&ancestry, commit_a, commit_b),
+                        )
+                        .with_score(similarity),
+                    );
+                }
+            }
+        }
+        debug!("rejected {rejected} pairs with dissimilar diffs");
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        NAME
+    }
+}
+
+/// The Change-Id in the trailers of the given message, if any. If a message contains several
+/// Change-Ids (e.g., because commits were squashed), the last one is used, as Gerrit does.
+fn change_id(message: &str) -> Option<&str> {
+    message.lines().rev().find_map(|line| {
+        let id = line.trim().strip_prefix(TRAILER)?.trim();
+        let valid =
+            id.len() == 41 && id.starts_with('I') && id[1..].bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then_some(id)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::methods::change_id::{change_id, ChangeIdMatch};
+    use crate::{Commit, SearchMethod};
+
+    const CHANGE_ID: &str = "I8473b95934b5732ac55d26311a706c9c2bde9940";
+
+    #[test]
+    fn change_ids_are_extracted() {
+        let message = format!("Fix parser\n\nBug: 1234\nChange-Id: {CHANGE_ID}\n");
+        assert_eq!(change_id(&message), Some(CHANGE_ID));
+        assert_eq!(change_id("Fix parser\n\nChange-Id: I1234\n"), None);
+        assert_eq!(change_id("Fix the Change-Id: parser"), None);
+    }
+
+    #[test]
+    fn commits_with_the_same_change_id_are_paired() {
+        let fixture = TestRepository::new();
+        let fix = format!("Fix parser\n\nChange-Id: {CHANGE_ID}");
+        let base = fixture.commit_file("main", "a\nb\nc\nd\n", None, "base");
+        fixture.commit_file("main", "a\nB\nc\nd\n", Some(base), &fix);
+        // The backport is adapted to the release branch
+        let release = fixture.commit_file("release", "a\nb\nc\n", Some(base), "release");
+        fixture.commit_file("release", "a\nB\nc\n", Some(release), &fix);
+        // An unrelated change that reuses the Change-Id
+        fixture.commit_file("other", "x\ny\nz\n", Some(base), &fix);
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let results = ChangeIdMatch::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result = results.into_iter().next().unwrap();
+        assert_eq!(result.search_method(), "ChangeIdMatch");
+        for commit in result.commit_pair().as_vec() {
+            assert!(commit.message().starts_with("Fix parser"));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::git::{calculate_diffs, Commit, LineType};
+use crate::search::ancestry::Ancestry;
+use crate::{CherryAndTarget, SearchMethod, SearchResult};
+use firestorm::{profile_fn, profile_method};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Instant;
+
+pub const NAME: &str = "ExactChangeMatch";
+
+/// The changed lines of a diff, in order, with the files that they change
+type ChangeKey<'c> = Vec<(Option<&'c Path>, LineType, &'c str)>;
+
+/// ExactChangeMatch identifies cherry picks by comparing only the changed lines of commits.
+///
+/// Unlike ExactDiffMatch, the context lines of the diffs are ignored, as is the leading and
+/// trailing whitespace of the changed lines. Thus, ExactChangeMatch also finds picks that were
+/// applied to a different version of the changed files (e.g., on a release branch whose context
+/// differs), as long as the changes themselves were not adapted. The changed files must be the
+/// same.
+///
+/// As for ExactDiffMatch, all pairwise combinations of commits with the same changes are reported,
+/// and the older commit of a pair is considered the cherry.
+#[derive(Default)]
+pub struct ExactChangeMatch();
+
+impl SearchMethod for ExactChangeMatch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let start = Instant::now();
+        calculate_diffs(commits);
+        let mut commit_map: HashMap<ChangeKey, Vec<&Commit>> = HashMap::new();
+        for commit in commits.iter() {
+            let key = change_key(commit);
+            // Commits without changes (e.g., empty merges) are not picks of each other
+            if !key.is_empty() {
+                commit_map.entry(key).or_default().push(commit);
+            }
+        }
+
+        let ancestry = Ancestry::default();
+        let mut results = HashSet::new();
+        for group in commit_map.values().filter(|group| group.len() > 1) {
+            for (i, commit_a) in group.iter().enumerate() {
+                for commit_b in group.iter().skip(i + 1) {
+                    if commit_a.id() != commit_b.id() {
+                        results.insert(
+                            SearchResult::new(
+                                NAME.to_string(),
+                                CherryAndTarget::construct_with(&ancestry, commit_a, commit_b),
+                            )
+                            .with_score(1.0),
+                        );
+                    }
+                }
+            }
+        }
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        NAME
+    }
+}
+
+/// The changed lines of the given commit, whose diff must have been calculated
+fn change_key<'c>(commit: &'c Commit) -> ChangeKey<'c> {
+    profile_fn!(change_key);
+    commit
+        .diff()
+        .hunks
+        .iter()
+        .flat_map(|hunk| {
+            let file = hunk.new_file().as_deref().or(hunk.old_file().as_deref());
+            hunk.body()
+                .iter()
+                .filter(|line| line.line_type() != LineType::Context)
+                .map(move |line| (file, line.line_type(), line.content().trim()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::{Commit, ExactChangeMatch, ExactDiffMatch, SearchMethod};
+
+    #[test]
+    fn picks_with_different_context_are_found() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("main", "a\nb\nc\nd\n", None, "base");
+        fixture.commit_file("main", "a\nB\nc\nd\n", Some(base), "fix");
+        // The release branch lacks the last line, which is part of the fix's context
+        let release = fixture.commit_file("release", "a\nb\nc\n", Some(base), "release");
+        fixture.commit_file("release", "a\n  B\nc\n", Some(release), "pick");
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        assert!(ExactDiffMatch::default().search(&mut commits).is_empty());
+
+        let results = ExactChangeMatch::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result = results.into_iter().next().unwrap();
+        assert_eq!(result.search_method(), "ExactChangeMatch");
+        assert_eq!(result.score(), Some(1.0));
+        assert_eq!(result.commit_pair().target().directories(), ["."]);
+        let mut messages: Vec<&str> = result
+            .commit_pair()
+            .as_vec()
+            .into_iter()
+            .map(|c| c.message())
+            .collect();
+        messages.sort();
+        assert_eq!(messages, vec!["fix", "pick"]);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::git::{Commit, Diff};
+use crate::git::{calculate_diffs, Commit, Diff};
+use crate::search::ancestry::Ancestry;
 use crate::{CherryAndTarget, SearchMethod, SearchResult};
 use firestorm::{profile_fn, profile_method};
 use log::debug;
@@ -31,6 +32,7 @@ impl SearchMethod for ExactDiffMatch {
     fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
         profile_method!(search);
         let start = Instant::now();
+        calculate_diffs(commits);
         // map all commits to a hash of their diff
         let mut commit_map: HashMap<Diff, Vec<&Commit>> = HashMap::new();
         commits.iter_mut().for_each(|commit| {
@@ -41,16 +43,11 @@ impl SearchMethod for ExactDiffMatch {
         });
 
         // then, return results for all entries with more than one commit mapped to them
+        let ancestry = Ancestry::default();
         let results: HashSet<SearchResult> = commit_map
-            .iter()
-            .filter_map(|(_, commits)| {
-                if commits.len() > 1 {
-                    Some(commits)
-                } else {
-                    None
-                }
-            })
-            .flat_map(|commit_vec| build_all_possible_result_pairs(commit_vec))
+            .values()
+            .filter(|commits| commits.len() > 1)
+            .flat_map(|commit_vec| build_all_possible_result_pairs(&ancestry, commit_vec))
             .collect();
         debug!("found {} results in {:?}", results.len(), start.elapsed());
         results
@@ -59,9 +56,42 @@ impl SearchMethod for ExactDiffMatch {
     fn name(&self) -> &'static str {
         NAME
     }
+
+    fn search_across(&self, left: &mut [Commit], right: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search_across);
+        let start = Instant::now();
+        calculate_diffs(left);
+        calculate_diffs(right);
+        // Only the diffs of one side are mapped; the other side only probes the map
+        let mut commit_map: HashMap<&Diff, Vec<&Commit>> = HashMap::new();
+        left.iter().for_each(|commit| {
+            commit_map.entry(commit.diff()).or_default().push(commit);
+        });
+
+        let ancestry = &Ancestry::default();
+        let results: HashSet<SearchResult> = right
+            .iter()
+            .flat_map(|commit| {
+                commit_map
+                    .get(commit.diff())
+                    .into_iter()
+                    .flatten()
+                    .filter(move |other| other.id() != commit.id())
+                    .map(move |other| {
+                        SearchResult::new(
+                            NAME.to_string(),
+                            CherryAndTarget::construct_with(ancestry, other, commit),
+                        )
+                        .with_score(1.0)
+                    })
+            })
+            .collect();
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        results
+    }
 }
 
-fn build_all_possible_result_pairs(commits: &[&Commit]) -> Vec<SearchResult> {
+fn build_all_possible_result_pairs(ancestry: &Ancestry, commits: &[&Commit]) -> Vec<SearchResult> {
     profile_fn!(build_all_possible_result_pairs);
     let mut results = vec![];
     // consider all possible commit pairs in the vector of commits associated with the current diff
@@ -74,10 +104,10 @@ fn build_all_possible_result_pairs(commits: &[&Commit]) -> Vec<SearchResult> {
             }
 
             // create a commit pair whose order depends on the commit time of both commits
-            let commit_pair = CherryAndTarget::construct(commit, other_commit);
+            let commit_pair = CherryAndTarget::construct_with(ancestry, commit, other_commit);
             // debug!("{:#?}", commit_pair);
             // debug!("{:#?} - {:#?}", commit.diff(), other_commit.diff());
-            results.push(SearchResult::new(NAME.to_string(), commit_pair));
+            results.push(SearchResult::new(NAME.to_string(), commit_pair).with_score(1.0));
         }
     }
     results
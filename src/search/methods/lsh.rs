@@ -1,23 +1,45 @@
 mod compare;
+mod diagnostics;
+mod memory;
+mod sketch;
+// Internal, only public for benchmarking
+#[doc(hidden)]
 pub mod preprocessing;
 
-use crate::search::methods::lsh::preprocessing::{preprocess_commits, Signature};
+use crate::calibration::Calibration;
+use crate::git::{calculate_diffs, Diff};
+use crate::hashing::StableHasher;
+use crate::search::ancestry::Ancestry;
+use crate::search::methods::lsh::diagnostics::signature_similarity;
+use crate::search::methods::lsh::memory::LshRun;
+use crate::search::methods::lsh::preprocessing::{preprocess_texts_with_vocabulary, Signature};
+use crate::search::SearchCounters;
 use crate::{CherryAndTarget, Commit, SearchMethod, SearchResult};
-use firestorm::profile_method;
-use log::{debug, info};
+use firestorm::{profile_fn, profile_method};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
 use std::time::Instant;
 
-pub use compare::DiffSimilarity;
+pub use compare::{
+    DiffSimilarity, HunkMatch, Similarity, SimilarityMetrics, DEFAULT_CHANGE_WEIGHT,
+};
+pub use diagnostics::{BucketSummary, CurvePoint, LshDiagnostics};
+pub use memory::LshMemory;
+pub use preprocessing::{Shingling, VocabularyPruning};
+pub use sketch::CountMinSketch;
 
-pub type Band<'a> = &'a [u32];
+pub(crate) type Band<'a> = &'a [u32];
 
 /// Split a given signature into n bands of size `(signature.len() / n_splits)`
 ///
 /// # Panics
 /// This functions panics if the signature cannot be split into bands of equal size (i.e., if the
 /// length of the signature is not dividable by n_splits)
-pub fn split_signature(signature: &Signature, n_splits: usize) -> Vec<Band> {
+pub(crate) fn split_signature(signature: &Signature, n_splits: usize) -> Vec<Band<'_>> {
     assert_eq!(
         signature.len() % n_splits,
         0,
@@ -36,6 +58,21 @@ pub fn split_signature(signature: &Signature, n_splits: usize) -> Vec<Band> {
 
 type ID = usize;
 
+/// The largest number of distinct diffs that are compared pairwise by default, instead of being
+/// indexed (see [TraditionalLSH::with_brute_force_limit])
+pub const DEFAULT_BRUTE_FORCE_LIMIT: usize = 64;
+
+/// How the candidates of a TraditionalLSH search were selected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LshStrategy {
+    /// Diffs whose signatures share a band became candidates
+    #[default]
+    Index,
+    /// All pairs of diffs became candidates, because there were too few diffs to index
+    BruteForce,
+}
+
 /// Implementation of traditional locality-sensitive hashing. This approach tries to find
 /// commits that have highly similar diffs, but do not necessarily have to have the same diff.
 ///
@@ -53,31 +90,53 @@ type ID = usize;
 /// quantities of commits.
 #[derive(Debug)]
 pub struct TraditionalLSH {
+    shingling: Shingling,
     arity: usize,
     signature_size: usize,
     n_bands: usize,
+    /// The number of neighboring buckets that are probed per band (see
+    /// [TraditionalLSH::with_multi_probe])
+    probes: usize,
     threshold: f64,
+    /// The weight of the similarity of the changes (see [DiffSimilarity::with_change_weight])
+    change_weight: f64,
+    hunk_matches: bool,
+    /// The largest number of distinct diffs that are compared pairwise instead of being indexed
+    brute_force_limit: usize,
+    /// The number of largest buckets that are reported, if diagnostics are enabled
+    diagnostics: Option<usize>,
+    last_diagnostics: Mutex<Option<LshDiagnostics>>,
+    /// The file in which the statistics of the runs are persisted, if any
+    memory: Option<PathBuf>,
+    /// Whether the band size was tuned with the statistics of earlier runs
+    tuned: bool,
+    last_memory: Mutex<Option<LshMemory>>,
+    last_counters: Mutex<Option<SearchCounters>>,
+    /// The pruning of rare shingles from the vocabulary, if any
+    pruning: Option<VocabularyPruning>,
+    /// The seed of the vocabulary and MinHash of each search, if the signatures are reproducible
+    seed: Option<u64>,
 }
 
 impl TraditionalLSH {
     /// Initialize the traditional LHS approach with the given parameters:
     /// * arity: Size of the sliding window used for the creation of the signature. This defines the
-    /// size of shingles created during the shingling of a given text. A higher value
-    /// will lead to more strict signatures which in turn will lead to less candidates being found.
-    /// A good value to try out is `8`.
+    ///   size of shingles created during the shingling of a given text. A higher value
+    ///   will lead to more strict signatures which in turn will lead to less candidates being found.
+    ///   A good value to try out is `8`.
     ///
     /// * signature_size: Number of values in each signature vector. A greater number of values
-    /// will improve the chance to find matching candidates, but will negatively impact the runtime.
-    /// A good value to try is `100`.
+    ///   will improve the chance to find matching candidates, but will negatively impact the runtime.
+    ///   A good value to try is `100`.
     ///
     /// * band_size: LHS splits each signatures into sub-vectors (aka. bands) of this size. Smaller bands
-    /// increase the chance of hash conflicts and thus lead to more candidates being found. However, this
-    /// also increases the runtime. The 'signature_size' must be dividable by `band_size`. A good
-    /// value to try is `5` for a signature size of `100`.
+    ///   increase the chance of hash conflicts and thus lead to more candidates being found. However, this
+    ///   also increases the runtime. The 'signature_size' must be dividable by `band_size`. A good
+    ///   value to try is `5` for a signature size of `100`.
     ///
     /// * similarity_threshold: The similarity threshold must have a value in the interval `[0, 1]`.
-    /// It defines the lowest value of similarity a candidate pair must have in order to be considered
-    /// a real match. A good value to start is `0.75`.
+    ///   It defines the lowest value of similarity a candidate pair must have in order to be considered
+    ///   a real match. A good value to start is `0.75`.
     ///
     /// # Panics
     /// This function panics if the signature size cannot be divided by the band size
@@ -94,11 +153,193 @@ impl TraditionalLSH {
             "a signature of length {signature_size} cannot be divided into bands of length {band_size}"
         );
         Self {
+            shingling: Shingling::default(),
             arity,
             signature_size,
             n_bands: signature_size / band_size,
+            probes: 0,
             threshold: similarity_threshold,
+            change_weight: DEFAULT_CHANGE_WEIGHT,
+            hunk_matches: false,
+            brute_force_limit: DEFAULT_BRUTE_FORCE_LIMIT,
+            diagnostics: None,
+            last_diagnostics: Mutex::new(None),
+            memory: None,
+            tuned: false,
+            last_memory: Mutex::new(None),
+            last_counters: Mutex::new(None),
+            pruning: None,
+            seed: None,
+        }
+    }
+
+    /// Initialize the traditional LHS approach with the given parameters (see
+    /// [TraditionalLSH::new]), whose signatures are created with random number generators that are
+    /// seeded with the given seed. Otherwise, the vocabulary and the MinHash of each search are
+    /// randomized anew, so that the candidates, and thus the results, may differ between runs.
+    ///
+    /// # Panics
+    /// This function panics if the signature size cannot be divided by the band size
+    /// (i.e. `signature_size % band_size != 0).
+    pub fn new_with_seed(
+        arity: usize,
+        signature_size: usize,
+        band_size: usize,
+        similarity_threshold: f64,
+        seed: u64,
+    ) -> Self {
+        Self {
+            seed: Some(seed),
+            ..Self::new(arity, signature_size, band_size, similarity_threshold)
+        }
+    }
+
+    /// Seed the signatures with the given seed (see [TraditionalLSH::new_with_seed])
+    pub(crate) fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Split the diffs into shingles of `arity` code tokens instead of characters (see
+    /// [Shingling]), so that diffs that only differ in their indentation or, with anonymized
+    /// tokens, in the names of identifiers have equal signatures. As tokens are longer than
+    /// characters, smaller arities of, e.g., `3` are suitable. Characters are shingled by default.
+    pub fn with_shingling(mut self, shingling: Shingling) -> Self {
+        self.shingling = shingling;
+        self
+    }
+
+    /// Weight the similarity of the changes of two diffs with the given weight, and the
+    /// similarity of their full texts with the remaining weight (see
+    /// [DiffSimilarity::with_change_weight]). Both are weighted equally by default.
+    pub fn with_change_weight(mut self, change_weight: f64) -> Self {
+        self.change_weight = change_weight;
+        self
+    }
+
+    /// Use the similarity threshold and metric weights that were fitted to labeled pairs (see
+    /// [crate::calibration::calibrate])
+    pub fn with_calibration(mut self, calibration: &Calibration) -> Self {
+        self.threshold = calibration.threshold;
+        self.change_weight = calibration.change_weight;
+        self
+    }
+
+    /// Annotate each result with the matches of the cherry's hunks to the target's hunks (see
+    /// [DiffSimilarity::hunk_matches]). Disabled by default.
+    pub fn with_hunk_matches(mut self, hunk_matches: bool) -> Self {
+        self.hunk_matches = hunk_matches;
+        self
+    }
+
+    /// Compare all pairs of distinct diffs instead of indexing their signatures, if a search has
+    /// at most the given number of distinct diffs. For few diffs, creating and banding the
+    /// signatures takes longer than comparing all pairs, which also finds the matches that the
+    /// bands would miss. Defaults to [DEFAULT_BRUTE_FORCE_LIMIT]; a limit of `0` always uses the
+    /// index.
+    pub fn with_brute_force_limit(mut self, max_distinct_diffs: usize) -> Self {
+        self.brute_force_limit = max_distinct_diffs;
+        self
+    }
+
+    /// Also probe the given number of buckets that neighbor the bucket of each band, so that pairs
+    /// whose bands nearly collide become candidates as well. The `i`-th neighboring bucket of a
+    /// band contains the signatures whose bands are equal except for their `i`-th value. Thus,
+    /// pairs of diffs whose bands differ in a single value are found without smaller bands or more
+    /// bands, which would increase the number of candidates of all pairs. At most `band_size - 1`
+    /// buckets are probed per band. Disabled by default, i.e., no neighboring buckets are probed.
+    pub fn with_multi_probe(mut self, probes: usize) -> Self {
+        self.probes = probes;
+        self
+    }
+
+    /// The number of neighboring buckets that are probed per band, which is limited by the band
+    /// size (see [TraditionalLSH::with_multi_probe])
+    pub(crate) fn probes(&self) -> usize {
+        self.probes.min(self.signature_size / self.n_bands - 1)
+    }
+
+    /// Prune the shingles that occur in too few diffs from the vocabulary size that each search
+    /// reports (see [VocabularyPruning] and [LshMemory::vocabulary]). The signatures still contain
+    /// the pruned shingles. Disabled by default, i.e., the vocabulary size counts all shingles.
+    pub fn with_vocabulary_pruning(mut self, pruning: VocabularyPruning) -> Self {
+        self.pruning = Some(pruning);
+        self
+    }
+
+    /// Create a diagnostics report after each search, which lists the given number of largest
+    /// buckets (see [LshDiagnostics]). The report of the last search can be retrieved with
+    /// [TraditionalLSH::diagnostics]. Disabled by default.
+    pub fn with_diagnostics(mut self, top_n_buckets: usize) -> Self {
+        self.diagnostics = Some(top_n_buckets);
+        self
+    }
+
+    /// The diagnostics report of the last search, if diagnostics are enabled
+    pub fn diagnostics(&self) -> Option<LshDiagnostics> {
+        self.last_diagnostics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Persist the statistics of each search in the given file (see [LshMemory]), and use the
+    /// statistics of earlier searches that are already in the file to select the band size. The
+    /// given band size is replaced by the largest band size with which the matches of the earlier
+    /// searches are still found, so that repeated searches of the same repository become faster.
+    /// Statistics that were recorded with another arity, shingling, or signature size are replaced,
+    /// but not used, so the shingling must be selected before. Disabled by default.
+    pub fn with_memory<P: AsRef<Path>>(mut self, path: P) -> Self {
+        let path = path.as_ref();
+        let memory = LshMemory::load(path).unwrap_or_else(|error| {
+            warn!("ignoring the LSH memory in {}: {error}", path.display());
+            None
+        });
+        let band_size = memory
+            .as_ref()
+            .and_then(|m| m.tuned_band_size(self.arity, self.shingling, self.signature_size));
+        if let Some(band_size) = band_size {
+            info!(
+                "using a band size of {band_size} instead of {}, as tuned by {} earlier run(s)",
+                self.signature_size / self.n_bands,
+                memory.as_ref().map_or(0, |m| m.runs)
+            );
+            self.n_bands = self.signature_size / band_size;
+            self.tuned = true;
         }
+        self.memory = Some(path.to_path_buf());
+        self.last_memory = Mutex::new(memory);
+        self
+    }
+
+    /// The statistics of all searches, if a memory is used (see [TraditionalLSH::with_memory])
+    pub fn memory(&self) -> Option<LshMemory> {
+        self.last_memory
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Record the statistics of a search in the memory and persist them
+    fn remember(&self, run: LshRun) {
+        let Some(path) = &self.memory else {
+            return;
+        };
+        let mut last_memory = self
+            .last_memory
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let memory = LshMemory::record(last_memory.as_ref(), run);
+        if let Some(directory) = path.parent() {
+            let _ = std::fs::create_dir_all(directory);
+        }
+        if let Err(error) = memory.write(path) {
+            warn!(
+                "could not write the LSH memory to {}: {error}",
+                path.display()
+            );
+        }
+        *last_memory = Some(memory);
     }
 
     /// Build the hash maps for the different bands. The maps are used to collect all signatures
@@ -154,64 +395,349 @@ impl TraditionalLSH {
         id_pairs
     }
 
-    /// Collect the final matches by comparing the similarities of match candidates
+    /// Collect the candidates from the neighboring buckets of each band (see
+    /// [TraditionalLSH::with_multi_probe]). The buckets are built one at a time, and a bucket is
+    /// identified by the hash of the values of a band except for the perturbed value. Conflicts of
+    /// the hashes only add candidates, which are verified like all other candidates.
+    fn collect_probed_candidates(&self, signatures: &[Signature], id_pairs: &mut HashSet<IdPair>) {
+        profile_method!(collect_probed_candidates);
+        let probes = self.probes();
+        if probes == 0 {
+            return;
+        }
+        let bands: Vec<Vec<Band>> = signatures
+            .iter()
+            .map(|signature| split_signature(signature, self.n_bands))
+            .collect();
+        let before = id_pairs.len();
+        for band in 0..self.n_bands {
+            for perturbed in 0..probes {
+                let mut buckets: HashMap<u64, Vec<ID>> = HashMap::new();
+                for (id, signature_bands) in bands.iter().enumerate() {
+                    let values = signature_bands[band];
+                    let mut hasher = StableHasher::default();
+                    values[..perturbed].hash(&mut hasher);
+                    values[perturbed + 1..].hash(&mut hasher);
+                    buckets.entry(hasher.finish()).or_default().push(id);
+                }
+                for ids in buckets.values() {
+                    for (i, id_a) in ids.iter().enumerate() {
+                        for id_b in &ids[i + 1..] {
+                            id_pairs.insert(IdPair::new(*id_a, *id_b));
+                        }
+                    }
+                }
+            }
+        }
+        debug!(
+            "probed {probes} neighboring buckets per band, which added {} candidates",
+            id_pairs.len() - before
+        );
+    }
+
+    /// Collect the final matches by comparing the similarities of match candidates. The ids of
+    /// the candidates refer to groups of commits with identical diffs. If two groups are similar,
+    /// all pairs of their commits are matches. Commits within the same group are always compared
+    /// as well.
+    ///
+    /// If the commits are split into sides (see [Sides]), pairs of commits on the same side are
+    /// skipped. Returns the results and the number of compared pairs.
     fn build_results(
         &self,
         id_pairs: HashSet<IdPair>,
+        groups: &[Vec<usize>],
         commits: &[Commit],
-    ) -> HashSet<SearchResult> {
+        sides: Sides,
+        mut outcomes: Option<&mut Vec<(ID, ID, bool)>>,
+    ) -> (HashSet<SearchResult>, usize) {
         profile_method!(build_results);
-        let mut similarity_comparator = DiffSimilarity::new();
+        let mut similarity_comparator =
+            DiffSimilarity::new().with_change_weight(self.change_weight);
+        let ancestry = Ancestry::default();
         let mut results = HashSet::new();
+        let mut comparisons = 0;
+        // All commits in a group share the same diff; thus, it suffices to compare the first ones
+        for group in groups
+            .iter()
+            .filter(|group| group.len() > 1 && sides.spans(group, group))
+        {
+            let (commit_a, commit_b) = (&commits[group[0]], &commits[group[1]]);
+            let similarity = similarity_comparator.change_similarity(commit_a, commit_b);
+            comparisons += 1;
+            if similarity > self.threshold {
+                for (i, index_a) in group.iter().enumerate() {
+                    for index_b in &group[i + 1..] {
+                        if sides.across(*index_a, *index_b) {
+                            let (commit_a, commit_b) = (&commits[*index_a], &commits[*index_b]);
+                            self.add_result(
+                                &mut results,
+                                &ancestry,
+                                commit_a,
+                                commit_b,
+                                similarity,
+                            );
+                        }
+                    }
+                }
+            }
+        }
         for IdPair(id_a, id_b) in id_pairs.into_iter() {
-            let commit_a = &commits[id_a];
-            let commit_b = &commits[id_b];
-            if commit_a.id() == commit_b.id() {
+            let (group_a, group_b) = (&groups[id_a], &groups[id_b]);
+            if !sides.spans(group_a, group_b) {
                 continue;
             }
-            if similarity_comparator.change_similarity(commit_a, commit_b) > self.threshold {
-                results.insert(SearchResult::new(
-                    self.name().to_string(),
-                    CherryAndTarget::construct(commit_a, commit_b),
-                ));
+            let (commit_a, commit_b) = (&commits[group_a[0]], &commits[group_b[0]]);
+            let similarity = similarity_comparator.change_similarity(commit_a, commit_b);
+            comparisons += 1;
+            let matched = similarity > self.threshold;
+            if let Some(outcomes) = outcomes.as_mut() {
+                outcomes.push((id_a, id_b, matched));
+            }
+            if matched {
+                for index_a in group_a {
+                    for index_b in group_b {
+                        if sides.across(*index_a, *index_b) {
+                            let (commit_a, commit_b) = (&commits[*index_a], &commits[*index_b]);
+                            self.add_result(
+                                &mut results,
+                                &ancestry,
+                                commit_a,
+                                commit_b,
+                                similarity,
+                            );
+                        }
+                    }
+                }
             }
         }
-        results
+        (results, comparisons)
+    }
+
+    fn add_result(
+        &self,
+        results: &mut HashSet<SearchResult>,
+        ancestry: &Ancestry,
+        commit_a: &Commit,
+        commit_b: &Commit,
+        similarity: Similarity,
+    ) {
+        if commit_a.id() == commit_b.id() {
+            return;
+        }
+        let cherry_and_target = CherryAndTarget::construct_with(ancestry, commit_a, commit_b);
+        let mut result =
+            SearchResult::new(self.name().to_string(), cherry_and_target).with_score(similarity);
+        if self.hunk_matches {
+            let (cherry, target) =
+                match result.commit_pair().cherry().id() == commit_a.id().to_string() {
+                    true => (commit_a, commit_b),
+                    false => (commit_b, commit_a),
+                };
+            result.set_hunk_matches(Some(DiffSimilarity::hunk_matches(
+                cherry.diff(),
+                target.diff(),
+            )));
+        }
+        results.insert(result);
     }
 }
 
-impl SearchMethod for TraditionalLSH {
-    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+/// Group the indices of commits with identical diffs (see [Diff]'s equality). The groups are
+/// ordered by the index of their first commit.
+///
+/// In fork networks, many commits have exact duplicates, which only have to be processed once by
+/// the ANN stage.
+fn group_duplicate_diffs(commits: &[Commit]) -> Vec<Vec<usize>> {
+    profile_fn!(group_duplicate_diffs);
+    let mut group_ids: HashMap<&Diff, usize> = HashMap::with_capacity(commits.len());
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for (index, commit) in commits.iter().enumerate() {
+        let group_id = *group_ids.entry(commit.diff()).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[group_id].push(index);
+    }
+    groups
+}
+
+/// The split of the searched commits into two sides, of which only pairs across the sides are
+/// matched (see [SearchMethod::search_across]). The commits of the left side come first.
+#[derive(Clone, Copy)]
+enum Sides {
+    /// All commits are on the same side; all pairs are matched
+    Single,
+    /// The number of commits on the left side
+    Split(usize),
+}
+
+impl Sides {
+    /// Whether the commits with the given indices are on different sides
+    fn across(&self, index_a: usize, index_b: usize) -> bool {
+        match self {
+            Sides::Single => true,
+            Sides::Split(n_left) => (index_a < *n_left) != (index_b < *n_left),
+        }
+    }
+
+    /// Whether any pair of commits from the given groups is on different sides
+    fn spans(&self, group_a: &[usize], group_b: &[usize]) -> bool {
+        group_a
+            .iter()
+            .any(|a| group_b.iter().any(|b| self.across(*a, *b)))
+    }
+}
+
+impl TraditionalLSH {
+    fn search_sides(&self, commits: &mut [Commit], sides: Sides) -> HashSet<SearchResult> {
         let start = Instant::now();
         info!("initialized traditional LSH approach");
         profile_method!(search_lsh);
-        let signatures = preprocess_commits(commits, self.arity, self.signature_size);
+        calculate_diffs(commits);
+        // Collapse exact duplicates into one representative, which is expanded again afterwards
+        let groups = group_duplicate_diffs(commits);
         debug!(
-            "created {} signatures for {} commits",
-            signatures.len(),
-            commits.len()
+            "collapsed {} commits into {} distinct diffs",
+            commits.len(),
+            groups.len()
         );
+        let strategy = match groups.len() <= self.brute_force_limit {
+            true => {
+                info!("comparing all pairs of {} distinct diffs", groups.len());
+                LshStrategy::BruteForce
+            }
+            false => {
+                info!("indexing {} distinct diffs", groups.len());
+                LshStrategy::Index
+            }
+        };
+        let texts = groups
+            .iter()
+            .map(|group| commits[group[0]].diff().diff_text())
+            .collect::<Vec<&str>>();
+        // Brute force only requires the signatures for the statistics of the run
+        let (signatures, vocabulary) = match strategy == LshStrategy::Index
+            || self.diagnostics.is_some()
+            || self.memory.is_some()
+        {
+            true => preprocess_texts_with_vocabulary(
+                &texts,
+                self.shingling,
+                self.arity,
+                self.signature_size,
+                self.pruning.as_ref(),
+                self.seed,
+            ),
+            false => (vec![], 0),
+        };
+        debug!("created {} signatures", signatures.len());
 
         let band_maps = self.build_band_maps(&signatures);
         debug!("banded all signatures");
+        // The band maps are consumed by the candidate collection
+        let report = self.diagnostics.map(|top_n| {
+            let mut report = LshDiagnostics::from_buckets(self, &band_maps, &texts, top_n);
+            report.strategy = strategy;
+            report
+        });
+        let run = self.memory.as_ref().map(|_| {
+            let mut run = LshRun::from_buckets(self, &band_maps, vocabulary);
+            run.set_strategy(strategy);
+            run
+        });
 
         // Search for pairs
-        let id_pairs = self.collect_candidates(band_maps);
+        let id_pairs = match strategy {
+            LshStrategy::Index => {
+                let mut id_pairs = self.collect_candidates(band_maps);
+                self.collect_probed_candidates(&signatures, &mut id_pairs);
+                id_pairs
+            }
+            LshStrategy::BruteForce => all_pairs(groups.len()),
+        };
         debug!("collected {} candidate pairs", id_pairs.len());
+        let candidates = id_pairs.len();
 
         // Final similarity check
-        let results = self.build_results(id_pairs, commits);
+        let mut outcomes = vec![];
+        let (results, comparisons) = self.build_results(
+            id_pairs,
+            &groups,
+            commits,
+            sides,
+            (report.is_some() || run.is_some()).then_some(&mut outcomes),
+        );
         debug!("found {} results in {:?}", results.len(), start.elapsed());
+        *self
+            .last_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Some(SearchCounters::new(candidates, comparisons));
+
+        if let Some(mut run) = run {
+            run.record_candidates(outcomes.iter().map(|(id_a, id_b, matched)| {
+                (
+                    signature_similarity(&signatures[*id_a], &signatures[*id_b]),
+                    *matched,
+                )
+            }));
+            run.set_elapsed_ms(start.elapsed().as_millis() as u64);
+            self.remember(run);
+        }
+
+        if let Some(mut report) = report {
+            report.record_candidates(&signatures, &outcomes);
+            info!(
+                "LSH diagnostics: {} of {} candidates matched; pairs become likely candidates \
+                 from a similarity of {:.2}",
+                report.matches,
+                report.candidates,
+                report.curve_threshold()
+            );
+            report
+                .hints()
+                .iter()
+                .for_each(|hint| info!("LSH hint: {hint}"));
+            *self
+                .last_diagnostics
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(report);
+        }
         results
     }
+}
+
+impl SearchMethod for TraditionalLSH {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        self.search_sides(commits, Sides::Single)
+    }
+
+    fn search_across(&self, left: &mut [Commit], right: &mut [Commit]) -> HashSet<SearchResult> {
+        let mut commits: Vec<Commit> = left.iter().chain(right.iter()).cloned().collect();
+        self.search_sides(&mut commits, Sides::Split(left.len()))
+    }
 
     fn name(&self) -> &'static str {
         "TraditionalLSH"
     }
+
+    fn last_counters(&self) -> Option<SearchCounters> {
+        *self
+            .last_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// All pairs of the given number of ids
+fn all_pairs(n_ids: usize) -> HashSet<IdPair> {
+    (0..n_ids)
+        .flat_map(|id_a| (id_a + 1..n_ids).map(move |id_b| IdPair(id_a, id_b)))
+        .collect()
 }
 
 /// Represent a pair of ids in which the ids are ordered ascending.
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Debug, Eq, PartialEq, Hash)]
 struct IdPair(ID, ID);
 
 impl IdPair {
@@ -225,7 +751,14 @@ impl IdPair {
 
 #[cfg(test)]
 mod tests {
-    use crate::search::methods::lsh::{split_signature, Band};
+    use crate::git::test_util::TestRepository;
+    use crate::git::{calculate_diffs, clone_or_load, collect_commits};
+    use crate::search::methods::lsh::{
+        group_duplicate_diffs, split_signature, Band, IdPair, LshStrategy,
+    };
+    use crate::{Commit, RepoLocation, SearchMethod, TraditionalLSH};
+    use std::collections::HashSet;
+    use std::env;
     use std::iter::zip;
 
     #[test]
@@ -296,4 +829,151 @@ mod tests {
     fn candidate_check(bands_a: &Vec<Band>, bands_b: &Vec<Band>) -> bool {
         zip(bands_a, bands_b).any(|(band_a, band_b)| band_a == band_b)
     }
+
+    #[test]
+    fn duplicate_diffs_are_grouped() {
+        let location = RepoLocation::Filesystem(env::current_dir().unwrap());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let loaded_repo = [runtime.block_on(clone_or_load(&location)).unwrap()];
+        let mut commits = collect_commits(&loaded_repo)
+            .into_iter()
+            .collect::<Vec<Commit>>();
+        // Duplicate each commit's diff by collecting the commits twice
+        commits.extend(collect_commits(&loaded_repo));
+        calculate_diffs(&mut commits);
+
+        let groups = group_duplicate_diffs(&commits);
+        assert!(groups.len() <= commits.len() / 2);
+        let mut grouped = HashSet::new();
+        for group in &groups {
+            assert!(group.len() >= 2);
+            let diff = commits[group[0]].diff();
+            for index in group {
+                assert_eq!(commits[*index].diff(), diff);
+                assert!(grouped.insert(*index));
+            }
+        }
+        assert_eq!(grouped.len(), commits.len());
+    }
+
+    #[test]
+    fn multi_probe_finds_near_misses() {
+        // No band of the first signature collides with a band of the others
+        let signatures = vec![vec![1, 2, 3, 4], vec![5, 2, 7, 4], vec![1, 9, 3, 8]];
+        let candidates = |lsh: TraditionalLSH| {
+            let mut id_pairs = lsh.collect_candidates(lsh.build_band_maps(&signatures));
+            lsh.collect_probed_candidates(&signatures, &mut id_pairs);
+            id_pairs
+        };
+        assert!(candidates(TraditionalLSH::new(8, 4, 2, 0.5)).is_empty());
+
+        // The bands of the second signature only differ in their first value
+        let lsh = TraditionalLSH::new(8, 4, 2, 0.5).with_multi_probe(1);
+        assert_eq!(candidates(lsh), HashSet::from([IdPair::new(0, 1)]));
+        // The second value of a band of size 2 is never perturbed, which would pair all bands
+        let lsh = TraditionalLSH::new(8, 4, 2, 0.5).with_multi_probe(2);
+        assert_eq!(lsh.probes(), 1);
+        assert_eq!(candidates(lsh), HashSet::from([IdPair::new(0, 1)]));
+    }
+
+    #[test]
+    fn diagnostics_report_buckets_and_candidates() {
+        let location = RepoLocation::Filesystem(env::current_dir().unwrap());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let loaded_repo = [runtime.block_on(clone_or_load(&location)).unwrap()];
+        let mut commits = collect_commits(&loaded_repo)
+            .into_iter()
+            .collect::<Vec<Commit>>();
+
+        let lsh = TraditionalLSH::new(8, 100, 5, 0.75);
+        lsh.search(&mut commits);
+        assert!(lsh.diagnostics().is_none());
+        let lsh = lsh.with_diagnostics(3);
+        let results = lsh.search(&mut commits);
+        let report = lsh.diagnostics().unwrap();
+
+        assert_eq!((report.band_size, report.n_bands), (5, 20));
+        // Each distinct diff is stored in one bucket per band
+        let stored: usize = report.bucket_sizes.iter().map(|(s, n)| s * n).sum();
+        assert_eq!(stored, report.distinct_diffs * report.n_bands);
+        assert!(report.largest_buckets.len() <= 3);
+        assert!(report
+            .largest_buckets
+            .windows(2)
+            .all(|b| b[0].size >= b[1].size));
+        assert!(report.matches <= report.candidates);
+        assert!(report.matches <= results.len());
+        let binned: usize = report.s_curve.iter().map(|p| p.candidates).sum();
+        assert_eq!(binned, report.candidates);
+        assert!(report
+            .s_curve
+            .windows(2)
+            .all(|p| p[0].candidate_probability <= p[1].candidate_probability));
+        assert_eq!(report.candidate_probability(1.0), 1.0);
+        assert!((report.curve_threshold() - 0.5493).abs() < 0.001);
+        let mut probed = report.clone();
+        probed.probes = 2;
+        assert!(probed.candidate_probability(0.5) > report.candidate_probability(0.5));
+        assert_eq!(probed.candidate_probability(1.0), 1.0);
+        let written = serde_yaml::to_string(&report).unwrap();
+        crate::schema::validate(crate::schema::Artifact::LshDiagnostics, &written).unwrap();
+    }
+
+    #[test]
+    fn memory_tunes_later_searches() {
+        let fixture = TestRepository::new();
+        let lines: Vec<String> = (0..20).map(|i| format!("line {i}\n")).collect();
+        let content = |replaced: &[(usize, &str)]| {
+            let mut lines = lines.clone();
+            for (index, line) in replaced {
+                lines[*index] = line.to_string();
+            }
+            lines.concat()
+        };
+        let base = fixture.commit_file("main", &content(&[]), None, "base");
+        fixture.commit_file("main", &content(&[(5, "fixed 5\n")]), Some(base), "fix");
+        let release = fixture.commit_file(
+            "release",
+            &content(&[(15, "release\n")]),
+            Some(base),
+            "release",
+        );
+        // The pick was adapted, so that its diff is not an exact duplicate
+        let pick = content(&[(5, "fixed 5\n"), (7, "adapted 7\n"), (15, "release\n")]);
+        fixture.commit_file("release", &pick, Some(release), "pick");
+        let (directory, repository) = fixture.into_loaded();
+        let loaded_repo = [repository];
+        let mut commits = collect_commits(&loaded_repo)
+            .into_iter()
+            .collect::<Vec<Commit>>();
+
+        let memory_file = directory.path().join("memory/lsh.yaml");
+        let lsh = TraditionalLSH::new_with_seed(8, 100, 1, 0.5, 42).with_memory(&memory_file);
+        let results = lsh.search(&mut commits);
+        assert!(!results.is_empty());
+        let memory = lsh.memory().unwrap();
+        assert_eq!((memory.runs, memory.band_size, memory.tuned), (1, 1, false));
+        // The few diffs were compared pairwise
+        assert_eq!(memory.strategy, LshStrategy::BruteForce);
+        assert!(memory.matches > 0);
+        assert!(memory.vocabulary > 0);
+        assert!(memory_file.exists());
+
+        // The next search uses larger bands, with which the same picks are found
+        let lsh = TraditionalLSH::new_with_seed(8, 100, 1, 0.5, 42).with_memory(&memory_file);
+        assert_eq!(lsh.search(&mut commits), results);
+        let memory = lsh.memory().unwrap();
+        assert_eq!(memory.runs, 2);
+        assert!(memory.tuned);
+        assert!(memory.band_size > 1);
+        let written = serde_yaml::to_string(&memory).unwrap();
+        crate::schema::validate(crate::schema::Artifact::LshMemory, &written).unwrap();
+
+        // The index finds the same picks
+        let lsh = TraditionalLSH::new_with_seed(8, 100, 1, 0.5, 42)
+            .with_diagnostics(1)
+            .with_brute_force_limit(0);
+        assert_eq!(lsh.search(&mut commits), results);
+        assert_eq!(lsh.diagnostics().unwrap().strategy, LshStrategy::Index);
+    }
 }
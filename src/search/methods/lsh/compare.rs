@@ -1,11 +1,63 @@
-use crate::git::LineType;
+use crate::git::{Hunk, LineType};
 use crate::{Commit, Diff};
 use firestorm::{profile_fn, profile_method};
 use git2::Oid;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
 pub type Similarity = f64;
 
+/// The weight of the similarity of only the changes in the combined similarity of two diffs; the
+/// similarity of the full diff texts receives the remaining weight
+pub const DEFAULT_CHANGE_WEIGHT: f64 = 0.5;
+
+/// The individual metrics from which the similarity of two diffs is combined
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SimilarityMetrics {
+    /// The Jaccard similarity of the added and deleted lines
+    pub changes: Similarity,
+    /// The Jaccard similarity of all lines, including context lines
+    pub diff: Similarity,
+}
+
+impl SimilarityMetrics {
+    /// The weighted mean of the metrics, in which the changes receive the given weight in `[0, 1]`
+    pub fn combine(&self, change_weight: f64) -> Similarity {
+        change_weight * self.changes + (1.0 - change_weight) * self.diff
+    }
+}
+
+/// Maps a hunk of a cherry to the most similar hunk of its target. Hunks are identified by their
+/// index in the hunks of the respective diff.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HunkMatch {
+    pub cherry_hunk: usize,
+    /// The most similar hunk of the target, or None if no hunk of the target shares any line
+    /// with the cherry's hunk
+    pub target_hunk: Option<usize>,
+    pub similarity: Similarity,
+}
+
+// Similarities are never NaN, so comparing their bits is sufficient
+impl PartialEq for HunkMatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.cherry_hunk == other.cherry_hunk
+            && self.target_hunk == other.target_hunk
+            && self.similarity.to_bits() == other.similarity.to_bits()
+    }
+}
+
+impl Eq for HunkMatch {}
+
+impl Hash for HunkMatch {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cherry_hunk.hash(state);
+        self.target_hunk.hash(state);
+        self.similarity.to_bits().hash(state);
+    }
+}
+
 #[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
 struct CountedLine<'a> {
     content: &'a str,
@@ -19,9 +71,18 @@ struct UncountedLine<'a> {
     line_type: LineType,
 }
 
-#[derive(Default)]
 pub struct DiffSimilarity<'a> {
     counted_lines: HashMap<Oid, HashSet<CountedLine<'a>>>,
+    change_weight: f64,
+}
+
+impl Default for DiffSimilarity<'_> {
+    fn default() -> Self {
+        Self {
+            counted_lines: HashMap::new(),
+            change_weight: DEFAULT_CHANGE_WEIGHT,
+        }
+    }
 }
 
 impl<'a> DiffSimilarity<'a> {
@@ -29,8 +90,16 @@ impl<'a> DiffSimilarity<'a> {
         Self::default()
     }
 
-    /// Calculate the mean Jaccard similarity for the changes and the full diff text for the two
-    /// given commits. Thereby, the metric accounts for the similarity of only the changes, but
+    /// Weight the similarity of the changes with the given weight in `[0, 1]` and the similarity
+    /// of the full diffs with the remaining weight, instead of weighting both equally (e.g., with
+    /// a weight that was fitted by [crate::calibration::calibrate])
+    pub fn with_change_weight(mut self, change_weight: f64) -> Self {
+        self.change_weight = change_weight;
+        self
+    }
+
+    /// Calculate the (by default, equally weighted) mean Jaccard similarity for the changes and the
+    /// full diff text for the two given commits. Thereby, the metric accounts for the similarity of only the changes, but
     /// also takes the similarity of context lines into account, which is important in the case
     /// of very simple changes, such as insertions of empty lines.
     ///
@@ -40,6 +109,12 @@ impl<'a> DiffSimilarity<'a> {
     /// how often this line has been observed.
     pub fn change_similarity(&mut self, commit_a: &'a Commit, commit_b: &'a Commit) -> Similarity {
         profile_method!(change_similarity);
+        self.metrics(commit_a, commit_b).combine(self.change_weight)
+    }
+
+    /// Calculate the individual metrics of the similarity of the two given commits (see
+    /// [DiffSimilarity::change_similarity])
+    pub fn metrics(&mut self, commit_a: &'a Commit, commit_b: &'a Commit) -> SimilarityMetrics {
         self.counted_lines
             .entry(commit_a.id())
             .or_insert_with(|| Self::counted_lines(commit_a.diff()));
@@ -49,33 +124,78 @@ impl<'a> DiffSimilarity<'a> {
 
         let diff_lines_a = self.counted_lines.get(&commit_a.id()).unwrap();
         let diff_lines_b = self.counted_lines.get(&commit_b.id()).unwrap();
-        Self::diff_similarity(diff_lines_a, diff_lines_b)
+        Self::diff_metrics(diff_lines_a, diff_lines_b)
+    }
+
+    /// Map each hunk of the cherry's diff to the most similar hunk of the target's diff. The
+    /// similarity of two hunks is calculated in the same way as the similarity of two diffs (see
+    /// [DiffSimilarity::change_similarity]).
+    ///
+    /// The returned matches are ordered by the index of the cherry's hunks. Hunks of the cherry
+    /// that were not transferred to the target (e.g., in a partial cherry-pick) have a low
+    /// similarity or no target hunk at all.
+    pub fn hunk_matches(cherry: &Diff, target: &Diff) -> Vec<HunkMatch> {
+        profile_fn!(hunk_matches);
+        let target_lines = target
+            .hunks
+            .iter()
+            .map(|hunk| Self::counted_hunk_lines([hunk]))
+            .collect::<Vec<HashSet<CountedLine>>>();
+
+        cherry
+            .hunks
+            .iter()
+            .enumerate()
+            .map(|(cherry_hunk, hunk)| {
+                let cherry_lines = Self::counted_hunk_lines([hunk]);
+                let best = target_lines
+                    .iter()
+                    .map(|lines| Self::diff_similarity(&cherry_lines, lines))
+                    .enumerate()
+                    .filter(|(_, similarity)| *similarity > 0.0)
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+                HunkMatch {
+                    cherry_hunk,
+                    target_hunk: best.map(|(index, _)| index),
+                    similarity: best.map_or(0.0, |(_, similarity)| similarity),
+                }
+            })
+            .collect()
     }
 
     fn diff_similarity(
         diff_lines_a: &HashSet<CountedLine>,
         diff_lines_b: &HashSet<CountedLine>,
     ) -> Similarity {
-        profile_method!(diff_similarity);
+        Self::diff_metrics(diff_lines_a, diff_lines_b).combine(DEFAULT_CHANGE_WEIGHT)
+    }
+
+    fn diff_metrics(
+        diff_lines_a: &HashSet<CountedLine>,
+        diff_lines_b: &HashSet<CountedLine>,
+    ) -> SimilarityMetrics {
+        profile_method!(diff_metrics);
         let changes_a = Self::extract_changes(diff_lines_a);
         let changes_b = Self::extract_changes(diff_lines_b);
 
-        let intersection_size_changes = changes_a.intersection(&changes_b).count() as f64;
-        let union_size_changes = changes_a.union(&changes_b).count() as f64;
-        let intersection_size_diff = diff_lines_a.intersection(diff_lines_b).count() as f64;
-        let union_size_diff = diff_lines_a.union(diff_lines_b).count() as f64;
+        SimilarityMetrics {
+            changes: jaccard(&changes_a, &changes_b),
+            diff: jaccard(diff_lines_a, diff_lines_b),
+        }
+    }
 
-        let jaccard_changes = intersection_size_changes / union_size_changes;
-        let jaccard_diff = intersection_size_diff / union_size_diff;
-        (jaccard_changes + jaccard_diff) / 2.0
+    fn counted_lines(diff: &Diff) -> HashSet<CountedLine<'_>> {
+        Self::counted_hunk_lines(&diff.hunks)
     }
 
-    fn counted_lines(diff: &Diff) -> HashSet<CountedLine> {
+    fn counted_hunk_lines<'h, I: IntoIterator<Item = &'h Hunk>>(
+        hunks: I,
+    ) -> HashSet<CountedLine<'h>> {
         profile_fn!(extract_changes);
         let mut change_count: HashMap<UncountedLine, usize> = HashMap::new();
 
-        diff.hunks
-            .iter()
+        hunks
+            .into_iter()
             .flat_map(|h| h.body())
             // Append the line type prefix to the line
             .map(|l| UncountedLine {
@@ -115,6 +235,15 @@ impl<'a> DiffSimilarity<'a> {
     }
 }
 
+/// The Jaccard similarity of two sets. Two empty sets have a similarity of 0.
+fn jaccard<T: Eq + Hash>(set_a: &HashSet<T>, set_b: &HashSet<T>) -> Similarity {
+    let union_size = set_a.union(set_b).count();
+    if union_size == 0 {
+        return 0.0;
+    }
+    set_a.intersection(set_b).count() as f64 / union_size as f64
+}
+
 #[cfg(test)]
 mod tests {
     use crate::git::IdeaPatch;
@@ -215,6 +344,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn hunks_of_cherry_and_pick_match() {
+        init();
+        let matches = DiffSimilarity::hunk_matches(&cherry_a(), &pick_a());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].cherry_hunk, 0);
+        assert_eq!(matches[0].target_hunk, Some(0));
+        assert!(matches[0].similarity > 0.5);
+
+        let matches = DiffSimilarity::hunk_matches(&cherry_b(), &pick_b());
+        assert_eq!(matches[0].target_hunk, Some(0));
+        assert!(matches[0].similarity > 0.99999);
+    }
+
     #[test]
     fn non_cherries_not_similar() {
         init();
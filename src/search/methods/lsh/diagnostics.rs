@@ -0,0 +1,274 @@
+use crate::journal::{serialize_atomic, Format};
+use crate::schema::SCHEMA_VERSION;
+use crate::search::methods::lsh::preprocessing::{Shingling, Signature};
+use crate::search::methods::lsh::{Band, LshStrategy, TraditionalLSH, ID};
+use crate::Result;
+use firestorm::profile_fn;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// The number of similarity bins of the S-curve
+const CURVE_BINS: usize = 10;
+/// The maximum number of bucket members whose shingles are sampled
+const SAMPLED_MEMBERS: usize = 20;
+/// The number of shingles that are sampled per bucket
+const SAMPLED_SHINGLES: usize = 5;
+
+/// A diagnostics report of a TraditionalLSH run (see [TraditionalLSH::with_diagnostics]). The
+/// report shows how the signatures were distributed over the buckets of the band maps, and how
+/// many of the resulting candidates turned out to be matches, so that users can see why they get
+/// too many or too few candidates and how to adjust the parameters.
+///
+/// The probability that two diffs with a Jaccard similarity `s` become candidates is
+/// `1 - (1 - s^r)^b` for `b` bands of `r` values each (the S-curve of LSH). If `p` neighboring
+/// buckets are probed per band (see [TraditionalLSH::with_multi_probe]), a band also collides if
+/// one of its first `p` values differs, i.e., with a probability of `s^r + p * (1 - s) * s^(r-1)`.
+/// Each point of the S-curve compares this estimate with the candidates that were actually
+/// observed in its similarity bin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LshDiagnostics {
+    /// The schema version with which the report was written (see [crate::schema])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub arity: usize,
+    pub signature_size: usize,
+    /// The number of signature values per band (`r`)
+    pub band_size: usize,
+    /// The number of bands (`b`)
+    pub n_bands: usize,
+    /// The number of neighboring buckets that were probed per band (`p`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub probes: usize,
+    pub threshold: f64,
+    /// How the candidates were selected. If all pairs were compared, the buckets did not select
+    /// the candidates.
+    #[serde(default)]
+    pub strategy: LshStrategy,
+    /// The number of distinct diffs that were hashed; exact duplicates are hashed only once
+    pub distinct_diffs: usize,
+    /// The number of buckets per bucket size, over the buckets of all bands
+    pub bucket_sizes: BTreeMap<usize, usize>,
+    /// The largest buckets, ordered by size
+    pub largest_buckets: Vec<BucketSummary>,
+    /// The number of candidate pairs of distinct diffs
+    pub candidates: usize,
+    /// The number of candidate pairs whose similarity exceeded the threshold
+    pub matches: usize,
+    pub s_curve: Vec<CurvePoint>,
+}
+
+/// One of the largest buckets of a band map
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BucketSummary {
+    /// The index of the band whose map contains the bucket
+    pub band: usize,
+    /// The number of distinct diffs in the bucket
+    pub size: usize,
+    /// The shingles that are most common among the diffs in the bucket. Huge buckets with generic
+    /// shingles (e.g., license headers) usually stem from noise commits.
+    pub sample_shingles: Vec<String>,
+}
+
+/// A point of the S-curve for the candidates in the similarity bin `[similarity, similarity +
+/// 0.1)`. The similarity of a candidate pair is estimated by the fraction of equal signature
+/// values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurvePoint {
+    pub similarity: f64,
+    /// The probability that a pair with the bin's mean similarity becomes a candidate
+    pub candidate_probability: f64,
+    /// The number of observed candidates in the bin
+    pub candidates: usize,
+    /// The number of observed candidates in the bin that were matches
+    pub matches: usize,
+}
+
+impl CurvePoint {
+    /// The fraction of candidates in the bin that were matches, if there were any candidates
+    pub fn precision(&self) -> Option<f64> {
+        (self.candidates > 0).then(|| self.matches as f64 / self.candidates as f64)
+    }
+}
+
+impl LshDiagnostics {
+    /// Start the report of a run with the buckets of the band maps. The given texts are the texts of
+    /// the distinct diffs whose ids are stored in the buckets.
+    pub(crate) fn from_buckets(
+        lsh: &TraditionalLSH,
+        band_maps: &[HashMap<Band, HashSet<ID>>],
+        texts: &[&str],
+        top_n: usize,
+    ) -> Self {
+        profile_fn!(build_lsh_diagnostics);
+        let mut bucket_sizes = BTreeMap::new();
+        let mut buckets: Vec<(usize, &HashSet<ID>)> = vec![];
+        for (band, map) in band_maps.iter().enumerate() {
+            for ids in map.values() {
+                *bucket_sizes.entry(ids.len()).or_insert(0) += 1;
+                buckets.push((band, ids));
+            }
+        }
+        buckets.sort_by(|(band_a, a), (band_b, b)| b.len().cmp(&a.len()).then(band_a.cmp(band_b)));
+        let largest_buckets = buckets
+            .into_iter()
+            .take(top_n)
+            .map(|(band, ids)| BucketSummary {
+                band,
+                size: ids.len(),
+                sample_shingles: sample_shingles(ids, texts, lsh.shingling, lsh.arity),
+            })
+            .collect();
+
+        let mut report = Self {
+            schema_version: Some(SCHEMA_VERSION),
+            arity: lsh.arity,
+            signature_size: lsh.signature_size,
+            band_size: lsh.signature_size / lsh.n_bands,
+            n_bands: lsh.n_bands,
+            probes: lsh.probes(),
+            threshold: lsh.threshold,
+            strategy: LshStrategy::Index,
+            distinct_diffs: texts.len(),
+            bucket_sizes,
+            largest_buckets,
+            candidates: 0,
+            matches: 0,
+            s_curve: vec![],
+        };
+        report.s_curve = (0..CURVE_BINS)
+            .map(|bin| CurvePoint {
+                similarity: bin as f64 / CURVE_BINS as f64,
+                candidate_probability: report
+                    .candidate_probability((bin as f64 + 0.5) / CURVE_BINS as f64),
+                candidates: 0,
+                matches: 0,
+            })
+            .collect();
+        report
+    }
+
+    /// Record the outcomes of the similarity checks of all candidates, given as the ids of the
+    /// candidates' diffs and whether the candidates were matches
+    pub(crate) fn record_candidates(
+        &mut self,
+        signatures: &[Signature],
+        outcomes: &[(ID, ID, bool)],
+    ) {
+        for (id_a, id_b, matched) in outcomes {
+            let similarity = signature_similarity(&signatures[*id_a], &signatures[*id_b]);
+            let bin = ((similarity * CURVE_BINS as f64) as usize).min(CURVE_BINS - 1);
+            self.candidates += 1;
+            self.s_curve[bin].candidates += 1;
+            if *matched {
+                self.matches += 1;
+                self.s_curve[bin].matches += 1;
+            }
+        }
+    }
+
+    /// The probability that two diffs with the given Jaccard similarity become candidates
+    pub fn candidate_probability(&self, similarity: f64) -> f64 {
+        let r = self.band_size as i32;
+        let band_collision =
+            similarity.powi(r) + self.probes as f64 * (1.0 - similarity) * similarity.powi(r - 1);
+        1.0 - (1.0 - band_collision.min(1.0)).powi(self.n_bands as i32)
+    }
+
+    /// The similarity at which the S-curve is steepest, i.e., the similarity from which on pairs
+    /// are likely to become candidates (approximately `(1/b)^(1/r)`)
+    pub fn curve_threshold(&self) -> f64 {
+        (1.0 / self.n_bands as f64).powf(1.0 / self.band_size as f64)
+    }
+
+    /// The fraction of candidates that were matches, if there were any candidates
+    pub fn precision(&self) -> Option<f64> {
+        (self.candidates > 0).then(|| self.matches as f64 / self.candidates as f64)
+    }
+
+    /// Suggestions for adjusting the parameters, based on the observations of the run
+    pub fn hints(&self) -> Vec<String> {
+        let mut hints = vec![];
+        if self.strategy == LshStrategy::BruteForce {
+            // The parameters of the bands did not affect the candidates
+            return hints;
+        }
+        let at_threshold = self.candidate_probability(self.threshold);
+        if at_threshold < 0.9 {
+            hints.push(format!(
+                "pairs at the similarity threshold {} only become candidates with a probability of \
+                 {at_threshold:.2}; decrease the band size to miss fewer matches",
+                self.threshold
+            ));
+        }
+        match self.precision() {
+            None if self.distinct_diffs > 1 => hints.push(
+                "no candidates were found; decrease the band size to find more candidates"
+                    .to_string(),
+            ),
+            Some(precision) if precision < 0.1 => hints.push(format!(
+                "only {:.1}% of the candidates are matches; increase the band size or the arity to \
+                 reduce the number of candidates",
+                precision * 100.0
+            )),
+            _ => {}
+        }
+        if let Some(bucket) = self.largest_buckets.first() {
+            if bucket.size > 100 && bucket.size * 100 > self.distinct_diffs {
+                hints.push(format!(
+                    "the largest bucket contains {} diffs; consider ignoring the commits that \
+                     produce it as noise (see NoiseList)",
+                    bucket.size
+                ));
+            }
+        }
+        hints
+    }
+
+    /// Write the report as YAML (or JSON, for paths ending in `.json`)
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// The fraction of equal values in the given signatures
+pub(super) fn signature_similarity(a: &Signature, b: &Signature) -> f64 {
+    let equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    equal as f64 / a.len().max(1) as f64
+}
+
+/// The shingles that occur in the most texts of the given bucket
+fn sample_shingles(
+    ids: &HashSet<ID>,
+    texts: &[&str],
+    shingling: Shingling,
+    arity: usize,
+) -> Vec<String> {
+    let mut ids: Vec<ID> = ids.iter().copied().collect();
+    ids.sort_unstable();
+    let normalized: Vec<Cow<str>> = ids
+        .into_iter()
+        .take(SAMPLED_MEMBERS)
+        .map(|id| shingling.normalize(texts[id]))
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for text in &normalized {
+        let shingled_text = shingling.shingle(text, arity);
+        let shingles: HashSet<&str> = shingled_text.shingles().iter().copied().collect();
+        for shingle in shingles {
+            *counts.entry(shingle).or_insert(0) += 1;
+        }
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(shingle_a, a), (shingle_b, b)| b.cmp(a).then(shingle_a.cmp(shingle_b)));
+    counts
+        .into_iter()
+        .take(SAMPLED_SHINGLES)
+        .map(|(shingle, _)| shingle.to_string())
+        .collect()
+}
@@ -0,0 +1,261 @@
+use crate::journal::{serialize_atomic, Format};
+use crate::schema::SCHEMA_VERSION;
+use crate::search::methods::lsh::{Band, LshStrategy, Shingling, TraditionalLSH, ID};
+use crate::{Error, ErrorKind, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// The lowest probability with which the matches of earlier runs must become candidates, if the
+/// band size is tuned
+const TARGET_RECALL: f64 = 0.95;
+/// The margin by which the lowest observed similarity of a match is lowered. The similarities are
+/// estimated from MinHash signatures, so they deviate from the exact similarities of the diffs
+/// (with a standard deviation of up to `0.5 / sqrt(signature_size)`, i.e., 0.05 for 100 values),
+/// and the hash functions differ between runs that are not seeded.
+const SIMILARITY_MARGIN: f64 = 0.1;
+
+/// The statistics of the TraditionalLSH runs on one repository, which are persisted between
+/// harvests (see [crate::TraditionalLSH::with_memory]).
+///
+/// The statistics show how the diffs of the repository are distributed over the buckets and how
+/// many candidates were verified as matches. On subsequent runs, they are used to select the
+/// largest band size with which the matches of all earlier runs still become candidates (see
+/// [LshMemory::tuned_band_size]). Larger bands produce fewer candidates, so that repeated harvests
+/// of a repository become progressively faster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LshMemory {
+    /// The schema version with which the memory was written (see [crate::schema])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// The number of recorded runs
+    pub runs: usize,
+    pub arity: usize,
+    /// How the diffs were split into shingles. Memories without it were recorded with character
+    /// shingles.
+    #[serde(default)]
+    pub shingling: Shingling,
+    pub signature_size: usize,
+    /// The band size of the last run
+    pub band_size: usize,
+    pub threshold: f64,
+    /// Whether the band size of the last run was tuned with the statistics of earlier runs
+    #[serde(default)]
+    pub tuned: bool,
+    /// How the candidates of the last run were selected
+    #[serde(default)]
+    pub strategy: LshStrategy,
+    /// The number of distinct diffs of the last run
+    pub distinct_diffs: usize,
+    /// The number of distinct shingles of the last run
+    pub vocabulary: usize,
+    /// The number of buckets per bucket size in the last run, over the buckets of all bands
+    pub bucket_sizes: BTreeMap<usize, usize>,
+    /// The number of candidate pairs of distinct diffs in the last run
+    pub candidates: usize,
+    /// The number of candidate pairs whose similarity exceeded the threshold in the last run
+    pub matches: usize,
+    /// The lowest estimated similarity (i.e., the fraction of equal signature values) of a match
+    /// over all runs, if there were any matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_match_similarity: Option<f64>,
+    /// The duration of the last run in milliseconds
+    pub elapsed_ms: u64,
+}
+
+impl LshMemory {
+    /// Load the memory in the given file, if it exists
+    ///
+    /// # Errors
+    /// Returns an error if the file exists, but cannot be read or parsed.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        serde_yaml::from_str(&content).map(Some).map_err(|error| {
+            Error::new(ErrorKind::Configuration(format!(
+                "invalid LSH memory {}: {error}",
+                path.display()
+            )))
+        })
+    }
+
+    /// Write the memory as YAML
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serialize_atomic(&path, self, Format::of(&path))
+    }
+
+    /// The fraction of the candidates of the last run that were verified as matches, if there were
+    /// any candidates
+    pub fn verification_yield(&self) -> Option<f64> {
+        (self.candidates > 0).then(|| self.matches as f64 / self.candidates as f64)
+    }
+
+    /// The largest band size with which pairs as similar as the least similar match of all earlier
+    /// runs become candidates with a probability of at least 95%. The band size must divide the
+    /// signature size. Returns None if the memory does not apply to the given parameters (i.e., if
+    /// it was recorded with another arity, shingling, or signature size), or if no matches were
+    /// recorded.
+    pub fn tuned_band_size(
+        &self,
+        arity: usize,
+        shingling: Shingling,
+        signature_size: usize,
+    ) -> Option<usize> {
+        if !self.applies_to(arity, shingling, signature_size) {
+            return None;
+        }
+        let similarity = (self.min_match_similarity? - SIMILARITY_MARGIN).max(0.0);
+        (1..=signature_size)
+            .rev()
+            .filter(|band_size| signature_size % band_size == 0)
+            .find(|band_size| {
+                let n_bands = (signature_size / band_size) as i32;
+                1.0 - (1.0 - similarity.powi(*band_size as i32)).powi(n_bands) >= TARGET_RECALL
+            })
+    }
+
+    /// Whether the similarities in the memory were estimated with the given parameters
+    fn applies_to(&self, arity: usize, shingling: Shingling, signature_size: usize) -> bool {
+        self.arity == arity && self.shingling == shingling && self.signature_size == signature_size
+    }
+
+    /// Record the statistics of a run. The lowest similarity of a match is kept over all runs, so
+    /// that matches that only become candidates with smaller bands are not forgotten. A memory
+    /// that was recorded with other parameters is replaced.
+    pub(crate) fn record(previous: Option<&LshMemory>, run: LshRun) -> Self {
+        let previous =
+            previous.filter(|p| p.applies_to(run.0.arity, run.0.shingling, run.0.signature_size));
+        let min_match_similarity = match (
+            previous.and_then(|p| p.min_match_similarity),
+            run.0.min_match_similarity,
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            schema_version: Some(SCHEMA_VERSION),
+            runs: previous.map_or(0, |p| p.runs) + 1,
+            min_match_similarity,
+            ..run.0
+        }
+    }
+}
+
+/// The statistics of a single run, before they are merged with the memory of earlier runs
+pub(crate) struct LshRun(LshMemory);
+
+impl LshRun {
+    /// Collect the statistics of the band maps of a run. The vocabulary is the number of distinct
+    /// shingles of the hashed diffs.
+    pub(crate) fn from_buckets(
+        lsh: &TraditionalLSH,
+        band_maps: &[HashMap<Band, HashSet<ID>>],
+        vocabulary: usize,
+    ) -> Self {
+        let mut bucket_sizes = BTreeMap::new();
+        let mut distinct_diffs = 0;
+        for (band, map) in band_maps.iter().enumerate() {
+            for ids in map.values() {
+                *bucket_sizes.entry(ids.len()).or_insert(0) += 1;
+                if band == 0 {
+                    distinct_diffs += ids.len();
+                }
+            }
+        }
+        Self(LshMemory {
+            schema_version: None,
+            runs: 0,
+            arity: lsh.arity,
+            shingling: lsh.shingling,
+            signature_size: lsh.signature_size,
+            band_size: lsh.signature_size / lsh.n_bands,
+            threshold: lsh.threshold,
+            tuned: lsh.tuned,
+            strategy: LshStrategy::Index,
+            distinct_diffs,
+            vocabulary,
+            bucket_sizes,
+            candidates: 0,
+            matches: 0,
+            min_match_similarity: None,
+            elapsed_ms: 0,
+        })
+    }
+
+    /// Record the outcomes of the similarity checks of all candidates, given as the estimated
+    /// similarities of the candidates and whether the candidates were matches
+    pub(crate) fn record_candidates(&mut self, outcomes: impl Iterator<Item = (f64, bool)>) {
+        for (similarity, matched) in outcomes {
+            self.0.candidates += 1;
+            if matched {
+                self.0.matches += 1;
+                self.0.min_match_similarity = Some(
+                    self.0
+                        .min_match_similarity
+                        .map_or(similarity, |min| min.min(similarity)),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn set_strategy(&mut self, strategy: LshStrategy) {
+        self.0.strategy = strategy;
+    }
+
+    pub(crate) fn set_elapsed_ms(&mut self, elapsed_ms: u64) {
+        self.0.elapsed_ms = elapsed_ms;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::methods::lsh::memory::{LshMemory, LshRun};
+    use crate::search::methods::lsh::Shingling;
+    use crate::TraditionalLSH;
+    use std::collections::HashMap;
+
+    #[test]
+    fn band_sizes_are_tuned_by_earlier_matches() {
+        let mut run =
+            LshRun::from_buckets(&TraditionalLSH::new(8, 100, 5, 0.75), &[HashMap::new()], 0);
+        run.record_candidates([(0.98, true), (0.3, false), (0.99, true)].into_iter());
+        let memory = LshMemory::record(None, run);
+        assert_eq!((memory.runs, memory.candidates, memory.matches), (1, 3, 2));
+        assert_eq!(memory.min_match_similarity, Some(0.98));
+        assert_eq!(memory.verification_yield(), Some(2.0 / 3.0));
+        // Pairs with a similarity of 0.88 become candidates with 10 bands of 10 values
+        assert_eq!(
+            memory.tuned_band_size(8, Shingling::Characters, 100),
+            Some(10)
+        );
+        assert_eq!(memory.tuned_band_size(5, Shingling::Characters, 100), None);
+        assert_eq!(memory.tuned_band_size(8, Shingling::Tokens, 100), None);
+
+        // Less similar matches of later runs lower the band size again
+        let mut run =
+            LshRun::from_buckets(&TraditionalLSH::new(8, 100, 10, 0.75), &[HashMap::new()], 0);
+        run.record_candidates([(0.6, true)].into_iter());
+        let memory = LshMemory::record(Some(&memory), run);
+        assert_eq!(memory.runs, 2);
+        assert_eq!(memory.min_match_similarity, Some(0.6));
+        assert_eq!(
+            memory.tuned_band_size(8, Shingling::Characters, 100),
+            Some(2)
+        );
+
+        let run = LshRun::from_buckets(&TraditionalLSH::new(8, 100, 2, 0.75), &[HashMap::new()], 0);
+        let memory = LshMemory::record(Some(&memory), run);
+        assert_eq!(memory.min_match_similarity, Some(0.6));
+        assert_eq!(memory.verification_yield(), None);
+
+        // The similarities of token shingles are not comparable to those of character shingles
+        let lsh = TraditionalLSH::new(8, 100, 2, 0.75).with_shingling(Shingling::Tokens);
+        let run = LshRun::from_buckets(&lsh, &[HashMap::new()], 0);
+        let memory = LshMemory::record(Some(&memory), run);
+        assert_eq!((memory.runs, memory.min_match_similarity), (1, None));
+    }
+}
@@ -1,12 +1,22 @@
 use crate::error::Error;
-use crate::error::ErrorKind::ANNPreprocessing;
+use crate::error::ErrorKind::{ANNPreprocessing, Configuration};
+use crate::git::calculate_diffs;
+use crate::hashing::stable_hash;
+use crate::search::methods::lsh::sketch::{
+    CountMinSketch, DEFAULT_SKETCH_DEPTH, DEFAULT_SKETCH_WIDTH,
+};
 use crate::{Commit, Diff};
 use bit_vec::BitVec;
 use firestorm::{profile_fn, profile_method};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::iter;
+use std::str::FromStr;
 
 pub type Shingle<'a> = &'a str;
 
@@ -16,93 +26,157 @@ pub struct ShingledText<'a> {
     arity: usize,
 }
 
-pub fn shingle_diff(diff: &Diff, arity: usize) -> ShingledText {
+pub fn shingle_diff(diff: &Diff, arity: usize) -> ShingledText<'_> {
     ShingledText::new(diff.diff_text(), arity)
 }
 
-pub fn shingle_text(diff: &str, arity: usize) -> ShingledText {
+pub fn shingle_text(diff: &str, arity: usize) -> ShingledText<'_> {
     ShingledText::new(diff, arity)
 }
 
 fn shingle_commits<'a>(commits: &'a mut [Commit], arity: usize) -> Vec<ShingledText<'a>> {
+    calculate_diffs(commits);
     commits
         .iter_mut()
         .map(|c| shingle_diff(c.calculate_diff(), arity))
         .collect()
 }
 
-fn shingle_texts<'a>(texts: &[&'a str], arity: usize) -> Vec<ShingledText<'a>> {
-    texts.iter().map(|text| shingle_text(text, arity)).collect()
-}
-
 pub fn preprocess_commits(
     commits: &mut [Commit],
     arity: usize,
     signature_size: usize,
 ) -> Vec<Signature> {
     profile_fn!(preprocess_commits);
-    shingles_into_signatures(shingle_commits(commits, arity), signature_size)
+    let shingled_commits = shingle_commits(commits, arity);
+    shingles_into_signatures(&shingled_commits, signature_size, &mut thread_rng())
 }
 
+#[deprecated(note = "the encodings are not used by any search method")]
 pub fn encode_commits_f64(commits: &mut [Commit<'_, '_>], arity: usize) -> Vec<Vec<f64>> {
     profile_fn!(preprocess_commits);
     let shingled_commits = shingle_commits(commits, arity);
     let vocabulary = Vocabulary::build(&shingled_commits);
     shingled_commits
         .iter()
-        .map(|s| vocabulary.encode_f64(s).unwrap())
+        .map(|s| vocabulary.normalized_indices(s).unwrap())
         .collect()
 }
 
+#[deprecated(note = "the encodings are not used by any search method")]
 pub fn encode_commits_u32(commits: &mut [Commit<'_, '_>], arity: usize) -> Vec<Vec<u32>> {
     profile_fn!(preprocess_commits);
     let shingled_commits = shingle_commits(commits, arity);
     let vocabulary = Vocabulary::build(&shingled_commits);
     shingled_commits
         .iter()
-        .map(|s| vocabulary.encode_u32(s).unwrap())
+        .map(|s| {
+            vocabulary
+                .indices(s)
+                .unwrap()
+                .map(|index| index as u32)
+                .collect()
+        })
         .collect()
 }
 
 pub fn preprocess_texts(texts: &[&str], arity: usize, signature_size: usize) -> Vec<Signature> {
+    preprocess_texts_with_vocabulary(
+        texts,
+        Shingling::Characters,
+        arity,
+        signature_size,
+        None,
+        None,
+    )
+    .0
+}
+
+/// Create the signatures of the given texts and return them with the size of their vocabulary,
+/// i.e., the number of distinct shingles of the texts. The signatures are hashed from the shingles
+/// directly, so that no vocabulary is built. If a pruning is given, only the shingles that are
+/// frequent enough are counted (see [VocabularyPruning]). If a seed is given, the MinHash is
+/// created with a random number generator that is seeded with it, so that the signatures are
+/// equal in every run.
+pub(crate) fn preprocess_texts_with_vocabulary(
+    texts: &[&str],
+    shingling: Shingling,
+    arity: usize,
+    signature_size: usize,
+    pruning: Option<&VocabularyPruning>,
+    seed: Option<u64>,
+) -> (Vec<Signature>, usize) {
     profile_fn!(preprocess_commits);
-    let shingled_commits = shingle_texts(texts, arity);
+    let normalized: Vec<Cow<str>> = texts.iter().map(|text| shingling.normalize(text)).collect();
+    let shingled_commits: Vec<ShingledText> = normalized
+        .iter()
+        .map(|text| shingling.shingle(text, arity))
+        .collect();
+    let mut rng: Box<dyn RngCore> = match seed {
+        Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+        None => Box::new(thread_rng()),
+    };
+    (
+        shingles_into_signatures(&shingled_commits, signature_size, &mut rng),
+        count_vocabulary(&shingled_commits, pruning),
+    )
+}
 
-    shingles_into_signatures(shingled_commits, signature_size)
+/// The number of distinct shingles of the given texts that are kept by the given pruning. The
+/// shingles are counted by their hashes, which are only eight bytes each.
+fn count_vocabulary(shingled_texts: &[ShingledText], pruning: Option<&VocabularyPruning>) -> usize {
+    profile_fn!(count_vocabulary);
+    let distinct = |text: &ShingledText| -> HashSet<u64> {
+        text.shingles.iter().map(|s| hash_shingle(s)).collect()
+    };
+    let Some(pruning) = pruning else {
+        return shingled_texts
+            .iter()
+            .flat_map(distinct)
+            .collect::<HashSet<u64>>()
+            .len();
+    };
+    // Shingles are counted once per text, i.e., the sketch counts the texts of each shingle
+    let mut sketch = CountMinSketch::new(pruning.sketch_width, pruning.sketch_depth);
+    for text in shingled_texts {
+        distinct(text).iter().for_each(|hash| sketch.add(hash));
+    }
+    shingled_texts
+        .iter()
+        .flat_map(distinct)
+        .filter(|hash| sketch.estimate(hash) >= pruning.min_frequency)
+        .collect::<HashSet<u64>>()
+        .len()
 }
 
-fn shingles_into_signatures(
-    shingled_texts: Vec<ShingledText>,
+fn shingles_into_signatures<R: Rng + ?Sized>(
+    shingled_texts: &[ShingledText],
     signature_size: usize,
+    rng: &mut R,
 ) -> Vec<Signature> {
-    let vocabulary = Vocabulary::build(&shingled_texts);
-    let minhash = MinHash::new(signature_size, vocabulary.len());
+    let minhash = MinHash::with_rng(signature_size, rng);
     shingled_texts
         .iter()
-        .map(|st| {
-            let one_hot = vocabulary.one_hot(st).unwrap();
-            minhash.hash_signature(&one_hot)
-        })
+        .map(|st| minhash.hash_shingles(st.shingles().iter().copied()))
         .collect()
 }
 
 impl<'a> ShingledText<'a> {
     pub fn new(text: &'a str, arity: usize) -> Self {
         profile_fn!(new_shingled_text);
-        let mut shingles = Vec::new();
-        let char_indices = text.char_indices().map(|(i, _)| i).collect::<Vec<usize>>();
-
-        for (i, window_position) in char_indices.iter().enumerate() {
-            // chars can take more than one index; thus, we have to index into the char_indices vector
-            let index_of_end_index = i + arity;
-            let window_end = if index_of_end_index >= char_indices.len() {
-                text.len()
-            } else {
-                char_indices[index_of_end_index]
-            };
-
-            let shingle = &text[*window_position..window_end];
-            shingles.push(shingle);
+        // Shingles borrow from the text; the only allocation is the vector of shingles, which has
+        // at most one entry per byte of the text
+        let mut shingles = Vec::with_capacity(text.len());
+        // chars can take more than one byte; thus, each window ends at the start index of the char
+        // that follows the window, or at the end of the text
+        let mut window_ends = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .skip(arity)
+            .chain(iter::repeat(text.len()));
+        for (window_start, _) in text.char_indices() {
+            let window_end = window_ends.next().unwrap_or(text.len());
+            shingles.push(&text[window_start..window_end]);
         }
 
         if shingles.is_empty() {
@@ -111,6 +185,40 @@ impl<'a> ShingledText<'a> {
 
         ShingledText { shingles, arity }
     }
+
+    /// Shingle a text of tokens that are separated by line breaks (see [Shingling::normalize]).
+    /// Each shingle spans `arity` consecutive tokens, including the line breaks between them.
+    pub fn from_tokens(tokens: &'a str, arity: usize) -> Self {
+        profile_fn!(new_shingled_tokens);
+        // The start and end of each token in the text
+        let mut bounds = Vec::new();
+        let mut start = 0;
+        for token in tokens.split('\n') {
+            if !token.is_empty() {
+                bounds.push((start, start + token.len()));
+            }
+            start += token.len() + 1;
+        }
+        let shingles: Vec<Shingle<'a>> = (0..bounds.len())
+            .map(|i| {
+                let (window_start, _) = bounds[i];
+                let (_, window_end) = bounds[(i + arity.max(1)).min(bounds.len()) - 1];
+                &tokens[window_start..window_end]
+            })
+            .collect();
+
+        match shingles.is_empty() {
+            true => ShingledText {
+                shingles: vec!["EMPTY"],
+                arity,
+            },
+            false => ShingledText { shingles, arity },
+        }
+    }
+
+    pub fn shingles(&self) -> &[Shingle<'a>] {
+        &self.shingles
+    }
 }
 
 impl<'a> Display for ShingledText<'a> {
@@ -122,28 +230,389 @@ impl<'a> Display for ShingledText<'a> {
     }
 }
 
+/// How the texts of diffs are split into shingles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shingling {
+    /// Each shingle is a window of `arity` characters of the text
+    #[default]
+    Characters,
+    /// Each shingle is a window of `arity` code tokens of the text (see [tokenize]). Whitespace
+    /// only separates tokens, so that changes of the indentation or spacing of code do not affect
+    /// the shingles.
+    Tokens,
+    /// Like [Shingling::Tokens], but all identifiers except keywords are replaced by a
+    /// placeholder, so that renamed variables and functions do not affect the shingles either
+    AnonymizedTokens,
+}
+
+impl Shingling {
+    /// The text whose shingles are created. For character shingling, this is the given text;
+    /// otherwise, it consists of the tokens of the text, each followed by a line break.
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Shingling::Characters => Cow::Borrowed(text),
+            Shingling::Tokens => Cow::Owned(normalize_tokens(text, false)),
+            Shingling::AnonymizedTokens => Cow::Owned(normalize_tokens(text, true)),
+        }
+    }
+
+    /// The shingles of a text that was normalized with [Shingling::normalize]
+    pub fn shingle<'a>(&self, normalized: &'a str, arity: usize) -> ShingledText<'a> {
+        match self {
+            Shingling::Characters => ShingledText::new(normalized, arity),
+            Shingling::Tokens | Shingling::AnonymizedTokens => {
+                ShingledText::from_tokens(normalized, arity)
+            }
+        }
+    }
+}
+
+impl Display for Shingling {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shingling::Characters => write!(f, "characters"),
+            Shingling::Tokens => write!(f, "tokens"),
+            Shingling::AnonymizedTokens => write!(f, "anonymized_tokens"),
+        }
+    }
+}
+
+impl FromStr for Shingling {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "characters" => Ok(Shingling::Characters),
+            "tokens" => Ok(Shingling::Tokens),
+            "anonymized_tokens" => Ok(Shingling::AnonymizedTokens),
+            _ => Err(Error::new(Configuration(format!(
+                "unknown shingling '{s}'; expected characters, tokens, or anonymized_tokens"
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Identifier,
+    Keyword,
+    /// A number, string, or character
+    Literal,
+    Operator,
+}
+
+/// A token of a line of code, which borrows from the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+}
+
+/// The placeholder of anonymized identifiers
+const IDENTIFIER_PLACEHOLDER: &str = "$id";
+
+/// The keywords of common programming languages, which are kept by the anonymization. Sorted for
+/// binary search.
+const KEYWORDS: [&str; 70] = [
+    "abstract",
+    "as",
+    "async",
+    "await",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "def",
+    "default",
+    "defer",
+    "delete",
+    "do",
+    "double",
+    "elif",
+    "else",
+    "enum",
+    "except",
+    "extends",
+    "extern",
+    "false",
+    "final",
+    "finally",
+    "float",
+    "fn",
+    "for",
+    "func",
+    "go",
+    "if",
+    "impl",
+    "implements",
+    "import",
+    "in",
+    "int",
+    "interface",
+    "let",
+    "long",
+    "loop",
+    "match",
+    "mod",
+    "mut",
+    "new",
+    "nil",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "pub",
+    "public",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "throws",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "use",
+    "var",
+    "void",
+    "while",
+];
+
+/// Operators of more than one character, with longer operators before their prefixes
+const OPERATORS: [&str; 30] = [
+    "<<=", ">>=", "...", "===", "!==", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "+=",
+    "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<", ">>", "++", "--", "..", "**", "?.", "??",
+];
+
+/// Split a line of code into identifiers, keywords, literals, and operators. Whitespace only
+/// separates tokens. The tokenizer does not depend on the language of the code: identifiers are
+/// words that start with a letter or underscore, literals in quotes end at the closing quote or
+/// at the end of the line, and all other characters are operators.
+pub fn tokenize(line: &str) -> Vec<Token<'_>> {
+    profile_fn!(tokenize);
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(line.len(), |(offset, _)| *offset);
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = (i, chars[i].1);
+        let kind = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && is_word(chars[i].1) {
+                i += 1;
+            }
+            match KEYWORDS.binary_search(&&line[offset(start)..offset(i)]) {
+                Ok(_) => TokenKind::Keyword,
+                Err(_) => TokenKind::Identifier,
+            }
+        } else if c.is_ascii_digit() {
+            // Numbers with suffixes, fractions, and exponents, e.g., `0x1Fu8` or `1.5e3`
+            while i < chars.len()
+                && (is_word(chars[i].1)
+                    || chars[i].1 == '.'
+                        && chars.get(i + 1).is_some_and(|(_, c)| c.is_ascii_digit()))
+            {
+                i += 1;
+            }
+            TokenKind::Literal
+        } else if c == '"' || c == '\'' && is_char_literal(&chars[i..]) {
+            i += 1;
+            while i < chars.len() && chars[i].1 != c {
+                // Skip escaped characters, e.g., escaped quotes
+                if chars[i].1 == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            TokenKind::Literal
+        } else {
+            let rest = &line[offset(i)..];
+            // Operators of more than one character are ASCII
+            i += OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(**operator))
+                .map_or(1, |operator| operator.len());
+            TokenKind::Operator
+        };
+        tokens.push(Token {
+            kind,
+            text: &line[offset(start)..offset(i)],
+        });
+    }
+    tokens
+}
+
+/// Whether the quote at the start of the given characters opens a character literal (e.g., `'a'`
+/// or `'\n'`) instead of, e.g., a lifetime in Rust
+fn is_char_literal(chars: &[(usize, char)]) -> bool {
+    matches!(chars, [_, (_, '\\'), ..] | [_, _, (_, '\''), ..])
+}
+
+/// The tokens of all lines of the given text, each followed by a line break. The marker of a
+/// changed line in a diff is a token of its own, e.g., the line `+x++` has the tokens `+`, `x`,
+/// and `++`.
+fn normalize_tokens(text: &str, anonymize: bool) -> String {
+    profile_fn!(normalize_tokens);
+    let mut normalized = String::with_capacity(text.len());
+    let mut push = |token: &str| {
+        normalized.push_str(token);
+        normalized.push('\n');
+    };
+    for line in text.lines() {
+        let code = match line.strip_prefix(['+', '-']) {
+            Some(code) => {
+                push(&line[..1]);
+                code
+            }
+            None => line,
+        };
+        for token in tokenize(code) {
+            match token.kind {
+                TokenKind::Identifier if anonymize => push(IDENTIFIER_PLACEHOLDER),
+                _ => push(token.text),
+            }
+        }
+    }
+    normalized
+}
+
+/// The pruning of the shingles that are too rare to make two texts similar. A shingle that
+/// occurs in a single text (i.e., a hapax) cannot be shared by two texts, so that vocabularies of
+/// large corpora can be reduced considerably. The pruned shingles are still part of the
+/// signatures, because [MinHash] hashes the shingles of a text directly instead of looking them
+/// up in a vocabulary; otherwise, texts that only differ in rare shingles would have equal
+/// signatures. Searches thus only apply the pruning to the vocabulary size that they report,
+/// while [Vocabulary::build_pruned] only adds the kept shingles to the vocabulary.
+///
+/// The frequencies are counted in a first pass with a [CountMinSketch], whose memory is fixed.
+/// Only the shingles whose estimated frequency reaches the floor are kept in a second pass. As the
+/// sketch never underestimates frequencies, no shingle above the floor is pruned, but some rarer
+/// shingles may be kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VocabularyPruning {
+    /// The smallest number of texts in which a shingle has to occur to be kept. With a floor of
+    /// `2`, only hapax shingles are pruned; higher floors prune shingles that some texts share.
+    pub min_frequency: u32,
+    /// The number of counters per row of the sketch
+    pub sketch_width: usize,
+    /// The number of rows of the sketch
+    pub sketch_depth: usize,
+}
+
+impl VocabularyPruning {
+    /// Prune the shingles that occur in fewer texts than the given floor, with a sketch of the
+    /// default size (four rows of 2^20 counters, i.e., 16 MiB)
+    pub fn new(min_frequency: u32) -> Self {
+        Self {
+            min_frequency,
+            sketch_width: DEFAULT_SKETCH_WIDTH,
+            sketch_depth: DEFAULT_SKETCH_DEPTH,
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct Vocabulary<'text>(HashMap<Shingle<'text>, usize>);
+pub struct Vocabulary<'text> {
+    shingles: HashMap<Shingle<'text>, usize>,
+    // Whether rare shingles were pruned, in which case texts may contain unknown shingles
+    pruned: bool,
+}
 
 impl<'text> Vocabulary<'text> {
     pub fn build(shingled_texts: &'text [ShingledText]) -> Self {
+        Self::build_with_rng(shingled_texts, &mut thread_rng())
+    }
+
+    /// Build the vocabulary with a random number generator that is seeded with the given seed, so
+    /// that the vocabulary of the same texts is equal in every run
+    pub fn build_with_seed(shingled_texts: &'text [ShingledText], seed: u64) -> Self {
+        Self::build_with_rng(shingled_texts, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Build the vocabulary and use the given random number generator for the shuffled
+    /// assignment of indices to shingles.
+    pub fn build_with_rng<R: Rng + ?Sized>(
+        shingled_texts: &'text [ShingledText],
+        rng: &mut R,
+    ) -> Self {
         profile_fn!(build_vocabulary);
-        // Filter duplicate shingles for vocabulary creation
-        let mut shingles = HashSet::new();
+        // Filter duplicate shingles for vocabulary creation. Repeated shingles are only hashed once
+        // and not copied, because shingles borrow from the shingled texts.
+        let max_shingles = shingled_texts.iter().map(|st| st.shingles.len()).sum();
+        let mut shingles = HashSet::with_capacity(max_shingles);
         shingled_texts
             .iter()
             .flat_map(|sd| &sd.shingles)
             .for_each(|s| {
-                if !shingles.contains(s) {
-                    shingles.insert(*s);
-                }
+                shingles.insert(*s);
             });
 
+        Self::assign_indices(shingles, rng, false)
+    }
+
+    /// Build a vocabulary that only contains the shingles that occur in at least
+    /// [VocabularyPruning::min_frequency] texts (see [VocabularyPruning])
+    pub fn build_pruned(
+        shingled_texts: &'text [ShingledText],
+        pruning: &VocabularyPruning,
+    ) -> Self {
+        Self::build_pruned_with_rng(shingled_texts, pruning, &mut thread_rng())
+    }
+
+    /// Build a pruned vocabulary and use the given random number generator for the shuffled
+    /// assignment of indices to shingles.
+    pub fn build_pruned_with_rng<R: Rng + ?Sized>(
+        shingled_texts: &'text [ShingledText],
+        pruning: &VocabularyPruning,
+        rng: &mut R,
+    ) -> Self {
+        profile_fn!(build_pruned_vocabulary);
+        // Shingles are counted once per text, i.e., the sketch counts the texts of each shingle
+        let distinct = |text: &'text ShingledText| -> HashSet<Shingle<'text>> {
+            text.shingles.iter().copied().collect()
+        };
+        let mut sketch = CountMinSketch::new(pruning.sketch_width, pruning.sketch_depth);
+        for text in shingled_texts {
+            distinct(text).into_iter().for_each(|s| sketch.add(s));
+        }
+        let mut shingles = HashSet::new();
+        for text in shingled_texts {
+            shingles.extend(
+                distinct(text)
+                    .into_iter()
+                    .filter(|s| sketch.estimate(*s) >= pruning.min_frequency),
+            );
+        }
+        Self::assign_indices(shingles, rng, true)
+    }
+
+    fn assign_indices<R: Rng + ?Sized>(
+        shingles: HashSet<Shingle<'text>>,
+        rng: &mut R,
+        pruned: bool,
+    ) -> Self {
+        // The shingles are sorted, so that the assignment does not depend on the iteration order
+        // of the set, but only on the random number generator
+        let mut shingles: Vec<Shingle<'text>> = shingles.into_iter().collect();
+        shingles.sort_unstable();
         // The process requires shuffled assignments for the words in the vocabulary
         let mut indices: Vec<usize> = (0..shingles.len()).collect();
-        indices.shuffle(&mut thread_rng());
+        indices.shuffle(rng);
 
-        let mut shingle_map = HashMap::new();
+        let mut shingle_map = HashMap::with_capacity(shingles.len());
         // The vocabulary assigns each shingle a random index
         shingles.into_iter().enumerate().for_each(|(i, shingle)| {
             if shingle_map.insert(shingle, indices[i]).is_some() {
@@ -151,16 +620,25 @@ impl<'text> Vocabulary<'text> {
             }
         });
 
-        Self(shingle_map)
+        Self {
+            shingles: shingle_map,
+            pruned,
+        }
     }
 
+    /// The one-hot vector of the given text, in which the indices of the text's shingles are set.
+    /// The shingles that a pruned vocabulary does not contain are ignored.
+    ///
+    /// # Errors
+    /// Returns an error if a vocabulary that was not pruned does not contain one of the shingles.
     pub fn one_hot(&self, shingled_diff: &ShingledText) -> Result<BitVec, Error> {
         profile_method!(one_hot);
-        let mut one_hot: BitVec = BitVec::from_elem(self.0.len(), false);
+        let mut one_hot: BitVec = BitVec::from_elem(self.shingles.len(), false);
 
         // Set values of all occurring shingles to 1
         for shingle in &shingled_diff.shingles {
-            match self.0.get(shingle) {
+            match self.shingles.get(shingle) {
+                None if self.pruned => {}
                 None => return Err(Error::new(ANNPreprocessing("Shingle in diff not part of vocabulary. Have you used it during vocabulary building?".to_string()))),
                 Some(number) => {one_hot.set(*number, true);}
             }
@@ -170,111 +648,237 @@ impl<'text> Vocabulary<'text> {
     }
 
     /// Encode a given shingled text by mapping each shingle to a f64 according to the vocabulary
+    #[deprecated(note = "the encodings are not used by any search method")]
     pub fn encode_f64(&self, shingled_text: &ShingledText) -> Result<Vec<f64>, Error> {
-        let mut encoding = Vec::with_capacity(shingled_text.shingles.len());
-        let norm_factor = 1.0 / self.0.len() as f64;
-        for shingle in &shingled_text.shingles {
-            match self.0.get(shingle) {
-                None => {return Err(Error::new(
-                    ANNPreprocessing("Shingle in diff not part of vocabulary. Have you used it during vocabulary building?".to_string())))}
-                Some(index) => {
-                    let index = *index as f64;
-                    // For now, we try simple normalization to [0, 1]
-                    let val = index * norm_factor;
-                    encoding.push(val);
-                }
-            }
-        }
-        Ok(encoding)
+        self.normalized_indices(shingled_text)
+    }
+
+    /// The indices of the given text's shingles, normalized to [0, 1]
+    fn normalized_indices(&self, shingled_text: &ShingledText) -> Result<Vec<f64>, Error> {
+        let norm_factor = 1.0 / self.shingles.len() as f64;
+        // For now, we try simple normalization to [0, 1]
+        self.indices(shingled_text)
+            .map(|indices| indices.map(|index| index as f64 * norm_factor).collect())
     }
 
     /// Encode a given shingled text by mapping each shingle to an u32 according to the vocabulary
+    #[deprecated(note = "the encodings are not used by any search method")]
     pub fn encode_u32(&self, shingled_text: &ShingledText) -> Result<Vec<u32>, Error> {
-        let mut encoding = Vec::with_capacity(shingled_text.shingles.len());
-        for shingle in &shingled_text.shingles {
-            match self.0.get(shingle) {
-                None => {return Err(Error::new(
-                    ANNPreprocessing("Shingle in diff not part of vocabulary. Have you used it during vocabulary building?".to_string())))}
-                Some(index) => {
-                    encoding.push(*index as u32);
-                }
-            }
+        self.indices(shingled_text)
+            .map(|indices| indices.map(|index| index as u32).collect())
+    }
+
+    /// The indices of the given text's shingles, in the order of the shingles
+    fn indices<'s>(
+        &'s self,
+        shingled_text: &'s ShingledText,
+    ) -> Result<impl Iterator<Item = usize> + 's, Error> {
+        if shingled_text
+            .shingles
+            .iter()
+            .any(|s| !self.shingles.contains_key(s))
+        {
+            return Err(Error::new(ANNPreprocessing("Shingle in diff not part of vocabulary. Have you used it during vocabulary building?".to_string())));
         }
-        Ok(encoding)
+        Ok(shingled_text.shingles.iter().map(|s| self.shingles[s]))
+    }
+
+    /// The distinct shingles of the given text that the vocabulary does not contain
+    pub fn unknown<'a>(&self, shingled_text: &ShingledText<'a>) -> Vec<Shingle<'a>> {
+        let unknown: HashSet<Shingle> = shingled_text
+            .shingles
+            .iter()
+            .filter(|s| !self.shingles.contains_key(*s))
+            .copied()
+            .collect();
+        unknown.into_iter().collect()
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.shingles.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.shingles.is_empty()
     }
 }
 
 pub type Signature = Vec<u32>;
 
+/// The Mersenne prime `2^61 - 1`, modulo which the hash functions of [MinHash] are computed
+const MERSENNE_61: u64 = (1 << 61) - 1;
+
+/// MinHash with one universal hash function `h(x) = (a * x + b) mod p` per signature value, whose
+/// coefficients are drawn at random. In contrast to hashing with permutations of the vocabulary,
+/// the memory of the hash functions does not depend on the size of the vocabulary, and the
+/// signature of a text can be computed from its shingles in a single pass, without a vocabulary.
 pub struct MinHash {
-    signature_size: usize,
-    data_size: usize,
-    hash_vectors: Vec<Vec<usize>>,
+    coefficients: Vec<(u64, u64)>,
 }
 
 impl MinHash {
-    pub fn new(signature_size: usize, data_size: usize) -> Self {
+    pub fn new(signature_size: usize) -> Self {
+        Self::with_rng(signature_size, &mut thread_rng())
+    }
+
+    /// Create a new MinHash whose hash functions are drawn with the given random number generator.
+    pub fn with_rng<R: Rng + ?Sized>(signature_size: usize, rng: &mut R) -> Self {
         profile_fn!(new_minhash);
         // We require one hash function for each dimension in the signature
-        let mut hash_vectors = Vec::with_capacity(signature_size);
-        // We require one value for each word in the vocabulary, for which we want to apply MinHash
-        let mut initial_vector: Vec<usize> = (0..data_size).collect();
-        let mut rng = thread_rng();
-        for _ in 0..signature_size {
-            initial_vector.shuffle(&mut rng);
-            hash_vectors.push(initial_vector.clone())
-        }
+        let coefficients = (0..signature_size)
+            .map(|_| (rng.gen_range(1..MERSENNE_61), rng.gen_range(0..MERSENNE_61)))
+            .collect();
+        Self { coefficients }
+    }
 
-        Self {
-            signature_size,
-            data_size,
-            hash_vectors,
-        }
+    /// Create a new MinHash whose hash functions are drawn with a random number generator that is
+    /// seeded with the given seed, so that the signatures are equal in every run
+    pub fn with_seed(signature_size: usize, seed: u64) -> Self {
+        Self::with_rng(signature_size, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// The signature of the given shingles, which are consumed one at a time
+    pub fn hash_shingles<'s, I: IntoIterator<Item = &'s str>>(&self, shingles: I) -> Signature {
+        profile_method!(hash_shingles);
+        self.hash_values(shingles.into_iter().map(hash_shingle))
     }
 
+    /// The signature of a text whose shingles are the 'hot' indices of the given one-hot vector
+    /// (see [Vocabulary::one_hot])
     pub fn hash_signature(&self, one_hot: &BitVec) -> Signature {
         profile_method!(hash_signature);
-        assert_eq!(
-            one_hot.len(),
-            self.data_size,
-            "the given one-hot vector's size does not match the expected data size"
-        );
-        let mut signature: Signature = Vec::with_capacity(self.signature_size);
-
-        for vector in &self.hash_vectors {
-            // Get the first value that maps to a 'hot' index
-            // value and index are switched here on purpose, because MinHashing expects that the values
-            // are incremented from lowest to highest. Thus, we assume that our shuffled vector maps
-            // values to indices (technically, its the other way around)
-            for (value, index) in vector.iter().enumerate() {
-                if one_hot.get(*index).unwrap() {
-                    signature.push(value as u32);
-                    break;
-                }
+        let indices = one_hot
+            .iter()
+            .enumerate()
+            .filter(|(_, hot)| *hot)
+            .map(|(index, _)| index as u64);
+        self.hash_values(indices)
+    }
+
+    /// The minimum of each hash function over the given values. Each minimum is truncated to its
+    /// lower 32 bits, so that unequal minima are only equal with a probability of `2^-32`.
+    fn hash_values<I: Iterator<Item = u64>>(&self, values: I) -> Signature {
+        let mut minima = vec![u64::MAX; self.coefficients.len()];
+        for value in values {
+            let value = value % MERSENNE_61;
+            for (minimum, (a, b)) in minima.iter_mut().zip(&self.coefficients) {
+                *minimum = (*minimum).min(universal_hash(*a, *b, value));
             }
         }
+        minima.into_iter().map(|minimum| minimum as u32).collect()
+    }
+}
 
-        signature
+/// `(a * x + b) mod (2^61 - 1)` for values below the modulus
+fn universal_hash(a: u64, b: u64, x: u64) -> u64 {
+    let product = a as u128 * x as u128 + b as u128;
+    // As 2^61 = 1 modulo 2^61 - 1, the upper bits can be added to the lower bits
+    let folded = ((product & MERSENNE_61 as u128) + (product >> 61)) as u64;
+    let folded = (folded & MERSENNE_61) + (folded >> 61);
+    match folded >= MERSENNE_61 {
+        true => folded - MERSENNE_61,
+        false => folded,
     }
 }
 
+/// The stable hash of the given shingle, so that the signatures of a seeded MinHash are equal in
+/// every run
+fn hash_shingle(shingle: &str) -> u64 {
+    stable_hash(shingle.as_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::git::IdeaPatch;
     use crate::search::methods::lsh::preprocessing::{
-        preprocess_texts, shingle_diff, MinHash, ShingledText, Signature, Vocabulary,
+        preprocess_texts, preprocess_texts_with_vocabulary, shingle_diff, tokenize, MinHash,
+        ShingledText, Shingling, Signature, TokenKind, Vocabulary, VocabularyPruning,
     };
     use crate::Diff;
     use bit_vec::BitVec;
 
+    #[test]
+    fn shingles_of_multibyte_text() {
+        let shingled = ShingledText::new("aäb", 2);
+        assert_eq!(shingled.shingles, vec!["aä", "äb", "b"]);
+        assert_eq!(ShingledText::new("", 2).shingles, vec!["EMPTY"]);
+    }
+
+    #[test]
+    fn tokens_of_code() {
+        let tokens: Vec<(TokenKind, &str)> =
+            tokenize("  let näme = \"a \\\" b\" + 'c' >= 1.5e3; // x")
+                .into_iter()
+                .map(|token| (token.kind, token.text))
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Keyword, "let"),
+                (TokenKind::Identifier, "näme"),
+                (TokenKind::Operator, "="),
+                (TokenKind::Literal, "\"a \\\" b\""),
+                (TokenKind::Operator, "+"),
+                (TokenKind::Literal, "'c'"),
+                (TokenKind::Operator, ">="),
+                (TokenKind::Literal, "1.5e3"),
+                (TokenKind::Operator, ";"),
+                (TokenKind::Operator, "/"),
+                (TokenKind::Operator, "/"),
+                (TokenKind::Identifier, "x"),
+            ]
+        );
+        // Lifetimes are no character literals
+        assert_eq!(tokenize("&'a str")[1].text, "'");
+    }
+
+    #[test]
+    fn token_shingles_ignore_indentation_and_renames() {
+        let original = "+fn sum(values: &[u32]) -> u32 {\n+    values.iter().sum()\n+}\n";
+        let indented = "+  fn sum(values: &[u32]) -> u32 {\n+        values.iter().sum()\n+  }\n";
+        let renamed = "+fn total(items: &[u32]) -> u32 {\n+    items.iter().sum()\n+}\n";
+        let shingles = |shingling: Shingling, text: &str| {
+            let normalized = shingling.normalize(text);
+            let shingled = shingling.shingle(&normalized, 3);
+            shingled
+                .shingles()
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>()
+        };
+
+        assert_ne!(
+            shingles(Shingling::Characters, original),
+            shingles(Shingling::Characters, indented)
+        );
+        let tokens = shingles(Shingling::Tokens, original);
+        assert_eq!(tokens[0], "+\nfn\nsum");
+        assert_eq!(tokens, shingles(Shingling::Tokens, indented));
+        assert_ne!(tokens, shingles(Shingling::Tokens, renamed));
+        assert_eq!(
+            shingles(Shingling::AnonymizedTokens, original),
+            shingles(Shingling::AnonymizedTokens, renamed)
+        );
+        assert_eq!(shingles(Shingling::Tokens, " \n"), vec!["EMPTY"]);
+
+        let texts = [original, indented, renamed];
+        let (signatures, _) = preprocess_texts_with_vocabulary(
+            &texts,
+            Shingling::AnonymizedTokens,
+            3,
+            20,
+            None,
+            None,
+        );
+        assert_eq!(signatures[0], signatures[1]);
+        assert_eq!(signatures[0], signatures[2]);
+        assert_eq!(
+            "anonymized_tokens".parse::<Shingling>().unwrap(),
+            Shingling::AnonymizedTokens
+        );
+        assert!("words".parse::<Shingling>().is_err());
+    }
+
     #[test]
     fn one_hot_with_only_one_diff() {
         // We expect that all values in the one-hot encoding are 1
@@ -314,6 +918,47 @@ mod tests {
         assert_eq!(ones_in_intersection, 2);
     }
 
+    #[test]
+    fn pruned_vocabulary_only_contains_shared_shingles() {
+        let shingled_texts = vec![
+            ShingledText::new("a\nb\nc", 2),
+            ShingledText::new("b\nc\nd", 2),
+            // Repeated shingles of a single text are counted once
+            ShingledText::new("xxxx", 2),
+        ];
+        let vocabulary = Vocabulary::build_pruned(&shingled_texts, &VocabularyPruning::new(2));
+        assert_eq!(vocabulary.len(), 2);
+        let one_hot_first = vocabulary.one_hot(&shingled_texts[0]).unwrap();
+        assert_eq!(one_hot_first.iter().filter(|v| *v).count(), 2);
+        let one_hot_unique = vocabulary.one_hot(&shingled_texts[2]).unwrap();
+        assert!(one_hot_unique.none());
+
+        // The pruned shingles still distinguish the signatures of texts
+        let template = "fn function() {\n    return value;\n}\n";
+        let texts = [
+            format!("{template}// 1f8e"),
+            format!("{template}// 77a2"),
+            format!("{template}// 77a2"),
+            "xxxx".to_string(),
+        ];
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let pruning = VocabularyPruning::new(3);
+        let (signatures, vocabulary) = preprocess_texts_with_vocabulary(
+            &texts,
+            Shingling::Characters,
+            2,
+            50,
+            Some(&pruning),
+            None,
+        );
+        assert!(vocabulary < ShingledText::new(texts[0], 2).shingles.len());
+        assert!(signatures.iter().all(|s| s.len() == 50));
+        assert_ne!(signatures[0], signatures[1]);
+        assert_eq!(signatures[1], signatures[2]);
+        // Texts without any of the kept shingles share no values with other texts
+        assert!(signatures[3].iter().all(|v| !signatures[0].contains(v)));
+    }
+
     #[test]
     fn text_one_hot_similarity() {
         // We expect that all values in the one-hot encoding are 1
@@ -341,7 +986,7 @@ mod tests {
 
     #[test]
     fn simple_minhash_test() {
-        let minhash = MinHash::new(4, 6);
+        let minhash = MinHash::new(4);
 
         let mut one_hot_a = BitVec::from_elem(6, false);
         one_hot_a.set(0, true);
@@ -359,6 +1004,49 @@ mod tests {
         assert_ne!(signature_a, signature_b);
     }
 
+    #[test]
+    fn seeded_signatures_are_reproducible() {
+        let shingled_texts = vec![ShingledText::new(TEXT, 3), ShingledText::new(TEXT_FAR, 3)];
+        let first = Vocabulary::build_with_seed(&shingled_texts, 42);
+        let second = Vocabulary::build_with_seed(&shingled_texts, 42);
+        assert_eq!(first.shingles, second.shingles);
+
+        let one_hot = first.one_hot(&shingled_texts[0]).unwrap();
+        let minhash = MinHash::with_seed(16, 42);
+        let signature = minhash.hash_signature(&one_hot);
+        assert_eq!(
+            signature,
+            MinHash::with_seed(16, 42).hash_signature(&one_hot)
+        );
+
+        let texts = [TEXT, TEXT_CLOSE, TEXT_FAR];
+        let signatures = |seed| {
+            preprocess_texts_with_vocabulary(&texts, Shingling::Characters, 3, 32, None, seed).0
+        };
+        assert_eq!(signatures(Some(7)), signatures(Some(7)));
+        assert_ne!(signatures(Some(7)), signatures(Some(8)));
+    }
+
+    #[test]
+    fn shingle_signatures_estimate_jaccard_similarity() {
+        let minhash = MinHash::with_seed(1000, 7);
+        let a: Vec<String> = (0..300).map(|i| format!("shingle {i}")).collect();
+        let b: Vec<String> = (100..400).map(|i| format!("shingle {i}")).collect();
+        let signature_a = minhash.hash_shingles(a.iter().map(String::as_str));
+        let signature_b = minhash.hash_shingles(b.iter().map(String::as_str));
+
+        // Neither the order nor repetitions of shingles affect the signature
+        let shuffled = a.iter().rev().chain(&a).map(String::as_str);
+        assert_eq!(signature_a, minhash.hash_shingles(shuffled));
+        let equal = signature_a
+            .iter()
+            .zip(&signature_b)
+            .filter(|(a, b)| a == b)
+            .count();
+        // The Jaccard similarity of the shingles is 0.5
+        assert!((400..600).contains(&equal), "{equal}");
+    }
+
     #[test]
     fn text_signature_similarity() {
         let signatures = preprocess_texts(&[TEXT, TEXT_CLOSE, TEXT_FAR], 3, 8);
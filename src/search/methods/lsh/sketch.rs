@@ -0,0 +1,99 @@
+use crate::hashing::{splitmix64, StableHasher};
+use std::hash::{Hash, Hasher};
+
+/// The default number of counters per row of a [CountMinSketch]
+pub const DEFAULT_SKETCH_WIDTH: usize = 1 << 20;
+/// The default number of rows of a [CountMinSketch]
+pub const DEFAULT_SKETCH_DEPTH: usize = 4;
+
+/// A count-min sketch, which approximates the frequencies of items in a fixed amount of memory
+/// (`width * depth` counters), regardless of the number of distinct items.
+///
+/// Each item increments one counter per row, which is selected by a row-specific hash of the
+/// item. The hashes are stable (i.e., equal in every run), so that the estimates of the same items
+/// are equal in every run. The estimated frequency of an item is the smallest of its counters. Collisions only
+/// increase counters; thus, the estimate is never below the true frequency, but may be above it.
+#[derive(Debug, Clone)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u32>,
+}
+
+impl CountMinSketch {
+    /// Create an empty sketch with the given number of counters per row and number of rows
+    ///
+    /// # Panics
+    /// Panics if the width or depth is zero
+    pub fn new(width: usize, depth: usize) -> Self {
+        assert!(
+            width > 0 && depth > 0,
+            "a count-min sketch requires at least one counter"
+        );
+        Self {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+        }
+    }
+
+    /// Count one occurrence of the given item
+    pub fn add<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = hash_item(item);
+        for row in 0..self.depth {
+            let index = self.index(row, hash);
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+    }
+
+    /// The estimated number of occurrences of the given item, which is at least its true number
+    pub fn estimate<T: Hash + ?Sized>(&self, item: &T) -> u32 {
+        let hash = hash_item(item);
+        (0..self.depth)
+            .map(|row| self.counters[self.index(row, hash)])
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// The index of the counter of the item with the given hash in the given row
+    fn index(&self, row: usize, hash: u64) -> usize {
+        // Each row mixes the item's hash with a different offset
+        let row_hash = splitmix64(hash ^ row as u64);
+        row * self.width + (row_hash % self.width as u64) as usize
+    }
+}
+
+fn hash_item<T: Hash + ?Sized>(item: &T) -> u64 {
+    let mut hasher = StableHasher::default();
+    item.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::methods::lsh::sketch::CountMinSketch;
+
+    #[test]
+    fn estimates_are_never_below_the_frequency() {
+        // A tiny sketch, in which many items collide
+        let mut sketch = CountMinSketch::new(16, 2);
+        let items: Vec<String> = (0..100).map(|i| format!("item {i}")).collect();
+        for (i, item) in items.iter().enumerate() {
+            for _ in 0..i % 5 {
+                sketch.add(item.as_str());
+            }
+        }
+        for (i, item) in items.iter().enumerate() {
+            assert!(sketch.estimate(item.as_str()) >= (i % 5) as u32);
+        }
+
+        // Without collisions, the estimates are exact
+        let mut sketch = CountMinSketch::new(1 << 16, 4);
+        sketch.add("frequent");
+        sketch.add("frequent");
+        sketch.add("rare");
+        assert_eq!(sketch.estimate("frequent"), 2);
+        assert_eq!(sketch.estimate("rare"), 1);
+        assert_eq!(sketch.estimate("absent"), 0);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::git::Commit;
-use crate::search::SearchMethod;
+use crate::search::{CommitFacet, SearchMethod};
 use crate::{CherryAndTarget, SearchResult};
 use firestorm::profile_method;
 use git2::Oid;
@@ -18,55 +18,76 @@ use std::time::Instant;
 /// Lastly, it initializes a *SearchResult* for the commit whose message contained the text and the commit
 /// identified by the extracted hash.
 ///
+/// Hashes are resolved against the commits of all searched repositories, so that references to
+/// cherries that only live in another repository of a fork network are found as well. Abbreviated
+/// hashes are resolved if they match exactly one commit. Each result records whether the reference
+/// crossed repositories (see SearchResult::cross_repository).
+///
 /// Under the assumption that commit messages have not been corrupted with invalid
 /// *(cherry picked from...)* text deliberately, this search will only return correct results.
 /// However, the search cannot guarantee to find all cherry picks, because the commit message text
 /// is only generated if developers specify the *-x* option while using
 /// `git cherry-pick`. Thus, the search cannot find cherry picks that were done without the option,
 /// or that were done manually (i.e., copy-paste).  
-#[derive(Default)]
-pub struct MessageScan();
+///
+/// Projects that backport with their own tooling often use other, e.g., translated, markers (such
+/// as `(rétroporté depuis le commit SOME_HASH)`). Such markers can be added with
+/// [MessageScan::with_markers].
+pub struct MessageScan {
+    markers: Vec<String>,
+}
+
+impl Default for MessageScan {
+    fn default() -> Self {
+        Self {
+            markers: vec![SEARCH_STR.to_string()],
+        }
+    }
+}
+
+impl MessageScan {
+    /// Additionally recognize the given markers. The (possibly abbreviated) hash of the cherry
+    /// must follow a marker, optionally separated by whitespace. Markers are tried in order, after
+    /// the default marker of git.
+    pub fn with_markers<I: IntoIterator<Item = String>>(mut self, markers: I) -> Self {
+        self.markers
+            .extend(markers.into_iter().filter(|marker| !marker.is_empty()));
+        self
+    }
+}
 
 const NAME: &str = "MessageScan";
 
+const SEARCH_STR: &str = "(cherry picked from commit ";
+
+/// The shortest abbreviated hash that is resolved; shorter hashes are too ambiguous
+const MIN_HASH_LENGTH: usize = 7;
+
 impl SearchMethod for MessageScan {
     fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
         profile_method!(search);
         let start = Instant::now();
-        let mut commit_map = HashMap::with_capacity(commits.len());
-        commits.iter().for_each(|c| {
-            commit_map.insert(c.id(), c);
-        });
-
-        let search_str = "(cherry picked from commit ";
-        let results: HashSet<SearchResult> = commits
-            .iter()
-            .filter_map(|c| {
-                if let Some(index) = c.message().and_then(|m| m.find(search_str)) {
-                    let index = index + search_str.len();
-                    let message = c.message().unwrap();
-                    // Filter merged pull requests that list the commit message of all merged
-                    // commits and thus may contain the search string
-                    if message.trim_start().starts_with("Merge ") {
-                        return None;
-                    }
-                    if let Some(end_index) = message[index..].find(')') {
-                        // we have to increase the end_index by the number of bytes that were cut off through slicing
-                        let end_index = end_index + index;
-                        let cherry_id = Oid::from_str(&message[index..end_index]);
-
-                        if let Some(cherry) = cherry_id.ok().and_then(|id| commit_map.get(&id)) {
-                            return Some(SearchResult::new(
-                                String::from(NAME),
-                                // Pair of Source-Target
-                                CherryAndTarget::new(cherry, c),
-                            ));
-                        }
-                    }
-                }
-                None
-            })
-            .collect();
+        let index = HashIndex::new(commits);
+        let mut stats = ReferenceStats::default();
+        let results = scan_references(commits, &index, &self.markers, &mut stats);
+        stats.log();
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        results
+    }
+
+    fn search_across(&self, left: &mut [Commit], right: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search_across);
+        let start = Instant::now();
+        // References are only resolved against the commits of the other side
+        let mut stats = ReferenceStats::default();
+        let mut results = scan_references(left, &HashIndex::new(right), &self.markers, &mut stats);
+        results.extend(scan_references(
+            right,
+            &HashIndex::new(left),
+            &self.markers,
+            &mut stats,
+        ));
+        stats.log();
         debug!("found {} results in {:?}", results.len(), start.elapsed());
         results
     }
@@ -74,4 +95,251 @@ impl SearchMethod for MessageScan {
     fn name(&self) -> &'static str {
         NAME
     }
+
+    fn required_facet(&self) -> CommitFacet {
+        CommitFacet::Metadata
+    }
+}
+
+/// The (possibly abbreviated) hash of the cherry that is referenced in the given message by one of
+/// the given markers, if any
+fn referenced_hash<'m>(message: &'m str, markers: &[String]) -> Option<&'m str> {
+    // Filter merged pull requests that list the commit message of all merged
+    // commits and thus may contain the search string
+    if message.trim_start().starts_with("Merge ") {
+        return None;
+    }
+    markers.iter().find_map(|marker| {
+        // A match ends on a char boundary, so slicing at its end is safe for multibyte messages
+        let rest = message[message.find(marker.as_str())? + marker.len()..].trim_start();
+        let length = rest.bytes().take_while(u8::is_ascii_hexdigit).count();
+        (length > 0).then(|| &rest[..length])
+    })
+}
+
+/// Creates a result for each of the given targets whose message references a cherry in the index
+fn scan_references(
+    targets: &[Commit],
+    index: &HashIndex,
+    markers: &[String],
+    stats: &mut ReferenceStats,
+) -> HashSet<SearchResult> {
+    targets
+        .iter()
+        .filter_map(|c| {
+            let message = c.message()?;
+            let hash = referenced_hash(message, markers)?;
+            stats.references += 1;
+            let cherry = match index.resolve(hash) {
+                HashLookup::Found(cherry) => cherry,
+                HashLookup::Unknown => {
+                    stats.unknown += 1;
+                    return None;
+                }
+                HashLookup::Ambiguous => {
+                    stats.ambiguous += 1;
+                    return None;
+                }
+            };
+            // The cherry lives in another repository of the network, if the target's
+            // repository does not contain it
+            let cross_repository = c.repository().find_commit(cherry.id()).is_err();
+            if cross_repository {
+                stats.cross_repository += 1;
+            }
+            let mut result = SearchResult::new(
+                String::from(NAME),
+                // Pair of Source-Target
+                CherryAndTarget::new(cherry, c),
+            )
+            // Referenced cherries are certain
+            .with_score(1.0);
+            result.set_cross_repository(Some(cross_repository));
+            Some(result)
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct ReferenceStats {
+    references: usize,
+    cross_repository: usize,
+    unknown: usize,
+    ambiguous: usize,
+}
+
+impl ReferenceStats {
+    fn log(&self) {
+        debug!(
+            "resolved {} of {} references ({} across repositories, {} unknown, {} ambiguous)",
+            self.references - self.unknown - self.ambiguous,
+            self.references,
+            self.cross_repository,
+            self.unknown,
+            self.ambiguous
+        );
+    }
+}
+
+/// The outcome of resolving a hash with a HashIndex
+enum HashLookup<'a, 'r, 'c> {
+    Found(&'a Commit<'r, 'c>),
+    Unknown,
+    /// The abbreviated hash matches several commits
+    Ambiguous,
+}
+
+/// Maps the full and abbreviated hashes of all commits of a fork network to the commits. The index
+/// is built once per search over the commits of all repositories, so that references can be
+/// resolved regardless of the repository in which the referenced commit lives.
+struct HashIndex<'a, 'r, 'c> {
+    by_id: HashMap<Oid, &'a Commit<'r, 'c>>,
+    by_prefix: HashMap<String, Vec<&'a Commit<'r, 'c>>>,
+}
+
+impl<'a, 'r, 'c> HashIndex<'a, 'r, 'c> {
+    fn new(commits: &'a [Commit<'r, 'c>]) -> Self {
+        let mut by_id = HashMap::with_capacity(commits.len());
+        for commit in commits {
+            // Pseudo-commits of the same merge share an id
+            by_id.entry(commit.id()).or_insert(commit);
+        }
+        let mut by_prefix: HashMap<String, Vec<&Commit>> = HashMap::with_capacity(by_id.len());
+        for commit in by_id.values() {
+            // Abbreviated hashes are matched by their shortest allowed prefix
+            let mut prefix = commit.id().to_string();
+            prefix.truncate(MIN_HASH_LENGTH);
+            by_prefix.entry(prefix).or_default().push(commit);
+        }
+        Self { by_id, by_prefix }
+    }
+
+    fn resolve(&self, hash: &str) -> HashLookup<'a, 'r, 'c> {
+        if hash.len() < MIN_HASH_LENGTH || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return HashLookup::Unknown;
+        }
+        let hash = hash.to_ascii_lowercase();
+        if let Some(commit) = Oid::from_str(&hash).ok().and_then(|id| self.by_id.get(&id)) {
+            return HashLookup::Found(commit);
+        }
+        let mut matches = self
+            .by_prefix
+            .get(&hash[..MIN_HASH_LENGTH])
+            .into_iter()
+            .flatten()
+            .filter(|c| c.id().to_string().starts_with(&hash));
+        match (matches.next(), matches.next()) {
+            (Some(commit), None) => HashLookup::Found(commit),
+            (Some(_), Some(_)) => HashLookup::Ambiguous,
+            (None, _) => HashLookup::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::methods::message_scan::{referenced_hash, SEARCH_STR};
+    use crate::{MessageScan, SearchMethod};
+
+    #[test]
+    fn references_are_extracted() {
+        let markers = [SEARCH_STR.to_string()];
+        assert_eq!(
+            referenced_hash(
+                "Fix parser\n\n(cherry picked from commit 4e39e24)",
+                &markers
+            ),
+            Some("4e39e24")
+        );
+        assert_eq!(
+            referenced_hash(
+                "Merge branch 'fix'\n\n(cherry picked from commit 4e39e24)",
+                &markers
+            ),
+            None
+        );
+        assert_eq!(referenced_hash("Fix parser", &markers), None);
+        // Multibyte characters around the marker
+        assert_eq!(
+            referenced_hash(
+                "修复解析器 (cherry picked from commit 4e39e24)修复",
+                &markers
+            ),
+            Some("4e39e24")
+        );
+        assert_eq!(
+            referenced_hash("Réparer (cherry picked from commit é)", &markers),
+            None
+        );
+    }
+
+    #[test]
+    fn additional_markers_are_recognized() {
+        let scan = MessageScan::default().with_markers(["(rétroporté depuis ".to_string()]);
+        assert_eq!(
+            referenced_hash("Réparer\n\n(rétroporté depuis  4E39E24)", &scan.markers),
+            Some("4E39E24")
+        );
+        assert_eq!(
+            referenced_hash("Réparer\n\n(rétroporté depuis le commit)", &scan.markers),
+            None
+        );
+    }
+
+    #[test]
+    fn latin1_messages_are_scanned() {
+        let fixture = TestRepository::new();
+        let cherry = fixture.commit("main", &[], &[], "Réparer l'analyseur");
+        let repository = fixture.repository();
+        let tree = repository.find_commit(cherry).unwrap().tree_id();
+        let mut raw = format!(
+            "tree {tree}\nparent {cherry}\nauthor test <test@example.com> 0 +0000\n\
+             committer test <test@example.com> 0 +0000\nencoding ISO-8859-1\n\n"
+        )
+        .into_bytes();
+        raw.extend(b"R\xe9parer l'analyseur\n\n");
+        raw.extend(format!("(cherry picked from commit {cherry})\n").into_bytes());
+        let target = repository
+            .odb()
+            .unwrap()
+            .write(git2::ObjectType::Commit, &raw)
+            .unwrap();
+        repository
+            .reference("refs/heads/main", target, true, "")
+            .unwrap();
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<_> = collect_commits(&repos).into_iter().collect();
+        let target_commit = commits.iter().find(|c| c.id() == target).unwrap();
+        assert!(target_commit.message().unwrap().starts_with("Réparer"));
+        let results = MessageScan::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result = results.iter().next().unwrap();
+        assert_eq!(result.commit_pair().cherry().id(), cherry.to_string());
+    }
+
+    #[test]
+    fn abbreviated_references_resolve_across_repositories() {
+        let upstream = TestRepository::new();
+        let fork = TestRepository::new();
+
+        let cherry = upstream.commit("main", &[], &[], "Fix parser");
+        let abbreviated = &cherry.to_string()[..10];
+        let message = format!("Fix parser\n\n(cherry picked from commit {abbreviated})");
+        let target = fork.commit("main", &[], &[], &message);
+
+        let (_upstream_dir, upstream) = upstream.into_loaded();
+        let (_fork_dir, fork) = fork.into_loaded();
+        let repos = [upstream, fork];
+        let mut commits: Vec<_> = collect_commits(&repos).into_iter().collect();
+        let results = MessageScan::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result = results.iter().next().unwrap();
+        assert_eq!(result.commit_pair().cherry().id(), cherry.to_string());
+        assert_eq!(result.commit_pair().target().id(), target.to_string());
+        assert_eq!(result.cross_repository(), Some(true));
+    }
 }
@@ -0,0 +1,285 @@
+use crate::git::Commit;
+use crate::search::ancestry::Ancestry;
+use crate::search::{CommitFacet, SearchCounters, SearchMethod};
+use crate::{CherryAndTarget, SearchResult};
+use firestorm::{profile_fn, profile_method};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+pub const NAME: &str = "MessageSimilarity";
+
+/// MessageSimilarity identifies cherry picks by the similarity of their commit messages.
+///
+/// Developers who pick commits manually (e.g., by applying a patch, or with `git cherry-pick`
+/// without *-x*) usually copy the message of the cherry, sometimes with small adjustments such as
+/// a `[backport]` prefix. Such picks are not found by MessageScan, which relies on the text that
+/// git adds with *-x*.
+///
+/// Each message is normalized first: Trailers (e.g., `Signed-off-by: ...`) and cherry-pick
+/// markers are removed, and the remaining text is split into lowercase words. The words are
+/// shingled, and all pairs of commits whose shingles have a Jaccard similarity of at least the
+/// threshold are reported. Messages of merges and messages with fewer words than the minimum
+/// (e.g., GitHub's default `Update README.md`) are ignored, because they are equal for many
+/// unrelated commits. As for ExactDiffMatch, the older commit of a pair is considered the cherry.
+///
+/// Only the messages are compared, so the results are candidates that should be confirmed by other
+/// methods (e.g., by the similarity of the diffs).
+pub struct MessageSimilarity {
+    threshold: f64,
+    arity: usize,
+    min_words: usize,
+    last_counters: Mutex<Option<SearchCounters>>,
+}
+
+impl Default for MessageSimilarity {
+    fn default() -> Self {
+        Self::new(0.8)
+    }
+}
+
+impl MessageSimilarity {
+    /// Initialize the search with the lowest similarity in the interval `[0, 1]` that the messages
+    /// of a reported pair must have. Messages are shingled into pairs of words.
+    pub fn new(similarity_threshold: f64) -> Self {
+        Self {
+            threshold: similarity_threshold,
+            arity: 2,
+            min_words: 4,
+            last_counters: Mutex::new(None),
+        }
+    }
+
+    /// Set the number of consecutive words per shingle. Single words (i.e., an arity of 1) ignore
+    /// the order of the words.
+    pub fn with_arity(mut self, arity: usize) -> Self {
+        self.arity = arity.max(1);
+        self
+    }
+
+    /// Set the lowest number of words that a normalized message must have to be compared
+    pub fn with_min_words(mut self, min_words: usize) -> Self {
+        self.min_words = min_words;
+        self
+    }
+
+    /// The shingles of the given message, or None if the message is not compared
+    fn shingles(&self, message: &str) -> Option<HashSet<String>> {
+        if message.trim_start().starts_with("Merge ") {
+            return None;
+        }
+        let words = words(message);
+        if words.is_empty() || words.len() < self.min_words {
+            return None;
+        }
+        let shingles = words
+            .windows(self.arity.min(words.len()))
+            .map(|window| window.join(" "))
+            .collect();
+        Some(shingles)
+    }
+}
+
+impl SearchMethod for MessageSimilarity {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let start = Instant::now();
+        let shingled: Vec<(&Commit, HashSet<String>)> = commits
+            .iter()
+            .filter_map(|commit| Some((commit, self.shingles(commit.message()?)?)))
+            .collect();
+        debug!(
+            "comparing the messages of {} of {} commits",
+            shingled.len(),
+            commits.len()
+        );
+
+        let ancestry = Ancestry::default();
+        let mut results = HashSet::new();
+        let candidates = candidate_pairs(&shingled, self.threshold);
+        debug!("found {} candidate pairs", candidates.len());
+        let counters = SearchCounters::new(candidates.len(), candidates.len());
+        for (a, b) in candidates {
+            let (commit_a, shingles_a) = &shingled[a];
+            let (commit_b, shingles_b) = &shingled[b];
+            if commit_a.id() == commit_b.id() {
+                continue;
+            }
+            let common = shingles_a.intersection(shingles_b).count();
+            let similarity = common as f64 / (shingles_a.len() + shingles_b.len() - common) as f64;
+            if similarity >= self.threshold {
+                results.insert(
+                    SearchResult::new(
+                        NAME.to_string(),
+                        CherryAndTarget::construct_with(&ancestry, commit_a, commit_b),
+                    )
+                    .with_score(similarity),
+                );
+            }
+        }
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        *self
+            .last_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(counters);
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn last_counters(&self) -> Option<SearchCounters> {
+        *self
+            .last_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn required_facet(&self) -> CommitFacet {
+        CommitFacet::Metadata
+    }
+}
+
+/// The lowercase words of the given message, without its trailers and cherry-pick markers
+fn words(message: &str) -> Vec<String> {
+    message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !is_trailer(line) && !line.starts_with("(cherry picked from commit "))
+        .flat_map(|line| line.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether the given line is a trailer, such as `Signed-off-by: Jane Doe <jane@example.com>`
+fn is_trailer(line: &str) -> bool {
+    match line.split_once(": ") {
+        Some((key, _)) => {
+            key.contains('-') && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => false,
+    }
+}
+
+/// The indices of all pairs of shingle sets that may reach the threshold.
+///
+/// To avoid comparing all pairs, the shingles of each set are ordered by their frequency, and only
+/// sets that share one of their rarest shingles are paired (i.e., prefix filtering). A set with n
+/// shingles can only reach the threshold with another set if they share at least one of its
+/// `n - ceil(threshold * n) + 1` rarest shingles.
+fn candidate_pairs(
+    shingled: &[(&Commit, HashSet<String>)],
+    threshold: f64,
+) -> HashSet<(usize, usize)> {
+    profile_fn!(candidate_pairs);
+    if threshold <= 0.0 {
+        // Sets without any common shingle are similar enough as well
+        return (0..shingled.len())
+            .flat_map(|a| (a + 1..shingled.len()).map(move |b| (a, b)))
+            .collect();
+    }
+    let mut frequencies: HashMap<&str, usize> = HashMap::new();
+    for (_, shingles) in shingled {
+        for shingle in shingles {
+            *frequencies.entry(shingle).or_default() += 1;
+        }
+    }
+
+    let mut index: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, (_, shingles)) in shingled.iter().enumerate() {
+        let mut ordered: Vec<&str> = shingles.iter().map(String::as_str).collect();
+        ordered.sort_unstable_by_key(|shingle| (frequencies[shingle], *shingle));
+        let required = (threshold.min(1.0) * ordered.len() as f64).ceil() as usize;
+        let prefix = (ordered.len() + 1).saturating_sub(required.max(1));
+        for shingle in &ordered[..prefix] {
+            index.entry(shingle).or_default().push(i);
+        }
+    }
+    index
+        .values()
+        .flat_map(|group| {
+            group
+                .iter()
+                .enumerate()
+                .flat_map(move |(i, a)| group[i + 1..].iter().map(move |b| (*a, *b)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::methods::message_similarity::words;
+    use crate::{Commit, MessageSimilarity, SearchMethod};
+
+    #[test]
+    fn messages_are_normalized() {
+        let message = "Fix the parser's handling of UTF-8\n\n\
+            Signed-off-by: Jane Doe <jane@example.com>\n\
+            (cherry picked from commit 6a5c6e1a3b)";
+        assert_eq!(
+            words(message),
+            vec!["fix", "the", "parser", "s", "handling", "of", "utf", "8"]
+        );
+    }
+
+    #[test]
+    fn picks_with_copied_messages_are_found() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("main", "a\nb\nc\n", None, "Initial import of the project");
+        let fix = fixture.commit_file(
+            "main",
+            "a\nB\nc\n",
+            Some(base),
+            "Fix the overflow in the line counter of the parser",
+        );
+        fixture.commit_file("main", "a\nB\nC\n", Some(fix), "Update README.md");
+        let release = fixture.commit_file("release", "a\nb\n", Some(base), "Prepare the release");
+        // The pick was adapted and its message was copied with a prefix and a trailer
+        let pick = fixture.commit_file(
+            "release",
+            "a\nB\n",
+            Some(release),
+            "[backport] Fix the overflow in the line counter of the parser\n\n\
+            Signed-off-by: Jane Doe <jane@example.com>",
+        );
+        fixture.commit_file("release", "a\nB\nD\n", Some(pick), "Update README.md");
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+
+        let results = MessageSimilarity::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result = results.into_iter().next().unwrap();
+        assert_eq!(result.search_method(), "MessageSimilarity");
+        // 9 of the 10 shingles of the pick's message are shared
+        assert_eq!(result.score(), Some(0.9));
+        let mut subjects: Vec<&str> = result
+            .commit_pair()
+            .as_vec()
+            .into_iter()
+            .map(|c| c.message().lines().next().unwrap())
+            .collect();
+        subjects.sort();
+        assert_eq!(
+            subjects,
+            vec![
+                "Fix the overflow in the line counter of the parser",
+                "[backport] Fix the overflow in the line counter of the parser"
+            ]
+        );
+
+        // Short messages are compared if the minimum is lowered
+        let results = MessageSimilarity::default()
+            .with_min_words(1)
+            .search(&mut commits);
+        assert_eq!(results.len(), 2);
+        // Messages that share few words are not paired, even with a low threshold
+        let results = MessageSimilarity::new(0.1).search(&mut commits);
+        assert_eq!(results.len(), 1);
+    }
+}
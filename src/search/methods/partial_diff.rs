@@ -0,0 +1,254 @@
+use crate::error::{Error, ErrorKind};
+use crate::git::{calculate_diffs, Commit, Hunk};
+use crate::hashing::StableHasher;
+use crate::search::ancestry::Ancestry;
+use crate::search::methods::lsh::HunkMatch;
+use crate::{CherryAndTarget, Result, SearchMethod, SearchResult};
+use firestorm::{profile_fn, profile_method};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+
+pub const NAME: &str = "PartialDiffMatch";
+
+/// The hunks that are shared by more commits are not indexed by default
+pub const DEFAULT_MAX_HUNK_COMMITS: usize = 50;
+
+/// PartialDiffMatch identifies cherry picks whose diffs only partially match, e.g., because the
+/// pick was squashed with other changes, or because only some of the cherry's changes were picked.
+///
+/// Unlike ExactDiffMatch, which requires the whole diffs of two commits to match, PartialDiffMatch
+/// indexes the individual hunks of all diffs by the hash of their files and normalized bodies, in
+/// which the leading and trailing whitespace of each line is ignored. Two commits are reported if they share
+/// at least the configured fraction of the hunks of the commit with fewer hunks. Thus, a cherry
+/// whose hunks are all contained in a larger squashed commit is found with a fraction of 1.0.
+///
+/// Each result records the matched hunks (see [SearchResult::hunk_matches]) and is scored with the
+/// fraction of shared hunks. As for ExactDiffMatch, the older commit of a pair is considered the
+/// cherry.
+///
+/// Hunks that many commits share (e.g., the bump of a version number) are no evidence of a pick
+/// and would pair all of these commits, so hunks of more than `max_hunk_commits` commits are not
+/// indexed.
+pub struct PartialDiffMatch {
+    min_fraction: f64,
+    max_hunk_commits: usize,
+}
+
+impl Default for PartialDiffMatch {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl PartialDiffMatch {
+    /// Initialize the search with the lowest fraction in the interval `(0, 1]` of hunks that two
+    /// commits must share
+    ///
+    /// # Panics
+    /// This function panics if the fraction is not in the interval `(0, 1]` (see
+    /// [PartialDiffMatch::try_new]).
+    pub fn new(min_fraction: f64) -> Self {
+        Self::try_new(min_fraction).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Initialize the search with the lowest fraction in the interval `(0, 1]` of hunks that two
+    /// commits must share
+    ///
+    /// # Errors
+    /// Returns a Configuration error if the fraction is not in the interval `(0, 1]`. With a
+    /// fraction of 0, all pairs of commits that share any hunk would be reported.
+    pub fn try_new(min_fraction: f64) -> Result<Self> {
+        if !(min_fraction > 0.0 && min_fraction <= 1.0) {
+            return Err(Error::new(ErrorKind::Configuration(format!(
+                "the minimum fraction of shared hunks of {NAME} must be in the interval (0, 1], \
+                 but is {min_fraction}"
+            ))));
+        }
+        Ok(Self {
+            min_fraction,
+            max_hunk_commits: DEFAULT_MAX_HUNK_COMMITS,
+        })
+    }
+
+    /// Do not index hunks that are shared by more than the given number of commits
+    /// ([DEFAULT_MAX_HUNK_COMMITS] by default)
+    pub fn with_max_hunk_commits(mut self, max_hunk_commits: usize) -> Self {
+        self.max_hunk_commits = max_hunk_commits;
+        self
+    }
+}
+
+impl SearchMethod for PartialDiffMatch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let start = Instant::now();
+        calculate_diffs(commits);
+        // The distinct hunks of each commit, mapped to the index of their first occurrence
+        let hunks: Vec<HashMap<u64, usize>> = commits.iter().map(hunk_hashes).collect();
+        let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (commit, hashes) in hunks.iter().enumerate() {
+            for hash in hashes.keys() {
+                index.entry(*hash).or_default().push(commit);
+            }
+        }
+
+        // Count the shared hunks of all pairs of commits that share any indexed hunk
+        let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+        for postings in index.values() {
+            if postings.len() < 2 || postings.len() > self.max_hunk_commits {
+                continue;
+            }
+            for (i, a) in postings.iter().enumerate() {
+                for b in &postings[i + 1..] {
+                    *shared.entry((*a, *b)).or_default() += 1;
+                }
+            }
+        }
+
+        let ancestry = Ancestry::default();
+        let mut results = HashSet::new();
+        for ((a, b), count) in shared {
+            let (commit_a, commit_b) = (&commits[a], &commits[b]);
+            let fraction = count as f64 / hunks[a].len().min(hunks[b].len()) as f64;
+            if fraction < self.min_fraction || commit_a.id() == commit_b.id() {
+                continue;
+            }
+            let pair = CherryAndTarget::construct_with(&ancestry, commit_a, commit_b);
+            let (cherry, target) = match pair.cherry().id() == commit_a.id().to_string() {
+                true => (&hunks[a], &hunks[b]),
+                false => (&hunks[b], &hunks[a]),
+            };
+            let mut result = SearchResult::new(NAME.to_string(), pair).with_score(fraction);
+            result.set_hunk_matches(Some(matched_hunks(cherry, target)));
+            results.insert(result);
+        }
+        debug!(
+            "found {} results among {} distinct hunks in {:?}",
+            results.len(),
+            index.len(),
+            start.elapsed()
+        );
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        NAME
+    }
+}
+
+/// The hashes of the files and normalized bodies of the hunks of the given commit, whose diff must
+/// have been calculated, mapped to the index of the first hunk with the hash
+fn hunk_hashes(commit: &Commit) -> HashMap<u64, usize> {
+    profile_fn!(hunk_hashes);
+    let mut hashes = HashMap::new();
+    for (index, hunk) in commit.diff().hunks.iter().enumerate() {
+        hashes.entry(normalized_hash(hunk)).or_insert(index);
+    }
+    hashes
+}
+
+/// The hash of the hunk's file and body, in which the leading and trailing whitespace of each line
+/// is ignored. Equal changes to different files (e.g., the same import added to two modules) are
+/// not the same change. The hash is stable, so that results do not depend on the Rust release.
+fn normalized_hash(hunk: &Hunk) -> u64 {
+    let mut hasher = StableHasher::default();
+    // Deleted files only have an old file
+    hunk.new_file()
+        .as_ref()
+        .or(hunk.old_file().as_ref())
+        .hash(&mut hasher);
+    for line in hunk.body() {
+        line.line_type().hash(&mut hasher);
+        line.content().trim().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// The hunks of the cherry that are also hunks of the target, ordered by the cherry's hunks
+fn matched_hunks(cherry: &HashMap<u64, usize>, target: &HashMap<u64, usize>) -> Vec<HunkMatch> {
+    let mut matches: Vec<HunkMatch> = cherry
+        .iter()
+        .filter_map(|(hash, cherry_hunk)| {
+            Some(HunkMatch {
+                cherry_hunk: *cherry_hunk,
+                target_hunk: Some(*target.get(hash)?),
+                similarity: 1.0,
+            })
+        })
+        .collect();
+    matches.sort_by_key(|m| m.cherry_hunk);
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::{signature_at, TestRepository};
+    use crate::{Commit, ExactDiffMatch, PartialDiffMatch, SearchMethod};
+    use git2::Oid;
+
+    #[test]
+    fn squashed_picks_are_found() {
+        let fixture = TestRepository::new();
+        // Each commit is named after its branch
+        let commit = |branch: &str, [notes, main]: [&str; 2], parents: &[Oid], time| {
+            let files = [("a_notes", notes), ("b_main", main)];
+            fixture.commit_as(&signature_at(time), branch, &files, parents, branch)
+        };
+        let base = commit("main", ["x\n", "a\nb\nc\n"], &[], 100);
+        commit("main", ["x\n", "a\nB\nc\n"], &[base], 200);
+        commit("backport", ["x\n", "a\nB\nc\n"], &[base], 300);
+        // The fix was squashed with unrelated changes, and its line was indented
+        commit("release", ["y\n", "a\n  B\nc\n"], &[base], 400);
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        assert_eq!(ExactDiffMatch::default().search(&mut commits).len(), 1);
+
+        let results = PartialDiffMatch::new(1.0).search(&mut commits);
+        assert_eq!(results.len(), 3);
+        let squash = results
+            .iter()
+            .find(|result| {
+                result.commit_pair().target().message() == "release"
+                    && result.commit_pair().cherry().message() == "main"
+            })
+            .unwrap();
+        assert_eq!(squash.search_method(), "PartialDiffMatch");
+        assert_eq!(squash.score(), Some(1.0));
+        let matches = squash.hunk_matches().unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            (matches[0].cherry_hunk, matches[0].target_hunk),
+            (0, Some(1))
+        );
+
+        // Hunks that too many commits share are not indexed
+        let results = PartialDiffMatch::new(0.5)
+            .with_max_hunk_commits(2)
+            .search(&mut commits);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn equal_changes_to_other_files_are_not_matched() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit("main", &[("a", "x\n"), ("b", "x\n")], &[], "base");
+        fixture.commit("main", &[("a", "y\n"), ("b", "x\n")], &[base], "a");
+        fixture.commit("other", &[("a", "x\n"), ("b", "y\n")], &[base], "b");
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        assert!(PartialDiffMatch::new(1.0).search(&mut commits).is_empty());
+    }
+
+    #[test]
+    fn fractions_outside_the_unit_interval_are_rejected() {
+        for fraction in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(PartialDiffMatch::try_new(fraction).is_err());
+        }
+        assert!(PartialDiffMatch::try_new(1.0).is_ok());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::fingerprint::patch_id;
+use crate::git::{calculate_diffs, Commit};
+use crate::search::ancestry::Ancestry;
+use crate::{CherryAndTarget, SearchMethod, SearchResult};
+use firestorm::profile_method;
+use git2::Oid;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+pub const NAME: &str = "PatchIdMatch";
+
+/// PatchIdMatch identifies cherry picks by comparing the patch-ids of commits, as computed by
+/// `git patch-id --stable`.
+///
+/// A patch-id is a hash of the changed files and lines of a diff. Line numbers and whitespace are
+/// ignored, so that PatchIdMatch also finds picks whose hunks were applied at a different offset
+/// (e.g., because lines were added above the changed lines), which ExactDiffMatch misses. Context
+/// lines are part of the patch-id; use ExactChangeMatch to ignore them as well.
+///
+/// As for ExactDiffMatch, all pairwise combinations of commits with the same patch-id are
+/// reported, and the older commit of a pair is considered the cherry.
+#[derive(Default)]
+pub struct PatchIdMatch();
+
+impl SearchMethod for PatchIdMatch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let start = Instant::now();
+        calculate_diffs(commits);
+        let mut by_patch_id: HashMap<Oid, Vec<&Commit>> = HashMap::new();
+        for commit in commits.iter() {
+            // Patch-ids of commits without changes (e.g., empty merges) are all equal
+            if commit.diff().hunks.is_empty() {
+                continue;
+            }
+            if let Some(id) = patch_id(commit) {
+                by_patch_id.entry(id).or_default().push(commit);
+            }
+        }
+
+        let ancestry = Ancestry::default();
+        let mut results = HashSet::new();
+        for group in by_patch_id.values().filter(|group| group.len() > 1) {
+            for (i, commit_a) in group.iter().enumerate() {
+                for commit_b in group.iter().skip(i + 1) {
+                    if commit_a.id() != commit_b.id() {
+                        results.insert(
+                            SearchResult::new(
+                                NAME.to_string(),
+                                CherryAndTarget::construct_with(&ancestry, commit_a, commit_b),
+                            )
+                            .with_score(1.0),
+                        );
+                    }
+                }
+            }
+        }
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::{Commit, ExactDiffMatch, PatchIdMatch, SearchMethod};
+
+    #[test]
+    fn picks_with_shifted_hunks_are_found() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit_file("main", "a\nb\nc\nd\ne\nf\ng\n", None, "base");
+        fixture.commit_file("main", "a\nb\nc\nD\ne\nf\ng\n", Some(base), "fix");
+        // The release branch has two additional lines above the fix, which shifts its hunk
+        let release = fixture.commit_file(
+            "release",
+            "0\n1\na\nb\nc\nd\ne\nf\ng\n",
+            Some(base),
+            "release",
+        );
+        fixture.commit_file(
+            "release",
+            "0\n1\na\nb\nc\n  D\ne\nf\ng\n",
+            Some(release),
+            "pick",
+        );
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        assert!(ExactDiffMatch::default().search(&mut commits).is_empty());
+
+        let results = PatchIdMatch::default().search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let result = results.into_iter().next().unwrap();
+        assert_eq!(result.search_method(), "PatchIdMatch");
+        let mut messages: Vec<&str> = result
+            .commit_pair()
+            .as_vec()
+            .into_iter()
+            .map(|c| c.message())
+            .collect();
+        messages.sort();
+        assert_eq!(messages, vec!["fix", "pick"]);
+    }
+}
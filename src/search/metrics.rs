@@ -0,0 +1,132 @@
+use crate::git::Commit;
+use crate::{SearchMethod, SearchResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::time::Instant;
+
+/// The work of a search method that compares candidate pairs of commits (e.g., TraditionalLSH),
+/// as reported by [SearchMethod::last_counters]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchCounters {
+    /// The largest number of candidate pairs that a single search selected for comparison
+    pub peak_candidates: usize,
+    /// The number of similarity comparisons of pairs of commits
+    pub comparisons: usize,
+}
+
+impl SearchCounters {
+    pub fn new(candidates: usize, comparisons: usize) -> Self {
+        Self {
+            peak_candidates: candidates,
+            comparisons,
+        }
+    }
+
+    /// Combine the counters of two searches, e.g., of two shards: the peaks of the candidates are
+    /// kept, and the comparisons add up.
+    pub fn merge(self, other: SearchCounters) -> Self {
+        Self {
+            peak_candidates: self.peak_candidates.max(other.peak_candidates),
+            comparisons: self.comparisons + other.comparisons,
+        }
+    }
+}
+
+/// The performance of one search method in one search, so that regressions across method
+/// parameters can be tracked (see [crate::search_with_metrics])
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodMetrics {
+    pub method: String,
+    /// The wall-clock time of the search in milliseconds
+    pub elapsed_ms: u64,
+    /// The number of searched commits
+    pub commits: usize,
+    /// The number of results
+    pub results: usize,
+    /// The candidates and comparisons, if the method reports them (see
+    /// [SearchMethod::last_counters])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counters: Option<SearchCounters>,
+}
+
+impl MethodMetrics {
+    /// Search the given commits with the given method and measure the search
+    pub fn measure(
+        method: &dyn SearchMethod,
+        commits: &mut [Commit],
+    ) -> (HashSet<SearchResult>, MethodMetrics) {
+        let start = Instant::now();
+        let results = method.search(commits);
+        let metrics = MethodMetrics {
+            method: method.name().to_string(),
+            elapsed_ms: start.elapsed().as_millis() as u64,
+            commits: commits.len(),
+            results: results.len(),
+            counters: method.last_counters(),
+        };
+        (results, metrics)
+    }
+}
+
+impl Display for MethodMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} found {} results among {} commits in {}ms",
+            self.method, self.results, self.commits, self.elapsed_ms
+        )?;
+        if let Some(counters) = self.counters {
+            write!(
+                f,
+                " ({} peak candidates, {} comparisons)",
+                counters.peak_candidates, counters.comparisons
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::{clone_or_load, collect_commits};
+    use crate::search::metrics::{MethodMetrics, SearchCounters};
+    use crate::search::shard::{PathSharding, ShardedSearch};
+    use crate::{Commit, ExactDiffMatch, MessageSimilarity, RepoLocation, TraditionalLSH};
+    use std::env;
+
+    #[test]
+    fn searches_are_measured() {
+        let location = RepoLocation::Filesystem(env::current_dir().unwrap());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let loaded_repo = [runtime.block_on(clone_or_load(&location)).unwrap()];
+        let mut commits: Vec<Commit> = collect_commits(&loaded_repo).into_iter().collect();
+        commits.truncate(200);
+
+        let (results, metrics) = MethodMetrics::measure(&ExactDiffMatch::default(), &mut commits);
+        assert_eq!(metrics.method, "ExactDiffMatch");
+        assert_eq!(metrics.commits, commits.len());
+        assert_eq!(metrics.results, results.len());
+        assert_eq!(metrics.counters, None);
+
+        let lsh = TraditionalLSH::new(8, 100, 5, 0.7).with_brute_force_limit(10);
+        let (_, metrics) = MethodMetrics::measure(&lsh, &mut commits);
+        let counters = metrics.counters.unwrap();
+        assert!(counters.comparisons >= counters.peak_candidates);
+
+        let (_, metrics) = MethodMetrics::measure(&MessageSimilarity::default(), &mut commits);
+        assert!(metrics.counters.is_some());
+
+        // Shards report the largest candidate set of any shard
+        let sharded = ShardedSearch::new(
+            Box::new(TraditionalLSH::new(8, 100, 5, 0.7).with_brute_force_limit(0)),
+            PathSharding::new(["src/search", "src/git"]),
+        );
+        let (_, metrics) = MethodMetrics::measure(&sharded, &mut commits);
+        assert!(metrics.counters.is_some());
+        assert_eq!(
+            SearchCounters::new(3, 3).merge(SearchCounters::new(2, 4)),
+            SearchCounters::new(3, 7)
+        );
+    }
+}
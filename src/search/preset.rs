@@ -0,0 +1,236 @@
+use crate::git::{calculate_diffs, Commit};
+use crate::search::SearchCounters;
+use crate::{ExactChangeMatch, PatchIdMatch, SearchMethod, SearchResult, TraditionalLSH};
+use firestorm::{profile_fn, profile_method};
+use git2::{Delta, Oid, Repository as G2Repository};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// The name of the results of the patch-id stage
+pub const PATCH_ID_STAGE: &str = crate::search::methods::patch_id::NAME;
+
+/// A SearchPreset is a ready-made cascade of search methods with good default parameters, for
+/// users who do not want to tune each method on their own. The stages of the cascade run from
+/// fast to accurate:
+///
+/// 1. Commits with the same patch-id are paired (see [PatchIdMatch]).
+/// 2. Commits with the same changed lines are paired (see [ExactChangeMatch]).
+/// 3. The commits that were not paired by the exact stages are searched with TraditionalLSH,
+///    so that the expensive similarity comparisons are saved for the commits whose picks have not
+///    been found yet. As a consequence, adapted picks of commits that also have exact picks are
+///    not found.
+/// 4. Each result of the LSH stage is verified by applying its cherry to the parent of its target
+///    (if enabled). Results are rejected if the cherry applies cleanly, but the target does not
+///    contain the applied changes. Results whose cherry cannot be applied without conflicts are
+///    kept, because picks are often adapted to resolve conflicts.
+///
+/// Each result keeps the name of the stage that found it (i.e., `PatchIdMatch`,
+/// `ExactChangeMatch`, or `TraditionalLSH`).
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::prelude::*;
+///
+/// let methods: Vec<Box<dyn SearchMethod>> = vec![Box::new(SearchPreset::balanced())];
+/// assert_eq!(methods[0].name(), "BalancedPreset");
+/// ```
+pub struct SearchPreset {
+    name: &'static str,
+    lsh: TraditionalLSH,
+    verification: bool,
+}
+
+impl SearchPreset {
+    /// The "fast-then-accurate" preset, which runs all stages. Its LSH stage uses a lower
+    /// similarity threshold than TraditionalLSH's suggested defaults, because the verification
+    /// rejects most of the additional false positives.
+    pub fn balanced() -> Self {
+        Self {
+            name: "BalancedPreset",
+            lsh: TraditionalLSH::new(8, 100, 4, 0.6).with_hunk_matches(true),
+            verification: true,
+        }
+    }
+
+    /// Enable or disable the verification of the LSH stage's results. Enabled by default.
+    pub fn with_verification(mut self, verification: bool) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Seed the signatures of the LSH stage, so that every search finds the same picks (see
+    /// [TraditionalLSH::new_with_seed]). Otherwise, they are randomized anew for each search.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.lsh = self.lsh.with_seed(seed);
+        self
+    }
+}
+
+impl SearchMethod for SearchPreset {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let start = Instant::now();
+        calculate_diffs(commits);
+
+        let mut results = PatchIdMatch::default().search(commits);
+        let found: HashSet<(String, String)> = results.iter().map(pair_key).collect();
+        let exact_changes = ExactChangeMatch::default().search(commits);
+        results.extend(
+            exact_changes
+                .into_iter()
+                .filter(|result| !found.contains(&pair_key(result))),
+        );
+        debug!("found {} results in the exact stages", results.len());
+
+        let paired: HashSet<&str> = results
+            .iter()
+            .flat_map(|result| {
+                let pair = result.commit_pair();
+                [pair.cherry().id(), pair.target().id()]
+            })
+            .collect();
+        let mut remaining: Vec<Commit> = commits
+            .iter()
+            .filter(|commit| !paired.contains(commit.id().to_string().as_str()))
+            .cloned()
+            .collect();
+        let mut similar = self.lsh.search(&mut remaining);
+        if self.verification {
+            let count = similar.len();
+            let by_id: HashMap<String, &Commit> = remaining
+                .iter()
+                .map(|commit| (commit.id().to_string(), commit))
+                .collect();
+            similar.retain(|result| {
+                let pair = result.commit_pair();
+                match (by_id.get(pair.cherry().id()), by_id.get(pair.target().id())) {
+                    (Some(cherry), Some(target)) => {
+                        apply_verifies(target.repository(), cherry.id(), target.id()) != Some(false)
+                    }
+                    _ => true,
+                }
+            });
+            debug!(
+                "rejected {} of {count} results of the LSH stage",
+                count - similar.len()
+            );
+        }
+        results.extend(similar);
+        debug!("found {} results in {:?}", results.len(), start.elapsed());
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The exact stages do not compare pairs; only the LSH stage is counted
+    fn last_counters(&self) -> Option<SearchCounters> {
+        self.lsh.last_counters()
+    }
+}
+
+/// The ids of a result's commits, independent of which commit is the cherry
+fn pair_key(result: &SearchResult) -> (String, String) {
+    let pair = result.commit_pair();
+    let (a, b) = (pair.cherry().id(), pair.target().id());
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+/// Whether applying the cherry to the first parent of the target reproduces the target's version
+/// of every file that the cherry changes. Returns None if the cherry cannot be applied without
+/// conflicts, or if the result cannot be determined (e.g., because the cherry is not part of the
+/// target's repository or the target has no parent).
+fn apply_verifies(repository: &G2Repository, cherry: Oid, target: Oid) -> Option<bool> {
+    profile_fn!(apply_verifies);
+    let cherry = repository.find_commit(cherry).ok()?;
+    let target = repository.find_commit(target).ok()?;
+    let onto = target.parent(0).ok()?;
+    // Merges are picked relative to their first parent
+    let mainline = if cherry.parent_count() > 1 { 1 } else { 0 };
+    let index = repository
+        .cherrypick_commit(&cherry, &onto, mainline, None)
+        .ok()?;
+    if index.has_conflicts() {
+        return None;
+    }
+    let applied = repository
+        .diff_tree_to_index(Some(&onto.tree().ok()?), Some(&index), None)
+        .ok()?;
+    let target_tree = target.tree().ok()?;
+    // A cherry whose changes are already contained in the target's parent was not picked by it
+    Some(
+        applied.deltas().len() > 0
+            && applied.deltas().all(|delta| {
+                let applied_file = delta.new_file();
+                let Some(path) = applied_file.path().or(delta.old_file().path()) else {
+                    return false;
+                };
+                let target_file = target_tree.get_path(path).ok().map(|entry| entry.id());
+                match delta.status() {
+                    Delta::Deleted => target_file.is_none(),
+                    _ => target_file == Some(applied_file.id()),
+                }
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::preset::{apply_verifies, SearchPreset};
+    use crate::{Commit, SearchMethod};
+
+    #[test]
+    fn picks_are_verified_by_applying_them() {
+        let fixture = TestRepository::new();
+        let files = |content| [("file", content), ("other", "x\ny\nz\n")];
+        let base = fixture.commit("main", &files("a\nb\nc\n"), &[], "base");
+        let cherry = fixture.commit("main", &files("a\nB\nc\n"), &[base], "fix");
+        let pick = fixture.commit("pick", &files("a\nB\nc\n"), &[base], "fix");
+        // Changes the other file in the same way, but does not contain the cherry's changes
+        let unrelated = fixture.commit(
+            "unrelated",
+            &[("file", "a\nb\nc\n"), ("other", "x\nB\nz\n")],
+            &[base],
+            "unrelated",
+        );
+        // Has already been changed by the cherry
+        let later = fixture.commit("main", &files("A\nB\nc\n"), &[cherry], "later");
+
+        let repository = fixture.repository();
+        assert_eq!(apply_verifies(repository, cherry, pick), Some(true));
+        assert_eq!(apply_verifies(repository, cherry, unrelated), Some(false));
+        assert_eq!(apply_verifies(repository, cherry, later), Some(false));
+        assert_eq!(apply_verifies(repository, cherry, base), None);
+    }
+
+    #[test]
+    fn exact_stages_run_first() {
+        let fixture = TestRepository::new();
+        let base = fixture.commit("main", &[("file", "a\nb\nc\nd\n")], &[], "base");
+        fixture.commit("main", &[("file", "a\nB\nc\nd\n")], &[base], "fix");
+        fixture.commit("hotfix", &[("file", "a\nB\nc\nd\n")], &[base], "hotfix");
+        let release = fixture.commit("release", &[("file", "a\nb\nc\n")], &[base], "r");
+        fixture.commit("release", &[("file", "a\nB\nc\n")], &[release], "fix");
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let results = SearchPreset::balanced().search(&mut commits);
+        let mut stages: Vec<&str> = results.iter().map(|r| r.search_method()).collect();
+        stages.sort();
+        // The fixes on main and on the hotfix branch have the same patch-id, and the fix on the
+        // release branch only shares their changes
+        assert_eq!(
+            stages,
+            vec!["ExactChangeMatch", "ExactChangeMatch", "PatchIdMatch"]
+        );
+    }
+}
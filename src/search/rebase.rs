@@ -0,0 +1,194 @@
+use crate::SearchResult;
+use firestorm::profile_fn;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The number of consecutive picks from which a run of picks is considered a rebase
+pub const DEFAULT_MIN_RUN: usize = 10;
+
+/// A run of consecutive picks between two branches that preserves the order of the commits, as
+/// produced by rebasing a branch or by re-creating it from another branch. Such runs are not
+/// cherry picks in the usual sense, but a single event.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebaseRun {
+    /// The id of the run, i.e., the id of the target of the run's first (i.e., oldest) pick
+    pub id: String,
+    /// The number of picks in the run
+    pub length: usize,
+    /// The position of the pick in the run, starting with 0 for the first pick
+    pub position: usize,
+}
+
+/// Annotates each result that is part of a run of at least `min_run` consecutive picks between two
+/// branches (see SearchResult::rebase).
+///
+/// Two picks of the same search method are consecutive, if the first parents of the commits of
+/// one pick are the commits of the other pick, i.e., a pick continues the run of the pick of its
+/// parents. Thus, a run is a sequence of picks that preserves the order of the commits on both
+/// branches, which is characteristic of rebased or re-created branches, but rare for picks of
+/// individual commits.
+pub fn annotate_rebases(results: &mut [SearchResult], min_run: usize) {
+    profile_fn!(annotate_rebases);
+    // Picks are identified by their method and their commits, regardless of which commit is the
+    // cherry
+    let key = |method: &str, a: String, b: String| match a <= b {
+        true => (method.to_string(), a, b),
+        false => (method.to_string(), b, a),
+    };
+    let pick_key = |result: &SearchResult| {
+        let pair = result.commit_pair();
+        key(
+            result.search_method(),
+            pair.cherry().id().to_string(),
+            pair.target().id().to_string(),
+        )
+    };
+    let index: HashMap<(String, String, String), usize> = results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| (pick_key(result), i))
+        .collect();
+    let previous: Vec<Option<usize>> = results
+        .iter()
+        .map(|result| {
+            let pair = result.commit_pair();
+            let cherry_parent = pair.cherry().parent_ids().first()?;
+            let target_parent = pair.target().parent_ids().first()?;
+            index
+                .get(&key(
+                    result.search_method(),
+                    cherry_parent.clone(),
+                    target_parent.clone(),
+                ))
+                .copied()
+        })
+        .collect();
+
+    // The first pick of each pick's run and the pick's position in the run
+    let mut starts: Vec<Option<(usize, usize)>> = vec![None; results.len()];
+    for i in 0..results.len() {
+        let mut path = vec![];
+        let mut current = i;
+        let (start, mut position) = loop {
+            if let Some((start, position)) = starts[current] {
+                break (start, position);
+            }
+            match previous[current] {
+                Some(p) => {
+                    path.push(current);
+                    current = p;
+                }
+                None => {
+                    starts[current] = Some((current, 0));
+                    break (current, 0);
+                }
+            }
+        };
+        for &pick in path.iter().rev() {
+            position += 1;
+            starts[pick] = Some((start, position));
+        }
+    }
+
+    let mut lengths: HashMap<usize, usize> = HashMap::new();
+    for (start, _) in starts.iter().flatten() {
+        *lengths.entry(*start).or_default() += 1;
+    }
+    let mut rebased = 0;
+    for (i, start) in starts.into_iter().enumerate() {
+        let Some((start, position)) = start else {
+            continue;
+        };
+        let length = lengths[&start];
+        if length < min_run {
+            results[i].set_rebase(None);
+            continue;
+        }
+        rebased += 1;
+        let id = results[start].commit_pair().target().id().to_string();
+        results[i].set_rebase(Some(RebaseRun {
+            id,
+            length,
+            position,
+        }));
+    }
+    debug!(
+        "{rebased} of {} picks are part of rebases with at least {min_run} picks",
+        results.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::rebase::annotate_rebases;
+    use crate::{Commit, ExactChangeMatch, SearchMethod, SearchResult};
+    use git2::Oid;
+    use std::collections::HashSet;
+
+    #[test]
+    fn rebased_branches_are_detected() {
+        let fixture = TestRepository::new();
+        // Each commit is named after its branch and its number of files
+        let commit = |branch: &str, files: &[(&str, &str)], parents: &[Oid]| {
+            let message = format!("{branch} {}", files.len());
+            fixture.commit(branch, files, parents, &message)
+        };
+        let base = commit("main", &[("base", "base\n")], &[]);
+        let upstream = commit("main", &[("base", "base\n"), ("other", "other\n")], &[base]);
+        // A feature branch of four commits, which is rebased onto main
+        let (mut old, mut new) = (base, upstream);
+        for i in 0..4 {
+            let feature = (0..=i).map(|j| format!("{j}\n")).collect::<String>();
+            let mut files = vec![("base", "base\n"), ("feature", feature.as_str())];
+            old = commit("feature", &files, &[old]);
+            files.push(("other", "other\n"));
+            new = commit("rebased", &files, &[new]);
+        }
+        // A single pick of the first feature commit to a release branch
+        let release = commit(
+            "release",
+            &[("base", "base\n"), ("release", "release\n")],
+            &[base],
+        );
+        commit(
+            "release",
+            &[
+                ("base", "base\n"),
+                ("feature", "0\n"),
+                ("release", "release\n"),
+            ],
+            &[release],
+        );
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        let mut results: Vec<SearchResult> = ExactChangeMatch::default()
+            .search(&mut commits)
+            .into_iter()
+            .collect();
+        // Four picks of the rebase and the first feature commit's picks to the release branch
+        assert_eq!(results.len(), 6);
+
+        annotate_rebases(&mut results, 4);
+        let rebased: Vec<&SearchResult> = results.iter().filter(|r| r.is_rebased()).collect();
+        assert_eq!(rebased.len(), 4);
+        let ids: HashSet<&str> = rebased
+            .iter()
+            .map(|r| r.rebase().unwrap().id.as_str())
+            .collect();
+        assert_eq!(ids.len(), 1);
+        let mut positions: Vec<usize> = rebased
+            .iter()
+            .map(|r| r.rebase().unwrap().position)
+            .collect();
+        positions.sort();
+        assert_eq!(positions, vec![0, 1, 2, 3]);
+        assert!(rebased.iter().all(|r| r.rebase().unwrap().length == 4));
+
+        annotate_rebases(&mut results, 5);
+        assert!(results.iter().all(|r| !r.is_rebased()));
+    }
+}
@@ -0,0 +1,594 @@
+use crate::error::{Error, ErrorKind};
+use crate::search::combined::{self, CombinedSearch, VotingPolicy};
+use crate::search::component::{ComponentSearch, VirtualRepository};
+use crate::search::methods::lsh::{
+    VocabularyPruning, DEFAULT_BRUTE_FORCE_LIMIT, DEFAULT_CHANGE_WEIGHT,
+};
+use crate::search::methods::partial_diff::DEFAULT_MAX_HUNK_COMMITS;
+use crate::search::shard::{PathSharding, ShardedSearch};
+use crate::{
+    ChangeIdMatch, ExactChangeMatch, ExactDiffMatch, MessageScan, MessageSimilarity,
+    PartialDiffMatch, PatchIdMatch, Result, SearchMethod, SearchPreset, TraditionalLSH,
+};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A factory that creates a search method from its configuration.
+pub type MethodFactory = Box<dyn Fn(&MethodConfig) -> Result<Box<dyn SearchMethod>> + Send + Sync>;
+
+/// The configuration of a single search method, as it is specified in a method configuration
+/// file. The parameters are interpreted by the factory that has been registered for the method.
+///
+/// Every method additionally accepts the parameters `shard_prefixes` and `shard_remainder`, which
+/// restrict its search to shards of the commit corpus (see [PathSharding]), and the parameter
+/// `components`, which searches for picks between the components of a monorepo instead (see
+/// [ComponentSearch]).
+///
+/// # Examples
+/// ```yaml
+/// - name: MessageScan
+///   params:
+///     # Markers in addition to git's "(cherry picked from commit "
+///     markers: ["(rétroporté depuis le commit "]
+/// - name: MessageSimilarity
+///   params:
+///     threshold: 0.8
+///     arity: 2
+///     min_words: 4
+/// - name: ExactDiffMatch
+///   params:
+///     shard_prefixes: [drivers/, fs/]
+/// - name: ExactDiffMatch
+///   params:
+///     components:
+///       - name: lib
+///         paths: [lib/]
+///       - name: vendored
+///         paths: [app/vendor/lib/]
+/// - name: ChangeIdMatch
+///   params:
+///     threshold: 0.5
+/// - name: ExactChangeMatch
+/// - name: PatchIdMatch
+/// # Picks that were squashed with other changes
+/// - name: PartialDiffMatch
+///   params:
+///     min_fraction: 0.8
+///     max_hunk_commits: 20
+/// # A cascade of exact and similarity-based matching with good defaults
+/// - name: BalancedPreset
+///   params:
+///     verify: true
+///     seed: 42
+/// # Picks that at least two of the three methods agree on
+/// - name: CombinedSearch
+///   params:
+///     vote: majority
+///     methods:
+///       - name: MessageScan
+///       - name: PatchIdMatch
+///       - name: ExactChangeMatch
+/// - name: TraditionalLSH
+///   params:
+///     # Shingle characters, tokens, or anonymized_tokens, whose identifiers are replaced
+///     shingling: characters
+///     arity: 8
+///     signature_size: 100
+///     band_size: 5
+///     # Also probe the buckets of bands that differ in one of their first 2 values
+///     probes: 2
+///     threshold: 0.7
+///     # Seed the signatures, so that every run finds the same picks
+///     seed: 42
+///     # The weight of the changes in the diff similarity, e.g., as fitted by a calibration
+///     change_weight: 0.6
+///     hunk_matches: true
+///     # Compare all pairs of diffs in searches of at most 64 distinct diffs
+///     brute_force_limit: 64
+///     # Prune the shingles that occur in a single diff from the vocabulary
+///     min_shingle_frequency: 2
+///     # Log a diagnostics report with the 10 largest buckets after each search
+///     diagnostics: 10
+///     # Persist the statistics of each search in this file, and tune the band size with the
+///     # statistics of earlier searches
+///     memory: output/cache/lsh/project.yaml
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MethodConfig {
+    pub name: String,
+    #[serde(default)]
+    pub params: HashMap<String, Value>,
+}
+
+impl MethodConfig {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            params: HashMap::new(),
+        }
+    }
+
+    /// This configuration with the given `seed` parameter, unless the configuration sets its own
+    /// seed. Only the randomized methods (i.e., TraditionalLSH and BalancedPreset) are seeded,
+    /// including the inner methods of a CombinedSearch, so that a harvest with the same seed finds
+    /// the same picks (see [crate::HarvestRun::component_seed]).
+    pub fn with_default_seed(mut self, seed: u64) -> Self {
+        match self.name.as_str() {
+            "TraditionalLSH" | "BalancedPreset" => {
+                self.params
+                    .entry("seed".to_string())
+                    .or_insert_with(|| Value::from(seed));
+            }
+            combined::NAME => {
+                // Invalid inner methods are reported when the combined search is created
+                let inner = self.params.get("methods").and_then(|value| {
+                    serde_yaml::from_value::<Vec<MethodConfig>>(value.clone()).ok()
+                });
+                if let Some(inner) = inner {
+                    let inner: Vec<MethodConfig> = inner
+                        .into_iter()
+                        .map(|config| config.with_default_seed(seed))
+                        .collect();
+                    if let Ok(value) = serde_yaml::to_value(inner) {
+                        self.params.insert("methods".to_string(), value);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Retrieve an unsigned integer parameter, or the default if the parameter was not set.
+    pub fn param_usize(&self, key: &str, default: usize) -> Result<usize> {
+        match self.params.get(key) {
+            None => Ok(default),
+            Some(value) => value
+                .as_u64()
+                .map(|v| v as usize)
+                .ok_or_else(|| self.invalid_param(key, "an unsigned integer")),
+        }
+    }
+
+    /// Retrieve a floating point parameter, or the default if the parameter was not set.
+    pub fn param_f64(&self, key: &str, default: f64) -> Result<f64> {
+        match self.params.get(key) {
+            None => Ok(default),
+            Some(value) => value
+                .as_f64()
+                .ok_or_else(|| self.invalid_param(key, "a number")),
+        }
+    }
+
+    /// Retrieve a boolean parameter, or the default if the parameter was not set.
+    pub fn param_bool(&self, key: &str, default: bool) -> Result<bool> {
+        match self.params.get(key) {
+            None => Ok(default),
+            Some(value) => value
+                .as_bool()
+                .ok_or_else(|| self.invalid_param(key, "a boolean")),
+        }
+    }
+
+    /// Retrieve a string parameter, or None if the parameter was not set.
+    pub fn param_string(&self, key: &str) -> Result<Option<String>> {
+        match self.params.get(key) {
+            None => Ok(None),
+            Some(value) => value
+                .as_str()
+                .map(|v| Some(v.to_string()))
+                .ok_or_else(|| self.invalid_param(key, "a string")),
+        }
+    }
+
+    /// Retrieve a list of strings, or None if the parameter was not set.
+    pub fn param_strings(&self, key: &str) -> Result<Option<Vec<String>>> {
+        match self.params.get(key) {
+            None => Ok(None),
+            Some(value) => value
+                .as_sequence()
+                .and_then(|values| {
+                    values
+                        .iter()
+                        .map(|v| v.as_str().map(String::from))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .map(Some)
+                .ok_or_else(|| self.invalid_param(key, "a list of strings")),
+        }
+    }
+
+    fn invalid_param(&self, key: &str, expected: &str) -> Error {
+        Error::new(ErrorKind::Configuration(format!(
+            "parameter '{key}' of {} must be {expected}",
+            self.name
+        )))
+    }
+}
+
+/// Load a list of method configurations from a YAML file.
+pub fn load_method_configs<P: AsRef<Path>>(path: P) -> Result<Vec<MethodConfig>> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// A MethodRegistry maps the names of search methods to factories that create them. It allows
+/// downstream crates to compose their own search methods with the built-in ones, and to select
+/// the methods of a harvest through a configuration file instead of code changes.
+///
+/// The default registry contains all search methods provided by this crate.
+///
+/// # Examples
+/// ```
+/// use cherry_harvest::search::registry::{MethodConfig, MethodRegistry};
+/// use cherry_harvest::{Commit, SearchMethod, SearchResult};
+/// use std::collections::HashSet;
+///
+/// struct MyHeuristic;
+///
+/// impl SearchMethod for MyHeuristic {
+///     fn search(&self, _commits: &mut [Commit]) -> HashSet<SearchResult> {
+///         HashSet::new()
+///     }
+///
+///     fn name(&self) -> &'static str {
+///         "MyHeuristic"
+///     }
+/// }
+///
+/// let mut registry = MethodRegistry::default();
+/// registry.register("MyHeuristic", |_| Ok(Box::new(MyHeuristic)));
+/// let methods = registry
+///     .create_all(&[MethodConfig::new("MessageScan"), MethodConfig::new("MyHeuristic")])
+///     .unwrap();
+/// assert_eq!(methods[1].name(), "MyHeuristic");
+/// ```
+pub struct MethodRegistry {
+    factories: HashMap<String, MethodFactory>,
+}
+
+impl MethodRegistry {
+    /// Create a registry without any registered methods.
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register a factory for the method with the given name. A factory that was previously
+    /// registered under the same name is replaced.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&MethodConfig) -> Result<Box<dyn SearchMethod>> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name) || name == combined::NAME
+    }
+
+    /// The names of all registered methods in arbitrary order, including CombinedSearch
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.factories.keys().map(|k| k.as_str()).collect();
+        if !self.factories.contains_key(combined::NAME) {
+            names.push(combined::NAME);
+        }
+        names
+    }
+
+    /// Create the search method for the given configuration.
+    ///
+    /// A CombinedSearch is created by the registry itself, because its inner methods (parameter
+    /// `methods`) are created with the registry as well. Its voting policy is configured with the
+    /// parameter `vote` (i.e., `any`, `majority`, or `all`).
+    ///
+    /// # Errors
+    /// Returns an ErrorKind::Configuration error, if no method with the configured name has been
+    /// registered, or if the factory rejects the configuration.
+    pub fn create(&self, config: &MethodConfig) -> Result<Box<dyn SearchMethod>> {
+        let method = match self.factories.get(&config.name) {
+            Some(factory) => factory(config)?,
+            None if config.name == combined::NAME => self.create_combined(config)?,
+            None => {
+                return Err(Error::new(ErrorKind::Configuration(format!(
+                    "no search method with the name '{}' has been registered",
+                    config.name
+                ))))
+            }
+        };
+        // Any method can be restricted to shards of the commit corpus
+        let method: Box<dyn SearchMethod> = match config.param_strings("shard_prefixes")? {
+            None => method,
+            Some(prefixes) => {
+                let sharding = PathSharding::new(prefixes)
+                    .keep_remainder(config.param_bool("shard_remainder", false)?);
+                Box::new(ShardedSearch::new(method, sharding))
+            }
+        };
+        // ... and any method can search between the components of a monorepo
+        match config.params.get("components") {
+            None => Ok(method),
+            Some(value) => {
+                let components: Vec<VirtualRepository> = serde_yaml::from_value(value.clone())
+                    .map_err(|_| config.invalid_param("components", "a list of names and paths"))?;
+                Ok(Box::new(ComponentSearch::new(method, components)))
+            }
+        }
+    }
+
+    /// Create a CombinedSearch whose inner methods are created with this registry
+    fn create_combined(&self, config: &MethodConfig) -> Result<Box<dyn SearchMethod>> {
+        let inner: Vec<MethodConfig> = match config.params.get("methods") {
+            Some(value) => serde_yaml::from_value(value.clone())
+                .map_err(|_| config.invalid_param("methods", "a list of method configurations"))?,
+            None => vec![],
+        };
+        if inner.is_empty() {
+            return Err(config.invalid_param("methods", "a non-empty list of methods"));
+        }
+        let policy = match config.params.get("vote") {
+            None => VotingPolicy::default(),
+            Some(value) => value
+                .as_str()
+                .ok_or_else(|| config.invalid_param("vote", "any, majority, or all"))?
+                .parse()?,
+        };
+        Ok(Box::new(CombinedSearch::new(
+            self.create_all(&inner)?,
+            policy,
+        )))
+    }
+
+    /// Create the search methods for all given configurations.
+    pub fn create_all(&self, configs: &[MethodConfig]) -> Result<Vec<Box<dyn SearchMethod>>> {
+        configs.iter().map(|c| self.create(c)).collect()
+    }
+}
+
+impl Default for MethodRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("MessageScan", |config| {
+            let markers = config.param_strings("markers")?.unwrap_or_default();
+            Ok(Box::new(MessageScan::default().with_markers(markers)))
+        });
+        registry.register("MessageSimilarity", |config| {
+            Ok(Box::new(
+                MessageSimilarity::new(config.param_f64("threshold", 0.8)?)
+                    .with_arity(config.param_usize("arity", 2)?)
+                    .with_min_words(config.param_usize("min_words", 4)?),
+            ))
+        });
+        registry.register("ExactDiffMatch", |_| Ok(Box::<ExactDiffMatch>::default()));
+        registry.register("ExactChangeMatch", |_| {
+            Ok(Box::<ExactChangeMatch>::default())
+        });
+        registry.register("PatchIdMatch", |_| Ok(Box::<PatchIdMatch>::default()));
+        registry.register("PartialDiffMatch", |config| {
+            Ok(Box::new(
+                PartialDiffMatch::try_new(config.param_f64("min_fraction", 0.5)?)?
+                    .with_max_hunk_commits(
+                        config.param_usize("max_hunk_commits", DEFAULT_MAX_HUNK_COMMITS)?,
+                    ),
+            ))
+        });
+        registry.register("ChangeIdMatch", |config| {
+            Ok(Box::new(ChangeIdMatch::new(
+                config.param_f64("threshold", 0.5)?,
+            )))
+        });
+        registry.register("TraditionalLSH", |config| {
+            let (arity, signature_size, band_size, threshold) = (
+                config.param_usize("arity", 8)?,
+                config.param_usize("signature_size", 100)?,
+                config.param_usize("band_size", 5)?,
+                config.param_f64("threshold", 0.75)?,
+            );
+            if signature_size == 0 {
+                return Err(config.invalid_param("signature_size", "a positive integer"));
+            }
+            // The signatures are split into bands of equal size
+            if band_size == 0 || signature_size % band_size != 0 {
+                let expected = format!("a divisor of the signature size {signature_size}");
+                return Err(config.invalid_param("band_size", &expected));
+            }
+            let method = match config.params.contains_key("seed") {
+                true => {
+                    let seed = config.param_usize("seed", 0)? as u64;
+                    TraditionalLSH::new_with_seed(arity, signature_size, band_size, threshold, seed)
+                }
+                false => TraditionalLSH::new(arity, signature_size, band_size, threshold),
+            };
+            let mut method = method
+                .with_change_weight(config.param_f64("change_weight", DEFAULT_CHANGE_WEIGHT)?)
+                .with_multi_probe(config.param_usize("probes", 0)?)
+                .with_hunk_matches(config.param_bool("hunk_matches", false)?)
+                .with_brute_force_limit(
+                    config.param_usize("brute_force_limit", DEFAULT_BRUTE_FORCE_LIMIT)?,
+                );
+            match config.param_usize("diagnostics", 0)? {
+                0 => {}
+                top_n => method = method.with_diagnostics(top_n),
+            }
+            match config.param_usize("min_shingle_frequency", 0)? {
+                0 | 1 => {}
+                floor => {
+                    let pruning = VocabularyPruning::new(floor.try_into().unwrap_or(u32::MAX));
+                    method = method.with_vocabulary_pruning(pruning);
+                }
+            }
+            if let Some(shingling) = config.param_string("shingling")? {
+                method = method.with_shingling(shingling.parse()?);
+            }
+            if let Some(path) = config.param_string("memory")? {
+                method = method.with_memory(path);
+            }
+            Ok(Box::new(method))
+        });
+        registry.register("BalancedPreset", |config| {
+            let preset =
+                SearchPreset::balanced().with_verification(config.param_bool("verify", true)?);
+            Ok(Box::new(match config.params.contains_key("seed") {
+                true => preset.with_seed(config.param_usize("seed", 0)? as u64),
+                false => preset,
+            }))
+        });
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::registry::{MethodConfig, MethodRegistry};
+    use crate::{Commit, HarvestRun};
+
+    #[test]
+    fn create_builtin_methods() {
+        let configs: Vec<MethodConfig> = serde_yaml::from_str(
+            r#"
+- name: MessageScan
+  params:
+    markers: ["(backported from "]
+- name: TraditionalLSH
+  params:
+    shingling: tokens
+    arity: 3
+    signature_size: 32
+    band_size: 4
+    probes: 1
+    threshold: 0.5
+    seed: 7
+    min_shingle_frequency: 2
+- name: ExactDiffMatch
+  params:
+    shard_prefixes: [src/]
+- name: ChangeIdMatch
+- name: BalancedPreset
+- name: PatchIdMatch
+- name: MessageSimilarity
+  params:
+    threshold: 0.6
+- name: PartialDiffMatch
+  params:
+    min_fraction: 0.8
+- name: CombinedSearch
+  params:
+    vote: all
+    methods:
+      - name: MessageScan
+      - name: ExactDiffMatch
+"#,
+        )
+        .unwrap();
+        let methods = MethodRegistry::default().create_all(&configs).unwrap();
+        assert_eq!(methods[0].name(), "MessageScan");
+        assert_eq!(methods[1].name(), "TraditionalLSH");
+        assert_eq!(methods[2].name(), "ExactDiffMatch");
+        assert_eq!(methods[3].name(), "ChangeIdMatch");
+        assert_eq!(methods[4].name(), "BalancedPreset");
+        assert_eq!(methods[5].name(), "PatchIdMatch");
+        assert_eq!(methods[6].name(), "MessageSimilarity");
+        assert_eq!(methods[7].name(), "PartialDiffMatch");
+        assert_eq!(methods[8].name(), "CombinedSearch");
+        assert!(MethodRegistry::default().contains("CombinedSearch"));
+    }
+
+    #[test]
+    fn unknown_method_is_an_error() {
+        let registry = MethodRegistry::default();
+        assert!(registry.create(&MethodConfig::new("Unknown")).is_err());
+    }
+
+    #[test]
+    fn invalid_parameter_is_an_error() {
+        let mut config = MethodConfig::new("TraditionalLSH");
+        config
+            .params
+            .insert("arity".to_string(), serde_yaml::Value::from("eight"));
+        assert!(MethodRegistry::default().create(&config).is_err());
+        config.params = serde_yaml::from_str("{shingling: words}").unwrap();
+        assert!(MethodRegistry::default().create(&config).is_err());
+        for params in ["{band_size: 3}", "{band_size: 0}", "{signature_size: 0}"] {
+            config.params = serde_yaml::from_str(params).unwrap();
+            assert!(MethodRegistry::default().create(&config).is_err());
+        }
+        let mut config = MethodConfig::new("PartialDiffMatch");
+        config.params = serde_yaml::from_str("{min_fraction: 1.5}").unwrap();
+        assert!(MethodRegistry::default().create(&config).is_err());
+
+        // Combined searches require known inner methods and policies
+        for params in [
+            "{}",
+            "{methods: []}",
+            "{methods: [{name: Unknown}]}",
+            "{methods: [{name: MessageScan}], vote: unanimous}",
+        ] {
+            let mut config = MethodConfig::new("CombinedSearch");
+            config.params = serde_yaml::from_str(params).unwrap();
+            assert!(MethodRegistry::default().create(&config).is_err());
+        }
+    }
+
+    #[test]
+    fn runs_with_the_same_seed_find_the_same_candidates() {
+        let fixture = TestRepository::new();
+        let lines: Vec<String> = (0..12).map(|i| format!("line {i}\n")).collect();
+        let mut parent = None;
+        for i in 0..12 {
+            // Each commit changes a few neighboring lines, so that the diffs partially overlap
+            let mut content = lines.clone();
+            content[i] = format!("changed {i}\n");
+            content[(i + 1) % 12] = format!("changed {}\n", i + 1);
+            let message = format!("change {i}");
+            parent = Some(fixture.commit_file("main", &content.concat(), parent, &message));
+        }
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+
+        // Every candidate is a result with a negative threshold, and the signatures are indexed
+        // instead of comparing all pairs
+        let config: MethodConfig = serde_yaml::from_str(
+            "{name: TraditionalLSH, params: {arity: 3, signature_size: 20, band_size: 2, \
+             threshold: -1.0, brute_force_limit: 0}}",
+        )
+        .unwrap();
+        let mut candidates = |run: HarvestRun| {
+            let config = config
+                .clone()
+                .with_default_seed(run.component_seed("minhash"));
+            let method = MethodRegistry::default().create(&config).unwrap();
+            method.search(&mut commits)
+        };
+        let first = candidates(HarvestRun::with_seed(42));
+        assert!(!first.is_empty());
+        assert_eq!(candidates(HarvestRun::with_seed(42)), first);
+    }
+
+    #[test]
+    fn configured_seeds_are_kept() {
+        let mut config = MethodConfig::new("TraditionalLSH");
+        config.params = serde_yaml::from_str("{seed: 7}").unwrap();
+        let config = config.with_default_seed(42);
+        assert_eq!(config.param_usize("seed", 0).unwrap(), 7);
+        let config = MethodConfig::new("MessageScan").with_default_seed(42);
+        assert!(config.params.is_empty());
+
+        // The inner methods of combined searches are seeded as well
+        let config: MethodConfig = serde_yaml::from_str(
+            "{name: CombinedSearch, params: {methods: [{name: BalancedPreset}, {name: MessageScan}]}}",
+        )
+        .unwrap();
+        let config = config.with_default_seed(42);
+        let inner: Vec<MethodConfig> =
+            serde_yaml::from_value(config.params["methods"].clone()).unwrap();
+        assert_eq!(inner[0].param_usize("seed", 0).unwrap(), 42);
+        assert!(!inner[1].params.contains_key("seed"));
+        assert!(MethodRegistry::default().create(&config).is_ok());
+    }
+}
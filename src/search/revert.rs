@@ -0,0 +1,134 @@
+use crate::git::Commit;
+use crate::SearchResult;
+use firestorm::profile_fn;
+use git2::Oid;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const REVERT_MARKER: &str = "This reverts commit ";
+
+/// A commit that reverts the target of a cherry-pick
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revert {
+    pub commit_id: String,
+    /// The commit time of the revert, in the same format as CommitMetadata::time
+    pub time: String,
+}
+
+/// The commit that is reverted by a commit with the given message, based on the text that
+/// `git revert` inserts into the message (i.e., `This reverts commit SOME_HASH.`). The returned
+/// hash may be abbreviated.
+pub fn reverted_commit(message: &str) -> Option<&str> {
+    let index = message.find(REVERT_MARKER)? + REVERT_MARKER.len();
+    let hash = &message[index..];
+    let end = hash
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(hash.len());
+    // Shorter hashes are too ambiguous
+    Some(&hash[..end]).filter(|hash| hash.len() >= 7)
+}
+
+/// The subject of the commit that is reverted by a commit with the given message, based on the
+/// default subject of `git revert` (i.e., `Revert "SUBJECT"`).
+fn reverted_subject(message: &str) -> Option<&str> {
+    message
+        .lines()
+        .next()?
+        .strip_prefix("Revert \"")?
+        .strip_suffix('"')
+}
+
+/// Annotates each result whose target was reverted by a subsequent commit on the target's branch
+/// (see SearchResult::revert). Picked-then-reverted changes did not persist in the target and are
+/// thus qualitatively different from picks that remained.
+///
+/// A commit is considered a revert of the target if its message references the target's id (as
+/// generated by `git revert`), or if its subject is `Revert "TARGET_SUBJECT"`. In both cases, the
+/// revert must be a descendant of the target. If several commits revert the target, the earliest
+/// one is recorded.
+pub fn annotate_reverts(results: &mut [SearchResult], commits: &[Commit]) {
+    profile_fn!(annotate_reverts);
+    let mut by_hash: HashMap<&str, Vec<&Commit>> = HashMap::new();
+    let mut by_subject: HashMap<&str, Vec<&Commit>> = HashMap::new();
+    for commit in commits {
+        let Some(message) = commit.message() else {
+            continue;
+        };
+        if let Some(hash) = reverted_commit(message) {
+            // Abbreviated hashes are matched by their shortest allowed prefix
+            by_hash.entry(&hash[..7]).or_default().push(commit);
+        }
+        if let Some(subject) = reverted_subject(message) {
+            by_subject.entry(subject).or_default().push(commit);
+        }
+    }
+    if by_hash.is_empty() && by_subject.is_empty() {
+        return;
+    }
+
+    let commits_by_id: HashMap<String, &Commit> =
+        commits.iter().map(|c| (c.id().to_string(), c)).collect();
+    let mut reverted = 0;
+    for result in results.iter_mut() {
+        let target_id = result.commit_pair().target().id();
+        let Some(target) = commits_by_id.get(target_id) else {
+            continue;
+        };
+        let subject = target
+            .message()
+            .and_then(|m| m.lines().next())
+            .unwrap_or_default();
+        let hash_matches = by_hash
+            .get(&target_id[..target_id.len().min(7)])
+            .into_iter()
+            .flatten()
+            .filter(|revert| {
+                revert
+                    .message()
+                    .and_then(reverted_commit)
+                    .is_some_and(|hash| target_id.starts_with(hash))
+            });
+        let subject_matches = by_subject.get(subject).into_iter().flatten();
+        let revert = hash_matches
+            .chain(subject_matches)
+            .filter(|revert| is_descendant(revert, target.id()))
+            .min_by_key(|revert| revert.time());
+        if let Some(revert) = revert {
+            result.set_revert(Some(Revert {
+                commit_id: revert.id().to_string(),
+                time: format!("{:?}", revert.time()),
+            }));
+            reverted += 1;
+        }
+    }
+    debug!("{reverted} of {} picks were reverted", results.len());
+}
+
+fn is_descendant(commit: &Commit, ancestor: Oid) -> bool {
+    commit
+        .repository()
+        .graph_descendant_of(commit.id(), ancestor)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::revert::{reverted_commit, reverted_subject};
+
+    #[test]
+    fn reverts_are_recognized() {
+        let message = "Revert \"Fix parser\"\n\nThis reverts commit 4e39e242712568e6f9f5b6ff113839603b722683.\n";
+        assert_eq!(
+            reverted_commit(message),
+            Some("4e39e242712568e6f9f5b6ff113839603b722683")
+        );
+        assert_eq!(reverted_subject(message), Some("Fix parser"));
+        assert_eq!(
+            reverted_commit("This reverts commit 4e39e24, reversing\nchanges"),
+            Some("4e39e24")
+        );
+        assert_eq!(reverted_commit("This reverts commit 4e39."), None);
+        assert_eq!(reverted_subject("Revert the parser changes"), None);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::git::{calculate_diffs, Commit};
+use crate::search::SearchCounters;
+use crate::{SearchMethod, SearchResult};
+use firestorm::{profile_fn, profile_method};
+use log::debug;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// Splits a commit corpus into sub-corpora (aka. shards) by the paths of the files that commits
+/// change. Each shard is identified by a path prefix, such as `drivers/` or `fs/` in the Linux
+/// kernel.
+///
+/// A commit is assigned to every shard whose prefix matches at least one of the files changed by
+/// the commit. Commits that match none of the prefixes are assigned to a remainder shard, if
+/// enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathSharding {
+    prefixes: Vec<PathBuf>,
+    keep_remainder: bool,
+}
+
+impl PathSharding {
+    /// Create a sharding with one shard per given path prefix. Commits that match none of the
+    /// prefixes are dropped.
+    pub fn new<P: Into<PathBuf>, I: IntoIterator<Item = P>>(prefixes: I) -> Self {
+        Self {
+            prefixes: prefixes.into_iter().map(Into::into).collect(),
+            keep_remainder: false,
+        }
+    }
+
+    /// Whether commits that match none of the prefixes are collected in an additional shard.
+    pub fn keep_remainder(mut self, keep_remainder: bool) -> Self {
+        self.keep_remainder = keep_remainder;
+        self
+    }
+
+    pub fn prefixes(&self) -> &[PathBuf] {
+        &self.prefixes
+    }
+
+    /// Split the given commits into shards. The shards are ordered like the prefixes, followed by
+    /// the remainder shard (if enabled). Empty shards are omitted.
+    pub fn shard<'r, 'c>(&self, commits: &mut [Commit<'r, 'c>]) -> Vec<Vec<Commit<'r, 'c>>> {
+        profile_method!(shard);
+        // The changed paths are only known after the diffs have been calculated
+        calculate_diffs(commits);
+        let mut shards: Vec<Vec<Commit>> = vec![vec![]; self.prefixes.len() + 1];
+        for commit in commits.iter() {
+            let paths = changed_paths(commit);
+            let mut assigned = false;
+            for (prefix, shard) in self.prefixes.iter().zip(shards.iter_mut()) {
+                if paths.iter().any(|path| path.starts_with(prefix)) {
+                    shard.push(commit.clone());
+                    assigned = true;
+                }
+            }
+            if !assigned && self.keep_remainder {
+                shards[self.prefixes.len()].push(commit.clone());
+            }
+        }
+        shards.retain(|shard| !shard.is_empty());
+        shards
+    }
+}
+
+/// All paths of files that are changed by the given commit. The diff of the commit must have been
+/// calculated.
+fn changed_paths<'c>(commit: &'c Commit) -> HashSet<&'c Path> {
+    profile_fn!(changed_paths);
+    commit
+        .diff()
+        .hunks
+        .iter()
+        .flat_map(|hunk| [hunk.old_file(), hunk.new_file()])
+        .filter_map(|path| path.as_deref())
+        .collect()
+}
+
+/// ShardedSearch runs a search method separately within each shard of a commit corpus (see
+/// [PathSharding]). Commits in different shards are never matched with each other, which enables
+/// analyses of individual subsystems and prevents matches across subsystems.
+///
+/// The results of all shards are merged; each result keeps the name of the wrapped method.
+pub struct ShardedSearch {
+    method: Box<dyn SearchMethod>,
+    sharding: PathSharding,
+    last_counters: Mutex<Option<SearchCounters>>,
+}
+
+impl ShardedSearch {
+    pub fn new(method: Box<dyn SearchMethod>, sharding: PathSharding) -> Self {
+        Self {
+            method,
+            sharding,
+            last_counters: Mutex::new(None),
+        }
+    }
+
+    pub fn sharding(&self) -> &PathSharding {
+        &self.sharding
+    }
+}
+
+impl SearchMethod for ShardedSearch {
+    fn search(&self, commits: &mut [Commit]) -> HashSet<SearchResult> {
+        profile_method!(search);
+        let shards = self.sharding.shard(commits);
+        debug!(
+            "searching with {} in {} shards",
+            self.method.name(),
+            shards.len()
+        );
+        let mut counters: Option<SearchCounters> = None;
+        let mut results = HashSet::new();
+        for mut shard in shards {
+            results.extend(self.method.search(&mut shard));
+            // The counters of the wrapped method only cover the last shard
+            if let Some(shard_counters) = self.method.last_counters() {
+                counters = Some(counters.map_or(shard_counters, |c| c.merge(shard_counters)));
+            }
+        }
+        *self
+            .last_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = counters;
+        results
+    }
+
+    fn name(&self) -> &'static str {
+        self.method.name()
+    }
+
+    fn last_counters(&self) -> Option<SearchCounters> {
+        *self
+            .last_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::collect_commits;
+    use crate::git::test_util::TestRepository;
+    use crate::search::shard::{PathSharding, ShardedSearch};
+    use crate::{Commit, ExactDiffMatch, SearchMethod};
+    use std::collections::BTreeSet;
+
+    /// The messages of the commits in each shard
+    fn messages(shards: &[Vec<Commit>]) -> Vec<BTreeSet<String>> {
+        shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .iter()
+                    .map(|commit| commit.message().unwrap().to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn set(messages: &[&str]) -> BTreeSet<String> {
+        messages.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn commits_are_sharded_by_path() {
+        let fixture = TestRepository::new();
+        // Root commits, so that each commit changes exactly the files it contains
+        fixture.commit("docs", &[("README", "r\n")], &[], "docs");
+        fixture.commit("search", &[("lib/search/query.rs", "a\n")], &[], "search");
+        fixture.commit("port", &[("lib/search/query.rs", "a\n")], &[], "port");
+        fixture.commit("git", &[("lib/git/repo.rs", "b\n")], &[], "git");
+        fixture.commit(
+            "both",
+            &[("lib/search/query.rs", "c\n"), ("lib/git/repo.rs", "d\n")],
+            &[],
+            "both",
+        );
+        // Prefixes match whole path components
+        fixture.commit("sibling", &[("lib/searching.rs", "e\n")], &[], "sibling");
+
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let mut commits: Vec<Commit> = collect_commits(&repos).into_iter().collect();
+        assert_eq!(commits.len(), 6);
+
+        let sharding = PathSharding::new(["lib/search", "lib/git"]);
+        let shards = sharding.shard(&mut commits);
+        assert_eq!(
+            messages(&shards),
+            [set(&["search", "port", "both"]), set(&["git", "both"])]
+        );
+
+        let with_remainder = sharding.clone().keep_remainder(true).shard(&mut commits);
+        assert_eq!(
+            messages(&with_remainder),
+            [
+                set(&["search", "port", "both"]),
+                set(&["git", "both"]),
+                set(&["docs", "sibling"])
+            ]
+        );
+
+        let search = ShardedSearch::new(Box::<ExactDiffMatch>::default(), sharding);
+        assert_eq!(search.name(), "ExactDiffMatch");
+        let results = search.search(&mut commits);
+        assert_eq!(results.len(), 1);
+        let pair = results.iter().next().unwrap().commit_pair();
+        let matched: BTreeSet<&str> = pair.as_vec().iter().map(|c| c.message()).collect();
+        assert_eq!(matched, BTreeSet::from(["search", "port"]));
+    }
+}
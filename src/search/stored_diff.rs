@@ -0,0 +1,240 @@
+use crate::git::{Diff, Hunk};
+use crate::{Commit, SearchResult};
+use firestorm::profile_fn;
+use git2::{ObjectType, Oid};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+
+/// The number of hex digits of the hashes that replace redacted lines
+const HASH_LENGTH: usize = 16;
+
+/// Whether and how the diffs of cherry and target are stored with each result (see
+/// [CommitMetadata::diff](crate::CommitMetadata::diff)). By default, no diffs are stored.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub enum DiffExport {
+    /// Results only contain the statistics and directories of the diffs
+    #[default]
+    None,
+    /// Results contain the diffs with their source lines
+    Plain,
+    /// Results contain the diffs, but the content of each line (and the section heading of each
+    /// hunk header) is replaced by a hash that is salted with the given salt (see
+    /// [StoredDiff::redacted]). This keeps the structure of the diffs (i.e., their files, hunks,
+    /// line types, and line counts) for datasets that must not contain source code.
+    Redacted { salt: String },
+}
+
+impl Debug for DiffExport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffExport::None => write!(f, "None"),
+            DiffExport::Plain => write!(f, "Plain"),
+            DiffExport::Redacted { .. } => write!(f, "Redacted {{ salt: <redacted> }}"),
+        }
+    }
+}
+
+/// The diff of a commit as stored with results
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredDiff {
+    /// Whether the contents of the lines have been replaced by salted hashes
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+    pub hunks: Vec<StoredHunk>,
+}
+
+/// A hunk of a stored diff, whose lines are prefixed with the char of their line type (e.g., `+`
+/// for additions)
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredHunk {
+    pub header: String,
+    pub old_file: Option<PathBuf>,
+    pub new_file: Option<PathBuf>,
+    pub old_start: u32,
+    pub new_start: u32,
+    pub lines: Vec<String>,
+}
+
+impl From<&Hunk> for StoredHunk {
+    fn from(hunk: &Hunk) -> Self {
+        Self {
+            header: hunk.header().to_string(),
+            old_file: hunk.old_file().clone(),
+            new_file: hunk.new_file().clone(),
+            old_start: hunk.old_start(),
+            new_start: hunk.new_start(),
+            lines: hunk.body().iter().map(|line| line.to_string()).collect(),
+        }
+    }
+}
+
+impl StoredDiff {
+    pub fn new(diff: &Diff) -> Self {
+        Self {
+            redacted: false,
+            hunks: diff.hunks.iter().map(StoredHunk::from).collect(),
+        }
+    }
+
+    /// Store the given diff according to the given export mode, or None if no diffs are stored
+    pub fn export(diff: &Diff, export: &DiffExport) -> Option<Self> {
+        match export {
+            DiffExport::None => None,
+            DiffExport::Plain => Some(Self::new(diff)),
+            DiffExport::Redacted { salt } => Some(Self::new(diff).redacted(salt)),
+        }
+    }
+
+    /// Replace the content of each line by a hash of the content that is salted with the given
+    /// salt, and the section heading of each hunk header (i.e., the text after the line ranges,
+    /// which usually is the signature of the enclosing function) by the hash of the heading. The
+    /// files, line ranges, line types, and the number of lines are kept.
+    ///
+    /// The hashes are truncated SHA-1 hashes of the salt and the content. Equal lines have equal
+    /// hashes, so that lines can still be matched between the diffs of a dataset (e.g., between
+    /// cherry and target), but not between datasets that were redacted with different salts. A
+    /// secret salt prevents that the hashes of common lines are reversed with a dictionary.
+    /// Diffs that are already redacted are returned unchanged.
+    pub fn redacted(self, salt: &str) -> Self {
+        if self.redacted {
+            return self;
+        }
+        let mut hashes = HashMap::new();
+        let mut hash = |content: &str| -> String {
+            hashes
+                .entry(content.to_string())
+                .or_insert_with(|| salted_hash(salt, content))
+                .clone()
+        };
+        let hunks = self
+            .hunks
+            .into_iter()
+            .map(|hunk| {
+                let header = match hunk.header.rsplit_once("@@") {
+                    Some((ranges, heading)) if !heading.trim().is_empty() => {
+                        format!("{ranges}@@ {}", hash(heading.trim()))
+                    }
+                    _ => hunk.header,
+                };
+                let lines = hunk
+                    .lines
+                    .iter()
+                    .map(|line| {
+                        let mut chars = line.chars();
+                        match chars.next() {
+                            Some(line_type) => format!("{line_type}{}", hash(chars.as_str())),
+                            None => String::new(),
+                        }
+                    })
+                    .collect();
+                StoredHunk {
+                    header,
+                    lines,
+                    ..hunk
+                }
+            })
+            .collect();
+        Self {
+            redacted: true,
+            hunks,
+        }
+    }
+}
+
+fn salted_hash(salt: &str, content: &str) -> String {
+    let data = format!("{salt}\n{content}");
+    match Oid::hash_object(ObjectType::Blob, data.as_bytes()) {
+        Ok(oid) => oid.to_string()[..HASH_LENGTH].to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Store the diffs of the cherry and target of each result according to the given export mode.
+/// The diffs of commits that have not been extracted yet (e.g., because all methods only
+/// required commit metadata) are extracted for the commits of the results only.
+pub fn attach_diffs(results: &mut [SearchResult], commits: &mut [Commit], export: &DiffExport) {
+    profile_fn!(attach_diffs);
+    if *export == DiffExport::None || results.is_empty() {
+        return;
+    }
+    let mut wanted: HashMap<String, Option<StoredDiff>> = results
+        .iter()
+        .flat_map(|result| result.commit_pair().as_vec())
+        .map(|commit| (commit.id().to_string(), None))
+        .collect();
+    for commit in commits.iter_mut() {
+        if let Some(stored) = wanted.get_mut(&commit.id().to_string()) {
+            // Merges that are collected once per parent share their id; the first diff is kept
+            if stored.is_none() {
+                *stored = StoredDiff::export(commit.calculate_diff(), export);
+            }
+        }
+    }
+    for result in results {
+        let pair = result.commit_pair_mut();
+        let cherry = wanted.get(pair.cherry().id()).cloned().flatten();
+        let target = wanted.get(pair.target().id()).cloned().flatten();
+        pair.cherry_mut().set_diff(cherry);
+        pair.target_mut().set_diff(target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::stored_diff::{DiffExport, StoredDiff, StoredHunk};
+    use std::path::PathBuf;
+
+    fn plain() -> StoredDiff {
+        StoredDiff {
+            redacted: false,
+            hunks: vec![StoredHunk {
+                header: "@@ -1,3 +1,3 @@ fn secret_algorithm()".to_string(),
+                old_file: Some(PathBuf::from("src/lib.rs")),
+                new_file: Some(PathBuf::from("src/lib.rs")),
+                old_start: 1,
+                new_start: 1,
+                lines: vec![
+                    " let a = 1;".to_string(),
+                    "-let b = a + 1;".to_string(),
+                    "+let b = a.saturating_add(1);".to_string(),
+                    " let a = 1;".to_string(),
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn redaction_keeps_the_structure() {
+        let plain = plain();
+        let redacted = plain.clone().redacted("pepper");
+        assert!(redacted.redacted);
+        assert_eq!(redacted.hunks.len(), plain.hunks.len());
+        let (plain_hunk, hunk) = (&plain.hunks[0], &redacted.hunks[0]);
+        assert_eq!(hunk.old_file, plain_hunk.old_file);
+        assert_eq!(hunk.old_start, plain_hunk.old_start);
+        assert_eq!(hunk.lines.len(), plain_hunk.lines.len());
+        assert!(hunk.header.starts_with("@@ -1,3 +1,3 @@ "));
+        assert!(!hunk.header.contains("secret_algorithm"));
+        for (plain_line, line) in plain_hunk.lines.iter().zip(&hunk.lines) {
+            assert_eq!(line.chars().next(), plain_line.chars().next());
+            assert_eq!(line.len(), 17);
+        }
+        // Equal lines have equal hashes, which depend on the salt
+        assert_eq!(hunk.lines[0], hunk.lines[3]);
+        assert_ne!(hunk.lines[1][1..], hunk.lines[2][1..]);
+        let salted = plain.redacted("salt");
+        assert_ne!(salted.hunks[0].lines[0], hunk.lines[0]);
+        // Redacted diffs are not redacted again
+        assert_eq!(redacted.clone().redacted("salt"), redacted);
+    }
+
+    #[test]
+    fn salt_is_not_debug_printed() {
+        let export = DiffExport::Redacted {
+            salt: "pepper".to_string(),
+        };
+        assert!(!format!("{export:?}").contains("pepper"));
+    }
+}
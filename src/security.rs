@@ -0,0 +1,232 @@
+use crate::git::annotation::CommitAnnotator;
+use crate::git::{collect_commits_with_config, CollectionConfig, LoadedRepository};
+use crate::search::stored_diff::attach_diffs;
+use crate::{
+    extract_facets, load_repositories, remove_noise, trace_result_repositories, Commit,
+    GitRepository, Result, SearchMethod, SearchResult,
+};
+use firestorm::profile_fn;
+use git2::Oid;
+use log::info;
+use octocrab::models::RepositoryId;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// The annotation key under which the CVE ids referenced by a commit are stored
+pub const CVE_ANNOTATION: &str = "cve";
+/// The annotation key with which security fixes are marked
+pub const SECURITY_ANNOTATION: &str = "security";
+
+static CVE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bCVE-\d{4}-\d{4,}\b").unwrap());
+static KEYWORD_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(security|vulnerab\w*|exploit\w*|CWE-\d+|buffer overflow|use[- ]after[- ]free|xss|csrf|sql injection|remote code execution|privilege escalation)\b",
+    )
+    .unwrap()
+});
+
+/// Annotates commits whose messages reference CVEs or contain security keywords. Such commits
+/// are marked with the `security` annotation; referenced CVE ids are stored as comma-separated
+/// list in the `cve` annotation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SecurityFixAnnotator;
+
+impl CommitAnnotator for SecurityFixAnnotator {
+    fn annotate(&self, commit: &Commit) -> Vec<(String, String)> {
+        commit.message().map_or(vec![], security_annotations)
+    }
+}
+
+fn security_annotations(message: &str) -> Vec<(String, String)> {
+    let mut cves = CVE_PATTERN
+        .find_iter(message)
+        .map(|m| m.as_str().to_uppercase())
+        .collect::<Vec<String>>();
+    cves.sort();
+    cves.dedup();
+    let mut annotations = vec![];
+    if !cves.is_empty() {
+        annotations.push((CVE_ANNOTATION.to_string(), cves.join(",")));
+    }
+    if !cves.is_empty() || KEYWORD_PATTERN.is_match(message) {
+        annotations.push((SECURITY_ANNOTATION.to_string(), "true".to_string()));
+    }
+    annotations
+}
+
+/// A commit that has been identified as security fix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityFix {
+    pub commit_id: String,
+    /// The first line of the commit message
+    pub summary: String,
+    /// The CVE ids referenced by the commit message
+    pub cves: Vec<String>,
+}
+
+/// Whether a repository has received a security fix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatchStatus {
+    /// The fix commit itself is reachable from one of the repository's branches
+    Contains,
+    /// A pick of the fix is reachable from one of the repository's branches
+    Picked { commit_id: String },
+    /// Neither the fix nor any of its picks are reachable
+    Unpatched,
+}
+
+/// The patch status of a single security fix in all searched repositories
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixReport {
+    pub fix: SecurityFix,
+    pub repositories: BTreeMap<RepositoryId, PatchStatus>,
+}
+
+/// Reports which of the searched repositories did or did not receive each security fix.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityReport {
+    pub fixes: Vec<FixReport>,
+}
+
+impl SecurityReport {
+    /// All pairs of security fixes and repositories that have not received the fix
+    pub fn unpatched(&self) -> Vec<(&SecurityFix, RepositoryId)> {
+        self.fixes
+            .iter()
+            .flat_map(|report| {
+                report
+                    .repositories
+                    .iter()
+                    .filter(|(_, status)| **status == PatchStatus::Unpatched)
+                    .map(move |(id, _)| (&report.fix, *id))
+            })
+            .collect()
+    }
+}
+
+/// Preset pipeline that detects security fixes and their backports in the given repositories
+/// (e.g., the repositories of a ForkNetwork).
+///
+/// Security fixes are identified by the [SecurityFixAnnotator]. The given search methods are used
+/// to find picks of the fixes. Then, each repository is checked for whether it contains the fix
+/// itself or one of its picks on any of its branches.
+pub async fn detect_security_backports(
+    repos: &[&GitRepository],
+    methods: &[Box<dyn SearchMethod>],
+    config: &CollectionConfig,
+) -> Result<SecurityReport> {
+    profile_fn!(detect_security_backports);
+    let loaded_repos = load_repositories(repos, &config.clone).await?;
+    let mut config = config.clone();
+    config.annotators.push(SecurityFixAnnotator);
+    let mut commits = collect_commits_with_config(&loaded_repos, &config)
+        .into_iter()
+        .collect::<Vec<Commit>>();
+    remove_noise(&mut commits, repos, &config);
+
+    let fixes = commits
+        .iter()
+        .filter(|c| c.annotations().contains_key(SECURITY_ANNOTATION))
+        .map(|c| SecurityFix {
+            commit_id: c.id().to_string(),
+            summary: c
+                .message()
+                .and_then(|m| m.lines().next())
+                .unwrap_or_default()
+                .to_string(),
+            cves: c
+                .annotations()
+                .get(CVE_ANNOTATION)
+                .map(|cves| cves.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+        })
+        .collect::<Vec<SecurityFix>>();
+    info!("found {} security fixes", fixes.len());
+    extract_facets(&mut commits, methods.iter().map(|m| m.required_facet()));
+
+    let mut results = methods
+        .iter()
+        .flat_map(|m| m.search(&mut commits))
+        .collect::<HashSet<SearchResult>>()
+        .into_iter()
+        .collect::<Vec<SearchResult>>();
+    trace_result_repositories(&mut results, &commits, repos, &loaded_repos);
+    attach_diffs(&mut results, &mut commits, &config.result_diffs);
+
+    let fixes = fixes
+        .into_iter()
+        .map(|fix| {
+            let picks = pick_ids(&fix.commit_id, &results);
+            let repositories = repos
+                .iter()
+                .zip(&loaded_repos)
+                .map(|(repo, loaded)| {
+                    let status = if contains(loaded, &fix.commit_id) {
+                        PatchStatus::Contains
+                    } else {
+                        match picks.iter().find(|pick| contains(loaded, pick)) {
+                            Some(pick) => PatchStatus::Picked {
+                                commit_id: pick.to_string(),
+                            },
+                            None => PatchStatus::Unpatched,
+                        }
+                    };
+                    (repo.id, status)
+                })
+                .collect();
+            FixReport { fix, repositories }
+        })
+        .collect();
+    Ok(SecurityReport { fixes })
+}
+
+/// The ids of all commits that have been identified as pick of the given commit, or of which the
+/// given commit is a pick.
+fn pick_ids<'r>(commit_id: &str, results: &'r [SearchResult]) -> Vec<&'r str> {
+    let mut ids = results
+        .iter()
+        .filter_map(|result| {
+            let pair = result.commit_pair();
+            if pair.cherry().id() == commit_id {
+                Some(pair.target().id())
+            } else if pair.target().id() == commit_id {
+                Some(pair.cherry().id())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<&str>>();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn contains(loaded: &LoadedRepository, commit_id: &str) -> bool {
+    Oid::from_str(commit_id).is_ok_and(|oid| loaded.contains_commit(oid))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::security::{security_annotations, CVE_ANNOTATION, SECURITY_ANNOTATION};
+
+    #[test]
+    fn security_fixes_are_annotated() {
+        assert_eq!(
+            security_annotations("Fix heap overflow (cve-2024-1234, CVE-2023-98765)"),
+            vec![
+                (
+                    CVE_ANNOTATION.to_string(),
+                    "CVE-2023-98765,CVE-2024-1234".to_string()
+                ),
+                (SECURITY_ANNOTATION.to_string(), "true".to_string())
+            ]
+        );
+        assert_eq!(
+            security_annotations("Prevent use-after-free in parser"),
+            vec![(SECURITY_ANNOTATION.to_string(), "true".to_string())]
+        );
+        assert!(security_annotations("Update README").is_empty());
+    }
+}
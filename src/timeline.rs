@@ -0,0 +1,251 @@
+use crate::{Result, SearchResult};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use octocrab::models::RepositoryId;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+/// The length of the periods in which picks are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Period {
+    /// Weeks starting on Monday
+    Week,
+    Month,
+}
+
+impl Period {
+    /// The first day of the period that contains the given time
+    pub fn start_of(&self, time: DateTime<Utc>) -> NaiveDate {
+        let date = time.date_naive();
+        match self {
+            Period::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Period::Month => date.with_day(1).unwrap(),
+        }
+    }
+}
+
+/// The scope in which picks are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Scope {
+    /// Picks whose target was found in the repository with the given id
+    Repository(RepositoryId),
+    /// Picks within the fork network whose source has the given id
+    Network(RepositoryId),
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Repository(id) => write!(f, "repository,{id}"),
+            Scope::Network(id) => write!(f, "network,{id}"),
+        }
+    }
+}
+
+/// A single data point of a timeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub scope: Scope,
+    pub method: String,
+    pub period_start: NaiveDate,
+    pub picks: usize,
+}
+
+/// A Timeline aggregates search results into time series of the number of picks per period. Each
+/// pick is dated by the commit time of its target (i.e., the time at which the change was picked)
+/// and is counted for the target's repository and for the fork network, if these are known (see
+/// [crate::git::github::ForkNetwork::annotate_results]). Picks are counted separately for each
+/// search method.
+///
+/// Results whose target's time or repository is unknown are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    period: Period,
+    counts: BTreeMap<(Scope, String, NaiveDate), usize>,
+}
+
+impl Timeline {
+    pub fn new(period: Period) -> Self {
+        Self {
+            period,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn period(&self) -> Period {
+        self.period
+    }
+
+    /// Count the given result in all scopes that are known for it.
+    pub fn add(&mut self, result: &SearchResult) {
+        let target = result.commit_pair().target();
+        let Some(time) = target.timestamp() else {
+            return;
+        };
+        let period_start = self.period.start_of(time);
+        let scopes = [
+            target.repository().map(Scope::Repository),
+            result
+                .fork_context()
+                .map(|context| Scope::Network(context.network_source)),
+        ];
+        for scope in scopes.into_iter().flatten() {
+            *self
+                .counts
+                .entry((scope, result.search_method().to_string(), period_start))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn add_all<'a, I: IntoIterator<Item = &'a SearchResult>>(&mut self, results: I) {
+        results.into_iter().for_each(|result| self.add(result));
+    }
+
+    /// Merge the counts of another timeline into this one.
+    ///
+    /// # Panics
+    /// Panics if the timelines have different periods.
+    pub fn merge(&mut self, other: Timeline) {
+        assert_eq!(self.period, other.period, "cannot merge timelines");
+        for (key, count) in other.counts {
+            *self.counts.entry(key).or_default() += count;
+        }
+    }
+
+    /// All entries, ordered by scope, method, and period
+    pub fn entries(&self) -> Vec<TimelineEntry> {
+        self.counts
+            .iter()
+            .map(|((scope, method, period_start), picks)| TimelineEntry {
+                scope: *scope,
+                method: method.clone(),
+                period_start: *period_start,
+                picks: *picks,
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Export the timeline as CSV with the columns `scope,id,method,period_start,picks`.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "scope,id,method,period_start,picks")?;
+        for ((scope, method, period_start), picks) in &self.counts {
+            writeln!(writer, "{scope},{method},{period_start},{picks}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use crate::git::collect_commits;
+    use crate::git::test_util::{ResultBuilder, TestRepository};
+    use crate::timeline::{Period, Scope, Timeline};
+    use crate::{CherryAndTarget, Commit, SearchResult};
+    use chrono::{Duration, NaiveDate};
+    use serde_json::json;
+
+    fn result(method: &str, target_time: i64) -> SearchResult {
+        ResultBuilder::new(method)
+            .cherry("timestamp", 0)
+            .target("timestamp", target_time)
+            .target("repository", 7)
+            .with(
+                "fork_context",
+                json!({
+                    "network_source": 1,
+                    "cherry_depth": 0,
+                    "target_depth": 1,
+                    "target_is_fork_of_cherry": true,
+                    "cherry_is_fork_of_target": false,
+                }),
+            )
+            .build()
+    }
+
+    #[test]
+    fn picks_are_counted_per_period_and_scope() {
+        // 2023-01-27 (Friday) and 2023-02-01 (Wednesday)
+        let results = [
+            result("MessageScan", 1674832148),
+            result("MessageScan", 1675245600),
+        ];
+        let mut monthly = Timeline::new(Period::Month);
+        monthly.add_all(&results);
+        let entries = monthly.entries();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].scope, Scope::Repository(7.into()));
+        assert_eq!(
+            entries[0].period_start,
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()
+        );
+
+        let mut weekly = Timeline::new(Period::Week);
+        weekly.add_all(&results);
+        let entries = weekly.entries();
+        assert_eq!(
+            entries[0].period_start,
+            NaiveDate::from_ymd_opt(2023, 1, 23).unwrap()
+        );
+        assert_eq!(entries[1].picks, 1);
+
+        let mut csv = vec![];
+        weekly.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("scope,id,method,period_start,picks\n"));
+        assert!(csv.contains("network,1,MessageScan,2023-01-30,1\n"));
+    }
+
+    #[test]
+    fn picks_of_synthetic_commits_are_dated_by_their_targets() {
+        // 2023-01-27, 2023-01-31, and 2023-02-01
+        let clock = ManualClock::at_timestamp(1674832148);
+        let fixture = TestRepository::new();
+        for (message, days) in [("fix", 0), ("pick-1", 4), ("pick-2", 1)] {
+            clock.advance(Duration::try_days(days).unwrap());
+            let signature = clock.signature("test", "test@example.com");
+            fixture.commit_as(&signature, message, &[("file", message)], &[], message);
+        }
+        let (_directory, repository) = fixture.into_loaded();
+        let repos = [repository];
+        let commits = collect_commits(&repos);
+        let by_message = |message: &str| -> &Commit {
+            commits
+                .iter()
+                .find(|c| c.message() == Some(message))
+                .unwrap()
+        };
+        let results: Vec<SearchResult> = ["pick-1", "pick-2"]
+            .into_iter()
+            .map(|pick| {
+                // The direction is inferred from the commit times
+                let pair = CherryAndTarget::construct(by_message(pick), by_message("fix"));
+                let mut result = SearchResult::new("MessageScan".to_string(), pair);
+                result
+                    .commit_pair_mut()
+                    .target_mut()
+                    .set_repository(Some(7.into()));
+                result
+            })
+            .collect();
+
+        let mut monthly = Timeline::new(Period::Month);
+        monthly.add_all(&results);
+        let periods: Vec<(NaiveDate, usize)> = monthly
+            .entries()
+            .iter()
+            .map(|entry| (entry.period_start, entry.picks))
+            .collect();
+        assert_eq!(
+            periods,
+            vec![
+                (NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), 1),
+                (NaiveDate::from_ymd_opt(2023, 2, 1).unwrap(), 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{CommitMetadata, Result, SearchResult};
+use octocrab::models::RepositoryId;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The number of chars of the message summary in the label of a commit
+const SUMMARY_LENGTH: usize = 40;
+/// The number of hex digits of the abbreviated commit ids
+const SHORT_ID_LENGTH: usize = 8;
+/// The Graphviz command that lays out the graph for the SVG export
+pub const LAYOUT_COMMAND: &str = "dot";
+
+/// A commit that is involved in at least one pick, i.e., a node of a [CherryGraph]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub id: String,
+    /// The first line of the commit message
+    pub summary: String,
+    /// The repository in which the commit was found first, if known
+    pub repository: Option<RepositoryId>,
+    /// The branch on which the commit was found, if known (see [CherryGraph::set_branch])
+    pub branch: Option<String>,
+    parent_ids: Vec<String>,
+}
+
+/// A pick from the cherry to the target, together with all methods that found it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CherryEdge {
+    pub cherry: String,
+    pub target: String,
+    pub methods: BTreeSet<String>,
+    /// The highest score that any of the methods assigned to the pick
+    pub score: Option<f64>,
+}
+
+/// A graph of the abbreviated history of the commits that are involved in picks. It only contains
+/// the cherries and targets of the given results; a commit is connected to its parent by a
+/// history edge if the parent is involved in a pick as well. Picks that were found by several
+/// methods are shown as a single cherry edge, which is labeled with all of its methods.
+///
+/// The graph is exported in the DOT format of Graphviz, in which the commits of each repository
+/// are grouped into a cluster. If Graphviz is installed, the graph can also be laid out and
+/// exported as SVG.
+///
+/// # Examples
+/// ```no_run
+/// use cherry_harvest::visualization::CherryGraph;
+/// use cherry_harvest::SearchResult;
+///
+/// # fn plot(results: &[SearchResult]) -> cherry_harvest::Result<()> {
+/// let mut graph = CherryGraph::new(results);
+/// graph.set_branch("4b825dc642cb6eb9a060e54bf8d69288fbee4904", "main");
+/// graph.write_dot(std::fs::File::create("picks.dot")?)?;
+/// graph.write_svg("picks.svg")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CherryGraph {
+    nodes: BTreeMap<String, GraphNode>,
+    edges: BTreeMap<(String, String), CherryEdge>,
+}
+
+impl CherryGraph {
+    /// The graph of the commits that are involved in the given results
+    pub fn new<'a, I: IntoIterator<Item = &'a SearchResult>>(results: I) -> Self {
+        let mut graph = Self::default();
+        graph.add_all(results);
+        graph
+    }
+
+    /// Add the cherry and target of the given result, and the pick between them
+    pub fn add(&mut self, result: &SearchResult) {
+        let pair = result.commit_pair();
+        for commit in pair.as_vec() {
+            self.nodes
+                .entry(commit.id().to_string())
+                .or_insert_with(|| GraphNode::from(commit));
+        }
+        let (cherry, target) = (pair.cherry().id(), pair.target().id());
+        let edge = self
+            .edges
+            .entry((cherry.to_string(), target.to_string()))
+            .or_insert_with(|| CherryEdge {
+                cherry: cherry.to_string(),
+                target: target.to_string(),
+                methods: BTreeSet::new(),
+                score: None,
+            });
+        edge.methods.insert(result.search_method().to_string());
+        if let Some(score) = result.score() {
+            edge.score = Some(edge.score.map_or(score, |s| s.max(score)));
+        }
+    }
+
+    pub fn add_all<'a, I: IntoIterator<Item = &'a SearchResult>>(&mut self, results: I) {
+        results.into_iter().for_each(|result| self.add(result));
+    }
+
+    /// Annotate the commit with the given id with the branch on which it was found (e.g., the
+    /// reference of its [Provenance](crate::git::Provenance)). Commits that are not in the graph
+    /// are ignored.
+    pub fn set_branch(&mut self, commit_id: &str, branch: &str) {
+        if let Some(node) = self.nodes.get_mut(commit_id) {
+            node.branch = Some(branch.to_string());
+        }
+    }
+
+    /// The involved commits, ordered by id
+    pub fn nodes(&self) -> impl Iterator<Item = &GraphNode> {
+        self.nodes.values()
+    }
+
+    /// The picks, ordered by the ids of cherry and target
+    pub fn cherry_edges(&self) -> impl Iterator<Item = &CherryEdge> {
+        self.edges.values()
+    }
+
+    /// The pairs of parent and child of which both are involved in picks
+    pub fn history_edges(&self) -> Vec<(&str, &str)> {
+        self.nodes
+            .values()
+            .flat_map(|node| {
+                node.parent_ids
+                    .iter()
+                    .filter(|parent| self.nodes.contains_key(*parent))
+                    .map(|parent| (parent.as_str(), node.id.as_str()))
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The graph in the DOT format
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph picks {\n");
+        dot.push_str("  rankdir=LR;\n");
+        dot.push_str("  node [shape=box, fontname=monospace, fontsize=10];\n");
+
+        let mut by_repository: BTreeMap<Option<RepositoryId>, Vec<&GraphNode>> = BTreeMap::new();
+        for node in self.nodes.values() {
+            by_repository.entry(node.repository).or_default().push(node);
+        }
+        for (repository, nodes) in by_repository {
+            let indent = match repository {
+                Some(repository) => {
+                    // Subgraphs whose names start with "cluster" are drawn as boxes
+                    let _ = writeln!(dot, "  subgraph \"cluster_{repository}\" {{");
+                    let _ = writeln!(dot, "    label=\"repository {repository}\";");
+                    "    "
+                }
+                None => "  ",
+            };
+            for node in nodes {
+                let _ = writeln!(
+                    dot,
+                    "{indent}\"{}\" [label=\"{}\"];",
+                    escape(&node.id),
+                    escape(&node.label())
+                );
+            }
+            if repository.is_some() {
+                dot.push_str("  }\n");
+            }
+        }
+
+        for (parent, child) in self.history_edges() {
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [color=gray];",
+                escape(parent),
+                escape(child)
+            );
+        }
+        for edge in self.edges.values() {
+            let mut label = edge.methods.iter().cloned().collect::<Vec<_>>().join(", ");
+            if let Some(score) = edge.score {
+                let _ = write!(label, " ({score:.2})");
+            }
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [color=red, style=dashed, constraint=false, label=\"{}\"];",
+                escape(&edge.cherry),
+                escape(&edge.target),
+                escape(&label)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(self.to_dot().as_bytes())?;
+        Ok(())
+    }
+
+    /// Lay out the graph with Graphviz and write it as SVG to the given file
+    ///
+    /// # Errors
+    /// Returns an error if Graphviz's [LAYOUT_COMMAND] is not installed, or if it fails.
+    pub fn write_svg<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut layout = Command::new(LAYOUT_COMMAND)
+            .arg("-Tsvg")
+            .arg("-o")
+            .arg(path.as_ref())
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = layout.stdin.take() {
+            stdin.write_all(self.to_dot().as_bytes())?;
+        }
+        let output = layout.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{LAYOUT_COMMAND} failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl From<&CommitMetadata> for GraphNode {
+    fn from(commit: &CommitMetadata) -> Self {
+        Self {
+            id: commit.id().to_string(),
+            summary: commit
+                .message()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            repository: commit.repository(),
+            branch: None,
+            parent_ids: commit.parent_ids().to_vec(),
+        }
+    }
+}
+
+impl GraphNode {
+    /// The abbreviated id, the abbreviated summary, and the branch of the commit
+    fn label(&self) -> String {
+        let short_id: String = self.id.chars().take(SHORT_ID_LENGTH).collect();
+        let mut summary: String = self.summary.chars().take(SUMMARY_LENGTH).collect();
+        if self.summary.chars().count() > SUMMARY_LENGTH {
+            summary.push('…');
+        }
+        match &self.branch {
+            Some(branch) => format!("{short_id}\n{summary}\n[{branch}]"),
+            None => format!("{short_id}\n{summary}"),
+        }
+    }
+}
+
+/// Escape the given text for a quoted DOT string, in which line breaks are written as `\n`
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::test_util::ResultBuilder;
+    use crate::visualization::CherryGraph;
+    use crate::SearchResult;
+
+    fn result(method: &str, cherry: &str, target: &str, target_parent: &str) -> SearchResult {
+        ResultBuilder::new(method)
+            .cherry("id", cherry)
+            .cherry("message", "Fix \"quoted\" bug")
+            .cherry("repository", 1)
+            .target("id", target)
+            .target("parent_ids", [target_parent].as_slice())
+            .target("message", "Fix bug\n\n(cherry picked)")
+            .target("repository", 2)
+            .build()
+    }
+
+    #[test]
+    fn picks_are_exported_as_dot() {
+        let results = [
+            result("MessageScan", "aaaa", "bbbb", "cccc"),
+            // Found again by another method
+            result("ExactDiffMatch", "aaaa", "bbbb", "cccc"),
+            // A second pick whose target is the parent of the first target
+            result("MessageScan", "aaaa", "cccc", "dddd"),
+        ];
+        let mut graph = CherryGraph::new(&results);
+        graph.set_branch("bbbb", "refs/heads/release");
+        graph.set_branch("eeee", "refs/heads/main");
+
+        assert_eq!(graph.nodes().count(), 3);
+        assert_eq!(graph.cherry_edges().count(), 2);
+        let methods: Vec<&str> = graph
+            .cherry_edges()
+            .next()
+            .unwrap()
+            .methods
+            .iter()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(methods, ["ExactDiffMatch", "MessageScan"]);
+        // The parent of the second target is not involved in any pick
+        assert_eq!(graph.history_edges(), vec![("cccc", "bbbb")]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph picks {\n"));
+        assert!(dot.contains("subgraph \"cluster_1\""));
+        assert!(dot.contains("subgraph \"cluster_2\""));
+        assert!(dot.contains("\"aaaa\" [label=\"aaaa\\nFix \\\"quoted\\\" bug\"];"));
+        assert!(dot.contains("\"bbbb\" [label=\"bbbb\\nFix bug\\n[refs/heads/release]\"];"));
+        assert!(dot.contains("\"cccc\" -> \"bbbb\" [color=gray];"));
+        assert!(dot.contains("label=\"ExactDiffMatch, MessageScan\""));
+        assert!(dot.ends_with("}\n"));
+    }
+}
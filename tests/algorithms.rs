@@ -1,9 +1,9 @@
-mod util;
-
+use cherry_harvest::evaluation::GroundTruth;
 use cherry_harvest::git::GitRepository;
-use cherry_harvest::{ExactDiffMatch, MessageScan, RepoLocation};
+use cherry_harvest::{ExactDiffMatch, MessageScan, RepoLocation, SearchMethod, SearchResult};
+use futures_util::StreamExt;
 use log::{info, LevelFilter};
-use util::ground_truth::GroundTruth;
+use std::collections::HashSet;
 
 const CHERRIES_ONE: &str = "https://github.com/AlexanderSchultheiss/cherries-one.git";
 
@@ -15,7 +15,7 @@ fn init() -> GroundTruth {
         .try_init();
 
     // load and return ground truth for cherries_one
-    GroundTruth::load("tests/resources/cherries_one_gt.yaml")
+    GroundTruth::load("tests/resources/cherries_one_gt.yaml").unwrap()
 }
 
 #[test]
@@ -91,3 +91,28 @@ fn diff_exact() {
         assert!(result_ids.contains(&expected));
     }
 }
+
+#[test]
+fn streamed_results_match_collected_results() {
+    init();
+    let repository =
+        GitRepository::from(RepoLocation::Filesystem(std::env::current_dir().unwrap()));
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (_, expected) = runtime
+        .block_on(cherry_harvest::search_with(
+            &[&repository],
+            ExactDiffMatch::default(),
+        ))
+        .unwrap();
+
+    let methods: Vec<Box<dyn SearchMethod + Send>> = vec![Box::<ExactDiffMatch>::default()];
+    let streamed = runtime.block_on(
+        cherry_harvest::search_with_streaming(&[&repository], methods)
+            .map(|result| result.unwrap())
+            .collect::<Vec<SearchResult>>(),
+    );
+    assert_eq!(
+        streamed.into_iter().collect::<HashSet<SearchResult>>(),
+        expected.into_iter().collect::<HashSet<SearchResult>>()
+    );
+}
@@ -0,0 +1,110 @@
+//! Integration tests of the harvest pipeline under injected failures (see cherry_harvest::chaos).
+//! Run with `cargo test --features chaos --test chaos`.
+use cherry_harvest::chaos::{self, ChaosConfig, Fault};
+use cherry_harvest::error::ErrorKind;
+use cherry_harvest::git::github::search_repositories;
+use cherry_harvest::git::{clone_or_load_with, CloneConfig, RetryPolicy};
+use cherry_harvest::report::{catch_panic, FailureKind, HarvestReport};
+use cherry_harvest::{ExactDiffMatch, GitRepository, HarvestRun, RepoId, RepoLocation};
+use log::LevelFilter;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+use temp_dir::TempDir;
+
+fn init() {
+    let _ = env_logger::builder()
+        .is_test(true)
+        .filter_level(LevelFilter::Debug)
+        .try_init();
+}
+
+/// A repository with a single commit in a temporary directory
+fn local_repository() -> TempDir {
+    let directory = TempDir::new().unwrap();
+    let repository = git2::Repository::init(directory.path()).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    let blob = repository.blob(b"a\n").unwrap();
+    let mut builder = repository.treebuilder(None).unwrap();
+    builder.insert("file", blob, 0o100644).unwrap();
+    let tree = repository.find_tree(builder.write().unwrap()).unwrap();
+    repository
+        .commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])
+        .unwrap();
+    directory
+}
+
+// The injected faults are global, so all scenarios run in a single test
+#[test]
+fn injected_failures_are_retried_and_reported() {
+    init();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    // Requests that are not intercepted fail locally instead of reaching GitHub
+    let _guard = runtime.enter();
+    octocrab::initialise(
+        octocrab::Octocrab::builder()
+            .base_uri("http://127.0.0.1:1")
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+    let directory = local_repository();
+    let local = RepoLocation::Filesystem(directory.path().to_path_buf());
+
+    // Transient clone failures are retried until the attempts are exhausted
+    chaos::install(ChaosConfig::new(1).with_rate(Fault::CloneError, 1.0));
+    let config = CloneConfig {
+        retry: RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        },
+        ..CloneConfig::default()
+    };
+    let error = runtime
+        .block_on(clone_or_load_with(
+            &RepoLocation::Server(format!("file://{}", directory.path().display())),
+            &config,
+        ))
+        .err()
+        .unwrap();
+    assert!(matches!(error.0, ErrorKind::RepoClone(_)));
+    assert_eq!(chaos::injected(Fault::CloneError), 3);
+
+    // Forbidden requests to GitHub's API are not mistaken for rate limits
+    chaos::install(ChaosConfig::new(1).with_rate(Fault::ApiForbidden, 1.0));
+    match runtime.block_on(search_repositories("language:Rust")) {
+        Err(octocrab::Error::GitHub { source, .. }) => {
+            assert_eq!(source.status_code, http::StatusCode::FORBIDDEN)
+        }
+        other => panic!("expected an injected 403, got {other:?}"),
+    }
+    assert_eq!(chaos::injected(Fault::ApiForbidden), 1);
+
+    // Slow repositories are delayed
+    chaos::install(
+        ChaosConfig::new(1)
+            .with_rate(Fault::SlowRepo, 1.0)
+            .with_delay(Duration::from_millis(200)),
+    );
+    let start = Instant::now();
+    runtime
+        .block_on(clone_or_load_with(&local, &CloneConfig::default()))
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // Corrupt diffs only fail the affected repository, whose failure is reported
+    chaos::install(ChaosConfig::new(1).with_rate(Fault::CorruptDiff, 1.0));
+    let repository = GitRepository::from(local);
+    let outcome = catch_panic(AssertUnwindSafe(|| {
+        runtime.block_on(cherry_harvest::search_with(
+            &[&repository],
+            ExactDiffMatch::default(),
+        ))
+    }));
+    assert!(chaos::injected(Fault::CorruptDiff) > 0);
+    chaos::uninstall();
+    let error = outcome.err().unwrap();
+    let mut report = HarvestReport::new(HarvestRun::with_seed(1));
+    report.add_failure(&RepoId::github("owner", "corrupt"), &error);
+    assert_eq!(report.failures_of(FailureKind::Panic).count(), 1);
+}
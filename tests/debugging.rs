@@ -1,6 +1,6 @@
 extern crate core;
 
-use cherry_harvest::git::GitRepository;
+use cherry_harvest::git::{CommitFilter, GitRepository};
 use cherry_harvest::{ExactDiffMatch, SearchMethod, TraditionalLSH};
 use log::{debug, info, LevelFilter};
 use std::collections::HashSet;
@@ -28,7 +28,11 @@ fn traditional_lsh_finds_exact() {
     let methods = vec![exact_diff, lsh_search];
     let runtime = tokio::runtime::Runtime::new().unwrap();
     let (_, results) = runtime
-        .block_on(cherry_harvest::search_with_multiple(&[&repo], &methods))
+        .block_on(cherry_harvest::search_with_multiple(
+            &[&repo],
+            &methods,
+            &CommitFilter::default(),
+        ))
         .unwrap();
 
     let mut exact_results = HashSet::new();
@@ -70,6 +74,7 @@ fn traditional_lsh_finds_exact() {
 }
 
 #[test]
+#[ignore = "debugging aid that searches a large remote repository; run with `--ignored`"]
 fn tmp_debug() {
     let start = init();
     let repo = GitRepository::from(cherry_harvest::RepoLocation::Server(
@@ -77,10 +82,14 @@ fn tmp_debug() {
     ));
     let exact_diff = Box::<ExactDiffMatch>::default() as Box<dyn SearchMethod>;
     let lsh_search = Box::new(TraditionalLSH::new(8, 100, 5, 0.7)) as Box<dyn SearchMethod>;
-    let methods = vec![exact_diff];
+    let methods = vec![exact_diff, lsh_search];
     let runtime = tokio::runtime::Runtime::new().unwrap();
     let (_, results) = runtime
-        .block_on(cherry_harvest::search_with_multiple(&[&repo], &methods))
+        .block_on(cherry_harvest::search_with_multiple(
+            &[&repo],
+            &methods,
+            &CommitFilter::default(),
+        ))
         .unwrap();
 
     let mut exact_results = HashSet::new();
@@ -0,0 +1,483 @@
+//! Snapshot of the supported public API of cherry-harvest, i.e., the items that are defined in or
+//! re-exported by the crate root and the items that are re-exported by the prelude. Changing these
+//! items requires a corresponding version bump. Internals may change freely, as long as this
+//! snapshot does not change.
+//!
+//! The items and their signatures (function signatures, public fields, enum variants, trait
+//! methods, inherent methods, and implemented traits) are read from the rustdoc JSON of the
+//! library, which requires a nightly toolchain. The check is thus ignored by a plain `cargo test`;
+//! run it with `cargo test --test public_api -- --ignored`, which fails if no nightly toolchain
+//! is installed.
+//!
+//! After an intended change of the public API, update the snapshot with
+//! `UPDATE_PUBLIC_API=1 cargo test --test public_api`.
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SNAPSHOT: &str = "tests/resources/public_api.txt";
+
+/// Traits that the compiler implements on its own (auto traits) or for derived implementations
+/// (e.g., `StructuralPartialEq` for `#[derive(PartialEq)]`). Which of them rustdoc lists changes
+/// between nightly versions, and none of them are part of the API that the crate maintains.
+const GENERATED_TRAITS: [&str; 8] = [
+    "Freeze",
+    "RefUnwindSafe",
+    "Send",
+    "StructuralEq",
+    "StructuralPartialEq",
+    "Sync",
+    "Unpin",
+    "UnwindSafe",
+];
+
+/// Builds the rustdoc JSON of the library with the nightly toolchain
+fn rustdoc_json() -> Value {
+    let nightly = Command::new("cargo")
+        .args(["+nightly", "--version"])
+        .output();
+    assert!(
+        nightly.is_ok_and(|output| output.status.success()),
+        "the public API check requires a nightly toolchain (`rustup toolchain install nightly`)"
+    );
+    // A separate target directory, because the one of the running tests is locked
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir.join("target").join("public-api");
+    let status = Command::new("cargo")
+        .args(["+nightly", "rustdoc", "--lib", "--target-dir"])
+        .arg(&target_dir)
+        .args(["--", "-Z", "unstable-options", "--output-format", "json"])
+        .current_dir(manifest_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "could not build the rustdoc JSON");
+    let json = fs::read_to_string(target_dir.join("doc").join("cherry_harvest.json")).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+/// The only entry of an externally tagged enum value, e.g., `{"slice": ...}`
+fn variant(value: &Value) -> (&str, &Value) {
+    match value {
+        Value::String(kind) => (kind, &Value::Null),
+        Value::Object(map) if map.len() == 1 => {
+            let (kind, inner) = map.iter().next().unwrap();
+            (kind, inner)
+        }
+        _ => ("", value),
+    }
+}
+
+/// The name of a path's item, without its module path
+fn name(path: &Value) -> &str {
+    path["path"].as_str().unwrap().rsplit("::").next().unwrap()
+}
+
+fn array(value: &Value) -> &[Value] {
+    value.as_array().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Lists the items of the public API with their signatures, one per line
+struct Api<'json> {
+    index: &'json Map<String, Value>,
+    lines: Vec<String>,
+    // The path under which each item has already been listed
+    listed: HashMap<String, String>,
+}
+
+impl<'json> Api<'json> {
+    fn item(&self, id: &Value) -> Option<&'json Value> {
+        self.index.get(&id.to_string())
+    }
+
+    /// The public items of the given module as `(name, id)`, where re-exports are resolved
+    fn public_items(&self, module: &'json Value) -> Vec<(String, &'json Value)> {
+        let mut items = vec![];
+        for id in array(&module["inner"]["module"]["items"]) {
+            let item = self.item(id).unwrap();
+            if item["visibility"] != "public" {
+                continue;
+            }
+            match variant(&item["inner"]) {
+                ("use", import) => {
+                    assert_ne!(import["is_glob"], true, "glob re-exports are not supported");
+                    items.push((import["name"].as_str().unwrap().to_string(), &import["id"]));
+                }
+                // The prelude is listed on its own; the other modules are internal
+                ("module", _) => {}
+                _ => items.push((item["name"].as_str().unwrap().to_string(), id)),
+            }
+        }
+        items
+    }
+
+    fn list(&mut self, path: String, id: &Value) {
+        if let Some(listed) = self.listed.get(&id.to_string()) {
+            self.lines.push(format!("{path} = {listed}"));
+            return;
+        }
+        self.listed.insert(id.to_string(), path.clone());
+        let Some(item) = self.item(id) else {
+            self.lines.push(format!("{path} (external)"));
+            return;
+        };
+        let (kind, inner) = variant(&item["inner"]);
+        match kind {
+            "function" => {
+                let function = self.function(&path, inner);
+                self.lines.push(function);
+            }
+            "struct" => {
+                let (params, predicates) = self.generics(&inner["generics"]);
+                self.lines
+                    .push(format!("struct {path}{params}{predicates}"));
+                match variant(&inner["kind"]) {
+                    ("plain", plain) => self.fields(&path, &plain["fields"]),
+                    ("tuple", fields) => self.fields(&path, fields),
+                    _ => {}
+                }
+                self.impls(&path, &inner["impls"]);
+            }
+            "enum" => {
+                let (params, predicates) = self.generics(&inner["generics"]);
+                self.lines.push(format!("enum {path}{params}{predicates}"));
+                for id in array(&inner["variants"]) {
+                    let variant_item = self.item(id).unwrap();
+                    let name = variant_item["name"].as_str().unwrap();
+                    let variant_path = format!("{path}::{name}");
+                    self.lines.push(variant_path.clone());
+                    match variant(&variant_item["inner"]["variant"]["kind"]) {
+                        ("tuple", fields) => self.fields(&variant_path, fields),
+                        ("struct", fields) => self.fields(&variant_path, &fields["fields"]),
+                        _ => {}
+                    }
+                }
+                self.impls(&path, &inner["impls"]);
+            }
+            "trait" => {
+                let (params, predicates) = self.generics(&inner["generics"]);
+                let bounds = self.bounds(&inner["bounds"]);
+                let bounds = match bounds.is_empty() {
+                    true => bounds,
+                    false => format!(": {bounds}"),
+                };
+                self.lines
+                    .push(format!("trait {path}{params}{bounds}{predicates}"));
+                self.associated_items(&path, &inner["items"], false);
+            }
+            "type_alias" => {
+                let (params, predicates) = self.generics(&inner["generics"]);
+                let aliased = self.ty(&inner["type"]);
+                self.lines
+                    .push(format!("type {path}{params}{predicates} = {aliased}"));
+            }
+            _ => self.lines.push(format!("{kind} {path}")),
+        }
+    }
+
+    fn fields(&mut self, path: &str, fields: &Value) {
+        for (position, id) in array(fields).iter().enumerate() {
+            // Private fields of tuple structs are null
+            let Some(field) = self.item(id) else {
+                continue;
+            };
+            let name = match &field["name"] {
+                Value::String(name) if name.parse::<usize>().is_err() => name.clone(),
+                _ => position.to_string(),
+            };
+            let ty = self.ty(&field["inner"]["struct_field"]);
+            self.lines.push(format!("{path}.{name}: {ty}"));
+        }
+    }
+
+    /// Lists the inherent methods and the implemented traits, except for blanket implementations
+    /// and the [GENERATED_TRAITS]
+    fn impls(&mut self, path: &str, impls: &Value) {
+        let mut implemented_traits = vec![];
+        for id in array(impls) {
+            let implementation = &self.item(id).unwrap()["inner"]["impl"];
+            if implementation["is_synthetic"] == true || !implementation["blanket_impl"].is_null() {
+                continue;
+            }
+            match &implementation["trait"] {
+                Value::Null => self.associated_items(path, &implementation["items"], true),
+                implemented if GENERATED_TRAITS.contains(&name(implemented)) => {}
+                implemented => {
+                    let implemented = self.path(implemented);
+                    implemented_traits.push(format!("impl {implemented} for {path}"));
+                }
+            }
+        }
+        // The order of the implementations depends on rustdoc
+        implemented_traits.sort();
+        self.lines.extend(implemented_traits);
+    }
+
+    fn associated_items(&mut self, path: &str, items: &Value, only_public: bool) {
+        for id in array(items) {
+            let item = self.item(id).unwrap();
+            if only_public && item["visibility"] != "public" {
+                continue;
+            }
+            let item_path = format!("{path}::{}", item["name"].as_str().unwrap());
+            let line = match variant(&item["inner"]) {
+                ("function", function) => self.function(&item_path, function),
+                ("assoc_const", constant) => {
+                    format!("const {item_path}: {}", self.ty(&constant["type"]))
+                }
+                ("assoc_type", associated) => {
+                    let bounds = self.bounds(&associated["bounds"]);
+                    format!("type {item_path}: {bounds}")
+                }
+                (kind, _) => format!("{kind} {item_path}"),
+            };
+            self.lines.push(line);
+        }
+    }
+
+    fn function(&self, path: &str, function: &Value) -> String {
+        let header = &function["header"];
+        let mut qualifiers = String::new();
+        for qualifier in ["const", "async", "unsafe"] {
+            if header[format!("is_{qualifier}")] == true {
+                qualifiers.push_str(qualifier);
+                qualifiers.push(' ');
+            }
+        }
+        let (params, predicates) = self.generics(&function["generics"]);
+        let inputs: Vec<String> = array(&function["sig"]["inputs"])
+            .iter()
+            .map(|input| format!("{}: {}", input[0].as_str().unwrap(), self.ty(&input[1])))
+            .collect();
+        let output = match &function["sig"]["output"] {
+            Value::Null => String::new(),
+            output => format!(" -> {}", self.ty(output)),
+        };
+        format!(
+            "{qualifiers}fn {path}{params}({}){output}{predicates}",
+            inputs.join(", ")
+        )
+    }
+
+    /// The generic parameters and the where clause
+    fn generics(&self, generics: &Value) -> (String, String) {
+        let params: Vec<String> = array(&generics["params"])
+            .iter()
+            .filter_map(|param| {
+                let name = param["name"].as_str().unwrap();
+                match variant(&param["kind"]) {
+                    ("lifetime", lifetime) => {
+                        let outlives: Vec<&str> = array(&lifetime["outlives"])
+                            .iter()
+                            .map(|lifetime| lifetime.as_str().unwrap())
+                            .collect();
+                        match outlives.is_empty() {
+                            true => Some(name.to_string()),
+                            false => Some(format!("{name}: {}", outlives.join(" + "))),
+                        }
+                    }
+                    // The parameters of `impl Trait` arguments
+                    ("type", ty) if ty["is_synthetic"] == true => None,
+                    ("type", ty) => {
+                        let bounds = self.bounds(&ty["bounds"]);
+                        match bounds.is_empty() {
+                            true => Some(name.to_string()),
+                            false => Some(format!("{name}: {bounds}")),
+                        }
+                    }
+                    ("const", constant) => {
+                        Some(format!("const {name}: {}", self.ty(&constant["type"])))
+                    }
+                    _ => Some(param.to_string()),
+                }
+            })
+            .collect();
+        let predicates: Vec<String> = array(&generics["where_predicates"])
+            .iter()
+            .map(|predicate| match variant(predicate) {
+                ("bound_predicate", bound) => format!(
+                    "{}: {}",
+                    self.ty(&bound["type"]),
+                    self.bounds(&bound["bounds"])
+                ),
+                ("lifetime_predicate", lifetime) => {
+                    let outlives: Vec<&str> = array(&lifetime["outlives"])
+                        .iter()
+                        .map(|lifetime| lifetime.as_str().unwrap())
+                        .collect();
+                    format!(
+                        "{}: {}",
+                        lifetime["lifetime"].as_str().unwrap(),
+                        outlives.join(" + ")
+                    )
+                }
+                _ => predicate.to_string(),
+            })
+            .collect();
+        let params = match params.is_empty() {
+            true => String::new(),
+            false => format!("<{}>", params.join(", ")),
+        };
+        let predicates = match predicates.is_empty() {
+            true => String::new(),
+            false => format!(" where {}", predicates.join(", ")),
+        };
+        (params, predicates)
+    }
+
+    fn bounds(&self, bounds: &Value) -> String {
+        let bounds: Vec<String> = array(bounds)
+            .iter()
+            .map(|bound| match variant(bound) {
+                ("trait_bound", bound) => {
+                    let maybe = match bound["modifier"] == "maybe" {
+                        true => "?",
+                        false => "",
+                    };
+                    format!("{maybe}{}", self.path(&bound["trait"]))
+                }
+                ("outlives", lifetime) => lifetime.as_str().unwrap().to_string(),
+                _ => bound.to_string(),
+            })
+            .collect();
+        bounds.join(" + ")
+    }
+
+    /// The name of the path's item with its generic arguments. Only the name is used, so that
+    /// moving an item between internal modules does not change the public API.
+    fn path(&self, path: &Value) -> String {
+        format!("{}{}", name(path), self.generic_args(&path["args"]))
+    }
+
+    fn generic_args(&self, args: &Value) -> String {
+        match variant(args) {
+            ("angle_bracketed", args) => {
+                let mut rendered: Vec<String> = array(&args["args"])
+                    .iter()
+                    .map(|arg| match variant(arg) {
+                        ("lifetime", lifetime) => lifetime.as_str().unwrap().to_string(),
+                        ("type", ty) => self.ty(ty),
+                        _ => arg.to_string(),
+                    })
+                    .collect();
+                for constraint in array(&args["constraints"]) {
+                    let name = constraint["name"].as_str().unwrap();
+                    rendered.push(match variant(&constraint["binding"]) {
+                        ("equality", term) => format!("{name} = {}", self.ty(&term["type"])),
+                        ("constraint", bounds) => format!("{name}: {}", self.bounds(bounds)),
+                        _ => constraint.to_string(),
+                    });
+                }
+                match rendered.is_empty() {
+                    true => String::new(),
+                    false => format!("<{}>", rendered.join(", ")),
+                }
+            }
+            ("parenthesized", args) => {
+                let inputs: Vec<String> =
+                    array(&args["inputs"]).iter().map(|i| self.ty(i)).collect();
+                let output = match &args["output"] {
+                    Value::Null => String::new(),
+                    output => format!(" -> {}", self.ty(output)),
+                };
+                format!("({}){output}", inputs.join(", "))
+            }
+            _ if args.is_null() => String::new(),
+            _ => args.to_string(),
+        }
+    }
+
+    fn ty(&self, ty: &Value) -> String {
+        match variant(ty) {
+            ("resolved_path", path) => self.path(path),
+            ("generic" | "primitive", name) => name.as_str().unwrap().to_string(),
+            ("borrowed_ref", reference) => {
+                let lifetime = match &reference["lifetime"] {
+                    Value::String(lifetime) => format!("{lifetime} "),
+                    _ => String::new(),
+                };
+                let mutable = match reference["is_mutable"] == true {
+                    true => "mut ",
+                    false => "",
+                };
+                format!("&{lifetime}{mutable}{}", self.ty(&reference["type"]))
+            }
+            ("raw_pointer", pointer) => {
+                let mutability = match pointer["is_mutable"] == true {
+                    true => "mut",
+                    false => "const",
+                };
+                format!("*{mutability} {}", self.ty(&pointer["type"]))
+            }
+            ("slice", element) => format!("[{}]", self.ty(element)),
+            ("array", array) => format!(
+                "[{}; {}]",
+                self.ty(&array["type"]),
+                array["len"].as_str().unwrap()
+            ),
+            ("tuple", elements) => {
+                let elements: Vec<String> = array(elements).iter().map(|e| self.ty(e)).collect();
+                format!("({})", elements.join(", "))
+            }
+            ("impl_trait", bounds) => format!("impl {}", self.bounds(bounds)),
+            ("dyn_trait", dyn_trait) => {
+                let mut bounds: Vec<String> = array(&dyn_trait["traits"])
+                    .iter()
+                    .map(|bound| self.path(&bound["trait"]))
+                    .collect();
+                if let Value::String(lifetime) = &dyn_trait["lifetime"] {
+                    bounds.push(lifetime.clone());
+                }
+                format!("dyn {}", bounds.join(" + "))
+            }
+            ("qualified_path", qualified) => {
+                let self_type = self.ty(&qualified["self_type"]);
+                let name = qualified["name"].as_str().unwrap();
+                match &qualified["trait"] {
+                    Value::Null => format!("{self_type}::{name}"),
+                    implemented => format!("<{self_type} as {}>::{name}", self.path(implemented)),
+                }
+            }
+            _ => ty.to_string(),
+        }
+    }
+}
+
+#[test]
+#[ignore = "requires a nightly toolchain; run with `cargo test --test public_api -- --ignored`"]
+fn public_api_is_unchanged() {
+    let json = rustdoc_json();
+    let index = json["index"].as_object().unwrap();
+    let mut api = Api {
+        index,
+        lines: vec![],
+        listed: HashMap::new(),
+    };
+    let root = api.item(&json["root"]).unwrap();
+    let mut exposed: Vec<(String, &Value)> = api.public_items(root);
+    let prelude = array(&root["inner"]["module"]["items"])
+        .iter()
+        .map(|id| api.item(id).unwrap())
+        .find(|item| item["name"] == "prelude")
+        .unwrap();
+    exposed.extend(
+        api.public_items(prelude)
+            .into_iter()
+            .map(|(name, id)| (format!("prelude::{name}"), id)),
+    );
+    exposed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, id) in exposed {
+        api.list(path, id);
+    }
+    let api = api.lines.join("\n") + "\n";
+
+    if std::env::var("UPDATE_PUBLIC_API").is_ok() {
+        fs::write(SNAPSHOT, &api).unwrap();
+    }
+    let snapshot = fs::read_to_string(SNAPSHOT).unwrap();
+    assert_eq!(
+        api, snapshot,
+        "the public API changed; update the snapshot if the change is intended"
+    );
+}
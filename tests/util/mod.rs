@@ -1 +0,0 @@
-pub mod ground_truth;